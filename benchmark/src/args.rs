@@ -14,7 +14,7 @@ pub struct Benchmark {
     /// Clients number
     #[clap(long, required = true)]
     pub clients: usize,
-    /// Use curp or not
+    /// Use curp or not, only `put` and `range` support it
     #[clap(long)]
     pub use_curp: bool,
     /// Output to stdout
@@ -27,6 +27,7 @@ pub struct Benchmark {
 
 /// Types of sub command
 #[derive(Subcommand, Debug, Clone, Copy)]
+#[non_exhaustive]
 pub enum Commands {
     /// Put args
     Put {
@@ -46,4 +47,67 @@ pub enum Commands {
         #[clap(long, default_value_t = false)]
         sequential_keys: bool,
     },
+    /// Range args, the keys are generated in the same way as `put`
+    Range {
+        /// Key size
+        #[clap(long, default_value_t = 8)]
+        key_size: usize,
+        /// Total number of ranges
+        #[clap(long, default_value_t = 10000)]
+        total: usize,
+        /// Key space size
+        #[clap(long, default_value_t = 1)]
+        key_space_size: usize,
+        /// sequential keys or not
+        #[clap(long, default_value_t = false)]
+        sequential_keys: bool,
+        /// Serializable range or not
+        #[clap(long, default_value_t = false)]
+        serializable: bool,
+    },
+    /// Txn args, each txn puts `txn_ops` sequential keys
+    Txn {
+        /// Key size
+        #[clap(long, default_value_t = 8)]
+        key_size: usize,
+        /// Value size
+        #[clap(long, default_value_t = 8)]
+        val_size: usize,
+        /// Total number of txns
+        #[clap(long, default_value_t = 10000)]
+        total: usize,
+        /// Key space size, it must not be less than `txn_ops`
+        #[clap(long, default_value_t = 10000)]
+        key_space_size: usize,
+        /// Number of puts in a txn
+        #[clap(long, default_value_t = 1)]
+        txn_ops: usize,
+    },
+    /// Watch args, each client watches its own key, puts the key and waits for the event,
+    /// the latency is from sending the put to receiving the event
+    Watch {
+        /// Key size
+        #[clap(long, default_value_t = 8)]
+        key_size: usize,
+        /// Value size
+        #[clap(long, default_value_t = 8)]
+        val_size: usize,
+        /// Total number of events
+        #[clap(long, default_value_t = 10000)]
+        total: usize,
+    },
+}
+
+impl Commands {
+    /// Total number of requests
+    #[inline]
+    #[must_use]
+    pub fn total(&self) -> usize {
+        match *self {
+            Commands::Put { total, .. }
+            | Commands::Range { total, .. }
+            | Commands::Txn { total, .. }
+            | Commands::Watch { total, .. } => total,
+        }
+    }
 }
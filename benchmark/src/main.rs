@@ -16,14 +16,22 @@ fn tracing_init(stdout: bool) {
             tracing_subscriber::fmt::layer()
                 .with_ansi(false)
                 .with_writer(io::stdout)
-                .with_filter(Targets::new().with_target("benchmark", LevelFilter::INFO)),
+                .with_filter(
+                    Targets::new()
+                        .with_target("benchmark", LevelFilter::INFO)
+                        .with_target("xline_bench", LevelFilter::INFO),
+                ),
         )
     } else {
         None
     };
     let layer = tracing_subscriber::fmt::layer()
         .with_writer(io::stderr)
-        .with_filter(Targets::new().with_target("benchmark", LevelFilter::DEBUG));
+        .with_filter(
+            Targets::new()
+                .with_target("benchmark", LevelFilter::DEBUG)
+                .with_target("xline_bench", LevelFilter::DEBUG),
+        );
     tracing_subscriber::registry()
         .with(layer)
         .with(option_layer)
@@ -40,9 +48,11 @@ async fn main() -> Result<()> {
 
     let summary = stats.summary();
     let histogram = stats.histogram();
+    let percentiles = stats.percentiles();
 
     info!("{}", summary);
     info!("{}", histogram);
+    info!("{}", percentiles);
 
     Ok(())
 }
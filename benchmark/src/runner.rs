@@ -1,12 +1,13 @@
 use std::{
     collections::HashMap,
+    future::Future,
     sync::{
         atomic::{AtomicUsize, Ordering},
         Arc,
     },
 };
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use clippy_utilities::{NumericCast, OverflowArithmetic};
 use indicatif::ProgressBar;
 use rand::RngCore;
@@ -19,7 +20,22 @@ use tokio::{
 };
 use tracing::debug;
 use utils::config::ClientTimeout;
-use xline::client::{kv_types::PutRequest, Client};
+use xline::client::{
+    kv_types::{PutRequest, RangeRequest},
+    Client,
+};
+use xline_client::{
+    clients::{
+        kv::KvClient,
+        watch::{WatchStream, Watcher},
+    },
+    errors::ClientError,
+    types::{
+        kv::{PutRequest as EtcdPutRequest, TxnOp, TxnRequest},
+        watch::WatchRequest,
+    },
+    Client as EtcdClient, ClientOptions,
+};
 
 use crate::{args::Commands, Benchmark};
 
@@ -32,6 +48,37 @@ struct CmdResult {
     error: Option<String>,
 }
 
+/// Worker of the watch benchmark
+#[derive(Debug)]
+struct WatchWorker {
+    /// Kv client to put the key
+    kv: KvClient,
+    /// The watched key
+    key: Vec<u8>,
+    /// Watcher of the key, it's kept for the lifetime of the worker
+    _watcher: Watcher,
+    /// Stream of the events of the key
+    stream: WatchStream,
+}
+
+impl WatchWorker {
+    /// Put the key and wait for the event of the put
+    async fn put_and_watch(&mut self, val: &[u8]) -> Result<(), ClientError> {
+        let _resp = self
+            .kv
+            .put(EtcdPutRequest::new(self.key.clone(), val))
+            .await?;
+        while let Some(resp) = self.stream.message().await? {
+            if !resp.events.is_empty() {
+                return Ok(());
+            }
+        }
+        Err(ClientError::StreamError(
+            "watch stream is closed".to_owned(),
+        ))
+    }
+}
+
 /// `CommandRunner` is the main struct for running commands.
 #[allow(clippy::module_name_repetitions)]
 #[derive(Debug)]
@@ -121,6 +168,29 @@ impl Stats {
         }
         s
     }
+
+    /// Calculate the latency distribution from the sorted latencies.
+    pub fn percentiles(&self) -> String {
+        let percentiles = [
+            ("10", 100),
+            ("25", 250),
+            ("50", 500),
+            ("75", 750),
+            ("90", 900),
+            ("95", 950),
+            ("99", 990),
+            ("99.9", 999),
+        ];
+        let last = self.latencies.len().saturating_sub(1);
+        let mut s = String::from("\nLatency distribution:\n");
+        for (name, per_mille) in percentiles {
+            let idx = last.overflow_mul(per_mille).overflow_div(1000);
+            if let Some(latency) = self.latencies.get(idx) {
+                s.push_str(&format!("  {name}% in {:.4} secs\n", latency.as_secs_f64()));
+            }
+        }
+        s
+    }
 }
 
 impl CommandRunner {
@@ -141,23 +211,83 @@ impl CommandRunner {
     /// Errors if the benchmark fails.
     #[inline]
     pub async fn run(&mut self) -> Result<Stats> {
-        let clients = self.crate_clients().await?;
         match self.args.command {
             Commands::Put {
                 key_size,
                 val_size,
-                total,
                 key_space_size,
                 sequential_keys,
+                ..
+            } => {
+                let clients = self.crate_clients().await?;
+                let val = Arc::new(Self::random_bytes(val_size));
+                self.bench(clients, move |mut client: Client, idx| {
+                    let key = Self::gen_key(key_size, idx, key_space_size, sequential_keys);
+                    let val = Arc::clone(&val);
+                    async move {
+                        let result = client.put(PutRequest::new(key, val.as_slice())).await;
+                        (client, result.err().map(|e| format!("{e:?}")))
+                    }
+                })
+                .await
+            }
+            Commands::Range {
+                key_size,
+                key_space_size,
+                sequential_keys,
+                serializable,
+                ..
+            } => {
+                let clients = self.crate_clients().await?;
+                self.bench(clients, move |mut client: Client, idx| {
+                    let key = Self::gen_key(key_size, idx, key_space_size, sequential_keys);
+                    async move {
+                        let request = RangeRequest::new(key).with_serializable(serializable);
+                        let result = client.range(request).await;
+                        (client, result.err().map(|e| format!("{e:?}")))
+                    }
+                })
+                .await
+            }
+            Commands::Txn {
+                key_size,
+                val_size,
+                key_space_size,
+                txn_ops,
+                ..
+            } => {
+                if txn_ops > key_space_size {
+                    bail!("txn ops {txn_ops} is larger than the key space size {key_space_size}");
+                }
+                let clients = self.kv_clients().await?;
+                let val = Arc::new(Self::random_bytes(val_size));
+                self.bench(clients, move |mut client: KvClient, idx| {
+                    let ops: Vec<_> = (0..txn_ops)
+                        .map(|i| {
+                            let n = idx.wrapping_mul(txn_ops).wrapping_add(i);
+                            let key = Self::gen_key(key_size, n, key_space_size, true);
+                            TxnOp::put(EtcdPutRequest::new(key, val.as_slice()))
+                        })
+                        .collect();
+                    async move {
+                        let result = client.txn(TxnRequest::new().and_then(ops)).await;
+                        (client, result.err().map(|e| format!("{e:?}")))
+                    }
+                })
+                .await
+            }
+            Commands::Watch {
+                key_size, val_size, ..
             } => {
-                self.put_bench(
-                    clients,
-                    key_size,
-                    val_size,
-                    total,
-                    key_space_size,
-                    sequential_keys,
-                )
+                let workers = self.watch_workers(key_size).await?;
+                let val = Arc::new(Self::random_bytes(val_size));
+                self.bench(workers, move |mut worker: WatchWorker, _idx| {
+                    let val = Arc::clone(&val);
+                    async move {
+                        let result = worker.put_and_watch(&val).await;
+                        (worker, result.err().map(|e| format!("{e:?}")))
+                    }
+                })
                 .await
             }
         }
@@ -178,53 +308,85 @@ impl CommandRunner {
         Ok(clients)
     }
 
-    /// Run put benchmark
-    async fn put_bench(
-        &mut self,
-        clients: Vec<Client>,
-        key_size: usize,
-        val_size: usize,
-        total: usize,
-        key_space_size: usize,
-        sequential_keys: bool,
-    ) -> Result<Stats> {
+    /// Create clients of the etcd compatible API, which are used by the benchmarks that
+    /// don't support curp
+    async fn etcd_clients(&self) -> Result<Vec<EtcdClient>> {
+        if self.args.use_curp {
+            bail!("the benchmark doesn't support curp");
+        }
+        let mut clients = Vec::with_capacity(self.args.clients);
+        for _ in 0..self.args.clients {
+            let client =
+                EtcdClient::connect(self.args.endpoints.values(), ClientOptions::default()).await?;
+            clients.push(client);
+        }
+        Ok(clients)
+    }
+
+    /// Create kv clients of the etcd compatible API
+    async fn kv_clients(&self) -> Result<Vec<KvClient>> {
+        Ok(self
+            .etcd_clients()
+            .await?
+            .iter()
+            .map(EtcdClient::kv_client)
+            .collect())
+    }
+
+    /// Create a `WatchWorker` for each client, each of them watches its own key
+    async fn watch_workers(&self, key_size: usize) -> Result<Vec<WatchWorker>> {
+        let clients = self.etcd_clients().await?;
+        let mut workers = Vec::with_capacity(clients.len());
+        for (i, client) in clients.into_iter().enumerate() {
+            let key = Self::gen_key(key_size, i, usize::MAX, true);
+            let (watcher, stream) = client
+                .watch_client()
+                .watch(WatchRequest::new(key.clone()))
+                .await?;
+            workers.push(WatchWorker {
+                kv: client.kv_client(),
+                key,
+                _watcher: watcher,
+                stream,
+            });
+        }
+        Ok(workers)
+    }
+
+    /// Run the benchmark, a worker is spawned for each client, and each worker sends the
+    /// requests built by `op` until `total` requests are sent. The client is moved into
+    /// the request and returned with the error of the request.
+    async fn bench<C, F, Fut>(&mut self, clients: Vec<C>, op: F) -> Result<Stats>
+    where
+        C: Send + 'static,
+        F: Fn(C, usize) -> Fut + Clone + Send + 'static,
+        Fut: Future<Output = (C, Option<String>)> + Send + 'static,
+    {
+        let total = self.args.command.total();
         let count = Arc::new(AtomicUsize::new(0));
         let b = Arc::new(Barrier::new(clients.len().overflow_add(1)));
         let (tx, rx) = mpsc::channel(clients.len());
 
-        let mut val = vec![0u8; val_size];
-        rand::thread_rng().fill_bytes(&mut val);
-        let val = Arc::new(val);
-
         let mut handles = Vec::with_capacity(clients.len());
-        for mut client in clients {
+        for client in clients {
             let c = Arc::clone(&b);
             let count_clone = Arc::clone(&count);
-            let val_clone = Arc::clone(&val);
             let tx_clone = tx.clone();
+            let op_clone = op.clone();
             let handle = tokio::spawn(async move {
-                let mut key = vec![0u8; key_size];
+                let mut client = client;
                 let _ = c.wait().await;
                 loop {
                     let idx = count_clone.fetch_add(1, Ordering::SeqCst);
                     if idx >= total {
                         break;
                     }
-                    if sequential_keys {
-                        Self::fill_usize_to_buf(&mut key, idx.overflow_rem(key_space_size));
-                    } else {
-                        Self::fill_usize_to_buf(
-                            &mut key,
-                            rand::random::<usize>().overflow_rem(key_space_size),
-                        );
-                    }
                     let start = Instant::now();
-                    let result = client
-                        .put(PutRequest::new(key.as_slice(), val_clone.as_slice()))
-                        .await;
+                    let (returned, error) = op_clone(client, idx).await;
+                    client = returned;
                     let cmd_result = CmdResult {
                         elapsed: start.elapsed(),
-                        error: result.err().map(|e| format!("{e:?}")),
+                        error,
                     };
                     assert!(
                         tx_clone.send(cmd_result).await.is_ok(),
@@ -249,9 +411,7 @@ impl CommandRunner {
         clippy::float_arithmetic
     )]
     async fn collecter(&mut self, mut rx: Receiver<CmdResult>, b: Arc<Barrier>) -> Stats {
-        let bar_len = match self.args.command {
-            Commands::Put { total, .. } => total,
-        };
+        let bar_len = self.args.command.total();
         let bar = Arc::new(ProgressBar::new(bar_len.numeric_cast()));
 
         if bar.is_hidden() {
@@ -299,6 +459,26 @@ impl CommandRunner {
         stats
     }
 
+    /// Generate a key of `key_size` bytes from `idx` if `sequential` is set, otherwise from
+    /// a random number, the keys are in a space of `key_space_size` keys
+    fn gen_key(key_size: usize, idx: usize, key_space_size: usize, sequential: bool) -> Vec<u8> {
+        let mut key = vec![0u8; key_size];
+        let n = if sequential {
+            idx
+        } else {
+            rand::random::<usize>()
+        };
+        Self::fill_usize_to_buf(&mut key, n.overflow_rem(key_space_size));
+        key
+    }
+
+    /// Generate `size` random bytes
+    fn random_bytes(size: usize) -> Vec<u8> {
+        let mut bytes = vec![0u8; size];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        bytes
+    }
+
     /// Fill `usize` to `buf`
     fn fill_usize_to_buf(buf: &mut [u8], value: usize) {
         let mut value = value;
@@ -326,4 +506,25 @@ mod test {
         CommandRunner::fill_usize_to_buf(&mut buf, 257);
         assert_eq!(buf, vec![1, 1, 0, 0, 0, 0, 0, 0]);
     }
+
+    #[test]
+    fn test_gen_key() {
+        assert_eq!(CommandRunner::gen_key(2, 258, 1000, true), vec![2, 1]);
+        assert_eq!(CommandRunner::gen_key(2, 258, 10, true), vec![8, 0]);
+        assert!(matches!(
+            CommandRunner::gen_key(2, 0, 10, false).as_slice(),
+            &[n, 0] if n < 10
+        ));
+    }
+
+    #[test]
+    fn test_percentiles() {
+        let stats = Stats {
+            latencies: (1..=1000).map(Duration::from_millis).collect(),
+            ..Stats::default()
+        };
+        let percentiles = stats.percentiles();
+        assert!(percentiles.contains("  50% in 0.5000 secs"));
+        assert!(percentiles.contains("  99.9% in 0.9990 secs"));
+    }
 }
@@ -4,6 +4,7 @@ use event_listener::Event;
 use futures::{pin_mut, stream::FuturesUnordered, StreamExt};
 use parking_lot::RwLock;
 use tokio::{sync::broadcast, time::timeout};
+use tonic::transport::ClientTlsConfig;
 use tracing::{debug, instrument, warn};
 use utils::{config::ClientTimeout, parking_lot_lock::RwLockMap};
 
@@ -90,9 +91,20 @@ where
     /// Create a new protocol client based on the addresses
     #[inline]
     pub async fn new(addrs: HashMap<ServerId, String>, timeout: ClientTimeout) -> Self {
+        Self::new_with_tls(addrs, timeout, None).await
+    }
+
+    /// Create a new protocol client based on the addresses, the servers are connected over
+    /// TLS if `tls_config` is given
+    #[inline]
+    pub async fn new_with_tls(
+        addrs: HashMap<ServerId, String>,
+        timeout: ClientTimeout,
+        tls_config: Option<ClientTlsConfig>,
+    ) -> Self {
         Self {
             state: RwLock::new(State::new()),
-            connects: rpc::connect(addrs, None).await,
+            connects: rpc::connect(addrs, None, tls_config).await,
             timeout,
             phantom: PhantomData,
         }
@@ -465,6 +477,15 @@ where
     pub fn leader_rx(&self) -> broadcast::Receiver<ServerId> {
         self.state.read().leader_tx.subscribe()
     }
+
+    /// Connect to a server at its new address, eg. after it moves to another host. An
+    /// unknown id is ignored.
+    #[inline]
+    pub async fn update_addr(&self, id: &str, addr: String) {
+        if let Some(connect) = self.connects.get(id) {
+            connect.update_addr(addr).await;
+        }
+    }
 }
 
 #[cfg(test)]
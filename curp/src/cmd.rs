@@ -1,4 +1,4 @@
-use std::{fmt::Display, hash::Hash};
+use std::{fmt::Display, hash::Hash, path::Path};
 
 use async_trait::async_trait;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
@@ -28,6 +28,9 @@ pub trait Command:
     /// After_sync result
     type ASR: std::fmt::Debug + Send + Sync + Clone + Serialize + DeserializeOwned;
 
+    /// Prepare result, it's handed to the after_sync of the command
+    type PR: std::fmt::Debug + Send + Sync;
+
     /// Get keys of the command
     fn keys(&self) -> &[Self::K];
 
@@ -43,13 +46,27 @@ pub trait Command:
         <E as CommandExecutor<Self>>::execute(e, self).await
     }
 
+    /// Prepare the after_sync of the command according to the executor
+    #[inline]
+    fn prepare<E>(&self, e: &E, index: LogIndex) -> Self::PR
+    where
+        E: CommandExecutor<Self> + Send + Sync,
+    {
+        <E as CommandExecutor<Self>>::prepare(e, self, index)
+    }
+
     /// Execute the command after_sync callback
     #[inline]
-    async fn after_sync<E>(&self, e: &E, index: LogIndex) -> Result<Self::ASR, E::Error>
+    async fn after_sync<E>(
+        &self,
+        e: &E,
+        index: LogIndex,
+        prepare_res: Self::PR,
+    ) -> Result<Self::ASR, E::Error>
     where
         E: CommandExecutor<Self> + Send + Sync,
     {
-        <E as CommandExecutor<Self>>::after_sync(e, self, index).await
+        <E as CommandExecutor<Self>>::after_sync(e, self, index, prepare_res).await
     }
 }
 
@@ -65,6 +82,13 @@ impl ProposeId {
     pub fn new(id: String) -> Self {
         Self(id)
     }
+
+    /// Get the id as a string
+    #[inline]
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
 }
 
 impl Display for ProposeId {
@@ -111,12 +135,34 @@ where
     /// Execute the command
     async fn execute(&self, cmd: &C) -> Result<C::ER, Self::Error>;
 
-    /// Execute the after_sync callback
-    async fn after_sync(&self, cmd: &C, index: LogIndex) -> Result<C::ASR, Self::Error>;
+    /// Prepare the after_sync of the command. It's called in the order of the log, while the
+    /// after_syncs of non-conflicting commands may run concurrently, so anything that relies on
+    /// the log order (eg. allocating a revision) should be done here. The result is dropped
+    /// without calling after_sync if the execution of the command fails.
+    fn prepare(&self, cmd: &C, index: LogIndex) -> C::PR;
+
+    /// Execute the after_sync callback, commands that don't conflict with each other may be
+    /// after synced concurrently
+    async fn after_sync(
+        &self,
+        cmd: &C,
+        index: LogIndex,
+        prepare_res: C::PR,
+    ) -> Result<C::ASR, Self::Error>;
 
     /// Reset the command executor to the initial state
     async fn reset(&self);
 
+    /// Take a snapshot of the command executor into the file at `path`, it contains all log
+    /// entries applied so far. The snapshot should be streamed into the file, it may be
+    /// larger than the memory.
+    async fn snapshot(&self, path: &Path) -> Result<(), Self::Error>;
+
+    /// Replace the state of the command executor with a snapshot in the file at `path`
+    /// taken by another server, `index` is the index of the last log entry included in the
+    /// snapshot
+    async fn install_snapshot(&self, path: &Path, index: LogIndex) -> Result<(), Self::Error>;
+
     /// Index of the last log entry that has been successfully applied to the command executor
     fn last_applied(&self) -> Result<LogIndex, Self::Error>;
 }
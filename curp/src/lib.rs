@@ -167,6 +167,9 @@ mod message;
 /// Log Entry
 mod log_entry;
 
+/// Snapshot of the command executor
+mod snapshot;
+
 /// Protobuf generated types that are used in RPC
 mod rpc;
 
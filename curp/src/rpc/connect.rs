@@ -4,7 +4,8 @@ use async_trait::async_trait;
 #[cfg(test)]
 use mockall::automock;
 use tokio::sync::RwLock;
-use tracing::{debug, instrument};
+use tonic::transport::{ClientTlsConfig, Endpoint};
+use tracing::{debug, info, instrument};
 use utils::tracing::Inject;
 
 use crate::{
@@ -12,8 +13,9 @@ use crate::{
     message::ServerId,
     rpc::{
         proto::protocol_client::ProtocolClient, AppendEntriesRequest, AppendEntriesResponse,
-        FetchLeaderRequest, FetchLeaderResponse, ProposeRequest, ProposeResponse, VoteRequest,
-        VoteResponse, WaitSyncedRequest, WaitSyncedResponse,
+        FetchLeaderRequest, FetchLeaderResponse, InstallSnapshotRequest, InstallSnapshotResponse,
+        ProposeRequest, ProposeResponse, TryBecomeLeaderNowRequest, TryBecomeLeaderNowResponse,
+        VoteRequest, VoteResponse, WaitSyncedRequest, WaitSyncedResponse,
     },
 };
 
@@ -26,21 +28,21 @@ pub trait TxFilter: Send + Sync + Debug {
     fn boxed_clone(&self) -> Box<dyn TxFilter>;
 }
 
-/// Convert a vec of addr string to a vec of `Connect`
+/// Convert a vec of addr string to a vec of `Connect`, the servers are connected over TLS
+/// if `tls_config` is given
 pub(crate) async fn connect(
     addrs: HashMap<ServerId, String>,
     tx_filter: Option<Box<dyn TxFilter>>,
+    tls_config: Option<ClientTlsConfig>,
 ) -> HashMap<ServerId, Arc<Connect>> {
-    futures::future::join_all(addrs.into_iter().map(|(id, mut addr)| async move {
-        // Addrs must start with "http" to communicate with the server
-        if !addr.starts_with("http://") {
-            addr.insert_str(0, "http://");
-        }
-        (
-            id,
-            addr.clone(),
-            ProtocolClient::connect(addr.clone()).await,
-        )
+    let tls = tls_config.as_ref();
+    futures::future::join_all(addrs.into_iter().map(|(id, addr)| async move {
+        let addr = with_scheme(addr, tls.is_some());
+        let conn = match endpoint(addr.clone(), tls) {
+            Ok(endpoint) => endpoint.connect().await.map(ProtocolClient::new),
+            Err(e) => Err(e),
+        };
+        (id, addr, conn)
     }))
     .await
     .into_iter()
@@ -49,7 +51,8 @@ pub(crate) async fn connect(
         let connect = Arc::new(Connect {
             id: id.clone(),
             rpc_connect: RwLock::new(conn),
-            addr,
+            addr: parking_lot::RwLock::new(addr),
+            tls_config: tls_config.clone(),
             tx_filter: tx_filter.as_ref().map(|f| f.boxed_clone()),
         });
         (id, connect)
@@ -57,6 +60,27 @@ pub(crate) async fn connect(
     .collect()
 }
 
+/// Addrs must start with "http" to communicate with the server, or with "https" if it's
+/// connected over TLS
+fn with_scheme(mut addr: String, tls: bool) -> String {
+    if !addr.starts_with("http://") && !addr.starts_with("https://") {
+        addr.insert_str(0, if tls { "https://" } else { "http://" });
+    }
+    addr
+}
+
+/// Endpoint of the server at `addr`, with the TLS config if there is one
+fn endpoint(
+    addr: String,
+    tls_config: Option<&ClientTlsConfig>,
+) -> Result<Endpoint, tonic::transport::Error> {
+    let endpoint = Endpoint::from_shared(addr)?;
+    match tls_config {
+        Some(tls_config) => endpoint.tls_config(tls_config.clone()),
+        None => Ok(endpoint),
+    }
+}
+
 /// Connect interface
 #[cfg_attr(test, automock)]
 #[async_trait]
@@ -103,6 +127,20 @@ pub(crate) trait ConnectApi: Send + Sync + 'static {
         request: FetchLeaderRequest,
         timeout: Duration,
     ) -> Result<tonic::Response<FetchLeaderResponse>, ProposeError>;
+
+    /// Send `TryBecomeLeaderNowRequest`
+    async fn try_become_leader_now(
+        &self,
+        request: TryBecomeLeaderNowRequest,
+        timeout: Duration,
+    ) -> Result<tonic::Response<TryBecomeLeaderNowResponse>, ProposeError>;
+
+    /// Send `InstallSnapshotRequest`
+    async fn install_snapshot(
+        &self,
+        request: InstallSnapshotRequest,
+        timeout: Duration,
+    ) -> Result<tonic::Response<InstallSnapshotResponse>, ProposeError>;
 }
 
 /// The connection struct to hold the real rpc connections, it may failed to connect, but it also
@@ -113,8 +151,10 @@ pub(crate) struct Connect {
     id: ServerId,
     /// The rpc connection, if it fails it contains a error, otherwise the rpc client is there
     rpc_connect: RwLock<Result<ProtocolClient<tonic::transport::Channel>, tonic::transport::Error>>,
-    /// The addr used to connect if failing met
-    addr: String,
+    /// The addr used to connect if failing met, it changes when the server moves
+    addr: parking_lot::RwLock<String>,
+    /// TLS config of the connection, `None` if it's in plaintext
+    tls_config: Option<ClientTlsConfig>,
     /// The injected filter
     tx_filter: Option<Box<dyn TxFilter>>,
 }
@@ -137,12 +177,11 @@ impl ConnectApi for Connect {
         if let Ok(ref client) = *connect_write {
             return Ok(client.clone());
         }
-        let client = ProtocolClient::<_>::connect(self.addr.clone())
+        let addr = self.addr.read().clone();
+        let client = endpoint(addr, self.tls_config.as_ref())?
+            .connect()
             .await
-            .map(|client| {
-                *connect_write = Ok(client.clone());
-                client
-            })?;
+            .map(ProtocolClient::new)?;
         *connect_write = Ok(client.clone());
         Ok(client)
     }
@@ -220,9 +259,53 @@ impl ConnectApi for Connect {
         req.set_timeout(timeout);
         client.fetch_leader(req).await.map_err(Into::into)
     }
+
+    /// Send `TryBecomeLeaderNowRequest`
+    async fn try_become_leader_now(
+        &self,
+        request: TryBecomeLeaderNowRequest,
+        timeout: Duration,
+    ) -> Result<tonic::Response<TryBecomeLeaderNowResponse>, ProposeError> {
+        self.filter()?;
+
+        let mut client = self.get().await?;
+        let mut req = tonic::Request::new(request);
+        req.set_timeout(timeout);
+        client.try_become_leader_now(req).await.map_err(Into::into)
+    }
+
+    /// Send `InstallSnapshotRequest`
+    async fn install_snapshot(
+        &self,
+        request: InstallSnapshotRequest,
+        timeout: Duration,
+    ) -> Result<tonic::Response<InstallSnapshotResponse>, ProposeError> {
+        self.filter()?;
+
+        let mut client = self.get().await?;
+        let mut req = tonic::Request::new(request);
+        req.set_timeout(timeout);
+        client.install_snapshot(req).await.map_err(Into::into)
+    }
 }
 
 impl Connect {
+    /// Connect to the server at its new address, the requests in flight on the old
+    /// connection still finish, the following ones are sent to the new address. The new
+    /// connection is made lazily, so that a server which isn't at its new address yet
+    /// doesn't hold up the others.
+    pub(crate) async fn update_addr(&self, addr: String) {
+        let addr = with_scheme(addr, self.tls_config.is_some());
+        if *self.addr.read() == addr {
+            return;
+        }
+        info!("the address of server {} changes to {addr}", self.id);
+        let mut connect_write = self.rpc_connect.write().await;
+        *self.addr.write() = addr.clone();
+        *connect_write = endpoint(addr, self.tls_config.as_ref())
+            .map(|endpoint| ProtocolClient::new(endpoint.connect_lazy()));
+    }
+
     /// Filter requests
     // TODO: add request as input
     fn filter(&self) -> Result<(), ProposeError> {
@@ -234,3 +317,30 @@ impl Connect {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn update_addr_changes_the_address() {
+        let connects = connect(
+            HashMap::from([("S1".to_owned(), "127.0.0.1:1".to_owned())]),
+            None,
+            None,
+        )
+        .await;
+        let connect = connects.get("S1").unwrap();
+        assert_eq!(*connect.addr.read(), "http://127.0.0.1:1");
+
+        connect.update_addr("127.0.0.1:2".to_owned()).await;
+        assert_eq!(*connect.addr.read(), "http://127.0.0.1:2");
+        // the scheme is added like at the start
+        connect.update_addr("http://127.0.0.1:2".to_owned()).await;
+        assert_eq!(*connect.addr.read(), "http://127.0.0.1:2");
+        assert_eq!(
+            with_scheme("127.0.0.1:2".to_owned(), true),
+            "https://127.0.0.1:2"
+        );
+    }
+}
@@ -9,8 +9,9 @@ pub(crate) use self::proto::{
     protocol_server::Protocol,
     wait_synced_response::{Success, SyncResult as SyncResultRaw},
     AppendEntriesRequest, AppendEntriesResponse, FetchLeaderRequest, FetchLeaderResponse,
-    ProposeRequest, ProposeResponse, VoteRequest, VoteResponse, WaitSyncedRequest,
-    WaitSyncedResponse,
+    InstallSnapshotRequest, InstallSnapshotResponse, ProposeRequest, ProposeResponse,
+    TryBecomeLeaderNowRequest, TryBecomeLeaderNowResponse, VoteRequest, VoteResponse,
+    WaitSyncedRequest, WaitSyncedResponse,
 };
 use crate::{
     cmd::{Command, ProposeId},
@@ -53,6 +54,50 @@ impl FetchLeaderResponse {
     }
 }
 
+impl TryBecomeLeaderNowRequest {
+    /// Create a new `TryBecomeLeaderNowRequest`
+    pub(crate) fn new(term: u64) -> Self {
+        Self { term }
+    }
+}
+
+impl TryBecomeLeaderNowResponse {
+    /// Create a new `TryBecomeLeaderNowResponse`
+    pub(crate) fn new(term: u64) -> Self {
+        Self { term }
+    }
+}
+
+impl InstallSnapshotRequest {
+    /// Create a new `InstallSnapshotRequest` carrying a chunk of the snapshot from `offset`
+    pub(crate) fn new(
+        term: u64,
+        leader_id: ServerId,
+        last_included_index: usize,
+        last_included_term: u64,
+        offset: usize,
+        data: Vec<u8>,
+        done: bool,
+    ) -> Self {
+        Self {
+            term,
+            leader_id,
+            last_included_index: last_included_index.numeric_cast(),
+            last_included_term,
+            offset: offset.numeric_cast(),
+            data,
+            done,
+        }
+    }
+}
+
+impl InstallSnapshotResponse {
+    /// Create a new `InstallSnapshotResponse`
+    pub(crate) fn new(term: u64) -> Self {
+        Self { term }
+    }
+}
+
 impl ProposeRequest {
     /// Create a new `Propose` request
     pub(crate) fn new<C: Command>(cmd: &C) -> bincode::Result<Self> {
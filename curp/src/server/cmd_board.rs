@@ -3,6 +3,8 @@ use std::{collections::HashMap, sync::Arc};
 use event_listener::{Event, EventListener};
 use indexmap::{IndexMap, IndexSet};
 use parking_lot::RwLock;
+use tokio::time::Instant;
+use tracing::Span;
 use utils::parking_lot_lock::RwLockMap;
 
 use crate::cmd::{Command, ProposeId};
@@ -23,6 +25,9 @@ pub(super) struct CommandBoard<C: Command> {
     pub(super) er_buffer: IndexMap<ProposeId, Result<C::ER, String>>,
     /// Store all after sync results
     pub(super) asr_buffer: IndexMap<ProposeId, Result<C::ASR, String>>,
+    /// Spans of the proposals received by this server and when they are received, they are the parents of the execution and after sync spans.
+    /// A span is removed when its proposal is after synced, or by the gc if it never is, eg. it's dropped by a leader change
+    pub(super) spans: HashMap<ProposeId, (Instant, Span)>,
 }
 
 impl<C: Command> CommandBoard<C> {
@@ -34,6 +39,7 @@ impl<C: Command> CommandBoard<C> {
             sync: IndexSet::new(),
             er_buffer: IndexMap::new(),
             asr_buffer: IndexMap::new(),
+            spans: HashMap::new(),
         }
     }
 
@@ -51,6 +57,7 @@ impl<C: Command> CommandBoard<C> {
     pub(super) fn clear(&mut self) {
         self.er_buffer.clear();
         self.asr_buffer.clear();
+        self.spans.clear();
         self.release_notifiers();
     }
 
@@ -9,13 +9,17 @@ use std::{
     sync::Arc,
 };
 
+use clippy_utilities::NumericCast;
 use tracing::{debug, error};
 
-use super::CEEvent;
-use crate::cmd::{Command, ProposeId};
+use super::{CEEvent, SnapshotTx};
+use crate::{
+    cmd::{Command, CommandExecutor, ProposeId},
+    snapshot::Snapshot,
+};
 
 /// CE task
-pub(super) struct Task<C> {
+pub(super) struct Task<C: Command> {
     /// Corresponding vertex id
     vid: u64,
     /// Task type
@@ -23,24 +27,26 @@ pub(super) struct Task<C> {
 }
 
 /// Task Type
-pub(super) enum TaskType<C> {
+pub(super) enum TaskType<C: Command> {
     /// Execute a cmd
     SpecExe(Arc<C>),
-    /// After sync a cmd
-    AS(Arc<C>, usize),
-    /// Reset the CE
-    Reset,
+    /// After sync a cmd, the prepare result is taken by the worker
+    AS(Arc<C>, usize, Option<C::PR>),
+    /// Reset the CE, to the snapshot if there is one
+    Reset(Option<Arc<Snapshot>>),
+    /// Take the snapshot into its file
+    Snapshot(Snapshot, SnapshotTx),
 }
 
-impl<C> Task<C> {
-    /// Get inner task
-    pub(super) fn inner(&self) -> &TaskType<C> {
-        &self.inner
+impl<C: Command> Task<C> {
+    /// Get mutable inner task
+    pub(super) fn inner_mut(&mut self) -> &mut TaskType<C> {
+        &mut self.inner
     }
 }
 
 /// Vertex
-struct Vertex<C> {
+struct Vertex<C: Command> {
     /// Successor cmds that arrive later with keys that conflict this cmd
     successors: HashSet<u64>,
     /// Number of predecessor cmds that arrive earlier with keys that conflict this cmd
@@ -64,7 +70,7 @@ impl<C: Command> Vertex<C> {
 }
 
 /// Vertex inner
-enum VertexInner<C> {
+enum VertexInner<C: Command> {
     /// A cmd vertex
     Cmd {
         /// Cmd
@@ -73,9 +79,16 @@ enum VertexInner<C> {
         exe_st: ExeState,
         /// After sync state
         as_st: AsState,
+        /// Result of the prepare, it's set when the cmd is ready for after sync
+        prepare_res: Option<C::PR>,
+    },
+    /// A barrier vertex, which conflicts with all other vertexes
+    Barrier {
+        /// Barrier state
+        st: BarrierState,
+        /// The task to send when the barrier is ready, it's taken once sent
+        task: Option<TaskType<C>>,
     },
-    /// A reset vertex
-    Reset(ResetState),
 }
 
 /// Execute state of a cmd
@@ -102,20 +115,20 @@ enum AsState {
     AfterSynced,
 }
 
-/// Reset state
+/// Barrier state
 #[derive(Debug, Clone, Copy)]
-enum ResetState {
-    /// Reset ready
-    ResetReady,
-    /// Resetting
-    Resetting,
+enum BarrierState {
+    /// Barrier ready
+    Ready,
+    /// Running
+    Running,
     /// Completed
     Completed,
 }
 
 /// The filter will block any msg if its predecessors(msgs that arrive earlier and conflict with it) haven't finished process
 /// Internally it maintains a dependency graph of conflicting cmds
-struct Filter<C> {
+struct Filter<C: Command, CE> {
     /// Index from `ProposeId` to `vertex`
     cmd_vid: HashMap<ProposeId, u64>,
     /// Conflict graph
@@ -124,16 +137,19 @@ struct Filter<C> {
     next_id: u64,
     /// Send task to users
     filter_tx: flume::Sender<Task<C>>,
+    /// Command executor, used to prepare the after sync of cmds in the order of the log
+    ce: Arc<CE>,
 }
 
-impl<C: Command> Filter<C> {
+impl<C: Command, CE: CommandExecutor<C>> Filter<C, CE> {
     /// Create a new filter that checks conflict in between msgs
-    fn new(filter_tx: flume::Sender<Task<C>>) -> Self {
+    fn new(filter_tx: flume::Sender<Task<C>>, ce: Arc<CE>) -> Self {
         Self {
             cmd_vid: HashMap::new(),
             vs: HashMap::new(),
             next_id: 0,
             filter_tx,
+            ce,
         }
     }
 
@@ -184,13 +200,13 @@ impl<C: Command> Filter<C> {
         self.update_graph(vid);
     }
 
-    /// Mark a vertex reset
-    fn mark_reset(&mut self, vid: u64) {
+    /// Mark a barrier vertex completed
+    fn mark_barrier_completed(&mut self, vid: u64) {
         let v = self.get_vertex_mut(vid);
         match v.inner {
-            VertexInner::Reset(ref mut st) => {
-                debug_assert!(matches!(*st, ResetState::Resetting));
-                *st = ResetState::Completed;
+            VertexInner::Barrier { ref mut st, .. } => {
+                debug_assert!(matches!(*st, BarrierState::Running));
+                *st = BarrierState::Completed;
             }
             _ => unreachable!("impossible vertex type"),
         }
@@ -237,6 +253,7 @@ impl<C: Command> Filter<C> {
                 ref cmd,
                 ref mut exe_st,
                 ref mut as_st,
+                ref mut prepare_res,
             } => match (*exe_st, *as_st) {
                 (ExeState::ExecuteReady, AsState::NotSynced | AsState::AfterSyncReady(_)) => {
                     *exe_st = ExeState::Executing;
@@ -253,7 +270,7 @@ impl<C: Command> Filter<C> {
                     *as_st = AsState::AfterSyncing;
                     let task = Task {
                         vid,
-                        inner: TaskType::AS(Arc::clone(cmd), index),
+                        inner: TaskType::AS(Arc::clone(cmd), index, prepare_res.take()),
                     };
                     if let Err(e) = self.filter_tx.send(task) {
                         error!("failed to send task through filter, {e}");
@@ -269,20 +286,25 @@ impl<C: Command> Filter<C> {
                     unreachable!("no such cmd state can be reached: {exe_st:?}, {as_st:?}")
                 }
             },
-            VertexInner::Reset(ref mut st) => match *st {
-                ResetState::ResetReady => {
+            VertexInner::Barrier {
+                ref mut st,
+                ref mut task,
+            } => match *st {
+                BarrierState::Ready => {
                     let task = Task {
                         vid,
-                        inner: TaskType::Reset,
+                        inner: task
+                            .take()
+                            .unwrap_or_else(|| unreachable!("barrier task has been sent")),
                     };
-                    *st = ResetState::Resetting;
+                    *st = BarrierState::Running;
                     if let Err(e) = self.filter_tx.send(task) {
                         error!("failed to send task through filter, {e}");
                     }
                     false
                 }
-                ResetState::Resetting => false,
-                ResetState::Completed => true,
+                BarrierState::Running => false,
+                BarrierState::Completed => true,
             },
         }
     }
@@ -295,6 +317,21 @@ impl<C: Command> Filter<C> {
             .expect("no such vertex in conflict graph")
     }
 
+    /// Insert a barrier vertex which runs the task, return its id
+    fn insert_barrier(&mut self, task: TaskType<C>) -> u64 {
+        let new_vid = self.next_vertex_id();
+        let new_v = Vertex {
+            successors: HashSet::new(),
+            predecessor_cnt: 0,
+            inner: VertexInner::Barrier {
+                st: BarrierState::Ready,
+                task: Some(task),
+            },
+        };
+        self.insert_new_vertex(new_vid, new_v);
+        new_vid
+    }
+
     /// Handle event
     fn handle_event(&mut self, event: CEEvent<C>) {
         debug!("new ce event: {event:?}");
@@ -312,18 +349,26 @@ impl<C: Command> Filter<C> {
                         cmd,
                         exe_st: ExeState::ExecuteReady,
                         as_st: AsState::NotSynced,
+                        prepare_res: None,
                     },
                 };
                 self.insert_new_vertex(new_vid, new_v);
                 new_vid
             }
             CEEvent::ASReady(cmd, index) => {
+                // the events arrive in the order of the log, so the cmds are prepared in order
+                let prepare_res = self.ce.prepare(cmd.as_ref(), index.numeric_cast());
                 if let Some(vid) = self.cmd_vid.get(cmd.id()).copied() {
                     let v = self.get_vertex_mut(vid);
                     match v.inner {
-                        VertexInner::Cmd { ref mut as_st, .. } => {
+                        VertexInner::Cmd {
+                            ref mut as_st,
+                            prepare_res: ref mut res,
+                            ..
+                        } => {
                             debug_assert!(matches!(*as_st, AsState::NotSynced));
                             *as_st = AsState::AfterSyncReady(index);
+                            *res = Some(prepare_res);
                         }
                         _ => unreachable!("impossible vertex type"),
                     }
@@ -341,25 +386,23 @@ impl<C: Command> Filter<C> {
                             cmd,
                             exe_st: ExeState::ExecuteReady,
                             as_st: AsState::AfterSyncReady(index),
+                            prepare_res: Some(prepare_res),
                         },
                     };
                     self.insert_new_vertex(new_vid, new_v);
                     new_vid
                 }
             }
-            CEEvent::Reset => {
+            CEEvent::Reset(snapshot) => {
                 // since a reset is needed, all other vertexes doesn't matter anymore, so delete them all
                 self.cmd_vid.clear();
                 self.vs.clear();
 
-                let new_vid = self.next_vertex_id();
-                let new_v = Vertex {
-                    successors: HashSet::new(),
-                    predecessor_cnt: 0,
-                    inner: VertexInner::Reset(ResetState::ResetReady),
-                };
-                self.insert_new_vertex(new_vid, new_v);
-                new_vid
+                self.insert_barrier(TaskType::Reset(snapshot))
+            }
+            CEEvent::Snapshot(snapshot, tx) => {
+                // the snapshot must include all previous cmds, so it waits for all of them
+                self.insert_barrier(TaskType::Snapshot(snapshot, tx))
             }
         };
         self.update_graph(vid);
@@ -370,7 +413,9 @@ impl<C: Command> Filter<C> {
 // Message flow:
 // send_tx -> filter_rx -> filter -> filter_tx -> recv_rx -> done_tx -> done_rx
 #[allow(clippy::type_complexity)] // it's clear
-pub(super) fn channel<C: 'static + Command>() -> (
+pub(super) fn channel<C: 'static + Command, CE: 'static + CommandExecutor<C>>(
+    ce: Arc<CE>,
+) -> (
     flume::Sender<CEEvent<C>>,
     flume::Receiver<Task<C>>,
     flume::Sender<(Task<C>, bool)>,
@@ -382,7 +427,7 @@ pub(super) fn channel<C: 'static + Command>() -> (
     // recv from user to mark a msg done
     let (done_tx, done_rx) = flume::unbounded::<(Task<C>, bool)>();
     let _ig = tokio::spawn(async move {
-        let mut filter = Filter::new(filter_tx);
+        let mut filter = Filter::new(filter_tx, ce);
         #[allow(clippy::integer_arithmetic, clippy::pattern_type_mismatch)]
         // tokio internal triggers
         loop {
@@ -391,8 +436,10 @@ pub(super) fn channel<C: 'static + Command>() -> (
                 Ok((task, succeeded)) = done_rx.recv_async() => {
                     match task.inner {
                         TaskType::SpecExe(_) => filter.mark_executed(task.vid, succeeded),
-                        TaskType::AS(_, _) => filter.mark_after_synced(task.vid),
-                        TaskType::Reset => filter.mark_reset(task.vid),
+                        TaskType::AS(..) => filter.mark_after_synced(task.vid),
+                        TaskType::Reset(_) | TaskType::Snapshot(..) => {
+                            filter.mark_barrier_completed(task.vid);
+                        }
                     }
                 },
                 Ok(event) = filter_rx.recv_async() => {
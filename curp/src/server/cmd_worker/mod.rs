@@ -8,13 +8,14 @@ use clippy_utilities::NumericCast;
 #[cfg(test)]
 use mockall::automock;
 use tokio::task::JoinHandle;
-use tracing::{debug, error};
+use tracing::{debug, error, info_span, Instrument};
 
 use self::conflict_checked_mpmc::Task;
 use super::{curp_node::UncommittedPoolRef, spec_pool::SpecPoolRef};
 use crate::{
     cmd::{Command, CommandExecutor},
     server::{cmd_board::CmdBoardRef, cmd_worker::conflict_checked_mpmc::TaskType},
+    snapshot::Snapshot,
 };
 
 /// The special conflict checked mpmc
@@ -29,10 +30,15 @@ enum CEEvent<C> {
     SpecExeReady(Arc<C>),
     /// The cmd is ready for after sync
     ASReady(Arc<C>, usize),
-    /// Reset the command executor
-    Reset,
+    /// Reset the command executor, to the snapshot if there is one
+    Reset(Option<Arc<Snapshot>>),
+    /// Take the snapshot into its file
+    Snapshot(Snapshot, SnapshotTx),
 }
 
+/// Tx to send back the taken snapshot or the error
+pub(super) type SnapshotTx = flume::Sender<Result<Snapshot, String>>;
+
 impl<C: Command> Debug for CEEvent<C> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match *self {
@@ -42,7 +48,15 @@ impl<C: Command> Debug for CEEvent<C> {
                 .field(cmd.id())
                 .field(index)
                 .finish(),
-            Self::Reset => write!(f, "Reset"),
+            Self::Reset(ref snapshot) => f
+                .debug_tuple("Reset")
+                .field(&snapshot.as_ref().map(|s| s.last_included_index))
+                .finish(),
+            Self::Snapshot(ref snapshot, _) => f
+                .debug_tuple("Snapshot")
+                .field(&snapshot.last_included_index)
+                .field(&snapshot.last_included_term)
+                .finish(),
         }
     }
 }
@@ -56,32 +70,86 @@ async fn cmd_worker<C: Command + 'static, CE: 'static + CommandExecutor<C>>(
     ucp: UncommittedPoolRef<C>,
     ce: Arc<CE>,
 ) {
-    while let Ok(task) = dispatch_rx.recv().await {
-        let succeeded = match *task.inner() {
+    while let Ok(mut task) = dispatch_rx.recv().await {
+        let succeeded = match *task.inner_mut() {
             TaskType::SpecExe(ref cmd) => {
-                let er = ce.execute(cmd.as_ref()).await.map_err(|e| e.to_string());
+                let parent = cb
+                    .read()
+                    .spans
+                    .get(cmd.id())
+                    .and_then(|&(_, ref span)| span.id());
+                let span = info_span!(parent: parent, "execute", cmd_id = %cmd.id());
+                let er = ce
+                    .execute(cmd.as_ref())
+                    .instrument(span)
+                    .await
+                    .map_err(|e| e.to_string());
                 let er_ok = er.is_ok();
                 debug!("cmd({}) is speculatively executed", cmd.id());
                 cb.write().insert_er(cmd.id(), er);
                 er_ok
             }
-            TaskType::AS(ref cmd, index) => {
+            TaskType::AS(ref cmd, index, ref mut prepare_res) => {
+                let prepare_res = prepare_res
+                    .take()
+                    .unwrap_or_else(|| unreachable!("cmd({}) is not prepared", cmd.id()));
+                let parent = cb.write().spans.remove(cmd.id());
+                let span = info_span!(
+                    parent: parent.as_ref().and_then(|&(_, ref span)| span.id()),
+                    "after_sync",
+                    cmd_id = %cmd.id(),
+                    index
+                );
+                fail::fail_point!("curp_before_after_sync");
                 let asr = ce
-                    .after_sync(cmd.as_ref(), index.numeric_cast())
+                    .after_sync(cmd.as_ref(), index.numeric_cast(), prepare_res)
+                    .instrument(span)
                     .await
                     .map_err(|e| e.to_string());
                 let asr_ok = asr.is_ok();
+                // the command is synced, but the result isn't returned to the client yet
+                fail::fail_point!("curp_after_after_sync");
                 cb.write().insert_asr(cmd.id(), asr);
                 sp.lock().remove(cmd.id());
                 let _ig = ucp.lock().remove(cmd.id());
                 debug!("cmd({}) after sync is called", cmd.id());
                 asr_ok
             }
-            TaskType::Reset => {
+            TaskType::Reset(None) => {
                 ce.reset().await;
                 debug!("command executor has been reset");
                 true
             }
+            TaskType::Reset(Some(ref snapshot)) => {
+                let index = snapshot.last_included_index;
+                fail::fail_point!("curp_before_install_snapshot");
+                match ce
+                    .install_snapshot(&snapshot.path, index.numeric_cast())
+                    .await
+                {
+                    Ok(()) => {
+                        debug!("command executor has been reset to snapshot({index})");
+                        true
+                    }
+                    Err(e) => {
+                        error!("failed to install snapshot({index}), {e}");
+                        false
+                    }
+                }
+            }
+            TaskType::Snapshot(ref snapshot, ref tx) => {
+                let index = snapshot.last_included_index;
+                let result = ce
+                    .snapshot(&snapshot.path)
+                    .await
+                    .map(|()| snapshot.clone())
+                    .map_err(|e| e.to_string());
+                let succeeded = result.is_ok();
+                if tx.send(result).is_err() {
+                    debug!("snapshot({index}) is no longer needed");
+                }
+                succeeded
+            }
         };
         if let Err(e) = done_tx.send((task, succeeded)) {
             error!("can't mark a task done, the channel could be closed, {e}");
@@ -107,8 +175,13 @@ pub(super) trait CEEventTxApi<C: Command + 'static>: Send + Sync + 'static {
     /// Send after sync event to the background cmd worker so that after sync can be called
     fn send_after_sync(&self, cmd: Arc<C>, index: usize);
 
-    /// Send reset
-    fn send_reset(&self);
+    /// Send reset, the command executor will be reset to the snapshot if there is one
+    fn send_reset(&self, snapshot: Option<Arc<Snapshot>>);
+
+    /// Send snapshot event, the snapshot including log entries up to its last included
+    /// index will be taken into its file and sent back through `tx` after all previous
+    /// commands are done
+    fn send_snapshot(&self, snapshot: Snapshot, tx: SnapshotTx);
 }
 
 impl<C: Command + 'static> CEEventTxApi<C> for CEEventTx<C> {
@@ -126,12 +199,19 @@ impl<C: Command + 'static> CEEventTxApi<C> for CEEventTx<C> {
         }
     }
 
-    fn send_reset(&self) {
-        let msg = CEEvent::Reset;
+    fn send_reset(&self, snapshot: Option<Arc<Snapshot>>) {
+        let msg = CEEvent::Reset(snapshot);
         if let Err(e) = self.0.send(msg) {
             error!("failed to send reset event to background cmd worker, {e}");
         }
     }
+
+    fn send_snapshot(&self, snapshot: Snapshot, tx: SnapshotTx) {
+        let msg = CEEvent::Snapshot(snapshot, tx);
+        if let Err(e) = self.0.send(msg) {
+            error!("failed to send snapshot event to background cmd worker, {e}");
+        }
+    }
 }
 
 /// Cmd exe recv interface
@@ -158,7 +238,8 @@ pub(super) fn start_cmd_workers<C: Command + 'static, CE: 'static + CommandExecu
     cmd_board: CmdBoardRef<C>,
     shutdown_trigger: Arc<event_listener::Event>,
 ) -> CEEventTx<C> {
-    let (event_tx, task_rx, done_tx) = conflict_checked_mpmc::channel();
+    let cmd_executor = Arc::new(cmd_executor);
+    let (event_tx, task_rx, done_tx) = conflict_checked_mpmc::channel(Arc::clone(&cmd_executor));
     #[allow(clippy::shadow_unrelated)] // false positive
     let bg_worker_handles: Vec<JoinHandle<_>> = iter::repeat((
         task_rx,
@@ -166,7 +247,7 @@ pub(super) fn start_cmd_workers<C: Command + 'static, CE: 'static + CommandExecu
         cmd_board,
         spec_pool,
         uncommitted_pool,
-        Arc::new(cmd_executor),
+        cmd_executor,
     ))
     .take(N_WORKERS)
     .map(|(task_rx, done_tx, cb, sp, ucp, ce)| {
@@ -389,6 +470,34 @@ mod tests {
         assert_eq!(as_rx.recv().await.unwrap().1, 2);
     }
 
+    // If cmd1 and cmd2 don't conflict, cmd2 will not wait for the after sync of cmd1
+    #[traced_test]
+    #[tokio::test]
+    async fn non_conflict_cmds_after_sync_concurrently() {
+        let (er_tx, _er_rx) = mpsc::unbounded_channel();
+        let (as_tx, mut as_rx) = mpsc::unbounded_channel();
+        let ce = TestCE::new("S1".to_owned(), er_tx, as_tx);
+        let cmd_board = Arc::new(RwLock::new(CommandBoard::new()));
+        let spec_pool = Arc::new(Mutex::new(SpeculativePool::new()));
+        let uncommitted_pool = Arc::new(Mutex::new(UncommittedPool::new()));
+        let exe_tx = start_cmd_workers(
+            ce,
+            spec_pool,
+            uncommitted_pool,
+            Arc::clone(&cmd_board),
+            Arc::new(event_listener::Event::new()),
+        );
+
+        let cmd1 =
+            Arc::new(TestCommand::new_put(vec![1], 1).set_as_dur(Duration::from_millis(500)));
+        let cmd2 = Arc::new(TestCommand::new_put(vec![2], 2));
+        exe_tx.send_after_sync(Arc::clone(&cmd1), 1);
+        exe_tx.send_after_sync(Arc::clone(&cmd2), 2);
+
+        assert_eq!(as_rx.recv().await.unwrap().1, 2);
+        assert_eq!(as_rx.recv().await.unwrap().1, 1);
+    }
+
     #[traced_test]
     #[tokio::test]
     async fn reset_will_wipe_all_states_and_outdated_cmds() {
@@ -414,7 +523,7 @@ mod tests {
 
         assert_eq!(er_rx.recv().await.unwrap().1, vec![]);
 
-        exe_tx.send_reset();
+        exe_tx.send_reset(None);
 
         let cmd3 = Arc::new(TestCommand::new_get(vec![1]));
         exe_tx.send_after_sync(cmd3, 1);
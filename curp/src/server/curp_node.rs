@@ -1,4 +1,4 @@
-use std::{collections::HashMap, fmt::Debug, sync::Arc, time::Duration};
+use std::{collections::HashMap, fmt::Debug, io, sync::Arc, time::Duration};
 
 use clippy_utilities::NumericCast;
 use event_listener::Event;
@@ -7,10 +7,13 @@ use madsim::rand::{thread_rng, Rng};
 use parking_lot::{Mutex, RwLock};
 use thiserror::Error;
 use tokio::{
-    sync::{broadcast, mpsc},
+    fs::File,
+    io::{AsyncReadExt, AsyncWriteExt},
+    sync::{broadcast, mpsc, oneshot},
     task::JoinHandle,
     time::MissedTickBehavior,
 };
+use tonic::transport::ClientTlsConfig;
 use tracing::{debug, error, info, warn};
 use utils::config::CurpConfig;
 
@@ -20,22 +23,28 @@ use super::{
     gc::run_gc_tasks,
     raw_curp::{AppendEntries, RawCurp, TickAction, Vote},
     spec_pool::{SpecPoolRef, SpeculativePool},
-    storage::{StorageApi, StorageError},
+    storage::{LogChange, StorageApi, StorageError},
 };
 use crate::{
     cmd::{Command, CommandExecutor, ProposeId},
     error::ProposeError,
-    log_entry::LogEntry,
-    message::ServerId,
+    message::{LogIndex, ServerId},
     rpc::{
-        self, connect::ConnectApi, AppendEntriesRequest, AppendEntriesResponse, FetchLeaderRequest,
-        FetchLeaderResponse, ProposeRequest, ProposeResponse, VoteRequest, VoteResponse,
+        self,
+        connect::{Connect, ConnectApi},
+        AppendEntriesRequest, AppendEntriesResponse, FetchLeaderRequest, FetchLeaderResponse,
+        InstallSnapshotRequest, InstallSnapshotResponse, ProposeRequest, ProposeResponse,
+        TryBecomeLeaderNowRequest, TryBecomeLeaderNowResponse, VoteRequest, VoteResponse,
         WaitSyncedRequest, WaitSyncedResponse,
     },
     server::storage::rocksdb::RocksDBStorage,
+    snapshot::{Snapshot, SnapshotDir, TransferFile},
     TxFilter,
 };
 
+/// Max size of the data in an `InstallSnapshot` request
+const SNAPSHOT_CHUNK_SIZE: usize = 256 * 1024;
+
 /// Uncommitted pool type
 pub(super) type UncommittedPool<C> = HashMap<ProposeId, Arc<C>>;
 
@@ -51,11 +60,25 @@ pub(super) enum CurpError {
     /// Storage error
     #[error("storage error, {0}")]
     Storage(#[from] StorageError),
+    /// Snapshot file error
+    #[error("snapshot file error, {0}")]
+    SnapshotFile(#[from] io::Error),
     /// Get applied index error
     #[error("internal error {0}")]
     Internal(String),
 }
 
+/// A snapshot being received from the leader
+#[derive(Debug)]
+struct PendingSnapshot {
+    /// File the chunks are written to
+    file: File,
+    /// Transfer of the file, it's removed unless the snapshot is installed
+    transfer: TransferFile,
+    /// Bytes received so far
+    received: u64,
+}
+
 /// `CurpNode` represents a single node of curp cluster
 pub(super) struct CurpNode<C: Command> {
     /// `RawCurp` state machine
@@ -68,6 +91,14 @@ pub(super) struct CurpNode<C: Command> {
     shutdown_trigger: Arc<Event>,
     /// Storage
     storage: Arc<dyn StorageApi<Command = C>>,
+    /// Tx to send leadership transfer requests, the transferee will be sent back
+    transfer_tx: mpsc::UnboundedSender<oneshot::Sender<Option<ServerId>>>,
+    /// Tx to send the new addresses of the other servers
+    addr_tx: mpsc::UnboundedSender<(ServerId, String)>,
+    /// Directory of the snapshot files
+    snapshot_dir: SnapshotDir,
+    /// The snapshot being received
+    pending_snapshot: tokio::sync::Mutex<Option<PendingSnapshot>>,
 }
 
 // handlers
@@ -159,6 +190,79 @@ impl<C: 'static + Command> CurpNode<C> {
         let (leader_id, term) = self.curp.leader();
         Ok(FetchLeaderResponse::new(leader_id, term))
     }
+
+    /// Handle `InstallSnapshot` requests, the chunks are written to a file, which is kept
+    /// as the installed snapshot once all of them are received
+    pub(super) async fn install_snapshot(
+        &self,
+        req: InstallSnapshotRequest,
+    ) -> Result<InstallSnapshotResponse, CurpError> {
+        // the chunks from a stale leader are dropped before they are written
+        if let Err(term) = self
+            .curp
+            .check_snapshot_chunk(req.term, req.leader_id.clone())
+        {
+            return Ok(InstallSnapshotResponse::new(term));
+        }
+
+        let mut pending = self.pending_snapshot.lock().await;
+        if req.offset == 0 {
+            let transfer = self.snapshot_dir.receive_file();
+            let file = File::create(transfer.path()).await?;
+            *pending = Some(PendingSnapshot {
+                file,
+                transfer,
+                received: 0,
+            });
+        }
+        let received = pending.as_ref().map_or(0, |p| p.received);
+        let Some(ref mut snapshot) = *pending else {
+            return Err(CurpError::Internal(format!(
+                "unexpected snapshot chunk at {}, no snapshot is being received",
+                req.offset
+            )));
+        };
+        if req.offset != received {
+            return Err(CurpError::Internal(format!(
+                "unexpected snapshot chunk at {}, {received} bytes have been received",
+                req.offset
+            )));
+        }
+        snapshot.file.write_all(&req.data).await?;
+        snapshot.received = received.saturating_add(req.data.len().numeric_cast());
+        if !req.done {
+            return Ok(InstallSnapshotResponse::new(self.curp.leader().1));
+        }
+
+        let Some(PendingSnapshot { file, transfer, .. }) = pending.take() else {
+            unreachable!("the pending snapshot is checked above");
+        };
+        file.sync_all().await?;
+        drop(file);
+        let index: usize = req.last_included_index.numeric_cast();
+        let path = self
+            .snapshot_dir
+            .installed_path(index, req.last_included_term);
+        transfer.keep(&path)?;
+        let snapshot = Snapshot::new(index, req.last_included_term, path.clone());
+        let (term, installed) =
+            self.curp
+                .handle_install_snapshot(req.term, req.leader_id, snapshot);
+        if !installed {
+            tokio::fs::remove_file(&path).await?;
+        }
+        Ok(InstallSnapshotResponse::new(term))
+    }
+
+    /// Handle `TryBecomeLeaderNow` requests
+    #[allow(clippy::unnecessary_wraps, clippy::needless_pass_by_value)] // To keep type consistent with other request handlers
+    pub(super) fn try_become_leader_now(
+        &self,
+        req: TryBecomeLeaderNowRequest,
+    ) -> Result<TryBecomeLeaderNowResponse, CurpError> {
+        let term = self.curp.handle_try_become_leader_now(req.term);
+        Ok(TryBecomeLeaderNowResponse::new(term))
+    }
 }
 
 /// Spawned tasks
@@ -194,6 +298,7 @@ impl<C: 'static + Command> CurpNode<C> {
     async fn calibrate_task(
         curp: Arc<RawCurp<C>>,
         connects: HashMap<ServerId, Arc<impl ConnectApi>>,
+        snapshot_dir: SnapshotDir,
         mut calibrate_rx: mpsc::UnboundedReceiver<ServerId>,
     ) {
         let mut handlers: HashMap<ServerId, JoinHandle<()>> = HashMap::new();
@@ -208,10 +313,39 @@ impl<C: 'static + Command> CurpNode<C> {
                 .get(&follower_id)
                 .cloned()
                 .unwrap_or_else(|| unreachable!("no server {follower_id}'s connect"));
-            let hd = tokio::spawn(Self::leader_calibrates_follower(Arc::clone(&curp), connect));
+            let hd = tokio::spawn(Self::leader_calibrates_follower(
+                Arc::clone(&curp),
+                connect,
+                snapshot_dir.clone(),
+            ));
             let _prev_hd = handlers.insert(follower_id, hd);
         }
     }
+
+    /// Leader hands its leadership over to the most up-to-date follower on request
+    async fn leader_transfer_task(
+        curp: Arc<RawCurp<C>>,
+        connects: HashMap<ServerId, Arc<impl ConnectApi>>,
+        mut transfer_rx: mpsc::UnboundedReceiver<oneshot::Sender<Option<ServerId>>>,
+    ) {
+        while let Some(done_tx) = transfer_rx.recv().await {
+            let transferee = Self::leader_transfers(&curp, &connects).await;
+            let _ig = done_tx.send(transferee);
+        }
+    }
+
+    /// Connect to the other servers at their new addresses
+    async fn addr_update_task(
+        connects: HashMap<ServerId, Arc<Connect>>,
+        mut addr_rx: mpsc::UnboundedReceiver<(ServerId, String)>,
+    ) {
+        while let Some((id, addr)) = addr_rx.recv().await {
+            match connects.get(&id) {
+                Some(connect) => connect.update_addr(addr).await,
+                None => debug!("no server {id}'s connect to update its address"),
+            }
+        }
+    }
 }
 
 // utils
@@ -225,10 +359,13 @@ impl<C: 'static + Command> CurpNode<C> {
         cmd_executor: CE,
         curp_cfg: Arc<CurpConfig>,
         tx_filter: Option<Box<dyn TxFilter>>,
+        tls_config: Option<ClientTlsConfig>,
     ) -> Result<Self, CurpError> {
         let (sync_tx, sync_rx) = mpsc::unbounded_channel();
         let (calibrate_tx, calibrate_rx) = mpsc::unbounded_channel();
         let (log_tx, log_rx) = mpsc::unbounded_channel();
+        let (transfer_tx, transfer_rx) = mpsc::unbounded_channel();
+        let (addr_tx, addr_rx) = mpsc::unbounded_channel();
         let shutdown_trigger = Arc::new(Event::new());
         let cmd_board = Arc::new(RwLock::new(CommandBoard::new()));
         let spec_pool = Arc::new(Mutex::new(SpeculativePool::new()));
@@ -238,6 +375,7 @@ impl<C: 'static + Command> CurpNode<C> {
             .map_err(|e| CurpError::Internal(format!("get applied index error, {e}")))?;
 
         let storage = Arc::new(RocksDBStorage::new(&curp_cfg.data_dir)?);
+        let snapshot_dir = SnapshotDir::open(&curp_cfg.data_dir)?;
 
         // start cmd workers
         let exe_tx = start_cmd_workers(
@@ -249,8 +387,9 @@ impl<C: 'static + Command> CurpNode<C> {
         );
 
         // create curp state machine
-        let (voted_for, entries) = storage.recover().await?;
-        let curp = if voted_for.is_none() && entries.is_empty() {
+        let (voted_for, snapshot, entries) = storage.recover().await?;
+        snapshot_dir.remove_installed_except(snapshot.as_ref().map(|s| s.path.as_path()))?;
+        let curp = if voted_for.is_none() && snapshot.is_none() && entries.is_empty() {
             Arc::new(RawCurp::new(
                 id,
                 others.keys().cloned().collect(),
@@ -266,8 +405,9 @@ impl<C: 'static + Command> CurpNode<C> {
             ))
         } else {
             info!(
-                "{} recovered voted_for({voted_for:?}), entries from {:?} to {:?}",
+                "{} recovered voted_for({voted_for:?}), snapshot({:?}), entries from {:?} to {:?}",
                 id,
+                snapshot.as_ref().map(|s| s.last_included_index),
                 entries.first(),
                 entries.last()
             );
@@ -284,6 +424,7 @@ impl<C: 'static + Command> CurpNode<C> {
                 calibrate_tx,
                 log_tx,
                 voted_for,
+                snapshot,
                 entries,
                 last_applied.numeric_cast(),
             ))
@@ -294,21 +435,36 @@ impl<C: 'static + Command> CurpNode<C> {
         let curp_c = Arc::clone(&curp);
         let shutdown_trigger_c = Arc::clone(&shutdown_trigger);
         let storage_c = Arc::clone(&storage);
+        let snapshot_dir_c = snapshot_dir.clone();
         let _ig = tokio::spawn(async move {
             // establish connection with other servers
-            let connects = rpc::connect(others, tx_filter).await;
+            let connects = rpc::connect(others, tx_filter, tls_config).await;
             let tick_task = tokio::spawn(Self::tick_task(Arc::clone(&curp_c), connects.clone()));
             let sync_task = tokio::spawn(Self::sync_task(
                 Arc::clone(&curp_c),
                 connects.clone(),
                 sync_rx,
             ));
-            let calibrate_task = tokio::spawn(Self::calibrate_task(curp_c, connects, calibrate_rx));
-            let log_persist_task = tokio::spawn(Self::log_persist_task(log_rx, storage_c));
+            let calibrate_task = tokio::spawn(Self::calibrate_task(
+                Arc::clone(&curp_c),
+                connects.clone(),
+                snapshot_dir_c.clone(),
+                calibrate_rx,
+            ));
+            let transfer_task = tokio::spawn(Self::leader_transfer_task(
+                curp_c,
+                connects.clone(),
+                transfer_rx,
+            ));
+            let addr_update_task = tokio::spawn(Self::addr_update_task(connects, addr_rx));
+            let log_persist_task =
+                tokio::spawn(Self::log_persist_task(log_rx, storage_c, snapshot_dir_c));
             shutdown_trigger_c.listen().await;
             tick_task.abort();
             sync_task.abort();
             calibrate_task.abort();
+            transfer_task.abort();
+            addr_update_task.abort();
             log_persist_task.abort();
         });
 
@@ -318,6 +474,10 @@ impl<C: 'static + Command> CurpNode<C> {
             cmd_board,
             shutdown_trigger,
             storage,
+            transfer_tx,
+            addr_tx,
+            snapshot_dir,
+            pending_snapshot: tokio::sync::Mutex::new(None),
         })
     }
 
@@ -428,10 +588,33 @@ impl<C: 'static + Command> CurpNode<C> {
 
     /// Leader calibrates a follower
     #[allow(clippy::integer_arithmetic, clippy::indexing_slicing)] // log.len() >= 1 because we have a fake log[0], indexing of `next_index` or `match_index` won't panic because we created an entry when initializing the server state
-    async fn leader_calibrates_follower(curp: Arc<RawCurp<C>>, connect: Arc<dyn ConnectApi>) {
+    async fn leader_calibrates_follower(
+        curp: Arc<RawCurp<C>>,
+        connect: Arc<dyn ConnectApi>,
+        snapshot_dir: SnapshotDir,
+    ) {
         debug!("{} starts calibrating follower {}", curp.id(), connect.id());
         let (rpc_timeout, retry_timeout) = (curp.cfg().rpc_timeout, curp.cfg().retry_timeout);
         loop {
+            // send a snapshot if the follower is too far behind
+            let transfer = snapshot_dir.send_file(connect.id());
+            match curp.snapshot_for(connect.id(), transfer.path()) {
+                Ok(Some((term, snapshot_rx))) => {
+                    match Self::send_snapshot(&curp, connect.as_ref(), term, snapshot_rx, transfer)
+                        .await
+                    {
+                        Ok(true) => continue,
+                        Ok(false) => {
+                            tokio::time::sleep(retry_timeout).await;
+                            continue;
+                        }
+                        Err(()) => return,
+                    }
+                }
+                Ok(None) => {}
+                Err(()) => return,
+            }
+
             // send append entry
             let Ok(ae) = curp.append_entries(connect.id()) else {
                 return;
@@ -491,6 +674,77 @@ impl<C: 'static + Command> CurpNode<C> {
         }
     }
 
+    /// Leader sends a snapshot to a follower in chunks, they are read from the file the
+    /// snapshot is taken in, which is removed once it's sent
+    /// Return `Ok(true)` if the snapshot is sent, `Ok(false)` if it needs to be retried
+    /// Return `Err(())` if self is no longer the leader
+    async fn send_snapshot(
+        curp: &RawCurp<C>,
+        connect: &dyn ConnectApi,
+        term: u64,
+        snapshot_rx: flume::Receiver<Result<Snapshot, String>>,
+        transfer: TransferFile,
+    ) -> Result<bool, ()> {
+        let snapshot = match snapshot_rx.recv_async().await {
+            Ok(Ok(snapshot)) => snapshot,
+            Ok(Err(e)) => {
+                warn!("{} failed to take snapshot, {e}", curp.id());
+                return Ok(false);
+            }
+            Err(_e) => {
+                warn!("{} snapshot is cancelled", curp.id());
+                return Ok(false);
+            }
+        };
+        let (mut file, len) = match Self::open_snapshot(transfer.path()).await {
+            Ok(opened) => opened,
+            Err(e) => {
+                warn!("{} failed to open snapshot, {e}", curp.id());
+                return Ok(false);
+            }
+        };
+        let index = snapshot.last_included_index;
+        let mut offset: usize = 0;
+        loop {
+            let end = offset.saturating_add(SNAPSHOT_CHUNK_SIZE).min(len);
+            let mut chunk = vec![0; end.saturating_sub(offset)];
+            if let Err(e) = file.read_exact(&mut chunk).await {
+                warn!("{} failed to read snapshot, {e}", curp.id());
+                return Ok(false);
+            }
+            let done = end == len;
+            let req = InstallSnapshotRequest::new(
+                term,
+                curp.id().clone(),
+                index,
+                snapshot.last_included_term,
+                offset,
+                chunk,
+                done,
+            );
+            let resp = match connect.install_snapshot(req, curp.cfg().rpc_timeout).await {
+                Ok(resp) => resp.into_inner(),
+                Err(e) => {
+                    warn!("install_snapshot error: {e}");
+                    return Ok(false);
+                }
+            };
+            if done {
+                return curp
+                    .handle_install_snapshot_resp(connect.id(), resp.term, index)
+                    .map(|()| true);
+            }
+            offset = end;
+        }
+    }
+
+    /// Open the file of a snapshot, return it with its length
+    async fn open_snapshot(path: &std::path::Path) -> io::Result<(File, usize)> {
+        let file = File::open(path).await?;
+        let len = file.metadata().await?.len();
+        Ok((file, len.numeric_cast()))
+    }
+
     /// Sync task is responsible for replicating log entries
     async fn sync_task(
         curp: Arc<RawCurp<C>>,
@@ -574,18 +828,113 @@ impl<C: 'static + Command> CurpNode<C> {
         }
     }
 
+    /// Leader asks the most up-to-date follower to start an election immediately
+    /// Return the id of the transferee if the request is accepted
+    async fn leader_transfers(
+        curp: &RawCurp<C>,
+        connects: &HashMap<ServerId, Arc<impl ConnectApi>>,
+    ) -> Option<ServerId> {
+        let (term, transferee) = curp.leader_transferee()?;
+        let connect = connects
+            .get(&transferee)
+            .unwrap_or_else(|| unreachable!("no server {transferee}'s connect"));
+        let req = TryBecomeLeaderNowRequest::new(term);
+        match connect
+            .try_become_leader_now(req, curp.cfg().rpc_timeout)
+            .await
+        {
+            Ok(resp) if resp.get_ref().term == term => {
+                info!("{} transfers leadership to {transferee}", curp.id());
+                Some(transferee)
+            }
+            Ok(resp) => {
+                warn!(
+                    "{transferee} rejects leadership transfer, its term is {}",
+                    resp.get_ref().term
+                );
+                None
+            }
+            Err(e) => {
+                warn!("leadership transfer to {transferee} failed, {e}");
+                None
+            }
+        }
+    }
+
+    /// Transfer the leadership to another server and wait until a new leader is elected
+    /// Return the id of the new leader, or `None` if self is not the leader or the transfer fails
+    pub(super) async fn transfer_leadership(&self) -> Option<ServerId> {
+        let mut leader_rx = self.curp.leader_rx();
+        let (done_tx, done_rx) = oneshot::channel();
+        self.transfer_tx.send(done_tx).ok()?;
+        let _transferee = done_rx.await.ok()??;
+        let election_timeout = self
+            .curp
+            .cfg()
+            .heartbeat_interval
+            .saturating_mul(u32::from(self.curp.cfg().candidate_timeout_ticks));
+        tokio::time::timeout(election_timeout, async {
+            loop {
+                match leader_rx.recv().await {
+                    Ok(Some(leader_id)) if &leader_id != self.curp.id() => break Some(leader_id),
+                    Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                    Err(broadcast::error::RecvError::Closed) => break None,
+                }
+            }
+        })
+        .await
+        .ok()
+        .flatten()
+    }
+
+    /// Connect to another server at its new address
+    pub(super) fn update_addr(&self, id: ServerId, addr: String) {
+        if self.addr_tx.send((id, addr)).is_err() {
+            warn!("{} is shut down, the address isn't updated", self.curp.id());
+        }
+    }
+
     /// Get a rx for leader changes
     pub(super) fn leader_rx(&self) -> broadcast::Receiver<Option<ServerId>> {
         self.curp.leader_rx()
     }
 
+    /// Get the current term
+    pub(super) fn term(&self) -> u64 {
+        self.curp.leader().1
+    }
+
+    /// Check if the server can reach a quorum of the cluster
+    pub(super) fn quorum_connected(&self) -> bool {
+        self.curp.quorum_connected()
+    }
+
+    /// Get the commit index
+    pub(super) fn commit_index(&self) -> LogIndex {
+        self.curp.commit_index()
+    }
+
     /// Log persist task
     pub(super) async fn log_persist_task(
-        mut log_rx: mpsc::UnboundedReceiver<LogEntry<C>>,
+        mut log_rx: mpsc::UnboundedReceiver<LogChange<C>>,
         storage: Arc<dyn StorageApi<Command = C>>,
+        snapshot_dir: SnapshotDir,
     ) {
-        while let Some(e) = log_rx.recv().await {
-            if let Err(err) = storage.put_log_entry(e).await {
+        while let Some(change) = log_rx.recv().await {
+            let result = match change {
+                LogChange::Append(e) => storage.put_log_entry(e).await,
+                LogChange::InstallSnapshot(snapshot) => {
+                    let result = storage.put_snapshot(&snapshot).await;
+                    // the replaced snapshots are removed once the new one is persisted
+                    if result.is_ok() {
+                        if let Err(e) = snapshot_dir.remove_installed_except(Some(&snapshot.path)) {
+                            warn!("failed to remove replaced snapshots, {e}");
+                        }
+                    }
+                    result
+                }
+            };
+            if let Err(err) = result {
                 error!("storage error, {err}");
             }
         }
@@ -663,7 +1012,7 @@ mod tests {
     async fn send_log_will_stop_after_new_election() {
         let curp = {
             let mut exe_tx = MockCEEventTxApi::<TestCommand>::default();
-            exe_tx.expect_send_reset().returning(|| ());
+            exe_tx.expect_send_reset().returning(|_| ());
             Arc::new(RawCurp::new_test(3, exe_tx))
         };
 
@@ -698,7 +1047,7 @@ mod tests {
     async fn send_log_will_succeed_and_commit() {
         let curp = {
             let mut exe_tx = MockCEEventTxApi::<TestCommand>::default();
-            exe_tx.expect_send_reset().returning(|| ());
+            exe_tx.expect_send_reset().returning(|_| ());
             exe_tx.expect_send_after_sync().returning(|_, _| ());
             Arc::new(RawCurp::new_test(3, exe_tx))
         };
@@ -759,6 +1108,11 @@ mod tests {
             });
         mock_connect.expect_id().return_const("S1".to_owned());
 
-        CurpNode::leader_calibrates_follower(curp, Arc::new(mock_connect)).await;
+        let snapshot_dir = SnapshotDir::open(
+            &std::env::temp_dir()
+                .join(format!("curp-calibrate-{:016x}", thread_rng().gen::<u64>())),
+        )
+        .unwrap();
+        CurpNode::leader_calibrates_follower(curp, Arc::new(mock_connect), snapshot_dir).await;
     }
 }
@@ -45,6 +45,11 @@ async fn gc_cmd_board<C: Command + 'static>(cmd_board: CmdBoardRef<C>, interval:
         let new_recv = board.sync.split_off(last_check_len_recv);
         board.sync = new_recv;
         last_check_len_recv = board.sync.len();
+
+        // the spans are removed by the after sync, so they are aged by their own times
+        board
+            .spans
+            .retain(|_, &mut (received, _)| received.elapsed() < interval);
     }
 }
 
@@ -53,6 +58,7 @@ mod tests {
     use std::{sync::Arc, time::Duration};
 
     use parking_lot::{Mutex, RwLock};
+    use tokio::time::Instant;
 
     use super::*;
     use crate::{
@@ -117,6 +123,37 @@ mod tests {
         );
     }
 
+    #[allow(unused_results, clippy::unwrap_used)]
+    #[tokio::test]
+    async fn cmd_board_span_gc_test() {
+        let board: CmdBoardRef<TestCommand> = Arc::new(RwLock::new(CommandBoard::new()));
+        tokio::spawn(gc_cmd_board(Arc::clone(&board), Duration::from_millis(500)));
+
+        // a proposal which is never after synced
+        board.write().spans.insert(
+            ProposeId::new("1".to_owned()),
+            (Instant::now(), tracing::Span::none()),
+        );
+        // at 400ms
+        tokio::time::sleep(Duration::from_millis(400)).await;
+        board.write().spans.insert(
+            ProposeId::new("2".to_owned()),
+            (Instant::now(), tracing::Span::none()),
+        );
+
+        // at 600ms, only the span older than the interval is removed
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(board.read().spans.len(), 1);
+        assert!(board
+            .read()
+            .spans
+            .contains_key(&ProposeId::new("2".to_owned())));
+
+        // at 1100ms
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert!(board.read().spans.is_empty());
+    }
+
     #[allow(unused_results, clippy::unwrap_used)]
     #[tokio::test]
     async fn spec_gc_test() {
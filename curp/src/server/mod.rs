@@ -2,6 +2,7 @@ use std::{collections::HashMap, fmt::Debug, sync::Arc};
 
 use tokio::{net::TcpListener, sync::broadcast};
 use tokio_stream::wrappers::TcpListenerStream;
+use tonic::transport::ClientTlsConfig;
 use tower::filter::FilterLayer;
 use tracing::{info, instrument};
 use utils::{config::CurpConfig, tracing::Extract};
@@ -10,11 +11,12 @@ use self::curp_node::{CurpError, CurpNode};
 use crate::{
     cmd::{Command, CommandExecutor},
     error::ServerError,
-    message::ServerId,
+    message::{LogIndex, ServerId},
     rpc::{
         AppendEntriesRequest, AppendEntriesResponse, FetchLeaderRequest, FetchLeaderResponse,
-        ProposeRequest, ProposeResponse, ProtocolServer, VoteRequest, VoteResponse,
-        WaitSyncedRequest, WaitSyncedResponse,
+        InstallSnapshotRequest, InstallSnapshotResponse, ProposeRequest, ProposeResponse,
+        ProtocolServer, TryBecomeLeaderNowRequest, TryBecomeLeaderNowResponse, VoteRequest,
+        VoteResponse, WaitSyncedRequest, WaitSyncedResponse,
     },
     TxFilter,
 };
@@ -104,10 +106,30 @@ impl<C: 'static + Command> crate::rpc::Protocol for Rpc<C> {
             self.inner.fetch_leader(request.into_inner())?,
         ))
     }
+
+    #[instrument(skip_all, name = "curp_try_become_leader_now")]
+    async fn try_become_leader_now(
+        &self,
+        request: tonic::Request<TryBecomeLeaderNowRequest>,
+    ) -> Result<tonic::Response<TryBecomeLeaderNowResponse>, tonic::Status> {
+        Ok(tonic::Response::new(
+            self.inner.try_become_leader_now(request.into_inner())?,
+        ))
+    }
+
+    #[instrument(skip_all, name = "curp_install_snapshot")]
+    async fn install_snapshot(
+        &self,
+        request: tonic::Request<InstallSnapshotRequest>,
+    ) -> Result<tonic::Response<InstallSnapshotResponse>, tonic::Status> {
+        Ok(tonic::Response::new(
+            self.inner.install_snapshot(request.into_inner()).await?,
+        ))
+    }
 }
 
 impl<C: Command + 'static> Rpc<C> {
-    /// New `Rpc`
+    /// New `Rpc`, the other servers are connected over TLS if `tls_config` is given
     ///
     /// # Panics
     /// Panic if storage creation failed
@@ -119,15 +141,19 @@ impl<C: Command + 'static> Rpc<C> {
         executor: CE,
         curp_cfg: Arc<CurpConfig>,
         tx_filter: Option<Box<dyn TxFilter>>,
+        tls_config: Option<ClientTlsConfig>,
     ) -> Self {
         #[allow(clippy::panic)]
-        let curp_node =
-            match CurpNode::new(id, is_leader, others, executor, curp_cfg, tx_filter).await {
-                Ok(n) => n,
-                Err(err) => {
-                    panic!("failed to create curp service, {err}");
-                }
-            };
+        let curp_node = match CurpNode::new(
+            id, is_leader, others, executor, curp_cfg, tx_filter, tls_config,
+        )
+        .await
+        {
+            Ok(n) => n,
+            Err(err) => {
+                panic!("failed to create curp service, {err}");
+            }
+        };
 
         Self {
             inner: Arc::new(curp_node),
@@ -163,7 +189,7 @@ impl<C: Command + 'static> Rpc<C> {
     {
         let port = server_port.unwrap_or(DEFAULT_SERVER_PORT);
         info!("RPC server {id} started, listening on port {port}");
-        let server = Self::new(id, is_leader, others, executor, curp_cfg, tx_filter).await;
+        let server = Self::new(id, is_leader, others, executor, curp_cfg, tx_filter, None).await;
 
         if let Some(f) = rx_filter {
             tonic::transport::Server::builder()
@@ -215,7 +241,7 @@ impl<C: Command + 'static> Rpc<C> {
             ) -> Result<tonic::codegen::http::Request<tonic::transport::Body>, UE>,
         UE: 'static + Send + Sync + std::error::Error,
     {
-        let server = Self::new(id, is_leader, others, executor, curp_cfg, tx_filter).await;
+        let server = Self::new(id, is_leader, others, executor, curp_cfg, tx_filter, None).await;
 
         if let Some(f) = rx_filter {
             tonic::transport::Server::builder()
@@ -232,12 +258,50 @@ impl<C: Command + 'static> Rpc<C> {
         Ok(())
     }
 
+    /// Connect to another server at its new address, eg. after it moves to another host.
+    /// The other servers are connected to by their ids given at the start, an unknown id
+    /// is ignored.
+    #[inline]
+    pub fn update_addr(&self, id: ServerId, addr: String) {
+        self.inner.update_addr(id, addr);
+    }
+
     /// Get a subscriber for leader changes
     #[inline]
     #[must_use]
     pub fn leader_rx(&self) -> broadcast::Receiver<Option<ServerId>> {
         self.inner.leader_rx()
     }
+
+    /// Get the current term
+    #[inline]
+    #[must_use]
+    pub fn term(&self) -> u64 {
+        self.inner.term()
+    }
+
+    /// Check if the server can reach a quorum of the cluster, a follower can if it has
+    /// heard from the leader recently, and the leader can if it has heard from a quorum
+    #[inline]
+    #[must_use]
+    pub fn quorum_connected(&self) -> bool {
+        self.inner.quorum_connected()
+    }
+
+    /// Get the index of the last committed log entry
+    #[inline]
+    #[must_use]
+    pub fn commit_index(&self) -> LogIndex {
+        self.inner.commit_index()
+    }
+
+    /// Hand the leadership over to the most up-to-date follower, should be called before
+    /// the leader shuts down. Return the id of the new leader, or `None` if this server is
+    /// not the leader or no new leader is elected in time
+    #[inline]
+    pub async fn transfer_leadership(&self) -> Option<ServerId> {
+        self.inner.transfer_leadership().await
+    }
 }
 
 impl From<CurpError> for tonic::Status {
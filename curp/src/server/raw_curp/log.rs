@@ -8,6 +8,8 @@ use tracing::error;
 use crate::{
     cmd::{Command, ProposeId},
     log_entry::LogEntry,
+    server::storage::LogChange,
+    snapshot::Snapshot,
 };
 
 /// Curp logs
@@ -18,8 +20,10 @@ pub(super) struct Log<C: Command> {
     entries: Vec<LogEntry<C>>,
     /// Base index in entries, is `1`(if no snapshot) or `last_log_index_in_snapshot + 1`
     base_index: usize,
-    /// Term of log[base_index]
+    /// Term of log[base_index - 1], it's `0` if no snapshot
     base_term: u64,
+    /// The last installed snapshot, the cmd executor is reset to it when the leader retires
+    snapshot: Option<Arc<Snapshot>>,
     /// Index of highest log entry known to be committed
     pub(super) commit_index: usize,
     /// Index of highest log entry applied to state machine
@@ -27,8 +31,8 @@ pub(super) struct Log<C: Command> {
     /// in that it means the index of the last log sent to the `cmd_worker`(may not be executed yet)
     /// while the `last_applied` in command executor means index of the last log entry that has been successfully applied to the command executor.
     pub(super) last_applied: usize,
-    /// Tx to send log entries to persist task
-    log_tx: mpsc::UnboundedSender<LogEntry<C>>,
+    /// Tx to send log changes to persist task
+    log_tx: mpsc::UnboundedSender<LogChange<C>>,
 }
 
 impl<C: Command> Debug for Log<C> {
@@ -45,7 +49,7 @@ impl<C: Command> Debug for Log<C> {
 impl<C: 'static + Command> Log<C> {
     /// Create a new log
     pub(super) fn new(
-        log_tx: mpsc::UnboundedSender<LogEntry<C>>,
+        log_tx: mpsc::UnboundedSender<LogChange<C>>,
         entries: Vec<LogEntry<C>>,
     ) -> Self {
         Self {
@@ -54,10 +58,24 @@ impl<C: 'static + Command> Log<C> {
             last_applied: 0,
             base_index: 1,
             base_term: 0,
+            snapshot: None,
             log_tx,
         }
     }
 
+    /// Create a log recovered from the installed snapshot and the entries after it
+    pub(super) fn recover(
+        log_tx: mpsc::UnboundedSender<LogChange<C>>,
+        snapshot: Option<Snapshot>,
+        entries: Vec<LogEntry<C>>,
+    ) -> Self {
+        let mut log = Self::new(log_tx, entries);
+        if let Some(snapshot) = snapshot {
+            log.reset_base(Arc::new(snapshot));
+        }
+        log
+    }
+
     /// Get last log index
     pub(super) fn last_log_index(&self) -> usize {
         self.entries
@@ -89,6 +107,54 @@ impl<C: 'static + Command> Log<C> {
             .flatten()
     }
 
+    /// Get the term of log[i], the term of the last entry included in the snapshot is known
+    pub(super) fn term_of(&self, i: usize) -> Option<u64> {
+        if i + 1 == self.base_index {
+            Some(self.base_term)
+        } else {
+            self.get(i).map(|entry| entry.term)
+        }
+    }
+
+    /// Get the index of the first log entry that is not included in the snapshot
+    pub(super) fn base_index(&self) -> usize {
+        self.base_index
+    }
+
+    /// Get the last installed snapshot
+    pub(super) fn snapshot(&self) -> Option<Arc<Snapshot>> {
+        self.snapshot.clone()
+    }
+
+    /// Install a snapshot sent by the leader, the entries included in the snapshot are dropped.
+    /// Return `false` if the snapshot is outdated, otherwise the command executor should be
+    /// reset to it
+    pub(super) fn install_snapshot(&mut self, snapshot: Snapshot) -> bool {
+        if snapshot.last_included_index <= self.commit_index {
+            return false;
+        }
+        let snapshot = Arc::new(snapshot);
+        self.send_change(LogChange::InstallSnapshot(Arc::clone(&snapshot)));
+        self.reset_base(snapshot);
+        true
+    }
+
+    /// Move the base of the log to the end of the snapshot, the following entries are kept
+    /// if the last included entry matches the snapshot
+    fn reset_base(&mut self, snapshot: Arc<Snapshot>) {
+        let index = snapshot.last_included_index;
+        if matches!(self.get(index), Some(entry) if entry.term == snapshot.last_included_term) {
+            let _dropped = self.entries.drain(..=self.li_to_pi(index)).count();
+        } else {
+            self.entries.clear();
+        }
+        self.base_index = index + 1;
+        self.base_term = snapshot.last_included_term;
+        self.commit_index = self.commit_index.max(index);
+        self.last_applied = self.last_applied.max(index);
+        self.snapshot = Some(snapshot);
+    }
+
     /// Try to append log entries, hand back the entries if they can't be appended
     pub(super) fn try_append_entries(
         &mut self,
@@ -97,11 +163,7 @@ impl<C: 'static + Command> Log<C> {
         prev_log_term: u64,
     ) -> Result<(), Vec<LogEntry<C>>> {
         // check if entries can be appended
-        if !(prev_log_index == 0 && prev_log_term == 0)
-            && self
-                .get(prev_log_index)
-                .map_or(true, |entry| entry.term != prev_log_term)
-        {
+        if self.term_of(prev_log_index) != Some(prev_log_term) {
             return Err(entries);
         }
 
@@ -128,7 +190,12 @@ impl<C: 'static + Command> Log<C> {
 
     /// Send log entries to persist task
     pub(super) fn send_persist(&self, entry: LogEntry<C>) {
-        if let Err(err) = self.log_tx.send(entry) {
+        self.send_change(LogChange::Append(entry));
+    }
+
+    /// Send log changes to persist task
+    fn send_change(&self, change: LogChange<C>) {
+        if let Err(err) = self.log_tx.send(change) {
             error!("failed to send log to persist, {err}");
         }
     }
@@ -164,11 +231,13 @@ impl<C: 'static + Command> Log<C> {
         self.entries.iter().map(|entry| entry.cmd.id()).collect()
     }
 
-    /// Get previous log entry's term and index
+    /// Get previous log entry's term and index, the entries included in the snapshot are
+    /// treated as the last one of them
     pub(super) fn get_prev_entry_info(&self, i: usize) -> (u64, usize) {
         assert!(i > 0);
-        if i == 1 {
-            (0, 0) // fake log[0]
+        let i = i.max(self.base_index);
+        if i == self.base_index {
+            (self.base_term, i - 1) // fake log[0] or the last entry included in the snapshot
         } else {
             let entry = self.get(i - 1).unwrap_or_else(|| {
                 unreachable!(
@@ -183,7 +252,7 @@ impl<C: 'static + Command> Log<C> {
 
 #[cfg(test)]
 mod tests {
-    use std::{ops::Index, sync::Arc};
+    use std::{ops::Index, path::PathBuf, sync::Arc};
 
     use super::*;
     use crate::test_utils::test_cmd::TestCommand;
@@ -278,4 +347,42 @@ mod tests {
         );
         assert!(result.is_err());
     }
+
+    #[test]
+    fn install_snapshot_will_drop_included_entries() {
+        let (log_tx, mut log_rx) = mpsc::unbounded_channel();
+        let mut log = Log::<TestCommand>::new(log_tx, vec![]);
+        let result = log.try_append_entries(
+            (1..=3)
+                .map(|i| LogEntry::new(i, 1, Arc::new(TestCommand::default())))
+                .collect(),
+            0,
+            0,
+        );
+        assert!(result.is_ok());
+
+        assert!(log.install_snapshot(Snapshot::new(2, 1, PathBuf::new())));
+        assert_eq!(log.base_index(), 3);
+        assert_eq!(log.term_of(2), Some(1));
+        assert_eq!(log[3].index, 3);
+        assert_eq!(log.last_log_index(), 3);
+        assert_eq!(log.commit_index, 2);
+        assert_eq!(log.last_applied, 2);
+        let changes: Vec<_> = std::iter::from_fn(|| log_rx.try_recv().ok()).collect();
+        assert!(matches!(
+            changes.last(),
+            Some(&LogChange::InstallSnapshot(ref s)) if s.last_included_index == 2
+        ));
+
+        // outdated snapshot is ignored
+        assert!(!log.install_snapshot(Snapshot::new(1, 1, PathBuf::new())));
+
+        // conflicting entries are dropped
+        assert!(log.install_snapshot(Snapshot::new(5, 2, PathBuf::new())));
+        assert_eq!(log.base_index(), 6);
+        assert_eq!(log.last_log_index(), 5);
+        assert_eq!(log.term_of(5), Some(2));
+        assert!(log.try_append_entries(vec![], 5, 2).is_ok());
+        assert!(log.try_append_entries(vec![], 3, 1).is_err());
+    }
 }
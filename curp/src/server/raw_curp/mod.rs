@@ -13,6 +13,7 @@ use std::{
     cmp::min,
     collections::{HashMap, HashSet},
     fmt::Debug,
+    path::Path,
     sync::{
         atomic::{AtomicBool, AtomicU8, Ordering},
         Arc,
@@ -22,10 +23,14 @@ use std::{
 use clippy_utilities::NumericCast;
 use itertools::Itertools;
 use parking_lot::{Mutex, RwLock, RwLockUpgradableReadGuard};
-use tokio::sync::{broadcast, mpsc};
+use tokio::{
+    sync::{broadcast, mpsc},
+    time::Instant,
+};
 use tracing::{
     debug, error,
     log::{log_enabled, Level},
+    Span,
 };
 use utils::{
     config::CurpConfig,
@@ -36,13 +41,14 @@ use self::{
     log::Log,
     state::{CandidateState, LeaderState, State},
 };
-use super::{cmd_worker::CEEventTxApi, curp_node::UncommittedPoolRef};
+use super::{cmd_worker::CEEventTxApi, curp_node::UncommittedPoolRef, storage::LogChange};
 use crate::{
     cmd::{Command, ProposeId},
     error::ProposeError,
     log_entry::LogEntry,
-    message::ServerId,
+    message::{LogIndex, ServerId},
     server::{cmd_board::CmdBoardRef, spec_pool::SpecPoolRef},
+    snapshot::Snapshot,
 };
 
 /// Curp state
@@ -175,6 +181,9 @@ impl<C: 'static + Command> RawCurp<C> {
         });
         if role == Role::Leader {
             self.tick_heartbeat()
+        } else if self.is_standby() {
+            // a standby never campaigns for the leadership
+            TickAction::Nothing
         } else {
             self.tick_election(timeout)
         }
@@ -251,6 +260,17 @@ impl<C: 'static + Command> RawCurp<C> {
         cmd: Arc<C>,
     ) -> ((Option<ServerId>, u64), Result<bool, ProposeError>) {
         debug!("{} gets proposal for cmd({})", self.id(), cmd.id());
+        // a standby doesn't count toward the quorum, the commands in its speculative pool
+        // are never recovered, so it must not accept proposals
+        if self.is_standby() {
+            return (
+                self.leader(),
+                Err(ProposeError::ProtocolError(format!(
+                    "{} is a standby, it doesn't accept proposals",
+                    self.id()
+                ))),
+            );
+        }
         let mut conflict = self
             .ctx
             .sp
@@ -271,11 +291,15 @@ impl<C: 'static + Command> RawCurp<C> {
             );
         }
 
-        if !self
-            .ctx
-            .cb
-            .map_write(|mut cb_w| cb_w.sync.insert(cmd.id().clone()))
-        {
+        if !self.ctx.cb.map_write(|mut cb_w| {
+            let inserted = cb_w.sync.insert(cmd.id().clone());
+            if inserted {
+                let _prev = cb_w
+                    .spans
+                    .insert(cmd.id().clone(), (Instant::now(), Span::current()));
+            }
+            inserted
+        }) {
             return (info, Err(ProposeError::Duplicated));
         }
 
@@ -328,26 +352,9 @@ impl<C: 'static + Command> RawCurp<C> {
         );
 
         // validate term and set leader id
-        let st_r = self.st.upgradable_read();
-        match st_r.term.cmp(&term) {
-            std::cmp::Ordering::Less => {
-                let mut st_w = RwLockUpgradableReadGuard::upgrade(st_r);
-                self.update_to_term_and_become_follower(&mut st_w, term);
-                st_w.leader_id = Some(leader_id.clone());
-                let _ig = self.ctx.leader_tx.send(Some(leader_id)).ok();
-            }
-            std::cmp::Ordering::Equal => {
-                if st_r.leader_id.is_none() {
-                    let mut st_w = RwLockUpgradableReadGuard::upgrade(st_r);
-                    st_w.leader_id = Some(leader_id.clone());
-                    let _ig = self.ctx.leader_tx.send(Some(leader_id)).ok();
-                }
-            }
-            std::cmp::Ordering::Greater => {
-                return Err((st_r.term, self.log.read().commit_index + 1))
-            }
+        if let Err(cur_term) = self.follow_leader(term, leader_id) {
+            return Err((cur_term, self.log.read().commit_index + 1));
         }
-        self.reset_election_tick();
 
         // append log entries
         let mut log_w = self.log.write();
@@ -395,6 +402,8 @@ impl<C: 'static + Command> RawCurp<C> {
         if cur_role != Role::Leader {
             return Err(());
         }
+        self.lst
+            .map_write(|mut lst_w| lst_w.record_contact(follower_id));
 
         if !success {
             let mut lst_w = self.lst.write();
@@ -434,6 +443,78 @@ impl<C: 'static + Command> RawCurp<C> {
         Ok(true)
     }
 
+    /// Handle `install_snapshot`
+    /// Return the term of self, and whether the snapshot is installed, it's not if it's from
+    /// a stale leader or it's outdated
+    pub(super) fn handle_install_snapshot(
+        &self,
+        term: u64,
+        leader_id: ServerId,
+        snapshot: Snapshot,
+    ) -> (u64, bool) {
+        let index = snapshot.last_included_index;
+        debug!(
+            "{} received install_snapshot from {}: term({}), last_included_index({}), last_included_term({})",
+            self.id(), leader_id, term, index, snapshot.last_included_term
+        );
+
+        if let Err(cur_term) = self.follow_leader(term, leader_id) {
+            return (cur_term, false);
+        }
+
+        let mut log_w = self.log.write();
+        let installed = log_w.install_snapshot(snapshot);
+        if installed {
+            self.ctx.cmd_tx.send_reset(log_w.snapshot());
+            debug!("{} installs snapshot({index})", self.id());
+        } else {
+            debug!(
+                "{} ignores snapshot({index}), its commit index is {}",
+                self.id(),
+                log_w.commit_index
+            );
+        }
+        (term, installed)
+    }
+
+    /// Check the term of a chunk of a snapshot before it's received, the chunks from a
+    /// stale leader are dropped
+    /// Return `Err(term)` with the term of self if the leader is stale
+    pub(super) fn check_snapshot_chunk(&self, term: u64, leader_id: ServerId) -> Result<(), u64> {
+        self.follow_leader(term, leader_id)
+    }
+
+    /// Handle `install_snapshot` response
+    /// Return `Err(())` if self is no longer the leader
+    pub(super) fn handle_install_snapshot_resp(
+        &self,
+        follower_id: &ServerId,
+        term: u64,
+        last_included_index: usize,
+    ) -> Result<(), ()> {
+        // validate term
+        let (cur_term, cur_role) = self.st.map_read(|st_r| (st_r.term, st_r.role));
+        if cur_term < term {
+            let mut st_w = self.st.write();
+            self.update_to_term_and_become_follower(&mut st_w, term);
+            return Err(());
+        }
+        if cur_role != Role::Leader {
+            return Err(());
+        }
+
+        let mut lst_w = self.lst.write();
+        lst_w.update_match_index(follower_id, last_included_index);
+        if lst_w.get_next_index(follower_id) <= last_included_index {
+            lst_w.update_next_index(follower_id, last_included_index + 1);
+        }
+        debug!(
+            "{} installed snapshot({last_included_index}) on follower {follower_id}",
+            self.id()
+        );
+        Ok(())
+    }
+
     /// Handle `vote`
     /// Return `Ok(term, spec_pool)` if the vote is granted
     /// Return `Err(term)` if the vote is rejected
@@ -464,8 +545,8 @@ impl<C: 'static + Command> RawCurp<C> {
             self.update_to_term_and_become_follower(&mut st_w, term);
         }
 
-        // check self role
-        if st_w.role != Role::Follower {
+        // check self role, a standby never votes
+        if st_w.role != Role::Follower || self.is_standby() {
             return Err(st_w.term);
         }
 
@@ -554,6 +635,20 @@ impl<C: 'static + Command> RawCurp<C> {
 
         Ok(true)
     }
+
+    /// Handle `try_become_leader_now` request sent by a retiring leader
+    /// Return the current term, the election will start on the next tick if the request is valid
+    pub(super) fn handle_try_become_leader_now(&self, term: u64) -> u64 {
+        let st_r = self.st.read();
+        if st_r.term != term || st_r.role != Role::Follower || self.is_standby() {
+            return st_r.term;
+        }
+        debug!("{} is asked to start an election at term {term}", self.id());
+        self.ctx
+            .election_tick
+            .store(st_r.follower_timeout_ticks, Ordering::Relaxed);
+        st_r.term
+    }
 }
 
 /// Other small public interface
@@ -571,7 +666,7 @@ impl<C: 'static + Command> RawCurp<C> {
         cmd_tx: Box<dyn CEEventTxApi<C>>,
         sync_tx: mpsc::UnboundedSender<usize>,
         calibrate_tx: mpsc::UnboundedSender<ServerId>,
-        log_tx: mpsc::UnboundedSender<LogEntry<C>>,
+        log_tx: mpsc::UnboundedSender<LogChange<C>>,
     ) -> Self {
         let next_index = others.iter().map(|o| (o.clone(), 1)).collect();
         let match_index = others.iter().map(|o| (o.clone(), 0)).collect();
@@ -623,8 +718,9 @@ impl<C: 'static + Command> RawCurp<C> {
         cmd_tx: Box<dyn CEEventTxApi<C>>,
         sync_tx: mpsc::UnboundedSender<usize>,
         calibrate_tx: mpsc::UnboundedSender<ServerId>,
-        log_tx: mpsc::UnboundedSender<LogEntry<C>>,
+        log_tx: mpsc::UnboundedSender<LogChange<C>>,
         voted_for: Option<(u64, ServerId)>,
+        snapshot: Option<Snapshot>,
         entries: Vec<LogEntry<C>>,
         last_applied: usize,
     ) -> Self {
@@ -661,7 +757,7 @@ impl<C: 'static + Command> RawCurp<C> {
             log_w.commit_index = last_applied;
         });
 
-        raw_curp.log = RwLock::new(Log::new(log_tx, entries));
+        raw_curp.log = RwLock::new(Log::recover(log_tx, snapshot, entries));
 
         raw_curp
     }
@@ -671,6 +767,32 @@ impl<C: 'static + Command> RawCurp<C> {
         self.st.map_read(|st_r| (st_r.leader_id.clone(), st_r.term))
     }
 
+    /// Check if the server can reach a quorum: a follower has heard from the leader within
+    /// the election timeout, and the leader has heard from a quorum within the timeout
+    pub(super) fn quorum_connected(&self) -> bool {
+        let (role, has_leader) = self
+            .st
+            .map_read(|st_r| (st_r.role, st_r.leader_id.is_some()));
+        match role {
+            Role::Leader => {
+                let timeout =
+                    self.cfg().heartbeat_interval * u32::from(self.cfg().follower_timeout_ticks);
+                let contacted: u64 = self
+                    .lst
+                    .map_read(|lst_r| lst_r.contacted_within(timeout, &self.cfg().standbys))
+                    .numeric_cast();
+                contacted + 1 >= self.quorum()
+            }
+            Role::Follower => has_leader,
+            Role::Candidate => false,
+        }
+    }
+
+    /// Get the commit index
+    pub(super) fn commit_index(&self) -> LogIndex {
+        self.log.map_read(|log_r| log_r.commit_index.numeric_cast())
+    }
+
     /// Get self's id
     pub(super) fn id(&self) -> &ServerId {
         &self.ctx.id
@@ -726,6 +848,56 @@ impl<C: 'static + Command> RawCurp<C> {
         })
     }
 
+    /// Ask the cmd worker to take a snapshot into the file at `path` for `follower_id` if it's
+    /// too far behind, the snapshot will be sent back through the returned rx, attached with
+    /// the leader's term
+    /// Return `Ok(None)` if the follower can catch up with log entries
+    /// Return `Err(())` if self is no longer the leader
+    #[allow(clippy::type_complexity)] // it's clear
+    pub(super) fn snapshot_for(
+        &self,
+        follower_id: &ServerId,
+        path: &Path,
+    ) -> Result<Option<(u64, flume::Receiver<Result<Snapshot, String>>)>, ()> {
+        let st_r = self.st.read();
+        if st_r.role != Role::Leader {
+            return Err(());
+        }
+        let next_index = self.lst.map_read(|lst_r| lst_r.get_next_index(follower_id));
+        let log_r = self.log.read();
+        if next_index >= log_r.base_index()
+            && next_index + self.cfg().snapshot_threshold > log_r.last_applied
+        {
+            return Ok(None);
+        }
+        let index = log_r.last_applied;
+        let term = log_r.term_of(index).unwrap_or_else(|| {
+            unreachable!("system corrupted, leader has applied log[{index}] without having it")
+        });
+        let (tx, rx) = flume::bounded(1);
+        self.ctx
+            .cmd_tx
+            .send_snapshot(Snapshot::new(index, term, path.to_owned()), tx);
+        debug!(
+            "{} takes snapshot({index}) for follower {follower_id} whose next_index is {next_index}",
+            self.id()
+        );
+        Ok(Some((st_r.term, rx)))
+    }
+
+    /// Pick the follower with the most up-to-date log to take over the leadership
+    /// Return `None` if self is not the leader or there is no other server
+    pub(super) fn leader_transferee(&self) -> Option<(u64, ServerId)> {
+        let st_r = self.st.read();
+        if st_r.role != Role::Leader {
+            return None;
+        }
+        let lst_r = self.lst.read();
+        self.voters()
+            .max_by_key(|id| lst_r.get_match_index(id))
+            .map(|id| (st_r.term, id.clone()))
+    }
+
     /// Optimize out heartbeat
     pub(super) fn opt_out_hb(&self) {
         self.ctx.hb_opt.store(true, Ordering::Relaxed);
@@ -807,6 +979,30 @@ impl<C: 'static + Command> RawCurp<C> {
         );
     }
 
+    /// Validate the term of a request from the leader and follow the leader
+    /// Return `Err(term)` if the request is from a stale leader
+    fn follow_leader(&self, term: u64, leader_id: ServerId) -> Result<(), u64> {
+        let st_r = self.st.upgradable_read();
+        match st_r.term.cmp(&term) {
+            std::cmp::Ordering::Less => {
+                let mut st_w = RwLockUpgradableReadGuard::upgrade(st_r);
+                self.update_to_term_and_become_follower(&mut st_w, term);
+                st_w.leader_id = Some(leader_id.clone());
+                let _ig = self.ctx.leader_tx.send(Some(leader_id)).ok();
+            }
+            std::cmp::Ordering::Equal => {
+                if st_r.leader_id.is_none() {
+                    let mut st_w = RwLockUpgradableReadGuard::upgrade(st_r);
+                    st_w.leader_id = Some(leader_id.clone());
+                    let _ig = self.ctx.leader_tx.send(Some(leader_id)).ok();
+                }
+            }
+            std::cmp::Ordering::Greater => return Err(st_r.term),
+        }
+        self.reset_election_tick();
+        Ok(())
+    }
+
     /// Reset election tick
     fn reset_election_tick(&self) {
         self.ctx.election_tick.store(0, Ordering::Relaxed);
@@ -830,9 +1026,7 @@ impl<C: 'static + Command> RawCurp<C> {
         }
 
         let replicated_cnt: u64 = self
-            .ctx
-            .others
-            .iter()
+            .voters()
             .filter(|&id| lst.get_match_index(id) >= i)
             .count()
             .numeric_cast();
@@ -915,9 +1109,24 @@ impl<C: 'static + Command> RawCurp<C> {
         }
     }
 
-    /// Get quorum: the smallest number of servers who must be online for the cluster to work
+    /// Get quorum: the smallest number of servers who must be online for the cluster to work,
+    /// the standbys are not counted
     fn quorum(&self) -> u64 {
-        (self.ctx.others.len() / 2 + 1).numeric_cast()
+        (self.voters().count() / 2 + 1).numeric_cast()
+    }
+
+    /// Check if self is a standby, which replicates the log but never campaigns for the
+    /// leadership or counts toward the quorum
+    fn is_standby(&self) -> bool {
+        self.cfg().standbys.contains(self.id())
+    }
+
+    /// Get the other servers which count toward the quorum
+    fn voters(&self) -> impl Iterator<Item = &ServerId> {
+        self.ctx
+            .others
+            .iter()
+            .filter(|&id| !self.cfg().standbys.contains(id))
     }
 
     /// Get superquorum: the smallest number of servers who must contain a command in speculative pool for it to be recovered
@@ -929,14 +1138,15 @@ impl<C: 'static + Command> RawCurp<C> {
     fn leader_retires(&self) {
         debug!("leader {} retires", self.id());
 
-        // when a leader retires, it should wipe up speculatively executed cmds by resetting and re-executing
-        self.ctx.cmd_tx.send_reset();
+        // when a leader retires, it should wipe up speculatively executed cmds by resetting and re-executing,
+        // the cmds included in the installed snapshot can't be re-executed, so it resets to the snapshot
+        let log_r = self.log.read();
+        self.ctx.cmd_tx.send_reset(log_r.snapshot());
 
         let mut cb_w = self.ctx.cb.write();
         cb_w.clear();
 
-        let log_r = self.log.read();
-        for i in 1..=log_r.commit_index {
+        for i in log_r.base_index()..=log_r.commit_index {
             let entry = log_r.get(i).unwrap_or_else(|| {
                 unreachable!(
                     "system corrupted, apply log[{i}] when we only have {} log entries",
@@ -1,6 +1,7 @@
 use std::{
     collections::{HashMap, HashSet},
     sync::Arc,
+    time::{Duration, Instant},
 };
 
 use madsim::rand::{thread_rng, Rng};
@@ -53,6 +54,8 @@ pub(super) struct LeaderState {
     match_index: HashMap<ServerId, usize>,
     /// Servers that are being calibrated by the leader
     pub(super) calibrating: HashSet<ServerId>,
+    /// For each server, when the last `append_entries` response is received from it
+    last_contact: HashMap<ServerId, Instant>,
 }
 
 impl State {
@@ -99,9 +102,28 @@ impl LeaderState {
             next_index,
             match_index,
             calibrating: HashSet::new(),
+            last_contact: HashMap::new(),
         }
     }
 
+    /// Record that a response is received from server
+    pub(super) fn record_contact(&mut self, id: &ServerId) {
+        let _prev = self.last_contact.insert(id.clone(), Instant::now());
+    }
+
+    /// Count the servers from which a response is received within `timeout`, the standbys
+    /// are not counted
+    pub(super) fn contacted_within(
+        &self,
+        timeout: Duration,
+        standbys: &HashSet<ServerId>,
+    ) -> usize {
+        self.last_contact
+            .iter()
+            .filter(|&(id, last)| !standbys.contains(id) && last.elapsed() < timeout)
+            .count()
+    }
+
     /// Get `next_index` for server
     pub(super) fn get_next_index(&self, id: &ServerId) -> usize {
         *self
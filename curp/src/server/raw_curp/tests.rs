@@ -1,4 +1,4 @@
-use std::time::Instant;
+use std::{path::PathBuf, time::Instant};
 
 use tokio::time::sleep;
 use tracing_test::traced_test;
@@ -23,12 +23,22 @@ impl<C: 'static + Command> RawCurp<C> {
         self.st.read().role
     }
 
-    pub(crate) fn commit_index(&self) -> usize {
-        self.log.read().commit_index
+    pub(crate) fn new_test<Tx: CEEventTxApi<C>>(n: u64, exe_tx: Tx) -> Self {
+        Self::new_test_with_standbys(n, &[], exe_tx)
     }
 
-    pub(crate) fn new_test<Tx: CEEventTxApi<C>>(n: u64, exe_tx: Tx) -> Self {
+    /// New a test `RawCurp` whose servers `S{i}` in `standbys` are standbys, `S0` is the
+    /// leader unless it's a standby
+    pub(crate) fn new_test_with_standbys<Tx: CEEventTxApi<C>>(
+        n: u64,
+        standbys: &[u64],
+        exe_tx: Tx,
+    ) -> Self {
         let others = (1..n).map(|i| format!("S{i}")).collect();
+        let cfg = CurpConfig {
+            standbys: standbys.iter().map(|i| format!("S{i}")).collect(),
+            ..CurpConfig::default()
+        };
         let cmd_board = Arc::new(RwLock::new(CommandBoard::new()));
         let spec_pool = Arc::new(Mutex::new(SpeculativePool::new()));
         let uncommitted_pool = Arc::new(Mutex::new(UncommittedPool::new()));
@@ -38,11 +48,11 @@ impl<C: 'static + Command> RawCurp<C> {
         Self::new(
             "S0".to_owned(),
             others,
-            true,
+            !standbys.contains(&0),
             cmd_board,
             spec_pool,
             uncommitted_pool,
-            Arc::new(CurpConfig::default()),
+            Arc::new(cfg),
             Box::new(exe_tx),
             sync_tx,
             calibrate_tx,
@@ -205,6 +215,21 @@ fn heartbeat_will_calibrate_term() {
     assert_eq!(st_r.role, Role::Follower);
 }
 
+#[traced_test]
+#[test]
+fn leader_is_quorum_connected_after_hearing_from_quorum() {
+    let curp = RawCurp::new_test(5, MockCEEventTxApi::<TestCommand>::default());
+    assert!(!curp.quorum_connected());
+
+    let result = curp.handle_append_entries_resp(&"S1".to_owned(), None, 0, true, 0);
+    assert_eq!(result, Ok(true));
+    assert!(!curp.quorum_connected());
+
+    let result = curp.handle_append_entries_resp(&"S2".to_owned(), None, 0, true, 0);
+    assert_eq!(result, Ok(true));
+    assert!(curp.quorum_connected());
+}
+
 #[traced_test]
 #[test]
 fn heartbeat_will_calibrate_next_index() {
@@ -491,6 +516,44 @@ fn vote_will_calibrate_candidate_term() {
     assert_eq!(st_r.role, Role::Follower);
 }
 
+/*************** tests for leadership transfer **************/
+
+#[traced_test]
+#[test]
+fn leader_will_pick_the_most_up_to_date_follower_as_transferee() {
+    let curp = {
+        let exe_tx = MockCEEventTxApi::<TestCommand>::default();
+        Arc::new(RawCurp::new_test(3, exe_tx))
+    };
+    curp.lst
+        .map_write(|mut lst_w| lst_w.update_match_index(&"S2".to_owned(), 1));
+
+    let (term, transferee) = curp.leader_transferee().unwrap();
+    assert_eq!(term, 0);
+    assert_eq!(transferee, "S2");
+}
+
+#[traced_test]
+#[test]
+fn follower_will_start_election_on_next_tick_after_try_become_leader_now() {
+    let curp = {
+        let mut exe_tx = MockCEEventTxApi::<TestCommand>::default();
+        exe_tx.expect_send_reset().return_const(());
+        Arc::new(RawCurp::new_test(3, exe_tx))
+    };
+    curp.update_to_term_and_become_follower(&mut *curp.st.write(), 1);
+    assert!(curp.leader_transferee().is_none());
+
+    // request from an outdated leader is ignored
+    assert_eq!(curp.handle_try_become_leader_now(0), 1);
+    assert!(matches!(curp.tick(), TickAction::Nothing));
+
+    assert_eq!(curp.handle_try_become_leader_now(1), 1);
+    assert!(matches!(curp.tick(), TickAction::Votes(_)));
+    assert_eq!(curp.role(), Role::Candidate);
+    assert_eq!(curp.term(), 2);
+}
+
 /*************** tests for recovery **************/
 
 #[traced_test]
@@ -529,6 +592,92 @@ fn recover_from_spec_pools_will_pick_the_correct_cmds() {
     });
 }
 
+/*************** tests for snapshot **************/
+
+#[traced_test]
+#[test]
+fn leader_will_take_snapshot_for_follower_behind_its_base() {
+    let curp = {
+        let mut exe_tx = MockCEEventTxApi::<TestCommand>::default();
+        exe_tx
+            .expect_send_snapshot()
+            .times(1)
+            .returning(|snapshot, _tx| {
+                assert_eq!(
+                    (snapshot.last_included_index, snapshot.last_included_term),
+                    (2, 0)
+                );
+                assert_eq!(snapshot.path, PathBuf::from("snapshot"));
+            });
+        RawCurp::new_test(3, exe_tx)
+    };
+    let s1_id = "S1".to_owned();
+    curp.push_cmd(Arc::new(TestCommand::default()));
+    curp.push_cmd(Arc::new(TestCommand::default()));
+    curp.push_cmd(Arc::new(TestCommand::default()));
+    assert!(matches!(
+        curp.snapshot_for(&s1_id, Path::new("snapshot")),
+        Ok(None)
+    ));
+
+    // entries before log[3] are compacted by a snapshot
+    assert!(curp
+        .log
+        .write()
+        .install_snapshot(Snapshot::new(2, 0, PathBuf::new())));
+    let (term, _rx) = curp
+        .snapshot_for(&s1_id, Path::new("snapshot"))
+        .unwrap()
+        .unwrap();
+    assert_eq!(term, 0);
+
+    assert!(curp.handle_install_snapshot_resp(&s1_id, 0, 2).is_ok());
+    assert_eq!(curp.lst.read().get_match_index(&s1_id), 2);
+    assert_eq!(curp.lst.read().get_next_index(&s1_id), 3);
+    assert!(matches!(
+        curp.snapshot_for(&s1_id, Path::new("snapshot")),
+        Ok(None)
+    ));
+}
+
+#[traced_test]
+#[test]
+fn follower_handle_install_snapshot_will_reset_ce() {
+    let curp = {
+        let mut exe_tx = MockCEEventTxApi::<TestCommand>::default();
+        exe_tx
+            .expect_send_reset()
+            .withf(Option::is_none)
+            .return_const(());
+        exe_tx
+            .expect_send_reset()
+            .withf(|snapshot| {
+                snapshot
+                    .as_ref()
+                    .map_or(false, |s| s.last_included_index == 5)
+            })
+            .times(1)
+            .return_const(());
+        Arc::new(RawCurp::new_test(3, exe_tx))
+    };
+    curp.update_to_term_and_become_follower(&mut *curp.st.write(), 1);
+
+    let (term, installed) =
+        curp.handle_install_snapshot(2, "S2".to_owned(), Snapshot::new(5, 2, PathBuf::new()));
+    assert_eq!((term, installed), (2, true));
+    assert_eq!(curp.st.read().leader_id, Some("S2".to_owned()));
+    assert_eq!(curp.commit_index(), 5);
+
+    // a snapshot from an old term is rejected
+    let (term, installed) =
+        curp.handle_install_snapshot(1, "S1".to_owned(), Snapshot::new(8, 1, PathBuf::new()));
+    assert_eq!((term, installed), (2, false));
+    // so are the chunks of it
+    assert_eq!(curp.check_snapshot_chunk(1, "S1".to_owned()), Err(2));
+    assert_eq!(curp.check_snapshot_chunk(2, "S2".to_owned()), Ok(()));
+    assert_eq!(curp.commit_index(), 5);
+}
+
 /*************** tests for other small functions **************/
 
 #[traced_test]
@@ -542,3 +691,54 @@ fn quorum() {
     assert_eq!(curp.quorum(), 3);
     assert_eq!(curp.superquorum(), 2);
 }
+
+#[traced_test]
+#[test]
+fn standbys_should_not_count_toward_quorum() {
+    let curp = {
+        let mut exe_tx = MockCEEventTxApi::<TestCommand>::default();
+        exe_tx.expect_send_sp_exe().returning(|_| {});
+        exe_tx.expect_send_after_sync().returning(|_, _| {});
+        RawCurp::new_test_with_standbys(5, &[3, 4], exe_tx)
+    };
+    assert_eq!(curp.quorum(), 2);
+
+    let cmd = Arc::new(TestCommand::default());
+    let (_info, result) = curp.handle_propose(cmd);
+    assert!(result.is_ok());
+    for standby in ["S3", "S4"] {
+        let result = curp.handle_append_entries_resp(&standby.to_owned(), Some(1), 0, true, 1);
+        assert_eq!(result, Ok(true));
+    }
+    assert_eq!(curp.commit_index(), 0);
+    assert!(!curp.quorum_connected());
+
+    let result = curp.handle_append_entries_resp(&"S1".to_owned(), Some(1), 0, true, 1);
+    assert_eq!(result, Ok(true));
+    assert_eq!(curp.commit_index(), 1);
+    assert!(curp.quorum_connected());
+    assert_eq!(
+        curp.leader_transferee().map(|(_, id)| id),
+        Some("S1".to_owned())
+    );
+}
+
+#[traced_test]
+#[test]
+fn standby_should_never_campaign_vote_or_accept_proposals() {
+    let curp = RawCurp::new_test_with_standbys(3, &[0], MockCEEventTxApi::<TestCommand>::default());
+    assert_eq!(curp.role(), Role::Follower);
+    for _ in 0..100 {
+        assert!(matches!(curp.tick(), TickAction::Nothing));
+    }
+    assert_eq!(curp.role(), Role::Follower);
+
+    assert!(curp.handle_vote(1, "S1".to_owned(), 0, 0).is_err());
+    assert_eq!(curp.term(), 1);
+    assert_eq!(curp.handle_try_become_leader_now(1), 1);
+    assert!(matches!(curp.tick(), TickAction::Nothing));
+
+    let (_info, result) = curp.handle_propose(Arc::new(TestCommand::default()));
+    assert!(matches!(result, Err(ProposeError::ProtocolError(_))));
+    assert!(curp.spec_pool().lock().pool.is_empty());
+}
@@ -1,8 +1,10 @@
+use std::sync::Arc;
+
 use async_trait::async_trait;
 use engine::error::EngineError;
 use thiserror::Error;
 
-use crate::{cmd::Command, log_entry::LogEntry, message::ServerId};
+use crate::{cmd::Command, log_entry::LogEntry, message::ServerId, snapshot::Snapshot};
 
 /// Storage layer error
 #[derive(Error, Debug)]
@@ -15,6 +17,14 @@ pub(super) enum StorageError {
     Internal(#[from] EngineError),
 }
 
+/// Changes of the log to be persisted in order
+pub(in crate::server) enum LogChange<C> {
+    /// A log entry is appended
+    Append(LogEntry<C>),
+    /// A snapshot is installed, the entries included in it are dropped
+    InstallSnapshot(Arc<Snapshot>),
+}
+
 /// Curp storage api
 #[async_trait]
 pub(super) trait StorageApi: Send + Sync {
@@ -27,11 +37,23 @@ pub(super) trait StorageApi: Send + Sync {
     /// Put log entries in storage
     async fn put_log_entry(&self, entry: LogEntry<Self::Command>) -> Result<(), StorageError>;
 
+    /// Put the installed snapshot in storage and drop the log entries included in it, must
+    /// be flushed on disk before returning
+    async fn put_snapshot(&self, snapshot: &Snapshot) -> Result<(), StorageError>;
+
     /// Recover from persisted storage
-    /// Return `voted_for` and all log entries
+    /// Return `voted_for`, the installed snapshot and all log entries after it
+    #[allow(clippy::type_complexity)] // it's clear
     async fn recover(
         &self,
-    ) -> Result<(Option<(u64, ServerId)>, Vec<LogEntry<Self::Command>>), StorageError>;
+    ) -> Result<
+        (
+            Option<(u64, ServerId)>,
+            Option<Snapshot>,
+            Vec<LogEntry<Self::Command>>,
+        ),
+        StorageError,
+    >;
 }
 
 /// `RocksDB` storage implementation
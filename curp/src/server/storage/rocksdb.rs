@@ -1,14 +1,17 @@
-use std::{marker::PhantomData, path::Path};
+use std::{marker::PhantomData, mem::size_of, path::Path};
 
 use async_trait::async_trait;
 use engine::{rocksdb_engine::RocksEngine, StorageEngine, WriteOperation};
 
 use super::{StorageApi, StorageError};
-use crate::{cmd::Command, log_entry::LogEntry, message::ServerId};
+use crate::{cmd::Command, log_entry::LogEntry, message::ServerId, snapshot::Snapshot};
 
 /// Key for persisted state
 const VOTE_FOR: &[u8] = b"VoteFor";
 
+/// Key for the installed snapshot
+const SNAPSHOT: &[u8] = b"InstalledSnapshot";
+
 /// Column family name for curp storage
 const CF: &str = "curp";
 
@@ -34,6 +37,7 @@ impl<C: 'static + Command> StorageApi for RocksDBStorage<C> {
     }
 
     async fn put_log_entry(&self, entry: LogEntry<Self::Command>) -> Result<(), StorageError> {
+        fail::fail_point!("curp_before_put_log_entry");
         let bytes = bincode::serialize(&entry)?;
         let op = WriteOperation::new_put(CF, entry.index.to_be_bytes().to_vec(), bytes);
         self.db.write_batch(vec![op], false)?;
@@ -41,23 +45,53 @@ impl<C: 'static + Command> StorageApi for RocksDBStorage<C> {
         Ok(())
     }
 
+    async fn put_snapshot(&self, snapshot: &Snapshot) -> Result<(), StorageError> {
+        let bytes = bincode::serialize(snapshot)?;
+        #[allow(clippy::integer_arithmetic)] // won't overflow
+        let end = (snapshot.last_included_index + 1).to_be_bytes();
+        let ops = vec![
+            WriteOperation::new_put(CF, SNAPSHOT.to_vec(), bytes),
+            WriteOperation::new_delete_range(CF, 0_usize.to_be_bytes(), end),
+        ];
+        fail::fail_point!("curp_before_put_snapshot");
+        self.db.write_batch(ops, true)?;
+
+        Ok(())
+    }
+
     async fn recover(
         &self,
-    ) -> Result<(Option<(u64, ServerId)>, Vec<LogEntry<Self::Command>>), StorageError> {
+    ) -> Result<
+        (
+            Option<(u64, ServerId)>,
+            Option<Snapshot>,
+            Vec<LogEntry<Self::Command>>,
+        ),
+        StorageError,
+    > {
         let voted_for = self
             .db
             .get(CF, VOTE_FOR)?
             .map(|bytes| bincode::deserialize::<(u64, ServerId)>(&bytes))
             .transpose()?;
+        let snapshot = self
+            .db
+            .get(CF, SNAPSHOT)?
+            .map(|bytes| bincode::deserialize::<Snapshot>(&bytes))
+            .transpose()?;
 
         let mut entries = vec![];
-        let mut prev_index = 0;
+        let mut prev_index = snapshot.as_ref().map_or(0, |s| s.last_included_index);
         for (k, v) in self.db.get_all(CF)? {
             // we can identify whether a kv is state or entry by the key length
-            if k.len() == VOTE_FOR.len() {
+            if k.len() != size_of::<usize>() {
                 continue;
             }
             let entry: LogEntry<C> = bincode::deserialize(&v)?;
+            if entry.index <= prev_index {
+                // the entry is included in the snapshot
+                continue;
+            }
             #[allow(clippy::integer_arithmetic)] // won't overflow
             if entry.index != prev_index + 1 {
                 // break when logs are no longer consistent
@@ -67,7 +101,7 @@ impl<C: 'static + Command> StorageApi for RocksDBStorage<C> {
             entries.push(entry);
         }
 
-        Ok((voted_for, entries))
+        Ok((voted_for, snapshot, entries))
     }
 }
 
@@ -84,7 +118,7 @@ impl<C> RocksDBStorage<C> {
 
 #[cfg(test)]
 mod tests {
-    use std::{error::Error, sync::Arc};
+    use std::{error::Error, path::PathBuf, sync::Arc};
 
     use tokio::fs::remove_dir_all;
 
@@ -110,7 +144,8 @@ mod tests {
 
         {
             let s = RocksDBStorage::<TestCommand>::new(&db_dir)?;
-            let (voted_for, entries) = s.recover().await?;
+            let (voted_for, snapshot, entries) = s.recover().await?;
+            assert!(snapshot.is_none());
             assert_eq!(voted_for, Some((3, "S1".to_string())));
             assert_eq!(entries[0].index, 1);
             assert_eq!(entries[1].index, 2);
@@ -121,4 +156,40 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn put_snapshot_will_drop_included_entries() -> Result<(), Box<dyn Error>> {
+        let db_dir = format!("/tmp/curp-{}", random_id());
+
+        {
+            let s = RocksDBStorage::<TestCommand>::new(&db_dir)?;
+            s.flush_voted_for(2, "S1".to_string()).await?;
+            for i in 1..=3 {
+                s.put_log_entry(LogEntry::new(i, 1, Arc::new(TestCommand::default())))
+                    .await?;
+            }
+            s.put_snapshot(&Snapshot::new(2, 1, PathBuf::from("snapshot")))
+                .await?;
+            s.put_log_entry(LogEntry::new(4, 2, Arc::new(TestCommand::default())))
+                .await?;
+        }
+
+        {
+            let s = RocksDBStorage::<TestCommand>::new(&db_dir)?;
+            let (voted_for, snapshot, entries) = s.recover().await?;
+            assert_eq!(voted_for, Some((2, "S1".to_string())));
+            assert_eq!(
+                snapshot,
+                Some(Snapshot::new(2, 1, PathBuf::from("snapshot")))
+            );
+            assert_eq!(
+                entries.iter().map(|e| e.index).collect::<Vec<_>>(),
+                vec![3, 4]
+            );
+        }
+
+        remove_dir_all(db_dir).await?;
+
+        Ok(())
+    }
 }
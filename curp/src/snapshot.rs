@@ -0,0 +1,181 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use madsim::rand::{thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+/// Directory of the snapshot files in the data dir
+const SNAPSHOT_DIR: &str = "snapshots";
+/// Extension of the files of the installed snapshots
+const INSTALLED_EXTENSION: &str = "snap";
+/// Extension of the files of the snapshots being transferred
+const TRANSFER_EXTENSION: &str = "tmp";
+
+/// Snapshot of the command executor, it's used to catch up a follower which is too far behind
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Snapshot {
+    /// Index of the last log entry included in the snapshot
+    pub(crate) last_included_index: usize,
+    /// Term of the last log entry included in the snapshot
+    pub(crate) last_included_term: u64,
+    /// File of the snapshot data taken by the command executor
+    pub(crate) path: PathBuf,
+}
+
+impl Snapshot {
+    /// Create a new `Snapshot`
+    pub(crate) fn new(last_included_index: usize, last_included_term: u64, path: PathBuf) -> Self {
+        Self {
+            last_included_index,
+            last_included_term,
+            path,
+        }
+    }
+}
+
+/// Directory of the snapshot files. The snapshots are streamed through the files when they
+/// are taken for a follower and received from the leader, so that a snapshot is never held
+/// in memory as a whole, and the installed snapshot is kept in a file until another one is
+/// installed.
+#[derive(Debug, Clone)]
+pub(crate) struct SnapshotDir {
+    /// Path of the directory
+    dir: PathBuf,
+}
+
+impl SnapshotDir {
+    /// Open the snapshot dir in `data_dir`, the files of the transfers interrupted by a
+    /// restart are removed
+    pub(crate) fn open(data_dir: &Path) -> io::Result<Self> {
+        let dir = data_dir.join(SNAPSHOT_DIR);
+        fs::create_dir_all(&dir)?;
+        let snapshot_dir = Self { dir };
+        snapshot_dir.remove_files(TRANSFER_EXTENSION, None)?;
+        Ok(snapshot_dir)
+    }
+
+    /// A new file to take a snapshot for `follower_id` in, it's removed once it's sent
+    pub(crate) fn send_file(&self, follower_id: &str) -> TransferFile {
+        TransferFile::new(self.unique_path(&format!("send-{follower_id}"), TRANSFER_EXTENSION))
+    }
+
+    /// A new file to receive a snapshot from the leader in
+    pub(crate) fn receive_file(&self) -> TransferFile {
+        TransferFile::new(self.unique_path("receive", TRANSFER_EXTENSION))
+    }
+
+    /// Path of a new file to install the snapshot up to `index` in
+    pub(crate) fn installed_path(&self, index: usize, term: u64) -> PathBuf {
+        self.unique_path(&format!("snapshot-{index}-{term}"), INSTALLED_EXTENSION)
+    }
+
+    /// Remove the installed snapshots other than `installed`, they are replaced by it
+    pub(crate) fn remove_installed_except(&self, installed: Option<&Path>) -> io::Result<()> {
+        self.remove_files(INSTALLED_EXTENSION, installed)
+    }
+
+    /// Path of a new file, a random suffix keeps it from replacing another file
+    fn unique_path(&self, name: &str, extension: &str) -> PathBuf {
+        let suffix: u64 = thread_rng().gen();
+        self.dir.join(format!("{name}-{suffix:016x}.{extension}"))
+    }
+
+    /// Remove the files with `extension` except `keep`
+    fn remove_files(&self, extension: &str, keep: Option<&Path>) -> io::Result<()> {
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().map_or(false, |ext| ext == extension)
+                && Some(path.as_path()) != keep
+            {
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// File of a snapshot being transferred, it's removed when it's dropped unless it's kept as
+/// an installed snapshot, so an interrupted transfer leaves nothing behind
+#[derive(Debug)]
+pub(crate) struct TransferFile {
+    /// Path of the file, `None` once it's kept
+    path: Option<PathBuf>,
+}
+
+impl TransferFile {
+    /// New `TransferFile`
+    fn new(path: PathBuf) -> Self {
+        Self { path: Some(path) }
+    }
+
+    /// Path of the file
+    pub(crate) fn path(&self) -> &Path {
+        self.path
+            .as_deref()
+            .unwrap_or_else(|| unreachable!("the transfer file is kept"))
+    }
+
+    /// Keep the file as `path`
+    pub(crate) fn keep(mut self, path: &Path) -> io::Result<()> {
+        if let Some(ref from) = self.path {
+            fs::rename(from, path)?;
+        }
+        self.path = None;
+        Ok(())
+    }
+}
+
+impl Drop for TransferFile {
+    fn drop(&mut self) {
+        if let Some(path) = self.path.take() {
+            if let Err(e) = fs::remove_file(&path) {
+                if e.kind() != io::ErrorKind::NotFound {
+                    warn!("failed to remove snapshot file {path:?}, {e}");
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshot_dir_removes_stale_files() {
+        let data_dir = std::env::temp_dir().join(format!(
+            "curp-snapshot-dir-{:016x}",
+            thread_rng().gen::<u64>()
+        ));
+        let snapshot_dir = SnapshotDir::open(&data_dir).unwrap();
+
+        let sent = snapshot_dir.send_file("S1");
+        fs::write(sent.path(), b"snapshot").unwrap();
+        let sent_path = sent.path().to_owned();
+        drop(sent);
+        assert!(!sent_path.exists());
+
+        let old = snapshot_dir.installed_path(2, 1);
+        fs::write(&old, b"old").unwrap();
+        let received = snapshot_dir.receive_file();
+        fs::write(received.path(), b"new").unwrap();
+        let new = snapshot_dir.installed_path(5, 1);
+        received.keep(&new).unwrap();
+        assert_eq!(fs::read(&new).unwrap(), b"new");
+
+        // a transfer interrupted by a restart is removed at the next start
+        let interrupted_path = snapshot_dir.unique_path("receive", TRANSFER_EXTENSION);
+        fs::write(&interrupted_path, b"partial").unwrap();
+        let snapshot_dir = SnapshotDir::open(&data_dir).unwrap();
+        assert!(!interrupted_path.exists());
+
+        snapshot_dir.remove_installed_except(Some(&new)).unwrap();
+        assert!(!old.exists());
+        assert!(new.exists());
+
+        fs::remove_dir_all(data_dir).unwrap();
+    }
+}
@@ -21,8 +21,8 @@ use tokio_stream::wrappers::TcpListenerStream;
 use tracing::debug;
 use utils::config::{
     default_candidate_timeout_ticks, default_follower_timeout_ticks, default_heartbeat_interval,
-    default_retry_timeout, default_rpc_timeout, default_server_wait_synced_timeout, ClientTimeout,
-    CurpConfig,
+    default_retry_timeout, default_rpc_timeout, default_server_wait_synced_timeout,
+    default_snapshot_threshold, ClientTimeout, CurpConfig,
 };
 
 use crate::common::{
@@ -167,6 +167,7 @@ impl CurpGroup {
                             default_follower_timeout_ticks(),
                             default_candidate_timeout_ticks(),
                             PathBuf::from(storage_path_c),
+                            default_snapshot_threshold(),
                         )),
                         Some(Box::new(TestTxFilter::new(Arc::clone(&switch_c)))),
                         Some(reachable_layer),
@@ -294,6 +295,7 @@ impl CurpGroup {
                     default_follower_timeout_ticks(),
                     default_candidate_timeout_ticks(),
                     PathBuf::from(storage_path),
+                    default_snapshot_threshold(),
                 )),
                 Some(Box::new(TestTxFilter::new(Arc::clone(&switch_c)))),
                 Some(reachable_layer),
@@ -1,6 +1,8 @@
 use std::{
     collections::HashMap,
     fmt::Display,
+    fs::File,
+    path::Path,
     sync::{
         atomic::{AtomicU64, Ordering},
         Arc,
@@ -109,6 +111,8 @@ impl Command for TestCommand {
 
     type ASR = LogIndex;
 
+    type PR = ();
+
     fn keys(&self) -> &[Self::K] {
         &self.keys
     }
@@ -172,10 +176,13 @@ impl CommandExecutor<TestCommand> for TestCE {
         Ok(result)
     }
 
+    fn prepare(&self, _cmd: &TestCommand, _index: LogIndex) {}
+
     async fn after_sync(
         &self,
         cmd: &TestCommand,
         index: LogIndex,
+        _prepare_res: (),
     ) -> Result<LogIndex, ExecuteError> {
         sleep(cmd.as_dur).await;
         if cmd.as_should_fail {
@@ -194,6 +201,20 @@ impl CommandExecutor<TestCommand> for TestCE {
         self.store.lock().clear();
     }
 
+    async fn snapshot(&self, path: &Path) -> Result<(), ExecuteError> {
+        let file = File::create(path).map_err(|e| ExecuteError(e.to_string()))?;
+        bincode::serialize_into(file, &*self.store.lock()).map_err(|e| ExecuteError(e.to_string()))
+    }
+
+    async fn install_snapshot(&self, path: &Path, index: LogIndex) -> Result<(), ExecuteError> {
+        let file = File::open(path).map_err(|e| ExecuteError(e.to_string()))?;
+        *self.store.lock() =
+            bincode::deserialize_from(file).map_err(|e| ExecuteError(e.to_string()))?;
+        self.last_applied
+            .store(index.numeric_cast(), Ordering::Relaxed);
+        Ok(())
+    }
+
     fn last_applied(&self) -> Result<LogIndex, ExecuteError> {
         Ok(self.last_applied.load(Ordering::Relaxed))
     }
@@ -74,6 +74,38 @@ impl WriteOperation {
     }
 }
 
+/// Sizes of a table in bytes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TableSize {
+    /// Bytes allocated by the table, on the disk and in the memtables
+    physical: u64,
+    /// Bytes of the live key-values of the table
+    logical: u64,
+}
+
+impl TableSize {
+    /// New `TableSize`
+    #[inline]
+    #[must_use]
+    pub fn new(physical: u64, logical: u64) -> Self {
+        Self { physical, logical }
+    }
+
+    /// Bytes allocated by the table
+    #[inline]
+    #[must_use]
+    pub fn physical(&self) -> u64 {
+        self.physical
+    }
+
+    /// Bytes of the live key-values of the table
+    #[inline]
+    #[must_use]
+    pub fn logical(&self) -> u64 {
+        self.logical
+    }
+}
+
 /// The `StorageEngine` trait
 pub trait StorageEngine: Send + Sync + 'static + std::fmt::Debug {
     /// Get the value associated with a key value and the given table
@@ -110,4 +142,37 @@ pub trait StorageEngine: Send + Sync + 'static + std::fmt::Debug {
     /// Return `EngineError::TableNotFound` if the given table does not exist
     /// Return `EngineError` if met some errors
     fn write_batch(&self, wr_ops: Vec<WriteOperation>, sync: bool) -> Result<(), EngineError>;
+
+    /// Visit all the key-value pairs of the given tables, the pairs are read from a
+    /// consistent view of the engine, writes committed during the visit are not seen
+    ///
+    /// # Errors
+    /// Return `EngineError::TableNotFound` if the given table does not exist
+    /// Return `EngineError` if met some errors or the visitor returns an error
+    fn visit_snapshot<F>(&self, tables: &[&'static str], visitor: F) -> Result<(), EngineError>
+    where
+        F: FnMut(&'static str, &[u8], &[u8]) -> Result<(), EngineError>;
+
+    /// Visit the key-value pairs of the given table whose keys are not less than `from`, in
+    /// the order of the keys, from a consistent view of the engine
+    ///
+    /// # Errors
+    /// Return `EngineError::TableNotFound` if the given table does not exist
+    /// Return `EngineError` if met some errors or the visitor returns an error
+    fn visit_from<F>(
+        &self,
+        table: &'static str,
+        from: &[u8],
+        visitor: F,
+    ) -> Result<(), EngineError>
+    where
+        F: FnMut(&[u8], &[u8]) -> Result<(), EngineError>;
+
+    /// Get the sizes of the given table, the physical size may be larger than the logical
+    /// one by the overwritten and deleted key-values which are not compacted yet
+    ///
+    /// # Errors
+    /// Return `EngineError::TableNotFound` if the given table does not exist
+    /// Return `EngineError` if met some errors
+    fn table_size(&self, table: &str) -> Result<TableSize, EngineError>;
 }
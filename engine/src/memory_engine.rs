@@ -1,20 +1,36 @@
-use std::{cmp::Ordering, collections::HashMap, sync::Arc};
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    fs::{self, File},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use parking_lot::RwLock;
 
 use crate::{
-    engine_api::{StorageEngine, WriteOperation},
+    engine_api::{StorageEngine, TableSize, WriteOperation},
     error::EngineError,
 };
 
 /// A helper type to store the key-value pairs for the `MemoryEngine`
 type MemoryTable = HashMap<Vec<u8>, Vec<u8>>;
 
+/// Name of the file the contents of a file-backed `MemoryEngine` are dumped to
+const DUMP_FILE: &str = "memory.dump";
+
+/// Magic number at the beginning of the dump file, the last byte is the format version
+const DUMP_MAGIC: &[u8; 8] = b"XLMEMDB\x01";
+
 /// Memory Storage Engine Implementation
 #[derive(Debug, Default, Clone)]
 pub struct MemoryEngine {
     /// The inner storage engine of `MemoryStorage`
     inner: Arc<RwLock<HashMap<String, MemoryTable>>>,
+    /// The file the contents are dumped to at every synced write, `None` if the engine
+    /// isn't file-backed
+    dump_file: Option<Arc<PathBuf>>,
 }
 
 impl MemoryEngine {
@@ -31,8 +47,106 @@ impl MemoryEngine {
         }
         Ok(Self {
             inner: Arc::new(RwLock::new(inner)),
+            dump_file: None,
         })
     }
+
+    /// New file-backed `MemoryEngine`. The contents dumped to `data_dir` before are loaded,
+    /// and they are dumped again at every synced write, so the writes synced are kept
+    /// across restarts as the `RocksEngine` does.
+    ///
+    /// # Errors
+    ///
+    /// Returns `EngineError` when the data dir can't be created or the dump can't be loaded.
+    #[inline]
+    pub fn open(data_dir: impl AsRef<Path>, tables: &[&'static str]) -> Result<Self, EngineError> {
+        fs::create_dir_all(data_dir.as_ref())?;
+        let dump_file = data_dir.as_ref().join(DUMP_FILE);
+        let mut inner = match fs::read(&dump_file) {
+            Ok(buf) => decode_dump(&buf)
+                .ok_or_else(|| EngineError::Corruption(dump_file.display().to_string()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+        for table in tables {
+            let _ignore = inner.entry((*table).to_owned()).or_insert(HashMap::new());
+        }
+        Ok(Self {
+            inner: Arc::new(RwLock::new(inner)),
+            dump_file: Some(Arc::new(dump_file)),
+        })
+    }
+
+    /// Dump the contents to the dump file. It's written to a temporary file first and
+    /// renamed, so a crash in the middle leaves the last dump intact.
+    fn dump(dump_file: &Path, inner: &HashMap<String, MemoryTable>) -> Result<(), EngineError> {
+        let tmp_file = dump_file.with_extension("tmp");
+        let mut file = File::create(&tmp_file)?;
+        file.write_all(&encode_dump(inner)?)?;
+        file.sync_all()?;
+        fs::rename(&tmp_file, dump_file)?;
+        Ok(())
+    }
+}
+
+/// Encode the tables as the magic number followed by the tables, each of which is its name
+/// and the number of its pairs followed by the pairs, and all the lengths are prefixed
+fn encode_dump(inner: &HashMap<String, MemoryTable>) -> Result<Vec<u8>, EngineError> {
+    let len = |n: usize| {
+        u64::try_from(n)
+            .map(u64::to_le_bytes)
+            .map_err(|e| EngineError::InvalidArgument(e.to_string()))
+    };
+    let mut buf = DUMP_MAGIC.to_vec();
+    for (name, table) in inner {
+        buf.extend_from_slice(&len(name.len())?);
+        buf.extend_from_slice(name.as_bytes());
+        buf.extend_from_slice(&len(table.len())?);
+        for (key, value) in table {
+            buf.extend_from_slice(&len(key.len())?);
+            buf.extend_from_slice(key);
+            buf.extend_from_slice(&len(value.len())?);
+            buf.extend_from_slice(value);
+        }
+    }
+    Ok(buf)
+}
+
+/// Decode the tables encoded by `encode_dump`, return `None` if the dump is corrupted
+fn decode_dump(mut buf: &[u8]) -> Option<HashMap<String, MemoryTable>> {
+    /// Take `n` bytes from the beginning of `buf`
+    fn take<'a>(buf: &mut &'a [u8], n: usize) -> Option<&'a [u8]> {
+        (buf.len() >= n).then(|| {
+            let (head, rest) = buf.split_at(n);
+            *buf = rest;
+            head
+        })
+    }
+    /// Take a length from the beginning of `buf`
+    fn take_len(buf: &mut &[u8]) -> Option<usize> {
+        let bytes = take(buf, 8)?.try_into().ok()?;
+        usize::try_from(u64::from_le_bytes(bytes)).ok()
+    }
+
+    if take(&mut buf, DUMP_MAGIC.len())? != DUMP_MAGIC {
+        return None;
+    }
+    let mut inner = HashMap::new();
+    while !buf.is_empty() {
+        let name_len = take_len(&mut buf)?;
+        let name = String::from_utf8(take(&mut buf, name_len)?.to_vec()).ok()?;
+        let count = take_len(&mut buf)?;
+        let mut table = HashMap::new();
+        for _ in 0..count {
+            let key_len = take_len(&mut buf)?;
+            let key = take(&mut buf, key_len)?.to_vec();
+            let value_len = take_len(&mut buf)?;
+            let value = take(&mut buf, value_len)?.to_vec();
+            let _ignore = table.insert(key, value);
+        }
+        let _ignore = inner.insert(name, table);
+    }
+    Some(inner)
 }
 
 impl StorageEngine for MemoryEngine {
@@ -77,7 +191,7 @@ impl StorageEngine for MemoryEngine {
     }
 
     #[inline]
-    fn write_batch(&self, wr_ops: Vec<WriteOperation>, _sync: bool) -> Result<(), EngineError> {
+    fn write_batch(&self, wr_ops: Vec<WriteOperation>, sync: bool) -> Result<(), EngineError> {
         let mut inner = self.inner.write();
         for op in wr_ops {
             match op {
@@ -111,8 +225,72 @@ impl StorageEngine for MemoryEngine {
                 }
             }
         }
+        match self.dump_file {
+            Some(ref dump_file) if sync => Self::dump(dump_file, &inner),
+            Some(_) | None => Ok(()),
+        }
+    }
+
+    #[inline]
+    fn visit_snapshot<F>(&self, tables: &[&'static str], mut visitor: F) -> Result<(), EngineError>
+    where
+        F: FnMut(&'static str, &[u8], &[u8]) -> Result<(), EngineError>,
+    {
+        // writes are blocked during the visit, so the visitor must not write to this engine
+        let inner = self.inner.read();
+        for table in tables {
+            let mut pairs = inner
+                .get(*table)
+                .ok_or_else(|| EngineError::TableNotFound((*table).to_owned()))?
+                .iter()
+                .collect::<Vec<_>>();
+            pairs.sort_by(|p1, p2| p1.0.cmp(p2.0));
+            for (key, value) in pairs {
+                visitor(table, key, value)?;
+            }
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn visit_from<F>(
+        &self,
+        table: &'static str,
+        from: &[u8],
+        mut visitor: F,
+    ) -> Result<(), EngineError>
+    where
+        F: FnMut(&[u8], &[u8]) -> Result<(), EngineError>,
+    {
+        // writes are blocked during the visit, so the visitor must not write to this engine
+        let inner = self.inner.read();
+        let mut pairs = inner
+            .get(table)
+            .ok_or_else(|| EngineError::TableNotFound(table.to_owned()))?
+            .iter()
+            .filter(|&(key, _)| key.as_slice() >= from)
+            .collect::<Vec<_>>();
+        pairs.sort_by(|p1, p2| p1.0.cmp(p2.0));
+        for (key, value) in pairs {
+            visitor(key, value)?;
+        }
         Ok(())
     }
+
+    #[inline]
+    fn table_size(&self, table: &str) -> Result<TableSize, EngineError> {
+        let inner = self.inner.read();
+        let size = inner
+            .get(table)
+            .ok_or_else(|| EngineError::TableNotFound(table.to_owned()))?
+            .iter()
+            .fold(0_u64, |size, (key, value)| {
+                let len = key.len().saturating_add(value.len());
+                size.saturating_add(u64::try_from(len).unwrap_or(u64::MAX))
+            });
+        // nothing is left behind by the overwrites and deletions in the memory
+        Ok(TableSize::new(size, size))
+    }
 }
 
 #[cfg(test)]
@@ -208,4 +386,65 @@ mod test {
             .collect::<Vec<(Vec<u8>, Vec<u8>)>>();
         assert_eq!(res_3.sort(), expected_all_values.sort());
     }
+
+    #[test]
+    fn table_size_should_count_the_live_key_values() {
+        let engine = MemoryEngine::new(&TESTTABLES).unwrap();
+        let puts = vec![
+            WriteOperation::new_put("kv", "a", "1"),
+            WriteOperation::new_put("kv", "bb", "22"),
+        ];
+        engine.write_batch(puts, false).unwrap();
+        engine
+            .write_batch(vec![WriteOperation::new_delete("kv", "a")], false)
+            .unwrap();
+        assert_eq!(engine.table_size("kv").unwrap(), TableSize::new(4, 4));
+        assert_eq!(engine.table_size("lease").unwrap(), TableSize::default());
+        assert!(engine.table_size("hello").is_err());
+    }
+
+    #[test]
+    fn visit_from_should_start_from_the_key() {
+        let engine = MemoryEngine::new(&TESTTABLES).unwrap();
+        let puts = ["a", "c", "b", "d"]
+            .into_iter()
+            .map(|key| WriteOperation::new_put("kv", key, key))
+            .collect();
+        engine.write_batch(puts, false).unwrap();
+
+        let mut visited = vec![];
+        engine
+            .visit_from("kv", b"b", |key, _value| {
+                visited.push(key.to_vec());
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(visited, vec![b"b".to_vec(), b"c".to_vec(), b"d".to_vec()]);
+        assert!(engine.visit_from("hello", b"", |_, _| Ok(())).is_err());
+    }
+
+    #[test]
+    fn file_backed_engine_should_keep_synced_writes() {
+        let data_dir = std::env::temp_dir().join("memory_engine_file_backed_test");
+        let _ignore = fs::remove_dir_all(&data_dir);
+        let engine = MemoryEngine::open(&data_dir, &TESTTABLES).unwrap();
+        let puts = vec![
+            WriteOperation::new_put("kv", "hello", "world"),
+            WriteOperation::new_put("lease", "foo", "bar"),
+        ];
+        engine.write_batch(puts, true).unwrap();
+        let put = WriteOperation::new_put("kv", "not", "synced");
+        engine.write_batch(vec![put], false).unwrap();
+        drop(engine);
+
+        let engine = MemoryEngine::open(&data_dir, &TESTTABLES).unwrap();
+        assert_eq!(engine.get("kv", "hello").unwrap(), Some(b"world".to_vec()));
+        assert_eq!(engine.get("lease", "foo").unwrap(), Some(b"bar".to_vec()));
+        assert!(engine.get("kv", "not").unwrap().is_none());
+        assert!(engine.get_all("auth").unwrap().is_empty());
+
+        fs::write(data_dir.join(DUMP_FILE), b"XLMEMDB\x01\x01").unwrap();
+        assert!(MemoryEngine::open(&data_dir, &TESTTABLES).is_err());
+        fs::remove_dir_all(&data_dir).unwrap();
+    }
 }
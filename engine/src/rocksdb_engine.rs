@@ -5,10 +5,13 @@ use std::{
     sync::Arc,
 };
 
-use rocksdb::{Error as RocksError, Options, WriteBatchWithTransaction, WriteOptions, DB};
+use rocksdb::{
+    BlockBasedOptions, Cache, ColumnFamilyDescriptor, DBCompressionType, Error as RocksError,
+    Options, WriteBatchWithTransaction, WriteOptions, DB,
+};
 
 use crate::{
-    engine_api::{StorageEngine, WriteOperation},
+    engine_api::{StorageEngine, TableSize, WriteOperation},
     error::EngineError,
 };
 
@@ -36,6 +39,111 @@ impl From<RocksError> for EngineError {
     }
 }
 
+/// Compression of the blocks of a table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Compression {
+    /// No compression
+    None,
+    /// Snappy
+    Snappy,
+    /// LZ4
+    Lz4,
+    /// Zstandard
+    Zstd,
+}
+
+/// Tuning options of a table, the sizes are in bytes, and 0 means the default of `RocksDB`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TableOptions {
+    /// Size of the block cache of the table
+    block_cache_size: usize,
+    /// Compression of the blocks
+    compression: Compression,
+    /// Size of a memtable before it's flushed
+    write_buffer_size: usize,
+    /// Bits per key of the bloom filters, 0 means no bloom filter
+    bloom_filter_bits: u32,
+}
+
+impl TableOptions {
+    /// New `TableOptions`
+    #[inline]
+    #[must_use]
+    pub fn new(
+        block_cache_size: usize,
+        compression: Compression,
+        write_buffer_size: usize,
+        bloom_filter_bits: u32,
+    ) -> Self {
+        Self {
+            block_cache_size,
+            compression,
+            write_buffer_size,
+            bloom_filter_bits,
+        }
+    }
+
+    /// Build the `RocksDB` options of a column family, the block cache of the table is
+    /// `shared_cache` unless it has a block cache size of its own
+    fn cf_options(&self, shared_cache: Option<&Cache>) -> Result<Options, EngineError> {
+        let mut opts = Options::default();
+        opts.set_compression_type(match self.compression {
+            Compression::None => DBCompressionType::None,
+            Compression::Snappy => DBCompressionType::Snappy,
+            Compression::Lz4 => DBCompressionType::Lz4,
+            Compression::Zstd => DBCompressionType::Zstd,
+        });
+        if self.write_buffer_size != 0 {
+            opts.set_write_buffer_size(self.write_buffer_size);
+        }
+        let mut block_opts = BlockBasedOptions::default();
+        if self.block_cache_size != 0 {
+            block_opts.set_block_cache(&Cache::new_lru_cache(self.block_cache_size)?);
+        } else if let Some(cache) = shared_cache {
+            block_opts.set_block_cache(cache);
+        }
+        if self.bloom_filter_bits != 0 {
+            block_opts.set_bloom_filter(f64::from(self.bloom_filter_bits), false);
+        }
+        opts.set_block_based_table_factory(&block_opts);
+        Ok(opts)
+    }
+}
+
+impl Default for TableOptions {
+    #[inline]
+    fn default() -> Self {
+        Self::new(0, Compression::Snappy, 0, 0)
+    }
+}
+
+/// Tuning options of the whole engine
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EngineOptions {
+    /// Size in bytes of the block cache shared by the tables without a block cache size of
+    /// their own, 0 means each table has a default cache
+    block_cache_size: usize,
+    /// Read and write the files by O_DIRECT, bypassing the page cache
+    direct_io: bool,
+    /// Max bytes per second written by the background flushes and compactions, 0 means no
+    /// limit
+    compaction_rate_limit: u64,
+}
+
+impl EngineOptions {
+    /// New `EngineOptions`
+    #[inline]
+    #[must_use]
+    pub fn new(block_cache_size: usize, direct_io: bool, compaction_rate_limit: u64) -> Self {
+        Self {
+            block_cache_size,
+            direct_io,
+            compaction_rate_limit,
+        }
+    }
+}
+
 /// `RocksDB` Storage Engine
 #[derive(Debug, Clone)]
 pub struct RocksEngine {
@@ -51,11 +159,56 @@ impl RocksEngine {
     /// Return `EngineError` when DB open failed.
     #[inline]
     pub fn new(data_dir: impl AsRef<Path>, tables: &[&'static str]) -> Result<Self, EngineError> {
+        Self::with_options(data_dir, tables, EngineOptions::default(), &[])
+    }
+
+    /// New `RocksEngine` tuned by `engine_options`, whose tables are tuned by
+    /// `table_options`, the tables not in it use the default options
+    ///
+    /// # Errors
+    ///
+    /// Return `EngineError` when DB open failed.
+    #[inline]
+    pub fn with_options(
+        data_dir: impl AsRef<Path>,
+        tables: &[&'static str],
+        engine_options: EngineOptions,
+        table_options: &[(&str, TableOptions)],
+    ) -> Result<Self, EngineError> {
         let mut db_opts = Options::default();
         db_opts.create_missing_column_families(true);
         db_opts.create_if_missing(true);
+        if engine_options.direct_io {
+            db_opts.set_use_direct_reads(true);
+            db_opts.set_use_direct_io_for_flush_and_compaction(true);
+        }
+        if engine_options.compaction_rate_limit != 0 {
+            // refill every 100ms, and let the flushes and compactions share the rate fairly
+            db_opts.set_ratelimiter(
+                i64::try_from(engine_options.compaction_rate_limit).unwrap_or(i64::MAX),
+                100_000,
+                10,
+            );
+        }
+        let shared_cache = match engine_options.block_cache_size {
+            0 => None,
+            size => Some(Cache::new_lru_cache(size)?),
+        };
+        let cfs = tables
+            .iter()
+            .map(|&table| {
+                let options = table_options
+                    .iter()
+                    .find(|&&(name, _)| name == table)
+                    .map_or_else(TableOptions::default, |&(_, options)| options);
+                Ok(ColumnFamilyDescriptor::new(
+                    table,
+                    options.cf_options(shared_cache.as_ref())?,
+                ))
+            })
+            .collect::<Result<Vec<_>, EngineError>>()?;
         Ok(Self {
-            inner: Arc::new(DB::open_cf(&db_opts, data_dir, tables)?),
+            inner: Arc::new(DB::open_cf_descriptors(&db_opts, data_dir, cfs)?),
         })
     }
 }
@@ -135,6 +288,63 @@ impl StorageEngine for RocksEngine {
         opt.set_sync(sync);
         self.inner.write_opt(batch, &opt).map_err(EngineError::from)
     }
+
+    #[inline]
+    fn visit_snapshot<F>(&self, tables: &[&'static str], mut visitor: F) -> Result<(), EngineError>
+    where
+        F: FnMut(&'static str, &[u8], &[u8]) -> Result<(), EngineError>,
+    {
+        let snapshot = self.inner.snapshot();
+        for table in tables {
+            let cf = self
+                .inner
+                .cf_handle(table)
+                .ok_or_else(|| EngineError::TableNotFound((*table).to_owned()))?;
+            for pair in snapshot.iterator_cf(&cf, rocksdb::IteratorMode::Start) {
+                let (key, value) = pair?;
+                visitor(table, &key, &value)?;
+            }
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn visit_from<F>(
+        &self,
+        table: &'static str,
+        from: &[u8],
+        mut visitor: F,
+    ) -> Result<(), EngineError>
+    where
+        F: FnMut(&[u8], &[u8]) -> Result<(), EngineError>,
+    {
+        let snapshot = self.inner.snapshot();
+        let cf = self
+            .inner
+            .cf_handle(table)
+            .ok_or_else(|| EngineError::TableNotFound(table.to_owned()))?;
+        let mode = rocksdb::IteratorMode::From(from, rocksdb::Direction::Forward);
+        for pair in snapshot.iterator_cf(&cf, mode) {
+            let (key, value) = pair?;
+            visitor(&key, &value)?;
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn table_size(&self, table: &str) -> Result<TableSize, EngineError> {
+        let cf = self
+            .inner
+            .cf_handle(table)
+            .ok_or_else(|| EngineError::TableNotFound(table.to_owned()))?;
+        let property = |name: &str| -> Result<u64, EngineError> {
+            Ok(self.inner.property_int_value_cf(&cf, name)?.unwrap_or(0))
+        };
+        let memtables = property("rocksdb.cur-size-all-mem-tables")?;
+        let physical = property("rocksdb.total-sst-files-size")?.saturating_add(memtables);
+        let logical = property("rocksdb.estimate-live-data-size")?.saturating_add(memtables);
+        Ok(TableSize::new(physical, logical.min(physical)))
+    }
 }
 
 /// destroy will remove the db file. It's test only
@@ -180,6 +390,27 @@ mod test {
         destroy(&data_dir);
     }
 
+    #[test]
+    fn tuned_tables_should_keep_the_data() {
+        let data_dir = PathBuf::from("/tmp/tuned_tables_should_keep_the_data");
+        let engine = RocksEngine::new(&data_dir, &TESTTABLES).unwrap();
+        let put = WriteOperation::new_put("kv", "hello", "world");
+        engine.write_batch(vec![put], true).unwrap();
+        drop(engine);
+
+        let options = TableOptions::new(1024 * 1024, Compression::Zstd, 4 * 1024 * 1024, 10);
+        let engine = RocksEngine::with_options(
+            &data_dir,
+            &TESTTABLES,
+            EngineOptions::new(8 * 1024 * 1024, false, 16 * 1024 * 1024),
+            &[("kv", options)],
+        )
+        .unwrap();
+        assert_eq!(engine.get("kv", "hello").unwrap(), Some(b"world".to_vec()));
+        drop(engine);
+        destroy(&data_dir);
+    }
+
     #[test]
     fn write_batch_should_success() {
         let data_dir = PathBuf::from("/tmp/write_batch_should_success");
@@ -253,4 +484,37 @@ mod test {
         drop(engine);
         destroy(&data_dir);
     }
+
+    #[test]
+    fn visit_snapshot_should_not_see_later_writes() {
+        let data_dir = PathBuf::from("/tmp/visit_snapshot_should_not_see_later_writes");
+        let engine = RocksEngine::new(&data_dir, &TESTTABLES).unwrap();
+        let puts = vec![
+            WriteOperation::new_put("kv", "a", "1"),
+            WriteOperation::new_put("kv", "b", "2"),
+            WriteOperation::new_put("lease", "c", "3"),
+        ];
+        engine.write_batch(puts, false).unwrap();
+
+        let mut visited = vec![];
+        engine
+            .visit_snapshot(&["kv", "lease"], |table, key, value| {
+                // writes during the visit belong to a later view
+                engine.write_batch(vec![WriteOperation::new_put("lease", "d", "4")], false)?;
+                visited.push((table, key.to_vec(), value.to_vec()));
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(
+            visited,
+            vec![
+                ("kv", b"a".to_vec(), b"1".to_vec()),
+                ("kv", b"b".to_vec(), b"2".to_vec()),
+                ("lease", b"c".to_vec(), b"3".to_vec()),
+            ]
+        );
+        assert!(engine.visit_snapshot(&["hello"], |_, _, _| Ok(())).is_err());
+        drop(engine);
+        destroy(&data_dir);
+    }
 }
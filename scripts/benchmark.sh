@@ -35,7 +35,7 @@ benchmark_cmd() {
     clients=${4}
     total=${5}
     key_space_size=${6}
-    echo "docker exec ${container_name} /usr/local/bin/benchmark --endpoints ${endpoints} ${use_curp} --clients=${clients} --stdout put --key-size=8 --val-size=256 --total=${total} --key-space-size=${key_space_size}"
+    echo "docker exec ${container_name} /usr/local/bin/xline-bench --endpoints ${endpoints} ${use_curp} --clients=${clients} --stdout put --key-size=8 --val-size=256 --total=${total} --key-space-size=${key_space_size}"
 }
 
 # run xline node by index
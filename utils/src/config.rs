@@ -1,9 +1,15 @@
-use std::{collections::HashMap, path::PathBuf, time::Duration};
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 use getset::Getters;
 use serde::Deserialize;
 use tracing_appender::rolling::RollingFileAppender;
 
+use crate::{parse_members, ConfigParseError};
+
 /// Xline server configuration object
 #[allow(clippy::module_name_repetitions)]
 #[derive(Clone, Debug, Deserialize, PartialEq, Eq, Getters)]
@@ -23,6 +29,86 @@ pub struct XlineServerConfig {
     /// auth configuration object
     #[getset(get = "pub")]
     auth: AuthConfig,
+    /// grpc server configuration object
+    #[getset(get = "pub")]
+    #[serde(default = "GrpcConfig::default")]
+    grpc: GrpcConfig,
+    /// rate limit configuration object
+    #[getset(get = "pub")]
+    #[serde(default = "RateLimitConfig::default")]
+    rate_limit: RateLimitConfig,
+    /// server side timeout configuration object
+    #[getset(get = "pub")]
+    #[serde(default = "ServerTimeout::default")]
+    server_timeout: ServerTimeout,
+    /// audit log configuration object
+    #[getset(get = "pub")]
+    #[serde(default = "AuditConfig::default")]
+    audit: AuditConfig,
+    /// backup configuration object
+    #[getset(get = "pub")]
+    #[serde(default = "BackupConfig::default")]
+    backup: BackupConfig,
+    /// proposal batching configuration object
+    #[getset(get = "pub")]
+    #[serde(default = "BatchConfig::default")]
+    batch: BatchConfig,
+    /// readiness and liveness probe configuration object
+    #[getset(get = "pub")]
+    #[serde(default = "ProbeConfig::default")]
+    probe: ProbeConfig,
+    /// request limit configuration object
+    #[getset(get = "pub")]
+    #[serde(default = "RequestLimitConfig::default")]
+    request_limit: RequestLimitConfig,
+    /// per user write quota configuration object
+    #[getset(get = "pub")]
+    #[serde(default = "QuotaConfig::default")]
+    quota: QuotaConfig,
+    /// grpc response compression configuration object
+    #[getset(get = "pub")]
+    #[serde(default = "CompressionConfig::default")]
+    compression: CompressionConfig,
+    /// corruption check configuration object
+    #[getset(get = "pub")]
+    #[serde(default = "CorruptCheckConfig::default")]
+    corrupt_check: CorruptCheckConfig,
+    /// compaction configuration object
+    #[getset(get = "pub")]
+    #[serde(default = "CompactConfig::default")]
+    compact: CompactConfig,
+    /// client TLS configuration object
+    #[getset(get = "pub")]
+    #[serde(default = "TlsConfig::default")]
+    tls: TlsConfig,
+    /// peer TLS configuration object
+    #[getset(get = "pub")]
+    #[serde(default = "PeerTlsConfig::default")]
+    peer_tls: PeerTlsConfig,
+    /// PROXY protocol configuration object
+    #[getset(get = "pub")]
+    #[serde(default = "ProxyProtocolConfig::default")]
+    proxy_protocol: ProxyProtocolConfig,
+    /// Admission configuration object
+    #[getset(get = "pub")]
+    #[serde(default = "AdmissionConfig::default")]
+    admission: AdmissionConfig,
+    /// Namespaces of the keys
+    #[getset(get = "pub")]
+    #[serde(default, rename = "namespace")]
+    namespaces: Vec<NamespaceConfig>,
+    /// Trash configuration object
+    #[getset(get = "pub")]
+    #[serde(default = "TrashConfig::default")]
+    trash: TrashConfig,
+    /// Backend configuration object
+    #[getset(get = "pub")]
+    #[serde(default = "BackendConfig::default")]
+    backend: BackendConfig,
+    /// Lease configuration object
+    #[getset(get = "pub")]
+    #[serde(default = "LeaseConfig::default")]
+    lease: LeaseConfig,
 }
 
 // TODO: support persistent storage configuration in the future
@@ -61,10 +147,19 @@ pub struct ClusterConfig {
     name: String,
     /// All the nodes in the xline cluster
     #[getset(get = "pub")]
+    #[serde(default)]
     members: HashMap<String, String>,
+    /// Domain whose DNS SRV records list the nodes, used instead of `members`
+    #[getset(get = "pub")]
+    #[serde(default)]
+    discovery_srv: Option<String>,
     /// Leader node.
     #[getset(get = "pub")]
     is_leader: bool,
+    /// Whether the member bootstraps a new cluster or joins an existing one
+    #[getset(get = "pub")]
+    #[serde(default = "default_initial_cluster_state")]
+    initial_cluster_state: InitialClusterState,
     /// Curp server timeout settings
     #[getset(get = "pub")]
     #[serde(default = "CurpConfig::default")]
@@ -73,6 +168,17 @@ pub struct ClusterConfig {
     #[getset(get = "pub")]
     #[serde(default = "ClientTimeout::default")]
     client_timeout: ClientTimeout,
+    /// Version of the wire format the commands are proposed in, the latest one if it's not
+    /// set. An older version is set during a rolling upgrade, so that the members not
+    /// upgraded yet can decode the commands
+    #[getset(get = "pub")]
+    #[serde(default)]
+    command_wire_version: Option<u32>,
+    /// Members which replicate the data and serve the serializable reads and the watches,
+    /// but never campaign for the leadership or count toward the quorum
+    #[getset(get = "pub")]
+    #[serde(default)]
+    standby_members: Vec<String>,
 }
 
 impl ClusterConfig {
@@ -82,20 +188,60 @@ impl ClusterConfig {
     pub fn new(
         name: String,
         members: HashMap<String, String>,
+        discovery_srv: Option<String>,
         is_leader: bool,
+        initial_cluster_state: InitialClusterState,
         curp: CurpConfig,
         client_timeout: ClientTimeout,
+        command_wire_version: Option<u32>,
+        standby_members: Vec<String>,
     ) -> Self {
         Self {
             name,
             members,
+            discovery_srv,
             is_leader,
+            initial_cluster_state,
             curp_config: curp,
             client_timeout,
+            command_wire_version,
+            standby_members,
+        }
+    }
+}
+
+/// Whether a member bootstraps a new cluster or joins an existing one, like the
+/// `--initial-cluster-state` of etcd. It only matters when the data dir of the member is
+/// empty, a member with data has already joined its cluster.
+#[non_exhaustive]
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all(deserialize = "lowercase"))]
+pub enum InitialClusterState {
+    /// Bootstrap a new cluster, a member with an empty data dir refuses to start if its
+    /// peers already serve a cluster with data
+    New,
+    /// Join an existing cluster, a member with an empty data dir refuses to start until
+    /// one of its peers serves the cluster, and it can't be the initial leader
+    Existing,
+}
+
+impl std::fmt::Display for InitialClusterState {
+    #[inline]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            InitialClusterState::New => write!(f, "new"),
+            InitialClusterState::Existing => write!(f, "existing"),
         }
     }
 }
 
+/// default initial cluster state
+#[must_use]
+#[inline]
+pub fn default_initial_cluster_state() -> InitialClusterState {
+    InitialClusterState::New
+}
+
 /// Curp server timeout settings
 #[derive(Clone, Debug, Deserialize, PartialEq, Eq, Getters)]
 #[allow(clippy::module_name_repetitions, clippy::exhaustive_structs)]
@@ -133,6 +279,16 @@ pub struct CurpConfig {
     /// Curp storage path
     #[serde(default = "default_curp_data_dir")]
     pub data_dir: PathBuf,
+
+    /// How many log entries a follower can fall behind the leader's applied index before the
+    /// leader sends it a snapshot instead of the entries
+    #[serde(default = "default_snapshot_threshold")]
+    pub snapshot_threshold: usize,
+
+    /// Servers which replicate the log but never campaign for the leadership or count
+    /// toward the quorum, it's set from the standby members of the cluster config
+    #[serde(skip)]
+    pub standbys: HashSet<String>,
 }
 
 /// default heartbeat interval
@@ -198,10 +354,18 @@ pub fn default_curp_data_dir() -> PathBuf {
     PathBuf::from("/var/lib/curp")
 }
 
+/// default snapshot threshold
+#[must_use]
+#[inline]
+pub fn default_snapshot_threshold() -> usize {
+    10000
+}
+
 impl CurpConfig {
     /// Create a new server timeout
     #[must_use]
     #[inline]
+    #[allow(clippy::too_many_arguments)] // TODO: refactor this use builder pattern
     pub fn new(
         heartbeat_interval: Duration,
         wait_synced_timeout: Duration,
@@ -210,6 +374,7 @@ impl CurpConfig {
         follower_timeout_ticks: u8,
         candidate_timeout_ticks: u8,
         data_dir: PathBuf,
+        snapshot_threshold: usize,
     ) -> Self {
         Self {
             heartbeat_interval,
@@ -219,6 +384,8 @@ impl CurpConfig {
             follower_timeout_ticks,
             candidate_timeout_ticks,
             data_dir,
+            snapshot_threshold,
+            standbys: HashSet::new(),
         }
     }
 }
@@ -234,6 +401,8 @@ impl Default for CurpConfig {
             follower_timeout_ticks: default_follower_timeout_ticks(),
             candidate_timeout_ticks: default_candidate_timeout_ticks(),
             data_dir: default_curp_data_dir(),
+            snapshot_threshold: default_snapshot_threshold(),
+            standbys: HashSet::new(),
         }
     }
 }
@@ -300,6 +469,10 @@ impl Default for ClientTimeout {
 pub enum StorageConfig {
     /// Memory Storage Engine
     Memory,
+    /// Memory Storage Engine whose contents are dumped to the data dir at every synced write
+    /// and loaded from it at the start
+    #[serde(rename(deserialize = "memory_file"))]
+    MemoryFile(PathBuf),
     /// RocksDB Storage Engine
     RocksDB(PathBuf),
 }
@@ -450,6 +623,10 @@ pub struct TraceConfig {
     #[getset(get = "pub")]
     #[serde(with = "level_format", default = "default_log_level")]
     jaeger_level: LevelConfig,
+    /// The endpoint of the OTLP collector, spans are exported to it when it is set
+    #[getset(get = "pub")]
+    #[serde(default)]
+    otlp_endpoint: Option<String>,
 }
 
 impl TraceConfig {
@@ -461,12 +638,14 @@ impl TraceConfig {
         jaeger_offline: bool,
         jaeger_output_dir: PathBuf,
         jaeger_level: LevelConfig,
+        otlp_endpoint: Option<String>,
     ) -> Self {
         Self {
             jaeger_online,
             jaeger_offline,
             jaeger_output_dir,
             jaeger_level,
+            otlp_endpoint,
         }
     }
 }
@@ -481,6 +660,9 @@ pub struct AuthConfig {
     /// The private key file
     #[getset(get = "pub")]
     auth_private_key: Option<PathBuf>,
+    /// External authentication, `None` if only the users of Xline are authenticated
+    #[getset(get = "pub")]
+    external: Option<ExternalAuthConfig>,
     // TODO: support SSL/TLS configuration in the future
 }
 
@@ -488,146 +670,2717 @@ impl AuthConfig {
     /// Generate a new `AuthConfig` object
     #[must_use]
     #[inline]
-    pub fn new(auth_public_key: Option<PathBuf>, auth_private_key: Option<PathBuf>) -> Self {
+    pub fn new(
+        auth_public_key: Option<PathBuf>,
+        auth_private_key: Option<PathBuf>,
+        external: Option<ExternalAuthConfig>,
+    ) -> Self {
         Self {
             auth_public_key,
             auth_private_key,
+            external,
         }
     }
 }
 
-impl XlineServerConfig {
-    /// Generates a new `XlineServerConfig` object
+/// Provider of the external authentication
+#[non_exhaustive]
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all(deserialize = "lowercase"))]
+pub enum ExternalAuthProvider {
+    /// The bearer tokens are JWTs signed by an OIDC issuer, verified by the keys of the issuer
+    Oidc,
+    /// The bearer tokens are posted to a webhook, which returns the identities of them
+    Webhook,
+}
+
+/// External authentication configuration object, the bearer tokens validated by the provider
+/// are exchanged for the Xline tokens, whose roles are mapped from the groups of the
+/// external identities
+#[allow(clippy::module_name_repetitions)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Getters)]
+pub struct ExternalAuthConfig {
+    /// Provider validating the bearer tokens
+    #[getset(get = "pub")]
+    provider: ExternalAuthProvider,
+    /// URL of the OIDC issuer or the webhook
+    #[getset(get = "pub")]
+    url: String,
+    /// Audience the OIDC tokens must be issued to, required by the OIDC provider
+    #[getset(get = "pub")]
+    #[serde(default)]
+    audience: String,
+    /// Claim of the OIDC tokens holding the username
+    #[getset(get = "pub")]
+    #[serde(default = "default_username_claim")]
+    username_claim: String,
+    /// Claim of the OIDC tokens holding the groups
+    #[getset(get = "pub")]
+    #[serde(default = "default_groups_claim")]
+    groups_claim: String,
+    /// Timeout of the requests to the provider
+    #[getset(get = "pub")]
+    #[serde(with = "duration_format", default = "default_external_auth_timeout")]
+    timeout: Duration,
+    /// Xline roles of each group, the groups not in the mapping grant no role
+    #[getset(get = "pub")]
+    #[serde(default)]
+    role_mapping: HashMap<String, Vec<String>>,
+}
+
+/// default claim of the username
+#[must_use]
+#[inline]
+pub fn default_username_claim() -> String {
+    "sub".to_owned()
+}
+
+/// default claim of the groups
+#[must_use]
+#[inline]
+pub fn default_groups_claim() -> String {
+    "groups".to_owned()
+}
+
+/// default timeout of the requests to the external authentication provider
+#[must_use]
+#[inline]
+pub fn default_external_auth_timeout() -> Duration {
+    Duration::from_secs(5)
+}
+
+impl ExternalAuthConfig {
+    /// Generate a new `ExternalAuthConfig` object
     #[must_use]
     #[inline]
     pub fn new(
-        cluster: ClusterConfig,
-        storage: StorageConfig,
-        log: LogConfig,
-        trace: TraceConfig,
-        auth: AuthConfig,
+        provider: ExternalAuthProvider,
+        url: String,
+        audience: String,
+        username_claim: String,
+        groups_claim: String,
+        timeout: Duration,
+        role_mapping: HashMap<String, Vec<String>>,
     ) -> Self {
         Self {
-            cluster,
-            storage,
-            log,
-            trace,
-            auth,
+            provider,
+            url,
+            audience,
+            username_claim,
+            groups_claim,
+            timeout,
+            role_mapping,
         }
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[allow(clippy::unwrap_used)]
-    #[test]
-    fn test_xline_server_config_should_be_loaded() {
-        let config: XlineServerConfig = toml::from_str(
-            r#"[cluster]
-            name = 'node1'
-            is_leader = true
+/// Grpc server configuration object
+#[allow(clippy::module_name_repetitions)]
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq, Eq, Getters)]
+pub struct GrpcConfig {
+    /// Interval of the HTTP/2 keepalive ping
+    #[getset(get = "pub")]
+    #[serde(with = "duration_format", default = "default_keepalive_interval")]
+    keepalive_interval: Duration,
+    /// Timeout of the HTTP/2 keepalive ping, the connection will be closed if
+    /// the ack is not received within this timeout
+    #[getset(get = "pub")]
+    #[serde(with = "duration_format", default = "default_keepalive_timeout")]
+    keepalive_timeout: Duration,
+    /// Max size of a message the server can receive, in bytes
+    #[getset(get = "pub")]
+    #[serde(default = "default_max_recv_msg_size")]
+    max_recv_msg_size: usize,
+    /// Max size of a message the server can send, in bytes
+    #[getset(get = "pub")]
+    #[serde(default = "default_max_send_msg_size")]
+    max_send_msg_size: usize,
+    /// Max concurrent streams of each connection
+    #[getset(get = "pub")]
+    #[serde(default = "default_max_concurrent_streams")]
+    max_concurrent_streams: u32,
+}
 
-            [cluster.members]
-            node1 = '127.0.0.1:2379'
-            node2 = '127.0.0.1:2380'
-            node3 = '127.0.0.1:2381'
+/// default keepalive interval
+#[must_use]
+#[inline]
+pub fn default_keepalive_interval() -> Duration {
+    Duration::from_secs(2 * 60 * 60)
+}
 
-            [cluster.curp_config]
-            heartbeat_interval = '200ms'
-            wait_synced_timeout = '100ms'
-            rpc_timeout = '100ms'
-            retry_timeout = '100us'
+/// default keepalive timeout
+#[must_use]
+#[inline]
+pub fn default_keepalive_timeout() -> Duration {
+    Duration::from_secs(20)
+}
 
-            [cluster.client_timeout]
-            retry_timeout = '5s'
+/// default max receive message size
+#[must_use]
+#[inline]
+pub fn default_max_recv_msg_size() -> usize {
+    4 * 1024 * 1024
+}
 
-            [storage]
-            engine = 'memory'
+/// default max send message size
+#[must_use]
+#[inline]
+pub fn default_max_send_msg_size() -> usize {
+    usize::MAX
+}
 
-            [log]
-            path = '/var/log/xline'
-            rotation = 'daily'
-            level = 'info'
+/// default max concurrent streams
+#[must_use]
+#[inline]
+pub fn default_max_concurrent_streams() -> u32 {
+    u32::MAX
+}
 
-            [trace]
-            jaeger_online = false
-            jaeger_offline = false
-            jaeger_output_dir = './jaeger_jsons'
-            jaeger_level = 'info'
+impl GrpcConfig {
+    /// Generate a new `GrpcConfig` object
+    #[must_use]
+    #[inline]
+    pub fn new(
+        keepalive_interval: Duration,
+        keepalive_timeout: Duration,
+        max_recv_msg_size: usize,
+        max_send_msg_size: usize,
+        max_concurrent_streams: u32,
+    ) -> Self {
+        Self {
+            keepalive_interval,
+            keepalive_timeout,
+            max_recv_msg_size,
+            max_send_msg_size,
+            max_concurrent_streams,
+        }
+    }
+}
 
-            [auth]"#,
-        )
-        .unwrap();
+impl Default for GrpcConfig {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            keepalive_interval: default_keepalive_interval(),
+            keepalive_timeout: default_keepalive_timeout(),
+            max_recv_msg_size: default_max_recv_msg_size(),
+            max_send_msg_size: default_max_send_msg_size(),
+            max_concurrent_streams: default_max_concurrent_streams(),
+        }
+    }
+}
 
-        let curp_config = CurpConfig::new(
-            Duration::from_millis(200),
-            Duration::from_millis(100),
-            Duration::from_micros(100),
-            Duration::from_millis(100),
-            default_follower_timeout_ticks(),
-            default_candidate_timeout_ticks(),
-            default_curp_data_dir(),
-        );
+/// Per-client rate limit configuration object
+#[allow(clippy::module_name_repetitions)]
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq, Eq, Getters)]
+pub struct RateLimitConfig {
+    /// Requests allowed per second of each client, 0 means no limit
+    #[getset(get = "pub")]
+    #[serde(default = "default_rate_limit_qps")]
+    qps: u64,
+    /// Max requests a client can send in a burst
+    #[getset(get = "pub")]
+    #[serde(default = "default_rate_limit_burst")]
+    burst: u64,
+}
 
-        let client_timeout = ClientTimeout::new(
-            default_client_wait_synced_timeout(),
-            default_propose_timeout(),
-            Duration::from_secs(5),
-        );
+/// default rate limit qps
+#[must_use]
+#[inline]
+pub fn default_rate_limit_qps() -> u64 {
+    0
+}
 
-        assert_eq!(
-            config.cluster,
-            ClusterConfig::new(
-                "node1".to_owned(),
-                HashMap::from_iter([
-                    ("node1".to_owned(), "127.0.0.1:2379".to_owned()),
-                    ("node2".to_owned(), "127.0.0.1:2380".to_owned()),
-                    ("node3".to_owned(), "127.0.0.1:2381".to_owned()),
-                ]),
-                true,
-                curp_config,
-                client_timeout
-            )
-        );
+/// default rate limit burst
+#[must_use]
+#[inline]
+pub fn default_rate_limit_burst() -> u64 {
+    100
+}
 
-        assert_eq!(config.storage, StorageConfig::Memory);
+impl RateLimitConfig {
+    /// Generate a new `RateLimitConfig` object
+    #[must_use]
+    #[inline]
+    pub fn new(qps: u64, burst: u64) -> Self {
+        Self { qps, burst }
+    }
+}
 
-        assert_eq!(
-            config.log,
-            LogConfig::new(
-                PathBuf::from("/var/log/xline"),
-                RotationConfig::Daily,
-                LevelConfig::INFO
-            )
-        );
-        assert_eq!(
-            config.trace,
-            TraceConfig::new(
-                false,
-                false,
-                PathBuf::from("./jaeger_jsons"),
-                LevelConfig::INFO
-            )
-        );
+impl Default for RateLimitConfig {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            qps: default_rate_limit_qps(),
+            burst: default_rate_limit_burst(),
+        }
     }
+}
 
-    #[allow(clippy::unwrap_used)]
-    #[test]
-    fn test_xline_server_default_config_should_be_loaded() {
-        let config: XlineServerConfig = toml::from_str(
-            r#"[cluster]
-                name = 'node1'
-                is_leader = true
+/// Server side timeouts of different types of rpcs
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq, Eq, Getters)]
+pub struct ServerTimeout {
+    /// Timeout of read rpcs, eg. Range
+    #[getset(get = "pub")]
+    #[serde(with = "duration_format", default = "default_read_timeout")]
+    read_timeout: Duration,
+    /// Timeout of write rpcs, eg. Put, Txn
+    #[getset(get = "pub")]
+    #[serde(with = "duration_format", default = "default_write_timeout")]
+    write_timeout: Duration,
+    /// Timeout of maintenance rpcs, eg. Snapshot, Compact
+    #[getset(get = "pub")]
+    #[serde(with = "duration_format", default = "default_maintenance_timeout")]
+    maintenance_timeout: Duration,
+    /// Requests which take longer than it are logged with their phase timings
+    #[getset(get = "pub")]
+    #[serde(with = "duration_format", default = "default_slow_request_threshold")]
+    slow_request_threshold: Duration,
+}
 
-                [cluster.members]
-                node1 = '127.0.0.1:2379'
-                node2 = '127.0.0.1:2380'
-                node3 = '127.0.0.1:2381'
+/// default read timeout
+#[must_use]
+#[inline]
+pub fn default_read_timeout() -> Duration {
+    Duration::from_secs(5)
+}
 
-                [log]
-                path = '/var/log/xline'
+/// default write timeout
+#[must_use]
+#[inline]
+pub fn default_write_timeout() -> Duration {
+    Duration::from_secs(10)
+}
+
+/// default maintenance timeout
+#[must_use]
+#[inline]
+pub fn default_maintenance_timeout() -> Duration {
+    Duration::from_secs(600)
+}
+
+/// default slow request threshold
+#[must_use]
+#[inline]
+pub fn default_slow_request_threshold() -> Duration {
+    Duration::from_millis(100)
+}
+
+impl ServerTimeout {
+    /// Create a new server timeout
+    #[must_use]
+    #[inline]
+    pub fn new(
+        read_timeout: Duration,
+        write_timeout: Duration,
+        maintenance_timeout: Duration,
+        slow_request_threshold: Duration,
+    ) -> Self {
+        Self {
+            read_timeout,
+            write_timeout,
+            maintenance_timeout,
+            slow_request_threshold,
+        }
+    }
+}
+
+impl Default for ServerTimeout {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            read_timeout: default_read_timeout(),
+            write_timeout: default_write_timeout(),
+            maintenance_timeout: default_maintenance_timeout(),
+            slow_request_threshold: default_slow_request_threshold(),
+        }
+    }
+}
+
+/// Kinds of events recorded by the audit log
+#[non_exhaustive]
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all(deserialize = "snake_case"))]
+pub enum AuditEvent {
+    /// Authentication of users
+    Authentication,
+    /// Changes of the auth configuration, eg. enabling auth, adding users and granting roles
+    AuthConfig,
+    /// Writes to the key-value store, eg. Put, DeleteRange and Txn
+    Write,
+}
+
+/// Audit log configuration object
+#[allow(clippy::module_name_repetitions)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Getters)]
+pub struct AuditConfig {
+    /// Whether the audit log is enabled
+    #[getset(get = "pub")]
+    #[serde(default)]
+    enable: bool,
+    /// Audit log file path
+    #[getset(get = "pub")]
+    #[serde(default = "default_audit_path")]
+    path: PathBuf,
+    /// Audit log rotation strategy
+    #[getset(get = "pub")]
+    #[serde(with = "rotation_format", default = "default_rotation")]
+    rotation: RotationConfig,
+    /// Kinds of events to record
+    #[getset(get = "pub")]
+    #[serde(default = "default_audit_events")]
+    events: Vec<AuditEvent>,
+}
+
+/// default audit log path
+#[must_use]
+#[inline]
+pub fn default_audit_path() -> PathBuf {
+    PathBuf::from("/var/log/xline")
+}
+
+/// default audit events, writes are not recorded because there are too many of them
+#[must_use]
+#[inline]
+pub fn default_audit_events() -> Vec<AuditEvent> {
+    vec![AuditEvent::Authentication, AuditEvent::AuthConfig]
+}
+
+impl AuditConfig {
+    /// Generate a new `AuditConfig` object
+    #[must_use]
+    #[inline]
+    pub fn new(
+        enable: bool,
+        path: PathBuf,
+        rotation: RotationConfig,
+        events: Vec<AuditEvent>,
+    ) -> Self {
+        Self {
+            enable,
+            path,
+            rotation,
+            events,
+        }
+    }
+}
+
+impl Default for AuditConfig {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            enable: false,
+            path: default_audit_path(),
+            rotation: default_rotation(),
+            events: default_audit_events(),
+        }
+    }
+}
+
+/// Backup configuration object, snapshots are uploaded to an S3 compatible object storage.
+/// The credentials are read from `AWS_ACCESS_KEY_ID` and `AWS_SECRET_ACCESS_KEY`.
+#[allow(clippy::module_name_repetitions)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Getters)]
+pub struct BackupConfig {
+    /// Whether the periodic backup is enabled
+    #[getset(get = "pub")]
+    #[serde(default)]
+    enable: bool,
+    /// Endpoint of the object storage, eg. `https://s3.us-east-1.amazonaws.com`
+    #[getset(get = "pub")]
+    #[serde(default = "default_backup_endpoint")]
+    endpoint: String,
+    /// Bucket to store the snapshots
+    #[getset(get = "pub")]
+    #[serde(default = "default_backup_bucket")]
+    bucket: String,
+    /// Region of the bucket
+    #[getset(get = "pub")]
+    #[serde(default = "default_backup_region")]
+    region: String,
+    /// Prefix of the object keys of the snapshots
+    #[getset(get = "pub")]
+    #[serde(default)]
+    prefix: String,
+    /// Use path style urls, i.e. `<endpoint>/<bucket>/<key>`, which is required by `MinIO`
+    #[getset(get = "pub")]
+    #[serde(default)]
+    path_style: bool,
+    /// Interval between two snapshots
+    #[getset(get = "pub")]
+    #[serde(with = "duration_format", default = "default_backup_interval")]
+    interval: Duration,
+    /// Number of the latest snapshots to keep, older ones are deleted, 0 means keeping all
+    #[getset(get = "pub")]
+    #[serde(default = "default_backup_retention")]
+    retention: usize,
+    /// Whether to log the flushed operations to the change log, so that a backup agent can
+    /// ship the changes between two snapshots
+    #[getset(get = "pub")]
+    #[serde(default)]
+    change_log: bool,
+}
+
+/// default backup endpoint
+#[must_use]
+#[inline]
+pub fn default_backup_endpoint() -> String {
+    "https://s3.us-east-1.amazonaws.com".to_owned()
+}
+
+/// default backup bucket
+#[must_use]
+#[inline]
+pub fn default_backup_bucket() -> String {
+    "xline-backup".to_owned()
+}
+
+/// default backup region
+#[must_use]
+#[inline]
+pub fn default_backup_region() -> String {
+    "us-east-1".to_owned()
+}
+
+/// default backup interval
+#[must_use]
+#[inline]
+pub fn default_backup_interval() -> Duration {
+    Duration::from_secs(3600)
+}
+
+/// default number of snapshots to keep
+#[must_use]
+#[inline]
+pub fn default_backup_retention() -> usize {
+    24
+}
+
+impl BackupConfig {
+    /// Generate a new `BackupConfig` object
+    #[must_use]
+    #[inline]
+    #[allow(clippy::too_many_arguments)] // they are plain fields of the config
+    pub fn new(
+        enable: bool,
+        endpoint: String,
+        bucket: String,
+        region: String,
+        prefix: String,
+        path_style: bool,
+        interval: Duration,
+        retention: usize,
+        change_log: bool,
+    ) -> Self {
+        Self {
+            enable,
+            endpoint,
+            bucket,
+            region,
+            prefix,
+            path_style,
+            interval,
+            retention,
+            change_log,
+        }
+    }
+}
+
+impl Default for BackupConfig {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            enable: false,
+            endpoint: default_backup_endpoint(),
+            bucket: default_backup_bucket(),
+            region: default_backup_region(),
+            prefix: String::new(),
+            path_style: false,
+            interval: default_backup_interval(),
+            retention: default_backup_retention(),
+            change_log: false,
+        }
+    }
+}
+
+/// Proposal batching configuration object, small writes arriving within `window` are
+/// proposed to curp together
+#[allow(clippy::module_name_repetitions)]
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq, Eq, Getters)]
+pub struct BatchConfig {
+    /// Whether the batching is enabled
+    #[getset(get = "pub")]
+    #[serde(default)]
+    enable: bool,
+    /// Max number of writes in a batch
+    #[getset(get = "pub")]
+    #[serde(default = "default_batch_max_size")]
+    max_size: usize,
+    /// How long a batch waits for more writes after its first write arrives
+    #[getset(get = "pub")]
+    #[serde(with = "duration_format", default = "default_batch_window")]
+    window: Duration,
+}
+
+/// default max size of a batch
+#[must_use]
+#[inline]
+pub fn default_batch_max_size() -> usize {
+    128
+}
+
+/// default batch window
+#[must_use]
+#[inline]
+pub fn default_batch_window() -> Duration {
+    Duration::from_millis(1)
+}
+
+impl BatchConfig {
+    /// Generate a new `BatchConfig` object
+    #[must_use]
+    #[inline]
+    pub fn new(enable: bool, max_size: usize, window: Duration) -> Self {
+        Self {
+            enable,
+            max_size,
+            window,
+        }
+    }
+}
+
+impl Default for BatchConfig {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            enable: false,
+            max_size: default_batch_max_size(),
+            window: default_batch_window(),
+        }
+    }
+}
+
+/// Readiness and liveness probe configuration object, the probes are served over plain
+/// HTTP at `/readyz` and `/livez`
+#[allow(clippy::module_name_repetitions)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Getters)]
+pub struct ProbeConfig {
+    /// Whether the probe endpoints are served
+    #[getset(get = "pub")]
+    #[serde(default)]
+    enable: bool,
+    /// Address the probe endpoints listen on
+    #[getset(get = "pub")]
+    #[serde(default = "default_probe_addr")]
+    addr: String,
+    /// How long the committed log entries may stay unapplied before the server is
+    /// considered dead
+    #[getset(get = "pub")]
+    #[serde(with = "duration_format", default = "default_apply_stall_timeout")]
+    apply_stall_timeout: Duration,
+}
+
+/// default address of the probe endpoints
+#[must_use]
+#[inline]
+pub fn default_probe_addr() -> String {
+    "0.0.0.0:2381".to_owned()
+}
+
+/// default timeout of a stalled apply loop
+#[must_use]
+#[inline]
+pub fn default_apply_stall_timeout() -> Duration {
+    Duration::from_secs(60)
+}
+
+impl ProbeConfig {
+    /// Generate a new `ProbeConfig` object
+    #[must_use]
+    #[inline]
+    pub fn new(enable: bool, addr: String, apply_stall_timeout: Duration) -> Self {
+        Self {
+            enable,
+            addr,
+            apply_stall_timeout,
+        }
+    }
+}
+
+impl Default for ProbeConfig {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            enable: false,
+            addr: default_probe_addr(),
+            apply_stall_timeout: default_apply_stall_timeout(),
+        }
+    }
+}
+
+/// Request limit configuration object, the requests beyond the limits are rejected before
+/// they are proposed
+#[allow(clippy::module_name_repetitions)]
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq, Eq, Getters)]
+pub struct RequestLimitConfig {
+    /// Max number of operations in a txn, the compares, the success operations and the
+    /// failure operations are counted separately
+    #[getset(get = "pub")]
+    #[serde(default = "default_max_txn_ops")]
+    max_txn_ops: usize,
+    /// Max size of a request to be proposed in bytes
+    #[getset(get = "pub")]
+    #[serde(default = "default_max_request_bytes")]
+    max_request_bytes: usize,
+    /// Max size of a written key in bytes, 0 means no limit
+    #[getset(get = "pub")]
+    #[serde(default)]
+    max_key_bytes: usize,
+    /// Max size of a written value in bytes, 0 means no limit
+    #[getset(get = "pub")]
+    #[serde(default)]
+    max_value_bytes: usize,
+}
+
+/// default max number of operations in a txn
+#[must_use]
+#[inline]
+pub fn default_max_txn_ops() -> usize {
+    128
+}
+
+/// default max size of a request
+#[must_use]
+#[inline]
+pub fn default_max_request_bytes() -> usize {
+    1536 * 1024
+}
+
+impl RequestLimitConfig {
+    /// Generate a new `RequestLimitConfig` object
+    #[must_use]
+    #[inline]
+    pub fn new(
+        max_txn_ops: usize,
+        max_request_bytes: usize,
+        max_key_bytes: usize,
+        max_value_bytes: usize,
+    ) -> Self {
+        Self {
+            max_txn_ops,
+            max_request_bytes,
+            max_key_bytes,
+            max_value_bytes,
+        }
+    }
+}
+
+impl Default for RequestLimitConfig {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            max_txn_ops: default_max_txn_ops(),
+            max_request_bytes: default_max_request_bytes(),
+            max_key_bytes: 0,
+            max_value_bytes: 0,
+        }
+    }
+}
+
+/// Write quota configuration object, every authenticated user except root is limited by
+/// the quotas, 0 means no limit
+#[allow(clippy::module_name_repetitions)]
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq, Eq, Getters)]
+pub struct QuotaConfig {
+    /// Bytes a user may write before a warning is logged
+    #[getset(get = "pub")]
+    #[serde(default)]
+    soft_bytes: u64,
+    /// Bytes a user may write before the writes are rejected
+    #[getset(get = "pub")]
+    #[serde(default)]
+    hard_bytes: u64,
+    /// Keys a user may write before a warning is logged
+    #[getset(get = "pub")]
+    #[serde(default)]
+    soft_keys: u64,
+    /// Keys a user may write before the writes are rejected
+    #[getset(get = "pub")]
+    #[serde(default)]
+    hard_keys: u64,
+}
+
+impl QuotaConfig {
+    /// Generate a new `QuotaConfig` object
+    #[must_use]
+    #[inline]
+    pub fn new(soft_bytes: u64, hard_bytes: u64, soft_keys: u64, hard_keys: u64) -> Self {
+        Self {
+            soft_bytes,
+            hard_bytes,
+            soft_keys,
+            hard_keys,
+        }
+    }
+}
+
+impl Default for QuotaConfig {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            soft_bytes: 0,
+            hard_bytes: 0,
+            soft_keys: 0,
+            hard_keys: 0,
+        }
+    }
+}
+
+/// Compression configuration object, the gzip compression is enabled for each service
+/// separately. A service with compression enabled accepts gzip compressed requests, and
+/// compresses the responses for the clients which accept gzip.
+#[allow(clippy::module_name_repetitions)]
+#[allow(clippy::struct_excessive_bools)] // each service is enabled separately
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq, Eq, Getters)]
+pub struct CompressionConfig {
+    /// Compress the kv service, eg. large Range responses
+    #[getset(get = "pub")]
+    #[serde(default)]
+    kv: bool,
+    /// Compress the watch service
+    #[getset(get = "pub")]
+    #[serde(default)]
+    watch: bool,
+    /// Compress the lease service
+    #[getset(get = "pub")]
+    #[serde(default)]
+    lease: bool,
+    /// Compress the maintenance service, eg. snapshots
+    #[getset(get = "pub")]
+    #[serde(default)]
+    maintenance: bool,
+}
+
+impl CompressionConfig {
+    /// Generate a new `CompressionConfig` object
+    #[must_use]
+    #[inline]
+    pub fn new(kv: bool, watch: bool, lease: bool, maintenance: bool) -> Self {
+        Self {
+            kv,
+            watch,
+            lease,
+            maintenance,
+        }
+    }
+}
+
+impl Default for CompressionConfig {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            kv: false,
+            watch: false,
+            lease: false,
+            maintenance: false,
+        }
+    }
+}
+
+/// Corruption check configuration object. The checksums of the tables are recorded when
+/// the server shuts down, and verified along with the kv table and the recovered index when
+/// it starts again.
+#[allow(clippy::module_name_repetitions)]
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq, Eq, Getters)]
+pub struct CorruptCheckConfig {
+    /// Whether the storage is checked for corruption when the server starts
+    #[getset(get = "pub")]
+    #[serde(default)]
+    initial_check: bool,
+    /// Serve with the CORRUPT alarm raised instead of refusing to start when corruption is
+    /// found, the writes are rejected while the alarm is active
+    #[getset(get = "pub")]
+    #[serde(default)]
+    serve_on_corrupt: bool,
+    /// Whether the leader periodically compares the kv hashes of all members
+    #[getset(get = "pub")]
+    #[serde(default)]
+    periodic_check: bool,
+    /// Interval between two periodic checks
+    #[getset(get = "pub")]
+    #[serde(with = "duration_format", default = "default_corrupt_check_interval")]
+    check_interval: Duration,
+}
+
+/// default interval between two periodic corruption checks
+#[must_use]
+#[inline]
+pub fn default_corrupt_check_interval() -> Duration {
+    Duration::from_secs(300)
+}
+
+impl CorruptCheckConfig {
+    /// Generate a new `CorruptCheckConfig` object
+    #[must_use]
+    #[inline]
+    pub fn new(
+        initial_check: bool,
+        serve_on_corrupt: bool,
+        periodic_check: bool,
+        check_interval: Duration,
+    ) -> Self {
+        Self {
+            initial_check,
+            serve_on_corrupt,
+            periodic_check,
+            check_interval,
+        }
+    }
+}
+
+impl Default for CorruptCheckConfig {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            initial_check: false,
+            serve_on_corrupt: false,
+            periodic_check: false,
+            check_interval: default_corrupt_check_interval(),
+        }
+    }
+}
+
+/// Compaction configuration object
+#[allow(clippy::module_name_repetitions)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Getters)]
+pub struct CompactConfig {
+    /// The history written in this period of time is retained by the automatic compaction,
+    /// 0 disables the automatic compaction
+    #[getset(get = "pub")]
+    #[serde(with = "duration_format", default = "default_auto_compact_retention")]
+    auto_compact_retention: Duration,
+    /// Daily windows the background maintenance is allowed to run in, empty means at any time
+    #[getset(get = "pub")]
+    #[serde(with = "maintenance_windows_format", default)]
+    maintenance_windows: Vec<MaintenanceWindow>,
+}
+
+/// default retention of the automatic compaction, it's disabled by default
+#[must_use]
+#[inline]
+pub fn default_auto_compact_retention() -> Duration {
+    Duration::ZERO
+}
+
+impl CompactConfig {
+    /// Generate a new `CompactConfig` object
+    #[must_use]
+    #[inline]
+    pub fn new(
+        auto_compact_retention: Duration,
+        maintenance_windows: Vec<MaintenanceWindow>,
+    ) -> Self {
+        Self {
+            auto_compact_retention,
+            maintenance_windows,
+        }
+    }
+
+    /// Whether the background maintenance is allowed to run at `time`
+    #[must_use]
+    #[inline]
+    pub fn in_maintenance_window(&self, time: SystemTime) -> bool {
+        self.maintenance_windows.is_empty()
+            || self
+                .maintenance_windows
+                .iter()
+                .any(|window| window.contains(time))
+    }
+}
+
+impl Default for CompactConfig {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            auto_compact_retention: default_auto_compact_retention(),
+            maintenance_windows: Vec::new(),
+        }
+    }
+}
+
+/// A window of time in every day, in UTC, eg. `01:00-05:00`. The window wraps around
+/// midnight if it ends before it starts, eg. `22:00-02:00`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MaintenanceWindow {
+    /// Minute of the day the window starts at, inclusive
+    start: u32,
+    /// Minute of the day the window ends at, exclusive
+    end: u32,
+}
+
+impl MaintenanceWindow {
+    /// Minutes in a day
+    pub const MINUTES_PER_DAY: u32 = 24 * 60;
+
+    /// Generate a new `MaintenanceWindow` from the minutes of the day it starts and ends at
+    #[must_use]
+    #[inline]
+    pub fn new(start: u32, end: u32) -> Self {
+        Self { start, end }
+    }
+
+    /// Whether the window contains the minute of the day
+    #[must_use]
+    #[inline]
+    pub fn contains_minute(&self, minute: u32) -> bool {
+        if self.start <= self.end {
+            self.start <= minute && minute < self.end
+        } else {
+            self.start <= minute || minute < self.end
+        }
+    }
+
+    /// Whether the window contains `time`
+    #[must_use]
+    #[inline]
+    pub fn contains(&self, time: SystemTime) -> bool {
+        let secs = time
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since_epoch| since_epoch.as_secs());
+        #[allow(clippy::as_conversions)] // This cast is always valid
+        let minute = secs
+            .wrapping_div(60)
+            .wrapping_rem(u64::from(Self::MINUTES_PER_DAY)) as u32;
+        self.contains_minute(minute)
+    }
+}
+
+impl std::fmt::Display for MaintenanceWindow {
+    #[inline]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:02}:{:02}-{:02}:{:02}",
+            self.start.wrapping_div(60),
+            self.start.wrapping_rem(60),
+            self.end.wrapping_div(60),
+            self.end.wrapping_rem(60)
+        )
+    }
+}
+
+/// `MaintenanceWindow` list deserialization formatter
+pub mod maintenance_windows_format {
+    use serde::{self, Deserialize, Deserializer};
+
+    use super::MaintenanceWindow;
+    use crate::parse_maintenance_window;
+
+    /// deserializes a list of maintenance windows
+    #[allow(single_use_lifetimes)]
+    pub(crate) fn deserialize<'de, D>(deserializer: D) -> Result<Vec<MaintenanceWindow>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Vec::<String>::deserialize(deserializer)?
+            .iter()
+            .map(|s| parse_maintenance_window(s).map_err(serde::de::Error::custom))
+            .collect()
+    }
+}
+
+/// Client TLS configuration object. The clients are served over TLS on a listener of its
+/// own, while the peers keep using the member address. The certificate and the key are
+/// reloaded when their files change.
+#[allow(clippy::module_name_repetitions)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Getters)]
+pub struct TlsConfig {
+    /// Whether the clients are served over TLS
+    #[getset(get = "pub")]
+    #[serde(default)]
+    enable: bool,
+    /// Address the TLS listener listens on
+    #[getset(get = "pub")]
+    #[serde(default = "default_tls_addr")]
+    addr: String,
+    /// PEM file of the server certificate chain
+    #[getset(get = "pub")]
+    #[serde(default = "default_tls_cert_file")]
+    cert_file: PathBuf,
+    /// PEM file of the private key of the server certificate
+    #[getset(get = "pub")]
+    #[serde(default = "default_tls_key_file")]
+    key_file: PathBuf,
+    /// Interval between two checks of the certificate and key files for changes
+    #[getset(get = "pub")]
+    #[serde(with = "duration_format", default = "default_tls_reload_interval")]
+    reload_interval: Duration,
+}
+
+/// default address of the TLS listener
+#[must_use]
+#[inline]
+pub fn default_tls_addr() -> String {
+    "0.0.0.0:2389".to_owned()
+}
+
+/// default server certificate file, where cert-manager mounts a certificate secret
+#[must_use]
+#[inline]
+pub fn default_tls_cert_file() -> PathBuf {
+    PathBuf::from("/etc/xline/tls/tls.crt")
+}
+
+/// default private key file, where cert-manager mounts a certificate secret
+#[must_use]
+#[inline]
+pub fn default_tls_key_file() -> PathBuf {
+    PathBuf::from("/etc/xline/tls/tls.key")
+}
+
+/// default interval of the checks of the certificate and key files
+#[must_use]
+#[inline]
+pub fn default_tls_reload_interval() -> Duration {
+    Duration::from_secs(10)
+}
+
+impl TlsConfig {
+    /// Generate a new `TlsConfig` object
+    #[must_use]
+    #[inline]
+    pub fn new(
+        enable: bool,
+        addr: String,
+        cert_file: PathBuf,
+        key_file: PathBuf,
+        reload_interval: Duration,
+    ) -> Self {
+        Self {
+            enable,
+            addr,
+            cert_file,
+            key_file,
+            reload_interval,
+        }
+    }
+}
+
+impl Default for TlsConfig {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            enable: false,
+            addr: default_tls_addr(),
+            cert_file: default_tls_cert_file(),
+            key_file: default_tls_key_file(),
+            reload_interval: default_tls_reload_interval(),
+        }
+    }
+}
+
+/// Peer TLS configuration object. The members connect to each other over mutual TLS on the
+/// member address, and a peer is only accepted if its certificate is valid for the address
+/// of a member. The certificate and the key are reloaded when their files change.
+#[allow(clippy::module_name_repetitions)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Getters)]
+pub struct PeerTlsConfig {
+    /// Whether the peers connect over mutual TLS
+    #[getset(get = "pub")]
+    #[serde(default)]
+    enable: bool,
+    /// PEM file of the member certificate chain, it's presented both as a server and as a
+    /// client certificate
+    #[getset(get = "pub")]
+    #[serde(default = "default_peer_tls_cert_file")]
+    cert_file: PathBuf,
+    /// PEM file of the private key of the member certificate
+    #[getset(get = "pub")]
+    #[serde(default = "default_peer_tls_key_file")]
+    key_file: PathBuf,
+    /// PEM file of the CA certificates the certificates of the peers are verified against
+    #[getset(get = "pub")]
+    #[serde(default = "default_peer_tls_ca_file")]
+    ca_file: PathBuf,
+    /// Interval between two checks of the certificate and key files for changes
+    #[getset(get = "pub")]
+    #[serde(with = "duration_format", default = "default_tls_reload_interval")]
+    reload_interval: Duration,
+}
+
+/// default member certificate file, where cert-manager mounts a certificate secret
+#[must_use]
+#[inline]
+pub fn default_peer_tls_cert_file() -> PathBuf {
+    PathBuf::from("/etc/xline/peer-tls/tls.crt")
+}
+
+/// default private key file of the member certificate
+#[must_use]
+#[inline]
+pub fn default_peer_tls_key_file() -> PathBuf {
+    PathBuf::from("/etc/xline/peer-tls/tls.key")
+}
+
+/// default CA file, where cert-manager mounts the CA of a certificate secret
+#[must_use]
+#[inline]
+pub fn default_peer_tls_ca_file() -> PathBuf {
+    PathBuf::from("/etc/xline/peer-tls/ca.crt")
+}
+
+impl PeerTlsConfig {
+    /// Generate a new `PeerTlsConfig` object
+    #[must_use]
+    #[inline]
+    pub fn new(
+        enable: bool,
+        cert_file: PathBuf,
+        key_file: PathBuf,
+        ca_file: PathBuf,
+        reload_interval: Duration,
+    ) -> Self {
+        Self {
+            enable,
+            cert_file,
+            key_file,
+            ca_file,
+            reload_interval,
+        }
+    }
+}
+
+impl Default for PeerTlsConfig {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            enable: false,
+            cert_file: default_peer_tls_cert_file(),
+            key_file: default_peer_tls_key_file(),
+            ca_file: default_peer_tls_ca_file(),
+            reload_interval: default_tls_reload_interval(),
+        }
+    }
+}
+
+/// PROXY protocol configuration object. The client connections of the trusted proxies may
+/// start with a HAProxy PROXY protocol header carrying the address of the real client.
+#[allow(clippy::module_name_repetitions)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Getters)]
+pub struct ProxyProtocolConfig {
+    /// Whether the PROXY protocol headers are accepted
+    #[getset(get = "pub")]
+    #[serde(default)]
+    enable: bool,
+    /// Addresses or networks of the proxies whose headers are accepted, eg. `10.0.0.0/8`,
+    /// every source is trusted if it's empty
+    #[getset(get = "pub")]
+    #[serde(default)]
+    trusted_proxies: Vec<String>,
+}
+
+impl ProxyProtocolConfig {
+    /// Generate a new `ProxyProtocolConfig` object
+    #[must_use]
+    #[inline]
+    pub fn new(enable: bool, trusted_proxies: Vec<String>) -> Self {
+        Self {
+            enable,
+            trusted_proxies,
+        }
+    }
+}
+
+impl Default for ProxyProtocolConfig {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            enable: false,
+            trusted_proxies: vec![],
+        }
+    }
+}
+
+/// Admission configuration object, the connections and the requests beyond the limits are
+/// shed instead of queued, 0 means no limit
+#[allow(clippy::module_name_repetitions)]
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq, Eq, Getters)]
+pub struct AdmissionConfig {
+    /// Max number of client connections, the connections from the members are not counted
+    #[getset(get = "pub")]
+    #[serde(default)]
+    max_connections: usize,
+    /// Max number of client connections from an IP address
+    #[getset(get = "pub")]
+    #[serde(default)]
+    max_connections_per_ip: usize,
+    /// Max number of unary rpcs of the clients being served at the same time, the streams
+    /// like watches and lease keep alives are not counted
+    #[getset(get = "pub")]
+    #[serde(default)]
+    max_in_flight_requests: usize,
+    /// Max number of the heavy maintenance rpcs, eg. `Snapshot`, `HashKV` and `Compact`,
+    /// being served at the same time, the ones beyond it wait for their turn. They aren't
+    /// counted in `max_in_flight_requests`, 0 means no limit
+    #[getset(get = "pub")]
+    #[serde(default = "default_max_in_flight_maintenance_requests")]
+    max_in_flight_maintenance_requests: usize,
+}
+
+/// default max number of the heavy maintenance rpcs being served at the same time
+#[must_use]
+#[inline]
+pub fn default_max_in_flight_maintenance_requests() -> usize {
+    2
+}
+
+impl AdmissionConfig {
+    /// Generate a new `AdmissionConfig` object
+    #[must_use]
+    #[inline]
+    pub fn new(
+        max_connections: usize,
+        max_connections_per_ip: usize,
+        max_in_flight_requests: usize,
+        max_in_flight_maintenance_requests: usize,
+    ) -> Self {
+        Self {
+            max_connections,
+            max_connections_per_ip,
+            max_in_flight_requests,
+            max_in_flight_maintenance_requests,
+        }
+    }
+}
+
+impl Default for AdmissionConfig {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            max_connections: 0,
+            max_connections_per_ip: 0,
+            max_in_flight_requests: 0,
+            max_in_flight_maintenance_requests: default_max_in_flight_maintenance_requests(),
+        }
+    }
+}
+
+/// Trash configuration object. With the trash enabled, the keys deleted by `DeleteRange`
+/// and txns are kept in a hidden table for the retention period, and the deleted keys can
+/// be restored by `Undelete` meanwhile.
+#[allow(clippy::module_name_repetitions)]
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq, Eq, Getters)]
+pub struct TrashConfig {
+    /// Whether the deleted keys are kept in the trash
+    #[getset(get = "pub")]
+    #[serde(default)]
+    enable: bool,
+    /// How long the deleted keys are kept in the trash
+    #[getset(get = "pub")]
+    #[serde(with = "duration_format", default = "default_trash_retention")]
+    retention: Duration,
+}
+
+/// default retention of the deleted keys in the trash
+#[must_use]
+#[inline]
+pub fn default_trash_retention() -> Duration {
+    Duration::from_secs(24 * 60 * 60)
+}
+
+impl TrashConfig {
+    /// Generate a new `TrashConfig` object
+    #[must_use]
+    #[inline]
+    pub fn new(enable: bool, retention: Duration) -> Self {
+        Self { enable, retention }
+    }
+}
+
+impl Default for TrashConfig {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            enable: false,
+            retention: default_trash_retention(),
+        }
+    }
+}
+
+/// Compression of the blocks of a table
+#[non_exhaustive]
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all(deserialize = "lowercase"))]
+pub enum TableCompression {
+    /// No compression
+    None,
+    /// Snappy, fast with a moderate ratio
+    Snappy,
+    /// LZ4, faster than snappy with a similar ratio
+    Lz4,
+    /// Zstandard, the best ratio at a higher cost of CPU
+    Zstd,
+}
+
+/// Tuning options of a table in the RocksDB engine, the sizes are in bytes, and 0 means
+/// the default of RocksDB
+#[allow(clippy::module_name_repetitions)]
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq, Eq, Getters)]
+pub struct TableConfig {
+    /// Size of the block cache of the table
+    #[getset(get = "pub")]
+    #[serde(default)]
+    block_cache_size: usize,
+    /// Compression of the blocks
+    #[getset(get = "pub")]
+    #[serde(default = "default_table_compression")]
+    compression: TableCompression,
+    /// Size of a memtable of the table before it's flushed
+    #[getset(get = "pub")]
+    #[serde(default)]
+    write_buffer_size: usize,
+    /// Bits per key of the bloom filters, 0 means no bloom filter
+    #[getset(get = "pub")]
+    #[serde(default)]
+    bloom_filter_bits: u32,
+}
+
+/// default compression of the tables
+#[must_use]
+#[inline]
+pub fn default_table_compression() -> TableCompression {
+    TableCompression::Snappy
+}
+
+impl TableConfig {
+    /// Generate a new `TableConfig` object
+    #[must_use]
+    #[inline]
+    pub fn new(
+        block_cache_size: usize,
+        compression: TableCompression,
+        write_buffer_size: usize,
+        bloom_filter_bits: u32,
+    ) -> Self {
+        Self {
+            block_cache_size,
+            compression,
+            write_buffer_size,
+            bloom_filter_bits,
+        }
+    }
+}
+
+impl Default for TableConfig {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            block_cache_size: 0,
+            compression: default_table_compression(),
+            write_buffer_size: 0,
+            bloom_filter_bits: 0,
+        }
+    }
+}
+
+/// When the writes to the backend are synced to the disk
+#[non_exhaustive]
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all(deserialize = "lowercase"))]
+pub enum SyncPolicy {
+    /// Sync every flush of an applied command before it's acknowledged
+    Always,
+    /// Sync every interval, the writes are buffered by the OS meanwhile
+    Interval,
+    /// Leave the writes to the OS, only sync when the server shuts down
+    Never,
+}
+
+impl std::fmt::Display for SyncPolicy {
+    #[inline]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            SyncPolicy::Always => write!(f, "always"),
+            SyncPolicy::Interval => write!(f, "interval"),
+            SyncPolicy::Never => write!(f, "never"),
+        }
+    }
+}
+
+/// default sync policy of the backend
+#[must_use]
+#[inline]
+pub fn default_sync_policy() -> SyncPolicy {
+    SyncPolicy::Never
+}
+
+/// default interval between two syncs of the backend
+#[must_use]
+#[inline]
+pub fn default_sync_interval() -> Duration {
+    Duration::from_millis(100)
+}
+
+/// Backend configuration object, the tuning of the storage engine. The table options only
+/// apply to the RocksDB engine, the tables not listed use the defaults.
+#[allow(clippy::module_name_repetitions)]
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq, Eq, Getters)]
+pub struct BackendConfig {
+    /// Options of the kv table, where the revisions of the keys are stored
+    #[getset(get = "pub")]
+    #[serde(default)]
+    kv: TableConfig,
+    /// Options of the lease table
+    #[getset(get = "pub")]
+    #[serde(default)]
+    lease: TableConfig,
+    /// Options of the auth, user and role tables
+    #[getset(get = "pub")]
+    #[serde(default)]
+    auth: TableConfig,
+    /// When the writes are synced to the disk
+    #[getset(get = "pub")]
+    #[serde(default = "default_sync_policy")]
+    sync: SyncPolicy,
+    /// Interval between two syncs of the `interval` policy
+    #[getset(get = "pub")]
+    #[serde(with = "duration_format", default = "default_sync_interval")]
+    sync_interval: Duration,
+    /// Size in bytes of the block cache shared by the tables without a block cache size of
+    /// their own, 0 means each of them has a default cache
+    #[getset(get = "pub")]
+    #[serde(default)]
+    block_cache_size: usize,
+    /// Read and write the files of the engine by O_DIRECT, bypassing the page cache
+    #[getset(get = "pub")]
+    #[serde(default)]
+    direct_io: bool,
+    /// Max bytes per second written by the background compactions of the engine, 0 means no
+    /// limit
+    #[getset(get = "pub")]
+    #[serde(default)]
+    compaction_rate_limit: u64,
+}
+
+impl BackendConfig {
+    /// Generate a new `BackendConfig` object
+    #[must_use]
+    #[inline]
+    pub fn new(
+        kv: TableConfig,
+        lease: TableConfig,
+        auth: TableConfig,
+        sync: SyncPolicy,
+        sync_interval: Duration,
+        block_cache_size: usize,
+        direct_io: bool,
+        compaction_rate_limit: u64,
+    ) -> Self {
+        Self {
+            kv,
+            lease,
+            auth,
+            sync,
+            sync_interval,
+            block_cache_size,
+            direct_io,
+            compaction_rate_limit,
+        }
+    }
+}
+
+impl Default for BackendConfig {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            kv: TableConfig::default(),
+            lease: TableConfig::default(),
+            auth: TableConfig::default(),
+            sync: default_sync_policy(),
+            sync_interval: default_sync_interval(),
+            block_cache_size: 0,
+            direct_io: false,
+            compaction_rate_limit: 0,
+        }
+    }
+}
+
+/// Lease configuration object, how the leader expires and renews the leases. The jitter
+/// spreads the expiries of the leases granted or renewed at the same time, and the batch
+/// size limits the revocations of each check, so that the leases which expire together,
+/// eg. after a network partition, are revoked gradually instead of all at once. The min
+/// keep alive interval protects the leader from the clients renewing their leases too
+/// often.
+#[allow(clippy::module_name_repetitions)]
+#[derive(Copy, Clone, Debug, Default, Deserialize, PartialEq, Eq, Getters)]
+pub struct LeaseConfig {
+    /// Max time added to the expiry of a lease, each lease is delayed by a part of it
+    /// determined by its id, 0 means no jitter
+    #[getset(get = "pub")]
+    #[serde(with = "duration_format", default)]
+    expiry_jitter: Duration,
+    /// Max number of the expired leases revoked by each check, which runs every 500ms, 0
+    /// means no limit
+    #[getset(get = "pub")]
+    #[serde(default)]
+    revoke_batch_size: usize,
+    /// Min interval between two renewals of a lease, at most a third of its ttl, the
+    /// renewals in between are answered without renewing the lease, 0 means no limit
+    #[getset(get = "pub")]
+    #[serde(with = "duration_format", default)]
+    min_keep_alive_interval: Duration,
+    /// Interval between two reconciliations of the keys and the leases, which detach the
+    /// keys from the missing leases and the leases from the keys not attached to them, 0
+    /// means disabled
+    #[getset(get = "pub")]
+    #[serde(with = "duration_format", default)]
+    reconcile_interval: Duration,
+}
+
+impl LeaseConfig {
+    /// Generate a new `LeaseConfig` object
+    #[must_use]
+    #[inline]
+    pub fn new(
+        expiry_jitter: Duration,
+        revoke_batch_size: usize,
+        min_keep_alive_interval: Duration,
+        reconcile_interval: Duration,
+    ) -> Self {
+        Self {
+            expiry_jitter,
+            revoke_batch_size,
+            min_keep_alive_interval,
+            reconcile_interval,
+        }
+    }
+}
+
+/// Namespace configuration object. A namespace is a key prefix owned by some roles, the
+/// users with any of the roles can only access the keys under the prefixes of their
+/// namespaces, and the keys under the prefix are limited by the quotas, 0 means no limit
+#[allow(clippy::module_name_repetitions)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Getters)]
+pub struct NamespaceConfig {
+    /// Name of the namespace
+    #[getset(get = "pub")]
+    name: String,
+    /// Prefix of the keys in the namespace
+    #[getset(get = "pub")]
+    prefix: String,
+    /// Roles bound to the namespace
+    #[getset(get = "pub")]
+    #[serde(default)]
+    roles: Vec<String>,
+    /// Bytes of the keys and values in the namespace before the writes are rejected
+    #[getset(get = "pub")]
+    #[serde(default)]
+    hard_bytes: u64,
+    /// Number of the keys in the namespace before the writes are rejected
+    #[getset(get = "pub")]
+    #[serde(default)]
+    hard_keys: u64,
+}
+
+impl NamespaceConfig {
+    /// Generate a new `NamespaceConfig` object
+    #[must_use]
+    #[inline]
+    pub fn new(
+        name: String,
+        prefix: String,
+        roles: Vec<String>,
+        hard_bytes: u64,
+        hard_keys: u64,
+    ) -> Self {
+        Self {
+            name,
+            prefix,
+            roles,
+            hard_bytes,
+            hard_keys,
+        }
+    }
+}
+
+impl XlineServerConfig {
+    /// Generates a new `XlineServerConfig` object
+    #[must_use]
+    #[inline]
+    #[allow(clippy::too_many_arguments)] // TODO: refactor this use builder pattern
+    pub fn new(
+        cluster: ClusterConfig,
+        storage: StorageConfig,
+        log: LogConfig,
+        trace: TraceConfig,
+        auth: AuthConfig,
+        grpc: GrpcConfig,
+        rate_limit: RateLimitConfig,
+        server_timeout: ServerTimeout,
+        audit: AuditConfig,
+        backup: BackupConfig,
+        batch: BatchConfig,
+        probe: ProbeConfig,
+        request_limit: RequestLimitConfig,
+        quota: QuotaConfig,
+        compression: CompressionConfig,
+        corrupt_check: CorruptCheckConfig,
+        compact: CompactConfig,
+        tls: TlsConfig,
+        peer_tls: PeerTlsConfig,
+        proxy_protocol: ProxyProtocolConfig,
+        admission: AdmissionConfig,
+        namespaces: Vec<NamespaceConfig>,
+        trash: TrashConfig,
+        backend: BackendConfig,
+        lease: LeaseConfig,
+    ) -> Self {
+        Self {
+            cluster,
+            storage,
+            log,
+            trace,
+            auth,
+            grpc,
+            rate_limit,
+            server_timeout,
+            audit,
+            backup,
+            batch,
+            probe,
+            request_limit,
+            quota,
+            compression,
+            corrupt_check,
+            compact,
+            tls,
+            peer_tls,
+            proxy_protocol,
+            admission,
+            namespaces,
+            trash,
+            backend,
+            lease,
+        }
+    }
+
+    /// Parse the config from a toml document, the fields set by the `XLINE_*` environment
+    /// variables in `vars` take precedence over the ones in the document. Variables which
+    /// are not in `ENV_FIELDS` are ignored.
+    ///
+    /// # Errors
+    ///
+    /// Return `ConfigParseError` if the document or the value of a variable is invalid
+    #[inline]
+    pub fn from_toml_with_env<I>(document: &str, vars: I) -> Result<Self, ConfigParseError>
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        let mut value: toml::Value = toml::from_str(document)
+            .map_err(|e| ConfigParseError::InvalidValue(format!("invalid config: {e}")))?;
+        for (name, raw) in vars {
+            let Some(&(_, path, kind)) = ENV_FIELDS.iter().find(|field| field.0 == name) else {
+                continue;
+            };
+            let field = kind.parse(&raw).map_err(|e| {
+                ConfigParseError::InvalidValue(format!("invalid value of {name}: {e}"))
+            })?;
+            set_field(&mut value, path, field)?;
+        }
+        value
+            .try_into()
+            .map_err(|e| ConfigParseError::InvalidValue(format!("invalid config: {e}")))
+    }
+}
+
+/// Kind of the value of a config field set by an environment variable
+#[derive(Clone, Copy, Debug)]
+enum EnvValueKind {
+    /// Strings, durations, paths and enums
+    String,
+    /// Integers
+    Integer,
+    /// `true` or `false`
+    Bool,
+    /// Members of the cluster, eg. `node1=127.0.0.1:2379,node2=127.0.0.1:2380`
+    Members,
+    /// Comma-separated list of strings
+    List,
+}
+
+impl EnvValueKind {
+    /// Parse the value of an environment variable into a toml value
+    fn parse(self, raw: &str) -> Result<toml::Value, ConfigParseError> {
+        Ok(match self {
+            EnvValueKind::String => toml::Value::String(raw.to_owned()),
+            EnvValueKind::Integer => toml::Value::Integer(raw.parse()?),
+            EnvValueKind::Bool => {
+                toml::Value::Boolean(raw.parse().map_err(|_e| {
+                    ConfigParseError::InvalidValue(format!("{raw:?} is not a bool"))
+                })?)
+            }
+            EnvValueKind::Members => toml::Value::Table(
+                parse_members(raw)?
+                    .into_iter()
+                    .map(|(name, addr)| (name, toml::Value::String(addr)))
+                    .collect(),
+            ),
+            EnvValueKind::List => toml::Value::Array(
+                raw.split(',')
+                    .map(|item| toml::Value::String(item.trim().to_owned()))
+                    .collect(),
+            ),
+        })
+    }
+}
+
+/// Config fields which can be set by environment variables, and the paths of the fields in
+/// the config file. A variable is named after the command line flag of the same field, eg.
+/// `XLINE_LOG_LEVEL` sets `log.level` like `--log-level`.
+const ENV_FIELDS: [(&str, &[&str], EnvValueKind); 86] = [
+    ("XLINE_NAME", &["cluster", "name"], EnvValueKind::String),
+    (
+        "XLINE_MEMBERS",
+        &["cluster", "members"],
+        EnvValueKind::Members,
+    ),
+    (
+        "XLINE_DISCOVERY_SRV",
+        &["cluster", "discovery_srv"],
+        EnvValueKind::String,
+    ),
+    (
+        "XLINE_IS_LEADER",
+        &["cluster", "is_leader"],
+        EnvValueKind::Bool,
+    ),
+    (
+        "XLINE_INITIAL_CLUSTER_STATE",
+        &["cluster", "initial_cluster_state"],
+        EnvValueKind::String,
+    ),
+    (
+        "XLINE_STANDBY_MEMBERS",
+        &["cluster", "standby_members"],
+        EnvValueKind::List,
+    ),
+    (
+        "XLINE_HEARTBEAT_INTERVAL",
+        &["cluster", "curp_config", "heartbeat_interval"],
+        EnvValueKind::String,
+    ),
+    (
+        "XLINE_SERVER_WAIT_SYNCED_TIMEOUT",
+        &["cluster", "curp_config", "wait_synced_timeout"],
+        EnvValueKind::String,
+    ),
+    (
+        "XLINE_RETRY_TIMEOUT",
+        &["cluster", "curp_config", "retry_timeout"],
+        EnvValueKind::String,
+    ),
+    (
+        "XLINE_RPC_TIMEOUT",
+        &["cluster", "curp_config", "rpc_timeout"],
+        EnvValueKind::String,
+    ),
+    (
+        "XLINE_FOLLOWER_TIMEOUT_TICKS",
+        &["cluster", "curp_config", "follower_timeout_ticks"],
+        EnvValueKind::Integer,
+    ),
+    (
+        "XLINE_CANDIDATE_TIMEOUT_TICKS",
+        &["cluster", "curp_config", "candidate_timeout_ticks"],
+        EnvValueKind::Integer,
+    ),
+    (
+        "XLINE_CURP_DIR",
+        &["cluster", "curp_config", "data_dir"],
+        EnvValueKind::String,
+    ),
+    (
+        "XLINE_SNAPSHOT_THRESHOLD",
+        &["cluster", "curp_config", "snapshot_threshold"],
+        EnvValueKind::Integer,
+    ),
+    (
+        "XLINE_CLIENT_WAIT_SYNCED_TIMEOUT",
+        &["cluster", "client_timeout", "wait_synced_timeout"],
+        EnvValueKind::String,
+    ),
+    (
+        "XLINE_CLIENT_PROPOSE_TIMEOUT",
+        &["cluster", "client_timeout", "propose_timeout"],
+        EnvValueKind::String,
+    ),
+    (
+        "XLINE_CLIENT_RETRY_TIMEOUT",
+        &["cluster", "client_timeout", "retry_timeout"],
+        EnvValueKind::String,
+    ),
+    (
+        "XLINE_STORAGE_ENGINE",
+        &["storage", "engine"],
+        EnvValueKind::String,
+    ),
+    (
+        "XLINE_DATA_DIR",
+        &["storage", "data_dir"],
+        EnvValueKind::String,
+    ),
+    ("XLINE_LOG_FILE", &["log", "path"], EnvValueKind::String),
+    (
+        "XLINE_LOG_ROTATE",
+        &["log", "rotation"],
+        EnvValueKind::String,
+    ),
+    ("XLINE_LOG_LEVEL", &["log", "level"], EnvValueKind::String),
+    (
+        "XLINE_JAEGER_ONLINE",
+        &["trace", "jaeger_online"],
+        EnvValueKind::Bool,
+    ),
+    (
+        "XLINE_JAEGER_OFFLINE",
+        &["trace", "jaeger_offline"],
+        EnvValueKind::Bool,
+    ),
+    (
+        "XLINE_JAEGER_OUTPUT_DIR",
+        &["trace", "jaeger_output_dir"],
+        EnvValueKind::String,
+    ),
+    (
+        "XLINE_JAEGER_LEVEL",
+        &["trace", "jaeger_level"],
+        EnvValueKind::String,
+    ),
+    (
+        "XLINE_OTLP_ENDPOINT",
+        &["trace", "otlp_endpoint"],
+        EnvValueKind::String,
+    ),
+    (
+        "XLINE_AUTH_PUBLIC_KEY",
+        &["auth", "auth_public_key"],
+        EnvValueKind::String,
+    ),
+    (
+        "XLINE_AUTH_PRIVATE_KEY",
+        &["auth", "auth_private_key"],
+        EnvValueKind::String,
+    ),
+    (
+        "XLINE_GRPC_KEEPALIVE_INTERVAL",
+        &["grpc", "keepalive_interval"],
+        EnvValueKind::String,
+    ),
+    (
+        "XLINE_GRPC_KEEPALIVE_TIMEOUT",
+        &["grpc", "keepalive_timeout"],
+        EnvValueKind::String,
+    ),
+    (
+        "XLINE_GRPC_MAX_RECV_MSG_SIZE",
+        &["grpc", "max_recv_msg_size"],
+        EnvValueKind::Integer,
+    ),
+    (
+        "XLINE_GRPC_MAX_SEND_MSG_SIZE",
+        &["grpc", "max_send_msg_size"],
+        EnvValueKind::Integer,
+    ),
+    (
+        "XLINE_GRPC_MAX_CONCURRENT_STREAMS",
+        &["grpc", "max_concurrent_streams"],
+        EnvValueKind::Integer,
+    ),
+    (
+        "XLINE_RATE_LIMIT_QPS",
+        &["rate_limit", "qps"],
+        EnvValueKind::Integer,
+    ),
+    (
+        "XLINE_RATE_LIMIT_BURST",
+        &["rate_limit", "burst"],
+        EnvValueKind::Integer,
+    ),
+    (
+        "XLINE_READ_TIMEOUT",
+        &["server_timeout", "read_timeout"],
+        EnvValueKind::String,
+    ),
+    (
+        "XLINE_WRITE_TIMEOUT",
+        &["server_timeout", "write_timeout"],
+        EnvValueKind::String,
+    ),
+    (
+        "XLINE_MAINTENANCE_TIMEOUT",
+        &["server_timeout", "maintenance_timeout"],
+        EnvValueKind::String,
+    ),
+    (
+        "XLINE_SLOW_REQUEST_THRESHOLD",
+        &["server_timeout", "slow_request_threshold"],
+        EnvValueKind::String,
+    ),
+    ("XLINE_AUDIT_LOG", &["audit", "enable"], EnvValueKind::Bool),
+    (
+        "XLINE_AUDIT_LOG_FILE",
+        &["audit", "path"],
+        EnvValueKind::String,
+    ),
+    (
+        "XLINE_AUDIT_LOG_ROTATE",
+        &["audit", "rotation"],
+        EnvValueKind::String,
+    ),
+    (
+        "XLINE_AUDIT_EVENTS",
+        &["audit", "events"],
+        EnvValueKind::List,
+    ),
+    ("XLINE_BACKUP", &["backup", "enable"], EnvValueKind::Bool),
+    (
+        "XLINE_BACKUP_ENDPOINT",
+        &["backup", "endpoint"],
+        EnvValueKind::String,
+    ),
+    (
+        "XLINE_BACKUP_BUCKET",
+        &["backup", "bucket"],
+        EnvValueKind::String,
+    ),
+    (
+        "XLINE_BACKUP_REGION",
+        &["backup", "region"],
+        EnvValueKind::String,
+    ),
+    (
+        "XLINE_BACKUP_PREFIX",
+        &["backup", "prefix"],
+        EnvValueKind::String,
+    ),
+    (
+        "XLINE_BACKUP_PATH_STYLE",
+        &["backup", "path_style"],
+        EnvValueKind::Bool,
+    ),
+    (
+        "XLINE_BACKUP_INTERVAL",
+        &["backup", "interval"],
+        EnvValueKind::String,
+    ),
+    (
+        "XLINE_BACKUP_RETENTION",
+        &["backup", "retention"],
+        EnvValueKind::Integer,
+    ),
+    (
+        "XLINE_BACKUP_CHANGE_LOG",
+        &["backup", "change_log"],
+        EnvValueKind::Bool,
+    ),
+    ("XLINE_BATCH", &["batch", "enable"], EnvValueKind::Bool),
+    (
+        "XLINE_BATCH_MAX_SIZE",
+        &["batch", "max_size"],
+        EnvValueKind::Integer,
+    ),
+    (
+        "XLINE_BATCH_WINDOW",
+        &["batch", "window"],
+        EnvValueKind::String,
+    ),
+    ("XLINE_PROBE", &["probe", "enable"], EnvValueKind::Bool),
+    ("XLINE_PROBE_ADDR", &["probe", "addr"], EnvValueKind::String),
+    (
+        "XLINE_APPLY_STALL_TIMEOUT",
+        &["probe", "apply_stall_timeout"],
+        EnvValueKind::String,
+    ),
+    (
+        "XLINE_MAX_TXN_OPS",
+        &["request_limit", "max_txn_ops"],
+        EnvValueKind::Integer,
+    ),
+    (
+        "XLINE_MAX_REQUEST_BYTES",
+        &["request_limit", "max_request_bytes"],
+        EnvValueKind::Integer,
+    ),
+    (
+        "XLINE_MAX_KEY_BYTES",
+        &["request_limit", "max_key_bytes"],
+        EnvValueKind::Integer,
+    ),
+    (
+        "XLINE_MAX_VALUE_BYTES",
+        &["request_limit", "max_value_bytes"],
+        EnvValueKind::Integer,
+    ),
+    (
+        "XLINE_QUOTA_SOFT_BYTES",
+        &["quota", "soft_bytes"],
+        EnvValueKind::Integer,
+    ),
+    (
+        "XLINE_QUOTA_HARD_BYTES",
+        &["quota", "hard_bytes"],
+        EnvValueKind::Integer,
+    ),
+    (
+        "XLINE_QUOTA_SOFT_KEYS",
+        &["quota", "soft_keys"],
+        EnvValueKind::Integer,
+    ),
+    (
+        "XLINE_QUOTA_HARD_KEYS",
+        &["quota", "hard_keys"],
+        EnvValueKind::Integer,
+    ),
+    (
+        "XLINE_COMPRESS_KV",
+        &["compression", "kv"],
+        EnvValueKind::Bool,
+    ),
+    (
+        "XLINE_COMPRESS_WATCH",
+        &["compression", "watch"],
+        EnvValueKind::Bool,
+    ),
+    (
+        "XLINE_COMPRESS_LEASE",
+        &["compression", "lease"],
+        EnvValueKind::Bool,
+    ),
+    (
+        "XLINE_COMPRESS_MAINTENANCE",
+        &["compression", "maintenance"],
+        EnvValueKind::Bool,
+    ),
+    (
+        "XLINE_INITIAL_CORRUPT_CHECK",
+        &["corrupt_check", "initial_check"],
+        EnvValueKind::Bool,
+    ),
+    (
+        "XLINE_SERVE_ON_CORRUPT",
+        &["corrupt_check", "serve_on_corrupt"],
+        EnvValueKind::Bool,
+    ),
+    (
+        "XLINE_PERIODIC_CORRUPT_CHECK",
+        &["corrupt_check", "periodic_check"],
+        EnvValueKind::Bool,
+    ),
+    (
+        "XLINE_CORRUPT_CHECK_INTERVAL",
+        &["corrupt_check", "check_interval"],
+        EnvValueKind::String,
+    ),
+    (
+        "XLINE_AUTO_COMPACT_RETENTION",
+        &["compact", "auto_compact_retention"],
+        EnvValueKind::String,
+    ),
+    (
+        "XLINE_MAINTENANCE_WINDOWS",
+        &["compact", "maintenance_windows"],
+        EnvValueKind::List,
+    ),
+    ("XLINE_TLS", &["tls", "enable"], EnvValueKind::Bool),
+    ("XLINE_TLS_ADDR", &["tls", "addr"], EnvValueKind::String),
+    (
+        "XLINE_TLS_CERT_FILE",
+        &["tls", "cert_file"],
+        EnvValueKind::String,
+    ),
+    (
+        "XLINE_TLS_KEY_FILE",
+        &["tls", "key_file"],
+        EnvValueKind::String,
+    ),
+    (
+        "XLINE_TLS_RELOAD_INTERVAL",
+        &["tls", "reload_interval"],
+        EnvValueKind::String,
+    ),
+    (
+        "XLINE_PEER_TLS",
+        &["peer_tls", "enable"],
+        EnvValueKind::Bool,
+    ),
+    (
+        "XLINE_PEER_TLS_CERT_FILE",
+        &["peer_tls", "cert_file"],
+        EnvValueKind::String,
+    ),
+    (
+        "XLINE_PEER_TLS_KEY_FILE",
+        &["peer_tls", "key_file"],
+        EnvValueKind::String,
+    ),
+    (
+        "XLINE_PEER_TLS_CA_FILE",
+        &["peer_tls", "ca_file"],
+        EnvValueKind::String,
+    ),
+    (
+        "XLINE_PEER_TLS_RELOAD_INTERVAL",
+        &["peer_tls", "reload_interval"],
+        EnvValueKind::String,
+    ),
+    (
+        "XLINE_PROXY_PROTOCOL",
+        &["proxy_protocol", "enable"],
+        EnvValueKind::Bool,
+    ),
+    (
+        "XLINE_PROXY_PROTOCOL_TRUSTED_PROXIES",
+        &["proxy_protocol", "trusted_proxies"],
+        EnvValueKind::List,
+    ),
+    (
+        "XLINE_MAX_CONNECTIONS",
+        &["admission", "max_connections"],
+        EnvValueKind::Integer,
+    ),
+    (
+        "XLINE_MAX_CONNECTIONS_PER_IP",
+        &["admission", "max_connections_per_ip"],
+        EnvValueKind::Integer,
+    ),
+    (
+        "XLINE_MAX_IN_FLIGHT_REQUESTS",
+        &["admission", "max_in_flight_requests"],
+        EnvValueKind::Integer,
+    ),
+    (
+        "XLINE_MAX_IN_FLIGHT_MAINTENANCE_REQUESTS",
+        &["admission", "max_in_flight_maintenance_requests"],
+        EnvValueKind::Integer,
+    ),
+    ("XLINE_TRASH", &["trash", "enable"], EnvValueKind::Bool),
+    (
+        "XLINE_TRASH_RETENTION",
+        &["trash", "retention"],
+        EnvValueKind::String,
+    ),
+    (
+        "XLINE_BACKEND_SYNC",
+        &["backend", "sync"],
+        EnvValueKind::String,
+    ),
+    (
+        "XLINE_BACKEND_SYNC_INTERVAL",
+        &["backend", "sync_interval"],
+        EnvValueKind::String,
+    ),
+    (
+        "XLINE_BACKEND_BLOCK_CACHE_SIZE",
+        &["backend", "block_cache_size"],
+        EnvValueKind::Integer,
+    ),
+    (
+        "XLINE_BACKEND_DIRECT_IO",
+        &["backend", "direct_io"],
+        EnvValueKind::Bool,
+    ),
+    (
+        "XLINE_BACKEND_COMPACTION_RATE_LIMIT",
+        &["backend", "compaction_rate_limit"],
+        EnvValueKind::Integer,
+    ),
+    (
+        "XLINE_LEASE_EXPIRY_JITTER",
+        &["lease", "expiry_jitter"],
+        EnvValueKind::String,
+    ),
+    (
+        "XLINE_LEASE_REVOKE_BATCH_SIZE",
+        &["lease", "revoke_batch_size"],
+        EnvValueKind::Integer,
+    ),
+    (
+        "XLINE_LEASE_MIN_KEEP_ALIVE_INTERVAL",
+        &["lease", "min_keep_alive_interval"],
+        EnvValueKind::String,
+    ),
+    (
+        "XLINE_LEASE_RECONCILE_INTERVAL",
+        &["lease", "reconcile_interval"],
+        EnvValueKind::String,
+    ),
+];
+
+/// Set the field at `path` of a toml document, the missing tables on the path are created
+fn set_field(
+    document: &mut toml::Value,
+    path: &[&str],
+    field: toml::Value,
+) -> Result<(), ConfigParseError> {
+    let Some((key, parents)) = path.split_last() else {
+        return Ok(());
+    };
+    let mut table = document;
+    for parent in parents {
+        table = table
+            .as_table_mut()
+            .ok_or_else(|| ConfigParseError::InvalidValue(format!("{parent} is not a table")))?
+            .entry((*parent).to_owned())
+            .or_insert_with(|| toml::Value::Table(toml::value::Table::new()));
+    }
+    let _prev = table
+        .as_table_mut()
+        .ok_or_else(|| ConfigParseError::InvalidValue(format!("{key} is not in a table")))?
+        .insert((*key).to_owned(), field);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[allow(clippy::unwrap_used)]
+    #[test]
+    fn test_xline_server_config_should_be_loaded() {
+        let config: XlineServerConfig = toml::from_str(
+            r#"[cluster]
+            name = 'node1'
+            is_leader = true
+            initial_cluster_state = 'new'
+            command_wire_version = 0
+            standby_members = ['node3']
+
+            [cluster.members]
+            node1 = '127.0.0.1:2379'
+            node2 = '127.0.0.1:2380'
+            node3 = '127.0.0.1:2381'
+
+            [cluster.curp_config]
+            heartbeat_interval = '200ms'
+            wait_synced_timeout = '100ms'
+            rpc_timeout = '100ms'
+            retry_timeout = '100us'
+            snapshot_threshold = 500
+
+            [cluster.client_timeout]
+            retry_timeout = '5s'
+
+            [storage]
+            engine = 'memory'
+
+            [log]
+            path = '/var/log/xline'
+            rotation = 'daily'
+            level = 'info'
+
+            [trace]
+            jaeger_online = false
+            jaeger_offline = false
+            jaeger_output_dir = './jaeger_jsons'
+            jaeger_level = 'info'
+
+            [auth]"#,
+        )
+        .unwrap();
+
+        let curp_config = CurpConfig::new(
+            Duration::from_millis(200),
+            Duration::from_millis(100),
+            Duration::from_micros(100),
+            Duration::from_millis(100),
+            default_follower_timeout_ticks(),
+            default_candidate_timeout_ticks(),
+            default_curp_data_dir(),
+            500,
+        );
+
+        let client_timeout = ClientTimeout::new(
+            default_client_wait_synced_timeout(),
+            default_propose_timeout(),
+            Duration::from_secs(5),
+        );
+
+        assert_eq!(
+            config.cluster,
+            ClusterConfig::new(
+                "node1".to_owned(),
+                HashMap::from_iter([
+                    ("node1".to_owned(), "127.0.0.1:2379".to_owned()),
+                    ("node2".to_owned(), "127.0.0.1:2380".to_owned()),
+                    ("node3".to_owned(), "127.0.0.1:2381".to_owned()),
+                ]),
+                None,
+                true,
+                InitialClusterState::New,
+                curp_config,
+                client_timeout,
+                Some(0),
+                vec!["node3".to_owned()]
+            )
+        );
+
+        assert_eq!(config.storage, StorageConfig::Memory);
+
+        assert_eq!(
+            config.log,
+            LogConfig::new(
+                PathBuf::from("/var/log/xline"),
+                RotationConfig::Daily,
+                LevelConfig::INFO
+            )
+        );
+        assert_eq!(
+            config.trace,
+            TraceConfig::new(
+                false,
+                false,
+                PathBuf::from("./jaeger_jsons"),
+                LevelConfig::INFO,
+                None
+            )
+        );
+    }
+
+    #[allow(clippy::unwrap_used)]
+    #[test]
+    fn test_xline_server_grpc_config_should_be_loaded() {
+        let config: XlineServerConfig = toml::from_str(
+            r#"[cluster]
+            name = 'node1'
+            is_leader = true
+
+            [cluster.members]
+            node1 = '127.0.0.1:2379'
+
+            [storage]
+            engine = 'memory'
+
+            [log]
+            path = '/var/log/xline'
+
+            [trace]
+            jaeger_online = false
+            jaeger_offline = false
+            jaeger_output_dir = './jaeger_jsons'
+            jaeger_level = 'info'
+
+            [auth]
+
+            [auth.external]
+            provider = 'oidc'
+            url = 'https://accounts.example.com'
+            audience = 'xline'
+            groups_claim = 'roles'
+
+            [auth.external.role_mapping]
+            developers = ['dev-rw']
+            admins = ['root']
+
+            [grpc]
+            keepalive_interval = '10s'
+            max_recv_msg_size = 10485760
+
+            [rate_limit]
+            qps = 1000
+
+            [server_timeout]
+            write_timeout = '3s'
+            slow_request_threshold = '200ms'
+
+            [audit]
+            enable = true
+            rotation = 'never'
+            events = ['authentication', 'write']
+
+            [backup]
+            enable = true
+            endpoint = 'http://127.0.0.1:9000'
+            path_style = true
+            interval = '1800s'
+            change_log = true
+
+            [batch]
+            enable = true
+            window = '2ms'
+
+            [probe]
+            enable = true
+            addr = '127.0.0.1:2381'
+
+            [request_limit]
+            max_txn_ops = 256
+            max_value_bytes = 65536
+
+            [quota]
+            hard_bytes = 1048576
+            soft_keys = 1000
+
+            [compression]
+            kv = true
+            watch = true
+
+            [corrupt_check]
+            initial_check = true
+            check_interval = '600s'
+
+            [compact]
+            auto_compact_retention = '3h'
+            maintenance_windows = ['22:30-02:00', '12:00-13:00']
+
+            [tls]
+            enable = true
+            addr = '0.0.0.0:2443'
+            cert_file = '/etc/xline/certs/server.pem'
+
+            [peer_tls]
+            enable = true
+            ca_file = '/etc/xline/certs/ca.pem'
+
+            [proxy_protocol]
+            enable = true
+            trusted_proxies = ['10.0.0.0/8', '192.168.1.10']
+
+            [admission]
+            max_connections = 10000
+            max_in_flight_requests = 2048
+            max_in_flight_maintenance_requests = 1
+
+            [trash]
+            enable = true
+
+            [backend]
+            sync = 'interval'
+            sync_interval = '10ms'
+            block_cache_size = 1073741824
+            direct_io = true
+            compaction_rate_limit = 33554432
+
+            [backend.kv]
+            block_cache_size = 268435456
+            compression = 'zstd'
+            bloom_filter_bits = 10
+
+            [backend.auth]
+            compression = 'none'
+
+            [lease]
+            expiry_jitter = '30s'
+            revoke_batch_size = 100
+            min_keep_alive_interval = '1s'
+            reconcile_interval = '5m'
+
+            [[namespace]]
+            name = 'tenant-a'
+            prefix = '/tenant-a/'
+            roles = ['tenant-a-rw', 'tenant-a-ro']
+            hard_keys = 10000
+
+            [[namespace]]
+            name = 'tenant-b'
+            prefix = '/tenant-b/'"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.auth.external,
+            Some(ExternalAuthConfig::new(
+                ExternalAuthProvider::Oidc,
+                "https://accounts.example.com".to_owned(),
+                "xline".to_owned(),
+                default_username_claim(),
+                "roles".to_owned(),
+                default_external_auth_timeout(),
+                HashMap::from([
+                    ("developers".to_owned(), vec!["dev-rw".to_owned()]),
+                    ("admins".to_owned(), vec!["root".to_owned()]),
+                ])
+            ))
+        );
+        assert_eq!(
+            config.grpc,
+            GrpcConfig::new(
+                Duration::from_secs(10),
+                default_keepalive_timeout(),
+                10 * 1024 * 1024,
+                default_max_send_msg_size(),
+                default_max_concurrent_streams()
+            )
+        );
+        assert_eq!(
+            config.rate_limit,
+            RateLimitConfig::new(1000, default_rate_limit_burst())
+        );
+        assert_eq!(
+            config.server_timeout,
+            ServerTimeout::new(
+                default_read_timeout(),
+                Duration::from_secs(3),
+                default_maintenance_timeout(),
+                Duration::from_millis(200)
+            )
+        );
+        assert_eq!(
+            config.audit,
+            AuditConfig::new(
+                true,
+                default_audit_path(),
+                RotationConfig::Never,
+                vec![AuditEvent::Authentication, AuditEvent::Write]
+            )
+        );
+        assert_eq!(
+            config.backup,
+            BackupConfig::new(
+                true,
+                "http://127.0.0.1:9000".to_owned(),
+                default_backup_bucket(),
+                default_backup_region(),
+                String::new(),
+                true,
+                Duration::from_secs(1800),
+                default_backup_retention(),
+                true
+            )
+        );
+        assert_eq!(
+            config.batch,
+            BatchConfig::new(true, default_batch_max_size(), Duration::from_millis(2))
+        );
+        assert_eq!(
+            config.probe,
+            ProbeConfig::new(
+                true,
+                "127.0.0.1:2381".to_owned(),
+                default_apply_stall_timeout()
+            )
+        );
+        assert_eq!(
+            config.request_limit,
+            RequestLimitConfig::new(256, default_max_request_bytes(), 0, 65536)
+        );
+        assert_eq!(config.quota, QuotaConfig::new(0, 1_048_576, 1000, 0));
+        assert_eq!(
+            config.compression,
+            CompressionConfig::new(true, true, false, false)
+        );
+        assert_eq!(
+            config.corrupt_check,
+            CorruptCheckConfig::new(true, false, false, Duration::from_secs(600))
+        );
+        assert_eq!(
+            config.compact,
+            CompactConfig::new(
+                Duration::from_secs(10800),
+                vec![
+                    MaintenanceWindow::new(1350, 120),
+                    MaintenanceWindow::new(720, 780)
+                ]
+            )
+        );
+        assert_eq!(
+            config.tls,
+            TlsConfig::new(
+                true,
+                "0.0.0.0:2443".to_owned(),
+                PathBuf::from("/etc/xline/certs/server.pem"),
+                default_tls_key_file(),
+                default_tls_reload_interval()
+            )
+        );
+        assert_eq!(
+            config.peer_tls,
+            PeerTlsConfig::new(
+                true,
+                default_peer_tls_cert_file(),
+                default_peer_tls_key_file(),
+                PathBuf::from("/etc/xline/certs/ca.pem"),
+                default_tls_reload_interval()
+            )
+        );
+        assert_eq!(
+            config.proxy_protocol,
+            ProxyProtocolConfig::new(
+                true,
+                vec!["10.0.0.0/8".to_owned(), "192.168.1.10".to_owned()]
+            )
+        );
+        assert_eq!(config.admission, AdmissionConfig::new(10000, 0, 2048, 1));
+        assert_eq!(
+            config.namespaces,
+            vec![
+                NamespaceConfig::new(
+                    "tenant-a".to_owned(),
+                    "/tenant-a/".to_owned(),
+                    vec!["tenant-a-rw".to_owned(), "tenant-a-ro".to_owned()],
+                    0,
+                    10000
+                ),
+                NamespaceConfig::new("tenant-b".to_owned(), "/tenant-b/".to_owned(), vec![], 0, 0)
+            ]
+        );
+        assert_eq!(
+            config.trash,
+            TrashConfig::new(true, default_trash_retention())
+        );
+        assert_eq!(
+            config.backend,
+            BackendConfig::new(
+                TableConfig::new(256 * 1024 * 1024, TableCompression::Zstd, 0, 10),
+                TableConfig::default(),
+                TableConfig::new(0, TableCompression::None, 0, 0),
+                SyncPolicy::Interval,
+                Duration::from_millis(10),
+                1_073_741_824,
+                true,
+                33_554_432
+            )
+        );
+        assert_eq!(
+            config.lease,
+            LeaseConfig::new(
+                Duration::from_secs(30),
+                100,
+                Duration::from_secs(1),
+                Duration::from_secs(300)
+            )
+        );
+    }
+
+    #[allow(clippy::unwrap_used)]
+    #[test]
+    fn test_memory_file_storage_config_should_be_loaded() {
+        let config: StorageConfig = toml::from_str(
+            r#"engine = 'memory_file'
+                data_dir = '/usr/local/xline/data-dir'
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config,
+            StorageConfig::MemoryFile(PathBuf::from("/usr/local/xline/data-dir"))
+        );
+    }
+
+    #[allow(clippy::unwrap_used)]
+    #[test]
+    fn test_xline_server_default_config_should_be_loaded() {
+        let config: XlineServerConfig = toml::from_str(
+            r#"[cluster]
+                name = 'node1'
+                is_leader = true
+
+                [cluster.members]
+                node1 = '127.0.0.1:2379'
+                node2 = '127.0.0.1:2380'
+                node3 = '127.0.0.1:2381'
+
+                [log]
+                path = '/var/log/xline'
 
                 [storage]
                 engine = 'rocksdb'
@@ -654,9 +3407,13 @@ mod tests {
                     ("node2".to_owned(), "127.0.0.1:2380".to_owned()),
                     ("node3".to_owned(), "127.0.0.1:2381".to_owned()),
                 ]),
+                None,
                 true,
+                InitialClusterState::New,
                 CurpConfig::default(),
-                ClientTimeout::default()
+                ClientTimeout::default(),
+                None,
+                vec![]
             )
         );
 
@@ -680,8 +3437,122 @@ mod tests {
                 false,
                 false,
                 PathBuf::from("./jaeger_jsons"),
-                LevelConfig::INFO
+                LevelConfig::INFO,
+                None
             )
         );
+        assert_eq!(config.grpc, GrpcConfig::default());
+        assert_eq!(config.rate_limit, RateLimitConfig::default());
+        assert_eq!(config.server_timeout, ServerTimeout::default());
+        assert_eq!(config.audit, AuditConfig::default());
+        assert_eq!(config.backup, BackupConfig::default());
+        assert_eq!(config.batch, BatchConfig::default());
+        assert_eq!(config.probe, ProbeConfig::default());
+        assert_eq!(config.request_limit, RequestLimitConfig::default());
+        assert_eq!(config.quota, QuotaConfig::default());
+        assert_eq!(config.compression, CompressionConfig::default());
+        assert_eq!(config.corrupt_check, CorruptCheckConfig::default());
+        assert_eq!(config.compact, CompactConfig::default());
+        assert_eq!(config.tls, TlsConfig::default());
+        assert_eq!(config.peer_tls, PeerTlsConfig::default());
+        assert_eq!(config.proxy_protocol, ProxyProtocolConfig::default());
+        assert_eq!(config.admission, AdmissionConfig::default());
+        assert!(config.namespaces.is_empty());
+        assert_eq!(config.trash, TrashConfig::default());
+        assert_eq!(config.backend, BackendConfig::default());
+        assert_eq!(config.lease, LeaseConfig::default());
+    }
+
+    #[allow(clippy::unwrap_used)]
+    #[test]
+    fn test_cluster_config_with_discovery_srv_should_be_loaded() {
+        let config: XlineServerConfig = toml::from_str(
+            r#"[cluster]
+                name = 'node1'
+                is_leader = true
+                discovery_srv = 'example.com'
+
+                [log]
+                path = '/var/log/xline'
+
+                [storage]
+                engine = 'memory'
+
+                [trace]
+                jaeger_online = false
+                jaeger_offline = false
+                jaeger_output_dir = './jaeger_jsons'
+                jaeger_level = 'info'
+
+                [auth]"#,
+        )
+        .unwrap();
+
+        assert!(config.cluster.members.is_empty());
+        assert_eq!(config.cluster.discovery_srv.as_deref(), Some("example.com"));
+    }
+
+    #[allow(clippy::unwrap_used)]
+    #[test]
+    fn test_env_should_override_config_file() {
+        let document = r"[cluster]
+            name = 'node1'
+            is_leader = true
+
+            [cluster.members]
+            node1 = '127.0.0.1:2379'
+
+            [storage]
+            engine = 'memory'
+
+            [log]
+            path = '/var/log/xline'
+            level = 'info'
+
+            [trace]
+            jaeger_online = false
+            jaeger_offline = false
+            jaeger_output_dir = './jaeger_jsons'
+            jaeger_level = 'info'
+
+            [auth]";
+        let vars = [
+            ("XLINE_LOG_LEVEL", "debug"),
+            ("XLINE_MEMBERS", "node1=10.0.0.1:2379,node2=10.0.0.2:2379"),
+            ("XLINE_RPC_TIMEOUT", "100ms"),
+            ("XLINE_RATE_LIMIT_QPS", "100"),
+            ("XLINE_AUDIT_EVENTS", "write,authentication"),
+            ("XLINE_BACKUP", "true"),
+            ("XLINE_SERVER_CONFIG", "/etc/xline_server.conf"),
+            ("HOME", "/root"),
+        ]
+        .map(|(k, v)| (k.to_owned(), v.to_owned()));
+        let config = XlineServerConfig::from_toml_with_env(document, vars).unwrap();
+
+        assert_eq!(*config.log.level(), LevelConfig::DEBUG);
+        assert_eq!(config.cluster.members().len(), 2);
+        assert_eq!(
+            config.cluster.members().get("node2").map(String::as_str),
+            Some("10.0.0.2:2379")
+        );
+        assert!(*config.cluster.is_leader());
+        assert_eq!(
+            config.cluster.curp_config().rpc_timeout,
+            Duration::from_millis(100)
+        );
+        assert_eq!(
+            config.cluster.curp_config().heartbeat_interval,
+            default_heartbeat_interval()
+        );
+        assert_eq!(*config.rate_limit.qps(), 100);
+        assert_eq!(*config.rate_limit.burst(), default_rate_limit_burst());
+        assert_eq!(
+            *config.audit.events(),
+            vec![AuditEvent::Write, AuditEvent::Authentication]
+        );
+        assert!(*config.backup.enable());
+
+        let invalid = [("XLINE_RATE_LIMIT_QPS".to_owned(), "many".to_owned())];
+        assert!(XlineServerConfig::from_toml_with_env(document, invalid).is_err());
     }
 }
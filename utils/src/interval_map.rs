@@ -0,0 +1,428 @@
+use std::cmp::Ordering;
+
+/// A half-open interval `[low, high)`
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Interval<T> {
+    /// Inclusive lower bound
+    low: T,
+    /// Exclusive upper bound
+    high: T,
+}
+
+impl<T: Ord> Interval<T> {
+    /// New `Interval`, it contains nothing if `low` is not less than `high`
+    #[inline]
+    pub fn new(low: T, high: T) -> Self {
+        Self { low, high }
+    }
+
+    /// Get the inclusive lower bound
+    #[inline]
+    pub fn low(&self) -> &T {
+        &self.low
+    }
+
+    /// Get the exclusive upper bound
+    #[inline]
+    pub fn high(&self) -> &T {
+        &self.high
+    }
+
+    /// Check if two intervals overlap, an empty interval overlaps with nothing
+    #[inline]
+    pub fn overlaps(&self, other: &Self) -> bool {
+        self.low < self.high
+            && other.low < other.high
+            && self.low < other.high
+            && other.low < self.high
+    }
+}
+
+/// Link to a child node
+type Link<T, V> = Option<Box<Node<T, V>>>;
+
+/// Node of the interval tree
+#[derive(Debug, Clone)]
+struct Node<T, V> {
+    /// Interval of the node, it's the key of the tree
+    interval: Interval<T>,
+    /// Value of the interval
+    value: V,
+    /// The largest upper bound in the subtree
+    max: T,
+    /// Height of the subtree
+    height: usize,
+    /// Left child
+    left: Link<T, V>,
+    /// Right child
+    right: Link<T, V>,
+}
+
+/// Map from intervals to values, which finds the intervals overlapping with a given one
+/// in `O(log n + k)` time, where `k` is the number of the overlapping intervals. It's an
+/// AVL tree ordered by the intervals, and each node keeps the largest upper bound in its
+/// subtree, so the subtrees that end before the given interval are skipped.
+#[derive(Debug, Clone)]
+pub struct IntervalMap<T, V> {
+    /// Root of the tree
+    root: Link<T, V>,
+    /// Number of intervals
+    len: usize,
+}
+
+impl<T, V> Default for IntervalMap<T, V> {
+    #[inline]
+    fn default() -> Self {
+        Self { root: None, len: 0 }
+    }
+}
+
+impl<T: Ord + Clone, V> IntervalMap<T, V> {
+    /// New empty `IntervalMap`
+    #[inline]
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of intervals in the map
+    #[inline]
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Check if the map is empty
+    #[inline]
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Insert an interval, return the old value if the interval exists
+    #[inline]
+    pub fn insert(&mut self, interval: Interval<T>, value: V) -> Option<V> {
+        let (root, old) = Node::insert(self.root.take(), interval, value);
+        self.root = Some(root);
+        if old.is_none() {
+            self.len = self.len.wrapping_add(1);
+        }
+        old
+    }
+
+    /// Remove an interval, return its value if it exists
+    #[inline]
+    pub fn remove(&mut self, interval: &Interval<T>) -> Option<V> {
+        let (root, old) = Node::remove(self.root.take(), interval);
+        self.root = root;
+        if old.is_some() {
+            self.len = self.len.wrapping_sub(1);
+        }
+        old
+    }
+
+    /// Get the value of an interval
+    #[inline]
+    pub fn get(&self, interval: &Interval<T>) -> Option<&V> {
+        let mut link = &self.root;
+        while let Some(ref node) = *link {
+            link = match interval.cmp(&node.interval) {
+                Ordering::Less => &node.left,
+                Ordering::Greater => &node.right,
+                Ordering::Equal => return Some(&node.value),
+            };
+        }
+        None
+    }
+
+    /// Check if any interval in the map overlaps with the given one
+    #[inline]
+    pub fn overlaps(&self, interval: &Interval<T>) -> bool {
+        Node::overlaps(&self.root, interval)
+    }
+
+    /// Find all intervals overlapping with the given one, in the order of the intervals
+    #[inline]
+    pub fn find_all_overlap(&self, interval: &Interval<T>) -> Vec<(&Interval<T>, &V)> {
+        let mut found = Vec::new();
+        Node::find_all_overlap(&self.root, interval, &mut found);
+        found
+    }
+}
+
+#[allow(clippy::unnecessary_box_returns)] // the nodes are linked by boxes
+impl<T: Ord + Clone, V> Node<T, V> {
+    /// New leaf node
+    fn new_leaf(interval: Interval<T>, value: V) -> Box<Self> {
+        Box::new(Self {
+            max: interval.high.clone(),
+            interval,
+            value,
+            height: 1,
+            left: None,
+            right: None,
+        })
+    }
+
+    /// Height of a subtree
+    fn height(link: &Link<T, V>) -> usize {
+        link.as_ref().map_or(0, |node| node.height)
+    }
+
+    /// Update the height and the max upper bound after the children are changed
+    fn update(&mut self) {
+        self.height = Self::height(&self.left)
+            .max(Self::height(&self.right))
+            .wrapping_add(1);
+        let mut max = &self.interval.high;
+        for child in [&self.left, &self.right].into_iter().flatten() {
+            if child.max > *max {
+                max = &child.max;
+            }
+        }
+        self.max = max.clone();
+    }
+
+    /// Rotate the subtree to the right, the left child becomes the root
+    fn rotate_right(mut node: Box<Self>) -> Box<Self> {
+        let Some(mut left) = node.left.take() else {
+            return node;
+        };
+        node.left = left.right.take();
+        node.update();
+        left.right = Some(node);
+        left.update();
+        left
+    }
+
+    /// Rotate the subtree to the left, the right child becomes the root
+    fn rotate_left(mut node: Box<Self>) -> Box<Self> {
+        let Some(mut right) = node.right.take() else {
+            return node;
+        };
+        node.right = right.left.take();
+        node.update();
+        right.left = Some(node);
+        right.update();
+        right
+    }
+
+    /// Update the node and rotate it if its children's heights differ by more than one
+    fn rebalance(mut node: Box<Self>) -> Box<Self> {
+        node.update();
+        let left_height = Self::height(&node.left);
+        let right_height = Self::height(&node.right);
+        if left_height > right_height.wrapping_add(1) {
+            if let Some(left) = node.left.take() {
+                node.left = Some(if Self::height(&left.right) > Self::height(&left.left) {
+                    Self::rotate_left(left)
+                } else {
+                    left
+                });
+            }
+            Self::rotate_right(node)
+        } else if right_height > left_height.wrapping_add(1) {
+            if let Some(right) = node.right.take() {
+                node.right = Some(if Self::height(&right.left) > Self::height(&right.right) {
+                    Self::rotate_right(right)
+                } else {
+                    right
+                });
+            }
+            Self::rotate_left(node)
+        } else {
+            node
+        }
+    }
+
+    /// Insert an interval into the subtree, return the new root and the old value
+    fn insert(link: Link<T, V>, interval: Interval<T>, value: V) -> (Box<Self>, Option<V>) {
+        let Some(mut node) = link else {
+            return (Self::new_leaf(interval, value), None);
+        };
+        match interval.cmp(&node.interval) {
+            Ordering::Less => {
+                let (left, old) = Self::insert(node.left.take(), interval, value);
+                node.left = Some(left);
+                (Self::rebalance(node), old)
+            }
+            Ordering::Greater => {
+                let (right, old) = Self::insert(node.right.take(), interval, value);
+                node.right = Some(right);
+                (Self::rebalance(node), old)
+            }
+            Ordering::Equal => {
+                let old = std::mem::replace(&mut node.value, value);
+                (node, Some(old))
+            }
+        }
+    }
+
+    /// Remove an interval from the subtree, return the new root and the removed value
+    fn remove(link: Link<T, V>, interval: &Interval<T>) -> (Link<T, V>, Option<V>) {
+        let Some(mut node) = link else {
+            return (None, None);
+        };
+        match interval.cmp(&node.interval) {
+            Ordering::Less => {
+                let (left, old) = Self::remove(node.left.take(), interval);
+                node.left = left;
+                (Some(Self::rebalance(node)), old)
+            }
+            Ordering::Greater => {
+                let (right, old) = Self::remove(node.right.take(), interval);
+                node.right = right;
+                (Some(Self::rebalance(node)), old)
+            }
+            Ordering::Equal => {
+                let Node {
+                    value, left, right, ..
+                } = *node;
+                let root = match (left, right) {
+                    (None, child) | (child, None) => child,
+                    (Some(left), Some(right)) => {
+                        // replace the node with the smallest one of its right subtree
+                        let (right, mut min) = Self::remove_min(right);
+                        min.left = Some(left);
+                        min.right = right;
+                        Some(Self::rebalance(min))
+                    }
+                };
+                (root, Some(value))
+            }
+        }
+    }
+
+    /// Remove the smallest node of the subtree, return the new root and the removed node
+    fn remove_min(mut node: Box<Self>) -> (Link<T, V>, Box<Self>) {
+        match node.left.take() {
+            None => (node.right.take(), node),
+            Some(left) => {
+                let (left, min) = Self::remove_min(left);
+                node.left = left;
+                (Some(Self::rebalance(node)), min)
+            }
+        }
+    }
+
+    /// Check if any interval in the subtree overlaps with the given one
+    fn overlaps(link: &Link<T, V>, interval: &Interval<T>) -> bool {
+        let Some(ref node) = *link else {
+            return false;
+        };
+        // every interval in the subtree ends before the given one starts
+        if node.max <= interval.low {
+            return false;
+        }
+        node.interval.overlaps(interval)
+            || Self::overlaps(&node.left, interval)
+            || (node.interval.low < interval.high && Self::overlaps(&node.right, interval))
+    }
+
+    /// Collect the intervals in the subtree overlapping with the given one
+    fn find_all_overlap<'a>(
+        link: &'a Link<T, V>,
+        interval: &Interval<T>,
+        found: &mut Vec<(&'a Interval<T>, &'a V)>,
+    ) {
+        let Some(ref node) = *link else {
+            return;
+        };
+        if node.max <= interval.low {
+            return;
+        }
+        Self::find_all_overlap(&node.left, interval, found);
+        if node.interval.overlaps(interval) {
+            found.push((&node.interval, &node.value));
+        }
+        // the intervals on the right start after this one
+        if node.interval.low < interval.high {
+            Self::find_all_overlap(&node.right, interval, found);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn interval(low: u32, high: u32) -> Interval<u32> {
+        Interval::new(low, high)
+    }
+
+    /// Check the heights, the max upper bounds and the order of the tree
+    fn check<T: Ord + Clone + std::fmt::Debug, V>(link: &Link<T, V>) -> usize {
+        let Some(ref node) = *link else {
+            return 0;
+        };
+        let left_height = check(&node.left);
+        let right_height = check(&node.right);
+        assert!(left_height.abs_diff(right_height) <= 1);
+        assert_eq!(node.height, left_height.max(right_height).wrapping_add(1));
+        let mut max = node.interval.high.clone();
+        for child in [&node.left, &node.right].into_iter().flatten() {
+            max = max.max(child.max.clone());
+        }
+        assert_eq!(node.max, max);
+        if let Some(ref left) = node.left {
+            assert!(left.interval < node.interval);
+        }
+        if let Some(ref right) = node.right {
+            assert!(right.interval > node.interval);
+        }
+        node.height
+    }
+
+    #[test]
+    fn test_insert_and_remove() {
+        let mut map = IntervalMap::new();
+        for i in 0..100 {
+            assert!(map.insert(interval(i, i + 10), i).is_none());
+            let _height = check(&map.root);
+        }
+        assert_eq!(map.len(), 100);
+        assert_eq!(map.insert(interval(5, 15), 50), Some(5));
+        assert_eq!(map.get(&interval(5, 15)), Some(&50));
+        assert_eq!(map.len(), 100);
+
+        for i in (0..100).step_by(2) {
+            assert!(map.remove(&interval(i, i + 10)).is_some());
+            let _height = check(&map.root);
+        }
+        assert!(map.remove(&interval(0, 10)).is_none());
+        assert_eq!(map.len(), 50);
+        assert!(map.get(&interval(2, 12)).is_none());
+        assert_eq!(map.get(&interval(3, 13)), Some(&3));
+    }
+
+    #[test]
+    fn test_find_all_overlap() {
+        let mut map = IntervalMap::new();
+        for (low, high, value) in [
+            (1, 3, "a"),
+            (2, 6, "b"),
+            (5, 6, "c"),
+            (8, 20, "d"),
+            (9, 10, "e"),
+        ] {
+            assert!(map.insert(interval(low, high), value).is_none());
+        }
+
+        let values = |low, high| {
+            map.find_all_overlap(&interval(low, high))
+                .into_iter()
+                .map(|(_, value)| *value)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(values(0, 1), Vec::<&str>::new());
+        assert_eq!(values(0, 2), vec!["a"]);
+        assert_eq!(values(3, 5), vec!["b"]);
+        assert_eq!(values(5, 9), vec!["b", "c", "d"]);
+        assert_eq!(values(6, 8), Vec::<&str>::new());
+        assert_eq!(values(15, 100), vec!["d"]);
+        assert!(map.overlaps(&interval(9, 10)));
+        assert!(!map.overlaps(&interval(20, 30)));
+        // an empty interval overlaps with nothing
+        assert!(!map.overlaps(&interval(4, 4)));
+    }
+}
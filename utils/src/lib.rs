@@ -130,10 +130,15 @@ use std::{collections::HashMap, time::Duration};
 
 use thiserror::Error;
 
-use crate::config::{ClusterRange, LevelConfig, RotationConfig};
+use crate::config::{
+    AuditEvent, ClusterRange, InitialClusterState, LevelConfig, MaintenanceWindow, RotationConfig,
+    SyncPolicy,
+};
 
 /// configuration
 pub mod config;
+/// Map from intervals to values, based on an interval tree
+pub mod interval_map;
 /// utils of `parking_lot` lock
 #[cfg(feature = "parking_lot")]
 pub mod parking_lot_lock;
@@ -225,13 +230,26 @@ pub fn parse_duration(s: &str) -> Result<Duration, ConfigParseError> {
                 "the value of time should not be empty ({s})"
             )))
         }
+    } else if let Some(dur) = s.strip_suffix('m') {
+        parse_minutes(dur.parse()?, 1, s)
+    } else if let Some(dur) = s.strip_suffix('h') {
+        parse_minutes(dur.parse()?, 60, s)
     } else {
         Err(ConfigParseError::InvalidUnit(format!(
-            "the unit of time should be one of 'us', 'ms' or 's'({s})"
+            "the unit of time should be one of 'us', 'ms', 's', 'm' or 'h'({s})"
         )))
     }
 }
 
+/// Convert a number of minutes, or of `minutes` minutes long units, to `Duration`
+fn parse_minutes(count: u64, minutes: u64, s: &str) -> Result<Duration, ConfigParseError> {
+    count
+        .checked_mul(minutes)
+        .and_then(|m| m.checked_mul(60))
+        .map(Duration::from_secs)
+        .ok_or_else(|| ConfigParseError::InvalidValue(format!("the time is too long ({s})")))
+}
+
 /// Parse `LevelConfig` from string
 /// # Errors
 /// Return error when parsing the given string to `LevelConfig` failed
@@ -264,6 +282,87 @@ pub fn parse_rotation(s: &str) -> Result<RotationConfig, ConfigParseError> {
     }
 }
 
+/// Parse `SyncPolicy` from string
+/// # Errors
+/// Return error when parsing the given string to `SyncPolicy` failed
+#[inline]
+pub fn parse_sync_policy(s: &str) -> Result<SyncPolicy, ConfigParseError> {
+    match s {
+        "always" => Ok(SyncPolicy::Always),
+        "interval" => Ok(SyncPolicy::Interval),
+        "never" => Ok(SyncPolicy::Never),
+        _ => Err(ConfigParseError::InvalidValue(format!(
+            "the sync policy should be one of 'always', 'interval' or 'never' ({s})"
+        ))),
+    }
+}
+
+/// Parse `InitialClusterState` from string
+/// # Errors
+/// Return error when parsing the given string to `InitialClusterState` failed
+#[inline]
+pub fn parse_initial_cluster_state(s: &str) -> Result<InitialClusterState, ConfigParseError> {
+    match s {
+        "new" => Ok(InitialClusterState::New),
+        "existing" => Ok(InitialClusterState::Existing),
+        _ => Err(ConfigParseError::InvalidValue(format!(
+            "the initial cluster state should be 'new' or 'existing' ({s})"
+        ))),
+    }
+}
+
+/// Parse `AuditEvent` from string
+/// # Errors
+/// Return error when parsing the given string to `AuditEvent` failed
+#[inline]
+pub fn parse_audit_event(s: &str) -> Result<AuditEvent, ConfigParseError> {
+    match s {
+        "authentication" => Ok(AuditEvent::Authentication),
+        "auth_config" => Ok(AuditEvent::AuthConfig),
+        "write" => Ok(AuditEvent::Write),
+        _ => Err(ConfigParseError::InvalidValue(format!(
+            "the audit event should be one of 'authentication', 'auth_config' or 'write' ({s})"
+        ))),
+    }
+}
+
+/// Parse `MaintenanceWindow` from string, eg. `01:00-05:00`
+/// # Errors
+/// Return error when parsing the given string to `MaintenanceWindow` failed
+#[inline]
+pub fn parse_maintenance_window(s: &str) -> Result<MaintenanceWindow, ConfigParseError> {
+    let Some((start, end)) = s.split_once('-') else {
+        return Err(ConfigParseError::InvalidValue(format!(
+            "the maintenance window should be like 'HH:MM-HH:MM' ({s})"
+        )));
+    };
+    let start = parse_minute_of_day(start.trim(), s)?;
+    let end = parse_minute_of_day(end.trim(), s)?;
+    if start == end {
+        return Err(ConfigParseError::InvalidValue(format!(
+            "the maintenance window should not be empty ({s})"
+        )));
+    }
+    Ok(MaintenanceWindow::new(start, end))
+}
+
+/// Parse a time of the day like `HH:MM` to the minute of the day, `24:00` is the end of
+/// the day
+fn parse_minute_of_day(time: &str, s: &str) -> Result<u32, ConfigParseError> {
+    let invalid = || ConfigParseError::InvalidValue(format!("invalid time of the day ({s})"));
+    let (hour, minute) = time.split_once(':').ok_or_else(invalid)?;
+    let hour: u32 = hour.parse()?;
+    let minute: u32 = minute.parse()?;
+    if minute >= 60 {
+        return Err(invalid());
+    }
+    hour.checked_mul(60)
+        .and_then(|m| m.checked_add(minute))
+        .filter(|&m| m <= MaintenanceWindow::MINUTES_PER_DAY)
+        .map(|m| m.wrapping_rem(MaintenanceWindow::MINUTES_PER_DAY))
+        .ok_or_else(invalid)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -274,10 +373,14 @@ mod test {
         assert_eq!(parse_duration("5s").unwrap(), Duration::from_secs(5));
         assert_eq!(parse_duration("3ms").unwrap(), Duration::from_millis(3));
         assert_eq!(parse_duration("1us").unwrap(), Duration::from_micros(1));
+        assert_eq!(parse_duration("2m").unwrap(), Duration::from_secs(120));
+        assert_eq!(parse_duration("3h").unwrap(), Duration::from_secs(10800));
         let results = vec![
             parse_duration("hello world"),
             parse_duration("5x"),
             parse_duration("helloms"),
+            parse_duration("h"),
+            parse_duration("99999999999999999h"),
         ];
 
         for res in results {
@@ -337,4 +440,63 @@ mod test {
         let res = parse_rotation("hello world");
         assert!(res.is_err());
     }
+
+    #[allow(clippy::unwrap_used)]
+    #[test]
+    fn test_parse_sync_policy() {
+        assert_eq!(parse_sync_policy("always").unwrap(), SyncPolicy::Always);
+        assert_eq!(parse_sync_policy("interval").unwrap(), SyncPolicy::Interval);
+        assert_eq!(parse_sync_policy("never").unwrap(), SyncPolicy::Never);
+        assert!(parse_sync_policy("sometimes").is_err());
+    }
+
+    #[allow(clippy::unwrap_used)]
+    #[test]
+    fn test_parse_initial_cluster_state() {
+        assert_eq!(
+            parse_initial_cluster_state("new").unwrap(),
+            InitialClusterState::New
+        );
+        assert_eq!(
+            parse_initial_cluster_state("existing").unwrap(),
+            InitialClusterState::Existing
+        );
+        assert!(parse_initial_cluster_state("old").is_err());
+    }
+
+    #[allow(clippy::unwrap_used)]
+    #[test]
+    fn test_parse_audit_event() {
+        assert_eq!(
+            parse_audit_event("authentication").unwrap(),
+            AuditEvent::Authentication
+        );
+        assert_eq!(
+            parse_audit_event("auth_config").unwrap(),
+            AuditEvent::AuthConfig
+        );
+        assert_eq!(parse_audit_event("write").unwrap(), AuditEvent::Write);
+        assert!(parse_audit_event("member").is_err());
+    }
+
+    #[allow(clippy::unwrap_used)]
+    #[test]
+    fn test_parse_maintenance_window() {
+        let window = parse_maintenance_window("01:30-05:00").unwrap();
+        assert_eq!(window, MaintenanceWindow::new(90, 300));
+        assert!(window.contains_minute(90));
+        assert!(!window.contains_minute(300));
+        let window = parse_maintenance_window("22:00-24:00").unwrap();
+        assert_eq!(window, MaintenanceWindow::new(1320, 0));
+        let window = parse_maintenance_window("23:00-01:00").unwrap();
+        assert!(window.contains_minute(1439));
+        assert!(window.contains_minute(0));
+        assert!(!window.contains_minute(60));
+        assert!(!window.contains_minute(720));
+        assert_eq!(window.to_string(), "23:00-01:00");
+        assert!(parse_maintenance_window("01:00").is_err());
+        assert!(parse_maintenance_window("01:00-01:00").is_err());
+        assert!(parse_maintenance_window("01:60-02:00").is_err());
+        assert!(parse_maintenance_window("25:00-02:00").is_err());
+    }
 }
@@ -2,6 +2,7 @@ use opentelemetry::{
     global,
     propagation::{Extractor, Injector},
 };
+use tonic::codegen::http::{header::HeaderName, HeaderMap};
 use tracing::Span;
 use tracing_opentelemetry::OpenTelemetrySpanExt;
 
@@ -26,17 +27,46 @@ impl Extractor for ExtractMap<'_> {
     }
 }
 
+/// Struct for extract data from http `HeaderMap`
+struct ExtractHeaderMap<'a>(&'a HeaderMap);
+
+impl Extractor for ExtractHeaderMap<'_> {
+    /// Get a value for a key from the `HeaderMap`.  If the value can't be converted to &str, returns None
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    /// Collect all the keys from the `HeaderMap`.
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(HeaderName::as_str).collect::<Vec<_>>()
+    }
+}
+
 /// Function for extract data from some struct
 pub trait Extract {
+    /// extract span context from self and set as parent context of the given span
+    fn extract_span_into(&self, span: &Span);
+
     /// extract span context from self and set as parent context
-    fn extract_span(&self);
+    #[inline]
+    fn extract_span(&self) {
+        self.extract_span_into(&Span::current());
+    }
 }
 
 impl Extract for tonic::metadata::MetadataMap {
     #[inline]
-    fn extract_span(&self) {
+    fn extract_span_into(&self, span: &Span) {
         let parent_ctx = global::get_text_map_propagator(|prop| prop.extract(&ExtractMap(self)));
-        let span = Span::current();
+        span.set_parent(parent_ctx);
+    }
+}
+
+impl Extract for HeaderMap {
+    #[inline]
+    fn extract_span_into(&self, span: &Span) {
+        let parent_ctx =
+            global::get_text_map_propagator(|prop| prop.extract(&ExtractHeaderMap(self)));
         span.set_parent(parent_ctx);
     }
 }
@@ -102,6 +132,14 @@ mod test {
         request.metadata_mut().inject_current();
         let inner_trace_id = inner_fun(&request);
         assert_eq!(outer_trace_id, inner_trace_id);
+
+        let headers = request.metadata().clone().into_headers();
+        let header_span = info_span!("header span");
+        headers.extract_span_into(&header_span);
+        assert_eq!(
+            outer_trace_id,
+            header_span.context().span().span_context().trace_id()
+        );
         Ok(())
     }
 
@@ -0,0 +1,15 @@
+fn main() {
+    tonic_build::configure()
+        .build_server(false)
+        .compile(
+            &[
+                "../xline/proto/kv.proto",
+                "../xline/proto/rpc.proto",
+                "../xline/proto/auth.proto",
+                "../xline/proto/v3lock.proto",
+                "../xline/proto/health.proto",
+            ],
+            &["../xline/proto"],
+        )
+        .unwrap_or_else(|e| panic!("Failed to compile proto, error is {:?}", e));
+}
@@ -0,0 +1,261 @@
+use std::time::Duration;
+
+use tonic::{
+    codegen::InterceptedService,
+    metadata::AsciiMetadataValue,
+    service::Interceptor,
+    transport::{Channel, Endpoint},
+};
+
+use crate::{
+    clients::{
+        auth::AuthClient, cluster::ClusterClient, kv::KvClient, lease::LeaseClient,
+        lock::LockClient, maintenance::MaintenanceClient, watch::WatchClient,
+    },
+    errors::ClientError,
+    health,
+    retry::RetryPolicy,
+    rpc::{self, AuthenticateRequest},
+};
+
+/// Default interval between two health checks of the endpoints
+const DEFAULT_HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Options of the `Client`
+#[derive(Debug, Clone, Default)]
+pub struct ClientOptions {
+    /// Name and password of the user, the client authenticates with them if they are set
+    user: Option<(String, String)>,
+    /// Timeout of each request
+    timeout: Option<Duration>,
+    /// Timeout of connecting to an endpoint
+    connect_timeout: Option<Duration>,
+    /// Policy of retrying idempotent requests
+    retry_policy: RetryPolicy,
+    /// Interval between two health checks of the endpoints
+    health_check_interval: Option<Duration>,
+}
+
+impl ClientOptions {
+    /// New `ClientOptions`
+    #[inline]
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the user to authenticate with
+    #[inline]
+    #[must_use]
+    pub fn with_user(mut self, name: impl Into<String>, password: impl Into<String>) -> Self {
+        self.user = Some((name.into(), password.into()));
+        self
+    }
+
+    /// Set `timeout`
+    #[inline]
+    #[must_use]
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Set `connect_timeout`
+    #[inline]
+    #[must_use]
+    pub fn with_connect_timeout(mut self, connect_timeout: Duration) -> Self {
+        self.connect_timeout = Some(connect_timeout);
+        self
+    }
+
+    /// Set `retry_policy`
+    #[inline]
+    #[must_use]
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Set `health_check_interval`
+    #[inline]
+    #[must_use]
+    pub fn with_health_check_interval(mut self, health_check_interval: Duration) -> Self {
+        self.health_check_interval = Some(health_check_interval);
+        self
+    }
+}
+
+/// Xline client, it contains the clients of each service, which share one connection
+#[derive(Debug, Clone)]
+pub struct Client {
+    /// Kv client
+    kv: KvClient,
+    /// Watch client
+    watch: WatchClient,
+    /// Lease client
+    lease: LeaseClient,
+    /// Lock client
+    lock: LockClient,
+    /// Auth client
+    auth: AuthClient,
+    /// Maintenance client
+    maintenance: MaintenanceClient,
+    /// Cluster client
+    cluster: ClusterClient,
+}
+
+impl Client {
+    /// Connect to the Xline servers, eg. `["127.0.0.1:2379", "http://127.0.0.1:2380"]`.
+    /// The requests are balanced across the healthy endpoints, and the idempotent ones
+    /// are retried on transient failures according to the retry policy.
+    ///
+    /// # Errors
+    ///
+    /// If an endpoint is invalid, or the authentication fails
+    #[inline]
+    pub async fn connect<E>(
+        endpoints: impl IntoIterator<Item = E>,
+        options: ClientOptions,
+    ) -> Result<Self, ClientError>
+    where
+        E: AsRef<str>,
+    {
+        let endpoints = endpoints
+            .into_iter()
+            .map(|addr| Self::build_endpoint(addr.as_ref(), &options))
+            .collect::<Result<Vec<_>, _>>()?;
+        if endpoints.is_empty() {
+            return Err(ClientError::InvalidArgs("no endpoint is given".to_owned()));
+        }
+        let channel = health::balance_healthy(
+            endpoints,
+            options
+                .health_check_interval
+                .unwrap_or(DEFAULT_HEALTH_CHECK_INTERVAL),
+        );
+        let retry_policy = options.retry_policy;
+
+        let token = match options.user {
+            Some((name, password)) => {
+                let token = retry_policy
+                    .retry(|| {
+                        let mut client = rpc::AuthClient::new(channel.clone());
+                        let request = AuthenticateRequest {
+                            name: name.clone(),
+                            password: password.clone(),
+                            ..AuthenticateRequest::default()
+                        };
+                        async move { client.authenticate(request).await }
+                    })
+                    .await?
+                    .into_inner()
+                    .token;
+                let token = token
+                    .parse()
+                    .map_err(|_e| ClientError::InvalidArgs(format!("invalid token {token}")))?;
+                Some(token)
+            }
+            None => None,
+        };
+        let interceptor = AuthInterceptor { token };
+
+        Ok(Self {
+            kv: KvClient::new(channel.clone(), interceptor.clone(), retry_policy),
+            watch: WatchClient::new(channel.clone(), interceptor.clone(), retry_policy),
+            lease: LeaseClient::new(channel.clone(), interceptor.clone(), retry_policy),
+            lock: LockClient::new(channel.clone(), interceptor.clone()),
+            auth: AuthClient::new(channel.clone(), interceptor.clone(), retry_policy),
+            maintenance: MaintenanceClient::new(channel.clone(), interceptor.clone(), retry_policy),
+            cluster: ClusterClient::new(channel, interceptor, retry_policy),
+        })
+    }
+
+    /// Build the `Endpoint` of an address, `http` is used if the scheme is not given
+    fn build_endpoint(addr: &str, options: &ClientOptions) -> Result<Endpoint, ClientError> {
+        let uri = if addr.contains("://") {
+            addr.to_owned()
+        } else {
+            format!("http://{addr}")
+        };
+        let mut endpoint = Endpoint::from_shared(uri)
+            .map_err(|e| ClientError::InvalidArgs(format!("invalid endpoint {addr}, {e}")))?;
+        if let Some(timeout) = options.timeout {
+            endpoint = endpoint.timeout(timeout);
+        }
+        if let Some(connect_timeout) = options.connect_timeout {
+            endpoint = endpoint.connect_timeout(connect_timeout);
+        }
+        Ok(endpoint)
+    }
+
+    /// Get the kv client
+    #[inline]
+    #[must_use]
+    pub fn kv_client(&self) -> KvClient {
+        self.kv.clone()
+    }
+
+    /// Get the watch client
+    #[inline]
+    #[must_use]
+    pub fn watch_client(&self) -> WatchClient {
+        self.watch.clone()
+    }
+
+    /// Get the lease client
+    #[inline]
+    #[must_use]
+    pub fn lease_client(&self) -> LeaseClient {
+        self.lease.clone()
+    }
+
+    /// Get the lock client
+    #[inline]
+    #[must_use]
+    pub fn lock_client(&self) -> LockClient {
+        self.lock.clone()
+    }
+
+    /// Get the auth client
+    #[inline]
+    #[must_use]
+    pub fn auth_client(&self) -> AuthClient {
+        self.auth.clone()
+    }
+
+    /// Get the maintenance client
+    #[inline]
+    #[must_use]
+    pub fn maintenance_client(&self) -> MaintenanceClient {
+        self.maintenance.clone()
+    }
+
+    /// Get the cluster client
+    #[inline]
+    #[must_use]
+    pub fn cluster_client(&self) -> ClusterClient {
+        self.cluster.clone()
+    }
+}
+
+/// Interceptor that attaches the auth token to every request
+#[derive(Debug, Clone)]
+pub(crate) struct AuthInterceptor {
+    /// Token got by authentication, `None` if the client doesn't authenticate
+    token: Option<AsciiMetadataValue>,
+}
+
+impl Interceptor for AuthInterceptor {
+    fn call(
+        &mut self,
+        mut request: tonic::Request<()>,
+    ) -> Result<tonic::Request<()>, tonic::Status> {
+        if let Some(ref token) = self.token {
+            let _prev = request.metadata_mut().insert("token", token.clone());
+        }
+        Ok(request)
+    }
+}
+
+/// Channel that attaches the auth token to requests
+pub(crate) type AuthChannel = InterceptedService<Channel, AuthInterceptor>;
@@ -0,0 +1,505 @@
+use tonic::transport::Channel;
+
+use crate::{
+    client::{AuthChannel, AuthInterceptor},
+    errors::ClientError,
+    retry::RetryPolicy,
+    rpc::{
+        self, AuthApiKeyAddRequest, AuthApiKeyListRequest, AuthApiKeyRevokeRequest,
+        AuthDisableRequest, AuthEnableRequest, AuthRoleAddRequest, AuthRoleDeleteRequest,
+        AuthRoleGetRequest, AuthRoleGrantPermissionRequest, AuthRoleListRequest,
+        AuthRoleRevokePermissionRequest, AuthStatusRequest, AuthUserAddRequest,
+        AuthUserChangePasswordRequest, AuthUserDeleteRequest, AuthUserGetRequest,
+        AuthUserGrantRoleRequest, AuthUserListRequest, AuthUserRevokeRoleRequest,
+        AuthenticateRequest, UserAddOptions,
+    },
+    types::auth::{
+        AuthApiKeyAddResponse, AuthApiKeyListResponse, AuthApiKeyRevokeResponse,
+        AuthDisableResponse, AuthEnableResponse, AuthRoleAddResponse, AuthRoleDeleteResponse,
+        AuthRoleGetResponse, AuthRoleGrantPermissionResponse, AuthRoleListResponse,
+        AuthRoleRevokePermissionResponse, AuthStatusResponse, AuthUserAddResponse,
+        AuthUserChangePasswordResponse, AuthUserDeleteResponse, AuthUserGetResponse,
+        AuthUserGrantRoleResponse, AuthUserListResponse, AuthUserRevokeRoleResponse,
+        AuthenticateResponse, Permission,
+    },
+};
+
+/// Client for Auth operations
+#[derive(Debug, Clone)]
+pub struct AuthClient {
+    /// Inner client
+    inner: rpc::AuthClient<AuthChannel>,
+    /// Policy of retrying idempotent requests
+    retry_policy: RetryPolicy,
+}
+
+impl AuthClient {
+    /// New `AuthClient`
+    pub(crate) fn new(
+        channel: Channel,
+        interceptor: AuthInterceptor,
+        retry_policy: RetryPolicy,
+    ) -> Self {
+        Self {
+            inner: rpc::AuthClient::with_interceptor(channel, interceptor),
+            retry_policy,
+        }
+    }
+
+    /// Enable authentication
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn auth_enable(&mut self) -> Result<AuthEnableResponse, ClientError> {
+        let request = AuthEnableRequest {};
+        Ok(self.inner.auth_enable(request).await?.into_inner())
+    }
+
+    /// Disable authentication
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn auth_disable(&mut self) -> Result<AuthDisableResponse, ClientError> {
+        let request = AuthDisableRequest {};
+        Ok(self.inner.auth_disable(request).await?.into_inner())
+    }
+
+    /// Get the status of authentication
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn auth_status(&mut self) -> Result<AuthStatusResponse, ClientError> {
+        let request = AuthStatusRequest {};
+        let response = self
+            .retry_policy
+            .retry(|| {
+                let mut inner = self.inner.clone();
+                let request = request.clone();
+                async move { inner.auth_status(request).await }
+            })
+            .await?;
+        Ok(response.into_inner())
+    }
+
+    /// Authenticate a user and get a token
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn authenticate(
+        &mut self,
+        name: impl Into<String>,
+        password: impl Into<String>,
+    ) -> Result<AuthenticateResponse, ClientError> {
+        self.authenticate_scoped(name, password, [], 0).await
+    }
+
+    /// Authenticate a user and get a token restricted to `scopes`, which expires after `ttl`
+    /// seconds. It is an Xline extension, a request with the token is permitted only if both
+    /// the user and the scopes permit it, the token has all the permissions of the user if
+    /// `scopes` is empty, and `ttl` is the default TTL if it's 0.
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn authenticate_scoped(
+        &mut self,
+        name: impl Into<String>,
+        password: impl Into<String>,
+        scopes: impl IntoIterator<Item = Permission>,
+        ttl: i64,
+    ) -> Result<AuthenticateResponse, ClientError> {
+        let request = AuthenticateRequest {
+            name: name.into(),
+            password: password.into(),
+            scopes: scopes.into_iter().map(Into::into).collect(),
+            ttl,
+            external_token: String::new(),
+            api_key: String::new(),
+        };
+        self.send_authenticate(request).await
+    }
+
+    /// Exchange a bearer token issued outside Xline, eg. by an OIDC issuer, for a token of
+    /// Xline. It is an Xline extension, the bearer token is validated by the external
+    /// authentication provider of the server, and the token has the roles mapped from the
+    /// groups of the external identity.
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn authenticate_external(
+        &mut self,
+        external_token: impl Into<String>,
+    ) -> Result<AuthenticateResponse, ClientError> {
+        let request = AuthenticateRequest {
+            external_token: external_token.into(),
+            ..AuthenticateRequest::default()
+        };
+        self.send_authenticate(request).await
+    }
+
+    /// Authenticate by an API key and get a token of the user of the key. It is an Xline
+    /// extension for the machine accounts, the key is the one returned by `api_key_add`.
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn authenticate_api_key(
+        &mut self,
+        api_key: impl Into<String>,
+    ) -> Result<AuthenticateResponse, ClientError> {
+        let request = AuthenticateRequest {
+            api_key: api_key.into(),
+            ..AuthenticateRequest::default()
+        };
+        self.send_authenticate(request).await
+    }
+
+    /// Send an authenticate request with retries
+    async fn send_authenticate(
+        &self,
+        request: AuthenticateRequest,
+    ) -> Result<AuthenticateResponse, ClientError> {
+        let response = self
+            .retry_policy
+            .retry(|| {
+                let mut inner = self.inner.clone();
+                let request = request.clone();
+                async move { inner.authenticate(request).await }
+            })
+            .await?;
+        Ok(response.into_inner())
+    }
+
+    /// Add a user with a password
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn user_add(
+        &mut self,
+        name: impl Into<String>,
+        password: impl Into<String>,
+    ) -> Result<AuthUserAddResponse, ClientError> {
+        let request = AuthUserAddRequest {
+            name: name.into(),
+            password: password.into(),
+            options: Some(UserAddOptions { no_password: false }),
+            hashed_password: String::new(),
+        };
+        Ok(self.inner.user_add(request).await?.into_inner())
+    }
+
+    /// Add a user without a password, it can't be authenticated by a password
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn user_add_without_password(
+        &mut self,
+        name: impl Into<String>,
+    ) -> Result<AuthUserAddResponse, ClientError> {
+        let request = AuthUserAddRequest {
+            name: name.into(),
+            password: String::new(),
+            options: Some(UserAddOptions { no_password: true }),
+            hashed_password: String::new(),
+        };
+        Ok(self.inner.user_add(request).await?.into_inner())
+    }
+
+    /// Get a user
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn user_get(
+        &mut self,
+        name: impl Into<String>,
+    ) -> Result<AuthUserGetResponse, ClientError> {
+        let request = AuthUserGetRequest { name: name.into() };
+        let response = self
+            .retry_policy
+            .retry(|| {
+                let mut inner = self.inner.clone();
+                let request = request.clone();
+                async move { inner.user_get(request).await }
+            })
+            .await?;
+        Ok(response.into_inner())
+    }
+
+    /// List all users
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn user_list(&mut self) -> Result<AuthUserListResponse, ClientError> {
+        let request = AuthUserListRequest {};
+        let response = self
+            .retry_policy
+            .retry(|| {
+                let mut inner = self.inner.clone();
+                let request = request.clone();
+                async move { inner.user_list(request).await }
+            })
+            .await?;
+        Ok(response.into_inner())
+    }
+
+    /// Delete a user
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn user_delete(
+        &mut self,
+        name: impl Into<String>,
+    ) -> Result<AuthUserDeleteResponse, ClientError> {
+        let request = AuthUserDeleteRequest { name: name.into() };
+        Ok(self.inner.user_delete(request).await?.into_inner())
+    }
+
+    /// Change the password of a user
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn user_change_password(
+        &mut self,
+        name: impl Into<String>,
+        password: impl Into<String>,
+    ) -> Result<AuthUserChangePasswordResponse, ClientError> {
+        let request = AuthUserChangePasswordRequest {
+            name: name.into(),
+            password: password.into(),
+            hashed_password: String::new(),
+        };
+        Ok(self.inner.user_change_password(request).await?.into_inner())
+    }
+
+    /// Grant a role to a user
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn user_grant_role(
+        &mut self,
+        user: impl Into<String>,
+        role: impl Into<String>,
+    ) -> Result<AuthUserGrantRoleResponse, ClientError> {
+        let request = AuthUserGrantRoleRequest {
+            user: user.into(),
+            role: role.into(),
+        };
+        Ok(self.inner.user_grant_role(request).await?.into_inner())
+    }
+
+    /// Revoke a role from a user
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn user_revoke_role(
+        &mut self,
+        name: impl Into<String>,
+        role: impl Into<String>,
+    ) -> Result<AuthUserRevokeRoleResponse, ClientError> {
+        let request = AuthUserRevokeRoleRequest {
+            name: name.into(),
+            role: role.into(),
+        };
+        Ok(self.inner.user_revoke_role(request).await?.into_inner())
+    }
+
+    /// Add a role
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn role_add(
+        &mut self,
+        name: impl Into<String>,
+    ) -> Result<AuthRoleAddResponse, ClientError> {
+        let request = AuthRoleAddRequest { name: name.into() };
+        Ok(self.inner.role_add(request).await?.into_inner())
+    }
+
+    /// Get a role
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn role_get(
+        &mut self,
+        role: impl Into<String>,
+    ) -> Result<AuthRoleGetResponse, ClientError> {
+        let request = AuthRoleGetRequest { role: role.into() };
+        let response = self
+            .retry_policy
+            .retry(|| {
+                let mut inner = self.inner.clone();
+                let request = request.clone();
+                async move { inner.role_get(request).await }
+            })
+            .await?;
+        Ok(response.into_inner())
+    }
+
+    /// List all roles
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn role_list(&mut self) -> Result<AuthRoleListResponse, ClientError> {
+        let request = AuthRoleListRequest {};
+        let response = self
+            .retry_policy
+            .retry(|| {
+                let mut inner = self.inner.clone();
+                let request = request.clone();
+                async move { inner.role_list(request).await }
+            })
+            .await?;
+        Ok(response.into_inner())
+    }
+
+    /// Delete a role
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn role_delete(
+        &mut self,
+        role: impl Into<String>,
+    ) -> Result<AuthRoleDeleteResponse, ClientError> {
+        let request = AuthRoleDeleteRequest { role: role.into() };
+        Ok(self.inner.role_delete(request).await?.into_inner())
+    }
+
+    /// Grant a permission to a role
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn role_grant_permission(
+        &mut self,
+        name: impl Into<String>,
+        perm: Permission,
+    ) -> Result<AuthRoleGrantPermissionResponse, ClientError> {
+        let request = AuthRoleGrantPermissionRequest {
+            name: name.into(),
+            perm: Some(perm.into()),
+        };
+        Ok(self
+            .inner
+            .role_grant_permission(request)
+            .await?
+            .into_inner())
+    }
+
+    /// Revoke the permission on the range `[key, range_end)` from a role, `range_end` is
+    /// empty if the permission is on `key` only
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn role_revoke_permission(
+        &mut self,
+        role: impl Into<String>,
+        key: impl Into<Vec<u8>>,
+        range_end: impl Into<Vec<u8>>,
+    ) -> Result<AuthRoleRevokePermissionResponse, ClientError> {
+        let request = AuthRoleRevokePermissionRequest {
+            role: role.into(),
+            key: key.into(),
+            range_end: range_end.into(),
+        };
+        Ok(self
+            .inner
+            .role_revoke_permission(request)
+            .await?
+            .into_inner())
+    }
+
+    /// Add an API key of a user, the key is only returned in the response, the server
+    /// keeps its hash only. It is an Xline extension.
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn api_key_add(
+        &mut self,
+        user: impl Into<String>,
+        description: impl Into<String>,
+    ) -> Result<AuthApiKeyAddResponse, ClientError> {
+        let request = AuthApiKeyAddRequest {
+            user: user.into(),
+            description: description.into(),
+            ..AuthApiKeyAddRequest::default()
+        };
+        Ok(self.inner.api_key_add(request).await?.into_inner())
+    }
+
+    /// Revoke an API key by its id
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn api_key_revoke(
+        &mut self,
+        id: impl Into<String>,
+    ) -> Result<AuthApiKeyRevokeResponse, ClientError> {
+        let request = AuthApiKeyRevokeRequest { id: id.into() };
+        Ok(self.inner.api_key_revoke(request).await?.into_inner())
+    }
+
+    /// List the API keys of a user, or the keys of all users if `user` is empty
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn api_key_list(
+        &mut self,
+        user: impl Into<String>,
+    ) -> Result<AuthApiKeyListResponse, ClientError> {
+        let request = AuthApiKeyListRequest { user: user.into() };
+        let response = self
+            .retry_policy
+            .retry(|| {
+                let mut inner = self.inner.clone();
+                let request = request.clone();
+                async move { inner.api_key_list(request).await }
+            })
+            .await?;
+        Ok(response.into_inner())
+    }
+}
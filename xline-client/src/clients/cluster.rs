@@ -0,0 +1,120 @@
+use tonic::transport::Channel;
+
+use crate::{
+    client::{AuthChannel, AuthInterceptor},
+    errors::ClientError,
+    retry::RetryPolicy,
+    rpc::{
+        self, MemberAddRequest, MemberListRequest, MemberPromoteRequest, MemberRemoveRequest,
+        MemberUpdateRequest,
+    },
+    types::cluster::{
+        MemberAddResponse, MemberListResponse, MemberPromoteResponse, MemberRemoveResponse,
+        MemberUpdateResponse,
+    },
+};
+
+/// Client for Cluster operations
+#[derive(Debug, Clone)]
+pub struct ClusterClient {
+    /// Inner client
+    inner: rpc::ClusterClient<AuthChannel>,
+    /// Policy of retrying idempotent requests
+    retry_policy: RetryPolicy,
+}
+
+impl ClusterClient {
+    /// New `ClusterClient`
+    pub(crate) fn new(
+        channel: Channel,
+        interceptor: AuthInterceptor,
+        retry_policy: RetryPolicy,
+    ) -> Self {
+        Self {
+            inner: rpc::ClusterClient::with_interceptor(channel, interceptor),
+            retry_policy,
+        }
+    }
+
+    /// Add a member which communicates with the cluster by `peer_urls`, it's added as a
+    /// learner if `is_learner` is set
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn member_add(
+        &mut self,
+        peer_urls: impl Into<Vec<String>>,
+        is_learner: bool,
+    ) -> Result<MemberAddResponse, ClientError> {
+        let request = MemberAddRequest {
+            peer_ur_ls: peer_urls.into(),
+            is_learner,
+        };
+        Ok(self.inner.member_add(request).await?.into_inner())
+    }
+
+    /// Remove a member from the cluster
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn member_remove(&mut self, id: u64) -> Result<MemberRemoveResponse, ClientError> {
+        let request = MemberRemoveRequest { id };
+        Ok(self.inner.member_remove(request).await?.into_inner())
+    }
+
+    /// Replace the peer urls of a member
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn member_update(
+        &mut self,
+        id: u64,
+        peer_urls: impl Into<Vec<String>>,
+    ) -> Result<MemberUpdateResponse, ClientError> {
+        let request = MemberUpdateRequest {
+            id,
+            peer_ur_ls: peer_urls.into(),
+        };
+        Ok(self.inner.member_update(request).await?.into_inner())
+    }
+
+    /// List all members of the cluster, the list is read from the leader if `linearizable`
+    /// is set
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn member_list(
+        &mut self,
+        linearizable: bool,
+    ) -> Result<MemberListResponse, ClientError> {
+        let request = MemberListRequest { linearizable };
+        let response = self
+            .retry_policy
+            .retry(|| {
+                let mut inner = self.inner.clone();
+                let request = request.clone();
+                async move { inner.member_list(request).await }
+            })
+            .await?;
+        Ok(response.into_inner())
+    }
+
+    /// Promote a learner to a voting member
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn member_promote(&mut self, id: u64) -> Result<MemberPromoteResponse, ClientError> {
+        let request = MemberPromoteRequest { id };
+        Ok(self.inner.member_promote(request).await?.into_inner())
+    }
+}
@@ -0,0 +1,189 @@
+use tonic::transport::Channel;
+
+use crate::{
+    client::{AuthChannel, AuthInterceptor},
+    errors::ClientError,
+    retry::RetryPolicy,
+    rpc::{self, RevisionAtRequest, WaitRevisionRequest},
+    types::kv::{
+        CompactionRequest, CompactionResponse, DeleteRangeRequest, DeleteRangeResponse,
+        KeyHistoryRequest, KeyHistoryResponse, PutRequest, PutResponse, RangeRequest,
+        RangeResponse, RevisionAtResponse, TxnRequest, TxnResponse, UndeleteRequest,
+        UndeleteResponse, WaitRevisionResponse,
+    },
+};
+
+/// Client for KV operations
+#[derive(Debug, Clone)]
+pub struct KvClient {
+    /// Inner client
+    inner: rpc::KvClient<AuthChannel>,
+    /// Policy of retrying idempotent requests
+    retry_policy: RetryPolicy,
+}
+
+impl KvClient {
+    /// New `KvClient`
+    pub(crate) fn new(
+        channel: Channel,
+        interceptor: AuthInterceptor,
+        retry_policy: RetryPolicy,
+    ) -> Self {
+        Self {
+            inner: rpc::KvClient::with_interceptor(channel, interceptor),
+            retry_policy,
+        }
+    }
+
+    /// Put a key-value into the store
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn put(&mut self, request: PutRequest) -> Result<PutResponse, ClientError> {
+        let request = rpc::PutRequest::from(request);
+        Ok(self.inner.put(request).await?.into_inner())
+    }
+
+    /// Get the keys in a range from the store
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn range(&mut self, request: RangeRequest) -> Result<RangeResponse, ClientError> {
+        let request = rpc::RangeRequest::from(request);
+        let response = self
+            .retry_policy
+            .retry(|| {
+                let mut inner = self.inner.clone();
+                let request = request.clone();
+                async move { inner.range(request).await }
+            })
+            .await?;
+        Ok(response.into_inner())
+    }
+
+    /// Delete the keys in a range from the store
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn delete(
+        &mut self,
+        request: DeleteRangeRequest,
+    ) -> Result<DeleteRangeResponse, ClientError> {
+        let request = rpc::DeleteRangeRequest::from(request);
+        Ok(self.inner.delete_range(request).await?.into_inner())
+    }
+
+    /// Process the operations of a txn atomically
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn txn(&mut self, request: TxnRequest) -> Result<TxnResponse, ClientError> {
+        let request = rpc::TxnRequest::from(request);
+        Ok(self.inner.txn(request).await?.into_inner())
+    }
+
+    /// Compact the event history of the store
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn compact(
+        &mut self,
+        request: CompactionRequest,
+    ) -> Result<CompactionResponse, ClientError> {
+        let request = rpc::CompactionRequest::from(request);
+        Ok(self.inner.compact(request).await?.into_inner())
+    }
+
+    /// Wait until the member serving the request has applied `revision`, so that a
+    /// serializable range sent to it afterwards sees the writes before `revision`
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn wait_revision(
+        &mut self,
+        revision: i64,
+    ) -> Result<WaitRevisionResponse, ClientError> {
+        let request = WaitRevisionRequest { revision };
+        let response = self
+            .retry_policy
+            .retry(|| {
+                let mut inner = self.inner.clone();
+                let request = request.clone();
+                async move { inner.wait_revision(request).await }
+            })
+            .await?;
+        Ok(response.into_inner())
+    }
+
+    /// List the retained revisions of a key, including its deletions, from the member
+    /// serving the request
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn key_history(
+        &mut self,
+        request: KeyHistoryRequest,
+    ) -> Result<KeyHistoryResponse, ClientError> {
+        let request = rpc::KeyHistoryRequest::from(request);
+        let response = self
+            .retry_policy
+            .retry(|| {
+                let mut inner = self.inner.clone();
+                let request = request.clone();
+                async move { inner.key_history(request).await }
+            })
+            .await?;
+        Ok(response.into_inner())
+    }
+
+    /// Restore the deleted keys in a range from the trash, each key to the value before its
+    /// latest deletion. The keys which exist now are not restored, and a request restores a
+    /// limited number of keys, it should be sent again while the response has `more`.
+    ///
+    /// # Errors
+    ///
+    /// If the request fails, or the trash is disabled
+    #[inline]
+    pub async fn undelete(
+        &mut self,
+        request: UndeleteRequest,
+    ) -> Result<UndeleteResponse, ClientError> {
+        let request = rpc::UndeleteRequest::from(request);
+        Ok(self.inner.undelete(request).await?.into_inner())
+    }
+
+    /// Resolve the latest revision applied at or before `timestamp`, in seconds since the
+    /// unix epoch, by the member serving the request. The revision is 0 if the time is
+    /// before the first revision recorded by the member.
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn revision_at(&mut self, timestamp: i64) -> Result<RevisionAtResponse, ClientError> {
+        let request = RevisionAtRequest { timestamp };
+        let response = self
+            .retry_policy
+            .retry(|| {
+                let mut inner = self.inner.clone();
+                let request = request.clone();
+                async move { inner.revision_at(request).await }
+            })
+            .await?;
+        Ok(response.into_inner())
+    }
+}
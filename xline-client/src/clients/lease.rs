@@ -0,0 +1,378 @@
+use std::time::Duration;
+
+use tokio::{
+    sync::{mpsc, watch},
+    task::JoinHandle,
+    time::{timeout_at, Instant},
+};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{transport::Channel, Streaming};
+
+use crate::{
+    client::{AuthChannel, AuthInterceptor},
+    errors::ClientError,
+    retry::RetryPolicy,
+    rpc::{
+        self, LeaseKeepAliveRequest, LeaseLeasesRequest, LeaseModifyTtlRequest,
+        LeaseObserveRequest, LeaseRevokeRequest,
+    },
+    types::lease::{
+        LeaseGrantRequest, LeaseGrantResponse, LeaseKeepAliveResponse, LeaseLeasesResponse,
+        LeaseModifyTtlResponse, LeaseObserveResponse, LeaseRevokeResponse, LeaseTimeToLiveRequest,
+        LeaseTimeToLiveResponse,
+    },
+};
+
+/// Size of the channel of the keep alive requests
+const CHANNEL_SIZE: usize = 16;
+
+/// Client for Lease operations
+#[derive(Debug, Clone)]
+pub struct LeaseClient {
+    /// Inner client
+    inner: rpc::LeaseClient<AuthChannel>,
+    /// Policy of retrying idempotent requests
+    retry_policy: RetryPolicy,
+}
+
+impl LeaseClient {
+    /// New `LeaseClient`
+    pub(crate) fn new(
+        channel: Channel,
+        interceptor: AuthInterceptor,
+        retry_policy: RetryPolicy,
+    ) -> Self {
+        Self {
+            inner: rpc::LeaseClient::with_interceptor(channel, interceptor),
+            retry_policy,
+        }
+    }
+
+    /// Grant a lease, the keys attached to it are deleted when it expires
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn grant(
+        &mut self,
+        request: LeaseGrantRequest,
+    ) -> Result<LeaseGrantResponse, ClientError> {
+        let request = rpc::LeaseGrantRequest::from(request);
+        Ok(self.inner.lease_grant(request).await?.into_inner())
+    }
+
+    /// Revoke a lease and delete the keys attached to it
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn revoke(&mut self, id: i64) -> Result<LeaseRevokeResponse, ClientError> {
+        let request = LeaseRevokeRequest { id };
+        Ok(self.inner.lease_revoke(request).await?.into_inner())
+    }
+
+    /// Change the time-to-live of a lease in seconds and renew it, the keys stay attached
+    /// to it
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn modify_ttl(
+        &mut self,
+        id: i64,
+        ttl: i64,
+    ) -> Result<LeaseModifyTtlResponse, ClientError> {
+        let request = LeaseModifyTtlRequest { id, ttl };
+        Ok(self.inner.lease_modify_ttl(request).await?.into_inner())
+    }
+
+    /// Open a keep alive stream of a lease and refresh the lease. It's refreshed again each
+    /// time `LeaseKeepAliveSender::keep_alive` is called, and the new time-to-live is
+    /// received from the `LeaseKeepAliveStream`
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn keep_alive(
+        &mut self,
+        id: i64,
+    ) -> Result<(LeaseKeepAliveSender, LeaseKeepAliveStream), ClientError> {
+        let (sender, receiver) = mpsc::channel(CHANNEL_SIZE);
+        // the stream is opened with the first refresh
+        sender
+            .send(LeaseKeepAliveRequest { id })
+            .await
+            .map_err(|e| ClientError::StreamError(e.to_string()))?;
+        let stream = self
+            .inner
+            .lease_keep_alive(ReceiverStream::new(receiver))
+            .await?
+            .into_inner();
+        Ok((
+            LeaseKeepAliveSender { id, sender },
+            LeaseKeepAliveStream { inner: stream },
+        ))
+    }
+
+    /// Get the time-to-live of a lease
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn time_to_live(
+        &mut self,
+        request: LeaseTimeToLiveRequest,
+    ) -> Result<LeaseTimeToLiveResponse, ClientError> {
+        let request = rpc::LeaseTimeToLiveRequest::from(request);
+        let response = self
+            .retry_policy
+            .retry(|| {
+                let mut inner = self.inner.clone();
+                let request = request.clone();
+                async move { inner.lease_time_to_live(request).await }
+            })
+            .await?;
+        Ok(response.into_inner())
+    }
+
+    /// List all leases
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn leases(&mut self) -> Result<LeaseLeasesResponse, ClientError> {
+        let request = LeaseLeasesRequest {};
+        let response = self
+            .retry_policy
+            .retry(|| {
+                let mut inner = self.inner.clone();
+                let request = request.clone();
+                async move { inner.lease_leases(request).await }
+            })
+            .await?;
+        Ok(response.into_inner())
+    }
+
+    /// Observe the events of lease `id`, or of all leases if `id` is 0: granted, renewed,
+    /// revoked and expired. The stream ends with an error when the leader changes, the
+    /// leases should be listed and observed again then.
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn observe(&mut self, id: i64) -> Result<LeaseObserveStream, ClientError> {
+        let request = LeaseObserveRequest { id };
+        let stream = self.inner.lease_observe(request).await?.into_inner();
+        Ok(LeaseObserveStream { inner: stream })
+    }
+}
+
+/// Stream of the lease events
+#[derive(Debug)]
+pub struct LeaseObserveStream {
+    /// Inner stream
+    inner: Streaming<LeaseObserveResponse>,
+}
+
+impl LeaseObserveStream {
+    /// Get the next event, `None` if the stream is closed
+    ///
+    /// # Errors
+    ///
+    /// If the server returns an error
+    #[inline]
+    pub async fn message(&mut self) -> Result<Option<LeaseObserveResponse>, ClientError> {
+        Ok(self.inner.message().await?)
+    }
+}
+
+/// Sender of the keep alive requests of a lease
+#[derive(Debug, Clone)]
+pub struct LeaseKeepAliveSender {
+    /// Id of the lease
+    id: i64,
+    /// Sender of the requests to the stream
+    sender: mpsc::Sender<LeaseKeepAliveRequest>,
+}
+
+impl LeaseKeepAliveSender {
+    /// Get the id of the lease
+    #[inline]
+    #[must_use]
+    pub fn id(&self) -> i64 {
+        self.id
+    }
+
+    /// Refresh the lease
+    ///
+    /// # Errors
+    ///
+    /// If the stream is closed
+    #[inline]
+    pub async fn keep_alive(&self) -> Result<(), ClientError> {
+        self.sender
+            .send(LeaseKeepAliveRequest { id: self.id })
+            .await
+            .map_err(|e| ClientError::StreamError(e.to_string()))
+    }
+}
+
+/// Stream of the keep alive responses of a lease
+#[derive(Debug)]
+pub struct LeaseKeepAliveStream {
+    /// Inner stream
+    inner: Streaming<LeaseKeepAliveResponse>,
+}
+
+impl LeaseKeepAliveStream {
+    /// Get the next response, `None` if the stream is closed
+    ///
+    /// # Errors
+    ///
+    /// If the server returns an error
+    #[inline]
+    pub async fn message(&mut self) -> Result<Option<LeaseKeepAliveResponse>, ClientError> {
+        Ok(self.inner.message().await?)
+    }
+}
+
+/// Interval of reopening the keep alive stream after it's broken
+const REOPEN_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Keeper of a lease, it refreshes the lease in the background at a third of its
+/// time-to-live until it's dropped, and notifies the expiry of the lease. The lease can
+/// be attached to keys and locks by its id, eg. `LockRequest::with_lease(keeper.id())`,
+/// so that they are kept as long as the keeper lives.
+#[derive(Debug)]
+pub struct LeaseKeeper {
+    /// Id of the lease
+    id: i64,
+    /// Receiver of the expiry of the lease
+    expired: watch::Receiver<bool>,
+    /// Handle of the background task refreshing the lease
+    handle: JoinHandle<()>,
+}
+
+impl LeaseKeeper {
+    /// Grant a lease with the time-to-live in seconds and keep it alive
+    ///
+    /// # Errors
+    ///
+    /// If the lease is not granted
+    #[inline]
+    pub async fn grant(mut client: LeaseClient, ttl: i64) -> Result<Self, ClientError> {
+        let resp = client.grant(LeaseGrantRequest::new(ttl)).await?;
+        if !resp.error.is_empty() {
+            return Err(ClientError::InvalidArgs(resp.error));
+        }
+        Ok(Self::keep(client, resp.id, resp.ttl))
+    }
+
+    /// Keep a granted lease alive, `ttl` is its time-to-live in seconds
+    #[inline]
+    #[must_use]
+    pub fn keep(client: LeaseClient, id: i64, ttl: i64) -> Self {
+        let (sender, expired) = watch::channel(false);
+        let handle = tokio::spawn(async move {
+            Self::keep_alive(client, id, ttl).await;
+            let _ignore = sender.send(true);
+        });
+        Self {
+            id,
+            expired,
+            handle,
+        }
+    }
+
+    /// Get the id of the lease
+    #[inline]
+    #[must_use]
+    pub fn id(&self) -> i64 {
+        self.id
+    }
+
+    /// Check if the lease has expired
+    #[inline]
+    #[must_use]
+    pub fn is_expired(&self) -> bool {
+        *self.expired.borrow()
+    }
+
+    /// Wait until the lease expires
+    #[inline]
+    pub async fn expired(&mut self) {
+        while !*self.expired.borrow_and_update() {
+            if self.expired.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Stop refreshing the lease and revoke it
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn revoke(self, mut client: LeaseClient) -> Result<LeaseRevokeResponse, ClientError> {
+        self.handle.abort();
+        client.revoke(self.id).await
+    }
+
+    /// Refresh the lease until it expires, the keep alive stream is reopened if it's
+    /// broken before the lease expires
+    async fn keep_alive(mut client: LeaseClient, id: i64, ttl: i64) {
+        if ttl <= 0 {
+            return;
+        }
+        let mut deadline = Instant::now() + Duration::from_secs(ttl.unsigned_abs());
+        while !Self::keep_on_stream(&mut client, id, &mut deadline).await {
+            if Instant::now() + REOPEN_INTERVAL >= deadline {
+                return;
+            }
+            tokio::time::sleep(REOPEN_INTERVAL).await;
+        }
+    }
+
+    /// Refresh the lease on a keep alive stream, `deadline` is the time when the lease
+    /// expires if it's not refreshed. Returns `true` if the lease has expired, or `false`
+    /// if the stream is broken.
+    async fn keep_on_stream(client: &mut LeaseClient, id: i64, deadline: &mut Instant) -> bool {
+        let (sender, mut stream) = match timeout_at(*deadline, client.keep_alive(id)).await {
+            Ok(Ok(keep_alive)) => keep_alive,
+            Ok(Err(_)) => return false,
+            Err(_) => return true,
+        };
+        loop {
+            let resp = match timeout_at(*deadline, stream.message()).await {
+                Ok(Ok(Some(resp))) => resp,
+                Ok(Ok(None) | Err(_)) => return false,
+                Err(_) => return true,
+            };
+            // the lease is not found
+            if resp.ttl <= 0 {
+                return true;
+            }
+            let ttl = Duration::from_secs(resp.ttl.unsigned_abs());
+            *deadline = Instant::now() + ttl;
+            tokio::time::sleep(ttl / 3).await;
+            if sender.keep_alive().await.is_err() {
+                return false;
+            }
+        }
+    }
+}
+
+impl Drop for LeaseKeeper {
+    #[inline]
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
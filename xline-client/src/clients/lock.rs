@@ -0,0 +1,47 @@
+use tonic::transport::Channel;
+
+use crate::{
+    client::{AuthChannel, AuthInterceptor},
+    errors::ClientError,
+    rpc::{self, UnlockRequest},
+    types::lock::{LockRequest, LockResponse, UnlockResponse},
+};
+
+/// Client for Lock operations
+#[derive(Debug, Clone)]
+pub struct LockClient {
+    /// Inner client
+    inner: rpc::LockClient<AuthChannel>,
+}
+
+impl LockClient {
+    /// New `LockClient`
+    pub(crate) fn new(channel: Channel, interceptor: AuthInterceptor) -> Self {
+        Self {
+            inner: rpc::LockClient::with_interceptor(channel, interceptor),
+        }
+    }
+
+    /// Acquire a lock, it blocks until the lock is acquired. The key in the response
+    /// owns the lock until it's unlocked or the lease of the request expires.
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn lock(&mut self, request: LockRequest) -> Result<LockResponse, ClientError> {
+        let request = rpc::LockRequest::from(request);
+        Ok(self.inner.lock(request).await?.into_inner())
+    }
+
+    /// Release the lock owned by `key`
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn unlock(&mut self, key: impl Into<Vec<u8>>) -> Result<UnlockResponse, ClientError> {
+        let request = UnlockRequest { key: key.into() };
+        Ok(self.inner.unlock(request).await?.into_inner())
+    }
+}
@@ -0,0 +1,197 @@
+use tonic::{transport::Channel, Streaming};
+
+use crate::{
+    client::{AuthChannel, AuthInterceptor},
+    errors::ClientError,
+    retry::RetryPolicy,
+    rpc::{
+        self, AlarmRequest, DefragmentRequest, HashKvRequest, HashRequest, MoveLeaderRequest,
+        SnapshotRequest, StatusRequest, TableStatsRequest,
+    },
+    types::maintenance::{
+        AlarmAction, AlarmResponse, AlarmType, DefragmentResponse, HashKvResponse, HashResponse,
+        MoveLeaderResponse, SnapshotResponse, StatusResponse, TableStatsResponse,
+    },
+};
+
+/// Client for Maintenance operations
+#[derive(Debug, Clone)]
+pub struct MaintenanceClient {
+    /// Inner client
+    inner: rpc::MaintenanceClient<AuthChannel>,
+    /// Policy of retrying idempotent requests
+    retry_policy: RetryPolicy,
+}
+
+impl MaintenanceClient {
+    /// New `MaintenanceClient`
+    pub(crate) fn new(
+        channel: Channel,
+        interceptor: AuthInterceptor,
+        retry_policy: RetryPolicy,
+    ) -> Self {
+        Self {
+            inner: rpc::MaintenanceClient::with_interceptor(channel, interceptor),
+            retry_policy,
+        }
+    }
+
+    /// Get, activate or deactivate the alarms of a member, `member_id` is 0 for all members
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    #[allow(clippy::as_conversions)] // this case is always safe
+    pub async fn alarm(
+        &mut self,
+        action: AlarmAction,
+        alarm: AlarmType,
+        member_id: u64,
+    ) -> Result<AlarmResponse, ClientError> {
+        let request = AlarmRequest {
+            action: action as i32,
+            member_id,
+            alarm: alarm as i32,
+        };
+        Ok(self.inner.alarm(request).await?.into_inner())
+    }
+
+    /// Get the status of the member serving the request
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn status(&mut self) -> Result<StatusResponse, ClientError> {
+        let request = StatusRequest {};
+        let response = self
+            .retry_policy
+            .retry(|| {
+                let mut inner = self.inner.clone();
+                let request = request.clone();
+                async move { inner.status(request).await }
+            })
+            .await?;
+        Ok(response.into_inner())
+    }
+
+    /// Defragment the backend of the member serving the request
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn defragment(&mut self) -> Result<DefragmentResponse, ClientError> {
+        let request = DefragmentRequest {};
+        let response = self
+            .retry_policy
+            .retry(|| {
+                let mut inner = self.inner.clone();
+                let request = request.clone();
+                async move { inner.defragment(request).await }
+            })
+            .await?;
+        Ok(response.into_inner())
+    }
+
+    /// Get the hash of the backend of the member serving the request
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn hash(&mut self) -> Result<HashResponse, ClientError> {
+        let request = HashRequest {};
+        let response = self
+            .retry_policy
+            .retry(|| {
+                let mut inner = self.inner.clone();
+                let request = request.clone();
+                async move { inner.hash(request).await }
+            })
+            .await?;
+        Ok(response.into_inner())
+    }
+
+    /// Get the hash of the keys up to `revision`, 0 for the latest revision
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn hash_kv(&mut self, revision: i64) -> Result<HashKvResponse, ClientError> {
+        let request = HashKvRequest { revision };
+        let response = self
+            .retry_policy
+            .retry(|| {
+                let mut inner = self.inner.clone();
+                let request = request.clone();
+                async move { inner.hash_kv(request).await }
+            })
+            .await?;
+        Ok(response.into_inner())
+    }
+
+    /// Get a snapshot of the backend, it's sent in chunks by the returned stream
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn snapshot(&mut self) -> Result<SnapshotStream, ClientError> {
+        let request = SnapshotRequest {};
+        let stream = self.inner.snapshot(request).await?.into_inner();
+        Ok(SnapshotStream { inner: stream })
+    }
+
+    /// Transfer the leadership to another member
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn move_leader(&mut self, target_id: u64) -> Result<MoveLeaderResponse, ClientError> {
+        let request = MoveLeaderRequest { target_id };
+        Ok(self.inner.move_leader(request).await?.into_inner())
+    }
+
+    /// Get the statistics of the kv, lease and auth tables of the member serving the
+    /// request, it requires the root role if auth is enabled
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn table_stats(&mut self) -> Result<TableStatsResponse, ClientError> {
+        let request = TableStatsRequest {};
+        let response = self
+            .retry_policy
+            .retry(|| {
+                let mut inner = self.inner.clone();
+                let request = request.clone();
+                async move { inner.table_stats(request).await }
+            })
+            .await?;
+        Ok(response.into_inner())
+    }
+}
+
+/// Stream of the chunks of a snapshot
+#[derive(Debug)]
+pub struct SnapshotStream {
+    /// Inner stream
+    inner: Streaming<SnapshotResponse>,
+}
+
+impl SnapshotStream {
+    /// Get the next chunk, `None` if the whole snapshot is received
+    ///
+    /// # Errors
+    ///
+    /// If the server returns an error
+    #[inline]
+    pub async fn message(&mut self) -> Result<Option<SnapshotResponse>, ClientError> {
+        Ok(self.inner.message().await?)
+    }
+}
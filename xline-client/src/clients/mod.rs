@@ -0,0 +1,14 @@
+/// Auth client
+pub mod auth;
+/// Cluster client
+pub mod cluster;
+/// Kv client
+pub mod kv;
+/// Lease client
+pub mod lease;
+/// Lock client
+pub mod lock;
+/// Maintenance client
+pub mod maintenance;
+/// Watch client
+pub mod watch;
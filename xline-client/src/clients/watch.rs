@@ -0,0 +1,473 @@
+use std::collections::{HashMap, VecDeque};
+
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{transport::Channel, Streaming};
+
+use crate::{
+    client::{AuthChannel, AuthInterceptor},
+    errors::ClientError,
+    retry::RetryPolicy,
+    rpc::{self, RequestUnion, WatchCancelRequest, WatchCreateRequest, WatchProgressRequest},
+    types::watch::{WatchRequest, WatchResponse},
+};
+
+/// Size of the channel of the requests sent to the watch stream
+const CHANNEL_SIZE: usize = 128;
+
+/// Client for Watch operations
+#[derive(Debug, Clone)]
+pub struct WatchClient {
+    /// Inner client
+    inner: rpc::WatchClient<AuthChannel>,
+    /// Policy of reopening the watch stream after it's broken
+    retry_policy: RetryPolicy,
+}
+
+impl WatchClient {
+    /// New `WatchClient`
+    pub(crate) fn new(
+        channel: Channel,
+        interceptor: AuthInterceptor,
+        retry_policy: RetryPolicy,
+    ) -> Self {
+        Self {
+            inner: rpc::WatchClient::with_interceptor(channel, interceptor),
+            retry_policy,
+        }
+    }
+
+    /// Create a watcher on a watch stream. More watchers can be created on the stream by
+    /// the returned `Watcher`, and the events of all of them are received from the
+    /// returned `WatchStream`. If the stream is disconnected, the watchers are created
+    /// again on a new stream from the revision after the last received events.
+    ///
+    /// # Errors
+    ///
+    /// If the request fails or the watcher is not created
+    #[inline]
+    pub async fn watch(
+        &mut self,
+        request: WatchRequest,
+    ) -> Result<(Watcher, WatchStream), ClientError> {
+        let (upstream, receiver) = mpsc::channel(CHANNEL_SIZE);
+        let request = rpc::WatchRequest::from(request);
+        let mut tracker = WatcherTracker::default();
+        tracker.track_request(&request);
+        upstream
+            .send(request)
+            .await
+            .map_err(|e| ClientError::StreamError(e.to_string()))?;
+        let mut stream = self
+            .inner
+            .watch(ReceiverStream::new(receiver))
+            .await?
+            .into_inner();
+        let watch_id = match stream.message().await? {
+            Some(resp) if resp.created && !resp.canceled => {
+                let watch_id = resp.watch_id;
+                let _resp = tracker.track_response(resp);
+                watch_id
+            }
+            Some(resp) => {
+                return Err(ClientError::StreamError(format!(
+                    "failed to create watcher, {}",
+                    resp.cancel_reason
+                )))
+            }
+            None => {
+                return Err(ClientError::StreamError(
+                    "watch stream closed before the watcher is created".to_owned(),
+                ))
+            }
+        };
+        let (sender, requests) = mpsc::channel(CHANNEL_SIZE);
+        Ok((
+            Watcher { watch_id, sender },
+            WatchStream {
+                inner: stream,
+                upstream,
+                requests: Some(requests),
+                client: self.inner.clone(),
+                retry_policy: self.retry_policy,
+                tracker,
+            },
+        ))
+    }
+}
+
+/// Handle of a watch stream, it creates and cancels the watchers on the stream
+#[derive(Debug, Clone)]
+pub struct Watcher {
+    /// Id of the watcher created with the stream
+    watch_id: i64,
+    /// Sender of the requests to the stream
+    sender: mpsc::Sender<rpc::WatchRequest>,
+}
+
+impl Watcher {
+    /// Get the id of the watcher created with the stream
+    #[inline]
+    #[must_use]
+    pub fn watch_id(&self) -> i64 {
+        self.watch_id
+    }
+
+    /// Send a request to the watch stream
+    async fn send(&self, request_union: RequestUnion) -> Result<(), ClientError> {
+        self.sender
+            .send(rpc::WatchRequest {
+                request_union: Some(request_union),
+            })
+            .await
+            .map_err(|e| ClientError::StreamError(e.to_string()))
+    }
+
+    /// Create another watcher on the stream, its id is in the created response received
+    /// from the `WatchStream`
+    ///
+    /// # Errors
+    ///
+    /// If the stream is closed
+    #[inline]
+    pub async fn watch(&self, request: WatchRequest) -> Result<(), ClientError> {
+        self.send(RequestUnion::CreateRequest(request.into())).await
+    }
+
+    /// Cancel the watcher created with the stream
+    ///
+    /// # Errors
+    ///
+    /// If the stream is closed
+    #[inline]
+    pub async fn cancel(&self) -> Result<(), ClientError> {
+        self.cancel_by_id(self.watch_id).await
+    }
+
+    /// Cancel a watcher on the stream
+    ///
+    /// # Errors
+    ///
+    /// If the stream is closed
+    #[inline]
+    pub async fn cancel_by_id(&self, watch_id: i64) -> Result<(), ClientError> {
+        self.send(RequestUnion::CancelRequest(WatchCancelRequest { watch_id }))
+            .await
+    }
+
+    /// Request the progress of the watchers, the server sends a response with the current
+    /// revision if all watchers are synced
+    ///
+    /// # Errors
+    ///
+    /// If the stream is closed
+    #[inline]
+    pub async fn request_progress(&self) -> Result<(), ClientError> {
+        self.send(RequestUnion::ProgressRequest(WatchProgressRequest {}))
+            .await
+    }
+}
+
+/// Create request of a watcher waiting for the created response
+#[derive(Debug)]
+struct PendingWatcher {
+    /// The create request
+    request: WatchCreateRequest,
+    /// Whether the watcher is created again after the stream is reopened, its created
+    /// response is not passed to the user
+    resumed: bool,
+}
+
+/// Stream of the responses of the watchers. The requests sent by the `Watcher` are
+/// forwarded to the server while the responses are polled, and the stream is reopened
+/// if it's broken by a transient error.
+#[derive(Debug)]
+pub struct WatchStream {
+    /// Inner stream
+    inner: Streaming<WatchResponse>,
+    /// Sender of the requests to the inner stream
+    upstream: mpsc::Sender<rpc::WatchRequest>,
+    /// Receiver of the requests sent by the `Watcher`, `None` if all of them are dropped
+    requests: Option<mpsc::Receiver<rpc::WatchRequest>>,
+    /// Client to reopen the stream
+    client: rpc::WatchClient<AuthChannel>,
+    /// Policy of reopening the stream
+    retry_policy: RetryPolicy,
+    /// Tracker of the watchers on the stream
+    tracker: WatcherTracker,
+}
+
+impl WatchStream {
+    /// Get the next response, `None` if the stream is closed
+    ///
+    /// # Errors
+    ///
+    /// If the server returns an error, or the stream can't be reopened
+    #[inline]
+    pub async fn message(&mut self) -> Result<Option<WatchResponse>, ClientError> {
+        loop {
+            let result = match self.requests {
+                Some(ref mut requests) => {
+                    tokio::select! {
+                        request = requests.recv() => {
+                            match request {
+                                Some(request) => {
+                                    self.tracker.track_request(&request);
+                                    // if the stream is broken, the watchers are created
+                                    // when it's reopened
+                                    let _ignore = self.upstream.send(request).await;
+                                }
+                                None => self.requests = None,
+                            }
+                            continue;
+                        }
+                        result = self.inner.message() => result,
+                    }
+                }
+                None => self.inner.message().await,
+            };
+            match result {
+                Ok(Some(resp)) => {
+                    if let Some(resp) = self.tracker.track_response(resp) {
+                        return Ok(Some(resp));
+                    }
+                }
+                Ok(None) => return Ok(None),
+                Err(ref status) if RetryPolicy::is_transient(status) => self.resume().await?,
+                Err(status) => return Err(status.into()),
+            }
+        }
+    }
+
+    /// Reopen the stream, and create the watchers again from the revisions after their
+    /// last received events
+    async fn resume(&mut self) -> Result<(), ClientError> {
+        let creates = self.tracker.resume();
+        let (upstream, stream) = self
+            .retry_policy
+            .retry(|| {
+                let (upstream, receiver) = mpsc::channel(CHANNEL_SIZE.max(creates.len()));
+                for create in &creates {
+                    // the channel is large enough to hold all create requests
+                    let _ignore = upstream.try_send(create.clone());
+                }
+                let mut client = self.client.clone();
+                async move {
+                    let stream = client.watch(ReceiverStream::new(receiver)).await?;
+                    Ok((upstream, stream.into_inner()))
+                }
+            })
+            .await?;
+        self.upstream = upstream;
+        self.inner = stream;
+        Ok(())
+    }
+}
+
+/// Tracker of the watchers on a watch stream, it remembers the revision after the last
+/// received events of each watcher, so that the watchers can be created again from there
+/// after the stream is reopened
+#[derive(Debug, Default)]
+struct WatcherTracker {
+    /// Watchers waiting for the created responses, in the order of their create requests
+    pending: VecDeque<PendingWatcher>,
+    /// Create requests of the created watchers, their `start_revision` are the revisions
+    /// after the last received events
+    watchers: HashMap<i64, WatchCreateRequest>,
+}
+
+impl WatcherTracker {
+    /// Get the revision to resume a watcher from after receiving a response of it
+    fn next_revision(request: &WatchCreateRequest, resp: &WatchResponse) -> i64 {
+        let last_event = resp
+            .events
+            .iter()
+            .filter_map(|event| event.kv.as_ref())
+            .map(|kv| kv.mod_revision)
+            .max();
+        match last_event {
+            Some(revision) => revision.wrapping_add(1),
+            // a watcher created without `start_revision` watches the events after the
+            // current revision
+            None if request.start_revision == 0 => resp
+                .header
+                .as_ref()
+                .map_or(0, |header| header.revision.wrapping_add(1)),
+            None => request.start_revision,
+        }
+    }
+
+    /// Track a request sent to the stream
+    fn track_request(&mut self, request: &rpc::WatchRequest) {
+        match request.request_union {
+            Some(RequestUnion::CreateRequest(ref create)) => {
+                self.pending.push_back(PendingWatcher {
+                    request: create.clone(),
+                    resumed: false,
+                });
+            }
+            Some(RequestUnion::CancelRequest(ref cancel)) => {
+                let _prev = self.watchers.remove(&cancel.watch_id);
+            }
+            Some(RequestUnion::ProgressRequest(_)) | None => {}
+        }
+    }
+
+    /// Track a response received from the stream, and drop the events received before
+    /// the stream is reopened. Returns `None` if nothing in the response should be passed
+    /// to the user.
+    fn track_response(&mut self, mut resp: WatchResponse) -> Option<WatchResponse> {
+        if resp.created {
+            let pending = self.pending.pop_front();
+            if resp.canceled {
+                return Some(resp);
+            }
+            if let Some(mut pending) = pending {
+                pending.request.start_revision = Self::next_revision(&pending.request, &resp);
+                // a durable watcher is resumed by its token, so it's not registered again
+                if !resp.resume_token.is_empty() {
+                    pending.request.resume_token = resp.resume_token.clone();
+                }
+                let _prev = self.watchers.insert(resp.watch_id, pending.request);
+                if pending.resumed {
+                    return None;
+                }
+            }
+            return Some(resp);
+        }
+        if resp.canceled {
+            let _prev = self.watchers.remove(&resp.watch_id);
+            return Some(resp);
+        }
+        if let Some(request) = self.watchers.get_mut(&resp.watch_id) {
+            let received = resp.events.len();
+            let start_revision = request.start_revision;
+            resp.events.retain(|event| {
+                event
+                    .kv
+                    .as_ref()
+                    .map_or(true, |kv| kv.mod_revision >= start_revision)
+            });
+            if received > 0 && resp.events.is_empty() {
+                return None;
+            }
+            request.start_revision = Self::next_revision(request, &resp);
+        }
+        Some(resp)
+    }
+
+    /// Get the create requests to send after the stream is reopened, the created watchers
+    /// keep their ids and start from the revisions after their last received events
+    fn resume(&mut self) -> Vec<rpc::WatchRequest> {
+        let resumed = self.watchers.drain().map(|(watch_id, mut request)| {
+            request.watch_id = watch_id;
+            PendingWatcher {
+                request,
+                resumed: true,
+            }
+        });
+        self.pending = resumed.chain(self.pending.drain(..)).collect();
+        self.pending
+            .iter()
+            .map(|pending| rpc::WatchRequest {
+                request_union: Some(RequestUnion::CreateRequest(pending.request.clone())),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rpc::{Event, KeyValue, ResponseHeader};
+
+    fn events_response(watch_id: i64, revisions: &[i64]) -> WatchResponse {
+        WatchResponse {
+            watch_id,
+            events: revisions
+                .iter()
+                .map(|&mod_revision| Event {
+                    kv: Some(KeyValue {
+                        mod_revision,
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_next_revision() {
+        let request = WatchCreateRequest::default();
+        let resp = WatchResponse {
+            header: Some(ResponseHeader {
+                revision: 5,
+                ..Default::default()
+            }),
+            created: true,
+            ..Default::default()
+        };
+        assert_eq!(WatcherTracker::next_revision(&request, &resp), 6);
+        assert_eq!(
+            WatcherTracker::next_revision(&request, &events_response(1, &[7, 8])),
+            9
+        );
+        let request = WatchCreateRequest {
+            start_revision: 3,
+            ..Default::default()
+        };
+        assert_eq!(WatcherTracker::next_revision(&request, &resp), 3);
+    }
+
+    #[test]
+    fn test_resume_from_last_revision() {
+        let mut tracker = WatcherTracker::default();
+        tracker.track_request(&WatchRequest::new("foo").into());
+        let created = WatchResponse {
+            header: Some(ResponseHeader {
+                revision: 5,
+                ..Default::default()
+            }),
+            watch_id: 1,
+            created: true,
+            ..Default::default()
+        };
+        assert!(tracker.track_response(created.clone()).is_some());
+        assert!(tracker
+            .track_response(events_response(1, &[6, 7]))
+            .is_some());
+
+        let creates = tracker.resume();
+        assert!(matches!(
+            creates[0].request_union,
+            Some(RequestUnion::CreateRequest(ref create))
+                if create.watch_id == 1 && create.start_revision == 8
+        ));
+        // the created response of a resumed watcher is not passed to the user
+        assert!(tracker.track_response(created).is_none());
+        // replayed events are dropped
+        assert!(tracker.track_response(events_response(1, &[7])).is_none());
+        let resp = tracker.track_response(events_response(1, &[7, 8])).unwrap();
+        assert_eq!(resp.events.len(), 1);
+    }
+
+    #[test]
+    fn test_durable_watchers_are_resumed_by_the_tokens() {
+        let mut tracker = WatcherTracker::default();
+        tracker.track_request(&WatchRequest::new("foo").with_durable(true).into());
+        let created = WatchResponse {
+            watch_id: 1,
+            created: true,
+            resume_token: "token".to_owned(),
+            ..Default::default()
+        };
+        assert!(tracker.track_response(created).is_some());
+        let creates = tracker.resume();
+        assert!(matches!(
+            creates[0].request_union,
+            Some(RequestUnion::CreateRequest(ref create)) if create.resume_token == "token"
+        ));
+    }
+}
@@ -0,0 +1,19 @@
+use thiserror::Error;
+
+/// Client Error
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum ClientError {
+    /// Invalid arguments
+    #[error("invalid arguments {0}")]
+    InvalidArgs(String),
+    /// Failed to connect to the server
+    #[error("transport error {0}")]
+    TransportError(#[from] tonic::transport::Error),
+    /// Error status returned by the server
+    #[error("rpc error {0}")]
+    RpcError(#[from] tonic::Status),
+    /// Failed to send a request to a stream, the stream is closed
+    #[error("stream error {0}")]
+    StreamError(String),
+}
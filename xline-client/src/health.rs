@@ -0,0 +1,108 @@
+use std::time::Duration;
+
+use tokio::sync::mpsc::Sender;
+use tonic::transport::{Channel, Endpoint};
+use tower::discover::Change;
+
+use crate::rpc::{HealthCheckRequest, HealthClient, ServingStatus};
+
+/// Build a channel which balances requests across the healthy endpoints. The endpoints
+/// are checked every `interval`, an unhealthy one is removed from the channel and is
+/// added back once it recovers.
+pub(crate) fn balance_healthy(endpoints: Vec<Endpoint>, interval: Duration) -> Channel {
+    let (channel, sender) = Channel::balance_channel(endpoints.len());
+    for (id, endpoint) in endpoints.iter().enumerate() {
+        // the capacity of the channel is the number of endpoints, so it never fails
+        let _ignore = sender.try_send(Change::Insert(id, endpoint.clone()));
+    }
+    let _handle = tokio::spawn(HealthChecker::new(endpoints, sender, interval).run());
+    channel
+}
+
+/// An endpoint whose health is checked
+struct Probe {
+    /// The endpoint
+    endpoint: Endpoint,
+    /// Client to check the health of the endpoint
+    client: HealthClient<Channel>,
+    /// Whether the endpoint is used by the balanced channel
+    in_use: bool,
+}
+
+/// Health checker of the endpoints
+struct HealthChecker {
+    /// Probes of the endpoints, the index of a probe is the id of its endpoint
+    probes: Vec<Probe>,
+    /// Sender to change the endpoints of the balanced channel
+    sender: Sender<Change<usize, Endpoint>>,
+    /// Interval between two checks
+    interval: Duration,
+}
+
+impl HealthChecker {
+    /// New `HealthChecker`
+    fn new(
+        endpoints: Vec<Endpoint>,
+        sender: Sender<Change<usize, Endpoint>>,
+        interval: Duration,
+    ) -> Self {
+        let probes = endpoints
+            .into_iter()
+            .map(|endpoint| Probe {
+                client: HealthClient::new(endpoint.connect_lazy()),
+                endpoint,
+                in_use: true,
+            })
+            .collect();
+        Self {
+            probes,
+            sender,
+            interval,
+        }
+    }
+
+    /// Check if an endpoint is serving
+    #[allow(clippy::as_conversions)] // this case is always safe
+    async fn is_serving(client: &mut HealthClient<Channel>, timeout: Duration) -> bool {
+        let request = HealthCheckRequest {
+            service: String::new(),
+        };
+        match tokio::time::timeout(timeout, client.check(request)).await {
+            Ok(Ok(response)) => response.into_inner().status == ServingStatus::Serving as i32,
+            Ok(Err(_)) | Err(_) => false,
+        }
+    }
+
+    /// Check the endpoints periodically until the balanced channel is dropped
+    async fn run(mut self) {
+        let mut ticker = tokio::time::interval(self.interval);
+        loop {
+            let _instant = ticker.tick().await;
+            if self.sender.is_closed() {
+                return;
+            }
+            let mut serving = Vec::with_capacity(self.probes.len());
+            for probe in &mut self.probes {
+                serving.push(Self::is_serving(&mut probe.client, self.interval).await);
+            }
+            // keep all endpoints if none is healthy, so that requests fail instead of
+            // waiting for an endpoint forever
+            let none_serving = !serving.contains(&true);
+            for (id, (probe, is_serving)) in self.probes.iter_mut().zip(serving).enumerate() {
+                let use_it = is_serving || none_serving;
+                if use_it == probe.in_use {
+                    continue;
+                }
+                let change = if use_it {
+                    Change::Insert(id, probe.endpoint.clone())
+                } else {
+                    Change::Remove(id)
+                };
+                if self.sender.send(change).await.is_err() {
+                    return;
+                }
+                probe.in_use = use_it;
+            }
+        }
+    }
+}
@@ -0,0 +1,399 @@
+use crate::{
+    clients::{
+        kv::KvClient,
+        watch::{WatchClient, WatchStream, Watcher},
+    },
+    errors::ClientError,
+    rpc::{self, Request, Response},
+    types::{
+        kv::{
+            DeleteRangeRequest, DeleteRangeResponse, KeyValue, PutRequest, PutResponse,
+            RangeRequest, RangeResponse, TxnRequest, TxnResponse,
+        },
+        prefix_range,
+        watch::{WatchRequest, WatchResponse},
+    },
+};
+
+/// Namespace of the keys, the keys are prefixed with it before they are sent to the
+/// server, and the prefix is removed from the keys in the responses
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Namespace {
+    /// Prefix of the keys
+    prefix: Vec<u8>,
+}
+
+impl Namespace {
+    /// New `Namespace`
+    fn new(prefix: Vec<u8>) -> Self {
+        Self { prefix }
+    }
+
+    /// Prefix a key
+    fn prefix_key(&self, key: &mut Vec<u8>) {
+        let mut prefixed = Vec::with_capacity(self.prefix.len().wrapping_add(key.len()));
+        prefixed.extend_from_slice(&self.prefix);
+        prefixed.append(key);
+        *key = prefixed;
+    }
+
+    /// Prefix a range, the range to the end of the key space is limited to the end of
+    /// the namespace
+    fn prefix_range(&self, key: &mut Vec<u8>, range_end: &mut Vec<u8>) {
+        self.prefix_key(key);
+        if range_end.as_slice() == [0] {
+            *range_end = prefix_range(&self.prefix).1;
+        } else if !range_end.is_empty() {
+            self.prefix_key(range_end);
+        } else {
+            // a single key
+        }
+    }
+
+    /// Remove the prefix from the key of a key-value
+    fn strip_kv(&self, kv: &mut KeyValue) {
+        if kv.key.starts_with(&self.prefix) {
+            kv.key = kv.key.split_off(self.prefix.len());
+        }
+    }
+
+    /// Prefix the keys of a txn, including the keys of its compares and nested txns
+    fn prefix_txn(&self, txn: &mut rpc::TxnRequest) {
+        for cmp in &mut txn.compare {
+            self.prefix_range(&mut cmp.key, &mut cmp.range_end);
+        }
+        for op in txn.success.iter_mut().chain(txn.failure.iter_mut()) {
+            match op.request {
+                Some(Request::RequestRange(ref mut req)) => {
+                    self.prefix_range(&mut req.key, &mut req.range_end);
+                }
+                Some(Request::RequestPut(ref mut req)) => self.prefix_key(&mut req.key),
+                Some(Request::RequestDeleteRange(ref mut req)) => {
+                    self.prefix_range(&mut req.key, &mut req.range_end);
+                }
+                Some(Request::RequestTxn(ref mut req)) => self.prefix_txn(req),
+                None => {}
+            }
+        }
+    }
+
+    /// Remove the prefix from the keys in the responses of a txn
+    fn strip_txn(&self, txn: &mut TxnResponse) {
+        for op in &mut txn.responses {
+            match op.response {
+                Some(Response::ResponseRange(ref mut resp)) => {
+                    resp.kvs.iter_mut().for_each(|kv| self.strip_kv(kv));
+                }
+                Some(Response::ResponsePut(ref mut resp)) => {
+                    resp.prev_kv.iter_mut().for_each(|kv| self.strip_kv(kv));
+                }
+                Some(Response::ResponseDeleteRange(ref mut resp)) => {
+                    resp.prev_kvs.iter_mut().for_each(|kv| self.strip_kv(kv));
+                }
+                Some(Response::ResponseTxn(ref mut resp)) => self.strip_txn(resp),
+                None => {}
+            }
+        }
+    }
+
+    /// Remove the prefix from the keys of the events in a watch response
+    fn strip_watch(&self, resp: &mut WatchResponse) {
+        for event in &mut resp.events {
+            event
+                .kv
+                .iter_mut()
+                .chain(event.prev_kv.iter_mut())
+                .for_each(|kv| self.strip_kv(kv));
+        }
+    }
+}
+
+/// KV client whose keys are in a namespace, so that applications sharing a cluster don't
+/// see the keys of each other
+#[derive(Debug, Clone)]
+pub struct NamespaceKvClient {
+    /// Inner client
+    inner: KvClient,
+    /// Namespace of the keys
+    namespace: Namespace,
+}
+
+impl NamespaceKvClient {
+    /// New `NamespaceKvClient`, the keys are prefixed with `prefix`
+    #[inline]
+    pub fn new(inner: KvClient, prefix: impl Into<Vec<u8>>) -> Self {
+        Self {
+            inner,
+            namespace: Namespace::new(prefix.into()),
+        }
+    }
+
+    /// Put a key-value into the namespace
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn put(&mut self, mut request: PutRequest) -> Result<PutResponse, ClientError> {
+        self.namespace.prefix_key(&mut request.inner.key);
+        let mut response = self.inner.put(request).await?;
+        if let Some(ref mut kv) = response.prev_kv {
+            self.namespace.strip_kv(kv);
+        }
+        Ok(response)
+    }
+
+    /// Get the keys in a range from the namespace
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn range(&mut self, mut request: RangeRequest) -> Result<RangeResponse, ClientError> {
+        self.namespace
+            .prefix_range(&mut request.inner.key, &mut request.inner.range_end);
+        let mut response = self.inner.range(request).await?;
+        for kv in &mut response.kvs {
+            self.namespace.strip_kv(kv);
+        }
+        Ok(response)
+    }
+
+    /// Delete the keys in a range from the namespace
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn delete(
+        &mut self,
+        mut request: DeleteRangeRequest,
+    ) -> Result<DeleteRangeResponse, ClientError> {
+        self.namespace
+            .prefix_range(&mut request.inner.key, &mut request.inner.range_end);
+        let mut response = self.inner.delete(request).await?;
+        for kv in &mut response.prev_kvs {
+            self.namespace.strip_kv(kv);
+        }
+        Ok(response)
+    }
+
+    /// Process the operations of a txn in the namespace atomically
+    ///
+    /// # Errors
+    ///
+    /// If the request fails
+    #[inline]
+    pub async fn txn(&mut self, mut request: TxnRequest) -> Result<TxnResponse, ClientError> {
+        self.namespace.prefix_txn(&mut request.inner);
+        let mut response = self.inner.txn(request).await?;
+        self.namespace.strip_txn(&mut response);
+        Ok(response)
+    }
+}
+
+/// Watch client whose keys are in a namespace
+#[derive(Debug, Clone)]
+pub struct NamespaceWatchClient {
+    /// Inner client
+    inner: WatchClient,
+    /// Namespace of the keys
+    namespace: Namespace,
+}
+
+impl NamespaceWatchClient {
+    /// New `NamespaceWatchClient`, the keys are prefixed with `prefix`
+    #[inline]
+    pub fn new(inner: WatchClient, prefix: impl Into<Vec<u8>>) -> Self {
+        Self {
+            inner,
+            namespace: Namespace::new(prefix.into()),
+        }
+    }
+
+    /// Create a watcher of the keys in the namespace, see `WatchClient::watch`
+    ///
+    /// # Errors
+    ///
+    /// If the request fails or the watcher is not created
+    #[inline]
+    pub async fn watch(
+        &mut self,
+        mut request: WatchRequest,
+    ) -> Result<(NamespaceWatcher, NamespaceWatchStream), ClientError> {
+        self.namespace
+            .prefix_range(&mut request.inner.key, &mut request.inner.range_end);
+        let (watcher, stream) = self.inner.watch(request).await?;
+        Ok((
+            NamespaceWatcher {
+                inner: watcher,
+                namespace: self.namespace.clone(),
+            },
+            NamespaceWatchStream {
+                inner: stream,
+                namespace: self.namespace.clone(),
+            },
+        ))
+    }
+}
+
+/// Handle of a watch stream whose keys are in a namespace
+#[derive(Debug, Clone)]
+pub struct NamespaceWatcher {
+    /// Inner watcher
+    inner: Watcher,
+    /// Namespace of the keys
+    namespace: Namespace,
+}
+
+impl NamespaceWatcher {
+    /// Get the id of the watcher created with the stream
+    #[inline]
+    #[must_use]
+    pub fn watch_id(&self) -> i64 {
+        self.inner.watch_id()
+    }
+
+    /// Create another watcher of the keys in the namespace on the stream
+    ///
+    /// # Errors
+    ///
+    /// If the stream is closed
+    #[inline]
+    pub async fn watch(&self, mut request: WatchRequest) -> Result<(), ClientError> {
+        self.namespace
+            .prefix_range(&mut request.inner.key, &mut request.inner.range_end);
+        self.inner.watch(request).await
+    }
+
+    /// Cancel the watcher created with the stream
+    ///
+    /// # Errors
+    ///
+    /// If the stream is closed
+    #[inline]
+    pub async fn cancel(&self) -> Result<(), ClientError> {
+        self.inner.cancel().await
+    }
+
+    /// Cancel a watcher on the stream
+    ///
+    /// # Errors
+    ///
+    /// If the stream is closed
+    #[inline]
+    pub async fn cancel_by_id(&self, watch_id: i64) -> Result<(), ClientError> {
+        self.inner.cancel_by_id(watch_id).await
+    }
+
+    /// Request the progress of the watchers
+    ///
+    /// # Errors
+    ///
+    /// If the stream is closed
+    #[inline]
+    pub async fn request_progress(&self) -> Result<(), ClientError> {
+        self.inner.request_progress().await
+    }
+}
+
+/// Stream of the responses of the watchers whose keys are in a namespace
+#[derive(Debug)]
+pub struct NamespaceWatchStream {
+    /// Inner stream
+    inner: WatchStream,
+    /// Namespace of the keys
+    namespace: Namespace,
+}
+
+impl NamespaceWatchStream {
+    /// Get the next response, `None` if the stream is closed
+    ///
+    /// # Errors
+    ///
+    /// If the server returns an error
+    #[inline]
+    pub async fn message(&mut self) -> Result<Option<WatchResponse>, ClientError> {
+        let mut response = self.inner.message().await?;
+        if let Some(ref mut resp) = response {
+            self.namespace.strip_watch(resp);
+        }
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::types::kv::{Compare, CompareResult, TxnOp};
+
+    #[test]
+    fn test_prefix_range() {
+        let ns = Namespace::new(b"app/".to_vec());
+        let (mut key, mut range_end) = (b"foo".to_vec(), vec![]);
+        ns.prefix_range(&mut key, &mut range_end);
+        assert_eq!((key, range_end), (b"app/foo".to_vec(), vec![]));
+
+        let (mut key, mut range_end) = (b"foo".to_vec(), b"fop".to_vec());
+        ns.prefix_range(&mut key, &mut range_end);
+        assert_eq!((key, range_end), (b"app/foo".to_vec(), b"app/fop".to_vec()));
+
+        let (mut key, mut range_end) = (vec![0], vec![0]);
+        ns.prefix_range(&mut key, &mut range_end);
+        assert_eq!((key, range_end), (b"app/\0".to_vec(), b"app0".to_vec()));
+    }
+
+    #[test]
+    fn test_prefix_txn() {
+        let ns = Namespace::new(b"app/".to_vec());
+        let mut txn = rpc::TxnRequest::from(
+            TxnRequest::new()
+                .when([Compare::version("foo", CompareResult::Equal, 1)])
+                .and_then([TxnOp::txn(
+                    TxnRequest::new().and_then([TxnOp::put(PutRequest::new("bar", "v"))]),
+                )])
+                .or_else([TxnOp::delete(DeleteRangeRequest::new("baz").with_prefix())]),
+        );
+        ns.prefix_txn(&mut txn);
+        assert_eq!(txn.compare[0].key, b"app/foo");
+        assert!(matches!(
+            txn.success[0].request,
+            Some(Request::RequestTxn(ref nested)) if matches!(
+                nested.success[0].request,
+                Some(Request::RequestPut(ref put)) if put.key == b"app/bar"
+            )
+        ));
+        assert!(matches!(
+            txn.failure[0].request,
+            Some(Request::RequestDeleteRange(ref del))
+                if del.key == b"app/baz" && del.range_end == b"app/bba"
+        ));
+    }
+
+    #[test]
+    fn test_strip_watch() {
+        let ns = Namespace::new(b"app/".to_vec());
+        let kv = KeyValue {
+            key: b"app/foo".to_vec(),
+            ..Default::default()
+        };
+        let mut resp = WatchResponse {
+            events: vec![rpc::Event {
+                kv: Some(kv.clone()),
+                prev_kv: Some(kv),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        ns.strip_watch(&mut resp);
+        let event = &resp.events[0];
+        assert_eq!(
+            event.kv.as_ref().map(|kv| kv.key.as_slice()),
+            Some(&b"foo"[..])
+        );
+        assert_eq!(
+            event.prev_kv.as_ref().map(|kv| kv.key.as_slice()),
+            Some(&b"foo"[..])
+        );
+    }
+}
@@ -0,0 +1,124 @@
+use std::{future::Future, time::Duration};
+
+use tonic::Code;
+
+use crate::errors::ClientError;
+
+/// Policy of retrying idempotent requests which fail with transient errors. The delay
+/// before each retry starts from `initial_backoff` and is doubled after each retry, but it
+/// won't exceed `max_backoff`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Max number of retries of a request
+    max_retries: usize,
+    /// Delay before the first retry
+    initial_backoff: Duration,
+    /// Max delay before a retry
+    max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(50),
+            max_backoff: Duration::from_secs(1),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// New `RetryPolicy`
+    #[inline]
+    #[must_use]
+    pub fn new(max_retries: usize, initial_backoff: Duration, max_backoff: Duration) -> Self {
+        Self {
+            max_retries,
+            initial_backoff,
+            max_backoff,
+        }
+    }
+
+    /// `RetryPolicy` that never retries
+    #[inline]
+    #[must_use]
+    pub fn never() -> Self {
+        Self {
+            max_retries: 0,
+            ..Self::default()
+        }
+    }
+
+    /// Check if a failed request can be retried, the server may be unreachable or
+    /// overloaded, or the endpoint may be switching
+    pub(crate) fn is_transient(status: &tonic::Status) -> bool {
+        matches!(status.code(), Code::Unavailable | Code::DeadlineExceeded)
+    }
+
+    /// Send an idempotent request by `send`, and resend it if it fails with a transient
+    /// error
+    pub(crate) async fn retry<T, F, Fut>(&self, mut send: F) -> Result<T, ClientError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, tonic::Status>>,
+    {
+        let mut backoff = self.initial_backoff;
+        for _ in 0..self.max_retries {
+            match send().await {
+                Err(ref status) if Self::is_transient(status) => {}
+                res => return res.map_err(Into::into),
+            }
+            tokio::time::sleep(backoff).await;
+            backoff = backoff.saturating_mul(2).min(self.max_backoff);
+        }
+        send().await.map_err(Into::into)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_retry_transient_errors() {
+        let policy = RetryPolicy::new(3, Duration::from_millis(1), Duration::from_millis(2));
+        let calls = AtomicUsize::new(0);
+        let res = policy
+            .retry(|| async {
+                if calls.fetch_add(1, Ordering::Relaxed) < 2 {
+                    Err(tonic::Status::unavailable("connection lost"))
+                } else {
+                    Ok(1)
+                }
+            })
+            .await;
+        assert_eq!(res.unwrap(), 1);
+        assert_eq!(calls.load(Ordering::Relaxed), 3);
+
+        let calls = AtomicUsize::new(0);
+        let res: Result<(), _> = policy
+            .retry(|| async {
+                let _prev = calls.fetch_add(1, Ordering::Relaxed);
+                Err(tonic::Status::unavailable("connection lost"))
+            })
+            .await;
+        assert!(res.is_err());
+        assert_eq!(calls.load(Ordering::Relaxed), 4);
+    }
+
+    #[tokio::test]
+    async fn test_not_retry_other_errors() {
+        let calls = AtomicUsize::new(0);
+        let res: Result<(), _> = RetryPolicy::default()
+            .retry(|| async {
+                let _prev = calls.fetch_add(1, Ordering::Relaxed);
+                Err(tonic::Status::invalid_argument("key is not provided"))
+            })
+            .await;
+        assert!(res.is_err());
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+    }
+}
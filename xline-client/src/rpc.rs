@@ -0,0 +1,86 @@
+// Skip for generated code
+#![allow(
+    clippy::all,
+    clippy::restriction,
+    clippy::pedantic,
+    clippy::nursery,
+    clippy::cargo,
+    unused_qualifications,
+    unreachable_pub,
+    variant_size_differences,
+    missing_copy_implementations,
+    missing_docs
+)]
+
+mod etcdserverpb {
+    tonic::include_proto!("etcdserverpb");
+}
+
+mod authpb {
+    tonic::include_proto!("authpb");
+}
+
+mod mvccpb {
+    tonic::include_proto!("mvccpb");
+}
+
+mod v3lockpb {
+    tonic::include_proto!("v3lockpb");
+}
+
+mod healthpb {
+    tonic::include_proto!("grpc.health.v1");
+}
+
+pub use self::{
+    authpb::{permission::Type as PermissionType, ApiKey, Permission, UserAddOptions},
+    etcdserverpb::{
+        alarm_request::AlarmAction,
+        compare::{CompareResult, CompareTarget, TargetUnion},
+        lease_observe_response::EventType as LeaseEventType,
+        range_request::{SortOrder, SortTarget},
+        response_op::Response,
+        watch_create_request::FilterType,
+        AlarmMember, AlarmRequest, AlarmResponse, AlarmType, AuthApiKeyAddResponse,
+        AuthApiKeyListResponse, AuthApiKeyRevokeResponse, AuthDisableResponse, AuthEnableResponse,
+        AuthRoleAddResponse, AuthRoleDeleteResponse, AuthRoleGetResponse,
+        AuthRoleGrantPermissionResponse, AuthRoleListResponse, AuthRoleRevokePermissionResponse,
+        AuthStatusResponse, AuthUserAddResponse, AuthUserChangePasswordResponse,
+        AuthUserDeleteResponse, AuthUserGetResponse, AuthUserGrantRoleResponse,
+        AuthUserListResponse, AuthUserRevokeRoleResponse, AuthenticateResponse, CompactionResponse,
+        Compare, DefragmentResponse, DeleteRangeResponse, HashKvResponse, HashResponse,
+        KeyHistoryResponse, LeaseGrantResponse, LeaseKeepAliveResponse, LeaseLeasesResponse,
+        LeaseModifyTtlResponse, LeaseObserveResponse, LeaseRevokeResponse, LeaseStatus,
+        LeaseTimeToLiveResponse, Member, MemberAddResponse, MemberListResponse,
+        MemberPromoteResponse, MemberRemoveResponse, MemberUpdateResponse, MoveLeaderResponse,
+        PutResponse, RangeResponse, ResponseHeader, ResponseOp, RevisionAtResponse,
+        SnapshotResponse, StatusResponse, TableStats, TableStatsResponse, TxnResponse,
+        UndeleteResponse, WaitRevisionResponse, WatchResponse,
+    },
+    mvccpb::{event::EventType, Event, KeyValue},
+    v3lockpb::{LockResponse, UnlockResponse},
+};
+pub(crate) use self::{
+    etcdserverpb::{
+        auth_client::AuthClient, cluster_client::ClusterClient, kv_client::KvClient,
+        lease_client::LeaseClient, maintenance_client::MaintenanceClient, request_op::Request,
+        watch_client::WatchClient, watch_request::RequestUnion, AuthApiKeyAddRequest,
+        AuthApiKeyListRequest, AuthApiKeyRevokeRequest, AuthDisableRequest, AuthEnableRequest,
+        AuthRoleAddRequest, AuthRoleDeleteRequest, AuthRoleGetRequest,
+        AuthRoleGrantPermissionRequest, AuthRoleListRequest, AuthRoleRevokePermissionRequest,
+        AuthStatusRequest, AuthUserAddRequest, AuthUserChangePasswordRequest,
+        AuthUserDeleteRequest, AuthUserGetRequest, AuthUserGrantRoleRequest, AuthUserListRequest,
+        AuthUserRevokeRoleRequest, AuthenticateRequest, CompactionRequest, DefragmentRequest,
+        DeleteRangeRequest, HashKvRequest, HashRequest, KeyHistoryRequest, LeaseGrantRequest,
+        LeaseKeepAliveRequest, LeaseLeasesRequest, LeaseModifyTtlRequest, LeaseObserveRequest,
+        LeaseRevokeRequest, LeaseTimeToLiveRequest, MemberAddRequest, MemberListRequest,
+        MemberPromoteRequest, MemberRemoveRequest, MemberUpdateRequest, MoveLeaderRequest,
+        PutRequest, RangeRequest, RequestOp, RevisionAtRequest, SnapshotRequest, StatusRequest,
+        TableStatsRequest, TxnRequest, UndeleteRequest, WaitRevisionRequest, WatchCancelRequest,
+        WatchCreateRequest, WatchProgressRequest, WatchRange, WatchRequest, WatchValuePredicate,
+    },
+    healthpb::{
+        health_check_response::ServingStatus, health_client::HealthClient, HealthCheckRequest,
+    },
+    v3lockpb::{lock_client::LockClient, LockRequest, UnlockRequest},
+};
@@ -0,0 +1,212 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    future::Future,
+    pin::Pin,
+};
+
+use crate::{
+    clients::kv::KvClient,
+    errors::ClientError,
+    types::kv::{
+        Compare, CompareResult, DeleteRangeRequest, PutRequest, RangeRequest, TxnOp, TxnRequest,
+    },
+};
+
+/// Future returned by the closure of an STM transaction, it borrows the transaction
+pub type StmFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, ClientError>> + Send + 'a>>;
+
+/// A software transactional memory transaction. The keys are read from a snapshot of the
+/// store at the revision of the first read, and the writes are buffered until the closure
+/// returns. Then they are committed by a txn which checks that none of the keys read or
+/// written has been modified since the snapshot, like the serializable snapshot isolation
+/// of etcd's STM.
+#[derive(Debug)]
+pub struct Stm {
+    /// Client to read and commit by
+    client: KvClient,
+    /// Revision of the snapshot, 0 before the first read
+    revision: i64,
+    /// Values of the keys read from the snapshot, `None` if a key doesn't exist, and the
+    /// mod revisions of them
+    reads: HashMap<Vec<u8>, (Option<Vec<u8>>, i64)>,
+    /// Buffered writes, `None` deletes a key
+    writes: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+}
+
+impl Stm {
+    /// New `Stm`
+    fn new(client: KvClient) -> Self {
+        Self {
+            client,
+            revision: 0,
+            reads: HashMap::new(),
+            writes: BTreeMap::new(),
+        }
+    }
+
+    /// Get the value of a key, the buffered write of the key if there is one, otherwise the
+    /// value in the snapshot, `None` if the key doesn't exist
+    ///
+    /// # Errors
+    ///
+    /// If the range fails
+    #[inline]
+    pub async fn get(&mut self, key: impl Into<Vec<u8>>) -> Result<Option<Vec<u8>>, ClientError> {
+        let key = key.into();
+        if let Some(write) = self.writes.get(&key) {
+            return Ok(write.clone());
+        }
+        if let Some(&(ref value, _)) = self.reads.get(&key) {
+            return Ok(value.clone());
+        }
+        let resp = self
+            .client
+            .range(RangeRequest::new(key.clone()).with_revision(self.revision))
+            .await?;
+        if self.revision == 0 {
+            self.revision = resp.header.as_ref().map_or(0, |header| header.revision);
+        }
+        let (value, mod_revision) = resp
+            .kvs
+            .into_iter()
+            .next()
+            .map_or((None, 0), |kv| (Some(kv.value), kv.mod_revision));
+        let _prev = self.reads.insert(key, (value.clone(), mod_revision));
+        Ok(value)
+    }
+
+    /// Put a key-value when the transaction commits
+    #[inline]
+    pub fn put(&mut self, key: impl Into<Vec<u8>>, value: impl Into<Vec<u8>>) {
+        let _prev = self.writes.insert(key.into(), Some(value.into()));
+    }
+
+    /// Delete a key when the transaction commits
+    #[inline]
+    pub fn delete(&mut self, key: impl Into<Vec<u8>>) {
+        let _prev = self.writes.insert(key.into(), None);
+    }
+
+    /// Forget the snapshot and the writes, so that the transaction can be applied again
+    fn reset(&mut self) {
+        self.revision = 0;
+        self.reads.clear();
+        self.writes.clear();
+    }
+}
+
+/// Build the txn committing the buffered writes. It succeeds only if the keys read still
+/// have the mod revisions read, and the keys written are not modified after the snapshot.
+fn commit_txn(
+    revision: i64,
+    reads: &HashMap<Vec<u8>, (Option<Vec<u8>>, i64)>,
+    writes: &BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+) -> TxnRequest {
+    let read_cmps = reads.iter().map(|(key, &(_, mod_revision))| {
+        Compare::mod_revision(key.clone(), CompareResult::Equal, mod_revision)
+    });
+    let write_cmps = writes
+        .keys()
+        .filter(|key| revision > 0 && !reads.contains_key(*key))
+        .map(|key| {
+            Compare::mod_revision(key.clone(), CompareResult::Less, revision.saturating_add(1))
+        });
+    let ops = writes.iter().map(|(key, value)| match *value {
+        Some(ref value) => TxnOp::put(PutRequest::new(key.clone(), value.clone())),
+        None => TxnOp::delete(DeleteRangeRequest::new(key.clone())),
+    });
+    TxnRequest::new()
+        .when(read_cmps.chain(write_cmps))
+        .and_then(ops)
+}
+
+/// Run a software transactional memory transaction. The closure reads the keys by
+/// `Stm::get` and buffers the writes by `Stm::put` and `Stm::delete`, then the writes are
+/// committed if none of the keys read or written has been modified by others meanwhile.
+/// Otherwise the closure is applied again from a new snapshot until it commits, so it
+/// should have no side effects other than the ones on the `Stm`. The value returned by the
+/// closure which commits is returned.
+///
+/// ```rust,no_run
+/// use xline_client::{stm, Client, ClientOptions};
+///
+/// # async fn transfer() -> Result<(), Box<dyn std::error::Error>> {
+/// let client = Client::connect(["127.0.0.1:2379"], ClientOptions::new()).await?;
+/// stm::run(&client.kv_client(), |stm| {
+///     Box::pin(async move {
+///         let from = stm.get("a").await?.unwrap_or_default();
+///         let to = stm.get("b").await?.unwrap_or_default();
+///         stm.put("a", to);
+///         stm.put("b", from);
+///         Ok(())
+///     })
+/// })
+/// .await?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// If a request fails, or the closure returns an error, the writes are not committed then
+#[inline]
+pub async fn run<T, F>(client: &KvClient, mut apply: F) -> Result<T, ClientError>
+where
+    F: for<'a> FnMut(&'a mut Stm) -> StmFuture<'a, T>,
+{
+    let mut stm = Stm::new(client.clone());
+    loop {
+        let res = apply(&mut stm).await?;
+        let txn = commit_txn(stm.revision, &stm.reads, &stm.writes);
+        if stm.client.txn(txn).await?.succeeded {
+            return Ok(res);
+        }
+        stm.reset();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rpc::{self, Request, TargetUnion};
+
+    #[test]
+    fn test_commit_txn_should_check_the_keys_read_and_written() {
+        let reads = HashMap::from([
+            (b"a".to_vec(), (Some(b"1".to_vec()), 3)),
+            (b"b".to_vec(), (None, 0)),
+        ]);
+        let writes = BTreeMap::from([(b"a".to_vec(), Some(b"2".to_vec())), (b"c".to_vec(), None)]);
+        let txn = rpc::TxnRequest::from(commit_txn(5, &reads, &writes));
+        assert_eq!(txn.success.len(), 2);
+        assert!(matches!(
+            txn.success[1].request,
+            Some(Request::RequestDeleteRange(ref req)) if req.key == b"c"
+        ));
+        let mut compares: Vec<_> = txn
+            .compare
+            .into_iter()
+            .map(|cmp| {
+                let Some(TargetUnion::ModRevision(revision)) = cmp.target_union else {
+                    panic!("unexpected compare {cmp:?}");
+                };
+                (cmp.key, cmp.result, revision)
+            })
+            .collect();
+        compares.sort();
+        let equal = CompareResult::Equal as i32;
+        let less = CompareResult::Less as i32;
+        assert_eq!(
+            compares,
+            vec![
+                (b"a".to_vec(), equal, 3),
+                (b"b".to_vec(), equal, 0),
+                (b"c".to_vec(), less, 6)
+            ]
+        );
+
+        // the blind writes are not checked
+        let txn = rpc::TxnRequest::from(commit_txn(0, &HashMap::new(), &writes));
+        assert!(txn.compare.is_empty());
+    }
+}
@@ -0,0 +1,63 @@
+pub use crate::rpc::{
+    ApiKey, AuthApiKeyAddResponse, AuthApiKeyListResponse, AuthApiKeyRevokeResponse,
+    AuthDisableResponse, AuthEnableResponse, AuthRoleAddResponse, AuthRoleDeleteResponse,
+    AuthRoleGetResponse, AuthRoleGrantPermissionResponse, AuthRoleListResponse,
+    AuthRoleRevokePermissionResponse, AuthStatusResponse, AuthUserAddResponse,
+    AuthUserChangePasswordResponse, AuthUserDeleteResponse, AuthUserGetResponse,
+    AuthUserGrantRoleResponse, AuthUserListResponse, AuthUserRevokeRoleResponse,
+    AuthenticateResponse, Permission as PbPermission, PermissionType,
+};
+use crate::types::{from_key_range, prefix_range};
+
+/// Permission of a role on a range of keys
+#[derive(Debug, Clone, PartialEq)]
+pub struct Permission {
+    /// Inner permission
+    inner: PbPermission,
+}
+
+impl Permission {
+    /// New `Permission` on `key`
+    #[inline]
+    #[allow(clippy::as_conversions)] // this case is always safe
+    pub fn new(perm_type: PermissionType, key: impl Into<Vec<u8>>) -> Self {
+        Self {
+            inner: PbPermission {
+                perm_type: perm_type as i32,
+                key: key.into(),
+                range_end: vec![],
+            },
+        }
+    }
+
+    /// Set the permission on the keys with the prefix `key`
+    #[inline]
+    #[must_use]
+    pub fn with_prefix(mut self) -> Self {
+        (self.inner.key, self.inner.range_end) = prefix_range(&self.inner.key);
+        self
+    }
+
+    /// Set the permission on the keys not less than `key`
+    #[inline]
+    #[must_use]
+    pub fn with_from_key(mut self) -> Self {
+        (self.inner.key, self.inner.range_end) = from_key_range(&self.inner.key);
+        self
+    }
+
+    /// Set `range_end`
+    #[inline]
+    #[must_use]
+    pub fn with_range_end(mut self, range_end: impl Into<Vec<u8>>) -> Self {
+        self.inner.range_end = range_end.into();
+        self
+    }
+}
+
+impl From<Permission> for PbPermission {
+    #[inline]
+    fn from(perm: Permission) -> Self {
+        perm.inner
+    }
+}
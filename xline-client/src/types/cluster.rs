@@ -0,0 +1,4 @@
+pub use crate::rpc::{
+    Member, MemberAddResponse, MemberListResponse, MemberPromoteResponse, MemberRemoveResponse,
+    MemberUpdateResponse,
+};
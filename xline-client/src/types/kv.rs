@@ -0,0 +1,668 @@
+pub use crate::rpc::{
+    CompactionResponse, CompareResult, CompareTarget, DeleteRangeResponse, KeyHistoryResponse,
+    KeyValue, PutResponse, RangeResponse, Response as TxnOpResponse, ResponseHeader, ResponseOp,
+    RevisionAtResponse, SortOrder, SortTarget, TxnResponse, UndeleteResponse, WaitRevisionResponse,
+};
+use crate::{
+    rpc::{self, Request, RequestOp, TargetUnion},
+    types::{from_key_range, prefix_range},
+};
+
+/// Request for `Put`
+#[derive(Debug, Clone, PartialEq)]
+pub struct PutRequest {
+    /// Inner request
+    pub(crate) inner: rpc::PutRequest,
+}
+
+impl PutRequest {
+    /// New `PutRequest`
+    #[inline]
+    pub fn new(key: impl Into<Vec<u8>>, value: impl Into<Vec<u8>>) -> Self {
+        Self {
+            inner: rpc::PutRequest {
+                key: key.into(),
+                value: value.into(),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Set `lease`
+    #[inline]
+    #[must_use]
+    pub fn with_lease(mut self, lease: i64) -> Self {
+        self.inner.lease = lease;
+        self
+    }
+
+    /// Set `prev_kv`
+    #[inline]
+    #[must_use]
+    pub fn with_prev_kv(mut self, prev_kv: bool) -> Self {
+        self.inner.prev_kv = prev_kv;
+        self
+    }
+
+    /// Set `ignore_value`
+    #[inline]
+    #[must_use]
+    pub fn with_ignore_value(mut self, ignore_value: bool) -> Self {
+        self.inner.ignore_value = ignore_value;
+        self
+    }
+
+    /// Set `ignore_lease`
+    #[inline]
+    #[must_use]
+    pub fn with_ignore_lease(mut self, ignore_lease: bool) -> Self {
+        self.inner.ignore_lease = ignore_lease;
+        self
+    }
+
+    /// Get `key`
+    #[inline]
+    #[must_use]
+    pub fn key(&self) -> &[u8] {
+        &self.inner.key
+    }
+
+    /// Get `value`
+    #[inline]
+    #[must_use]
+    pub fn value(&self) -> &[u8] {
+        &self.inner.value
+    }
+}
+
+impl From<PutRequest> for rpc::PutRequest {
+    #[inline]
+    fn from(req: PutRequest) -> Self {
+        req.inner
+    }
+}
+
+/// Request for `Range`
+#[derive(Debug, Clone, PartialEq)]
+pub struct RangeRequest {
+    /// Inner request
+    pub(crate) inner: rpc::RangeRequest,
+}
+
+impl RangeRequest {
+    /// New `RangeRequest`
+    #[inline]
+    pub fn new(key: impl Into<Vec<u8>>) -> Self {
+        Self {
+            inner: rpc::RangeRequest {
+                key: key.into(),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Get the keys with the prefix `key`
+    #[inline]
+    #[must_use]
+    pub fn with_prefix(mut self) -> Self {
+        (self.inner.key, self.inner.range_end) = prefix_range(&self.inner.key);
+        self
+    }
+
+    /// Get the keys not less than `key`
+    #[inline]
+    #[must_use]
+    pub fn with_from_key(mut self) -> Self {
+        (self.inner.key, self.inner.range_end) = from_key_range(&self.inner.key);
+        self
+    }
+
+    /// Set `range_end`
+    #[inline]
+    #[must_use]
+    pub fn with_range_end(mut self, range_end: impl Into<Vec<u8>>) -> Self {
+        self.inner.range_end = range_end.into();
+        self
+    }
+
+    /// Set `limit`
+    #[inline]
+    #[must_use]
+    pub fn with_limit(mut self, limit: i64) -> Self {
+        self.inner.limit = limit;
+        self
+    }
+
+    /// Set `revision`
+    #[inline]
+    #[must_use]
+    pub fn with_revision(mut self, revision: i64) -> Self {
+        self.inner.revision = revision;
+        self
+    }
+
+    /// Set `sort_order`
+    #[inline]
+    #[must_use]
+    #[allow(clippy::as_conversions)] // this case is always safe
+    pub fn with_sort_order(mut self, sort_order: SortOrder) -> Self {
+        self.inner.sort_order = sort_order as i32;
+        self
+    }
+
+    /// Set `sort_target`
+    #[inline]
+    #[must_use]
+    #[allow(clippy::as_conversions)] // this case is always safe
+    pub fn with_sort_target(mut self, sort_target: SortTarget) -> Self {
+        self.inner.sort_target = sort_target as i32;
+        self
+    }
+
+    /// Set `serializable`
+    #[inline]
+    #[must_use]
+    pub fn with_serializable(mut self, serializable: bool) -> Self {
+        self.inner.serializable = serializable;
+        self
+    }
+
+    /// Set `keys_only`
+    #[inline]
+    #[must_use]
+    pub fn with_keys_only(mut self, keys_only: bool) -> Self {
+        self.inner.keys_only = keys_only;
+        self
+    }
+
+    /// Set `count_only`
+    #[inline]
+    #[must_use]
+    pub fn with_count_only(mut self, count_only: bool) -> Self {
+        self.inner.count_only = count_only;
+        self
+    }
+
+    /// Set `min_mod_revision`
+    #[inline]
+    #[must_use]
+    pub fn with_min_mod_revision(mut self, min_mod_revision: i64) -> Self {
+        self.inner.min_mod_revision = min_mod_revision;
+        self
+    }
+
+    /// Set `max_mod_revision`
+    #[inline]
+    #[must_use]
+    pub fn with_max_mod_revision(mut self, max_mod_revision: i64) -> Self {
+        self.inner.max_mod_revision = max_mod_revision;
+        self
+    }
+
+    /// Set `min_create_revision`
+    #[inline]
+    #[must_use]
+    pub fn with_min_create_revision(mut self, min_create_revision: i64) -> Self {
+        self.inner.min_create_revision = min_create_revision;
+        self
+    }
+
+    /// Set `max_create_revision`
+    #[inline]
+    #[must_use]
+    pub fn with_max_create_revision(mut self, max_create_revision: i64) -> Self {
+        self.inner.max_create_revision = max_create_revision;
+        self
+    }
+
+    /// Set `page_token` to the `next_page_token` of the previous page, the next page is
+    /// read at the revision of the first page. It is an Xline extension.
+    #[inline]
+    #[must_use]
+    pub fn with_page_token(mut self, page_token: impl Into<Vec<u8>>) -> Self {
+        self.inner.page_token = page_token.into();
+        self
+    }
+
+    /// Get `key`
+    #[inline]
+    #[must_use]
+    pub fn key(&self) -> &[u8] {
+        &self.inner.key
+    }
+
+    /// Get `range_end`
+    #[inline]
+    #[must_use]
+    pub fn range_end(&self) -> &[u8] {
+        &self.inner.range_end
+    }
+}
+
+impl From<RangeRequest> for rpc::RangeRequest {
+    #[inline]
+    fn from(req: RangeRequest) -> Self {
+        req.inner
+    }
+}
+
+/// Request for `DeleteRange`
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeleteRangeRequest {
+    /// Inner request
+    pub(crate) inner: rpc::DeleteRangeRequest,
+}
+
+impl DeleteRangeRequest {
+    /// New `DeleteRangeRequest`
+    #[inline]
+    pub fn new(key: impl Into<Vec<u8>>) -> Self {
+        Self {
+            inner: rpc::DeleteRangeRequest {
+                key: key.into(),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Delete the keys with the prefix `key`
+    #[inline]
+    #[must_use]
+    pub fn with_prefix(mut self) -> Self {
+        (self.inner.key, self.inner.range_end) = prefix_range(&self.inner.key);
+        self
+    }
+
+    /// Delete the keys not less than `key`
+    #[inline]
+    #[must_use]
+    pub fn with_from_key(mut self) -> Self {
+        (self.inner.key, self.inner.range_end) = from_key_range(&self.inner.key);
+        self
+    }
+
+    /// Set `range_end`
+    #[inline]
+    #[must_use]
+    pub fn with_range_end(mut self, range_end: impl Into<Vec<u8>>) -> Self {
+        self.inner.range_end = range_end.into();
+        self
+    }
+
+    /// Set `prev_kv`
+    #[inline]
+    #[must_use]
+    pub fn with_prev_kv(mut self, prev_kv: bool) -> Self {
+        self.inner.prev_kv = prev_kv;
+        self
+    }
+
+    /// Get `key`
+    #[inline]
+    #[must_use]
+    pub fn key(&self) -> &[u8] {
+        &self.inner.key
+    }
+
+    /// Get `range_end`
+    #[inline]
+    #[must_use]
+    pub fn range_end(&self) -> &[u8] {
+        &self.inner.range_end
+    }
+}
+
+impl From<DeleteRangeRequest> for rpc::DeleteRangeRequest {
+    #[inline]
+    fn from(req: DeleteRangeRequest) -> Self {
+        req.inner
+    }
+}
+
+/// Condition of a txn, it compares a target of the keys with a given value
+#[derive(Debug, Clone, PartialEq)]
+pub struct Compare {
+    /// Inner compare
+    inner: rpc::Compare,
+}
+
+impl Compare {
+    /// New `Compare`
+    #[allow(clippy::as_conversions)] // this case is always safe
+    fn new(key: Vec<u8>, result: CompareResult, target_union: TargetUnion) -> Self {
+        let target = match target_union {
+            TargetUnion::Version(_) => CompareTarget::Version,
+            TargetUnion::CreateRevision(_) => CompareTarget::Create,
+            TargetUnion::ModRevision(_) => CompareTarget::Mod,
+            TargetUnion::Value(_) => CompareTarget::Value,
+            TargetUnion::Lease(_) => CompareTarget::Lease,
+        };
+        Self {
+            inner: rpc::Compare {
+                result: result as i32,
+                target: target as i32,
+                key,
+                range_end: vec![],
+                target_union: Some(target_union),
+            },
+        }
+    }
+
+    /// Compare the version of the key
+    #[inline]
+    pub fn version(key: impl Into<Vec<u8>>, result: CompareResult, version: i64) -> Self {
+        Self::new(key.into(), result, TargetUnion::Version(version))
+    }
+
+    /// Compare the create revision of the key
+    #[inline]
+    pub fn create_revision(key: impl Into<Vec<u8>>, result: CompareResult, revision: i64) -> Self {
+        Self::new(key.into(), result, TargetUnion::CreateRevision(revision))
+    }
+
+    /// Compare the mod revision of the key
+    #[inline]
+    pub fn mod_revision(key: impl Into<Vec<u8>>, result: CompareResult, revision: i64) -> Self {
+        Self::new(key.into(), result, TargetUnion::ModRevision(revision))
+    }
+
+    /// Compare the value of the key
+    #[inline]
+    pub fn value(
+        key: impl Into<Vec<u8>>,
+        result: CompareResult,
+        value: impl Into<Vec<u8>>,
+    ) -> Self {
+        Self::new(key.into(), result, TargetUnion::Value(value.into()))
+    }
+
+    /// Compare the lease of the key
+    #[inline]
+    pub fn lease(key: impl Into<Vec<u8>>, result: CompareResult, lease: i64) -> Self {
+        Self::new(key.into(), result, TargetUnion::Lease(lease))
+    }
+
+    /// Compare the keys with the prefix `key`
+    #[inline]
+    #[must_use]
+    pub fn with_prefix(mut self) -> Self {
+        (self.inner.key, self.inner.range_end) = prefix_range(&self.inner.key);
+        self
+    }
+
+    /// Set `range_end`
+    #[inline]
+    #[must_use]
+    pub fn with_range_end(mut self, range_end: impl Into<Vec<u8>>) -> Self {
+        self.inner.range_end = range_end.into();
+        self
+    }
+}
+
+impl From<Compare> for rpc::Compare {
+    #[inline]
+    fn from(cmp: Compare) -> Self {
+        cmp.inner
+    }
+}
+
+/// Operation in a txn
+#[derive(Debug, Clone, PartialEq)]
+pub struct TxnOp {
+    /// Inner request
+    inner: Request,
+}
+
+impl TxnOp {
+    /// `Put` operation
+    #[inline]
+    #[must_use]
+    pub fn put(request: PutRequest) -> Self {
+        Self {
+            inner: Request::RequestPut(request.into()),
+        }
+    }
+
+    /// `Range` operation
+    #[inline]
+    #[must_use]
+    pub fn range(request: RangeRequest) -> Self {
+        Self {
+            inner: Request::RequestRange(request.into()),
+        }
+    }
+
+    /// `DeleteRange` operation
+    #[inline]
+    #[must_use]
+    pub fn delete(request: DeleteRangeRequest) -> Self {
+        Self {
+            inner: Request::RequestDeleteRange(request.into()),
+        }
+    }
+
+    /// Nested `Txn` operation
+    #[inline]
+    #[must_use]
+    pub fn txn(request: TxnRequest) -> Self {
+        Self {
+            inner: Request::RequestTxn(request.into()),
+        }
+    }
+}
+
+impl From<TxnOp> for RequestOp {
+    #[inline]
+    fn from(op: TxnOp) -> Self {
+        RequestOp {
+            request: Some(op.inner),
+        }
+    }
+}
+
+/// Request for `Txn`, the operations in `and_then` are executed if all compares
+/// succeed, otherwise the operations in `or_else` are executed
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TxnRequest {
+    /// Inner request
+    pub(crate) inner: rpc::TxnRequest,
+}
+
+impl TxnRequest {
+    /// New `TxnRequest`
+    #[inline]
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the compares of the txn
+    #[inline]
+    #[must_use]
+    pub fn when(mut self, compares: impl IntoIterator<Item = Compare>) -> Self {
+        self.inner
+            .compare
+            .extend(compares.into_iter().map(Into::into));
+        self
+    }
+
+    /// Add the operations executed if all compares succeed
+    #[inline]
+    #[must_use]
+    pub fn and_then(mut self, ops: impl IntoIterator<Item = TxnOp>) -> Self {
+        self.inner.success.extend(ops.into_iter().map(Into::into));
+        self
+    }
+
+    /// Add the operations executed if any compare fails
+    #[inline]
+    #[must_use]
+    pub fn or_else(mut self, ops: impl IntoIterator<Item = TxnOp>) -> Self {
+        self.inner.failure.extend(ops.into_iter().map(Into::into));
+        self
+    }
+}
+
+impl From<TxnRequest> for rpc::TxnRequest {
+    #[inline]
+    fn from(req: TxnRequest) -> Self {
+        req.inner
+    }
+}
+
+/// Request for `Compact`
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompactionRequest {
+    /// Inner request
+    inner: rpc::CompactionRequest,
+}
+
+impl CompactionRequest {
+    /// New `CompactionRequest`, the history before `revision` is compacted
+    #[inline]
+    #[must_use]
+    pub fn new(revision: i64) -> Self {
+        Self {
+            inner: rpc::CompactionRequest {
+                revision,
+                physical: false,
+            },
+        }
+    }
+
+    /// Set `physical`, the request returns after the history is removed from the
+    /// backend if it's set
+    #[inline]
+    #[must_use]
+    pub fn with_physical(mut self, physical: bool) -> Self {
+        self.inner.physical = physical;
+        self
+    }
+}
+
+impl From<CompactionRequest> for rpc::CompactionRequest {
+    #[inline]
+    fn from(req: CompactionRequest) -> Self {
+        req.inner
+    }
+}
+
+/// Request for `KeyHistory`
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyHistoryRequest {
+    /// Inner request
+    inner: rpc::KeyHistoryRequest,
+}
+
+impl KeyHistoryRequest {
+    /// New `KeyHistoryRequest`, the retained revisions of `key` are listed
+    #[inline]
+    pub fn new(key: impl Into<Vec<u8>>) -> Self {
+        Self {
+            inner: rpc::KeyHistoryRequest {
+                key: key.into(),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Set `start_revision`, the revisions from it are listed, 0 means from the compacted
+    /// revision
+    #[inline]
+    #[must_use]
+    pub fn with_start_revision(mut self, start_revision: i64) -> Self {
+        self.inner.start_revision = start_revision;
+        self
+    }
+
+    /// Set `limit`, the max number of the revisions listed, 0 means no limit
+    #[inline]
+    #[must_use]
+    pub fn with_limit(mut self, limit: i64) -> Self {
+        self.inner.limit = limit;
+        self
+    }
+}
+
+impl From<KeyHistoryRequest> for rpc::KeyHistoryRequest {
+    #[inline]
+    fn from(req: KeyHistoryRequest) -> Self {
+        req.inner
+    }
+}
+
+/// Request for `Undelete`
+#[derive(Debug, Clone, PartialEq)]
+pub struct UndeleteRequest {
+    /// Inner request
+    inner: rpc::UndeleteRequest,
+}
+
+impl UndeleteRequest {
+    /// New `UndeleteRequest`, the key is restored from the trash
+    #[inline]
+    pub fn new(key: impl Into<Vec<u8>>) -> Self {
+        Self {
+            inner: rpc::UndeleteRequest {
+                key: key.into(),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Restore the keys with the prefix `key`
+    #[inline]
+    #[must_use]
+    pub fn with_prefix(mut self) -> Self {
+        (self.inner.key, self.inner.range_end) = prefix_range(&self.inner.key);
+        self
+    }
+
+    /// Restore the keys not less than `key`
+    #[inline]
+    #[must_use]
+    pub fn with_from_key(mut self) -> Self {
+        (self.inner.key, self.inner.range_end) = from_key_range(&self.inner.key);
+        self
+    }
+
+    /// Set `range_end`
+    #[inline]
+    #[must_use]
+    pub fn with_range_end(mut self, range_end: impl Into<Vec<u8>>) -> Self {
+        self.inner.range_end = range_end.into();
+        self
+    }
+}
+
+impl From<UndeleteRequest> for rpc::UndeleteRequest {
+    #[inline]
+    fn from(req: UndeleteRequest) -> Self {
+        req.inner
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_txn_request() {
+        let txn = TxnRequest::new()
+            .when([Compare::value("foo", CompareResult::Equal, "bar").with_prefix()])
+            .and_then([TxnOp::put(PutRequest::new("foo", "baz"))])
+            .or_else([TxnOp::range(RangeRequest::new("foo"))]);
+        let txn = rpc::TxnRequest::from(txn);
+        assert_eq!(txn.compare.len(), 1);
+        assert_eq!(txn.compare[0].target, CompareTarget::Value as i32);
+        assert_eq!(txn.compare[0].range_end, b"fop");
+        assert!(matches!(
+            txn.success[0].request,
+            Some(Request::RequestPut(ref put)) if put.value == b"baz"
+        ));
+        assert!(matches!(
+            txn.failure[0].request,
+            Some(Request::RequestRange(ref range)) if range.key == b"foo"
+        ));
+    }
+}
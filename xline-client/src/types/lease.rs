@@ -0,0 +1,72 @@
+use crate::rpc;
+pub use crate::rpc::{
+    LeaseEventType, LeaseGrantResponse, LeaseKeepAliveResponse, LeaseLeasesResponse,
+    LeaseModifyTtlResponse, LeaseObserveResponse, LeaseRevokeResponse, LeaseStatus,
+    LeaseTimeToLiveResponse,
+};
+
+/// Request for `LeaseGrant`
+#[derive(Debug, Clone, PartialEq)]
+pub struct LeaseGrantRequest {
+    /// Inner request
+    inner: rpc::LeaseGrantRequest,
+}
+
+impl LeaseGrantRequest {
+    /// New `LeaseGrantRequest` with the time-to-live in seconds
+    #[inline]
+    #[must_use]
+    pub fn new(ttl: i64) -> Self {
+        Self {
+            inner: rpc::LeaseGrantRequest { ttl, id: 0 },
+        }
+    }
+
+    /// Set `id`, the server chooses an id if it's 0
+    #[inline]
+    #[must_use]
+    pub fn with_id(mut self, id: i64) -> Self {
+        self.inner.id = id;
+        self
+    }
+}
+
+impl From<LeaseGrantRequest> for rpc::LeaseGrantRequest {
+    #[inline]
+    fn from(req: LeaseGrantRequest) -> Self {
+        req.inner
+    }
+}
+
+/// Request for `LeaseTimeToLive`
+#[derive(Debug, Clone, PartialEq)]
+pub struct LeaseTimeToLiveRequest {
+    /// Inner request
+    inner: rpc::LeaseTimeToLiveRequest,
+}
+
+impl LeaseTimeToLiveRequest {
+    /// New `LeaseTimeToLiveRequest`
+    #[inline]
+    #[must_use]
+    pub fn new(id: i64) -> Self {
+        Self {
+            inner: rpc::LeaseTimeToLiveRequest { id, keys: false },
+        }
+    }
+
+    /// Set `keys`, the keys attached to the lease are returned if it's set
+    #[inline]
+    #[must_use]
+    pub fn with_keys(mut self, keys: bool) -> Self {
+        self.inner.keys = keys;
+        self
+    }
+}
+
+impl From<LeaseTimeToLiveRequest> for rpc::LeaseTimeToLiveRequest {
+    #[inline]
+    fn from(req: LeaseTimeToLiveRequest) -> Self {
+        req.inner
+    }
+}
@@ -0,0 +1,37 @@
+use crate::rpc;
+pub use crate::rpc::{LockResponse, UnlockResponse};
+
+/// Request for `Lock`
+#[derive(Debug, Clone, PartialEq)]
+pub struct LockRequest {
+    /// Inner request
+    inner: rpc::LockRequest,
+}
+
+impl LockRequest {
+    /// New `LockRequest`, the lock is named `name`
+    #[inline]
+    pub fn new(name: impl Into<Vec<u8>>) -> Self {
+        Self {
+            inner: rpc::LockRequest {
+                name: name.into(),
+                lease: 0,
+            },
+        }
+    }
+
+    /// Set `lease`, the lock is released when the lease expires
+    #[inline]
+    #[must_use]
+    pub fn with_lease(mut self, lease: i64) -> Self {
+        self.inner.lease = lease;
+        self
+    }
+}
+
+impl From<LockRequest> for rpc::LockRequest {
+    #[inline]
+    fn from(req: LockRequest) -> Self {
+        req.inner
+    }
+}
@@ -0,0 +1,5 @@
+pub use crate::rpc::{
+    AlarmAction, AlarmMember, AlarmResponse, AlarmType, DefragmentResponse, HashKvResponse,
+    HashResponse, MoveLeaderResponse, SnapshotResponse, StatusResponse, TableStats,
+    TableStatsResponse,
+};
@@ -0,0 +1,54 @@
+/// Auth request builders and responses
+pub mod auth;
+/// Cluster responses
+pub mod cluster;
+/// KV request builders and responses
+pub mod kv;
+/// Lease request builders and responses
+pub mod lease;
+/// Lock request builders and responses
+pub mod lock;
+/// Maintenance responses
+pub mod maintenance;
+/// Watch request builders and responses
+pub mod watch;
+
+/// Get the range of the keys with the prefix `key`, it contains all keys if `key` is empty
+pub(crate) fn prefix_range(key: &[u8]) -> (Vec<u8>, Vec<u8>) {
+    if key.is_empty() {
+        return (vec![0], vec![0]);
+    }
+    let mut end = key.to_vec();
+    while let Some(last) = end.pop() {
+        if last < 0xFF {
+            end.push(last.wrapping_add(1));
+            return (key.to_vec(), end);
+        }
+    }
+    // next prefix does not exist (e.g., 0xffff);
+    (key.to_vec(), vec![0])
+}
+
+/// Get the range of the keys not less than `key`
+pub(crate) fn from_key_range(key: &[u8]) -> (Vec<u8>, Vec<u8>) {
+    if key.is_empty() {
+        (vec![0], vec![0])
+    } else {
+        (key.to_vec(), vec![0])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_prefix_range() {
+        assert_eq!(prefix_range(b"foo"), (b"foo".to_vec(), b"fop".to_vec()));
+        assert_eq!(prefix_range(b"a\xff"), (b"a\xff".to_vec(), b"b".to_vec()));
+        assert_eq!(prefix_range(b"\xff\xff"), (b"\xff\xff".to_vec(), vec![0]));
+        assert_eq!(prefix_range(b""), (vec![0], vec![0]));
+        assert_eq!(from_key_range(b""), (vec![0], vec![0]));
+        assert_eq!(from_key_range(b"foo"), (b"foo".to_vec(), vec![0]));
+    }
+}
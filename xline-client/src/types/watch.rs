@@ -0,0 +1,211 @@
+pub use crate::rpc::{Event, EventType, FilterType, WatchResponse};
+use crate::{
+    rpc::{self, WatchCreateRequest, WatchRange, WatchValuePredicate},
+    types::{from_key_range, prefix_range},
+};
+
+/// Request to create a watcher
+#[derive(Debug, Clone, PartialEq)]
+pub struct WatchRequest {
+    /// Inner request
+    pub(crate) inner: WatchCreateRequest,
+}
+
+impl WatchRequest {
+    /// New `WatchRequest`
+    #[inline]
+    pub fn new(key: impl Into<Vec<u8>>) -> Self {
+        Self {
+            inner: WatchCreateRequest {
+                key: key.into(),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Watch the keys with the prefix `key`
+    #[inline]
+    #[must_use]
+    pub fn with_prefix(mut self) -> Self {
+        (self.inner.key, self.inner.range_end) = prefix_range(&self.inner.key);
+        self
+    }
+
+    /// Watch the keys not less than `key`
+    #[inline]
+    #[must_use]
+    pub fn with_from_key(mut self) -> Self {
+        (self.inner.key, self.inner.range_end) = from_key_range(&self.inner.key);
+        self
+    }
+
+    /// Set `range_end`
+    #[inline]
+    #[must_use]
+    pub fn with_range_end(mut self, range_end: impl Into<Vec<u8>>) -> Self {
+        self.inner.range_end = range_end.into();
+        self
+    }
+
+    /// Set `start_revision`, the events from it are sent
+    #[inline]
+    #[must_use]
+    pub fn with_start_revision(mut self, start_revision: i64) -> Self {
+        self.inner.start_revision = start_revision;
+        self
+    }
+
+    /// Set `progress_notify`, the server sends empty responses periodically if it's set
+    #[inline]
+    #[must_use]
+    pub fn with_progress_notify(mut self, progress_notify: bool) -> Self {
+        self.inner.progress_notify = progress_notify;
+        self
+    }
+
+    /// Set `filters`, the events of the types in `filters` are not sent
+    #[inline]
+    #[must_use]
+    #[allow(clippy::as_conversions)] // this case is always safe
+    pub fn with_filters(mut self, filters: impl IntoIterator<Item = FilterType>) -> Self {
+        self.inner.filters = filters.into_iter().map(|f| f as i32).collect();
+        self
+    }
+
+    /// Set `prev_kv`
+    #[inline]
+    #[must_use]
+    pub fn with_prev_kv(mut self, prev_kv: bool) -> Self {
+        self.inner.prev_kv = prev_kv;
+        self
+    }
+
+    /// Set `watch_id`, the server chooses an id if it's 0
+    #[inline]
+    #[must_use]
+    pub fn with_watch_id(mut self, watch_id: i64) -> Self {
+        self.inner.watch_id = watch_id;
+        self
+    }
+
+    /// Set `fragment`, large responses are split into fragments if it's set
+    #[inline]
+    #[must_use]
+    pub fn with_fragment(mut self, fragment: bool) -> Self {
+        self.inner.fragment = fragment;
+        self
+    }
+
+    /// Watch the range [`key`, `range_end`) too, so that one watcher watches several
+    /// ranges. It is an Xline extension, the ranges should be disjoint.
+    #[inline]
+    #[must_use]
+    pub fn with_extra_range(
+        mut self,
+        key: impl Into<Vec<u8>>,
+        range_end: impl Into<Vec<u8>>,
+    ) -> Self {
+        self.inner.extra_ranges.push(WatchRange {
+            key: key.into(),
+            range_end: range_end.into(),
+        });
+        self
+    }
+
+    /// Watch the keys with the prefix `prefix` too, see `with_extra_range`
+    #[inline]
+    #[must_use]
+    pub fn with_extra_prefix(self, prefix: impl AsRef<[u8]>) -> Self {
+        let (key, range_end) = prefix_range(prefix.as_ref());
+        self.with_extra_range(key, range_end)
+    }
+
+    /// Send only the put events whose values start with `prefix`. It is an Xline extension,
+    /// the value conditions are evaluated by the server, and the delete events are not
+    /// filtered by them.
+    #[inline]
+    #[must_use]
+    pub fn with_value_prefix(mut self, prefix: impl Into<Vec<u8>>) -> Self {
+        self.value_predicate().value_prefix = prefix.into();
+        self
+    }
+
+    /// Send only the put events whose values match the regular expression `regex`, see
+    /// `with_value_prefix`
+    #[inline]
+    #[must_use]
+    pub fn with_value_regex(mut self, regex: impl Into<String>) -> Self {
+        self.value_predicate().value_regex = regex.into();
+        self
+    }
+
+    /// Send only the put events whose values are in [`min`, `max`] bytes, `max` is unbounded
+    /// if it's 0, see `with_value_prefix`
+    #[inline]
+    #[must_use]
+    pub fn with_value_size(mut self, min: u64, max: u64) -> Self {
+        let predicate = self.value_predicate();
+        predicate.min_value_size = min;
+        predicate.max_value_size = max;
+        self
+    }
+
+    /// Get the value predicate to set
+    fn value_predicate(&mut self) -> &mut WatchValuePredicate {
+        self.inner
+            .value_predicate
+            .get_or_insert_with(WatchValuePredicate::default)
+    }
+
+    /// Set `durable`, the server persists the watcher and returns a resume token in the
+    /// created response if it's set. It is an Xline extension.
+    #[inline]
+    #[must_use]
+    pub fn with_durable(mut self, durable: bool) -> Self {
+        self.inner.durable = durable;
+        self
+    }
+
+    /// Resume a durable watcher by its resume token, the watcher continues from the last
+    /// revision delivered. It is an Xline extension, the watcher is resumed on the server it
+    /// was created on.
+    #[inline]
+    pub fn resume(resume_token: impl Into<String>) -> Self {
+        Self {
+            inner: WatchCreateRequest {
+                resume_token: resume_token.into(),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Get `key`
+    #[inline]
+    #[must_use]
+    pub fn key(&self) -> &[u8] {
+        &self.inner.key
+    }
+
+    /// Get `range_end`
+    #[inline]
+    #[must_use]
+    pub fn range_end(&self) -> &[u8] {
+        &self.inner.range_end
+    }
+}
+
+impl From<WatchRequest> for WatchCreateRequest {
+    #[inline]
+    fn from(req: WatchRequest) -> Self {
+        req.inner
+    }
+}
+
+impl From<WatchRequest> for rpc::WatchRequest {
+    #[inline]
+    fn from(req: WatchRequest) -> Self {
+        rpc::WatchRequest {
+            request_union: Some(rpc::RequestUnion::CreateRequest(req.inner)),
+        }
+    }
+}
@@ -1,13 +1,19 @@
 fn main() {
+    let mut prost_config = prost_build::Config::new();
+    // values are shared by the requests, the storage and the watch events without copying
+    let _ignore = prost_config.bytes([".mvccpb.KeyValue.value", ".etcdserverpb.PutRequest.value"]);
     tonic_build::configure()
         .type_attribute(".", "#[derive(serde::Deserialize, serde::Serialize)]")
-        .compile(
+        .compile_with_config(
+            prost_config,
             &[
                 "proto/kv.proto",
                 "proto/rpc.proto",
                 "proto/auth.proto",
                 "proto/v3lock.proto",
                 "proto/lease.proto",
+                "proto/health.proto",
+                "proto/admin.proto",
             ],
             &["proto"],
         )
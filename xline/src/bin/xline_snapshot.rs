@@ -0,0 +1,153 @@
+//! this binary saves the storage of a stopped Xline server to a snapshot file, restores
+//! a snapshot file into the data dir of a new member, and applies a shipped change log to
+//! the restored data dir. It also imports a snapshot of etcd into the data dir of a new
+//! member to migrate an etcd cluster to Xline.
+
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+use utils::config::default_curp_data_dir;
+use xline::storage::{change_log, etcd_snapshot, snapshot};
+
+/// Offline snapshot tool of Xline
+#[derive(Parser, Debug, Clone, PartialEq, Eq)]
+struct SnapshotArgs {
+    /// Sub command
+    #[clap(subcommand)]
+    command: Commands,
+}
+
+/// Types of sub command
+#[derive(Subcommand, Debug, Clone, PartialEq, Eq)]
+enum Commands {
+    /// Save the storage in the data dir to a snapshot file, the server must be stopped
+    Save {
+        /// Data dir of the storage
+        #[clap(long, value_parser)]
+        data_dir: PathBuf,
+        /// Path of the snapshot file
+        #[clap(value_parser)]
+        path: PathBuf,
+    },
+    /// Restore a snapshot file into a new data dir
+    Restore {
+        /// Path of the snapshot file
+        #[clap(value_parser)]
+        path: PathBuf,
+        /// Data dir of the storage, it must be empty
+        #[clap(long, value_parser)]
+        data_dir: PathBuf,
+        /// Data dir of the consensus log of the restored member, it must be empty
+        #[clap(long, value_parser, default_value_os_t = default_curp_data_dir())]
+        curp_data_dir: PathBuf,
+    },
+    /// Verify the checksums of a snapshot file
+    Verify {
+        /// Path of the snapshot file
+        #[clap(value_parser)]
+        path: PathBuf,
+    },
+    /// Apply a shipped change log file to a data dir restored from a snapshot
+    Apply {
+        /// Path of the change log file
+        #[clap(value_parser)]
+        path: PathBuf,
+        /// Data dir of the restored storage
+        #[clap(long, value_parser)]
+        data_dir: PathBuf,
+    },
+    /// Import an etcd snapshot, saved by `etcdctl snapshot save` or copied from
+    /// `member/snap/db`, into a new data dir
+    ImportEtcd {
+        /// Path of the etcd snapshot file
+        #[clap(value_parser)]
+        path: PathBuf,
+        /// Data dir of the storage, it must be empty
+        #[clap(long, value_parser)]
+        data_dir: PathBuf,
+        /// Data dir of the consensus log of the imported member, it must be empty
+        #[clap(long, value_parser, default_value_os_t = default_curp_data_dir())]
+        curp_data_dir: PathBuf,
+    },
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = SnapshotArgs::parse();
+    match args.command {
+        Commands::Save { data_dir, path } => {
+            let count = snapshot::save_to_file(&data_dir, &path)?;
+            println!("saved {count} entries to {}", path.display());
+        }
+        Commands::Restore {
+            path,
+            data_dir,
+            curp_data_dir,
+        } => {
+            let count = snapshot::restore_from_file(&path, &data_dir, &curp_data_dir)?;
+            println!("restored {count} entries to {}", data_dir.display());
+        }
+        Commands::Verify { path } => {
+            let count = snapshot::verify_file(&path)?;
+            println!("{} is valid, {count} entries", path.display());
+        }
+        Commands::Apply { path, data_dir } => {
+            let count = change_log::apply_file(&path, &data_dir)?;
+            println!(
+                "applied {count} change log entries to {}",
+                data_dir.display()
+            );
+        }
+        Commands::ImportEtcd {
+            path,
+            data_dir,
+            curp_data_dir,
+        } => {
+            let summary = etcd_snapshot::import_from_file(&path, &data_dir, &curp_data_dir)?;
+            println!(
+                "imported {} key-values at revision {}, {} leases, {} users and {} roles to {}",
+                summary.kvs,
+                summary.revision,
+                summary.leases,
+                summary.users,
+                summary.roles,
+                data_dir.display()
+            );
+            if !summary.users_to_reset.is_empty() {
+                println!(
+                    "passwords of the following users must be changed before they authenticate: {}",
+                    summary.users_to_reset.join(", ")
+                );
+            }
+            if summary.auth_enabled {
+                println!(
+                    "auth was enabled in etcd, enable it again after the passwords are changed"
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_args() {
+        let args = SnapshotArgs::parse_from([
+            "xline_snapshot",
+            "restore",
+            "backup.snap",
+            "--data-dir",
+            "/tmp/xline-data",
+        ]);
+        assert_eq!(
+            args.command,
+            Commands::Restore {
+                path: "backup.snap".into(),
+                data_dir: "/tmp/xline-data".into(),
+                curp_data_dir: default_curp_data_dir(),
+            }
+        );
+    }
+}
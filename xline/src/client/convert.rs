@@ -1,3 +1,4 @@
+use bytes::Bytes;
 use etcd_client::{
     DeleteOptions, DeleteResponse as EtcdDeleteResponse, GetOptions,
     GetResponse as EtcdGetResponse, LeaseGrantOptions,
@@ -112,7 +113,7 @@ impl From<EtcdPutResponse> for PutResponse {
                 create_revision: kv.create_revision(),
                 mod_revision: kv.mod_revision(),
                 version: kv.version(),
-                value: kv.value().to_vec(),
+                value: Bytes::copy_from_slice(kv.value()),
                 lease: kv.lease(),
             }),
         }
@@ -137,7 +138,7 @@ impl From<EtcdGetResponse> for RangeResponse {
                     create_revision: kv.create_revision(),
                     mod_revision: kv.mod_revision(),
                     version: kv.version(),
-                    value: kv.value().to_vec(),
+                    value: Bytes::copy_from_slice(kv.value()),
                     lease: kv.lease(),
                 })
                 .collect(),
@@ -166,7 +167,7 @@ impl From<EtcdDeleteResponse> for DeleteRangeResponse {
                     create_revision: kv.create_revision(),
                     mod_revision: kv.mod_revision(),
                     version: kv.version(),
-                    value: kv.value().to_vec(),
+                    value: Bytes::copy_from_slice(kv.value()),
                     lease: kv.lease(),
                 })
                 .collect(),
@@ -1,3 +1,5 @@
+use bytes::Bytes;
+
 pub use crate::rpc::{SortOrder, SortTarget};
 use crate::server::command::KeyRange;
 
@@ -15,7 +17,7 @@ impl PutRequest {
         Self {
             inner: crate::rpc::PutRequest {
                 key: key.into(),
-                value: value.into(),
+                value: Bytes::from(value.into()),
                 ..Default::default()
             },
         }
@@ -122,7 +122,8 @@ impl Client {
     /// If `CurpClient` or `EtcdClient` failed to send request
     #[inline]
     pub async fn range(&mut self, request: RangeRequest) -> Result<RangeResponse, ClientError> {
-        if self.use_curp_client {
+        // a serializable range is served by the member locally, no need to propose it
+        if self.use_curp_client && !request.serializable() {
             let key_ranges = vec![KeyRange {
                 start: request.key().to_vec(),
                 end: request.range_end().to_vec(),
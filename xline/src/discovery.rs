@@ -0,0 +1,71 @@
+use std::{collections::HashMap, net::SocketAddr};
+
+use anyhow::{anyhow, bail, Result};
+use tracing::info;
+use trust_dns_resolver::TokioAsyncResolver;
+
+/// Service and protocol of the SRV records which list the members, the records of a
+/// domain `example.com` are looked up at `_xline-server._tcp.example.com`
+pub const SRV_SERVICE: &str = "_xline-server._tcp";
+
+/// Discover the members of a cluster from the DNS SRV records of `domain`, like etcd's
+/// `--discovery-srv`. Each record points to a member, the name of the member is the first
+/// label of the target, eg. `node1` for `node1.example.com`, and its address is the first
+/// ip of the target with the port of the record.
+///
+/// # Errors
+///
+/// Return error if the records can't be looked up, a target can't be resolved, or two
+/// records point to members of the same name
+#[inline]
+pub async fn discover_members(domain: &str) -> Result<HashMap<String, String>> {
+    let resolver = TokioAsyncResolver::tokio_from_system_conf()?;
+    let srv_name = format!("{SRV_SERVICE}.{domain}");
+    let records = resolver
+        .srv_lookup(srv_name.as_str())
+        .await
+        .map_err(|e| anyhow!("failed to look up SRV records {srv_name}, {e}"))?;
+    let mut members = HashMap::new();
+    for record in records.iter() {
+        let target = record.target().to_utf8();
+        let host = target.trim_end_matches('.');
+        let name = member_name(host)?;
+        let ip = resolver
+            .lookup_ip(host)
+            .await
+            .map_err(|e| anyhow!("failed to resolve {host}, {e}"))?
+            .iter()
+            .next()
+            .ok_or_else(|| anyhow!("no address found for {host}"))?;
+        let addr = SocketAddr::new(ip, record.port()).to_string();
+        info!("discovered member {name} at {addr} from {host}");
+        if members.insert(name.to_owned(), addr).is_some() {
+            bail!("more than one SRV record of {srv_name} points to member {name}");
+        }
+    }
+    if members.is_empty() {
+        bail!("no SRV record found at {srv_name}");
+    }
+    Ok(members)
+}
+
+/// Get the member name of a SRV target, which is the first label of the target
+fn member_name(host: &str) -> Result<&str> {
+    host.split('.')
+        .next()
+        .filter(|label| !label.is_empty())
+        .ok_or_else(|| anyhow!("invalid SRV target {host}"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_member_name() {
+        assert_eq!(member_name("node1.example.com").unwrap(), "node1");
+        assert_eq!(member_name("node2").unwrap(), "node2");
+        assert!(member_name("").is_err());
+        assert!(member_name(".example.com").is_err());
+    }
+}
@@ -1,6 +1,9 @@
-use std::sync::Arc;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
 
-use parking_lot::Mutex;
+use sha2::{Digest, Sha256};
 
 use crate::{revision_number::RevisionNumber, rpc::ResponseHeader};
 
@@ -11,9 +14,9 @@ pub(crate) struct HeaderGenerator {
     cluster_id: u64,
     /// Id of the member
     member_id: u64,
-    /// term of curp
-    term: Arc<Mutex<u64>>,
-    /// revision of kv store
+    /// term of curp, it's read by every header, so it's an atomic instead of a lock
+    term: AtomicU64,
+    /// revision of kv store, it's the applied revision published by the update sequencer
     revision: Arc<RevisionNumber>,
 }
 
@@ -23,7 +26,7 @@ impl HeaderGenerator {
         Self {
             cluster_id,
             member_id,
-            term: Arc::new(Mutex::new(0)),
+            term: AtomicU64::new(0),
             revision: Arc::new(RevisionNumber::default()),
         }
     }
@@ -33,7 +36,7 @@ impl HeaderGenerator {
         ResponseHeader {
             cluster_id: self.cluster_id,
             member_id: self.member_id,
-            raft_term: *self.term.lock(),
+            raft_term: self.term.load(Ordering::Relaxed),
             revision: self.revision(),
         }
     }
@@ -43,15 +46,24 @@ impl HeaderGenerator {
         ResponseHeader {
             cluster_id: self.cluster_id,
             member_id: self.member_id,
-            raft_term: *self.term.lock(),
+            raft_term: self.term.load(Ordering::Relaxed),
             revision: -1,
         }
     }
 
+    /// Get the id of the cluster
+    pub(crate) fn cluster_id(&self) -> u64 {
+        self.cluster_id
+    }
+
+    /// Get the id of the member
+    pub(crate) fn member_id(&self) -> u64 {
+        self.member_id
+    }
+
     /// Set term
-    #[allow(dead_code)] // Will be used in the future
     pub(crate) fn set_term(&self, term: u64) {
-        *self.term.lock() = term;
+        self.term.store(term, Ordering::Relaxed);
     }
 
     /// Get revision
@@ -64,3 +76,66 @@ impl HeaderGenerator {
         Arc::clone(&self.revision)
     }
 }
+
+/// Generate the id of a member from its name and address, so that the id is stable across
+/// restarts and differs between the members
+pub(crate) fn gen_member_id(name: &str, addr: &str) -> u64 {
+    let mut hasher = Sha256::new();
+    hasher.update(name.as_bytes());
+    hasher.update(addr.as_bytes());
+    truncate_digest(&hasher.finalize())
+}
+
+/// Generate the id of a cluster from the ids of all its members
+pub(crate) fn gen_cluster_id(member_ids: impl IntoIterator<Item = u64>) -> u64 {
+    let mut member_ids: Vec<_> = member_ids.into_iter().collect();
+    member_ids.sort_unstable();
+    let mut hasher = Sha256::new();
+    for id in member_ids {
+        hasher.update(id.to_be_bytes());
+    }
+    truncate_digest(&hasher.finalize())
+}
+
+/// Take the first 8 bytes of a digest as an id
+fn truncate_digest(digest: &[u8]) -> u64 {
+    let mut id = [0; 8];
+    id.iter_mut()
+        .zip(digest.iter())
+        .for_each(|(byte, &d)| *byte = d);
+    u64::from_be_bytes(id)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn test_gen_ids() {
+        let all_members = HashMap::from([
+            ("node1".to_owned(), "127.0.0.1:2379".to_owned()),
+            ("node2".to_owned(), "127.0.0.1:2380".to_owned()),
+        ]);
+        let id1 = gen_member_id("node1", "127.0.0.1:2379");
+        assert_eq!(id1, gen_member_id("node1", "127.0.0.1:2379"));
+        assert_ne!(id1, gen_member_id("node2", "127.0.0.1:2380"));
+        let other_members = HashMap::from([("node1".to_owned(), "127.0.0.1:2379".to_owned())]);
+        let cluster_id = |members: &HashMap<String, String>| {
+            gen_cluster_id(members.iter().map(|(name, addr)| gen_member_id(name, addr)))
+        };
+        assert_ne!(cluster_id(&all_members), cluster_id(&other_members));
+    }
+
+    #[test]
+    fn test_header_fields() {
+        let header_gen = HeaderGenerator::new(1, 2);
+        header_gen.set_term(3);
+        let header = header_gen.gen_header();
+        assert_eq!(header.cluster_id, 1);
+        assert_eq!(header.member_id, 2);
+        assert_eq!(header.raft_term, 3);
+        assert_eq!(header_gen.gen_header_without_revision().raft_term, 3);
+    }
+}
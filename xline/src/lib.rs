@@ -139,6 +139,8 @@
 
 /// Xline client
 pub mod client;
+/// Discovery of the cluster members by DNS
+pub mod discovery;
 /// Header generator
 mod header_gen;
 /// Unique id generator
@@ -110,108 +110,417 @@
     clippy::multiple_crate_versions, // caused by the dependency, can't be fixed
 )]
 
-use std::{collections::HashMap, env, path::PathBuf, time::Duration};
+use std::{
+    collections::{HashMap, HashSet},
+    env, future,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 use anyhow::{anyhow, Result};
 use clap::Parser;
 use jsonwebtoken::{DecodingKey, EncodingKey};
-use opentelemetry::{global, runtime::Tokio, sdk::propagation::TraceContextPropagator};
+use opentelemetry::{
+    global,
+    runtime::Tokio,
+    sdk::{propagation::TraceContextPropagator, trace, Resource},
+    KeyValue,
+};
 use opentelemetry_contrib::trace::exporter::jaeger_json::JaegerJsonExporter;
+use opentelemetry_otlp::WithExportConfig;
 use tokio::fs;
 use tracing::{debug, error};
 use tracing_appender::non_blocking::WorkerGuard;
-use tracing_subscriber::{fmt::format, prelude::*};
+use tracing_subscriber::{fmt::format, prelude::*, reload, EnvFilter};
 use utils::{
     config::{
-        default_candidate_timeout_ticks, default_client_wait_synced_timeout,
-        default_follower_timeout_ticks, default_heartbeat_interval, default_log_level,
-        default_propose_timeout, default_retry_timeout, default_rotation, default_rpc_timeout,
-        default_server_wait_synced_timeout, file_appender, AuthConfig, ClientTimeout,
-        ClusterConfig, CurpConfig, LevelConfig, LogConfig, RotationConfig, StorageConfig,
-        TraceConfig, XlineServerConfig,
+        default_apply_stall_timeout, default_audit_events, default_auto_compact_retention,
+        default_backup_bucket, default_backup_endpoint, default_backup_interval,
+        default_backup_region, default_backup_retention, default_batch_max_size,
+        default_batch_window, default_candidate_timeout_ticks, default_client_wait_synced_timeout,
+        default_corrupt_check_interval, default_follower_timeout_ticks, default_heartbeat_interval,
+        default_initial_cluster_state, default_keepalive_interval, default_keepalive_timeout,
+        default_log_level, default_maintenance_timeout, default_max_concurrent_streams,
+        default_max_in_flight_maintenance_requests, default_max_recv_msg_size,
+        default_max_request_bytes, default_max_send_msg_size, default_max_txn_ops,
+        default_peer_tls_ca_file, default_peer_tls_cert_file, default_peer_tls_key_file,
+        default_probe_addr, default_propose_timeout, default_rate_limit_burst,
+        default_rate_limit_qps, default_read_timeout, default_retry_timeout, default_rotation,
+        default_rpc_timeout, default_server_wait_synced_timeout, default_slow_request_threshold,
+        default_snapshot_threshold, default_sync_interval, default_sync_policy, default_tls_addr,
+        default_tls_cert_file, default_tls_key_file, default_tls_reload_interval,
+        default_trash_retention, default_write_timeout, file_appender, AdmissionConfig,
+        AuditConfig, AuditEvent, AuthConfig, BackendConfig, BackupConfig, BatchConfig,
+        ClientTimeout, ClusterConfig, CompactConfig, CompressionConfig, CorruptCheckConfig,
+        CurpConfig, GrpcConfig, InitialClusterState, LeaseConfig, LevelConfig, LogConfig,
+        MaintenanceWindow, PeerTlsConfig, ProbeConfig, ProxyProtocolConfig, QuotaConfig,
+        RateLimitConfig, RequestLimitConfig, RotationConfig, ServerTimeout, StorageConfig,
+        SyncPolicy, TableConfig, TlsConfig, TraceConfig, TrashConfig, XlineServerConfig,
     },
-    parse_duration, parse_log_level, parse_members, parse_rotation,
+    parse_audit_event, parse_duration, parse_initial_cluster_state, parse_log_level,
+    parse_maintenance_window, parse_members, parse_rotation, parse_sync_policy,
+};
+use xline::{
+    discovery::discover_members,
+    server::{set_command_wire_version, LogFilterHandle, XlineServer},
+    storage::db::DBProxy,
 };
-use xline::{server::XlineServer, storage::db::DBProxy};
+
+/// Use jemalloc, so that the heap statistics can be read by the admin service
+#[cfg(feature = "profiling")]
+#[global_allocator]
+static GLOBAL: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
 
 /// Command line arguments
+#[allow(clippy::struct_excessive_bools)] // they are independent command line flags
 #[derive(Parser)]
 #[clap(author, version, about, long_about = None)]
 struct ServerArgs {
     /// Node name
-    #[clap(long)]
+    #[clap(long, env = "XLINE_NAME")]
     name: String,
     /// Cluster peers. eg: 192.168.x.x:8080 192.168.x.x:8080
-    #[clap(long,value_parser = parse_members)]
-    members: HashMap<String, String>,
+    #[clap(
+        long,
+        env = "XLINE_MEMBERS",
+        value_parser = parse_members,
+        required_unless_present = "discovery_srv",
+        conflicts_with = "discovery_srv"
+    )]
+    members: Option<HashMap<String, String>>,
+    /// Discover the cluster peers from the DNS SRV records `_xline-server._tcp.<domain>`
+    #[clap(long, env = "XLINE_DISCOVERY_SRV")]
+    discovery_srv: Option<String>,
     /// If node is leader
-    #[clap(long)]
+    #[clap(long, env = "XLINE_IS_LEADER")]
     is_leader: bool,
+    /// Whether the node bootstraps a new cluster or joins an existing one, it only matters
+    /// when the data dir is empty, eg: existing
+    #[clap(long, env = "XLINE_INITIAL_CLUSTER_STATE", value_parser = parse_initial_cluster_state, default_value_t = default_initial_cluster_state())]
+    initial_cluster_state: InitialClusterState,
+    /// Version of the wire format the commands are proposed in, eg: 0 while upgrading from
+    /// the releases without the versioned format
+    #[clap(long, env = "XLINE_COMMAND_WIRE_VERSION")]
+    command_wire_version: Option<u32>,
+    /// Members which replicate the data and serve the serializable reads and the watches,
+    /// but never campaign for the leadership or count toward the quorum, eg: `node4,node5`
+    #[clap(long, env = "XLINE_STANDBY_MEMBERS", value_delimiter = ',')]
+    standby_members: Vec<String>,
     /// Private key used to sign the token
-    #[clap(long)]
+    #[clap(long, env = "XLINE_AUTH_PRIVATE_KEY")]
     auth_private_key: Option<PathBuf>,
     /// Public key used to verify the token
-    #[clap(long)]
+    #[clap(long, env = "XLINE_AUTH_PUBLIC_KEY")]
     auth_public_key: Option<PathBuf>,
     /// Open jaeger offline
-    #[clap(long)]
+    #[clap(long, env = "XLINE_JAEGER_OFFLINE")]
     jaeger_offline: bool,
     /// output dir for jaeger offline
-    #[clap(long, default_value = "./jaeger_jsons")]
+    #[clap(
+        long,
+        env = "XLINE_JAEGER_OUTPUT_DIR",
+        default_value = "./jaeger_jsons"
+    )]
     jaeger_output_dir: PathBuf,
     /// Open jaeger online
-    #[clap(long)]
+    #[clap(long, env = "XLINE_JAEGER_ONLINE")]
     jaeger_online: bool,
     /// Trace level of jaeger
-    #[clap(long, value_parser = parse_log_level, default_value_t = default_log_level())]
+    #[clap(long, env = "XLINE_JAEGER_LEVEL", value_parser = parse_log_level, default_value_t = default_log_level())]
     jaeger_level: LevelConfig,
+    /// Endpoint of the OTLP collector, eg: `http://127.0.0.1:4317`
+    #[clap(long, env = "XLINE_OTLP_ENDPOINT")]
+    otlp_endpoint: Option<String>,
     /// Log file path
-    #[clap(long, default_value = "/var/log/xline")]
+    #[clap(long, env = "XLINE_LOG_FILE", default_value = "/var/log/xline")]
     log_file: PathBuf,
     /// Log rotate strategy, eg: never, hourly, daily
-    #[clap(long, value_parser = parse_rotation, default_value_t = default_rotation())]
+    #[clap(long, env = "XLINE_LOG_ROTATE", value_parser = parse_rotation, default_value_t = default_rotation())]
     log_rotate: RotationConfig,
     /// Log verbosity level, eg: trace, debug, info, warn, error
-    #[clap(long, value_parser = parse_log_level, default_value_t = default_log_level())]
+    #[clap(long, env = "XLINE_LOG_LEVEL", value_parser = parse_log_level, default_value_t = default_log_level())]
     log_level: LevelConfig,
     /// Heartbeat interval between curp server nodes
-    #[clap(long, value_parser = parse_duration)]
+    #[clap(long, env = "XLINE_HEARTBEAT_INTERVAL", value_parser = parse_duration)]
     heartbeat_interval: Option<Duration>,
     /// Curp wait sync timeout
-    #[clap(long, value_parser = parse_duration)]
+    #[clap(long, env = "XLINE_SERVER_WAIT_SYNCED_TIMEOUT", value_parser = parse_duration)]
     server_wait_synced_timeout: Option<Duration>,
     /// Curp propose retry timeout
-    #[clap(long, value_parser = parse_duration)]
+    #[clap(long, env = "XLINE_RETRY_TIMEOUT", value_parser = parse_duration)]
     retry_timeout: Option<Duration>,
     /// Curp rpc timeout
-    #[clap(long, value_parser = parse_duration)]
+    #[clap(long, env = "XLINE_RPC_TIMEOUT", value_parser = parse_duration)]
     rpc_timeout: Option<Duration>,
     /// Follower election timeout ticks
-    #[clap(long, default_value_t = default_follower_timeout_ticks())]
+    #[clap(long, env = "XLINE_FOLLOWER_TIMEOUT_TICKS", default_value_t = default_follower_timeout_ticks())]
     follower_timeout_ticks: u8,
     /// Candidate election timeout ticks
-    #[clap(long, default_value_t = default_candidate_timeout_ticks())]
+    #[clap(long, env = "XLINE_CANDIDATE_TIMEOUT_TICKS", default_value_t = default_candidate_timeout_ticks())]
     candidate_timeout_ticks: u8,
+    /// How many log entries a follower can fall behind before the leader sends it a snapshot
+    #[clap(long, env = "XLINE_SNAPSHOT_THRESHOLD", default_value_t = default_snapshot_threshold())]
+    snapshot_threshold: usize,
     /// Curp client wait synced timeout
-    #[clap(long, value_parser = parse_duration)]
+    #[clap(long, env = "XLINE_CLIENT_WAIT_SYNCED_TIMEOUT", value_parser = parse_duration)]
     client_wait_synced_timeout: Option<Duration>,
     /// Propose request timeout
-    #[clap(long, value_parser = parse_duration)]
+    #[clap(long, env = "XLINE_CLIENT_PROPOSE_TIMEOUT", value_parser = parse_duration)]
     client_propose_timeout: Option<Duration>,
     /// Curp client retry timeout
-    #[clap(long, value_parser = parse_duration)]
+    #[clap(long, env = "XLINE_CLIENT_RETRY_TIMEOUT", value_parser = parse_duration)]
     client_retry_timeout: Option<Duration>,
     /// Storage engine
-    #[clap(long)]
+    #[clap(long, env = "XLINE_STORAGE_ENGINE")]
     storage_engine: String,
     /// DB directory
-    #[clap(long)]
+    #[clap(long, env = "XLINE_DATA_DIR")]
     data_dir: PathBuf,
     /// Curp directory
+    #[clap(long, env = "XLINE_CURP_DIR")]
     curp_dir: Option<PathBuf>,
+    /// Interval of the grpc keepalive ping
+    #[clap(long, env = "XLINE_GRPC_KEEPALIVE_INTERVAL", value_parser = parse_duration)]
+    grpc_keepalive_interval: Option<Duration>,
+    /// Timeout of the grpc keepalive ping
+    #[clap(long, env = "XLINE_GRPC_KEEPALIVE_TIMEOUT", value_parser = parse_duration)]
+    grpc_keepalive_timeout: Option<Duration>,
+    /// Max size of a message the server can receive, in bytes
+    #[clap(long, env = "XLINE_GRPC_MAX_RECV_MSG_SIZE", default_value_t = default_max_recv_msg_size())]
+    grpc_max_recv_msg_size: usize,
+    /// Max size of a message the server can send, in bytes
+    #[clap(long, env = "XLINE_GRPC_MAX_SEND_MSG_SIZE", default_value_t = default_max_send_msg_size())]
+    grpc_max_send_msg_size: usize,
+    /// Max concurrent streams of each grpc connection
+    #[clap(long, env = "XLINE_GRPC_MAX_CONCURRENT_STREAMS", default_value_t = default_max_concurrent_streams())]
+    grpc_max_concurrent_streams: u32,
+    /// Requests allowed per second of each client, 0 means no limit
+    #[clap(long, env = "XLINE_RATE_LIMIT_QPS", default_value_t = default_rate_limit_qps())]
+    rate_limit_qps: u64,
+    /// Max requests a client can send in a burst
+    #[clap(long, env = "XLINE_RATE_LIMIT_BURST", default_value_t = default_rate_limit_burst())]
+    rate_limit_burst: u64,
+    /// Server side timeout of read requests
+    #[clap(long, env = "XLINE_READ_TIMEOUT", value_parser = parse_duration)]
+    read_timeout: Option<Duration>,
+    /// Server side timeout of write requests
+    #[clap(long, env = "XLINE_WRITE_TIMEOUT", value_parser = parse_duration)]
+    write_timeout: Option<Duration>,
+    /// Server side timeout of maintenance requests
+    #[clap(long, env = "XLINE_MAINTENANCE_TIMEOUT", value_parser = parse_duration)]
+    maintenance_timeout: Option<Duration>,
+    /// Requests which take longer than it are logged as slow requests
+    #[clap(long, env = "XLINE_SLOW_REQUEST_THRESHOLD", value_parser = parse_duration)]
+    slow_request_threshold: Option<Duration>,
+    /// Enable the audit log
+    #[clap(long, env = "XLINE_AUDIT_LOG")]
+    audit_log: bool,
+    /// Audit log file path
+    #[clap(long, env = "XLINE_AUDIT_LOG_FILE", default_value = "/var/log/xline")]
+    audit_log_file: PathBuf,
+    /// Audit log rotate strategy, eg: never, hourly, daily
+    #[clap(long, env = "XLINE_AUDIT_LOG_ROTATE", value_parser = parse_rotation, default_value_t = default_rotation())]
+    audit_log_rotate: RotationConfig,
+    /// Events recorded by the audit log, eg: `authentication,auth_config,write`
+    #[clap(long, env = "XLINE_AUDIT_EVENTS", value_parser = parse_audit_event, value_delimiter = ',')]
+    audit_events: Vec<AuditEvent>,
+    /// Upload snapshots to an S3 compatible object storage periodically
+    #[clap(long, env = "XLINE_BACKUP")]
+    backup: bool,
+    /// Endpoint of the object storage
+    #[clap(long, env = "XLINE_BACKUP_ENDPOINT", default_value_t = default_backup_endpoint())]
+    backup_endpoint: String,
+    /// Bucket to store the snapshots
+    #[clap(long, env = "XLINE_BACKUP_BUCKET", default_value_t = default_backup_bucket())]
+    backup_bucket: String,
+    /// Region of the bucket
+    #[clap(long, env = "XLINE_BACKUP_REGION", default_value_t = default_backup_region())]
+    backup_region: String,
+    /// Prefix of the object keys of the snapshots
+    #[clap(long, env = "XLINE_BACKUP_PREFIX", default_value = "")]
+    backup_prefix: String,
+    /// Use path style urls, which is required by `MinIO`
+    #[clap(long, env = "XLINE_BACKUP_PATH_STYLE")]
+    backup_path_style: bool,
+    /// Interval between two snapshots
+    #[clap(long, env = "XLINE_BACKUP_INTERVAL", value_parser = parse_duration)]
+    backup_interval: Option<Duration>,
+    /// Number of the latest snapshots to keep, 0 means keeping all
+    #[clap(long, env = "XLINE_BACKUP_RETENTION", default_value_t = default_backup_retention())]
+    backup_retention: usize,
+    /// Log the flushed operations to the change log for incremental backups
+    #[clap(long, env = "XLINE_BACKUP_CHANGE_LOG")]
+    backup_change_log: bool,
+    /// Propose small writes arriving within the batch window together
+    #[clap(long, env = "XLINE_BATCH")]
+    batch: bool,
+    /// Max number of writes in a batch
+    #[clap(long, env = "XLINE_BATCH_MAX_SIZE", default_value_t = default_batch_max_size())]
+    batch_max_size: usize,
+    /// How long a batch waits for more writes
+    #[clap(long, env = "XLINE_BATCH_WINDOW", value_parser = parse_duration)]
+    batch_window: Option<Duration>,
+    /// Serve the `/readyz` and `/livez` probes over HTTP
+    #[clap(long, env = "XLINE_PROBE")]
+    probe: bool,
+    /// Address the probes listen on
+    #[clap(long, env = "XLINE_PROBE_ADDR", default_value_t = default_probe_addr())]
+    probe_addr: String,
+    /// How long the committed log entries may stay unapplied before the server is considered dead
+    #[clap(long, env = "XLINE_APPLY_STALL_TIMEOUT", value_parser = parse_duration)]
+    apply_stall_timeout: Option<Duration>,
+    /// Max number of operations in a txn
+    #[clap(long, env = "XLINE_MAX_TXN_OPS", default_value_t = default_max_txn_ops())]
+    max_txn_ops: usize,
+    /// Max size of a request to be proposed in bytes
+    #[clap(long, env = "XLINE_MAX_REQUEST_BYTES", default_value_t = default_max_request_bytes())]
+    max_request_bytes: usize,
+    /// Max size of a written key in bytes, 0 means no limit
+    #[clap(long, env = "XLINE_MAX_KEY_BYTES", default_value_t = 0)]
+    max_key_bytes: usize,
+    /// Max size of a written value in bytes, 0 means no limit
+    #[clap(long, env = "XLINE_MAX_VALUE_BYTES", default_value_t = 0)]
+    max_value_bytes: usize,
+    /// Bytes a user may write before a warning is logged, 0 means no limit
+    #[clap(long, env = "XLINE_QUOTA_SOFT_BYTES", default_value_t = 0)]
+    quota_soft_bytes: u64,
+    /// Bytes a user may write before the writes are rejected, 0 means no limit
+    #[clap(long, env = "XLINE_QUOTA_HARD_BYTES", default_value_t = 0)]
+    quota_hard_bytes: u64,
+    /// Keys a user may write before a warning is logged, 0 means no limit
+    #[clap(long, env = "XLINE_QUOTA_SOFT_KEYS", default_value_t = 0)]
+    quota_soft_keys: u64,
+    /// Keys a user may write before the writes are rejected, 0 means no limit
+    #[clap(long, env = "XLINE_QUOTA_HARD_KEYS", default_value_t = 0)]
+    quota_hard_keys: u64,
+    /// Compress the responses of the kv service with gzip
+    #[clap(long, env = "XLINE_COMPRESS_KV")]
+    compress_kv: bool,
+    /// Compress the responses of the watch service with gzip
+    #[clap(long, env = "XLINE_COMPRESS_WATCH")]
+    compress_watch: bool,
+    /// Compress the responses of the lease service with gzip
+    #[clap(long, env = "XLINE_COMPRESS_LEASE")]
+    compress_lease: bool,
+    /// Compress the responses of the maintenance service with gzip
+    #[clap(long, env = "XLINE_COMPRESS_MAINTENANCE")]
+    compress_maintenance: bool,
+    /// Check the storage for corruption before serving
+    #[clap(long, env = "XLINE_INITIAL_CORRUPT_CHECK")]
+    initial_corrupt_check: bool,
+    /// Serve with the CORRUPT alarm raised instead of refusing to start when corruption is found
+    #[clap(long, env = "XLINE_SERVE_ON_CORRUPT")]
+    serve_on_corrupt: bool,
+    /// Periodically compare the kv hashes of all members on the leader
+    #[clap(long, env = "XLINE_PERIODIC_CORRUPT_CHECK")]
+    periodic_corrupt_check: bool,
+    /// Interval between two periodic corruption checks
+    #[clap(long, env = "XLINE_CORRUPT_CHECK_INTERVAL", value_parser = parse_duration)]
+    corrupt_check_interval: Option<Duration>,
+    /// Retain the history written in this period of time and compact the older history
+    /// automatically, eg. `3h`, 0 disables the automatic compaction
+    #[clap(long, env = "XLINE_AUTO_COMPACT_RETENTION", value_parser = parse_duration)]
+    auto_compact_retention: Option<Duration>,
+    /// Daily windows in UTC the automatic compaction is allowed to run in, eg:
+    /// `01:00-05:00,22:00-23:30`, it's allowed to run at any time if not given
+    #[clap(long, env = "XLINE_MAINTENANCE_WINDOWS", value_parser = parse_maintenance_window, value_delimiter = ',')]
+    maintenance_windows: Vec<MaintenanceWindow>,
+    /// Serve the clients over TLS on a listener of its own
+    #[clap(long, env = "XLINE_TLS")]
+    tls: bool,
+    /// Address the TLS listener listens on
+    #[clap(long, env = "XLINE_TLS_ADDR", default_value_t = default_tls_addr())]
+    tls_addr: String,
+    /// PEM file of the server certificate chain, reloaded when it changes
+    #[clap(long, env = "XLINE_TLS_CERT_FILE", default_value_os_t = default_tls_cert_file())]
+    tls_cert_file: PathBuf,
+    /// PEM file of the private key, reloaded when it changes
+    #[clap(long, env = "XLINE_TLS_KEY_FILE", default_value_os_t = default_tls_key_file())]
+    tls_key_file: PathBuf,
+    /// Interval between two checks of the certificate and key files for changes
+    #[clap(long, env = "XLINE_TLS_RELOAD_INTERVAL", value_parser = parse_duration)]
+    tls_reload_interval: Option<Duration>,
+    /// Connect to the peers over mutual TLS, and only accept the peers whose certificates
+    /// are valid for the address of a member
+    #[clap(long, env = "XLINE_PEER_TLS")]
+    peer_tls: bool,
+    /// PEM file of the member certificate chain, reloaded when it changes
+    #[clap(long, env = "XLINE_PEER_TLS_CERT_FILE", default_value_os_t = default_peer_tls_cert_file())]
+    peer_tls_cert_file: PathBuf,
+    /// PEM file of the private key of the member certificate, reloaded when it changes
+    #[clap(long, env = "XLINE_PEER_TLS_KEY_FILE", default_value_os_t = default_peer_tls_key_file())]
+    peer_tls_key_file: PathBuf,
+    /// PEM file of the CA certificates the peers are verified against
+    #[clap(long, env = "XLINE_PEER_TLS_CA_FILE", default_value_os_t = default_peer_tls_ca_file())]
+    peer_tls_ca_file: PathBuf,
+    /// Interval between two checks of the member certificate and key files for changes
+    #[clap(long, env = "XLINE_PEER_TLS_RELOAD_INTERVAL", value_parser = parse_duration)]
+    peer_tls_reload_interval: Option<Duration>,
+    /// Accept the HAProxy PROXY protocol headers on the client connections
+    #[clap(long, env = "XLINE_PROXY_PROTOCOL")]
+    proxy_protocol: bool,
+    /// Proxies whose PROXY protocol headers are accepted, eg: `10.0.0.0/8,192.168.1.10`,
+    /// every source is trusted if it's empty
+    #[clap(
+        long,
+        env = "XLINE_PROXY_PROTOCOL_TRUSTED_PROXIES",
+        value_delimiter = ','
+    )]
+    proxy_protocol_trusted_proxies: Vec<String>,
+    /// Max number of client connections, 0 means no limit
+    #[clap(long, env = "XLINE_MAX_CONNECTIONS", default_value_t = 0)]
+    max_connections: usize,
+    /// Max number of client connections from an IP address, 0 means no limit
+    #[clap(long, env = "XLINE_MAX_CONNECTIONS_PER_IP", default_value_t = 0)]
+    max_connections_per_ip: usize,
+    /// Max number of unary rpcs served at the same time, 0 means no limit
+    #[clap(long, env = "XLINE_MAX_IN_FLIGHT_REQUESTS", default_value_t = 0)]
+    max_in_flight_requests: usize,
+    /// Max number of heavy maintenance rpcs like `Snapshot` served at the same time, the
+    /// others wait for their turn, 0 means no limit
+    #[clap(long, env = "XLINE_MAX_IN_FLIGHT_MAINTENANCE_REQUESTS", default_value_t = default_max_in_flight_maintenance_requests())]
+    max_in_flight_maintenance_requests: usize,
+    /// Keep the deleted keys in the trash, so that they can be restored by `Undelete`
+    #[clap(long, env = "XLINE_TRASH")]
+    trash: bool,
+    /// How long the deleted keys are kept in the trash, eg. `24h`
+    #[clap(long, env = "XLINE_TRASH_RETENTION", value_parser = parse_duration)]
+    trash_retention: Option<Duration>,
+    /// When the writes to the backend are synced to the disk, eg: always, interval, never
+    #[clap(long, env = "XLINE_BACKEND_SYNC", value_parser = parse_sync_policy, default_value_t = default_sync_policy())]
+    backend_sync: SyncPolicy,
+    /// Interval between two syncs of the backend for the `interval` sync policy, eg. `100ms`
+    #[clap(long, env = "XLINE_BACKEND_SYNC_INTERVAL", value_parser = parse_duration)]
+    backend_sync_interval: Option<Duration>,
+    /// Size in bytes of the block cache shared by the tables of the backend, 0 means each
+    /// table has a default cache
+    #[clap(long, env = "XLINE_BACKEND_BLOCK_CACHE_SIZE", default_value_t = 0)]
+    backend_block_cache_size: usize,
+    /// Read and write the files of the backend by O_DIRECT, bypassing the page cache
+    #[clap(long, env = "XLINE_BACKEND_DIRECT_IO")]
+    backend_direct_io: bool,
+    /// Max bytes per second written by the background compactions of the backend, 0 means
+    /// no limit
+    #[clap(long, env = "XLINE_BACKEND_COMPACTION_RATE_LIMIT", default_value_t = 0)]
+    backend_compaction_rate_limit: u64,
+    /// Max time added to the expiry of a lease to spread the expiries, eg. `30s`
+    #[clap(long, env = "XLINE_LEASE_EXPIRY_JITTER", value_parser = parse_duration)]
+    lease_expiry_jitter: Option<Duration>,
+    /// Max number of the expired leases revoked every 500ms, 0 means no limit
+    #[clap(long, env = "XLINE_LEASE_REVOKE_BATCH_SIZE", default_value_t = 0)]
+    lease_revoke_batch_size: usize,
+    /// Min interval between two renewals of a lease, at most a third of its ttl, 0 means no
+    /// limit, eg. `1s`
+    #[clap(long, env = "XLINE_LEASE_MIN_KEEP_ALIVE_INTERVAL", value_parser = parse_duration)]
+    lease_min_keep_alive_interval: Option<Duration>,
+    /// Interval between two reconciliations of the keys and the leases, 0 means disabled,
+    /// eg. `5m`
+    #[clap(long, env = "XLINE_LEASE_RECONCILE_INTERVAL", value_parser = parse_duration)]
+    lease_reconcile_interval: Option<Duration>,
 }
 
 impl From<ServerArgs> for XlineServerConfig {
+    #[allow(clippy::too_many_lines)] // one statement for each section of the config
     fn from(args: ServerArgs) -> Self {
         let curp_config = CurpConfig::new(
             args.heartbeat_interval
@@ -227,10 +536,12 @@ impl From<ServerArgs> for XlineServerConfig {
                 path.push("curp");
                 path
             }),
+            args.snapshot_threshold,
         );
 
         let storage = match args.storage_engine.as_str() {
             "memory" => StorageConfig::Memory,
+            "memory_file" => StorageConfig::MemoryFile(args.data_dir),
             "rocksdb" => StorageConfig::RocksDB(args.data_dir),
             &_ => unreachable!(),
         };
@@ -245,10 +556,14 @@ impl From<ServerArgs> for XlineServerConfig {
         );
         let cluster = ClusterConfig::new(
             args.name,
-            args.members,
+            args.members.unwrap_or_default(),
+            args.discovery_srv,
             args.is_leader,
+            args.initial_cluster_state,
             curp_config,
             client_timeout,
+            args.command_wire_version,
+            args.standby_members,
         );
         let log = LogConfig::new(args.log_file, args.log_rotate, args.log_level);
         let trace = TraceConfig::new(
@@ -256,27 +571,184 @@ impl From<ServerArgs> for XlineServerConfig {
             args.jaeger_offline,
             args.jaeger_output_dir,
             args.jaeger_level,
+            args.otlp_endpoint,
+        );
+        // the external authentication is only set in the config file
+        let auth = AuthConfig::new(args.auth_public_key, args.auth_private_key, None);
+        let grpc = GrpcConfig::new(
+            args.grpc_keepalive_interval
+                .unwrap_or_else(default_keepalive_interval),
+            args.grpc_keepalive_timeout
+                .unwrap_or_else(default_keepalive_timeout),
+            args.grpc_max_recv_msg_size,
+            args.grpc_max_send_msg_size,
+            args.grpc_max_concurrent_streams,
+        );
+        let rate_limit = RateLimitConfig::new(args.rate_limit_qps, args.rate_limit_burst);
+        let server_timeout = ServerTimeout::new(
+            args.read_timeout.unwrap_or_else(default_read_timeout),
+            args.write_timeout.unwrap_or_else(default_write_timeout),
+            args.maintenance_timeout
+                .unwrap_or_else(default_maintenance_timeout),
+            args.slow_request_threshold
+                .unwrap_or_else(default_slow_request_threshold),
+        );
+        let audit = AuditConfig::new(
+            args.audit_log,
+            args.audit_log_file,
+            args.audit_log_rotate,
+            if args.audit_events.is_empty() {
+                default_audit_events()
+            } else {
+                args.audit_events
+            },
+        );
+        let backup = BackupConfig::new(
+            args.backup,
+            args.backup_endpoint,
+            args.backup_bucket,
+            args.backup_region,
+            args.backup_prefix,
+            args.backup_path_style,
+            args.backup_interval.unwrap_or_else(default_backup_interval),
+            args.backup_retention,
+            args.backup_change_log,
+        );
+        let batch = BatchConfig::new(
+            args.batch,
+            args.batch_max_size,
+            args.batch_window.unwrap_or_else(default_batch_window),
+        );
+        let probe = ProbeConfig::new(
+            args.probe,
+            args.probe_addr,
+            args.apply_stall_timeout
+                .unwrap_or_else(default_apply_stall_timeout),
+        );
+        let request_limit = RequestLimitConfig::new(
+            args.max_txn_ops,
+            args.max_request_bytes,
+            args.max_key_bytes,
+            args.max_value_bytes,
+        );
+        let quota = QuotaConfig::new(
+            args.quota_soft_bytes,
+            args.quota_hard_bytes,
+            args.quota_soft_keys,
+            args.quota_hard_keys,
         );
-        let auth = AuthConfig::new(args.auth_public_key, args.auth_private_key);
-        XlineServerConfig::new(cluster, storage, log, trace, auth)
+        let compression = CompressionConfig::new(
+            args.compress_kv,
+            args.compress_watch,
+            args.compress_lease,
+            args.compress_maintenance,
+        );
+        let corrupt_check = CorruptCheckConfig::new(
+            args.initial_corrupt_check,
+            args.serve_on_corrupt,
+            args.periodic_corrupt_check,
+            args.corrupt_check_interval
+                .unwrap_or_else(default_corrupt_check_interval),
+        );
+        let compact = CompactConfig::new(
+            args.auto_compact_retention
+                .unwrap_or_else(default_auto_compact_retention),
+            args.maintenance_windows,
+        );
+        let tls = TlsConfig::new(
+            args.tls,
+            args.tls_addr,
+            args.tls_cert_file,
+            args.tls_key_file,
+            args.tls_reload_interval
+                .unwrap_or_else(default_tls_reload_interval),
+        );
+        let peer_tls = PeerTlsConfig::new(
+            args.peer_tls,
+            args.peer_tls_cert_file,
+            args.peer_tls_key_file,
+            args.peer_tls_ca_file,
+            args.peer_tls_reload_interval
+                .unwrap_or_else(default_tls_reload_interval),
+        );
+        let proxy_protocol =
+            ProxyProtocolConfig::new(args.proxy_protocol, args.proxy_protocol_trusted_proxies);
+        let admission = AdmissionConfig::new(
+            args.max_connections,
+            args.max_connections_per_ip,
+            args.max_in_flight_requests,
+            args.max_in_flight_maintenance_requests,
+        );
+        let trash = TrashConfig::new(
+            args.trash,
+            args.trash_retention.unwrap_or_else(default_trash_retention),
+        );
+        // the namespaces and the table options are only set in the config file
+        let backend = BackendConfig::new(
+            TableConfig::default(),
+            TableConfig::default(),
+            TableConfig::default(),
+            args.backend_sync,
+            args.backend_sync_interval
+                .unwrap_or_else(default_sync_interval),
+            args.backend_block_cache_size,
+            args.backend_direct_io,
+            args.backend_compaction_rate_limit,
+        );
+        let lease = LeaseConfig::new(
+            args.lease_expiry_jitter.unwrap_or_default(),
+            args.lease_revoke_batch_size,
+            args.lease_min_keep_alive_interval.unwrap_or_default(),
+            args.lease_reconcile_interval.unwrap_or_default(),
+        );
+        XlineServerConfig::new(
+            cluster,
+            storage,
+            log,
+            trace,
+            auth,
+            grpc,
+            rate_limit,
+            server_timeout,
+            audit,
+            backup,
+            batch,
+            probe,
+            request_limit,
+            quota,
+            compression,
+            corrupt_check,
+            compact,
+            tls,
+            peer_tls,
+            proxy_protocol,
+            admission,
+            Vec::new(),
+            trash,
+            backend,
+            lease,
+        )
     }
 }
 
-/// init tracing subscriber
+/// init tracing subscriber, the filter of the log file can be changed at runtime by the
+/// returned handle
 fn init_subscriber(
     name: &str,
     log_config: &LogConfig,
     trace_config: &TraceConfig,
-) -> Result<WorkerGuard> {
+) -> Result<(WorkerGuard, LogFilterHandle)> {
     let file_appender = file_appender(*log_config.rotation(), log_config.path(), name);
 
     // `WorkerGuard` should be assigned in the `main` function or whatever the entrypoint of the program is.
     let (non_blocking, guard) = tracing_appender::non_blocking(file_appender);
 
+    let log_directives = log_config.level().to_string();
+    let (log_filter, log_filter_handle) = reload::Layer::new(EnvFilter::try_new(&log_directives)?);
     let log_file_layer = tracing_subscriber::fmt::layer()
         .event_format(format().compact())
         .with_writer(non_blocking)
-        .with_filter(*log_config.level());
+        .with_filter(log_filter);
 
     let jaeger_level = *trace_config.jaeger_level();
     let jaeger_online_layer = trace_config
@@ -305,16 +777,49 @@ fn init_subscriber(
         )
     });
 
-    let jaeger_fmt_layer = tracing_subscriber::fmt::layer()
-        .with_filter(tracing_subscriber::EnvFilter::from_default_env());
+    #[cfg(feature = "tokio-console")]
+    let console_layer = console_subscriber::spawn();
+    #[cfg(not(feature = "tokio-console"))]
+    let console_layer = tracing_subscriber::layer::Identity::new();
+
+    let otlp_layer = trace_config
+        .otlp_endpoint()
+        .as_ref()
+        .map(|endpoint| {
+            opentelemetry_otlp::new_pipeline()
+                .tracing()
+                .with_exporter(
+                    opentelemetry_otlp::new_exporter()
+                        .tonic()
+                        .with_endpoint(endpoint),
+                )
+                .with_trace_config(trace::config().with_resource(Resource::new(vec![
+                    KeyValue::new("service.name", name.to_owned()),
+                ])))
+                .install_batch(Tokio)
+        })
+        .transpose()?
+        .map(|tracer| {
+            tracing_opentelemetry::layer()
+                .with_tracer(tracer)
+                .with_filter(jaeger_level)
+        });
+
+    let jaeger_fmt_layer =
+        tracing_subscriber::fmt::layer().with_filter(EnvFilter::from_default_env());
 
     tracing_subscriber::registry()
         .with(log_file_layer)
         .with(jaeger_fmt_layer)
         .with(jaeger_online_layer)
         .with(jaeger_offline_layer)
+        .with(otlp_layer)
+        .with(console_layer)
         .try_init()?;
-    Ok(guard)
+    Ok((
+        guard,
+        LogFilterHandle::new(log_filter_handle, log_directives),
+    ))
 }
 
 /// Read key pair from file
@@ -351,26 +856,62 @@ async fn read_key_pair(
     Some((encoding_key, decoding_key))
 }
 
+/// Build the curp config with the standby members of the cluster, the standby members must
+/// be members of the cluster, a standby can't be the initial leader, and at least one
+/// member must be able to campaign for the leadership
+fn standby_curp_config(
+    cluster_config: &ClusterConfig,
+    members: &HashMap<String, String>,
+    is_leader: bool,
+) -> Result<CurpConfig> {
+    let standbys: HashSet<String> = cluster_config.standby_members().iter().cloned().collect();
+    if let Some(unknown) = standbys.iter().find(|name| !members.contains_key(*name)) {
+        return Err(anyhow!(
+            "standby member {unknown} not found in cluster peers"
+        ));
+    }
+    if is_leader && standbys.contains(cluster_config.name()) {
+        return Err(anyhow!("a standby member can't be the leader"));
+    }
+    if standbys.len() >= members.len() {
+        return Err(anyhow!("at least one member should not be a standby"));
+    }
+    let mut curp_config = cluster_config.curp_config().clone();
+    curp_config.standbys = standbys;
+    Ok(curp_config)
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
+    // the failpoints listed in the `FAILPOINTS` environment variable are set up, eg.
+    // `FAILPOINTS=xline_after_flush_before_notify=panic` to crash before a notification
+    #[cfg(feature = "failpoints")]
+    let _scenario = fail::FailScenario::setup();
     global::set_text_map_propagator(TraceContextPropagator::new());
-    let config: XlineServerConfig = if env::args_os().len() == 1 {
-        let path =
-            env::var("XLINE_SERVER_CONFIG").unwrap_or_else(|_| "/etc/xline_server.conf".to_owned());
-        let config_file = fs::read_to_string(&path).await?;
-        toml::from_str(&config_file)?
-    } else {
-        let server_args: ServerArgs = ServerArgs::parse();
-        server_args.into()
-    };
+    let config_file_path =
+        env::var("XLINE_SERVER_CONFIG").unwrap_or_else(|_| "/etc/xline_server.conf".to_owned());
+    // Without arguments, the config file is read if it exists, otherwise the flags are read
+    // from the `XLINE_*` environment variables. Only a config file can be reloaded.
+    let (config, config_path): (XlineServerConfig, _) =
+        if env::args_os().len() == 1 && Path::new(&config_file_path).exists() {
+            let config_file = fs::read_to_string(&config_file_path).await?;
+            (
+                XlineServerConfig::from_toml_with_env(&config_file, env::vars())?,
+                Some(PathBuf::from(config_file_path)),
+            )
+        } else {
+            let server_args: ServerArgs = ServerArgs::parse();
+            (server_args.into(), None)
+        };
 
     let storage_config = config.storage();
     let log_config = config.log();
     let trace_config = config.trace();
     let cluster_config = config.cluster();
     let auth_config = config.auth();
+    let backup_config = config.backup();
 
-    let _guard = init_subscriber(cluster_config.name(), log_config, trace_config)?;
+    let (_guard, log_filter) = init_subscriber(cluster_config.name(), log_config, trace_config)?;
 
     let key_pair = read_key_pair(
         auth_config.auth_private_key().clone(),
@@ -378,8 +919,12 @@ async fn main() -> Result<()> {
     )
     .await;
 
-    let self_addr = cluster_config
-        .members()
+    let members = match *cluster_config.discovery_srv() {
+        Some(ref domain) if cluster_config.members().is_empty() => discover_members(domain).await?,
+        Some(_) => return Err(anyhow!("members and discovery_srv can't be set together")),
+        None => cluster_config.members().clone(),
+    };
+    let self_addr = members
         .get(cluster_config.name())
         .ok_or_else(|| {
             anyhow!(
@@ -389,24 +934,49 @@ async fn main() -> Result<()> {
         })?
         .parse()?;
 
+    if let Some(version) = *cluster_config.command_wire_version() {
+        set_command_wire_version(version)?;
+    }
     let is_leader = cluster_config.is_leader();
+    let initial_cluster_state = *cluster_config.initial_cluster_state();
+    if *is_leader && initial_cluster_state == InitialClusterState::Existing {
+        return Err(anyhow!(
+            "a node joining an existing cluster can't be the initial leader"
+        ));
+    }
+    let curp_config = standby_curp_config(cluster_config, &members, *is_leader)?;
     debug!("name = {:?}", cluster_config.name());
     debug!("server_addr = {:?}", self_addr);
-    debug!("cluster_peers = {:?}", cluster_config.members());
+    debug!("cluster_peers = {:?}", members);
 
-    let db_proxy = DBProxy::open(storage_config)?;
+    let db_proxy = DBProxy::open_with_backend(
+        storage_config,
+        config.backend(),
+        *backup_config.change_log(),
+    )?;
     let server = XlineServer::new(
-        cluster_config.name().clone(),
-        cluster_config.members().clone(),
-        *is_leader,
+        &config,
+        members,
+        curp_config,
         key_pair,
-        cluster_config.curp_config().clone(),
-        *cluster_config.client_timeout(),
+        Some(log_filter),
         db_proxy,
     )
-    .await;
+    .await?;
     debug!("{:?}", server);
-    server.start(self_addr).await?;
+    let reload = async {
+        if let Some(path) = config_path {
+            server.reload_config_on_hangup(path, config.clone()).await;
+        }
+        future::pending::<()>().await;
+    };
+    #[allow(clippy::integer_arithmetic)] // Introduced by tokio::select!
+    {
+        tokio::select! {
+            res = server.start(self_addr) => res?,
+            () = reload => {}
+        }
+    }
     global::shutdown_tracer_provider();
     Ok(())
 }
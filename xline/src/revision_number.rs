@@ -1,5 +1,7 @@
 use std::sync::atomic::{AtomicI64, Ordering};
 
+use tokio::sync::watch;
+
 /// Revision number
 #[derive(Debug)]
 pub(crate) struct RevisionNumber(AtomicI64);
@@ -32,3 +34,60 @@ impl Default for RevisionNumber {
         RevisionNumber::new(1)
     }
 }
+
+/// Revision number that can be waited for until it reaches a given value
+#[derive(Debug)]
+pub(crate) struct RevisionNumberWatcher(watch::Sender<i64>);
+
+impl RevisionNumberWatcher {
+    /// Create a new watcher
+    pub(crate) fn new(rev: i64) -> Self {
+        Self(watch::channel(rev).0)
+    }
+
+    /// Get the revision number
+    pub(crate) fn get(&self) -> i64 {
+        *self.0.borrow()
+    }
+
+    /// Set the revision number and wake up the waiters it reaches
+    pub(crate) fn set(&self, rev: i64) {
+        let _prev = self.0.send_replace(rev);
+    }
+
+    /// Wait until the revision number is not less than `rev`, return the current one
+    pub(crate) async fn wait(&self, rev: i64) -> i64 {
+        let mut rx = self.0.subscribe();
+        loop {
+            let current = *rx.borrow_and_update();
+            if current >= rev {
+                return current;
+            }
+            // the sender is owned by `self`, so it's never closed here
+            let _ignore = rx.changed().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{sync::Arc, time::Duration};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_wait_revision() {
+        let watcher = Arc::new(RevisionNumberWatcher::new(1));
+        assert_eq!(watcher.wait(1).await, 1);
+
+        let waiter = tokio::spawn({
+            let watcher = Arc::clone(&watcher);
+            async move { watcher.wait(3).await }
+        });
+        watcher.set(2);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!waiter.is_finished());
+        watcher.set(4);
+        assert_eq!(waiter.await.unwrap(), 4);
+    }
+}
@@ -32,40 +32,70 @@ mod leasepb {
     tonic::include_proto!("leasepb");
 }
 
+mod healthpb {
+    tonic::include_proto!("grpc.health.v1");
+}
+
+mod xlineadminpb {
+    tonic::include_proto!("xlineadminpb");
+}
+
+use prost::Message;
 use serde::{Deserialize, Serialize};
 
 pub use self::etcdserverpb::range_request::{SortOrder, SortTarget};
 pub(crate) use self::{
-    authpb::{permission::Type, Permission, Role, User},
+    authpb::{permission::Type, ApiKey, Permission, Role, User},
     etcdserverpb::{
+        alarm_request::AlarmAction,
         auth_server::{Auth, AuthServer},
+        cluster_client::ClusterClient,
+        cluster_server::{Cluster, ClusterServer},
         compare::{CompareResult, CompareTarget, TargetUnion},
         kv_client::KvClient,
         kv_server::{Kv, KvServer},
         lease_client::LeaseClient,
+        lease_observe_response::EventType as LeaseEventType,
         lease_server::{Lease, LeaseServer},
+        maintenance_client::MaintenanceClient,
+        maintenance_server::{Maintenance, MaintenanceServer},
         request_op::Request,
         response_op::Response,
         watch_client::WatchClient,
         watch_request::RequestUnion,
         watch_server::{Watch, WatchServer},
-        AuthDisableRequest, AuthDisableResponse, AuthEnableRequest, AuthEnableResponse,
-        AuthRoleAddRequest, AuthRoleAddResponse, AuthRoleDeleteRequest, AuthRoleDeleteResponse,
-        AuthRoleGetRequest, AuthRoleGetResponse, AuthRoleGrantPermissionRequest,
-        AuthRoleGrantPermissionResponse, AuthRoleListRequest, AuthRoleListResponse,
-        AuthRoleRevokePermissionRequest, AuthRoleRevokePermissionResponse, AuthStatusRequest,
-        AuthStatusResponse, AuthUserAddRequest, AuthUserAddResponse, AuthUserChangePasswordRequest,
-        AuthUserChangePasswordResponse, AuthUserDeleteRequest, AuthUserDeleteResponse,
-        AuthUserGetRequest, AuthUserGetResponse, AuthUserGrantRoleRequest,
+        AlarmMember, AlarmRequest, AlarmResponse, AlarmType, AuthApiKeyAddRequest,
+        AuthApiKeyAddResponse, AuthApiKeyListRequest, AuthApiKeyListResponse,
+        AuthApiKeyRevokeRequest, AuthApiKeyRevokeResponse, AuthDisableRequest, AuthDisableResponse,
+        AuthEnableRequest, AuthEnableResponse, AuthRoleAddRequest, AuthRoleAddResponse,
+        AuthRoleDeleteRequest, AuthRoleDeleteResponse, AuthRoleGetRequest, AuthRoleGetResponse,
+        AuthRoleGrantPermissionRequest, AuthRoleGrantPermissionResponse, AuthRoleListRequest,
+        AuthRoleListResponse, AuthRoleRevokePermissionRequest, AuthRoleRevokePermissionResponse,
+        AuthStatusRequest, AuthStatusResponse, AuthUserAddRequest, AuthUserAddResponse,
+        AuthUserChangePasswordRequest, AuthUserChangePasswordResponse, AuthUserDeleteRequest,
+        AuthUserDeleteResponse, AuthUserGetRequest, AuthUserGetResponse, AuthUserGrantRoleRequest,
         AuthUserGrantRoleResponse, AuthUserListRequest, AuthUserListResponse,
         AuthUserRevokeRoleRequest, AuthUserRevokeRoleResponse, AuthenticateRequest,
-        AuthenticateResponse, CompactionRequest, CompactionResponse, Compare, DeleteRangeRequest,
-        DeleteRangeResponse, LeaseGrantRequest, LeaseGrantResponse, LeaseKeepAliveRequest,
-        LeaseKeepAliveResponse, LeaseLeasesRequest, LeaseLeasesResponse, LeaseRevokeRequest,
-        LeaseRevokeResponse, LeaseStatus, LeaseTimeToLiveRequest, LeaseTimeToLiveResponse,
-        PutRequest, PutResponse, RangeRequest, RangeResponse, RequestOp, ResponseHeader,
-        ResponseOp, TxnRequest, TxnResponse, WatchCancelRequest, WatchCreateRequest, WatchRequest,
-        WatchResponse,
+        AuthenticateResponse, CompactionRequest, CompactionResponse, Compare, DefragmentRequest,
+        DefragmentResponse, DeleteRangeRequest, DeleteRangeResponse, HashKvRequest, HashKvResponse,
+        HashRequest, HashResponse, KeyHistoryRequest, KeyHistoryResponse, LeaseGrantRequest,
+        LeaseGrantResponse, LeaseKeepAliveRequest, LeaseKeepAliveResponse, LeaseLeasesRequest,
+        LeaseLeasesResponse, LeaseModifyTtlRequest, LeaseModifyTtlResponse, LeaseObserveRequest,
+        LeaseObserveResponse, LeaseRevokeRequest, LeaseRevokeResponse, LeaseStatus,
+        LeaseTimeToLiveRequest, LeaseTimeToLiveResponse, Member, MemberAddRequest,
+        MemberAddResponse, MemberListRequest, MemberListResponse, MemberPromoteRequest,
+        MemberPromoteResponse, MemberRemoveRequest, MemberRemoveResponse, MemberUpdateRequest,
+        MemberUpdateResponse, MoveLeaderRequest, MoveLeaderResponse, PutRequest, PutResponse,
+        RangeRequest, RangeResponse, RequestOp, ResponseHeader, ResponseOp, RevisionAtRequest,
+        RevisionAtResponse, SnapshotRequest, SnapshotResponse, StatusRequest, StatusResponse,
+        TableStats, TableStatsRequest, TableStatsResponse, TxnRequest, TxnResponse,
+        UndeleteRequest, UndeleteResponse, WaitRevisionRequest, WaitRevisionResponse,
+        WatchCancelRequest, WatchCreateRequest, WatchRequest, WatchResponse, WatchValuePredicate,
+    },
+    healthpb::{
+        health_check_response::ServingStatus,
+        health_server::{Health, HealthServer},
+        HealthCheckRequest, HealthCheckResponse,
     },
     leasepb::Lease as PbLease,
     mvccpb::{event::EventType, Event, KeyValue},
@@ -73,6 +103,13 @@ pub(crate) use self::{
         lock_server::{Lock, LockServer},
         LockRequest, LockResponse, UnlockRequest, UnlockResponse,
     },
+    xlineadminpb::{
+        admin_server::{Admin, AdminServer},
+        change_log_op::OpType,
+        ChangeLogEntry, ChangeLogOp, CpuProfileRequest, CpuProfileResponse, GetLogFilterRequest,
+        GetLogFilterResponse, HeapStatsRequest, HeapStatsResponse, SetLogFilterRequest,
+        SetLogFilterResponse, TrimChangeLogRequest, TrimChangeLogResponse, WatchChangeLogRequest,
+    },
 };
 
 impl User {
@@ -139,10 +176,20 @@ pub(crate) enum RequestWrapper {
     AuthUserRevokeRoleRequest(AuthUserRevokeRoleRequest),
     /// `AuthenticateRequest`
     AuthenticateRequest(AuthenticateRequest),
+    /// `AuthApiKeyAddRequest`
+    AuthApiKeyAddRequest(AuthApiKeyAddRequest),
+    /// `AuthApiKeyRevokeRequest`
+    AuthApiKeyRevokeRequest(AuthApiKeyRevokeRequest),
+    /// `AuthApiKeyListRequest`
+    AuthApiKeyListRequest(AuthApiKeyListRequest),
     /// `LeaseGrantRequest`
     LeaseGrantRequest(LeaseGrantRequest),
     /// `LeaseRevokeRequest`
     LeaseRevokeRequest(LeaseRevokeRequest),
+    /// `LeaseModifyTtlRequest`
+    LeaseModifyTtlRequest(LeaseModifyTtlRequest),
+    /// `MemberUpdateRequest`
+    MemberUpdateRequest(MemberUpdateRequest),
 }
 
 /// Wrapper for responses
@@ -193,10 +240,20 @@ pub(crate) enum ResponseWrapper {
     AuthUserRevokeRoleResponse(AuthUserRevokeRoleResponse),
     /// `AuthenticateResponse`
     AuthenticateResponse(AuthenticateResponse),
+    /// `AuthApiKeyAddResponse`
+    AuthApiKeyAddResponse(AuthApiKeyAddResponse),
+    /// `AuthApiKeyRevokeResponse`
+    AuthApiKeyRevokeResponse(AuthApiKeyRevokeResponse),
+    /// `AuthApiKeyListResponse`
+    AuthApiKeyListResponse(AuthApiKeyListResponse),
     /// `LeaseGrantResponse`
     LeaseGrantResponse(LeaseGrantResponse),
     /// `LeaseRevokeResponse`
     LeaseRevokeResponse(LeaseRevokeResponse),
+    /// `LeaseModifyTtlResponse`
+    LeaseModifyTtlResponse(LeaseModifyTtlResponse),
+    /// `MemberUpdateResponse`
+    MemberUpdateResponse(MemberUpdateResponse),
 }
 
 impl ResponseWrapper {
@@ -225,8 +282,13 @@ impl ResponseWrapper {
             ResponseWrapper::AuthUserListResponse(ref mut resp) => &mut resp.header,
             ResponseWrapper::AuthUserRevokeRoleResponse(ref mut resp) => &mut resp.header,
             ResponseWrapper::AuthenticateResponse(ref mut resp) => &mut resp.header,
+            ResponseWrapper::AuthApiKeyAddResponse(ref mut resp) => &mut resp.header,
+            ResponseWrapper::AuthApiKeyRevokeResponse(ref mut resp) => &mut resp.header,
+            ResponseWrapper::AuthApiKeyListResponse(ref mut resp) => &mut resp.header,
             ResponseWrapper::LeaseGrantResponse(ref mut resp) => &mut resp.header,
             ResponseWrapper::LeaseRevokeResponse(ref mut resp) => &mut resp.header,
+            ResponseWrapper::LeaseModifyTtlResponse(ref mut resp) => &mut resp.header,
+            ResponseWrapper::MemberUpdateResponse(ref mut resp) => &mut resp.header,
         };
         if let Some(ref mut header) = *header {
             header.revision = revision;
@@ -243,6 +305,8 @@ pub(crate) enum RequestBackend {
     Auth,
     /// Lease backend
     Lease,
+    /// Cluster backend
+    Cluster,
 }
 
 impl RequestWrapper {
@@ -270,10 +334,14 @@ impl RequestWrapper {
             | RequestWrapper::AuthUserGrantRoleRequest(_)
             | RequestWrapper::AuthUserListRequest(_)
             | RequestWrapper::AuthUserRevokeRoleRequest(_)
-            | RequestWrapper::AuthenticateRequest(_) => RequestBackend::Auth,
-            RequestWrapper::LeaseGrantRequest(_) | RequestWrapper::LeaseRevokeRequest(_) => {
-                RequestBackend::Lease
-            }
+            | RequestWrapper::AuthenticateRequest(_)
+            | RequestWrapper::AuthApiKeyAddRequest(_)
+            | RequestWrapper::AuthApiKeyRevokeRequest(_)
+            | RequestWrapper::AuthApiKeyListRequest(_) => RequestBackend::Auth,
+            RequestWrapper::LeaseGrantRequest(_)
+            | RequestWrapper::LeaseRevokeRequest(_)
+            | RequestWrapper::LeaseModifyTtlRequest(_) => RequestBackend::Lease,
+            RequestWrapper::MemberUpdateRequest(_) => RequestBackend::Cluster,
         }
     }
 
@@ -286,6 +354,7 @@ impl RequestWrapper {
                 | RequestWrapper::AuthRoleListRequest(_)
                 | RequestWrapper::AuthUserGetRequest(_)
                 | RequestWrapper::AuthUserListRequest(_)
+                | RequestWrapper::AuthApiKeyListRequest(_)
         )
     }
 
@@ -303,11 +372,60 @@ impl RequestWrapper {
     pub(crate) fn is_lease_request(&self) -> bool {
         self.backend() == RequestBackend::Lease
     }
+
+    /// Check if this request is a cluster request
+    pub(crate) fn is_cluster_request(&self) -> bool {
+        self.backend() == RequestBackend::Cluster
+    }
+
+    /// Check if this request may change the storage, a txn is a write if any of its
+    /// operations is, and an authentication is not a write
+    pub(crate) fn is_write_request(&self) -> bool {
+        #[allow(clippy::wildcard_enum_match_arm)]
+        match *self {
+            RequestWrapper::RangeRequest(_) | RequestWrapper::AuthenticateRequest(_) => false,
+            RequestWrapper::TxnRequest(ref req) => Self::is_write_txn(req),
+            _ => !self.is_auth_read_request(),
+        }
+    }
+
+    /// Check if any operation of a txn is a write
+    fn is_write_txn(txn: &TxnRequest) -> bool {
+        txn.success
+            .iter()
+            .chain(txn.failure.iter())
+            .any(|op| match op.request {
+                Some(Request::RequestPut(_) | Request::RequestDeleteRange(_)) => true,
+                Some(Request::RequestTxn(ref req)) => Self::is_write_txn(req),
+                Some(Request::RequestRange(_)) | None => false,
+            })
+    }
 }
 
 /// impl `From` trait for all request types
 macro_rules! impl_from_requests {
     ($($req:ident),*) => {
+        impl RequestWrapper {
+            /// Encode the request in the wire format of the commands, which is the name of
+            /// its type and its protobuf encoding, so that the fields added by a newer
+            /// version are skipped by an older one
+            pub(crate) fn encode_to_wire(&self) -> (&'static str, Vec<u8>) {
+                match *self {
+                    $(RequestWrapper::$req(ref req) => (stringify!($req), req.encode_to_vec()),)*
+                }
+            }
+
+            /// Decode a request encoded by `encode_to_wire`
+            pub(crate) fn decode_from_wire(request_type: &str, buf: &[u8]) -> Result<Self, String> {
+                match request_type {
+                    $(stringify!($req) => $req::decode(buf)
+                        .map(RequestWrapper::$req)
+                        .map_err(|e| format!("invalid {request_type}, {e}")),)*
+                    _ => Err(format!("unknown request type {request_type}")),
+                }
+            }
+        }
+
         $(
             impl From<$req> for RequestWrapper {
                 fn from(req: $req) -> Self {
@@ -372,8 +490,13 @@ impl_from_requests!(
     AuthUserListRequest,
     AuthUserRevokeRoleRequest,
     AuthenticateRequest,
+    AuthApiKeyAddRequest,
+    AuthApiKeyRevokeRequest,
+    AuthApiKeyListRequest,
     LeaseGrantRequest,
-    LeaseRevokeRequest
+    LeaseRevokeRequest,
+    LeaseModifyTtlRequest,
+    MemberUpdateRequest
 );
 
 impl_from_responses!(
@@ -399,8 +522,13 @@ impl_from_responses!(
     AuthUserListResponse,
     AuthUserRevokeRoleResponse,
     AuthenticateResponse,
+    AuthApiKeyAddResponse,
+    AuthApiKeyRevokeResponse,
+    AuthApiKeyListResponse,
     LeaseGrantResponse,
-    LeaseRevokeResponse
+    LeaseRevokeResponse,
+    LeaseModifyTtlResponse,
+    MemberUpdateResponse
 );
 
 impl From<RequestOp> for RequestWrapper {
@@ -415,6 +543,34 @@ impl From<RequestOp> for RequestWrapper {
     }
 }
 
+impl From<RequestWrapper> for RequestOp {
+    fn from(request_wrapper: RequestWrapper) -> Self {
+        #[allow(clippy::wildcard_enum_match_arm)]
+        let request = match request_wrapper {
+            RequestWrapper::RangeRequest(req) => Request::RequestRange(req),
+            RequestWrapper::PutRequest(req) => Request::RequestPut(req),
+            RequestWrapper::DeleteRangeRequest(req) => Request::RequestDeleteRange(req),
+            RequestWrapper::TxnRequest(req) => Request::RequestTxn(req),
+            _ => panic!("wrong request type"),
+        };
+        RequestOp {
+            request: Some(request),
+        }
+    }
+}
+
+impl From<ResponseOp> for ResponseWrapper {
+    fn from(response_op: ResponseOp) -> Self {
+        match response_op.response {
+            Some(Response::ResponseRange(resp)) => ResponseWrapper::RangeResponse(resp),
+            Some(Response::ResponsePut(resp)) => ResponseWrapper::PutResponse(resp),
+            Some(Response::ResponseDeleteRange(resp)) => ResponseWrapper::DeleteRangeResponse(resp),
+            Some(Response::ResponseTxn(resp)) => ResponseWrapper::TxnResponse(resp),
+            None => panic!("response is not set"),
+        }
+    }
+}
+
 impl From<ResponseWrapper> for ResponseOp {
     fn from(response_wrapper: ResponseWrapper) -> Self {
         #[allow(clippy::wildcard_enum_match_arm)]
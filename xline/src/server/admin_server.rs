@@ -0,0 +1,442 @@
+use std::{fmt::Debug, sync::Arc, time::Duration};
+
+use parking_lot::Mutex;
+use tokio::sync::{mpsc, watch};
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::{debug, info};
+use tracing_subscriber::{reload, EnvFilter};
+
+use super::{auth_server::get_token, profiling};
+use crate::{
+    rpc::{
+        Admin, ChangeLogEntry, CpuProfileRequest, CpuProfileResponse, GetLogFilterRequest,
+        GetLogFilterResponse, HeapStatsRequest, HeapStatsResponse, SetLogFilterRequest,
+        SetLogFilterResponse, TrimChangeLogRequest, TrimChangeLogResponse, WatchChangeLogRequest,
+    },
+    storage::{storage_api::StorageApi, AuthStore},
+};
+
+/// Default duration of a CPU profile
+const DEFAULT_CPU_PROFILE_DURATION: Duration = Duration::from_secs(10);
+/// Max duration of a CPU profile, the sampling thread isn't stopped by a canceled request
+const MAX_CPU_PROFILE_DURATION: Duration = Duration::from_secs(5 * 60);
+/// Default sampling frequency of a CPU profile
+const DEFAULT_CPU_PROFILE_FREQUENCY: i32 = 99;
+/// Default channel size
+const CHANNEL_SIZE: usize = 128;
+/// Max number of change log entries read from the storage at a time
+const CHANGE_LOG_READ_LIMIT: usize = 64;
+
+/// Function that replaces the filter of the log file
+type ReloadFn = dyn Fn(EnvFilter) -> Result<(), reload::Error> + Send + Sync;
+
+/// Handle to change the filter of the log file at runtime
+#[derive(Clone)]
+pub struct LogFilterHandle {
+    /// Replaces the filter of the log file layer
+    reload: Arc<ReloadFn>,
+    /// Directives of the current filter
+    directives: Arc<Mutex<String>>,
+}
+
+impl Debug for LogFilterHandle {
+    #[inline]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LogFilterHandle")
+            .field("directives", &*self.directives.lock())
+            .finish_non_exhaustive()
+    }
+}
+
+impl LogFilterHandle {
+    /// New `LogFilterHandle` from the reload handle of the log filter and the directives
+    /// the filter is built from
+    #[inline]
+    #[must_use]
+    pub fn new<S>(handle: reload::Handle<EnvFilter, S>, directives: String) -> Self
+    where
+        S: 'static,
+    {
+        Self {
+            reload: Arc::new(move |filter| handle.reload(filter)),
+            directives: Arc::new(Mutex::new(directives)),
+        }
+    }
+
+    /// Get the directives of the current filter
+    fn directives(&self) -> String {
+        self.directives.lock().clone()
+    }
+
+    /// Replace the filter with a new one built from the directives, return the previous directives
+    pub(crate) fn set(&self, directives: &str) -> Result<String, tonic::Status> {
+        let filter = EnvFilter::try_new(directives).map_err(|e| {
+            tonic::Status::invalid_argument(format!("invalid log filter {directives:?}: {e}"))
+        })?;
+        let mut current = self.directives.lock();
+        (self.reload)(filter)
+            .map_err(|e| tonic::Status::internal(format!("failed to reload log filter: {e}")))?;
+        Ok(std::mem::replace(&mut *current, directives.to_owned()))
+    }
+}
+
+/// Admin Server, serves operations on this server which are not part of the etcd API
+#[derive(Debug)]
+pub(crate) struct AdminServer<S>
+where
+    S: StorageApi,
+{
+    /// Auth storage
+    auth_storage: Arc<AuthStore<S>>,
+    /// Persistent storage
+    persistent: Arc<S>,
+    /// Handle to change the log filter, `None` if the log filter can't be changed
+    log_filter: Option<LogFilterHandle>,
+    /// Shutdown signal receiver
+    shutdown_rx: watch::Receiver<bool>,
+}
+
+impl<S> AdminServer<S>
+where
+    S: StorageApi,
+{
+    /// New `AdminServer`
+    pub(crate) fn new(
+        auth_storage: Arc<AuthStore<S>>,
+        persistent: Arc<S>,
+        log_filter: Option<LogFilterHandle>,
+        shutdown_rx: watch::Receiver<bool>,
+    ) -> Self {
+        Self {
+            auth_storage,
+            persistent,
+            log_filter,
+            shutdown_rx,
+        }
+    }
+
+    /// Check if the request is sent by a root user
+    fn check_admin<T>(&self, request: &tonic::Request<T>) -> Result<(), tonic::Status> {
+        self.auth_storage
+            .check_admin_token(get_token(request.metadata()).as_deref())
+            .map_err(|e| tonic::Status::permission_denied(e.to_string()))
+    }
+
+    /// Get the log filter handle
+    fn log_filter(&self) -> Result<&LogFilterHandle, tonic::Status> {
+        self.log_filter
+            .as_ref()
+            .ok_or_else(|| tonic::Status::failed_precondition("log filter is not reloadable"))
+    }
+
+    /// Send the change log entries from `next` to the stream until the stream is closed or
+    /// the server shuts down, new entries are sent as soon as they are appended
+    #[allow(clippy::integer_arithmetic)] // Introduced by tokio::select!
+    async fn send_change_log(
+        persistent: Arc<S>,
+        mut next: u64,
+        mut last_index_rx: watch::Receiver<u64>,
+        mut shutdown_rx: watch::Receiver<bool>,
+        tx: mpsc::Sender<Result<ChangeLogEntry, tonic::Status>>,
+    ) {
+        loop {
+            // the last index is read before the entries, if the entry `next` was appended
+            // but can't be read, it must have been trimmed
+            let last_index = *last_index_rx.borrow_and_update();
+            let entries = match persistent.read_change_log(next, CHANGE_LOG_READ_LIMIT) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    let _ignore = tx.send(Err(tonic::Status::internal(e.to_string()))).await;
+                    return;
+                }
+            };
+            if entries.is_empty() && next <= last_index {
+                let status = tonic::Status::out_of_range(format!(
+                    "change log entry {next} has been trimmed"
+                ));
+                let _ignore = tx.send(Err(status)).await;
+                return;
+            }
+            for entry in entries {
+                next = entry.index.saturating_add(1);
+                if tx.send(Ok(entry)).await.is_err() {
+                    return;
+                }
+            }
+            if next <= last_index {
+                continue;
+            }
+            tokio::select! {
+                res = last_index_rx.changed() => {
+                    if res.is_err() {
+                        return;
+                    }
+                }
+                _ = shutdown_rx.changed() => return,
+                () = tx.closed() => return,
+            }
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl<S> Admin for AdminServer<S>
+where
+    S: StorageApi,
+{
+    /// `GetLogFilter` gets the current filter of the log file.
+    async fn get_log_filter(
+        &self,
+        request: tonic::Request<GetLogFilterRequest>,
+    ) -> Result<tonic::Response<GetLogFilterResponse>, tonic::Status> {
+        self.check_admin(&request)?;
+        Ok(tonic::Response::new(GetLogFilterResponse {
+            filter: self.log_filter()?.directives(),
+        }))
+    }
+
+    /// `SetLogFilter` replaces the filter of the log file without restarting the server.
+    async fn set_log_filter(
+        &self,
+        request: tonic::Request<SetLogFilterRequest>,
+    ) -> Result<tonic::Response<SetLogFilterResponse>, tonic::Status> {
+        self.check_admin(&request)?;
+        let filter = &request.get_ref().filter;
+        let prev_filter = self.log_filter()?.set(filter)?;
+        info!("log filter changed from {prev_filter:?} to {filter:?}");
+        Ok(tonic::Response::new(SetLogFilterResponse { prev_filter }))
+    }
+
+    /// `CpuProfile` samples the call stacks of the server for a while and returns the profile.
+    async fn cpu_profile(
+        &self,
+        request: tonic::Request<CpuProfileRequest>,
+    ) -> Result<tonic::Response<CpuProfileResponse>, tonic::Status> {
+        self.check_admin(&request)?;
+        let req = request.into_inner();
+        let duration = if req.seconds == 0 {
+            DEFAULT_CPU_PROFILE_DURATION
+        } else {
+            Duration::from_secs(req.seconds)
+        };
+        if duration > MAX_CPU_PROFILE_DURATION {
+            return Err(tonic::Status::invalid_argument(format!(
+                "cpu profile can't be longer than {} seconds",
+                MAX_CPU_PROFILE_DURATION.as_secs()
+            )));
+        }
+        let frequency = if req.frequency <= 0 {
+            DEFAULT_CPU_PROFILE_FREQUENCY
+        } else {
+            req.frequency
+        };
+        info!("start cpu profiling for {duration:?} at {frequency}Hz");
+        let profile = profiling::cpu_profile(duration, frequency).await?;
+        Ok(tonic::Response::new(CpuProfileResponse { profile }))
+    }
+
+    /// `HeapStats` gets the statistics of the heap.
+    async fn heap_stats(
+        &self,
+        request: tonic::Request<HeapStatsRequest>,
+    ) -> Result<tonic::Response<HeapStatsResponse>, tonic::Status> {
+        self.check_admin(&request)?;
+        Ok(tonic::Response::new(profiling::heap_stats()?))
+    }
+
+    /// Server streaming response type for the `WatchChangeLog` method.
+    type WatchChangeLogStream = ReceiverStream<Result<ChangeLogEntry, tonic::Status>>;
+
+    /// `WatchChangeLog` sends the change log entries from the start index, and then sends
+    /// new entries as they are appended.
+    async fn watch_change_log(
+        &self,
+        request: tonic::Request<WatchChangeLogRequest>,
+    ) -> Result<tonic::Response<Self::WatchChangeLogStream>, tonic::Status> {
+        self.check_admin(&request)?;
+        let last_index_rx = self
+            .persistent
+            .subscribe_change_log()
+            .ok_or_else(|| tonic::Status::failed_precondition("change log is disabled"))?;
+        let start = request.into_inner().start_index.max(1);
+        debug!("watch change log from {start}");
+        let (tx, rx) = mpsc::channel(CHANNEL_SIZE);
+        let _hd = tokio::spawn(Self::send_change_log(
+            Arc::clone(&self.persistent),
+            start,
+            last_index_rx,
+            self.shutdown_rx.clone(),
+            tx,
+        ));
+        Ok(tonic::Response::new(ReceiverStream::new(rx)))
+    }
+
+    /// `TrimChangeLog` deletes the change log entries before the end index.
+    async fn trim_change_log(
+        &self,
+        request: tonic::Request<TrimChangeLogRequest>,
+    ) -> Result<tonic::Response<TrimChangeLogResponse>, tonic::Status> {
+        self.check_admin(&request)?;
+        if self.persistent.subscribe_change_log().is_none() {
+            return Err(tonic::Status::failed_precondition("change log is disabled"));
+        }
+        let end_index = request.into_inner().end_index;
+        self.persistent
+            .trim_change_log(end_index)
+            .map_err(|e| tonic::Status::internal(e.to_string()))?;
+        info!("change log is trimmed before {end_index}");
+        Ok(tonic::Response::new(TrimChangeLogResponse {}))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use curp::cmd::ProposeId;
+    use tokio_stream::StreamExt;
+    use tracing_subscriber::prelude::*;
+    use utils::config::StorageConfig;
+
+    use super::*;
+    use crate::{
+        header_gen::HeaderGenerator,
+        storage::{
+            db::{DBProxy, WriteOp},
+            namespace::NamespaceStore,
+        },
+    };
+
+    fn init_server(
+        log_filter: Option<LogFilterHandle>,
+        change_log: bool,
+    ) -> (AdminServer<DBProxy>, Arc<DBProxy>, watch::Sender<bool>) {
+        let (lease_cmd_tx, _) = mpsc::channel(1);
+        let db = DBProxy::open_with_change_log(&StorageConfig::Memory, change_log).unwrap();
+        let auth_storage = Arc::new(AuthStore::new(
+            lease_cmd_tx,
+            None,
+            Arc::new(HeaderGenerator::new(0, 0)),
+            Arc::clone(&db),
+            Arc::new(NamespaceStore::new(vec![])),
+        ));
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let server = AdminServer::new(auth_storage, Arc::clone(&db), log_filter, shutdown_rx);
+        (server, db, shutdown_tx)
+    }
+
+    fn flush_applied_index(db: &DBProxy, index: u64) -> Result<(), Box<dyn std::error::Error>> {
+        let id = ProposeId::new(format!("test-id-{index}"));
+        db.buffer_op(&id, WriteOp::PutAppliedIndex(index));
+        db.flush(&id)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_set_log_filter() -> Result<(), Box<dyn std::error::Error>> {
+        let (filter, handle) = reload::Layer::new(EnvFilter::new("info"));
+        let _subscriber = tracing_subscriber::registry().with(filter);
+        let (server, _db, _shutdown_tx) =
+            init_server(Some(LogFilterHandle::new(handle, "info".to_owned())), false);
+
+        let resp = server
+            .set_log_filter(tonic::Request::new(SetLogFilterRequest {
+                filter: "info,xline::storage::lease_store=debug".to_owned(),
+            }))
+            .await?;
+        assert_eq!(resp.into_inner().prev_filter, "info");
+        let resp = server
+            .get_log_filter(tonic::Request::new(GetLogFilterRequest {}))
+            .await?;
+        assert_eq!(
+            resp.into_inner().filter,
+            "info,xline::storage::lease_store=debug"
+        );
+
+        let err = server
+            .set_log_filter(tonic::Request::new(SetLogFilterRequest {
+                filter: "xline=loud".to_owned(),
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+        Ok(())
+    }
+
+    #[cfg(not(feature = "profiling"))]
+    #[tokio::test]
+    async fn test_profiling_is_unimplemented_without_feature() {
+        let (server, _db, _shutdown_tx) = init_server(None, false);
+        let err = server
+            .heap_stats(tonic::Request::new(HeapStatsRequest {}))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::Unimplemented);
+    }
+
+    #[tokio::test]
+    async fn test_cpu_profile_duration_is_capped() {
+        let (server, _db, _shutdown_tx) = init_server(None, false);
+        let err = server
+            .cpu_profile(tonic::Request::new(CpuProfileRequest {
+                seconds: 301,
+                frequency: 0,
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+    }
+
+    #[tokio::test]
+    async fn test_log_filter_not_reloadable() {
+        let (server, _db, _shutdown_tx) = init_server(None, false);
+        let err = server
+            .get_log_filter(tonic::Request::new(GetLogFilterRequest {}))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::FailedPrecondition);
+    }
+
+    #[tokio::test]
+    async fn test_watch_change_log() -> Result<(), Box<dyn std::error::Error>> {
+        let (server, db, _shutdown_tx) = init_server(None, true);
+        flush_applied_index(&db, 1)?;
+        flush_applied_index(&db, 2)?;
+
+        let mut stream = server
+            .watch_change_log(tonic::Request::new(WatchChangeLogRequest {
+                start_index: 2,
+            }))
+            .await?
+            .into_inner();
+        let entry = stream.next().await.unwrap()?;
+        assert_eq!(entry.index, 2);
+        flush_applied_index(&db, 3)?;
+        let entry = stream.next().await.unwrap()?;
+        assert_eq!(entry.index, 3);
+        assert_eq!(entry.ops.len(), 1);
+
+        let _resp = server
+            .trim_change_log(tonic::Request::new(TrimChangeLogRequest { end_index: 3 }))
+            .await?;
+        let mut stream = server
+            .watch_change_log(tonic::Request::new(WatchChangeLogRequest {
+                start_index: 1,
+            }))
+            .await?
+            .into_inner();
+        let err = stream.next().await.unwrap().unwrap_err();
+        assert_eq!(err.code(), tonic::Code::OutOfRange);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_change_log_disabled() {
+        let (server, _db, _shutdown_tx) = init_server(None, false);
+        let err = server
+            .watch_change_log(tonic::Request::new(WatchChangeLogRequest {
+                start_index: 1,
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::FailedPrecondition);
+    }
+}
@@ -0,0 +1,366 @@
+use std::{
+    collections::{HashMap, HashSet},
+    net::{IpAddr, SocketAddr},
+    pin::Pin,
+    sync::Arc,
+};
+
+use hyper::body::{Bytes, HttpBody, SizeHint};
+use parking_lot::Mutex;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tonic::{
+    body::BoxBody,
+    codegen::{http, BoxFuture, Context, Poll, Service},
+    transport::NamedService,
+};
+use utils::config::AdmissionConfig;
+
+use super::timeout_service::{service_and_method, RpcType};
+
+/// Numbers of the client connections
+#[derive(Debug, Default)]
+struct ConnectionCounts {
+    /// Total number of the client connections
+    total: usize,
+    /// Number of the connections from each address
+    per_ip: HashMap<IpAddr, usize>,
+}
+
+/// Limiter of the client connections, the connections beyond the limits are closed once
+/// they are accepted. The connections from the members are never limited, so that a flood
+/// of clients can't cut the peers off.
+#[derive(Debug)]
+pub(super) struct ConnectionLimiter {
+    /// Max number of the client connections, 0 means no limit
+    max_total: usize,
+    /// Max number of the client connections from an address, 0 means no limit
+    max_per_ip: usize,
+    /// Addresses of the members
+    members: HashSet<IpAddr>,
+    /// Numbers of the current connections
+    counts: Mutex<ConnectionCounts>,
+}
+
+impl ConnectionLimiter {
+    /// New `ConnectionLimiter`, return `None` if the connections are not limited. The
+    /// members given by host names rather than addresses are limited like the clients.
+    pub(super) fn new<'a>(
+        config: &AdmissionConfig,
+        member_addrs: impl IntoIterator<Item = &'a str>,
+    ) -> Option<Arc<Self>> {
+        if *config.max_connections() == 0 && *config.max_connections_per_ip() == 0 {
+            return None;
+        }
+        let members = member_addrs
+            .into_iter()
+            .filter_map(|addr| addr.parse::<SocketAddr>().ok())
+            .map(|addr| addr.ip())
+            .collect();
+        Some(Arc::new(Self {
+            max_total: *config.max_connections(),
+            max_per_ip: *config.max_connections_per_ip(),
+            members,
+            counts: Mutex::new(ConnectionCounts::default()),
+        }))
+    }
+
+    /// Admit a connection from the address, return `None` if it's beyond the limits,
+    /// otherwise the connection is counted until the permit is dropped
+    pub(super) fn admit(self: &Arc<Self>, ip: IpAddr) -> Option<ConnectionPermit> {
+        if self.members.contains(&ip) {
+            return Some(ConnectionPermit { limiter: None, ip });
+        }
+        let mut counts = self.counts.lock();
+        let from_ip = counts.per_ip.get(&ip).copied().unwrap_or(0);
+        if (self.max_total != 0 && counts.total >= self.max_total)
+            || (self.max_per_ip != 0 && from_ip >= self.max_per_ip)
+        {
+            return None;
+        }
+        counts.total = counts.total.saturating_add(1);
+        let _prev = counts.per_ip.insert(ip, from_ip.saturating_add(1));
+        Some(ConnectionPermit {
+            limiter: Some(Arc::clone(self)),
+            ip,
+        })
+    }
+
+    /// Stop counting a closed connection
+    fn release(&self, ip: IpAddr) {
+        let mut counts = self.counts.lock();
+        counts.total = counts.total.saturating_sub(1);
+        if let Some(from_ip) = counts.per_ip.get_mut(&ip) {
+            *from_ip = from_ip.saturating_sub(1);
+            if *from_ip == 0 {
+                let _ignore = counts.per_ip.remove(&ip);
+            }
+        }
+    }
+}
+
+/// An admitted connection, it's released when the connection is closed
+#[derive(Debug)]
+pub(super) struct ConnectionPermit {
+    /// Limiter which admitted the connection, `None` for the connections of the members
+    limiter: Option<Arc<ConnectionLimiter>>,
+    /// Address of the client
+    ip: IpAddr,
+}
+
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        if let Some(ref limiter) = self.limiter {
+            limiter.release(self.ip);
+        }
+    }
+}
+
+/// Lane an rpc is served in, the lanes are limited separately, so that the heavy
+/// maintenance can't starve the clients, and the clients can't starve the maintenance
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Lane {
+    /// Unary rpcs of the clients, the ones beyond the limit fail at once
+    Client,
+    /// Heavy maintenance rpcs, eg. `Snapshot`, `HashKV`, `Compact` and the member
+    /// management, the ones beyond the limit wait for their turn
+    Maintenance,
+    /// Rpcs that are never limited: the streams and the rpcs that may block for an
+    /// unbounded time, like `Lock`, and the light maintenance rpcs like `Status` and
+    /// `Alarm`, which should be answered even when the server is overloaded
+    Exempt,
+}
+
+impl Lane {
+    /// Get the lane of an rpc by its request path, eg. `/etcdserverpb.Maintenance/Snapshot`
+    pub(super) fn from_path(path: &str) -> Self {
+        match service_and_method(path) {
+            (
+                "etcdserverpb.Maintenance",
+                "Snapshot" | "Hash" | "HashKV" | "Defragment" | "MoveLeader" | "Downgrade",
+            )
+            | ("etcdserverpb.KV", "Compact")
+            | ("etcdserverpb.Cluster", _)
+            | ("xlineadminpb.Admin", "CpuProfile" | "TrimChangeLog") => Self::Maintenance,
+            _ => match RpcType::from_path(path) {
+                RpcType::Read | RpcType::Write => Self::Client,
+                RpcType::Maintenance | RpcType::Unbounded => Self::Exempt,
+            },
+        }
+    }
+}
+
+/// Permits of the lanes, shared by all services
+#[derive(Debug, Clone)]
+pub(crate) struct RequestLanes {
+    /// Permits of the client lane, `None` means no limit
+    client: Option<Arc<Semaphore>>,
+    /// Permits of the maintenance lane, `None` means no limit
+    maintenance: Option<Arc<Semaphore>>,
+}
+
+impl RequestLanes {
+    /// New `RequestLanes`
+    pub(crate) fn new(config: &AdmissionConfig) -> Self {
+        let permits = |limit: usize| (limit != 0).then(|| Arc::new(Semaphore::new(limit)));
+        Self {
+            client: permits(*config.max_in_flight_requests()),
+            maintenance: permits(*config.max_in_flight_maintenance_requests()),
+        }
+    }
+
+    /// Permits of a lane, `None` if it's not limited
+    fn permits(&self, lane: Lane) -> Option<&Arc<Semaphore>> {
+        match lane {
+            Lane::Client => self.client.as_ref(),
+            Lane::Maintenance => self.maintenance.as_ref(),
+            Lane::Exempt => None,
+        }
+    }
+}
+
+/// Body of a response which holds the permit of its lane until the body is sent, so that a
+/// streaming rpc like `Snapshot` is counted until it finishes
+#[derive(Debug)]
+struct PermitBody {
+    /// Inner body
+    inner: BoxBody,
+    /// Permit of the lane
+    _permit: OwnedSemaphorePermit,
+}
+
+impl HttpBody for PermitBody {
+    type Data = Bytes;
+    type Error = tonic::Status;
+
+    #[inline]
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        Pin::new(&mut self.inner).poll_data(cx)
+    }
+
+    #[inline]
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<http::HeaderMap>, Self::Error>> {
+        Pin::new(&mut self.inner).poll_trailers(cx)
+    }
+
+    #[inline]
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    #[inline]
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// Service that schedules the rpcs in their lanes. A client rpc beyond the limit of the
+/// client lane fails with `RESOURCE_EXHAUSTED` at once instead of piling up, while a heavy
+/// maintenance rpc beyond the limit of the maintenance lane waits for a running one to
+/// finish. The exempt rpcs are never limited.
+#[derive(Debug, Clone)]
+pub(crate) struct InFlightLimitService<T> {
+    /// Inner grpc service
+    inner: T,
+    /// Permits of the lanes
+    lanes: RequestLanes,
+}
+
+impl<T> InFlightLimitService<T> {
+    /// New `InFlightLimitService`
+    pub(crate) fn new(inner: T, lanes: RequestLanes) -> Self {
+        Self { inner, lanes }
+    }
+}
+
+impl<T, B> Service<http::Request<B>> for InFlightLimitService<T>
+where
+    T: Service<http::Request<B>, Response = http::Response<BoxBody>>,
+    T::Future: Send + 'static,
+{
+    type Response = T::Response;
+    type Error = T::Error;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    #[inline]
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        let lane = Lane::from_path(req.uri().path());
+        let Some(permits) = self.lanes.permits(lane).map(Arc::clone) else {
+            return Box::pin(self.inner.call(req));
+        };
+        // the inner future does nothing until it's polled, so the rpc doesn't start before
+        // it gets a permit
+        let fut = self.inner.call(req);
+        match lane {
+            Lane::Maintenance => Box::pin(async move {
+                let Ok(permit) = permits.acquire_owned().await else {
+                    let status = tonic::Status::unavailable("server is shutting down");
+                    return Ok(status.to_http());
+                };
+                let res = fut.await?;
+                Ok(res.map(|inner| {
+                    BoxBody::new(PermitBody {
+                        inner,
+                        _permit: permit,
+                    })
+                }))
+            }),
+            Lane::Client | Lane::Exempt => {
+                let Ok(permit) = permits.try_acquire_owned() else {
+                    let status = tonic::Status::resource_exhausted("too many requests in flight");
+                    return Box::pin(async move { Ok(status.to_http()) });
+                };
+                Box::pin(async move {
+                    let res = fut.await;
+                    drop(permit);
+                    res
+                })
+            }
+        }
+    }
+}
+
+impl<T: NamedService> NamedService for InFlightLimitService<T> {
+    const NAME: &'static str = T::NAME;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_connections_beyond_the_limits_should_be_refused() {
+        let config = AdmissionConfig::new(3, 2, 0, 0);
+        let limiter = ConnectionLimiter::new(&config, ["10.0.0.1:2379"]).unwrap();
+        let client1: IpAddr = "192.168.0.1".parse().unwrap();
+        let client2: IpAddr = "192.168.0.2".parse().unwrap();
+
+        let first = limiter.admit(client1).unwrap();
+        let _second = limiter.admit(client1).unwrap();
+        assert!(limiter.admit(client1).is_none());
+        let _third = limiter.admit(client2).unwrap();
+        assert!(limiter.admit(client2).is_none());
+        // the members are not limited
+        let _peers: Vec<_> = (0..5)
+            .map(|_| limiter.admit("10.0.0.1".parse().unwrap()).unwrap())
+            .collect();
+
+        drop(first);
+        assert!(limiter.admit(client2).is_some());
+        assert!(ConnectionLimiter::new(&AdmissionConfig::default(), []).is_none());
+    }
+
+    #[test]
+    fn test_lane_from_path() {
+        assert_eq!(Lane::from_path("/etcdserverpb.KV/Range"), Lane::Client);
+        assert_eq!(Lane::from_path("/etcdserverpb.KV/Put"), Lane::Client);
+        assert_eq!(
+            Lane::from_path("/etcdserverpb.KV/Compact"),
+            Lane::Maintenance
+        );
+        assert_eq!(
+            Lane::from_path("/etcdserverpb.Maintenance/Snapshot"),
+            Lane::Maintenance
+        );
+        assert_eq!(
+            Lane::from_path("/etcdserverpb.Maintenance/HashKV"),
+            Lane::Maintenance
+        );
+        assert_eq!(
+            Lane::from_path("/etcdserverpb.Cluster/MemberAdd"),
+            Lane::Maintenance
+        );
+        assert_eq!(
+            Lane::from_path("/etcdserverpb.Maintenance/Status"),
+            Lane::Exempt
+        );
+        assert_eq!(Lane::from_path("/etcdserverpb.Watch/Watch"), Lane::Exempt);
+        assert_eq!(Lane::from_path("/v3lockpb.Lock/Lock"), Lane::Exempt);
+    }
+
+    #[test]
+    fn test_lanes_are_limited_separately() {
+        let lanes = RequestLanes::new(&AdmissionConfig::new(0, 0, 1, 1));
+        let client = lanes.permits(Lane::Client).unwrap();
+        let maintenance = lanes.permits(Lane::Maintenance).unwrap();
+        let _running = Arc::clone(client).try_acquire_owned().unwrap();
+        assert!(Arc::clone(client).try_acquire_owned().is_err());
+        // a busy client lane doesn't block the maintenance, and vice versa
+        let _snapshot = Arc::clone(maintenance).try_acquire_owned().unwrap();
+        assert!(lanes.permits(Lane::Exempt).is_none());
+
+        let unlimited = RequestLanes::new(&AdmissionConfig::new(0, 0, 0, 0));
+        assert!(unlimited.permits(Lane::Client).is_none());
+        assert!(unlimited.permits(Lane::Maintenance).is_none());
+    }
+}
@@ -0,0 +1,299 @@
+use std::{collections::HashSet, io::Write, net::SocketAddr};
+
+use tracing_appender::non_blocking::{NonBlocking, WorkerGuard};
+use tracing_subscriber::fmt::{
+    format::Writer,
+    time::{FormatTime, SystemTime},
+};
+use utils::config::{file_appender, AuditConfig, AuditEvent};
+
+use super::slow_log::key_range_summary;
+use crate::{
+    rpc::RequestWrapper,
+    storage::{storage_api::StorageApi, AuthStore},
+};
+
+/// Append-only audit log, it records who did what and when
+#[derive(Debug)]
+pub(crate) struct AuditLog {
+    /// Writer of the audit log file, `None` if the audit log is disabled
+    writer: Option<NonBlocking>,
+    /// Flushes the buffered records when the audit log is dropped
+    _guard: Option<WorkerGuard>,
+    /// Kinds of events to record
+    events: HashSet<AuditEvent>,
+}
+
+impl AuditLog {
+    /// New `AuditLog`, records are written to `xline_{name}_audit.log` in the configured path
+    pub(crate) fn new(config: &AuditConfig, name: &str) -> Self {
+        if !*config.enable() {
+            return Self {
+                writer: None,
+                _guard: None,
+                events: HashSet::new(),
+            };
+        }
+        let appender = file_appender(*config.rotation(), config.path(), &format!("{name}_audit"));
+        let (writer, guard) = tracing_appender::non_blocking(appender);
+        Self {
+            writer: Some(writer),
+            _guard: Some(guard),
+            events: config.events().iter().copied().collect(),
+        }
+    }
+
+    /// Start to audit a request, return `None` if the request should not be recorded.
+    /// The user is the one to authenticate for `AuthenticateRequest`, or the owner of
+    /// the token for other requests.
+    pub(crate) fn entry<S>(
+        &self,
+        auth_store: &AuthStore<S>,
+        request: &RequestWrapper,
+        token: Option<&str>,
+        source: Option<SocketAddr>,
+    ) -> Option<AuditEntry<'_>>
+    where
+        S: StorageApi,
+    {
+        let writer = self.writer.as_ref()?;
+        let (event, description) = describe(request)?;
+        if !self.events.contains(&event) {
+            return None;
+        }
+        let user = if let RequestWrapper::AuthenticateRequest(ref req) = *request {
+            Some(req.name.clone())
+        } else {
+            token
+                .and_then(|t| auth_store.verify_token(t).ok())
+                .map(|claims| claims.username)
+        };
+        Some(AuditEntry {
+            writer,
+            event,
+            user,
+            source,
+            description,
+        })
+    }
+}
+
+/// A request being audited, it is recorded when the request finishes
+#[derive(Debug)]
+pub(crate) struct AuditEntry<'a> {
+    /// Writer of the audit log file
+    writer: &'a NonBlocking,
+    /// Kind of the event
+    event: AuditEvent,
+    /// User who sends the request
+    user: Option<String>,
+    /// Address of the client
+    source: Option<SocketAddr>,
+    /// What the request does
+    description: String,
+}
+
+impl AuditEntry<'_> {
+    /// Record the request with its result
+    pub(crate) fn finish<T>(self, result: &Result<T, tonic::Status>) {
+        let mut time = String::new();
+        let _format_res = SystemTime.format_time(&mut Writer::new(&mut time));
+        let result = match *result {
+            Ok(_) => "ok".to_owned(),
+            Err(ref status) => format!(
+                "error code={:?} message={:?}",
+                status.code(),
+                status.message()
+            ),
+        };
+        let record = format!(
+            "{time} event={:?} user={:?} source={} request={:?} result={result}\n",
+            self.event,
+            self.user.as_deref().unwrap_or("-"),
+            self.source
+                .map_or_else(|| "-".to_owned(), |addr| addr.to_string()),
+            self.description
+        );
+        // each write is sent to the worker as one message, so records are never interleaved
+        let _ignore = self.writer.clone().write_all(record.as_bytes());
+    }
+}
+
+/// Get the kind of event and the description of an audited request, return `None`
+/// for requests which are never audited, eg. reads
+fn describe(request: &RequestWrapper) -> Option<(AuditEvent, String)> {
+    #[allow(clippy::wildcard_enum_match_arm)]
+    let res = match *request {
+        RequestWrapper::PutRequest(ref req) => (
+            AuditEvent::Write,
+            format!("put {}", key_range_summary(&req.key, &[])),
+        ),
+        RequestWrapper::DeleteRangeRequest(ref req) => (
+            AuditEvent::Write,
+            format!(
+                "delete_range {}",
+                key_range_summary(&req.key, &req.range_end)
+            ),
+        ),
+        RequestWrapper::TxnRequest(ref req) => (
+            AuditEvent::Write,
+            format!(
+                "txn compare: {}, success: {}, failure: {}",
+                req.compare.len(),
+                req.success.len(),
+                req.failure.len()
+            ),
+        ),
+        RequestWrapper::AuthenticateRequest(ref req) if !req.api_key.is_empty() => (
+            AuditEvent::Authentication,
+            format!("authenticate api_key: {:?}", req.api_key),
+        ),
+        RequestWrapper::AuthenticateRequest(ref req) => (
+            AuditEvent::Authentication,
+            format!("authenticate name: {:?}", req.name),
+        ),
+        RequestWrapper::AuthEnableRequest(_) => (AuditEvent::AuthConfig, "auth_enable".to_owned()),
+        RequestWrapper::AuthDisableRequest(_) => {
+            (AuditEvent::AuthConfig, "auth_disable".to_owned())
+        }
+        RequestWrapper::AuthUserAddRequest(ref req) => (
+            AuditEvent::AuthConfig,
+            format!("user_add name: {:?}", req.name),
+        ),
+        RequestWrapper::AuthUserDeleteRequest(ref req) => (
+            AuditEvent::AuthConfig,
+            format!("user_delete name: {:?}", req.name),
+        ),
+        RequestWrapper::AuthUserChangePasswordRequest(ref req) => (
+            AuditEvent::AuthConfig,
+            format!("user_change_password name: {:?}", req.name),
+        ),
+        RequestWrapper::AuthUserGrantRoleRequest(ref req) => (
+            AuditEvent::AuthConfig,
+            format!("user_grant_role user: {:?}, role: {:?}", req.user, req.role),
+        ),
+        RequestWrapper::AuthUserRevokeRoleRequest(ref req) => (
+            AuditEvent::AuthConfig,
+            format!(
+                "user_revoke_role name: {:?}, role: {:?}",
+                req.name, req.role
+            ),
+        ),
+        RequestWrapper::AuthRoleAddRequest(ref req) => (
+            AuditEvent::AuthConfig,
+            format!("role_add name: {:?}", req.name),
+        ),
+        RequestWrapper::AuthRoleDeleteRequest(ref req) => (
+            AuditEvent::AuthConfig,
+            format!("role_delete role: {:?}", req.role),
+        ),
+        RequestWrapper::AuthRoleGrantPermissionRequest(ref req) => (
+            AuditEvent::AuthConfig,
+            req.perm.as_ref().map_or_else(
+                || format!("role_grant_permission name: {:?}", req.name),
+                |perm| {
+                    format!(
+                        "role_grant_permission name: {:?}, perm_type: {}, {}",
+                        req.name,
+                        perm.perm_type,
+                        key_range_summary(&perm.key, &perm.range_end)
+                    )
+                },
+            ),
+        ),
+        RequestWrapper::AuthRoleRevokePermissionRequest(ref req) => (
+            AuditEvent::AuthConfig,
+            format!(
+                "role_revoke_permission role: {:?}, {}",
+                req.role,
+                key_range_summary(&req.key, &req.range_end)
+            ),
+        ),
+        RequestWrapper::AuthApiKeyAddRequest(ref req) => (
+            AuditEvent::AuthConfig,
+            format!("api_key_add user: {:?}, id: {:?}", req.user, req.id),
+        ),
+        RequestWrapper::AuthApiKeyRevokeRequest(ref req) => (
+            AuditEvent::AuthConfig,
+            format!("api_key_revoke id: {:?}", req.id),
+        ),
+        _ => return None,
+    };
+    Some(res)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{path::PathBuf, sync::Arc};
+
+    use tokio::sync::mpsc;
+    use utils::config::{RotationConfig, StorageConfig};
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::{
+        header_gen::HeaderGenerator,
+        rpc::{AuthUserAddRequest, PutRequest, RangeRequest},
+        storage::{db::DBProxy, namespace::NamespaceStore},
+    };
+
+    fn init_auth_store() -> AuthStore<DBProxy> {
+        let (lease_cmd_tx, _) = mpsc::channel(1);
+        let db = DBProxy::open(&StorageConfig::Memory).unwrap();
+        AuthStore::new(
+            lease_cmd_tx,
+            None,
+            Arc::new(HeaderGenerator::new(0, 0)),
+            db,
+            Arc::new(NamespaceStore::new(vec![])),
+        )
+    }
+
+    #[test]
+    fn test_reads_are_not_audited() {
+        assert!(describe(&RequestWrapper::RangeRequest(RangeRequest::default())).is_none());
+        let (event, description) = describe(&RequestWrapper::PutRequest(PutRequest {
+            key: b"foo".to_vec(),
+            ..PutRequest::default()
+        }))
+        .unwrap();
+        assert_eq!(event, AuditEvent::Write);
+        assert_eq!(description, r#"put key: "foo""#);
+    }
+
+    #[tokio::test]
+    async fn test_audit_log_records_selected_events() {
+        let dir = std::env::temp_dir().join(format!("xline-audit-{}", Uuid::new_v4()));
+        let config = AuditConfig::new(
+            true,
+            dir.clone(),
+            RotationConfig::Never,
+            vec![AuditEvent::AuthConfig],
+        );
+        let auth_store = init_auth_store();
+        let audit_log = AuditLog::new(&config, "node1");
+        let put = RequestWrapper::PutRequest(PutRequest::default());
+        assert!(audit_log.entry(&auth_store, &put, None, None).is_none());
+
+        let user_add = RequestWrapper::AuthUserAddRequest(AuthUserAddRequest {
+            name: "bob".to_owned(),
+            ..AuthUserAddRequest::default()
+        });
+        let source = Some("127.0.0.1:2379".parse().unwrap());
+        audit_log
+            .entry(&auth_store, &user_add, None, source)
+            .unwrap()
+            .finish::<()>(&Err(tonic::Status::invalid_argument("user already exists")));
+        drop(audit_log);
+
+        let path: PathBuf = dir.join("xline_node1_audit.log");
+        let content = std::fs::read_to_string(path).unwrap();
+        assert_eq!(content.lines().count(), 1);
+        assert!(content.contains(r#"event=AuthConfig user="-" source=127.0.0.1:2379"#));
+        assert!(content.contains(r#"request="user_add name: \"bob\"""#));
+        assert!(
+            content.contains(r#"result=error code=InvalidArgument message="user already exists""#)
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,423 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt::Debug,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Result};
+use async_trait::async_trait;
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use parking_lot::Mutex;
+use reqwest::header::CONTENT_TYPE;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::warn;
+use utils::config::{ExternalAuthConfig, ExternalAuthProvider};
+
+use crate::storage::ExecuteError;
+
+/// Prefix of the usernames of the external identities, so that an external identity is
+/// never taken as the user of Xline with the same name
+const EXTERNAL_USER_PREFIX: &str = "external:";
+
+/// Min interval between two fetches of the keys of an OIDC issuer, so that the tokens
+/// signed by unknown keys don't make the server flood the issuer
+const JWKS_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Identity authenticated by an external provider
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub(crate) struct ExternalIdentity {
+    /// Name of the identity
+    pub(crate) name: String,
+    /// Groups of the identity, which are mapped onto the roles of Xline
+    #[serde(default)]
+    pub(crate) groups: Vec<String>,
+}
+
+/// A provider validating the bearer tokens issued outside Xline
+#[async_trait]
+pub(crate) trait AuthProvider: Debug + Send + Sync {
+    /// Validate a bearer token, return the identity it's issued to
+    async fn authenticate(&self, token: &str) -> Result<ExternalIdentity>;
+}
+
+/// External authentication, the identities authenticated by the provider get the roles
+/// mapped from their groups
+#[derive(Debug)]
+pub(crate) struct ExternalAuth {
+    /// Provider validating the bearer tokens
+    provider: Box<dyn AuthProvider>,
+    /// Roles of each group
+    role_mapping: HashMap<String, Vec<String>>,
+}
+
+impl ExternalAuth {
+    /// New `ExternalAuth`
+    pub(crate) fn new(config: &ExternalAuthConfig) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(*config.timeout())
+            .build()?;
+        let provider: Box<dyn AuthProvider> = match *config.provider() {
+            ExternalAuthProvider::Oidc => Box::new(OidcProvider::new(config, client)?),
+            ExternalAuthProvider::Webhook => Box::new(WebhookProvider::new(config, client)),
+            _ => bail!("unknown external authentication provider"),
+        };
+        Ok(Self {
+            provider,
+            role_mapping: config.role_mapping().clone(),
+        })
+    }
+
+    /// Authenticate a bearer token, return the username and the roles of its identity. An
+    /// identity without any role is rejected.
+    pub(crate) async fn authenticate(
+        &self,
+        token: &str,
+    ) -> Result<(String, Vec<String>), ExecuteError> {
+        let identity = self.provider.authenticate(token).await.map_err(|e| {
+            warn!("external token is rejected, {e}");
+            ExecuteError::auth_failed()
+        })?;
+        let roles = self.roles(&identity.groups);
+        if roles.is_empty() {
+            warn!(
+                "external identity {} is rejected, none of its groups is mapped to a role",
+                identity.name
+            );
+            return Err(ExecuteError::auth_failed());
+        }
+        Ok((format!("{EXTERNAL_USER_PREFIX}{}", identity.name), roles))
+    }
+
+    /// Map the groups onto the roles
+    fn roles(&self, groups: &[String]) -> Vec<String> {
+        groups
+            .iter()
+            .filter_map(|group| self.role_mapping.get(group))
+            .flatten()
+            .cloned()
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+}
+
+/// Metadata of an OIDC issuer
+#[derive(Debug, Deserialize)]
+struct OidcMetadata {
+    /// Issuer identifier
+    issuer: String,
+    /// URL of the keys of the issuer
+    jwks_uri: String,
+}
+
+/// Key set of an OIDC issuer
+#[derive(Debug, Deserialize)]
+struct JwkSet {
+    /// Keys
+    keys: Vec<Jwk>,
+}
+
+/// A key of an OIDC issuer, only the RSA keys are used
+#[derive(Debug, Deserialize)]
+struct Jwk {
+    /// Key id
+    #[serde(default)]
+    kid: String,
+    /// Key type
+    kty: String,
+    /// Modulus of a RSA key
+    #[serde(default)]
+    n: String,
+    /// Exponent of a RSA key
+    #[serde(default)]
+    e: String,
+}
+
+/// Keys of an OIDC issuer fetched last time
+#[derive(Default)]
+struct KeyCache {
+    /// Keys by their ids
+    keys: HashMap<String, DecodingKey>,
+    /// When the keys are fetched, `None` if they are never fetched
+    fetched_at: Option<Instant>,
+}
+
+/// Provider of the JWTs signed by an OIDC issuer, the keys of the issuer are discovered
+/// from its metadata, and fetched again when a token is signed by an unknown key
+pub(crate) struct OidcProvider {
+    /// Issuer identifier
+    issuer: String,
+    /// Audience the tokens must be issued to
+    audience: String,
+    /// Claim holding the username
+    username_claim: String,
+    /// Claim holding the groups
+    groups_claim: String,
+    /// Http client
+    client: reqwest::Client,
+    /// Keys of the issuer
+    keys: Mutex<KeyCache>,
+}
+
+impl Debug for OidcProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OidcProvider")
+            .field("issuer", &self.issuer)
+            .field("audience", &self.audience)
+            .field("username_claim", &self.username_claim)
+            .field("groups_claim", &self.groups_claim)
+            .finish()
+    }
+}
+
+impl OidcProvider {
+    /// New `OidcProvider`, the audience is required, otherwise the tokens issued to any
+    /// client of the issuer would be accepted
+    fn new(config: &ExternalAuthConfig, client: reqwest::Client) -> Result<Self> {
+        if config.audience().is_empty() {
+            bail!("the audience of the OIDC tokens is not set");
+        }
+        Ok(Self {
+            issuer: config.url().clone(),
+            audience: config.audience().clone(),
+            username_claim: config.username_claim().clone(),
+            groups_claim: config.groups_claim().clone(),
+            client,
+            keys: Mutex::new(KeyCache::default()),
+        })
+    }
+
+    /// Fetch the keys of the issuer
+    async fn fetch_keys(&self) -> Result<HashMap<String, DecodingKey>> {
+        let discovery_url = format!(
+            "{}/.well-known/openid-configuration",
+            self.issuer.trim_end_matches('/')
+        );
+        let metadata: OidcMetadata = self.get_json(&discovery_url).await?;
+        if metadata.issuer != self.issuer {
+            bail!(
+                "issuer {} in the metadata doesn't match {}",
+                metadata.issuer,
+                self.issuer
+            );
+        }
+        let jwks: JwkSet = self.get_json(&metadata.jwks_uri).await?;
+        let mut keys = HashMap::new();
+        for jwk in jwks.keys.into_iter().filter(|jwk| jwk.kty == "RSA") {
+            let key = DecodingKey::from_rsa_components(&jwk.n, &jwk.e)?;
+            let _prev = keys.insert(jwk.kid, key);
+        }
+        Ok(keys)
+    }
+
+    /// Get a json document
+    async fn get_json<T: for<'de> Deserialize<'de>>(&self, url: &str) -> Result<T> {
+        let body = self
+            .client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        Ok(serde_json::from_slice(&body)?)
+    }
+
+    /// Get the key of `kid`, the keys are fetched again if it's unknown
+    async fn key(&self, kid: &str) -> Result<DecodingKey> {
+        {
+            let cache = self.keys.lock();
+            if let Some(key) = cache.keys.get(kid) {
+                return Ok(key.clone());
+            }
+            if cache
+                .fetched_at
+                .map_or(false, |at| at.elapsed() < JWKS_REFRESH_INTERVAL)
+            {
+                bail!("unknown key {kid:?}");
+            }
+        }
+        let keys = self.fetch_keys().await?;
+        let mut cache = self.keys.lock();
+        cache.keys = keys;
+        cache.fetched_at = Some(Instant::now());
+        cache
+            .keys
+            .get(kid)
+            .cloned()
+            .ok_or_else(|| anyhow!("unknown key {kid:?}"))
+    }
+
+    /// Get the identity from the claims of a token
+    fn identity(&self, claims: &HashMap<String, Value>) -> Result<ExternalIdentity> {
+        let name = claims
+            .get(&self.username_claim)
+            .and_then(Value::as_str)
+            .filter(|name| !name.is_empty())
+            .ok_or_else(|| anyhow!("claim {} is not found", self.username_claim))?
+            .to_owned();
+        let groups = match claims.get(&self.groups_claim) {
+            Some(&Value::String(ref group)) => vec![group.clone()],
+            Some(&Value::Array(ref groups)) => groups
+                .iter()
+                .filter_map(Value::as_str)
+                .map(str::to_owned)
+                .collect(),
+            _ => vec![],
+        };
+        Ok(ExternalIdentity { name, groups })
+    }
+}
+
+#[async_trait]
+impl AuthProvider for OidcProvider {
+    async fn authenticate(&self, token: &str) -> Result<ExternalIdentity> {
+        let header = jsonwebtoken::decode_header(token)?;
+        // the symmetric algorithms are rejected, an issuer never shares its secret
+        if !matches!(
+            header.alg,
+            Algorithm::RS256
+                | Algorithm::RS384
+                | Algorithm::RS512
+                | Algorithm::PS256
+                | Algorithm::PS384
+                | Algorithm::PS512
+        ) {
+            bail!("unsupported algorithm {:?}", header.alg);
+        }
+        let key = self.key(header.kid.as_deref().unwrap_or_default()).await?;
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&self.issuer]);
+        validation.set_audience(&[&self.audience]);
+        let claims =
+            jsonwebtoken::decode::<HashMap<String, Value>>(token, &key, &validation)?.claims;
+        self.identity(&claims)
+    }
+}
+
+/// Request posted to the webhook
+#[derive(Debug, Serialize)]
+struct WebhookRequest<'a> {
+    /// Bearer token
+    token: &'a str,
+}
+
+/// Provider posting the bearer tokens to a webhook, which returns the identity of a valid
+/// token with a success status, eg. `{"name": "alice", "groups": ["developers"]}`
+#[derive(Debug)]
+pub(crate) struct WebhookProvider {
+    /// URL of the webhook
+    url: String,
+    /// Http client
+    client: reqwest::Client,
+}
+
+impl WebhookProvider {
+    /// New `WebhookProvider`
+    fn new(config: &ExternalAuthConfig, client: reqwest::Client) -> Self {
+        Self {
+            url: config.url().clone(),
+            client,
+        }
+    }
+}
+
+#[async_trait]
+impl AuthProvider for WebhookProvider {
+    async fn authenticate(&self, token: &str) -> Result<ExternalIdentity> {
+        let body = serde_json::to_vec(&WebhookRequest { token })?;
+        let resp = self
+            .client
+            .post(&self.url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(body)
+            .send()
+            .await?;
+        if !resp.status().is_success() {
+            bail!("webhook rejects the token with status {}", resp.status());
+        }
+        let identity: ExternalIdentity = serde_json::from_slice(&resp.bytes().await?)?;
+        if identity.name.is_empty() {
+            bail!("webhook returns an identity without a name");
+        }
+        Ok(identity)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Provider accepting the tokens which are the names of the identities
+    #[derive(Debug)]
+    struct NameProvider;
+
+    #[async_trait]
+    impl AuthProvider for NameProvider {
+        async fn authenticate(&self, token: &str) -> Result<ExternalIdentity> {
+            if token.is_empty() {
+                bail!("empty token");
+            }
+            Ok(ExternalIdentity {
+                name: token.to_owned(),
+                groups: vec!["developers".to_owned(), "admins".to_owned()],
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_external_identities_are_mapped_onto_roles() {
+        let mut auth = ExternalAuth {
+            provider: Box::new(NameProvider),
+            role_mapping: HashMap::from([
+                (
+                    "developers".to_owned(),
+                    vec!["dev-rw".to_owned(), "ro".to_owned()],
+                ),
+                ("admins".to_owned(), vec!["ro".to_owned()]),
+            ]),
+        };
+        assert_eq!(
+            auth.authenticate("alice").await.unwrap(),
+            (
+                "external:alice".to_owned(),
+                vec!["dev-rw".to_owned(), "ro".to_owned()]
+            )
+        );
+        assert!(auth.authenticate("").await.is_err());
+
+        auth.role_mapping.clear();
+        assert!(auth.authenticate("alice").await.is_err());
+    }
+
+    #[test]
+    fn test_oidc_identities_are_read_from_the_claims() {
+        let config: ExternalAuthConfig = toml::from_str(
+            "provider = 'oidc'
+            url = 'https://accounts.example.com'
+            audience = 'xline'",
+        )
+        .unwrap();
+        let provider = OidcProvider::new(&config, reqwest::Client::new()).unwrap();
+        let claims: HashMap<String, Value> =
+            serde_json::from_str(r#"{"sub": "alice", "groups": ["developers", 1]}"#).unwrap();
+        assert_eq!(
+            provider.identity(&claims).unwrap(),
+            ExternalIdentity {
+                name: "alice".to_owned(),
+                groups: vec!["developers".to_owned()]
+            }
+        );
+        let claims: HashMap<String, Value> =
+            serde_json::from_str(r#"{"sub": "bob", "groups": "admins"}"#).unwrap();
+        assert_eq!(provider.identity(&claims).unwrap().groups, ["admins"]);
+        let claims: HashMap<String, Value> =
+            serde_json::from_str(r#"{"email": "bob@example.com"}"#).unwrap();
+        assert!(provider.identity(&claims).is_err());
+
+        let config: ExternalAuthConfig =
+            toml::from_str("provider = 'oidc'\nurl = 'https://accounts.example.com'").unwrap();
+        assert!(OidcProvider::new(&config, reqwest::Client::new()).is_err());
+    }
+}
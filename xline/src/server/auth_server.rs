@@ -1,6 +1,6 @@
 use std::sync::Arc;
 
-use curp::{client::Client, cmd::ProposeId, error::ProposeError};
+use curp::{client::Client, cmd::ProposeId};
 use pbkdf2::{
     password_hash::{rand_core::OsRng, PasswordHasher, SaltString},
     Pbkdf2,
@@ -9,10 +9,18 @@ use tonic::metadata::MetadataMap;
 use tracing::debug;
 use uuid::Uuid;
 
-use super::command::{Command, CommandResponse, SyncResponse};
+use super::{
+    audit::AuditLog,
+    auth_provider::ExternalAuth,
+    command::{propose_err_status, Command, CommandResponse, SyncResponse},
+    listener::client_addr,
+    validation::RequestValidator,
+};
 use crate::{
     rpc::{
-        Auth, AuthDisableRequest, AuthDisableResponse, AuthEnableRequest, AuthEnableResponse,
+        Auth, AuthApiKeyAddRequest, AuthApiKeyAddResponse, AuthApiKeyListRequest,
+        AuthApiKeyListResponse, AuthApiKeyRevokeRequest, AuthApiKeyRevokeResponse,
+        AuthDisableRequest, AuthDisableResponse, AuthEnableRequest, AuthEnableResponse,
         AuthRoleAddRequest, AuthRoleAddResponse, AuthRoleDeleteRequest, AuthRoleDeleteResponse,
         AuthRoleGetRequest, AuthRoleGetResponse, AuthRoleGrantPermissionRequest,
         AuthRoleGrantPermissionResponse, AuthRoleListRequest, AuthRoleListResponse,
@@ -24,7 +32,10 @@ use crate::{
         AuthUserRevokeRoleRequest, AuthUserRevokeRoleResponse, AuthenticateRequest,
         AuthenticateResponse, RequestWithToken, RequestWrapper, ResponseWrapper,
     },
-    storage::{storage_api::StorageApi, AuthStore},
+    storage::{
+        alarm_store::AlarmStore, auth_store::hash_api_key, storage_api::StorageApi, AuthStore,
+        ExecuteError,
+    },
 };
 
 /// Auth Server
@@ -39,6 +50,14 @@ where
     client: Arc<Client<Command>>,
     /// Server name
     name: String,
+    /// Audit log
+    audit_log: Arc<AuditLog>,
+    /// Alarms of current node
+    alarm_store: Arc<AlarmStore>,
+    /// Validation of the requests
+    validator: RequestValidator,
+    /// External authentication, `None` if it's not configured
+    external_auth: Option<ExternalAuth>,
 }
 
 /// Get token from metadata
@@ -58,11 +77,19 @@ where
         storage: Arc<AuthStore<S>>,
         client: Arc<Client<Command>>,
         name: String,
+        audit_log: Arc<AuditLog>,
+        alarm_store: Arc<AlarmStore>,
+        validator: RequestValidator,
+        external_auth: Option<ExternalAuth>,
     ) -> Self {
         Self {
             storage,
             client,
             name,
+            audit_log,
+            alarm_store,
+            validator,
+            external_auth,
         }
     }
 
@@ -89,25 +116,19 @@ where
             Some(token) => RequestWithToken::new_with_token(request.into_inner().into(), token),
             None => RequestWithToken::new(request.into_inner().into()),
         };
+        self.validator.validate(&wrapper.request)?;
+        self.alarm_store.check_request(&wrapper.request)?;
         let propose_id = self.generate_propose_id();
         let cmd = Self::command_from_request_wrapper(propose_id, wrapper);
         if use_fast_path {
-            let cmd_res = self.client.propose(cmd).await.map_err(|err| {
-                if let ProposeError::ExecutionError(e) = err {
-                    tonic::Status::invalid_argument(e)
-                } else {
-                    panic!("propose err {err:?}")
-                }
-            })?;
+            let cmd_res = self.client.propose(cmd).await.map_err(propose_err_status)?;
             Ok((cmd_res, None))
         } else {
-            let (cmd_res, sync_res) = self.client.propose_indexed(cmd).await.map_err(|err| {
-                if let ProposeError::ExecutionError(e) = err {
-                    tonic::Status::invalid_argument(e)
-                } else {
-                    panic!("propose err {err:?}")
-                }
-            })?;
+            let (cmd_res, sync_res) = self
+                .client
+                .propose_indexed(cmd)
+                .await
+                .map_err(propose_err_status)?;
             Ok((cmd_res, Some(sync_res)))
         }
     }
@@ -129,10 +150,70 @@ where
     ) -> Result<i64, tonic::Status> {
         self.storage
             .check_password(username, password)
-            .map_err(|e| tonic::Status::invalid_argument(format!("Auth failed, error: {e}")))
+            .map_err(tonic::Status::from)
+    }
+
+    /// Check the password or the API key and propose the authenticate request, retry if
+    /// the auth store is changed during the check. A request with an external token is
+    /// authenticated by the external provider instead.
+    async fn handle_authenticate(
+        &self,
+        request: &AuthenticateRequest,
+    ) -> Result<AuthenticateResponse, tonic::Status> {
+        if !request.external_token.is_empty() {
+            return self.handle_external_authenticate(request).await;
+        }
+        loop {
+            let (name, checked_revision) = if request.api_key.is_empty() {
+                let checked_revision = self.check_password(&request.name, &request.password)?;
+                (request.name.clone(), checked_revision)
+            } else {
+                self.storage.check_api_key(&request.api_key)?
+            };
+            let authenticate_req = AuthenticateRequest {
+                name,
+                password: String::new(),
+                api_key: String::new(),
+                ..request.clone()
+            };
+
+            let (res, sync_res) = self
+                .propose(tonic::Request::new(authenticate_req), false)
+                .await?;
+
+            if checked_revision == self.storage.revision() {
+                if let Some(sync_res) = sync_res {
+                    let revision = sync_res.revision();
+                    debug!("Get revision {:?} for AuthDisableResponse", revision);
+                    let mut res: AuthenticateResponse = res.decode().into();
+                    if let Some(mut header) = res.header.as_mut() {
+                        header.revision = revision;
+                    }
+                    return Ok(res);
+                }
+            }
+        }
+    }
+
+    /// Validate the external token by the external provider and assign a token with the
+    /// roles of the external identity
+    async fn handle_external_authenticate(
+        &self,
+        request: &AuthenticateRequest,
+    ) -> Result<AuthenticateResponse, tonic::Status> {
+        let Some(ref external_auth) = self.external_auth else {
+            return Err(tonic::Status::failed_precondition(
+                "external authentication is not configured",
+            ));
+        };
+        let (username, roles) = external_auth.authenticate(&request.external_token).await?;
+        debug!("external identity {username} is authenticated with roles {roles:?}");
+        self.storage
+            .assign_external(request, &username, roles)
+            .map_err(tonic::Status::from)
     }
 
-    /// Propose request and make a response
+    /// Propose request and make a response, changes of the auth configuration are audited
     async fn handle_req<Req, Res>(
         &self,
         request: tonic::Request<Req>,
@@ -142,7 +223,18 @@ where
         Req: Into<RequestWrapper>,
         Res: From<ResponseWrapper>,
     {
-        let (cmd_res, sync_res) = self.propose(request, use_fast_path).await?;
+        let request = request.map(Into::<RequestWrapper>::into);
+        let audit_entry = self.audit_log.entry(
+            &self.storage,
+            request.get_ref(),
+            get_token(request.metadata()).as_deref(),
+            client_addr(&request),
+        );
+        let res = self.propose(request, use_fast_path).await;
+        if let Some(entry) = audit_entry {
+            entry.finish(&res);
+        }
+        let (cmd_res, sync_res) = res?;
         let mut res_wrapper = cmd_res.decode();
         if let Some(sync_res) = sync_res {
             res_wrapper.update_revision(sync_res.revision());
@@ -186,28 +278,26 @@ where
         request: tonic::Request<AuthenticateRequest>,
     ) -> Result<tonic::Response<AuthenticateResponse>, tonic::Status> {
         debug!("Receive AuthenticateRequest {:?}", request);
-        loop {
-            let checked_revision =
-                self.check_password(&request.get_ref().name, &request.get_ref().password)?;
-            let mut authenticate_req = request.get_ref().clone();
-            authenticate_req.password = String::new();
-
-            let (res, sync_res) = self
-                .propose(tonic::Request::new(authenticate_req), false)
-                .await?;
-
-            if checked_revision == self.storage.revision() {
-                if let Some(sync_res) = sync_res {
-                    let revision = sync_res.revision();
-                    debug!("Get revision {:?} for AuthDisableResponse", revision);
-                    let mut res: AuthenticateResponse = res.decode().into();
-                    if let Some(mut header) = res.header.as_mut() {
-                        header.revision = revision;
-                    }
-                    return Ok(tonic::Response::new(res));
-                }
-            }
+        // only the id of an API key is audited
+        let api_key_id = request
+            .get_ref()
+            .api_key
+            .split_once('.')
+            .map_or_else(String::new, |(id, _secret)| id.to_owned());
+        let audited_req = RequestWrapper::AuthenticateRequest(AuthenticateRequest {
+            password: String::new(),
+            external_token: String::new(),
+            api_key: api_key_id,
+            ..request.get_ref().clone()
+        });
+        let audit_entry =
+            self.audit_log
+                .entry(&self.storage, &audited_req, None, client_addr(&request));
+        let res = self.handle_authenticate(request.get_ref()).await;
+        if let Some(entry) = audit_entry {
+            entry.finish(&res);
         }
+        res.map(tonic::Response::new)
     }
 
     async fn user_add(
@@ -216,9 +306,6 @@ where
     ) -> Result<tonic::Response<AuthUserAddResponse>, tonic::Status> {
         debug!("Receive AuthUserAddRequest {:?}", request);
         let user_add_req = request.get_mut();
-        if user_add_req.name.is_empty() {
-            return Err(tonic::Status::invalid_argument("user name is empty"));
-        }
         let need_password = user_add_req
             .options
             .as_ref()
@@ -293,9 +380,6 @@ where
         request: tonic::Request<AuthRoleAddRequest>,
     ) -> Result<tonic::Response<AuthRoleAddResponse>, tonic::Status> {
         debug!("Receive AuthRoleAddRequest {:?}", request);
-        if request.get_ref().name.is_empty() {
-            return Err(tonic::Status::invalid_argument("Role name is empty"));
-        }
         self.handle_req(request, false).await
     }
 
@@ -330,9 +414,6 @@ where
         request: tonic::Request<AuthRoleGrantPermissionRequest>,
     ) -> Result<tonic::Response<AuthRoleGrantPermissionResponse>, tonic::Status> {
         debug!("Receive AuthRoleGrantPermissionRequest {:?}", request);
-        if request.get_ref().perm.is_none() {
-            return Err(tonic::Status::invalid_argument("Permission not given"));
-        }
         self.handle_req(request, false).await
     }
 
@@ -343,4 +424,41 @@ where
         debug!("Receive AuthRoleRevokePermissionRequest {:?}", request);
         self.handle_req(request, false).await
     }
+
+    async fn api_key_add(
+        &self,
+        mut request: tonic::Request<AuthApiKeyAddRequest>,
+    ) -> Result<tonic::Response<AuthApiKeyAddResponse>, tonic::Status> {
+        debug!(
+            "Receive AuthApiKeyAddRequest for user {:?}",
+            request.get_ref().user
+        );
+        // the key is only returned once, the auth table only keeps its hash
+        let id = Uuid::new_v4().simple().to_string();
+        let key = format!("{id}.{}", Uuid::new_v4().simple());
+        let api_key_add_req = request.get_mut();
+        api_key_add_req.id = id;
+        api_key_add_req.hashed_key = hash_api_key(&key);
+        let mut res: tonic::Response<AuthApiKeyAddResponse> =
+            self.handle_req(request, false).await?;
+        res.get_mut().key = key;
+        Ok(res)
+    }
+
+    async fn api_key_revoke(
+        &self,
+        request: tonic::Request<AuthApiKeyRevokeRequest>,
+    ) -> Result<tonic::Response<AuthApiKeyRevokeResponse>, tonic::Status> {
+        debug!("Receive AuthApiKeyRevokeRequest {:?}", request);
+        self.handle_req(request, false).await
+    }
+
+    async fn api_key_list(
+        &self,
+        request: tonic::Request<AuthApiKeyListRequest>,
+    ) -> Result<tonic::Response<AuthApiKeyListResponse>, tonic::Status> {
+        debug!("Receive AuthApiKeyListRequest {:?}", request);
+        let is_fast_path = true;
+        self.handle_req(request, is_fast_path).await
+    }
 }
@@ -0,0 +1,51 @@
+use std::{sync::Arc, time::Duration};
+
+use tokio::sync::watch;
+use tracing::warn;
+
+use crate::storage::storage_api::StorageApi;
+
+/// Periodic sync of the backend for the `interval` sync policy. The writes flushed between
+/// two syncs are buffered by the OS, so at most an interval of them is lost if the machine
+/// crashes, and they are recovered by applying the consensus log again.
+#[derive(Debug)]
+pub(crate) struct BackendSyncer<S>
+where
+    S: StorageApi,
+{
+    /// Persistent storage
+    persistent: Arc<S>,
+    /// Interval between two syncs
+    interval: Duration,
+}
+
+impl<S> BackendSyncer<S>
+where
+    S: StorageApi,
+{
+    /// New `BackendSyncer`
+    pub(crate) fn new(persistent: Arc<S>, interval: Duration) -> Self {
+        Self {
+            persistent,
+            interval,
+        }
+    }
+
+    /// Sync the backend every interval until the server shuts down
+    #[allow(clippy::integer_arithmetic)] // Introduced by tokio::select!
+    pub(crate) async fn run(self, mut shutdown_rx: watch::Receiver<bool>) {
+        let mut ticker = tokio::time::interval(self.interval);
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {}
+                _ = shutdown_rx.changed() => return,
+            }
+            let persistent = Arc::clone(&self.persistent);
+            match tokio::task::spawn_blocking(move || persistent.sync()).await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => warn!("failed to sync the backend, {e}"),
+                Err(e) => warn!("backend sync task failed, {e}"),
+            }
+        }
+    }
+}
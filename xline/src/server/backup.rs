@@ -0,0 +1,427 @@
+use std::{
+    io::{self, Write},
+    mem,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Result};
+use reqwest::header::ETAG;
+use rusty_s3::{
+    actions::{CreateMultipartUpload, ListObjectsV2},
+    Bucket, Credentials, S3Action, UrlStyle,
+};
+use tokio::{
+    sync::{mpsc, watch},
+    task::JoinHandle,
+};
+use tracing::{debug, info, warn};
+use utils::config::BackupConfig;
+
+use crate::{state::State, storage::storage_api::StorageApi};
+
+/// How long a signed request is valid
+const SIGN_DURATION: Duration = Duration::from_secs(3600);
+/// Prefix of the names of the snapshots
+const SNAPSHOT_PREFIX: &str = "xline-";
+/// Suffix of the object keys of the snapshots
+const SNAPSHOT_SUFFIX: &str = ".snap";
+/// Width of the zero padded time in the names of the snapshots
+const SNAPSHOT_TIME_WIDTH: usize = 20;
+/// Size of the parts of a snapshot upload, a multipart upload has at most 10000 parts of
+/// at least 5 MiB except the last one
+const PART_SIZE: usize = 16 * 1024 * 1024;
+
+/// Periodic backup of the storage, the leader uploads a snapshot to an S3 compatible object
+/// storage every interval and deletes the snapshots beyond the retention
+#[derive(Debug)]
+pub(crate) struct Backup<S>
+where
+    S: StorageApi,
+{
+    /// Persistent storage
+    persistent: Arc<S>,
+    /// State of current node
+    state: Arc<State>,
+    /// Bucket to store the snapshots
+    bucket: Bucket,
+    /// Credentials to sign the requests, requests are anonymous if it's `None`
+    credentials: Option<Credentials>,
+    /// Http client
+    client: reqwest::Client,
+    /// Prefix of the object keys of the snapshots
+    prefix: String,
+    /// Interval between two snapshots
+    interval: Duration,
+    /// Number of the latest snapshots to keep
+    retention: usize,
+}
+
+impl<S> Backup<S>
+where
+    S: StorageApi,
+{
+    /// New `Backup`, return `None` if the backup is disabled
+    pub(crate) fn new(
+        config: &BackupConfig,
+        persistent: Arc<S>,
+        state: Arc<State>,
+    ) -> Result<Option<Self>> {
+        if !*config.enable() {
+            return Ok(None);
+        }
+        let url_style = if *config.path_style() {
+            UrlStyle::Path
+        } else {
+            UrlStyle::VirtualHost
+        };
+        let bucket = Bucket::new(
+            config.endpoint().parse()?,
+            url_style,
+            config.bucket().clone(),
+            config.region().clone(),
+        )
+        .map_err(|e| anyhow!("invalid backup bucket: {e}"))?;
+        let credentials = Credentials::from_env();
+        if credentials.is_none() {
+            warn!("no credentials in the environment, backup requests are anonymous");
+        }
+        Ok(Some(Self {
+            persistent,
+            state,
+            bucket,
+            credentials,
+            client: reqwest::Client::new(),
+            prefix: config.prefix().clone(),
+            interval: *config.interval(),
+            retention: *config.retention(),
+        }))
+    }
+
+    /// Back up the storage every interval until the server shuts down
+    #[allow(clippy::integer_arithmetic)] // Introduced by tokio::select!
+    pub(crate) async fn run(self, mut shutdown_rx: watch::Receiver<bool>) {
+        let mut ticker = tokio::time::interval(self.interval);
+        // the first tick completes immediately, don't back up when the server just starts
+        let _ignore = ticker.tick().await;
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {}
+                _ = shutdown_rx.changed() => return,
+            }
+            if !self.state.is_leader() {
+                debug!("skip backup on a follower");
+                continue;
+            }
+            match self.backup().await {
+                Ok(key) => info!("backup {key} is uploaded"),
+                Err(e) => warn!("failed to back up, {e}"),
+            }
+        }
+    }
+
+    /// Upload a snapshot of the storage and delete the expired ones, return the object key
+    /// of the snapshot
+    async fn backup(&self) -> Result<String> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+        let key = snapshot_key(&self.prefix, now);
+        let (part_tx, part_rx) = mpsc::channel(1);
+        let persistent = Arc::clone(&self.persistent);
+        let snapshot = tokio::task::spawn_blocking(move || {
+            let mut writer = PartWriter::new(part_tx);
+            let _count = persistent.snapshot(&mut writer)?;
+            writer.finish()
+        });
+        self.upload(&key, part_rx, snapshot).await?;
+
+        for expired in expired_keys(self.list_snapshots().await?, self.retention) {
+            let delete_url = self
+                .bucket
+                .delete_object(self.credentials.as_ref(), &expired)
+                .sign(SIGN_DURATION);
+            let _delete_resp = self
+                .client
+                .delete(delete_url)
+                .send()
+                .await?
+                .error_for_status()?;
+            info!("expired backup {expired} is deleted");
+        }
+        Ok(key)
+    }
+
+    /// Upload the parts of a snapshot as they are written by `snapshot` in a multipart
+    /// upload, so the snapshot is never held in memory as a whole. The upload is aborted if
+    /// the snapshot or any part fails, so a partial snapshot is never left in the bucket.
+    async fn upload(
+        &self,
+        key: &str,
+        parts: mpsc::Receiver<Vec<u8>>,
+        snapshot: JoinHandle<Result<()>>,
+    ) -> Result<()> {
+        let url = self
+            .bucket
+            .create_multipart_upload(self.credentials.as_ref(), key)
+            .sign(SIGN_DURATION);
+        let body = self
+            .client
+            .post(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        let upload = CreateMultipartUpload::parse_response(&body)?;
+        let upload_id = upload.upload_id();
+        let res = async {
+            let etags = self.upload_parts(key, upload_id, parts).await?;
+            // the parts end early if the snapshot fails, it mustn't be completed then
+            snapshot.await??;
+            let action = self.bucket.complete_multipart_upload(
+                self.credentials.as_ref(),
+                key,
+                upload_id,
+                etags.iter().map(String::as_str),
+            );
+            let url = action.sign(SIGN_DURATION);
+            let _resp = self
+                .client
+                .post(url)
+                .body(action.body())
+                .send()
+                .await?
+                .error_for_status()?;
+            Ok(())
+        }
+        .await;
+        if res.is_err() {
+            let url = self
+                .bucket
+                .abort_multipart_upload(self.credentials.as_ref(), key, upload_id)
+                .sign(SIGN_DURATION);
+            if let Err(e) = self.client.delete(url).send().await {
+                warn!("failed to abort the upload of backup {key}, {e}");
+            }
+        }
+        res
+    }
+
+    /// Upload the parts received from `parts`, return their etags in order
+    async fn upload_parts(
+        &self,
+        key: &str,
+        upload_id: &str,
+        mut parts: mpsc::Receiver<Vec<u8>>,
+    ) -> Result<Vec<String>> {
+        let mut etags = Vec::new();
+        let mut part_number: u16 = 1;
+        while let Some(part) = parts.recv().await {
+            let url = self
+                .bucket
+                .upload_part(self.credentials.as_ref(), key, part_number, upload_id)
+                .sign(SIGN_DURATION);
+            let resp = self
+                .client
+                .put(url)
+                .body(part)
+                .send()
+                .await?
+                .error_for_status()?;
+            let etag = resp
+                .headers()
+                .get(ETAG)
+                .ok_or_else(|| anyhow!("no etag in the response of part {part_number}"))?
+                .to_str()?
+                .to_owned();
+            etags.push(etag);
+            part_number = part_number
+                .checked_add(1)
+                .ok_or_else(|| anyhow!("the snapshot has too many parts"))?;
+        }
+        Ok(etags)
+    }
+
+    /// List the object keys of all snapshots
+    async fn list_snapshots(&self) -> Result<Vec<String>> {
+        let mut keys = Vec::new();
+        let mut continuation_token = None;
+        loop {
+            let mut action = self.bucket.list_objects_v2(self.credentials.as_ref());
+            action.query_mut().insert("prefix", self.prefix.as_str());
+            if let Some(ref token) = continuation_token {
+                action
+                    .query_mut()
+                    .insert("continuation-token", String::clone(token));
+            }
+            let url = action.sign(SIGN_DURATION);
+            let body = self
+                .client
+                .get(url)
+                .send()
+                .await?
+                .error_for_status()?
+                .text()
+                .await?;
+            let resp = ListObjectsV2::parse_response(&body)?;
+            keys.extend(
+                resp.contents
+                    .into_iter()
+                    .map(|content| content.key)
+                    .filter(|key| is_snapshot_key(&self.prefix, key)),
+            );
+            continuation_token = resp.next_continuation_token;
+            if continuation_token.is_none() {
+                return Ok(keys);
+            }
+        }
+    }
+}
+
+/// Object key of the snapshot taken at `time` since the unix epoch, keys are ordered by time
+fn snapshot_key(prefix: &str, time: Duration) -> String {
+    format!(
+        "{prefix}{SNAPSHOT_PREFIX}{:0width$}{SNAPSHOT_SUFFIX}",
+        time.as_secs(),
+        width = SNAPSHOT_TIME_WIDTH
+    )
+}
+
+/// Check if `key` is the key of a snapshot under `prefix`, so that the other objects under
+/// the prefix, eg. the ones uploaded by hand or in a nested directory, are never deleted
+fn is_snapshot_key(prefix: &str, key: &str) -> bool {
+    key.strip_prefix(prefix)
+        .and_then(|name| name.strip_prefix(SNAPSHOT_PREFIX))
+        .and_then(|name| name.strip_suffix(SNAPSHOT_SUFFIX))
+        .map_or(false, |time| {
+            time.len() == SNAPSHOT_TIME_WIDTH && time.bytes().all(|b| b.is_ascii_digit())
+        })
+}
+
+/// Writer which splits a snapshot into the parts of a multipart upload
+#[derive(Debug)]
+struct PartWriter {
+    /// Part being written
+    buf: Vec<u8>,
+    /// Sender of the written parts
+    part_tx: mpsc::Sender<Vec<u8>>,
+    /// Whether a part is sent
+    sent: bool,
+}
+
+impl PartWriter {
+    /// New `PartWriter`
+    fn new(part_tx: mpsc::Sender<Vec<u8>>) -> Self {
+        Self {
+            buf: Vec::with_capacity(PART_SIZE),
+            part_tx,
+            sent: false,
+        }
+    }
+
+    /// Send a part, it fails if the upload has stopped
+    fn send(&mut self, part: Vec<u8>) -> io::Result<()> {
+        self.sent = true;
+        self.part_tx
+            .blocking_send(part)
+            .map_err(|_e| io::Error::new(io::ErrorKind::BrokenPipe, "the upload has stopped"))
+    }
+
+    /// Send the last part, an empty snapshot is uploaded as one empty part
+    fn finish(mut self) -> Result<()> {
+        if !self.buf.is_empty() || !self.sent {
+            let part = mem::take(&mut self.buf);
+            self.send(part)?;
+        }
+        Ok(())
+    }
+}
+
+impl Write for PartWriter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(data);
+        if self.buf.len() >= PART_SIZE {
+            let part = mem::replace(&mut self.buf, Vec::with_capacity(PART_SIZE));
+            self.send(part)?;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Keys of the snapshots beyond the retention, the latest `retention` snapshots are kept,
+/// all of them are kept if `retention` is 0
+fn expired_keys(mut keys: Vec<String>, retention: usize) -> Vec<String> {
+    if retention == 0 {
+        return vec![];
+    }
+    keys.sort();
+    let expired = keys.len().saturating_sub(retention);
+    keys.truncate(expired);
+    keys
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_keys_are_ordered_by_time() {
+        let key1 = snapshot_key("backup/", Duration::from_secs(999));
+        let key2 = snapshot_key("backup/", Duration::from_secs(1000));
+        assert_eq!(key1, "backup/xline-00000000000000000999.snap");
+        assert!(key1 < key2);
+    }
+
+    #[test]
+    fn test_only_snapshot_keys_match() {
+        let key = snapshot_key("backup/", Duration::from_secs(1000));
+        assert!(is_snapshot_key("backup/", &key));
+        assert!(!is_snapshot_key("other/", &key));
+        for key in [
+            "backup/notes.snap",
+            "backup/xline-latest.snap",
+            "backup/xline-1000.snap",
+            "backup/old/xline-00000000000000001000.snap",
+            "backup/xline-00000000000000001000.snap.bak",
+        ] {
+            assert!(!is_snapshot_key("backup/", key), "{key}");
+        }
+    }
+
+    #[test]
+    fn test_part_writer_splits_the_snapshot() {
+        let (part_tx, mut part_rx) = mpsc::channel(4);
+        let mut writer = PartWriter::new(part_tx);
+        writer.write_all(&vec![1; PART_SIZE + 10]).unwrap();
+        writer.write_all(&[2; 5]).unwrap();
+        writer.finish().unwrap();
+        assert_eq!(part_rx.blocking_recv().unwrap().len(), PART_SIZE + 10);
+        assert_eq!(part_rx.blocking_recv().unwrap(), vec![2; 5]);
+        assert!(part_rx.blocking_recv().is_none());
+
+        let (part_tx, mut part_rx) = mpsc::channel(4);
+        PartWriter::new(part_tx).finish().unwrap();
+        assert!(part_rx.blocking_recv().unwrap().is_empty());
+        assert!(part_rx.blocking_recv().is_none());
+    }
+
+    #[test]
+    fn test_expired_keys() {
+        let keys: Vec<_> = (1..=5)
+            .rev()
+            .map(|i| snapshot_key("", Duration::from_secs(i)))
+            .collect();
+        assert_eq!(
+            expired_keys(keys.clone(), 3),
+            vec![
+                snapshot_key("", Duration::from_secs(1)),
+                snapshot_key("", Duration::from_secs(2))
+            ]
+        );
+        assert!(expired_keys(keys.clone(), 10).is_empty());
+        assert!(expired_keys(keys, 0).is_empty());
+    }
+}
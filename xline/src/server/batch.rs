@@ -0,0 +1,269 @@
+use std::sync::Arc;
+
+use curp::{client::Client, cmd::ProposeId};
+use tokio::{
+    sync::{mpsc, oneshot},
+    time,
+};
+use tracing::debug;
+use utils::config::BatchConfig;
+use uuid::Uuid;
+
+use super::command::{propose_err_status, Command, CommandResponse, KeyRange};
+use crate::rpc::{RequestWithToken, RequestWrapper, TxnRequest, TxnResponse};
+
+/// Channel size of the pending writes
+const CHANNEL_SIZE: usize = 1024;
+
+/// A write waiting for its batch to be proposed
+#[derive(Debug)]
+struct PendingWrite {
+    /// The write request
+    request: RequestWithToken,
+    /// Key range of the write
+    key_range: KeyRange,
+    /// Sender of the result of the write
+    res_tx: oneshot::Sender<Result<CommandResponse, tonic::Status>>,
+}
+
+/// Proposal batcher, which collects the small writes arriving within the batch window and
+/// proposes them to curp as one txn, so that they are synced and flushed together
+#[derive(Debug)]
+pub(crate) struct ProposalBatcher {
+    /// Sender of the pending writes
+    write_tx: mpsc::Sender<PendingWrite>,
+}
+
+impl ProposalBatcher {
+    /// New `ProposalBatcher`, the batches are collected and proposed in background
+    pub(crate) fn new(client: Arc<Client<Command>>, name: String, config: BatchConfig) -> Self {
+        let (write_tx, write_rx) = mpsc::channel(CHANNEL_SIZE);
+        let _handle = tokio::spawn(Self::batch_task(write_rx, client, name, config));
+        Self { write_tx }
+    }
+
+    /// Check if a request can be batched. Writes which may fail in execution are proposed
+    /// alone, otherwise they would fail the other writes in their batches.
+    pub(crate) fn is_batchable(request: &RequestWrapper) -> bool {
+        #[allow(clippy::wildcard_enum_match_arm)]
+        match *request {
+            RequestWrapper::PutRequest(ref req) => {
+                req.lease == 0 && !req.ignore_value && !req.ignore_lease
+            }
+            RequestWrapper::DeleteRangeRequest(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Propose a batchable write together with the others in its batch
+    pub(crate) async fn propose(
+        &self,
+        request: RequestWithToken,
+    ) -> Result<CommandResponse, tonic::Status> {
+        #[allow(clippy::wildcard_enum_match_arm)]
+        let key_range = match request.request {
+            RequestWrapper::PutRequest(ref req) => KeyRange::new(req.key.clone(), vec![]),
+            RequestWrapper::DeleteRangeRequest(ref req) => {
+                KeyRange::new(req.key.clone(), req.range_end.clone())
+            }
+            _ => unreachable!("only batchable requests can be sent to the batcher"),
+        };
+        let (res_tx, res_rx) = oneshot::channel();
+        self.write_tx
+            .send(PendingWrite {
+                request,
+                key_range,
+                res_tx,
+            })
+            .await
+            .map_err(|_e| tonic::Status::internal("proposal batcher is closed"))?;
+        res_rx
+            .await
+            .map_err(|_e| tonic::Status::internal("batch is dropped before proposed"))?
+    }
+
+    /// Check if a write can join the batch. Writes in a batch share the same token, and
+    /// none of them overlap, so that every write sees the same state as it is proposed alone.
+    fn can_join(batch: &[PendingWrite], write: &PendingWrite) -> bool {
+        batch.iter().all(|pending| {
+            pending.request.token == write.request.token
+                && !pending.key_range.is_conflicted(&write.key_range)
+        })
+    }
+
+    /// Collect the pending writes into batches and propose them
+    #[allow(clippy::integer_arithmetic)] // Introduced by tokio::select!
+    async fn batch_task(
+        mut write_rx: mpsc::Receiver<PendingWrite>,
+        client: Arc<Client<Command>>,
+        name: String,
+        config: BatchConfig,
+    ) {
+        let mut next = None;
+        loop {
+            let first = match next.take() {
+                Some(write) => write,
+                None => match write_rx.recv().await {
+                    Some(write) => write,
+                    None => return,
+                },
+            };
+            let window = time::sleep(*config.window());
+            tokio::pin!(window);
+            let mut batch = vec![first];
+            while batch.len() < *config.max_size() {
+                let write = tokio::select! {
+                    write = write_rx.recv() => write,
+                    () = &mut window => None,
+                };
+                match write {
+                    Some(write) if Self::can_join(&batch, &write) => batch.push(write),
+                    Some(write) => {
+                        next = Some(write);
+                        break;
+                    }
+                    None => break,
+                }
+            }
+            let propose_id = ProposeId::new(format!("{name}-{}", Uuid::new_v4()));
+            let _handle = tokio::spawn(Self::propose_batch(Arc::clone(&client), propose_id, batch));
+        }
+    }
+
+    /// Propose a batch and send the results to the writes in it. A batch of one write is
+    /// proposed as it is, otherwise the writes are proposed as the success ops of a txn.
+    async fn propose_batch(
+        client: Arc<Client<Command>>,
+        propose_id: ProposeId,
+        mut batch: Vec<PendingWrite>,
+    ) {
+        if batch.len() == 1 {
+            if let Some(write) = batch.pop() {
+                let cmd = Command::new(vec![write.key_range], write.request, propose_id);
+                let res = client.propose(cmd).await.map_err(propose_err_status);
+                let _ignore = write.res_tx.send(res);
+            }
+            return;
+        }
+        debug!("propose a batch of {} writes", batch.len());
+        let token = batch.first().and_then(|write| write.request.token.clone());
+        let mut key_ranges = Vec::with_capacity(batch.len());
+        let mut success = Vec::with_capacity(batch.len());
+        let mut res_txs = Vec::with_capacity(batch.len());
+        for write in batch {
+            key_ranges.push(write.key_range);
+            success.push(write.request.request.into());
+            res_txs.push(write.res_tx);
+        }
+        let txn = TxnRequest {
+            compare: vec![],
+            success,
+            failure: vec![],
+        };
+        let request = RequestWithToken {
+            token,
+            request: txn.into(),
+        };
+        let cmd = Command::new(key_ranges, request, propose_id);
+        match client.propose(cmd).await {
+            Ok(cmd_res) => {
+                let txn_res: TxnResponse = cmd_res.decode().into();
+                for (res_tx, response) in res_txs.into_iter().zip(txn_res.responses) {
+                    let _ignore = res_tx.send(Ok(CommandResponse::new(response.into())));
+                }
+            }
+            Err(err) => {
+                let status = propose_err_status(err);
+                for res_tx in res_txs {
+                    let _ignore =
+                        res_tx.send(Err(tonic::Status::new(status.code(), status.message())));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+
+    use super::*;
+    use crate::rpc::{DeleteRangeRequest, PutRequest};
+
+    fn write(request: RequestWrapper, token: Option<&str>) -> PendingWrite {
+        #[allow(clippy::wildcard_enum_match_arm)]
+        let key_range = match request {
+            RequestWrapper::PutRequest(ref req) => KeyRange::new(req.key.clone(), vec![]),
+            RequestWrapper::DeleteRangeRequest(ref req) => {
+                KeyRange::new(req.key.clone(), req.range_end.clone())
+            }
+            _ => unreachable!(),
+        };
+        PendingWrite {
+            request: RequestWithToken {
+                token: token.map(str::to_owned),
+                request,
+            },
+            key_range,
+            res_tx: oneshot::channel().0,
+        }
+    }
+
+    fn put(key: &str) -> RequestWrapper {
+        PutRequest {
+            key: key.into(),
+            value: Bytes::from_static(b"value"),
+            ..PutRequest::default()
+        }
+        .into()
+    }
+
+    fn delete(key: &str, range_end: &str) -> RequestWrapper {
+        DeleteRangeRequest {
+            key: key.into(),
+            range_end: range_end.into(),
+            ..DeleteRangeRequest::default()
+        }
+        .into()
+    }
+
+    #[test]
+    fn test_is_batchable() {
+        assert!(ProposalBatcher::is_batchable(&put("a")));
+        assert!(ProposalBatcher::is_batchable(&delete("a", "c")));
+        let put_with_lease = PutRequest {
+            key: b"a".to_vec(),
+            lease: 1,
+            ..PutRequest::default()
+        };
+        assert!(!ProposalBatcher::is_batchable(&put_with_lease.into()));
+        let ignore_value = PutRequest {
+            key: b"a".to_vec(),
+            ignore_value: true,
+            ..PutRequest::default()
+        };
+        assert!(!ProposalBatcher::is_batchable(&ignore_value.into()));
+        let txn = TxnRequest::default();
+        assert!(!ProposalBatcher::is_batchable(&txn.into()));
+    }
+
+    #[test]
+    fn test_can_join() {
+        let batch = vec![write(put("a"), None), write(delete("c", "e"), None)];
+        assert!(ProposalBatcher::can_join(&batch, &write(put("b"), None)));
+        assert!(ProposalBatcher::can_join(&batch, &write(put("e"), None)));
+        // the same key
+        assert!(!ProposalBatcher::can_join(&batch, &write(put("a"), None)));
+        // overlaps with the deleted range
+        assert!(!ProposalBatcher::can_join(&batch, &write(put("d"), None)));
+        assert!(!ProposalBatcher::can_join(
+            &batch,
+            &write(delete("b", "d"), None)
+        ));
+        // a different token
+        assert!(!ProposalBatcher::can_join(
+            &batch,
+            &write(put("b"), Some("token"))
+        ));
+    }
+}
@@ -0,0 +1,130 @@
+use std::sync::Arc;
+
+use curp::{client::Client, cmd::ProposeId};
+use tracing::debug;
+use uuid::Uuid;
+
+use super::{
+    auth_server::get_token,
+    command::{propose_err_status, Command},
+    validation::RequestValidator,
+};
+use crate::{
+    rpc::{
+        Cluster, MemberAddRequest, MemberAddResponse, MemberListRequest, MemberListResponse,
+        MemberPromoteRequest, MemberPromoteResponse, MemberRemoveRequest, MemberRemoveResponse,
+        MemberUpdateRequest, MemberUpdateResponse, RequestWithToken,
+    },
+    storage::{alarm_store::AlarmStore, member_store::MemberStore},
+};
+
+/// Cluster Server, the members are given by the config, so they can't be added or removed,
+/// but their addresses can be updated
+#[derive(Debug)]
+pub(crate) struct ClusterServer {
+    /// Members of the cluster
+    member_store: Arc<MemberStore>,
+    /// Consensus client
+    client: Arc<Client<Command>>,
+    /// Server name
+    name: String,
+    /// Alarms of current node
+    alarm_store: Arc<AlarmStore>,
+    /// Validation of the requests
+    validator: RequestValidator,
+}
+
+impl ClusterServer {
+    /// New `ClusterServer`
+    pub(crate) fn new(
+        member_store: Arc<MemberStore>,
+        client: Arc<Client<Command>>,
+        name: String,
+        alarm_store: Arc<AlarmStore>,
+        validator: RequestValidator,
+    ) -> Self {
+        Self {
+            member_store,
+            client,
+            name,
+            alarm_store,
+            validator,
+        }
+    }
+
+    /// Generate propose id
+    fn generate_propose_id(&self) -> ProposeId {
+        ProposeId::new(format!("{}-{}", self.name, Uuid::new_v4()))
+    }
+}
+
+#[tonic::async_trait]
+impl Cluster for ClusterServer {
+    async fn member_add(
+        &self,
+        _request: tonic::Request<MemberAddRequest>,
+    ) -> Result<tonic::Response<MemberAddResponse>, tonic::Status> {
+        Err(tonic::Status::unimplemented(
+            "members can't be added at runtime, change the config of every member",
+        ))
+    }
+
+    async fn member_remove(
+        &self,
+        _request: tonic::Request<MemberRemoveRequest>,
+    ) -> Result<tonic::Response<MemberRemoveResponse>, tonic::Status> {
+        Err(tonic::Status::unimplemented(
+            "members can't be removed at runtime, change the config of every member",
+        ))
+    }
+
+    /// MemberUpdate updates the peer URL of a member, the other members connect to it at
+    /// the new address once the update is applied, and the member itself serves on it after
+    /// it's restarted with the new address in its config
+    async fn member_update(
+        &self,
+        request: tonic::Request<MemberUpdateRequest>,
+    ) -> Result<tonic::Response<MemberUpdateResponse>, tonic::Status> {
+        debug!("Receive MemberUpdateRequest {:?}", request);
+        let wrapper = match get_token(request.metadata()) {
+            Some(token) => RequestWithToken::new_with_token(request.into_inner().into(), token),
+            None => RequestWithToken::new(request.into_inner().into()),
+        };
+        self.validator.validate(&wrapper.request)?;
+        self.alarm_store.check_request(&wrapper.request)?;
+        let cmd = Command::new(vec![], wrapper, self.generate_propose_id());
+        let (res, sync_res) = self
+            .client
+            .propose_indexed(cmd)
+            .await
+            .map_err(propose_err_status)?;
+        let mut res: MemberUpdateResponse = res.decode().into();
+        if let Some(mut header) = res.header.as_mut() {
+            header.revision = sync_res.revision();
+        }
+        Ok(tonic::Response::new(res))
+    }
+
+    /// MemberList lists the members known to the member which receives the request, the
+    /// updates are applied through the log, so `linearizable` has no effect, and a list may
+    /// lag behind an update which isn't applied on the member yet
+    async fn member_list(
+        &self,
+        request: tonic::Request<MemberListRequest>,
+    ) -> Result<tonic::Response<MemberListResponse>, tonic::Status> {
+        debug!("Receive MemberListRequest {:?}", request);
+        Ok(tonic::Response::new(MemberListResponse {
+            header: Some(self.member_store.header()),
+            members: self.member_store.members(),
+        }))
+    }
+
+    async fn member_promote(
+        &self,
+        _request: tonic::Request<MemberPromoteRequest>,
+    ) -> Result<tonic::Response<MemberPromoteResponse>, tonic::Status> {
+        Err(tonic::Status::unimplemented(
+            "standbys can't be promoted at runtime, change the config of every member",
+        ))
+    }
+}
@@ -1,20 +1,55 @@
 use std::{
+    fmt,
+    fs::File,
+    io::{BufWriter, Write},
     ops::{Bound, RangeBounds},
-    sync::Arc,
+    path::Path,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
 };
 
+use anyhow::bail;
+use clippy_utilities::NumericCast;
 use curp::{
     cmd::{
         Command as CurpCommand, CommandExecutor as CurpCommandExecutor, ConflictCheck, ProposeId,
     },
+    error::ProposeError,
     LogIndex,
 };
-use itertools::Itertools;
-use serde::{Deserialize, Serialize};
+use once_cell::sync::OnceCell;
+use parking_lot::Mutex;
+use serde::{
+    de::{self, SeqAccess, Visitor},
+    ser::SerializeTuple,
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use tracing::{error, warn};
+use utils::interval_map::{Interval, IntervalMap};
 
+use super::probe_server::ApplyProgress;
 use crate::{
-    rpc::{RequestBackend, RequestWithToken, RequestWrapper, ResponseWrapper},
-    storage::{db::WriteOp, storage_api::StorageApi, AuthStore, ExecuteError, KvStore, LeaseStore},
+    rpc::{
+        AlarmType, Request, RequestBackend, RequestWithToken, RequestWrapper, ResponseWrapper,
+        TxnRequest,
+    },
+    storage::{
+        alarm_store::AlarmStore,
+        applied_ids::{AppliedIds, APPLIED_ID_WINDOW},
+        db::WriteOp,
+        execute_error::status_of_message,
+        member_store::MemberStore,
+        migration,
+        namespace::NamespaceStore,
+        quota::QuotaStore,
+        revision_time::RevisionTimeIndex,
+        storage_api::StorageApi,
+        trash::TrashStore,
+        update_sequencer::PreparedRevision,
+        AuthStore, ExecuteError, KvStore, LeaseStore,
+    },
 };
 
 /// Meta table name
@@ -27,6 +62,20 @@ const UNBOUNDED: &[u8] = &[0_u8];
 /// Range end to get one key
 const ONE_KEY: &[u8] = &[];
 
+/// Convert a `ProposeError` to the status returned to the client. The execution errors
+/// get the codes etcd returns with them, a failed sync is reported as a timeout like etcd.
+pub(crate) fn propose_err_status(err: ProposeError) -> tonic::Status {
+    #[allow(clippy::wildcard_enum_match_arm)] // `ProposeError` is non-exhaustive
+    match err {
+        ProposeError::ExecutionError(msg) => status_of_message(msg),
+        ProposeError::SyncedError(_) => tonic::Status::unavailable("etcdserver: request timed out"),
+        ProposeError::RpcError(_) | ProposeError::RpcStatus(_) => {
+            tonic::Status::unavailable(err.to_string())
+        }
+        _ => tonic::Status::internal(format!("propose err {err:?}")),
+    }
+}
+
 /// Type of `KeyRange`
 pub(crate) enum RangeType {
     /// `KeyRange` contains only one key
@@ -107,6 +156,26 @@ impl KeyRange {
         }
     }
 
+    /// Get the interval of the keys in the `KeyRange`
+    pub(crate) fn interval(&self) -> Interval<KeyBound> {
+        let low = match self.start_bound() {
+            Bound::Included(start) => start.to_vec(),
+            // the empty key is the smallest one
+            Bound::Excluded(_) | Bound::Unbounded => vec![],
+        };
+        let high = match self.end_bound() {
+            Bound::Included(end) => {
+                // the smallest key after `end`
+                let mut next = end.to_vec();
+                next.push(0);
+                KeyBound::Key(next)
+            }
+            Bound::Excluded(end) => KeyBound::Key(end.to_vec()),
+            Bound::Unbounded => KeyBound::Unbounded,
+        };
+        Interval::new(KeyBound::Key(low), high)
+    }
+
     /// Check if `KeyRange` contains a key
     pub(crate) fn contains_key(&self, key: &[u8]) -> bool {
         self.contains(key)
@@ -167,6 +236,62 @@ impl RangeBounds<[u8]> for KeyRange {
     }
 }
 
+/// Bound of the keys in an `Interval`, `Unbounded` is greater than any key
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub(crate) enum KeyBound {
+    /// A key
+    Key(Vec<u8>),
+    /// Greater than any key
+    Unbounded,
+}
+
+/// Get the key ranges of a kv request, a txn contains the keys of its compares and all of
+/// its ops, including the nested txns
+pub(crate) fn key_ranges(request: &RequestWrapper) -> Vec<KeyRange> {
+    #[allow(clippy::wildcard_enum_match_arm)]
+    match *request {
+        RequestWrapper::RangeRequest(ref req) => {
+            vec![KeyRange::new(req.key.as_slice(), req.range_end.as_slice())]
+        }
+        RequestWrapper::PutRequest(ref req) => vec![KeyRange::new(req.key.as_slice(), ONE_KEY)],
+        RequestWrapper::DeleteRangeRequest(ref req) => {
+            vec![KeyRange::new(req.key.as_slice(), req.range_end.as_slice())]
+        }
+        RequestWrapper::TxnRequest(ref req) => {
+            let mut key_ranges = Vec::new();
+            txn_key_ranges(req, &mut key_ranges);
+            key_ranges
+        }
+        // a compaction removes the history of every key
+        RequestWrapper::CompactionRequest(_) => vec![KeyRange::new(UNBOUNDED, UNBOUNDED)],
+        _ => vec![],
+    }
+}
+
+/// Collect the key ranges of a txn
+fn txn_key_ranges(txn: &TxnRequest, key_ranges: &mut Vec<KeyRange>) {
+    key_ranges.extend(
+        txn.compare
+            .iter()
+            .map(|cmp| KeyRange::new(cmp.key.as_slice(), cmp.range_end.as_slice())),
+    );
+    for op in txn.success.iter().chain(txn.failure.iter()) {
+        match op.request {
+            Some(Request::RequestRange(ref req)) => {
+                key_ranges.push(KeyRange::new(req.key.as_slice(), req.range_end.as_slice()));
+            }
+            Some(Request::RequestPut(ref req)) => {
+                key_ranges.push(KeyRange::new(req.key.as_slice(), ONE_KEY));
+            }
+            Some(Request::RequestDeleteRange(ref req)) => {
+                key_ranges.push(KeyRange::new(req.key.as_slice(), req.range_end.as_slice()));
+            }
+            Some(Request::RequestTxn(ref req)) => txn_key_ranges(req, key_ranges),
+            None => {}
+        }
+    }
+}
+
 /// Command Executor
 #[derive(Debug, Clone)]
 pub(crate) struct CommandExecutor<S>
@@ -179,8 +304,27 @@ where
     auth_storage: Arc<AuthStore<S>>,
     /// Lease Storage
     lease_storage: Arc<LeaseStore<S>>,
+    /// Members of the cluster
+    member_store: Arc<MemberStore>,
     /// persistent storage
     persistent: Arc<S>,
+    /// The largest applied index flushed to the persistent storage. After syncs finish out
+    /// of the log order, so it's only written when it grows.
+    applied_index: Arc<Mutex<LogIndex>>,
+    /// Propose ids of the latest log entries, the replayed proposals are skipped
+    applied_ids: Arc<Mutex<AppliedIds>>,
+    /// Progress of the apply loop, watched by the liveness probe
+    apply_progress: Arc<ApplyProgress>,
+    /// Write quotas of the users
+    quota_store: Arc<QuotaStore>,
+    /// Namespaces of the keys
+    namespace_store: Arc<NamespaceStore>,
+    /// Trash of the deleted keys
+    trash_store: Arc<TrashStore>,
+    /// Index from the wall-clock time to the revisions
+    revision_times: Arc<RevisionTimeIndex>,
+    /// Alarms of current node
+    alarm_store: Arc<AlarmStore>,
 }
 
 impl<S> CommandExecutor<S>
@@ -192,13 +336,144 @@ where
         kv_storage: Arc<KvStore<S>>,
         auth_storage: Arc<AuthStore<S>>,
         lease_storage: Arc<LeaseStore<S>>,
+        member_store: Arc<MemberStore>,
         persistent: Arc<S>,
+        apply_progress: Arc<ApplyProgress>,
+        quota_store: Arc<QuotaStore>,
+        namespace_store: Arc<NamespaceStore>,
+        trash_store: Arc<TrashStore>,
+        revision_times: Arc<RevisionTimeIndex>,
+        alarm_store: Arc<AlarmStore>,
     ) -> Self {
         Self {
             kv_storage,
             auth_storage,
             lease_storage,
+            member_store,
             persistent,
+            applied_index: Arc::new(Mutex::new(0)),
+            applied_ids: Arc::new(Mutex::new(AppliedIds::default())),
+            apply_progress,
+            quota_store,
+            namespace_store,
+            trash_store,
+            revision_times,
+            alarm_store,
+        }
+    }
+
+    /// Recover the propose ids, the index of the applied log entries and the quota usages
+    pub(crate) fn recover(&self) -> Result<(), ExecuteError> {
+        *self.applied_ids.lock() = AppliedIds::recover(self.persistent.as_ref())?;
+        self.quota_store.recover(self.persistent.as_ref())?;
+        self.apply_progress.advance(self.last_applied()?);
+        Ok(())
+    }
+
+    /// Flush the ops of a command along with its applied index and propose id. The memory
+    /// states are already updated when the flush fails, they have diverged from the storage,
+    /// so the CORRUPT alarm is raised and the member becomes read only.
+    fn flush(&self, id: &ProposeId, index: LogIndex) -> Result<(), ExecuteError> {
+        self.try_flush(id, index).map_err(|e| {
+            error!("failed to flush log entry {index} of proposal {id}, {e}");
+            self.apply_progress.record_flush_failure(index, &e);
+            let _activated = self.alarm_store.activate(AlarmType::Corrupt);
+            e
+        })
+    }
+
+    /// Try to flush the ops of a command
+    fn try_flush(&self, id: &ProposeId, index: LogIndex) -> Result<(), ExecuteError> {
+        self.persistent
+            .buffer_op(id, WriteOp::PutAppliedId(index, id.clone()));
+        if let Some(old) = index.checked_sub(APPLIED_ID_WINDOW) {
+            self.persistent.buffer_op(id, WriteOp::DeleteAppliedId(old));
+        }
+        // flushed under the lock, so that a smaller index never overwrites a larger one
+        let mut applied_index = self.applied_index.lock();
+        if index > *applied_index {
+            *applied_index = index;
+            self.persistent
+                .buffer_op(id, WriteOp::PutAppliedIndex(index));
+        }
+        self.persistent.flush(id)
+    }
+
+    /// Sync a command to the storage
+    async fn sync_cmd(
+        &self,
+        cmd: &Command,
+        index: LogIndex,
+        mut revision: Option<PreparedRevision>,
+    ) -> Result<SyncResponse, ExecuteError> {
+        let id = cmd.id();
+        if self.applied_ids.lock().is_replayed(id, index) {
+            warn!("skip the replayed proposal {id} at log index {index}");
+            self.flush(id, index)?;
+            return Ok(SyncResponse::new(self.kv_storage.revision()));
+        }
+        let wrapper = cmd.request();
+        self.auth_storage.check_permission(wrapper).await?;
+
+        let res = match wrapper.request.backend() {
+            RequestBackend::Kv => {
+                let revision = revision.as_mut().unwrap_or_else(|| {
+                    unreachable!("the revision of a kv request should be prepared")
+                });
+                self.kv_storage.after_sync(id, wrapper, revision).await?
+            }
+            RequestBackend::Auth => self.auth_storage.after_sync(id, wrapper)?,
+            RequestBackend::Lease => {
+                self.lease_storage
+                    .after_sync(id, wrapper, revision.as_mut())?
+            }
+            RequestBackend::Cluster => {
+                self.member_store
+                    .after_sync(self.persistent.as_ref(), id, wrapper)?
+            }
+        };
+        self.sync_quota(id, wrapper);
+        if let Some(ref revision) = revision {
+            self.namespace_store.apply(revision.events());
+            self.trash_store.sync(
+                self.persistent.as_ref(),
+                id,
+                &wrapper.request,
+                revision.events(),
+            );
+            self.revision_times.sync(
+                self.persistent.as_ref(),
+                id,
+                &wrapper.request,
+                revision.revision(),
+                revision.events(),
+            )?;
+        }
+        // the index and the lease collection are updated, but nothing is flushed yet
+        fail::fail_point!("xline_after_sync_before_flush");
+        self.flush(id, index)?;
+        // the writes are flushed, but the updates aren't sent to the watchers yet
+        fail::fail_point!("xline_after_flush_before_notify");
+        // the revision is finished after the writes are flushed, so that the data of an
+        // applied revision can be read
+        drop(revision);
+        Ok(res)
+    }
+
+    /// Charge the user of a synced write for its usage, or forget the usage of a deleted user
+    fn sync_quota(&self, id: &ProposeId, wrapper: &RequestWithToken) {
+        if let RequestWrapper::AuthUserDeleteRequest(ref req) = wrapper.request {
+            self.quota_store.remove(&req.name);
+            self.persistent
+                .buffer_op(id, WriteOp::DeleteQuotaUsage(req.name.clone()));
+            return;
+        }
+        let Some(user) = self.auth_storage.quota_user(wrapper) else {
+            return;
+        };
+        if let Some(usage) = self.quota_store.charge(&user, &wrapper.request) {
+            self.persistent
+                .buffer_op(id, WriteOp::PutQuotaUsage(user, usage));
         }
     }
 }
@@ -213,37 +488,82 @@ where
     async fn execute(&self, cmd: &Command) -> Result<CommandResponse, ExecuteError> {
         let wrapper = cmd.request();
         self.auth_storage.check_permission(wrapper).await?;
+        // the writes proposed through the other members are rejected here on the leader
+        self.alarm_store.check_request(&wrapper.request)?;
         match wrapper.request.backend() {
-            RequestBackend::Kv => self.kv_storage.execute(wrapper),
+            RequestBackend::Kv => {
+                if let Some(user) = self.auth_storage.quota_user(wrapper) {
+                    self.quota_store.check(&user, &wrapper.request)?;
+                }
+                self.namespace_store.check(&wrapper.request)?;
+                self.kv_storage.execute(wrapper)
+            }
             RequestBackend::Auth => self.auth_storage.execute(wrapper),
             RequestBackend::Lease => self.lease_storage.execute(wrapper),
+            RequestBackend::Cluster => self.member_store.execute(wrapper),
+        }
+    }
+
+    fn prepare(&self, cmd: &Command, index: LogIndex) -> Option<PreparedRevision> {
+        // a replayed proposal must not allocate a revision, it's skipped in the after sync
+        if !self.applied_ids.lock().insert(cmd.id(), index) {
+            return None;
         }
+        let request = &cmd.request().request;
+        (request.is_kv_request() || matches!(*request, RequestWrapper::LeaseRevokeRequest(_)))
+            .then(|| self.kv_storage.prepare())
     }
 
     async fn after_sync(
         &self,
         cmd: &Command,
         index: LogIndex,
+        revision: Option<PreparedRevision>,
     ) -> Result<SyncResponse, ExecuteError> {
-        let id = cmd.id();
-        let wrapper = cmd.request();
-        self.auth_storage.check_permission(wrapper).await?;
-        self.persistent
-            .buffer_op(id, WriteOp::PutAppliedIndex(index));
-
-        let res = match wrapper.request.backend() {
-            RequestBackend::Kv => self.kv_storage.after_sync(id, wrapper).await?,
-            RequestBackend::Auth => self.auth_storage.after_sync(id, wrapper)?,
-            RequestBackend::Lease => self.lease_storage.after_sync(id, wrapper).await?,
-        };
-        self.persistent.flush(id)?;
-        Ok(res)
+        let res = self.sync_cmd(cmd, index, revision).await;
+        self.apply_progress.advance(index);
+        res
     }
 
     async fn reset(&self) {
         self.persistent
             .reset()
             .unwrap_or_else(|e| panic!("reset backend failed, {e:?}"));
+        *self.applied_index.lock() = 0;
+        self.applied_ids.lock().clear();
+        self.quota_store.clear();
+        self.namespace_store.clear();
+    }
+
+    async fn snapshot(&self, path: &Path) -> Result<(), ExecuteError> {
+        let file = File::create(path).map_err(|e| {
+            ExecuteError::DbError(format!("Failed to create snapshot file, error: {e}"))
+        })?;
+        let mut writer = BufWriter::new(file);
+        let _count = self.persistent.snapshot(&mut writer)?;
+        writer
+            .flush()
+            .and_then(|()| writer.get_ref().sync_all())
+            .map_err(|e| {
+                ExecuteError::DbError(format!("Failed to write snapshot file, error: {e}"))
+            })
+    }
+
+    async fn install_snapshot(&self, path: &Path, index: LogIndex) -> Result<(), ExecuteError> {
+        self.persistent.install_snapshot(path, index)?;
+        // the storage is replaced, but the memory states are not recovered from it yet
+        fail::fail_point!("xline_install_snapshot_before_recover");
+        // the snapshot may be taken by a member of an older version
+        migration::migrate(self.persistent.as_ref())?;
+        *self.applied_index.lock() = index;
+        self.recover()?;
+        // lease storage must recover before kv storage
+        self.lease_storage.recover()?;
+        self.kv_storage.recover().await?;
+        self.namespace_store.recover(&self.kv_storage)?;
+        self.auth_storage.recover()?;
+        self.member_store.recover(self.persistent.as_ref())?;
+        Ok(())
     }
 
     fn last_applied(&self) -> Result<LogIndex, ExecuteError> {
@@ -257,8 +577,38 @@ where
     }
 }
 
+/// Latest version of the wire format of the commands. The version 0 is the unversioned
+/// format of the releases before, in which the fields of `Command` are encoded by serde.
+pub const COMMAND_WIRE_VERSION: u32 = 1;
+
+/// High bits of the first field of a versioned command, its low bits are the version. An
+/// unversioned command starts with the number of its keys, which never reaches it.
+const VERSIONED_COMMAND_MARKER: u64 = 0x786c_696e_0000_0000;
+
+/// Mask of the version in the first field of a versioned command
+const COMMAND_VERSION_MASK: u64 = 0xffff_ffff;
+
+/// Version of the wire format the commands are encoded in
+static WIRE_VERSION: AtomicU32 = AtomicU32::new(COMMAND_WIRE_VERSION);
+
+/// Set the version of the wire format the commands are encoded in. The commands of all the
+/// supported versions are decoded, so an older version is set during a rolling upgrade
+/// until all the members are upgraded.
+///
+/// # Errors
+///
+/// Return error if the version is newer than `COMMAND_WIRE_VERSION`
+#[inline]
+pub fn set_command_wire_version(version: u32) -> anyhow::Result<()> {
+    if version > COMMAND_WIRE_VERSION {
+        bail!("command wire version {version} is newer than {COMMAND_WIRE_VERSION}");
+    }
+    WIRE_VERSION.store(version, Ordering::Relaxed);
+    Ok(())
+}
+
 /// Command to run consensus protocol
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone)]
 pub(crate) struct Command {
     /// Keys of request
     keys: Vec<KeyRange>,
@@ -266,6 +616,115 @@ pub(crate) struct Command {
     request: RequestWithToken,
     /// Propose id
     id: ProposeId,
+    /// Interval tree of the keys, it's built when the command is checked for conflicts
+    /// with another one for the first time
+    key_index: OnceCell<IntervalMap<KeyBound, ()>>,
+}
+
+/// A command is encoded as a tuple. A versioned command is encoded as the marker with the
+/// version, the keys, the propose id, the token, the request type and the protobuf encoding
+/// of the request, so that the requests with the fields added by a newer version are
+/// still decoded. An unversioned command is encoded as the number of the keys, the keys,
+/// the request and the propose id, which is the same as the derived encoding of the older
+/// releases in bincode.
+impl Serialize for Command {
+    fn serialize<Ser>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error>
+    where
+        Ser: Serializer,
+    {
+        let version = WIRE_VERSION.load(Ordering::Relaxed);
+        if version == 0 {
+            let mut tuple = serializer.serialize_tuple(self.keys.len().saturating_add(3))?;
+            tuple.serialize_element(&self.keys.len().numeric_cast::<u64>())?;
+            for key in &self.keys {
+                tuple.serialize_element(key)?;
+            }
+            tuple.serialize_element(&self.request)?;
+            tuple.serialize_element(&self.id)?;
+            return tuple.end();
+        }
+        let (request_type, request) = self.request.request.encode_to_wire();
+        let mut tuple = serializer.serialize_tuple(6)?;
+        tuple.serialize_element(&(VERSIONED_COMMAND_MARKER | u64::from(version)))?;
+        tuple.serialize_element(&self.keys)?;
+        tuple.serialize_element(&self.id)?;
+        tuple.serialize_element(&self.request.token)?;
+        tuple.serialize_element(request_type)?;
+        tuple.serialize_element(&request)?;
+        tuple.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Command {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        // the number of the elements depends on the version, the visitor stops at the end
+        deserializer.deserialize_tuple(usize::MAX, CommandVisitor)
+    }
+}
+
+/// Visitor decoding the commands of all the supported wire versions
+struct CommandVisitor;
+
+impl<'de> Visitor<'de> for CommandVisitor {
+    type Value = Command;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str("a command")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let head: u64 = next_element(&mut seq)?;
+        if head & !COMMAND_VERSION_MASK != VERSIONED_COMMAND_MARKER {
+            // an unversioned command, the head is the number of its keys
+            let keys = (0..head)
+                .map(|_| next_element(&mut seq))
+                .collect::<Result<_, _>>()?;
+            let request = next_element(&mut seq)?;
+            let id = next_element(&mut seq)?;
+            return Ok(Command::new(keys, request, id));
+        }
+        match head & COMMAND_VERSION_MASK {
+            1 => {
+                let keys = next_element(&mut seq)?;
+                let id = next_element(&mut seq)?;
+                let token = next_element(&mut seq)?;
+                let request_type: String = next_element(&mut seq)?;
+                let request: Vec<u8> = next_element(&mut seq)?;
+                let request = RequestWrapper::decode_from_wire(&request_type, &request)
+                    .map_err(de::Error::custom)?;
+                Ok(Command::new(keys, RequestWithToken { token, request }, id))
+            }
+            version => Err(de::Error::custom(format!(
+                "unsupported command wire version {version}, the latest is {COMMAND_WIRE_VERSION}"
+            ))),
+        }
+    }
+}
+
+/// Decode the next element of a command
+fn next_element<'de, A, T>(seq: &mut A) -> Result<T, A::Error>
+where
+    A: SeqAccess<'de>,
+    T: Deserialize<'de>,
+{
+    seq.next_element()?
+        .ok_or_else(|| de::Error::custom("the command is truncated"))
+}
+
+impl std::fmt::Debug for Command {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Command")
+            .field("keys", &self.keys)
+            .field("request", &self.request)
+            .field("id", &self.id)
+            .finish_non_exhaustive()
+    }
 }
 
 impl ConflictCheck for Command {
@@ -288,17 +747,25 @@ impl ConflictCheck for Command {
             return true;
         }
 
+        // the members are updated one after another, so that they're recorded in the order
+        // of the log
+        if this_req.is_cluster_request() && other_req.is_cluster_request() {
+            return true;
+        }
+
         if (this_req.is_lease_request()) && (other_req.is_lease_request()) {
             #[allow(clippy::wildcard_enum_match_arm)]
             let lease_id1 = match *this_req {
                 RequestWrapper::LeaseGrantRequest(ref req) => req.id,
                 RequestWrapper::LeaseRevokeRequest(ref req) => req.id,
+                RequestWrapper::LeaseModifyTtlRequest(ref req) => req.id,
                 _ => unreachable!("other request can not in this match"),
             };
             #[allow(clippy::wildcard_enum_match_arm)]
             let lease_id2 = match *other_req {
                 RequestWrapper::LeaseGrantRequest(ref req) => req.id,
                 RequestWrapper::LeaseRevokeRequest(ref req) => req.id,
+                RequestWrapper::LeaseModifyTtlRequest(ref req) => req.id,
                 _ => unreachable!("other request can not in this match"),
             };
             if lease_id1 == lease_id2 {
@@ -306,10 +773,17 @@ impl ConflictCheck for Command {
             }
         }
 
-        self.keys()
+        // look up the keys of the smaller command in the interval tree of the larger one
+        let (fewer, more) = if self.keys.len() <= other.keys.len() {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        let key_index = more.key_index();
+        fewer
+            .keys
             .iter()
-            .cartesian_product(other.keys().iter())
-            .any(|(k1, k2)| k1.is_conflicted(k2))
+            .any(|key| key_index.overlaps(&key.interval()))
     }
 }
 
@@ -322,7 +796,23 @@ impl ConflictCheck for KeyRange {
 impl Command {
     /// New `Command`
     pub(crate) fn new(keys: Vec<KeyRange>, request: RequestWithToken, id: ProposeId) -> Self {
-        Self { keys, request, id }
+        Self {
+            keys,
+            request,
+            id,
+            key_index: OnceCell::new(),
+        }
+    }
+
+    /// Get the interval tree of the keys
+    fn key_index(&self) -> &IntervalMap<KeyBound, ()> {
+        self.key_index.get_or_init(|| {
+            let mut key_index = IntervalMap::new();
+            for key in &self.keys {
+                let _prev = key_index.insert(key.interval(), ());
+            }
+            key_index
+        })
     }
 
     /// get request
@@ -373,6 +863,7 @@ impl CurpCommand for Command {
     type K = KeyRange;
     type ER = CommandResponse;
     type ASR = SyncResponse;
+    type PR = Option<PreparedRevision>;
 
     fn keys(&self) -> &[Self::K] {
         self.keys.as_slice()
@@ -382,3 +873,163 @@ impl CurpCommand for Command {
         &self.id
     }
 }
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+
+    use super::*;
+    use crate::rpc::{Compare, DeleteRangeRequest, PutRequest, RangeRequest, RequestOp};
+
+    fn command(id: &str, keys: Vec<KeyRange>) -> Command {
+        Command::new(
+            keys,
+            RequestWithToken::new(RangeRequest::default().into()),
+            ProposeId::new(id.to_owned()),
+        )
+    }
+
+    /// A command in the unversioned format of the older releases
+    #[derive(Serialize)]
+    struct UnversionedCommand {
+        /// Keys of request
+        keys: Vec<KeyRange>,
+        /// Request data
+        request: RequestWithToken,
+        /// Propose id
+        id: ProposeId,
+    }
+
+    #[test]
+    fn test_commands_of_the_supported_wire_versions_are_decoded() {
+        let request = RequestWithToken::new_with_token(
+            PutRequest {
+                key: b"foo".to_vec(),
+                value: Bytes::from_static(b"bar"),
+                ..PutRequest::default()
+            }
+            .into(),
+            "token".to_owned(),
+        );
+        let keys = vec![KeyRange::new("foo", "")];
+        let id = ProposeId::new("id".to_owned());
+        let check = |cmd: Command| {
+            assert_eq!(cmd.keys, keys);
+            assert_eq!(cmd.id, id);
+            assert_eq!(cmd.request.token.as_deref(), Some("token"));
+            assert_eq!(cmd.request.request, request.request);
+        };
+
+        let cmd = Command::new(keys.clone(), request.clone(), id.clone());
+        let encoded = bincode::serialize(&cmd).unwrap();
+        check(bincode::deserialize(&encoded).unwrap());
+
+        let unversioned = UnversionedCommand {
+            keys: keys.clone(),
+            request: request.clone(),
+            id: id.clone(),
+        };
+        let encoded = bincode::serialize(&unversioned).unwrap();
+        check(bincode::deserialize(&encoded).unwrap());
+
+        let newer = (VERSIONED_COMMAND_MARKER | 2, keys.clone());
+        let encoded = bincode::serialize(&newer).unwrap();
+        assert!(bincode::deserialize::<Command>(&encoded).is_err());
+        let unknown = (
+            VERSIONED_COMMAND_MARKER | 1,
+            keys.clone(),
+            id.clone(),
+            Some("token"),
+            "UnknownRequest",
+            Vec::<u8>::new(),
+        );
+        let encoded = bincode::serialize(&unknown).unwrap();
+        assert!(bincode::deserialize::<Command>(&encoded).is_err());
+    }
+
+    #[test]
+    fn test_interval_agrees_with_is_conflicted() {
+        let ranges = [
+            KeyRange::new("a", ""),
+            KeyRange::new("b", ""),
+            KeyRange::new("a", "c"),
+            KeyRange::new("b", "d"),
+            KeyRange::new("c", "e"),
+            KeyRange::new("c", "\0"),
+            KeyRange::new("\0", "b"),
+            KeyRange::new("\0", "\0"),
+            KeyRange::new("a\0", "b"),
+        ];
+        for r1 in &ranges {
+            for r2 in &ranges {
+                assert_eq!(
+                    r1.interval().overlaps(&r2.interval()),
+                    r1.is_conflicted(r2),
+                    "{r1:?} and {r2:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_command_conflict() {
+        let cmd1 = command(
+            "1",
+            vec![
+                KeyRange::new("a", ""),
+                KeyRange::new("c", "e"),
+                KeyRange::new("g", ""),
+            ],
+        );
+        let cmd2 = command("2", vec![KeyRange::new("b", "")]);
+        let cmd3 = command("3", vec![KeyRange::new("b", ""), KeyRange::new("d", "")]);
+        let cmd4 = command("4", vec![KeyRange::new("f", "\0")]);
+        assert!(!cmd1.is_conflict(&cmd2));
+        assert!(cmd1.is_conflict(&cmd3));
+        assert!(cmd3.is_conflict(&cmd1));
+        assert!(cmd4.is_conflict(&cmd1));
+        assert!(!cmd4.is_conflict(&cmd2));
+    }
+
+    #[test]
+    fn test_txn_key_ranges() {
+        let put = |key: &str| RequestOp {
+            request: Some(Request::RequestPut(PutRequest {
+                key: key.into(),
+                value: Bytes::from_static(b"value"),
+                ..PutRequest::default()
+            })),
+        };
+        let txn = TxnRequest {
+            compare: vec![Compare {
+                key: b"a".to_vec(),
+                ..Compare::default()
+            }],
+            success: vec![
+                put("b"),
+                RequestOp {
+                    request: Some(Request::RequestTxn(TxnRequest {
+                        success: vec![put("c")],
+                        ..TxnRequest::default()
+                    })),
+                },
+            ],
+            failure: vec![RequestOp {
+                request: Some(Request::RequestDeleteRange(DeleteRangeRequest {
+                    key: b"d".to_vec(),
+                    range_end: b"f".to_vec(),
+                    ..DeleteRangeRequest::default()
+                })),
+            }],
+        };
+        assert_eq!(
+            key_ranges(&txn.into()),
+            vec![
+                KeyRange::new("a", ""),
+                KeyRange::new("b", ""),
+                KeyRange::new("c", ""),
+                KeyRange::new("d", "f"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,210 @@
+use std::{
+    collections::VecDeque,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use curp::{client::Client, cmd::ProposeId};
+use tokio::{sync::watch, time::Interval};
+use tracing::{debug, info, warn};
+use utils::config::CompactConfig;
+use uuid::Uuid;
+
+use super::command::{key_ranges, Command};
+use crate::{
+    rpc::{CompactionRequest, RequestWithToken, RequestWrapper},
+    state::State,
+    storage::{storage_api::StorageApi, AuthStore, KvStore},
+};
+
+/// The revision is sampled this many times in a retention period, so the history retained
+/// is at most one tenth of the retention longer than it
+const SAMPLES_PER_RETENTION: u32 = 10;
+/// The revision isn't sampled more often than this
+const MIN_SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Revisions of the kv store sampled at points of time, it maps a point of time to the
+/// latest revision written before it
+#[derive(Debug, Default)]
+pub(crate) struct RevisionTimeline {
+    /// Sampled points of time and revisions, in the order of time
+    samples: VecDeque<(Instant, i64)>,
+}
+
+impl RevisionTimeline {
+    /// Record the revision at a point of time
+    pub(crate) fn record(&mut self, time: Instant, revision: i64) {
+        self.samples.push_back((time, revision));
+    }
+
+    /// Take the latest revision sampled at or before `time`, the samples before it are
+    /// dropped as the history before them is no longer needed
+    pub(crate) fn take_revision_at(&mut self, time: Instant) -> Option<i64> {
+        let mut revision = None;
+        while let Some(&(sampled, rev)) = self.samples.front() {
+            if sampled > time {
+                break;
+            }
+            revision = Some(rev);
+            let _sample = self.samples.pop_front();
+        }
+        revision
+    }
+}
+
+/// Automatic compaction which retains the history written in a period of time, like the
+/// periodic mode of etcd's auto compaction. Every member samples its revision, and the
+/// leader compacts the history up to the revision sampled a retention period ago, so a
+/// new leader starts compacting one retention period after it's elected at the latest.
+/// When maintenance windows are configured, the compactions only run inside them. The
+/// config is reloadable, a zero retention pauses the compaction until it's set again.
+#[derive(Debug)]
+pub(crate) struct Compactor<S>
+where
+    S: StorageApi,
+{
+    /// KV storage
+    kv_storage: Arc<KvStore<S>>,
+    /// Auth storage, the compactions are proposed with the root token
+    auth_storage: Arc<AuthStore<S>>,
+    /// Consensus client
+    client: Arc<Client<Command>>,
+    /// State of current node
+    state: Arc<State>,
+    /// Compaction configuration, updated when the config is reloaded
+    config_rx: watch::Receiver<CompactConfig>,
+}
+
+impl<S> Compactor<S>
+where
+    S: StorageApi,
+{
+    /// New `Compactor`
+    pub(crate) fn new(
+        config_rx: watch::Receiver<CompactConfig>,
+        kv_storage: Arc<KvStore<S>>,
+        auth_storage: Arc<AuthStore<S>>,
+        client: Arc<Client<Command>>,
+        state: Arc<State>,
+    ) -> Self {
+        Self {
+            kv_storage,
+            auth_storage,
+            client,
+            state,
+            config_rx,
+        }
+    }
+
+    /// Sample the revision and compact the history until the server shuts down, the
+    /// sampling restarts with the new config once it's reloaded
+    #[allow(clippy::integer_arithmetic)] // Introduced by tokio::select!
+    pub(crate) async fn run(mut self, mut shutdown_rx: watch::Receiver<bool>) {
+        loop {
+            let config = self.config_rx.borrow_and_update().clone();
+            let retention = *config.auto_compact_retention();
+            // the samples taken with another retention may be too sparse for this one
+            let mut timeline = RevisionTimeline::default();
+            let mut ticker = (!retention.is_zero()).then(|| {
+                tokio::time::interval((retention / SAMPLES_PER_RETENTION).max(MIN_SAMPLE_INTERVAL))
+            });
+            loop {
+                tokio::select! {
+                    () = tick(ticker.as_mut()) => {}
+                    res = self.config_rx.changed() => {
+                        if res.is_err() {
+                            return;
+                        }
+                        break;
+                    }
+                    _ = shutdown_rx.changed() => return,
+                }
+                self.sample_and_compact(&mut timeline, &config, retention)
+                    .await;
+            }
+            info!("automatic compaction is reloaded, {retention:?} is retained");
+        }
+    }
+
+    /// Sample the revision, and compact the history retained longer than `retention` if
+    /// this member is the leader
+    async fn sample_and_compact(
+        &self,
+        timeline: &mut RevisionTimeline,
+        config: &CompactConfig,
+        retention: Duration,
+    ) {
+        let now = Instant::now();
+        timeline.record(now, self.kv_storage.revision());
+        let Some(retained_since) = now.checked_sub(retention) else {
+            return;
+        };
+        // The samples are kept outside the maintenance windows, the compaction catches
+        // up with them once a window opens
+        if !config.in_maintenance_window(SystemTime::now()) {
+            debug!("defer compaction outside the maintenance windows");
+            return;
+        }
+        let Some(revision) = timeline.take_revision_at(retained_since) else {
+            return;
+        };
+        if !self.state.is_leader() {
+            debug!("skip compaction on a follower");
+            return;
+        }
+        if revision <= self.kv_storage.compact_revision() {
+            return;
+        }
+        self.compact(revision, retention).await;
+    }
+
+    /// Propose a compaction at `revision`
+    async fn compact(&self, revision: i64, retention: Duration) {
+        let request = RequestWrapper::from(CompactionRequest {
+            revision,
+            physical: false,
+        });
+        let wrapper = match self.auth_storage.root_token() {
+            Ok(token) => RequestWithToken::new_with_token(request, token),
+            Err(_) => RequestWithToken::new(request),
+        };
+        let propose_id = ProposeId::new(format!("{}-{}", self.state.id(), Uuid::new_v4()));
+        let cmd = Command::new(key_ranges(&wrapper.request), wrapper, propose_id);
+        match self.client.propose_indexed(cmd).await {
+            Ok(_res) => {
+                info!("compacted the history before revision {revision}, {retention:?} is retained")
+            }
+            Err(e) => warn!("failed to compact the history at revision {revision}, {e:?}"),
+        }
+    }
+}
+
+/// Wait for the next tick of `ticker`, or forever if there's no ticker
+async fn tick(ticker: Option<&mut Interval>) {
+    match ticker {
+        Some(ticker) => {
+            let _instant = ticker.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_revision_timeline() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut timeline = RevisionTimeline::default();
+        for (secs, revision) in [(10, 1), (20, 5), (30, 8), (40, 8)] {
+            timeline.record(at(secs), revision);
+        }
+        assert_eq!(timeline.take_revision_at(at(0)), None);
+        assert_eq!(timeline.take_revision_at(at(25)), Some(5));
+        assert_eq!(timeline.take_revision_at(at(25)), None);
+        assert_eq!(timeline.take_revision_at(at(50)), Some(8));
+        assert!(timeline.samples.is_empty());
+    }
+}
@@ -0,0 +1,169 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use anyhow::{anyhow, Result};
+use tokio::sync::watch;
+use tracing::{debug, error, info, warn};
+use utils::config::CorruptCheckConfig;
+
+use crate::{
+    header_gen::HeaderGenerator,
+    rpc::{AlarmType, HashKvRequest, MaintenanceClient},
+    state::State,
+    storage::{alarm_store::AlarmStore, integrity, storage_api::StorageApi},
+};
+
+/// How long the leader waits for the hash of a member, hashing a large kv table is slow
+const HASH_KV_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Periodic corruption check across the members, like etcd's experimental corruption
+/// check. Every interval the leader hashes its kv table up to its current revision and
+/// collects the hashes of the other members at the same revision, the members which have
+/// compacted at another revision than the leader are skipped. The hash held by most
+/// members is taken as the correct one, and the CORRUPT alarm is raised on the leader for
+/// every member which diverges from it, so that the writes are rejected by the cluster.
+#[derive(Debug)]
+pub(crate) struct CorruptChecker<S>
+where
+    S: StorageApi,
+{
+    /// Persistent storage
+    persistent: Arc<S>,
+    /// State of current node
+    state: Arc<State>,
+    /// Header generator, it provides the current revision
+    header_gen: Arc<HeaderGenerator>,
+    /// Alarms of current node
+    alarm_store: Arc<AlarmStore>,
+    /// Interval between two checks
+    interval: Duration,
+}
+
+impl<S> CorruptChecker<S>
+where
+    S: StorageApi,
+{
+    /// New `CorruptChecker`, return `None` if the periodic check is disabled
+    pub(crate) fn new(
+        config: &CorruptCheckConfig,
+        persistent: Arc<S>,
+        state: Arc<State>,
+        header_gen: Arc<HeaderGenerator>,
+        alarm_store: Arc<AlarmStore>,
+    ) -> Option<Self> {
+        config.periodic_check().then(|| Self {
+            persistent,
+            state,
+            header_gen,
+            alarm_store,
+            interval: *config.check_interval(),
+        })
+    }
+
+    /// Check the members every interval until the server shuts down
+    #[allow(clippy::integer_arithmetic)] // Introduced by tokio::select!
+    pub(crate) async fn run(self, mut shutdown_rx: watch::Receiver<bool>) {
+        let mut ticker = tokio::time::interval(self.interval);
+        // the first tick completes immediately, the members may not be serving yet
+        let _ignore = ticker.tick().await;
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {}
+                _ = shutdown_rx.changed() => return,
+            }
+            if !self.state.is_leader() {
+                debug!("skip corruption check on a follower");
+                continue;
+            }
+            if let Err(e) = self.check().await {
+                warn!("failed to check the members for corruption, {e}");
+            }
+        }
+    }
+
+    /// Compare the kv hashes of the members at the current revision of the leader
+    async fn check(&self) -> Result<()> {
+        let revision = self.header_gen.revision();
+        let persistent = Arc::clone(&self.persistent);
+        let (leader_hash, compact_revision) =
+            tokio::task::spawn_blocking(move || integrity::hash_kv(persistent.as_ref(), revision))
+                .await??;
+        let mut hashes = vec![(self.alarm_store.member_id(), leader_hash)];
+        for (name, addr) in self.state.others() {
+            let hash = hash_kv(&self.state, &addr, revision);
+            match tokio::time::timeout(HASH_KV_TIMEOUT, hash).await {
+                Ok(Ok((member_id, hash, member_compact_revision)))
+                    if member_compact_revision == compact_revision =>
+                {
+                    hashes.push((member_id, hash));
+                }
+                Ok(Ok((_, _, member_compact_revision))) => debug!(
+                    "skip member {name} in corruption check, it's compacted at revision \
+                     {member_compact_revision} instead of {compact_revision}"
+                ),
+                // a member which hasn't applied the revision yet is checked next time
+                Ok(Err(e)) => debug!("skip member {name} in corruption check, {e}"),
+                Err(_elapsed) => warn!("member {name} doesn't respond to the corruption check"),
+            }
+        }
+        let majority = majority_hash(&hashes, leader_hash);
+        if hashes.iter().all(|&(_, hash)| hash == majority) {
+            info!(
+                "{} members have the same kv hash {leader_hash} at revision {revision}",
+                hashes.len()
+            );
+            return Ok(());
+        }
+        for &(member_id, hash) in &hashes {
+            if hash != majority {
+                error!(
+                    "member {member_id:x} has kv hash {hash} at revision {revision}, \
+                     most members have {majority}"
+                );
+                let _activated = self.alarm_store.activate_for(member_id, AlarmType::Corrupt);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Get the member id, the kv hash and the compacted revision of a member at `revision`
+async fn hash_kv(state: &State, addr: &str, revision: i64) -> Result<(u64, u32, i64)> {
+    let mut client = MaintenanceClient::new(state.connect(addr).await?);
+    let resp = client
+        .hash_kv(HashKvRequest { revision })
+        .await?
+        .into_inner();
+    let member_id = resp
+        .header
+        .ok_or_else(|| anyhow!("no header in hash kv response"))?
+        .member_id;
+    Ok((member_id, resp.hash, resp.compact_revision))
+}
+
+/// Get the hash held by most members, the leader's hash wins a tie
+fn majority_hash(hashes: &[(u64, u32)], leader_hash: u32) -> u32 {
+    let mut counts: HashMap<u32, usize> = HashMap::new();
+    for &(_, hash) in hashes {
+        let count = counts.entry(hash).or_default();
+        *count = count.saturating_add(1);
+    }
+    let leader_count = counts.get(&leader_hash).copied().unwrap_or_default();
+    counts
+        .into_iter()
+        .filter(|&(hash, count)| hash != leader_hash && count > leader_count)
+        .max_by_key(|&(_, count)| count)
+        .map_or(leader_hash, |(hash, _)| hash)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_majority_hash() {
+        assert_eq!(majority_hash(&[(1, 10), (2, 10), (3, 10)], 10), 10);
+        assert_eq!(majority_hash(&[(1, 10), (2, 10), (3, 11)], 10), 10);
+        assert_eq!(majority_hash(&[(1, 10), (2, 11), (3, 11)], 10), 11);
+        assert_eq!(majority_hash(&[(1, 10), (2, 11)], 10), 10);
+    }
+}
@@ -0,0 +1,254 @@
+use std::{sync::Arc, time::Duration};
+
+use tokio::sync::{mpsc, watch};
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::debug;
+
+use super::command::{APPLIED_INDEX_KEY, META_TABLE};
+use crate::{
+    rpc::{AlarmType, Health, HealthCheckRequest, HealthCheckResponse, ServingStatus},
+    state::State,
+    storage::{alarm_store::AlarmStore, storage_api::StorageApi},
+};
+
+/// Default channel size
+const CHANNEL_SIZE: usize = 128;
+/// Interval between two checks of a health watch
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+/// Services whose health can be checked, the empty name stands for the whole server
+const SERVICES: [&str; 6] = [
+    "",
+    "etcdserverpb.KV",
+    "etcdserverpb.Watch",
+    "etcdserverpb.Lease",
+    "etcdserverpb.Auth",
+    "v3lockpb.Lock",
+];
+
+/// Health Server, implements the `grpc.health.v1` protocol
+#[derive(Debug)]
+pub(crate) struct HealthServer<S>
+where
+    S: StorageApi,
+{
+    /// Persistent storage
+    persistent: Arc<S>,
+    /// State of current node
+    state: Arc<State>,
+    /// Alarms of current node
+    alarm_store: Arc<AlarmStore>,
+    /// Shutdown signal receiver
+    shutdown_rx: watch::Receiver<bool>,
+}
+
+impl<S> HealthServer<S>
+where
+    S: StorageApi,
+{
+    /// New `HealthServer`
+    pub(crate) fn new(
+        persistent: Arc<S>,
+        state: Arc<State>,
+        alarm_store: Arc<AlarmStore>,
+        shutdown_rx: watch::Receiver<bool>,
+    ) -> Self {
+        Self {
+            persistent,
+            state,
+            alarm_store,
+            shutdown_rx,
+        }
+    }
+
+    /// Get the serving status of the server, all services share the same status. The
+    /// server is not serving while the CORRUPT alarm is active, as the writes are rejected.
+    fn status(
+        persistent: &S,
+        state: &State,
+        alarm_store: &AlarmStore,
+        shutdown_rx: &watch::Receiver<bool>,
+    ) -> ServingStatus {
+        if *shutdown_rx.borrow() {
+            debug!("not serving: server is shutting down");
+            return ServingStatus::NotServing;
+        }
+        if let Err(e) = persistent.get_value(META_TABLE, APPLIED_INDEX_KEY) {
+            debug!("not serving: storage is not ready, {e}");
+            return ServingStatus::NotServing;
+        }
+        if state.leader_address().is_none() {
+            debug!("not serving: no leader in the cluster");
+            return ServingStatus::NotServing;
+        }
+        if alarm_store.is_active(AlarmType::Corrupt) {
+            debug!("not serving: the CORRUPT alarm is active");
+            return ServingStatus::NotServing;
+        }
+        ServingStatus::Serving
+    }
+
+    /// Check if the service is known by the health server
+    fn is_known_service(service: &str) -> bool {
+        SERVICES.contains(&service)
+    }
+}
+
+#[tonic::async_trait]
+impl<S> Health for HealthServer<S>
+where
+    S: StorageApi,
+{
+    /// Check gets the serving status of the requested service
+    async fn check(
+        &self,
+        request: tonic::Request<HealthCheckRequest>,
+    ) -> Result<tonic::Response<HealthCheckResponse>, tonic::Status> {
+        let service = request.into_inner().service;
+        if !Self::is_known_service(&service) {
+            return Err(tonic::Status::not_found(format!(
+                "unknown service {service}"
+            )));
+        }
+        let status = Self::status(
+            &self.persistent,
+            &self.state,
+            &self.alarm_store,
+            &self.shutdown_rx,
+        );
+        Ok(tonic::Response::new(HealthCheckResponse {
+            status: status.into(),
+        }))
+    }
+
+    ///Server streaming response type for the Watch method.
+    type WatchStream = ReceiverStream<Result<HealthCheckResponse, tonic::Status>>;
+
+    /// Watch sends the serving status of the requested service, and then sends a new
+    /// one whenever the status changes
+    #[allow(clippy::integer_arithmetic)] // Introduced by tokio::select!
+    async fn watch(
+        &self,
+        request: tonic::Request<HealthCheckRequest>,
+    ) -> Result<tonic::Response<Self::WatchStream>, tonic::Status> {
+        let service = request.into_inner().service;
+        let (tx, rx) = mpsc::channel(CHANNEL_SIZE);
+        let persistent = Arc::clone(&self.persistent);
+        let state = Arc::clone(&self.state);
+        let alarm_store = Arc::clone(&self.alarm_store);
+        let mut shutdown_rx = self.shutdown_rx.clone();
+        let _hd = tokio::spawn(async move {
+            if !Self::is_known_service(&service) {
+                let resp = HealthCheckResponse {
+                    status: ServingStatus::ServiceUnknown.into(),
+                };
+                if tx.send(Ok(resp)).await.is_ok() {
+                    tx.closed().await;
+                }
+                return;
+            }
+            let mut last_status = None;
+            loop {
+                let status = Self::status(&persistent, &state, &alarm_store, &shutdown_rx);
+                if last_status != Some(status) {
+                    let resp = HealthCheckResponse {
+                        status: status.into(),
+                    };
+                    if tx.send(Ok(resp)).await.is_err() {
+                        return;
+                    }
+                    last_status = Some(status);
+                }
+                tokio::select! {
+                    () = tokio::time::sleep(WATCH_INTERVAL) => {}
+                    _ = shutdown_rx.changed() => {}
+                    () = tx.closed() => return,
+                }
+            }
+        });
+        Ok(tonic::Response::new(ReceiverStream::new(rx)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use utils::config::StorageConfig;
+
+    use super::*;
+    use crate::storage::db::DBProxy;
+
+    fn init_health_server(
+        leader_id: Option<String>,
+    ) -> (HealthServer<DBProxy>, watch::Sender<bool>) {
+        let db = DBProxy::open(&StorageConfig::Memory).unwrap();
+        let members = HashMap::from([("node1".to_owned(), "127.0.0.1:2379".to_owned())]);
+        let state = Arc::new(State::new("node1".to_owned(), leader_id, members));
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let alarm_store = Arc::new(AlarmStore::new(1));
+        (
+            HealthServer::new(db, state, alarm_store, shutdown_rx),
+            shutdown_tx,
+        )
+    }
+
+    async fn check(server: &HealthServer<DBProxy>, service: &str) -> Result<i32, tonic::Status> {
+        let request = tonic::Request::new(HealthCheckRequest {
+            service: service.to_owned(),
+        });
+        Ok(server.check(request).await?.into_inner().status)
+    }
+
+    #[tokio::test]
+    async fn test_health_check() -> Result<(), Box<dyn std::error::Error>> {
+        let (server, shutdown_tx) = init_health_server(Some("node1".to_owned()));
+        assert_eq!(check(&server, "").await?, ServingStatus::Serving as i32);
+        assert_eq!(
+            check(&server, "etcdserverpb.KV").await?,
+            ServingStatus::Serving as i32
+        );
+        assert_eq!(
+            check(&server, "unknown").await.unwrap_err().code(),
+            tonic::Code::NotFound
+        );
+
+        shutdown_tx.send(true)?;
+        assert_eq!(check(&server, "").await?, ServingStatus::NotServing as i32);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_health_check_without_leader() -> Result<(), Box<dyn std::error::Error>> {
+        let (server, _shutdown_tx) = init_health_server(None);
+        assert_eq!(check(&server, "").await?, ServingStatus::NotServing as i32);
+        let _ignore = server.state.set_leader_id(Some("node1".to_owned()));
+        assert_eq!(check(&server, "").await?, ServingStatus::Serving as i32);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_health_check_with_corrupt_alarm() -> Result<(), Box<dyn std::error::Error>> {
+        let (server, _shutdown_tx) = init_health_server(Some("node1".to_owned()));
+        let _activated = server.alarm_store.activate_for(2, AlarmType::Corrupt);
+        assert_eq!(check(&server, "").await?, ServingStatus::NotServing as i32);
+        let _deactivated = server.alarm_store.deactivate_for(2, AlarmType::Corrupt);
+        assert_eq!(check(&server, "").await?, ServingStatus::Serving as i32);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_health_watch() -> Result<(), Box<dyn std::error::Error>> {
+        let (server, shutdown_tx) = init_health_server(Some("node1".to_owned()));
+        let request = tonic::Request::new(HealthCheckRequest {
+            service: String::new(),
+        });
+        let mut stream = server.watch(request).await?.into_inner().into_inner();
+        let resp = stream.recv().await.unwrap()?;
+        assert_eq!(resp.status, ServingStatus::Serving as i32);
+
+        shutdown_tx.send(true)?;
+        let resp = stream.recv().await.unwrap()?;
+        assert_eq!(resp.status, ServingStatus::NotServing as i32);
+        Ok(())
+    }
+}
@@ -0,0 +1,62 @@
+use std::{sync::Arc, time::Duration};
+
+use tokio::sync::watch;
+use tracing::{debug, warn};
+
+use crate::storage::{storage_api::StorageApi, KvStore};
+
+/// Interval between two index snapshots
+const SNAPSHOT_INTERVAL: Duration = Duration::from_secs(300);
+/// A snapshot is skipped if fewer revisions than it are applied since the last one
+const MIN_REVISIONS_PER_SNAPSHOT: i64 = 10000;
+
+/// Periodic snapshot of the index. The server recovers the index from the latest snapshot
+/// and the kv table written after it, so it restarts without reading the whole history. A
+/// snapshot is also saved when the server shuts down gracefully.
+#[derive(Debug)]
+pub(crate) struct IndexSnapshotter<S>
+where
+    S: StorageApi,
+{
+    /// KV storage
+    kv_storage: Arc<KvStore<S>>,
+}
+
+impl<S> IndexSnapshotter<S>
+where
+    S: StorageApi,
+{
+    /// New `IndexSnapshotter`
+    pub(crate) fn new(kv_storage: Arc<KvStore<S>>) -> Self {
+        Self { kv_storage }
+    }
+
+    /// Save a snapshot every interval until the server shuts down
+    #[allow(clippy::integer_arithmetic)] // Introduced by tokio::select!
+    pub(crate) async fn run(self, mut shutdown_rx: watch::Receiver<bool>) {
+        let mut ticker = tokio::time::interval(SNAPSHOT_INTERVAL);
+        // the first tick completes immediately, the index is just recovered
+        let _ignore = ticker.tick().await;
+        let mut last_revision = self.kv_storage.revision();
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {}
+                _ = shutdown_rx.changed() => return,
+            }
+            if self.kv_storage.revision().saturating_sub(last_revision) < MIN_REVISIONS_PER_SNAPSHOT
+            {
+                continue;
+            }
+            let kv_storage = Arc::clone(&self.kv_storage);
+            match tokio::task::spawn_blocking(move || kv_storage.save_index_snapshot()).await {
+                Ok(Ok(Some(revision))) => {
+                    debug!("index snapshot is saved at revision {revision}");
+                    last_revision = revision;
+                }
+                Ok(Ok(None)) => debug!("index snapshot is skipped for a concurrent compaction"),
+                Ok(Err(e)) => warn!("failed to save the index snapshot, {e}"),
+                Err(e) => warn!("index snapshot task failed, {e}"),
+            }
+        }
+    }
+}
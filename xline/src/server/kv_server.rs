@@ -1,27 +1,41 @@
-use std::{collections::HashSet, fmt::Debug, sync::Arc};
+use std::{collections::HashSet, fmt::Debug, sync::Arc, time::Duration};
 
-use curp::{client::Client, cmd::ProposeId, error::ProposeError};
+use clippy_utilities::NumericCast;
+
+use curp::{client::Client, cmd::ProposeId};
+use prost::Message;
 use tracing::{debug, instrument};
+use utils::config::{BatchConfig, RequestLimitConfig};
 use uuid::Uuid;
 
 use super::{
+    audit::AuditLog,
     auth_server::get_token,
-    command::{Command, CommandResponse, KeyRange, SyncResponse},
+    batch::ProposalBatcher,
+    command::{key_ranges, propose_err_status, Command, CommandResponse, KeyRange, SyncResponse},
+    listener::client_addr,
+    slow_log::{key_range_summary, SlowRequestTimer},
+    validation::RequestValidator,
 };
 use crate::{
     rpc::{
-        CompactionRequest, CompactionResponse, DeleteRangeRequest, DeleteRangeResponse, Kv,
+        CompactionRequest, CompactionResponse, Compare, CompareResult, CompareTarget,
+        DeleteRangeRequest, DeleteRangeResponse, KeyHistoryRequest, KeyHistoryResponse, Kv,
         KvClient, PutRequest, PutResponse, RangeRequest, RangeResponse, Request, RequestOp,
-        RequestWithToken, RequestWrapper, Response, ResponseOp, SortOrder, SortTarget, TxnRequest,
-        TxnResponse,
+        RequestWithToken, RequestWrapper, Response, ResponseOp, RevisionAtRequest,
+        RevisionAtResponse, TargetUnion, TxnRequest, TxnResponse, UndeleteRequest,
+        UndeleteResponse, WaitRevisionRequest, WaitRevisionResponse,
     },
     state::State,
-    storage::{storage_api::StorageApi, AuthStore, KvStore},
+    storage::{
+        alarm_store::AlarmStore,
+        revision_time::RevisionTimeIndex,
+        storage_api::StorageApi,
+        trash::{TrashEntry, TrashStore},
+        AuthStore, ExecuteError, KvStore,
+    },
 };
 
-/// Default max txn ops
-const DEFAULT_MAX_TXN_OPS: usize = 128;
-
 /// KV Server
 #[derive(Debug)]
 pub(crate) struct KvServer<S>
@@ -38,6 +52,28 @@ where
     name: String,
     /// State of current node
     state: Arc<State>,
+    /// Max number of operations in a txn
+    max_txn_ops: usize,
+    /// Validation of the requests
+    validator: RequestValidator,
+    /// Max size of a request to be proposed
+    max_request_bytes: usize,
+    /// Max size of a written key, 0 means no limit
+    max_key_bytes: usize,
+    /// Max size of a written value, 0 means no limit
+    max_value_bytes: usize,
+    /// Requests slower than it are logged
+    slow_request_threshold: Duration,
+    /// Audit log
+    audit_log: Arc<AuditLog>,
+    /// Batcher of small writes, `None` if the batching is disabled
+    batcher: Option<ProposalBatcher>,
+    /// Alarms of the member
+    alarm_store: Arc<AlarmStore>,
+    /// Trash of the deleted keys
+    trash_store: Arc<TrashStore>,
+    /// Persistent storage, where the trash is kept
+    persistent: Arc<S>,
 }
 
 impl<S> KvServer<S>
@@ -45,22 +81,90 @@ where
     S: StorageApi,
 {
     /// New `KvServer`
+    #[allow(clippy::too_many_arguments)] // all of them are needed
     pub(crate) fn new(
         kv_storage: Arc<KvStore<S>>,
         auth_storage: Arc<AuthStore<S>>,
         state: Arc<State>,
         client: Arc<Client<Command>>,
         name: String,
+        slow_request_threshold: Duration,
+        audit_log: Arc<AuditLog>,
+        batch_config: BatchConfig,
+        request_limit_config: RequestLimitConfig,
+        validator: RequestValidator,
+        alarm_store: Arc<AlarmStore>,
+        trash_store: Arc<TrashStore>,
+        persistent: Arc<S>,
     ) -> Self {
+        let batcher = batch_config
+            .enable()
+            .then(|| ProposalBatcher::new(Arc::clone(&client), name.clone(), batch_config));
         Self {
             kv_storage,
             auth_storage,
             client,
             name,
             state,
+            max_txn_ops: *request_limit_config.max_txn_ops(),
+            validator,
+            max_request_bytes: *request_limit_config.max_request_bytes(),
+            max_key_bytes: *request_limit_config.max_key_bytes(),
+            max_value_bytes: *request_limit_config.max_value_bytes(),
+            slow_request_threshold,
+            audit_log,
+            batcher,
+            alarm_store,
+            trash_store,
+            persistent,
         }
     }
 
+    /// Check if the request to be proposed is larger than `max_request_bytes`, a larger
+    /// request is rejected before it's proposed so that it can't stall the apply loop
+    fn check_request_size<M: Message>(&self, req: &M) -> Result<(), tonic::Status> {
+        if req.encoded_len() > self.max_request_bytes {
+            return Err(ExecuteError::request_too_large().into());
+        }
+        Ok(())
+    }
+
+    /// Check if the key or the value of a put is larger than the limits, a giant key or
+    /// value slows down the replication and the watchers even if the request fits
+    fn check_put_size(
+        req: &PutRequest,
+        max_key_bytes: usize,
+        max_value_bytes: usize,
+    ) -> Result<(), tonic::Status> {
+        if max_key_bytes != 0 && req.key.len() > max_key_bytes {
+            return Err(ExecuteError::key_too_large().into());
+        }
+        if max_value_bytes != 0 && req.value.len() > max_value_bytes {
+            return Err(ExecuteError::value_too_large().into());
+        }
+        Ok(())
+    }
+
+    /// Check the sizes of the puts in a txn, including the ones in the nested txns
+    fn check_txn_put_sizes(
+        req: &TxnRequest,
+        max_key_bytes: usize,
+        max_value_bytes: usize,
+    ) -> Result<(), tonic::Status> {
+        for op in req.success.iter().chain(req.failure.iter()) {
+            match op.request {
+                Some(Request::RequestPut(ref r)) => {
+                    Self::check_put_size(r, max_key_bytes, max_value_bytes)?;
+                }
+                Some(Request::RequestTxn(ref r)) => {
+                    Self::check_txn_put_sizes(r, max_key_bytes, max_value_bytes)?;
+                }
+                Some(Request::RequestRange(_) | Request::RequestDeleteRange(_)) | None => {}
+            }
+        }
+        Ok(())
+    }
+
     /// Parse `ResponseOp`
     pub(crate) fn parse_response_op(response_op: ResponseOp) -> Response {
         if let Some(response) = response_op.response {
@@ -72,38 +176,18 @@ where
 
     /// Generate `Command` proposal from `RequestWrapper`
     fn command_from_request_wrapper(propose_id: ProposeId, wrapper: RequestWithToken) -> Command {
-        #[allow(clippy::wildcard_enum_match_arm)]
-        let key_ranges = match wrapper.request {
-            RequestWrapper::RangeRequest(ref req) => vec![KeyRange {
-                start: req.key.clone(),
-                end: req.range_end.clone(),
-            }],
-            RequestWrapper::PutRequest(ref req) => vec![KeyRange {
-                start: req.key.clone(),
-                end: vec![],
-            }],
-            RequestWrapper::DeleteRangeRequest(ref req) => vec![KeyRange {
-                start: req.key.clone(),
-                end: req.range_end.clone(),
-            }],
-            RequestWrapper::TxnRequest(ref req) => req
-                .compare
-                .iter()
-                .map(|cmp| KeyRange {
-                    start: cmp.key.clone(),
-                    end: cmp.range_end.clone(),
-                })
-                .collect(),
-            _ => unreachable!("Other request should not be sent to this store"),
-        };
-        Command::new(key_ranges, wrapper, propose_id)
+        let keys = key_ranges(&wrapper.request);
+        Command::new(keys, wrapper, propose_id)
     }
 
-    /// Execute `RangeRequest` in current node
-    async fn serializable_range(
+    /// Execute a read only request in current node without proposing it
+    async fn serializable_execute<T>(
         &self,
-        request: tonic::Request<RangeRequest>,
-    ) -> Result<tonic::Response<RangeResponse>, tonic::Status> {
+        request: tonic::Request<T>,
+    ) -> Result<Response, tonic::Status>
+    where
+        T: Into<RequestWrapper>,
+    {
         let wrapper = match get_token(request.metadata()) {
             Some(token) => RequestWithToken::new_with_token(request.into_inner().into(), token),
             None => RequestWithToken::new(request.into_inner().into()),
@@ -111,12 +195,20 @@ where
         self.auth_storage
             .check_permission(&wrapper)
             .await
-            .map_err(|err| tonic::Status::invalid_argument(err.to_string()))?;
+            .map_err(tonic::Status::from)?;
         let cmd_res = self
             .kv_storage
             .execute(&wrapper)
-            .map_err(|e| tonic::Status::internal(format!("Execute failed: {e:?}")))?;
-        let res = Self::parse_response_op(cmd_res.decode().into());
+            .map_err(tonic::Status::from)?;
+        Ok(Self::parse_response_op(cmd_res.decode().into()))
+    }
+
+    /// Execute `RangeRequest` in current node
+    async fn serializable_range(
+        &self,
+        request: tonic::Request<RangeRequest>,
+    ) -> Result<tonic::Response<RangeResponse>, tonic::Status> {
+        let res = self.serializable_execute(request).await?;
         if let Response::ResponseRange(response) = res {
             Ok(tonic::Response::new(response))
         } else {
@@ -124,6 +216,59 @@ where
         }
     }
 
+    /// Get the `TxnResponse` of a txn executed or proposed
+    fn txn_response(res: Response) -> TxnResponse {
+        if let Response::ResponseTxn(response) = res {
+            response
+        } else {
+            panic!("Receive wrong response {res:?} for TxnRequest");
+        }
+    }
+
+    /// Check if a txn only contains serializable ranges, so that it can be
+    /// executed in current node like a serializable `RangeRequest`
+    fn is_serializable_txn(req: &TxnRequest) -> bool {
+        req.success.iter().chain(req.failure.iter()).all(|op| {
+            matches!(
+                op.request,
+                Some(Request::RequestRange(RangeRequest {
+                    serializable: true,
+                    ..
+                }))
+            )
+        })
+    }
+
+    /// Check if a txn only contains ranges, including the ones of its nested txns, so that
+    /// it can be served like a `RangeRequest` instead of being proposed
+    fn is_read_only_txn(req: &TxnRequest) -> bool {
+        req.success
+            .iter()
+            .chain(req.failure.iter())
+            .all(|op| match op.request {
+                Some(Request::RequestRange(_)) => true,
+                Some(Request::RequestTxn(ref txn)) => Self::is_read_only_txn(txn),
+                Some(Request::RequestPut(_) | Request::RequestDeleteRange(_)) | None => false,
+            })
+    }
+
+    /// Propose a write with the others in its batch
+    async fn propose_batched(
+        &self,
+        batcher: &ProposalBatcher,
+        wrapper: RequestWithToken,
+    ) -> Result<(CommandResponse, Option<SyncResponse>), tonic::Status> {
+        // a write without permission would fail the whole batch, so it's rejected here
+        self.auth_storage
+            .check_permission(&wrapper)
+            .await
+            .map_err(tonic::Status::from)?;
+        batcher
+            .propose(wrapper)
+            .await
+            .map(|cmd_res| (cmd_res, None))
+    }
+
     /// Propose request and get result with fast/slow path
     #[instrument(skip(self))]
     async fn propose<T>(
@@ -134,31 +279,44 @@ where
     where
         T: Into<RequestWrapper> + Debug,
     {
+        let source = client_addr(&request);
         let wrapper = match get_token(request.metadata()) {
             Some(token) => RequestWithToken::new_with_token(request.into_inner().into(), token),
             None => RequestWithToken::new(request.into_inner().into()),
         };
-        let propose_id = self.generate_propose_id();
-        let cmd = Self::command_from_request_wrapper(propose_id, wrapper);
-        if use_fast_path {
-            let cmd_res = self.client.propose(cmd).await.map_err(|err| {
-                if let ProposeError::ExecutionError(e) = err {
-                    tonic::Status::invalid_argument(e)
-                } else {
-                    panic!("propose err {err:?}")
-                }
-            })?;
-            Ok((cmd_res, None))
+        self.alarm_store.check_request(&wrapper.request)?;
+        let audit_entry = self.audit_log.entry(
+            &self.auth_storage,
+            &wrapper.request,
+            wrapper.token.as_deref(),
+            source,
+        );
+        let batcher = self
+            .batcher
+            .as_ref()
+            .filter(|_| use_fast_path && ProposalBatcher::is_batchable(&wrapper.request));
+        let res = if let Some(batcher) = batcher {
+            self.propose_batched(batcher, wrapper).await
         } else {
-            let (cmd_res, sync_res) = self.client.propose_indexed(cmd).await.map_err(|err| {
-                if let ProposeError::ExecutionError(e) = err {
-                    tonic::Status::invalid_argument(e)
-                } else {
-                    panic!("propose err {err:?}")
-                }
-            })?;
-            Ok((cmd_res, Some(sync_res)))
+            let propose_id = self.generate_propose_id();
+            let cmd = Self::command_from_request_wrapper(propose_id, wrapper);
+            if use_fast_path {
+                self.client
+                    .propose(cmd)
+                    .await
+                    .map(|cmd_res| (cmd_res, None))
+            } else {
+                self.client
+                    .propose_indexed(cmd)
+                    .await
+                    .map(|(cmd_res, sync_res)| (cmd_res, Some(sync_res)))
+            }
+            .map_err(propose_err_status)
+        };
+        if let Some(entry) = audit_entry {
+            entry.finish(&res);
         }
+        res
     }
 
     /// Update revision of `ResponseHeader`
@@ -197,153 +355,39 @@ where
         ProposeId::new(format!("{}-{}", self.name, Uuid::new_v4()))
     }
 
-    /// Validate range request before handle
-    fn check_range_request(req: &RangeRequest) -> Result<(), tonic::Status> {
-        if req.key.is_empty() {
-            return Err(tonic::Status::invalid_argument("key is not provided"));
-        }
-        if !SortOrder::is_valid(req.sort_order) || !SortTarget::is_valid(req.sort_target) {
-            return Err(tonic::Status::invalid_argument("invalid sort option"));
-        }
-
-        Ok(())
-    }
-
-    /// Validate put request before handle
-    fn check_put_request(req: &PutRequest) -> Result<(), tonic::Status> {
-        if req.key.is_empty() {
-            return Err(tonic::Status::invalid_argument("key is not provided"));
-        }
-        if req.ignore_value && !req.value.is_empty() {
-            return Err(tonic::Status::invalid_argument("value is provided"));
-        }
-        if req.ignore_lease && req.lease != 0 {
-            return Err(tonic::Status::invalid_argument("lease is provided"));
-        }
-
-        Ok(())
-    }
-
-    /// Validate delete range request before handle
-    fn check_delete_range_request(req: &DeleteRangeRequest) -> Result<(), tonic::Status> {
-        if req.key.is_empty() {
-            return Err(tonic::Status::invalid_argument("key is not provided"));
-        }
-
-        Ok(())
-    }
-
-    /// Validate txn request before handle
-    fn check_txn_request(req: &TxnRequest) -> Result<(), tonic::Status> {
-        let opc = req
-            .compare
-            .len()
-            .max(req.success.len())
-            .max(req.failure.len());
-        if opc > DEFAULT_MAX_TXN_OPS {
-            return Err(tonic::Status::invalid_argument(
-                "too many operations in txn request",
-            ));
-        }
-        for c in &req.compare {
-            if c.key.is_empty() {
-                return Err(tonic::Status::invalid_argument("key is not provided"));
-            }
-        }
-        for op in req.success.iter().chain(req.failure.iter()) {
-            if let Some(ref request) = op.request {
-                match *request {
-                    Request::RequestRange(ref r) => Self::check_range_request(r),
-                    Request::RequestPut(ref r) => Self::check_put_request(r),
-                    Request::RequestDeleteRange(ref r) => Self::check_delete_range_request(r),
-                    Request::RequestTxn(ref r) => Self::check_txn_request(r),
-                }?;
-            } else {
-                return Err(tonic::Status::invalid_argument("key not found"));
-            }
-        }
-
-        let _ignore_success = Self::check_intervals(&req.success)?;
-        let _ignore_failure = Self::check_intervals(&req.failure)?;
-
-        Ok(())
-    }
-
-    /// Check if puts and deletes overlap
-    fn check_intervals(
-        ops: &[RequestOp],
-    ) -> Result<(HashSet<&[u8]>, Vec<KeyRange>), tonic::Status> {
-        // TODO: use interval tree is better?
-
-        let mut dels = Vec::new();
-
-        for op in ops {
-            if let Some(Request::RequestDeleteRange(ref req)) = op.request {
-                // collect dels
-                let del = KeyRange {
-                    start: req.key.clone(),
-                    end: req.range_end.clone(),
-                };
-                dels.push(del);
-            }
-        }
-
-        let mut puts: HashSet<&[u8]> = HashSet::new();
-
-        for op in ops {
-            if let Some(Request::RequestTxn(ref req)) = op.request {
-                // handle child txn request
-                let (success_puts, mut success_dels) = Self::check_intervals(&req.success)?;
-                let (failure_puts, mut failure_dels) = Self::check_intervals(&req.failure)?;
-
-                for k in &success_puts {
-                    if !puts.insert(k) {
-                        return Err(tonic::Status::invalid_argument(
-                            "duplicate key given in txn request",
-                        ));
-                    }
-                    if dels.iter().any(|del| del.contains_key(k)) {
-                        return Err(tonic::Status::invalid_argument(
-                            "duplicate key given in txn request",
-                        ));
-                    }
-                }
-
-                for k in failure_puts {
-                    if !puts.insert(k) && !success_puts.contains(k) {
-                        // only keys in the puts and not in the success_puts is overlap
-                        return Err(tonic::Status::invalid_argument(
-                            "duplicate key given in txn request",
-                        ));
-                    }
-                    if dels.iter().any(|del| del.contains_key(k)) {
-                        return Err(tonic::Status::invalid_argument(
-                            "duplicate key given in txn request",
-                        ));
-                    }
-                }
-
-                dels.append(&mut success_dels);
-                dels.append(&mut failure_dels);
-            }
-        }
-
-        for op in ops {
-            if let Some(Request::RequestPut(ref req)) = op.request {
-                // check puts in this level
-                if !puts.insert(&req.key) {
-                    return Err(tonic::Status::invalid_argument(
-                        "duplicate key given in txn request",
-                    ));
-                }
-                if dels.iter().any(|del| del.contains_key(&req.key)) {
-                    return Err(tonic::Status::invalid_argument(
-                        "duplicate key given in txn request",
-                    ));
-                }
+    /// Build the txn restoring the deleted keys, each key is put back by a nested txn only
+    /// if it doesn't exist. The leases of the deleted keys may have expired, so the keys are
+    /// restored without leases.
+    fn undelete_txn(entries: &[TrashEntry]) -> TxnRequest {
+        let restores = entries.iter().map(|entry| {
+            let kv = entry.kv();
+            let absent = Compare {
+                result: CompareResult::Equal as i32,
+                target: CompareTarget::Version as i32,
+                key: kv.key.clone(),
+                range_end: vec![],
+                target_union: Some(TargetUnion::Version(0)),
+            };
+            let put = RequestOp {
+                request: Some(Request::RequestPut(PutRequest {
+                    key: kv.key.clone(),
+                    value: kv.value.clone(),
+                    ..PutRequest::default()
+                })),
+            };
+            RequestOp {
+                request: Some(Request::RequestTxn(TxnRequest {
+                    compare: vec![absent],
+                    success: vec![put],
+                    failure: vec![],
+                })),
             }
+        });
+        TxnRequest {
+            compare: vec![],
+            success: restores.collect(),
+            failure: vec![],
         }
-        Ok((puts, dels))
     }
 
     /// Check if the current node is leader
@@ -364,17 +408,38 @@ where
         request: tonic::Request<RangeRequest>,
     ) -> Result<tonic::Response<RangeResponse>, tonic::Status> {
         debug!("Receive RangeRequest {:?}", request);
+        let mut timer = SlowRequestTimer::new(self.slow_request_threshold);
         let range_req = request.get_ref();
-        Self::check_range_request(range_req)?;
-        if range_req.serializable || self.is_leader() {
-            self.serializable_range(request).await
+        RequestValidator::check_range(range_req)?;
+        timer.set_summary(format!(
+            "range request, {}",
+            key_range_summary(&range_req.key, &range_req.range_end)
+        ));
+        timer.phase("check");
+        let res = if range_req.serializable || self.is_leader() {
+            let res = self.serializable_range(request).await?;
+            timer.phase("execute");
+            res
         } else {
             let leader_addr = self.state.wait_leader().await?;
-            let mut kv_client = KvClient::connect(format!("http://{leader_addr}"))
-                .await
-                .map_err(|e| tonic::Status::internal(format!("Connect to leader error: {e}")))?;
-            kv_client.range(request).await
-        }
+            let mut kv_client =
+                KvClient::new(self.state.connect(&leader_addr).await.map_err(|e| {
+                    tonic::Status::internal(format!("Connect to leader error: {e}"))
+                })?);
+            timer.phase("connect leader");
+            let res = kv_client.range(request).await?;
+            timer.phase("forward");
+            res
+        };
+        let _logged = timer.finish(|| {
+            let response = res.get_ref();
+            format!(
+                "response_count: {}, response_size: {}",
+                response.count,
+                response.encoded_len()
+            )
+        });
+        Ok(res)
     }
 
     /// Put puts the given key into the key-value store.
@@ -386,9 +451,20 @@ where
         request: tonic::Request<PutRequest>,
     ) -> Result<tonic::Response<PutResponse>, tonic::Status> {
         debug!("Receive PutRequest {:?}", request);
-        Self::check_put_request(request.get_ref())?;
+        let mut timer = SlowRequestTimer::new(self.slow_request_threshold);
+        self.check_request_size(request.get_ref())?;
+        RequestValidator::check_put(request.get_ref())?;
+        Self::check_put_size(request.get_ref(), self.max_key_bytes, self.max_value_bytes)?;
+        timer.set_summary(format!(
+            "put request, {}, value_size: {}",
+            key_range_summary(&request.get_ref().key, &[]),
+            request.get_ref().value.len()
+        ));
+        timer.phase("check");
         let is_fast_path = true;
         let (cmd_res, sync_res) = self.propose(request, is_fast_path).await?;
+        timer.phase("propose");
+        let _logged = timer.finish(String::new);
 
         let mut res = Self::parse_response_op(cmd_res.decode().into());
         if let Some(sync_res) = sync_res {
@@ -412,9 +488,17 @@ where
         request: tonic::Request<DeleteRangeRequest>,
     ) -> Result<tonic::Response<DeleteRangeResponse>, tonic::Status> {
         debug!("Receive DeleteRangeRequest {:?}", request);
-        Self::check_delete_range_request(request.get_ref())?;
+        let mut timer = SlowRequestTimer::new(self.slow_request_threshold);
+        self.check_request_size(request.get_ref())?;
+        RequestValidator::check_delete_range(request.get_ref())?;
+        timer.set_summary(format!(
+            "delete range request, {}",
+            key_range_summary(&request.get_ref().key, &request.get_ref().range_end)
+        ));
+        timer.phase("check");
         let is_fast_path = true;
         let (cmd_res, sync_res) = self.propose(request, is_fast_path).await?;
+        timer.phase("propose");
 
         let mut res = Self::parse_response_op(cmd_res.decode().into());
         if let Some(sync_res) = sync_res {
@@ -423,6 +507,7 @@ where
             Self::update_header_revision(&mut res, revision);
         }
         if let Response::ResponseDeleteRange(response) = res {
+            let _logged = timer.finish(|| format!("deleted: {}", response.deleted));
             Ok(tonic::Response::new(response))
         } else {
             panic!("Receive wrong response {res:?} for DeleteRangeRequest");
@@ -439,87 +524,325 @@ where
         request: tonic::Request<TxnRequest>,
     ) -> Result<tonic::Response<TxnResponse>, tonic::Status> {
         debug!("Receive TxnRequest {:?}", request);
-        Self::check_txn_request(request.get_ref())?;
-        let is_fast_path = false; // lock need revision of txn
-        let (cmd_res, sync_res) = self.propose(request, is_fast_path).await?;
-
-        let mut res = Self::parse_response_op(cmd_res.decode().into());
-        if let Some(sync_res) = sync_res {
-            let revision = sync_res.revision();
-            debug!("Get revision {:?} for TxnRequest", revision);
-            Self::update_header_revision(&mut res, revision);
-        }
-        if let Response::ResponseTxn(response) = res {
-            Ok(tonic::Response::new(response))
+        let mut timer = SlowRequestTimer::new(self.slow_request_threshold);
+        self.check_request_size(request.get_ref())?;
+        self.validator.check_txn(request.get_ref())?;
+        Self::check_txn_put_sizes(request.get_ref(), self.max_key_bytes, self.max_value_bytes)?;
+        timer.set_summary(format!(
+            "txn request, compare: {}, success: {}, failure: {}",
+            request.get_ref().compare.len(),
+            request.get_ref().success.len(),
+            request.get_ref().failure.len()
+        ));
+        timer.phase("check");
+        let is_read_only = Self::is_read_only_txn(request.get_ref());
+        let response = if Self::is_serializable_txn(request.get_ref())
+            || (is_read_only && self.is_leader())
+        {
+            let mut res = self.serializable_execute(request).await?;
+            timer.phase("execute");
+            Self::update_header_revision(&mut res, self.kv_storage.revision());
+            Self::txn_response(res)
+        } else if is_read_only {
+            // a read-only txn is served by the leader like a linearizable range
+            let leader_addr = self.state.wait_leader().await?;
+            let mut kv_client =
+                KvClient::new(self.state.connect(&leader_addr).await.map_err(|e| {
+                    tonic::Status::internal(format!("Connect to leader error: {e}"))
+                })?);
+            timer.phase("connect leader");
+            let response = kv_client.txn(request).await?.into_inner();
+            timer.phase("forward");
+            response
         } else {
-            panic!("Receive wrong response {res:?} for TxnRequest");
-        }
+            let is_fast_path = false; // lock need revision of txn
+            let (cmd_res, sync_res) = self.propose(request, is_fast_path).await?;
+            timer.phase("propose and wait synced");
+
+            let mut res = Self::parse_response_op(cmd_res.decode().into());
+            if let Some(sync_res) = sync_res {
+                let revision = sync_res.revision();
+                debug!("Get revision {:?} for TxnRequest", revision);
+                Self::update_header_revision(&mut res, revision);
+            }
+            Self::txn_response(res)
+        };
+        let _logged = timer.finish(|| {
+            format!(
+                "succeeded: {}, response_size: {}",
+                response.succeeded,
+                response.encoded_len()
+            )
+        });
+        Ok(tonic::Response::new(response))
     }
 
     /// Compact compacts the event history in the etcd key-value store. The key-value
     /// store should be periodically compacted or the event history will continue to grow
-    /// indefinitely.
+    /// indefinitely. A physical compaction is responded after the compacted revisions are
+    /// removed from the index and the backend, others once the compaction is accepted.
     #[instrument(skip(self))]
     async fn compact(
         &self,
         request: tonic::Request<CompactionRequest>,
     ) -> Result<tonic::Response<CompactionResponse>, tonic::Status> {
         debug!("Receive CompactionRequest {:?}", request);
-        Err(tonic::Status::new(
-            tonic::Code::Unimplemented,
-            "Not Implemented".to_owned(),
-        ))
+        // the history is removed in the after sync
+        let is_fast_path = !request.get_ref().physical;
+        let (cmd_res, sync_res) = self.propose(request, is_fast_path).await?;
+        let mut res: CompactionResponse = cmd_res.decode().into();
+        if let (Some(sync_res), Some(header)) = (sync_res, res.header.as_mut()) {
+            header.revision = sync_res.revision();
+        }
+        Ok(tonic::Response::new(res))
+    }
+
+    /// WaitRevision blocks until the revision applied by current node reaches the given
+    /// revision, so that the writes before the revision can be read from current node.
+    #[instrument(skip(self))]
+    async fn wait_revision(
+        &self,
+        request: tonic::Request<WaitRevisionRequest>,
+    ) -> Result<tonic::Response<WaitRevisionResponse>, tonic::Status> {
+        debug!("Receive WaitRevisionRequest {:?}", request);
+        let revision = request.into_inner().revision;
+        let res = self.kv_storage.wait_revision(revision).await;
+        Ok(tonic::Response::new(res))
+    }
+
+    /// KeyHistory lists the retained revisions of a key in current node, including its
+    /// deletions. The history of a key can be listed by whom the key can be read by.
+    #[instrument(skip(self))]
+    async fn key_history(
+        &self,
+        request: tonic::Request<KeyHistoryRequest>,
+    ) -> Result<tonic::Response<KeyHistoryResponse>, tonic::Status> {
+        debug!("Receive KeyHistoryRequest {:?}", request);
+        let token = get_token(request.metadata());
+        let req = request.into_inner();
+        let range = RangeRequest {
+            key: req.key.clone(),
+            ..RangeRequest::default()
+        };
+        let wrapper = match token {
+            Some(token) => RequestWithToken::new_with_token(range.into(), token),
+            None => RequestWithToken::new(range.into()),
+        };
+        self.auth_storage
+            .check_permission(&wrapper)
+            .await
+            .map_err(tonic::Status::from)?;
+        let res = self
+            .kv_storage
+            .key_history(&req)
+            .map_err(tonic::Status::from)?;
+        Ok(tonic::Response::new(res))
+    }
+
+    /// Undelete restores the keys in the range from the trash, each key to the value before
+    /// its latest deletion, the keys which exist now are skipped. The keys are restored by a
+    /// txn, so that they need the same permissions as the puts of them.
+    #[instrument(skip(self))]
+    async fn undelete(
+        &self,
+        request: tonic::Request<UndeleteRequest>,
+    ) -> Result<tonic::Response<UndeleteResponse>, tonic::Status> {
+        debug!("Receive UndeleteRequest {:?}", request);
+        if !self.trash_store.is_enabled() {
+            return Err(tonic::Status::failed_precondition("the trash is disabled"));
+        }
+        let range = KeyRange::new(
+            request.get_ref().key.clone(),
+            request.get_ref().range_end.clone(),
+        );
+        let existing: HashSet<_> = self
+            .kv_storage
+            .latest_kvs(&range.start, &range.end)
+            .map_err(tonic::Status::from)?
+            .into_iter()
+            .map(|kv| kv.key)
+            .collect();
+        let mut entries: Vec<_> = self
+            .trash_store
+            .list(self.persistent.as_ref(), &range)
+            .map_err(tonic::Status::from)?
+            .into_iter()
+            .filter(|entry| !existing.contains(&entry.kv().key))
+            .collect();
+        let more = entries.len() > self.max_txn_ops;
+        entries.truncate(self.max_txn_ops);
+        if entries.is_empty() {
+            let res = UndeleteResponse {
+                header: Some(self.kv_storage.gen_header()),
+                restored: 0,
+                more,
+            };
+            return Ok(tonic::Response::new(res));
+        }
+
+        // the txn is proposed with the token of the request
+        let mut txn = tonic::Request::new(Self::undelete_txn(&entries));
+        *txn.metadata_mut() = request.metadata().clone();
+        let is_fast_path = false;
+        let (cmd_res, sync_res) = self.propose(txn, is_fast_path).await?;
+        let mut res = Self::parse_response_op(cmd_res.decode().into());
+        if let Some(sync_res) = sync_res {
+            Self::update_header_revision(&mut res, sync_res.revision());
+        }
+        let response = Self::txn_response(res);
+        let restored = response
+            .responses
+            .iter()
+            .filter(
+                |op| matches!(op.response, Some(Response::ResponseTxn(ref txn)) if txn.succeeded),
+            )
+            .count();
+        Ok(tonic::Response::new(UndeleteResponse {
+            header: response.header,
+            restored: restored.numeric_cast(),
+            more,
+        }))
+    }
+
+    /// RevisionAt resolves the latest revision applied at or before a time in current
+    /// node, by the clock of it when the revisions are applied. The revision is 0 if the
+    /// time is before the first revision recorded.
+    #[instrument(skip(self))]
+    async fn revision_at(
+        &self,
+        request: tonic::Request<RevisionAtRequest>,
+    ) -> Result<tonic::Response<RevisionAtResponse>, tonic::Status> {
+        debug!("Receive RevisionAtRequest {:?}", request);
+        let timestamp = u64::try_from(request.get_ref().timestamp)
+            .map_err(|_ignore| tonic::Status::invalid_argument("invalid timestamp"))?;
+        let revision = RevisionTimeIndex::revision_at(
+            self.persistent.as_ref(),
+            timestamp,
+            self.kv_storage.revision(),
+        )
+        .map_err(tonic::Status::from)?;
+        Ok(tonic::Response::new(RevisionAtResponse {
+            header: Some(self.kv_storage.gen_header()),
+            revision: revision.unwrap_or(0),
+            compact_revision: self.kv_storage.compact_revision(),
+        }))
     }
 }
 
 #[cfg(test)]
 mod test {
+    use bytes::Bytes;
     use engine::memory_engine::MemoryEngine;
 
     use super::*;
     use crate::storage::db::DB;
 
     #[test]
-    fn txn_check() {
+    fn oversized_key_or_value_should_fail() {
+        let put = PutRequest {
+            key: b"foo".to_vec(),
+            value: Bytes::from_static(b"bar"),
+            ..PutRequest::default()
+        };
+        assert!(KvServer::<DB<MemoryEngine>>::check_put_size(&put, 0, 0).is_ok());
+        assert!(KvServer::<DB<MemoryEngine>>::check_put_size(&put, 3, 3).is_ok());
+        let status = KvServer::<DB<MemoryEngine>>::check_put_size(&put, 2, 0).unwrap_err();
+        assert_eq!(status.message(), "etcdserver: key is too large");
+
+        let txn_req = TxnRequest {
+            compare: vec![],
+            success: vec![],
+            failure: vec![RequestOp {
+                request: Some(Request::RequestTxn(TxnRequest {
+                    compare: vec![],
+                    success: vec![RequestOp {
+                        request: Some(Request::RequestPut(put)),
+                    }],
+                    failure: vec![],
+                })),
+            }],
+        };
+        let status = KvServer::<DB<MemoryEngine>>::check_txn_put_sizes(&txn_req, 0, 2).unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        assert_eq!(status.message(), "etcdserver: value is too large");
+    }
+
+    #[test]
+    fn serializable_txn_check() {
+        let range = |serializable| RequestOp {
+            request: Some(Request::RequestRange(RangeRequest {
+                key: b"foo".to_vec(),
+                serializable,
+                ..RangeRequest::default()
+            })),
+        };
+        let txn_req = TxnRequest {
+            compare: vec![],
+            success: vec![range(true)],
+            failure: vec![range(true)],
+        };
+        assert!(KvServer::<DB<MemoryEngine>>::is_serializable_txn(&txn_req));
+        let txn_req = TxnRequest {
+            compare: vec![],
+            success: vec![range(true)],
+            failure: vec![range(false)],
+        };
+        assert!(!KvServer::<DB<MemoryEngine>>::is_serializable_txn(&txn_req));
         let txn_req = TxnRequest {
             compare: vec![],
             success: vec![
+                range(true),
                 RequestOp {
                     request: Some(Request::RequestDeleteRange(DeleteRangeRequest {
-                        key: b"foo1".to_vec(),
+                        key: b"foo".to_vec(),
                         range_end: vec![],
                         prev_kv: false,
                     })),
                 },
-                RequestOp {
-                    request: Some(Request::RequestTxn(TxnRequest {
-                        compare: vec![],
-                        success: vec![RequestOp {
-                            request: Some(Request::RequestPut(PutRequest {
-                                key: b"foo".to_vec(),
-                                value: b"bar".to_vec(),
-                                lease: 0,
-                                prev_kv: false,
-                                ignore_value: false,
-                                ignore_lease: false,
-                            })),
-                        }],
-                        failure: vec![RequestOp {
-                            request: Some(Request::RequestPut(PutRequest {
-                                key: b"foo".to_vec(),
-                                value: b"bar".to_vec(),
-                                lease: 0,
-                                prev_kv: false,
-                                ignore_value: false,
-                                ignore_lease: false,
-                            })),
-                        }],
-                    })),
-                },
             ],
             failure: vec![],
         };
-        let result = KvServer::<DB<MemoryEngine>>::check_txn_request(&txn_req);
-        assert!(result.is_ok());
+        assert!(!KvServer::<DB<MemoryEngine>>::is_serializable_txn(&txn_req));
+    }
+
+    #[test]
+    fn read_only_txn_check() {
+        let range = RequestOp {
+            request: Some(Request::RequestRange(RangeRequest {
+                key: b"foo".to_vec(),
+                ..RangeRequest::default()
+            })),
+        };
+        let put = RequestOp {
+            request: Some(Request::RequestPut(PutRequest {
+                key: b"foo".to_vec(),
+                ..PutRequest::default()
+            })),
+        };
+        let nested = |op: RequestOp| RequestOp {
+            request: Some(Request::RequestTxn(TxnRequest {
+                compare: vec![],
+                success: vec![op],
+                failure: vec![],
+            })),
+        };
+        let txn_req = TxnRequest {
+            compare: vec![],
+            success: vec![range.clone(), nested(range.clone())],
+            failure: vec![range.clone()],
+        };
+        assert!(KvServer::<DB<MemoryEngine>>::is_read_only_txn(&txn_req));
+        let txn_req = TxnRequest {
+            compare: vec![],
+            success: vec![range.clone()],
+            failure: vec![nested(put)],
+        };
+        assert!(!KvServer::<DB<MemoryEngine>>::is_read_only_txn(&txn_req));
+        let txn_req = TxnRequest {
+            compare: vec![],
+            success: vec![range, RequestOp { request: None }],
+            failure: vec![],
+        };
+        assert!(!KvServer::<DB<MemoryEngine>>::is_read_only_txn(&txn_req));
     }
 }
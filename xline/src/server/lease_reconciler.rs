@@ -0,0 +1,239 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use curp::{client::Client, cmd::ProposeId};
+use tokio::sync::watch;
+use tracing::{debug, info, warn};
+use utils::config::LeaseConfig;
+use uuid::Uuid;
+
+use super::command::{key_ranges, Command};
+use crate::{
+    rpc::{
+        Compare, CompareResult, CompareTarget, KeyValue, PutRequest, Request, RequestOp,
+        RequestWithToken, RequestWrapper, TargetUnion, TxnRequest,
+    },
+    state::State,
+    storage::{
+        lease_store::Lease, storage_api::StorageApi, AuthStore, ExecuteError, KvStore, LeaseStore,
+    },
+};
+
+/// Range start and end to get all keys
+const ALL_KEYS: &[u8] = &[0];
+
+/// Periodic reconciliation of the keys and the leases, which may diverge after a crash or a
+/// partial restore. A lease may still hold a key which is no longer attached to it, it's
+/// detached from the lease by every member locally, as the keys of the leases are only kept
+/// in memory. A key may be attached to a lease which no longer exists, the leader detaches
+/// it by proposing a put keeping its value, guarded by its mod revision, so that the keys
+/// written meanwhile are left alone.
+#[derive(Debug)]
+pub(crate) struct LeaseReconciler<S>
+where
+    S: StorageApi,
+{
+    /// KV storage
+    kv_storage: Arc<KvStore<S>>,
+    /// Lease storage
+    lease_storage: Arc<LeaseStore<S>>,
+    /// Auth storage, the repairs are proposed with the root token
+    auth_storage: Arc<AuthStore<S>>,
+    /// Consensus client
+    client: Arc<Client<Command>>,
+    /// State of current node
+    state: Arc<State>,
+    /// Interval between two reconciliations
+    interval: Duration,
+}
+
+impl<S> LeaseReconciler<S>
+where
+    S: StorageApi,
+{
+    /// New `LeaseReconciler`, return `None` if the reconciliation is disabled
+    pub(crate) fn new(
+        config: &LeaseConfig,
+        kv_storage: Arc<KvStore<S>>,
+        lease_storage: Arc<LeaseStore<S>>,
+        auth_storage: Arc<AuthStore<S>>,
+        client: Arc<Client<Command>>,
+        state: Arc<State>,
+    ) -> Option<Self> {
+        let interval = *config.reconcile_interval();
+        (!interval.is_zero()).then(|| Self {
+            kv_storage,
+            lease_storage,
+            auth_storage,
+            client,
+            state,
+            interval,
+        })
+    }
+
+    /// Reconcile the keys and the leases periodically until the server shuts down
+    #[allow(clippy::integer_arithmetic)] // Introduced by tokio::select!
+    pub(crate) async fn run(self, mut shutdown_rx: watch::Receiver<bool>) {
+        let mut ticker = tokio::time::interval(self.interval);
+        // the first tick completes immediately, the storage may be recovering then
+        let _first = ticker.tick().await;
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {}
+                _ = shutdown_rx.changed() => return,
+            }
+            if let Err(e) = self.reconcile().await {
+                warn!("failed to reconcile the keys and the leases, {e}");
+            }
+        }
+    }
+
+    /// Reconcile the keys and the leases once
+    async fn reconcile(&self) -> Result<(), ExecuteError> {
+        // the leases are read before the keys, so that a key attached to a lease after the
+        // keys are read is not taken as detached
+        let leases = self.lease_storage.leases();
+        let kvs = self.kv_storage.latest_kvs(ALL_KEYS, ALL_KEYS)?;
+
+        let stale = stale_attachments(&leases, &kvs);
+        for (lease_id, key) in stale {
+            // check again, the key may be attached to the lease again meanwhile
+            let current = self.kv_storage.latest_kvs(&key, &[])?;
+            if current.first().map_or(false, |kv| kv.lease == lease_id) {
+                continue;
+            }
+            self.lease_storage.detach(lease_id, &key)?;
+            info!(
+                "detached key {:?} from lease {lease_id}, it's not attached to the lease",
+                String::from_utf8_lossy(&key)
+            );
+        }
+
+        if !self.state.is_leader() {
+            debug!("skip the orphaned keys on a follower");
+            return Ok(());
+        }
+        let orphaned = orphaned_keys(&kvs, |lease_id| {
+            self.lease_storage.look_up(lease_id).is_some()
+        });
+        for kv in orphaned {
+            self.detach_orphaned(kv).await;
+        }
+        Ok(())
+    }
+
+    /// Propose a put detaching an orphaned key from its missing lease, it's skipped if the
+    /// key is modified after it's read
+    async fn detach_orphaned(&self, kv: &KeyValue) {
+        let request = RequestWrapper::from(detach_txn(kv));
+        let wrapper = match self.auth_storage.root_token() {
+            Ok(token) => RequestWithToken::new_with_token(request, token),
+            Err(_) => RequestWithToken::new(request),
+        };
+        let propose_id = ProposeId::new(format!("{}-{}", self.state.id(), Uuid::new_v4()));
+        let cmd = Command::new(key_ranges(&wrapper.request), wrapper, propose_id);
+        let key = String::from_utf8_lossy(&kv.key);
+        match self.client.propose_indexed(cmd).await {
+            Ok(_res) => info!(
+                "detached key {key:?} from lease {}, the lease doesn't exist",
+                kv.lease
+            ),
+            Err(e) => warn!(
+                "failed to detach key {key:?} from the missing lease {}, {e:?}",
+                kv.lease
+            ),
+        }
+    }
+}
+
+/// Find the keys held by the leases which are not attached to them
+fn stale_attachments(leases: &[Lease], kvs: &[KeyValue]) -> Vec<(i64, Vec<u8>)> {
+    let attached: HashMap<&[u8], i64> =
+        kvs.iter().map(|kv| (kv.key.as_slice(), kv.lease)).collect();
+    leases
+        .iter()
+        .flat_map(|lease| {
+            lease
+                .keys()
+                .iter()
+                .filter(|key| attached.get(key.as_slice()) != Some(&lease.id()))
+                .map(|key| (lease.id(), key.clone()))
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Find the keys attached to the leases which don't exist
+fn orphaned_keys(kvs: &[KeyValue], lease_exists: impl Fn(i64) -> bool) -> Vec<&KeyValue> {
+    kvs.iter()
+        .filter(|kv| kv.lease != 0 && !lease_exists(kv.lease))
+        .collect()
+}
+
+/// Build the txn detaching a key from its lease, it keeps the value and succeeds only if
+/// the key is not modified after `kv`
+fn detach_txn(kv: &KeyValue) -> TxnRequest {
+    #[allow(clippy::as_conversions)] // This cast is always valid
+    let compare = Compare {
+        result: CompareResult::Equal as i32,
+        target: CompareTarget::Mod as i32,
+        key: kv.key.clone(),
+        range_end: vec![],
+        target_union: Some(TargetUnion::ModRevision(kv.mod_revision)),
+    };
+    let put = PutRequest {
+        key: kv.key.clone(),
+        lease: 0,
+        ignore_value: true,
+        ..PutRequest::default()
+    };
+    TxnRequest {
+        compare: vec![compare],
+        success: vec![RequestOp {
+            request: Some(Request::RequestPut(put)),
+        }],
+        failure: vec![],
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn kv(key: &str, lease: i64) -> KeyValue {
+        KeyValue {
+            key: key.as_bytes().to_vec(),
+            mod_revision: 3,
+            lease,
+            ..KeyValue::default()
+        }
+    }
+
+    #[test]
+    fn test_stale_attachments_and_orphaned_keys_are_found() {
+        let mut lease1 = Lease::new(1, 10);
+        lease1.insert_key(b"a".to_vec());
+        lease1.insert_key(b"b".to_vec());
+        lease1.insert_key(b"c".to_vec());
+        let kvs = vec![kv("a", 1), kv("b", 0), kv("d", 2)];
+        // "b" is no longer attached and "c" doesn't exist
+        assert_eq!(
+            stale_attachments(&[lease1], &kvs),
+            vec![(1, b"b".to_vec()), (1, b"c".to_vec())]
+        );
+        let orphaned: Vec<_> = orphaned_keys(&kvs, |lease_id| lease_id == 1)
+            .into_iter()
+            .map(|kv| kv.key.clone())
+            .collect();
+        assert_eq!(orphaned, vec![b"d".to_vec()]);
+
+        let txn = detach_txn(&kvs[2]);
+        assert_eq!(
+            txn.compare[0].target_union,
+            Some(TargetUnion::ModRevision(3))
+        );
+        assert!(matches!(
+            txn.success[0].request,
+            Some(Request::RequestPut(ref put)) if put.lease == 0 && put.ignore_value
+        ));
+    }
+}
@@ -1,26 +1,33 @@
 use std::{sync::Arc, time::Duration};
 
 use clippy_utilities::Cast;
-use curp::{client::Client, cmd::ProposeId, error::ProposeError};
-use tokio::{sync::mpsc, time};
+use curp::{client::Client, cmd::ProposeId};
+use tokio::{
+    sync::{broadcast::error::RecvError, mpsc, watch},
+    time,
+};
 use tokio_stream::{wrappers::ReceiverStream, StreamExt};
 use tracing::{debug, info, warn};
 use uuid::Uuid;
 
 use super::{
     auth_server::get_token,
-    command::{Command, CommandResponse, KeyRange, SyncResponse},
+    command::{propose_err_status, Command, CommandResponse, KeyRange, SyncResponse},
+    validation::RequestValidator,
 };
 use crate::{
     id_gen::IdGenerator,
     rpc::{
         Lease, LeaseClient, LeaseGrantRequest, LeaseGrantResponse, LeaseKeepAliveRequest,
-        LeaseKeepAliveResponse, LeaseLeasesRequest, LeaseLeasesResponse, LeaseRevokeRequest,
+        LeaseKeepAliveResponse, LeaseLeasesRequest, LeaseLeasesResponse, LeaseModifyTtlRequest,
+        LeaseModifyTtlResponse, LeaseObserveRequest, LeaseObserveResponse, LeaseRevokeRequest,
         LeaseRevokeResponse, LeaseStatus, LeaseTimeToLiveRequest, LeaseTimeToLiveResponse,
         RequestWithToken, RequestWrapper,
     },
     state::State,
-    storage::{storage_api::StorageApi, AuthStore, LeaseStore},
+    storage::{
+        alarm_store::AlarmStore, storage_api::StorageApi, AuthStore, ExecuteError, LeaseStore,
+    },
 };
 
 /// Default channel size
@@ -28,6 +35,11 @@ const CHANNEL_SIZE: usize = 128;
 /// Default Lease Request Time
 const DEFAULT_LEASE_REQUEST_TIME: Duration = Duration::from_millis(500);
 
+/// Status sent to keep alive streams when the server shuts down
+fn shutdown_status() -> tonic::Status {
+    tonic::Status::unavailable("server is shutting down, please retry on another server")
+}
+
 /// Lease Server
 #[derive(Debug)]
 pub(crate) struct LeaseServer<S>
@@ -46,6 +58,12 @@ where
     state: Arc<State>,
     /// Id generator
     id_gen: Arc<IdGenerator>,
+    /// Shutdown signal receiver
+    shutdown_rx: watch::Receiver<bool>,
+    /// Alarms of current node
+    alarm_store: Arc<AlarmStore>,
+    /// Validation of the requests
+    validator: RequestValidator,
 }
 
 impl<S> LeaseServer<S>
@@ -53,6 +71,7 @@ where
     S: StorageApi,
 {
     /// New `LeaseServer`
+    #[allow(clippy::too_many_arguments)] // all of them are needed
     pub(crate) fn new(
         lease_storage: Arc<LeaseStore<S>>,
         auth_storage: Arc<AuthStore<S>>,
@@ -60,6 +79,9 @@ where
         name: String,
         state: Arc<State>,
         id_gen: Arc<IdGenerator>,
+        shutdown_rx: watch::Receiver<bool>,
+        alarm_store: Arc<AlarmStore>,
+        validator: RequestValidator,
     ) -> Arc<Self> {
         let lease_server = Arc::new(Self {
             lease_storage,
@@ -68,6 +90,9 @@ where
             name,
             state,
             id_gen,
+            shutdown_rx,
+            alarm_store,
+            validator,
         });
         let _h = tokio::spawn(Self::revoke_expired_leases_task(Arc::clone(&lease_server)));
         lease_server
@@ -121,8 +146,8 @@ where
         let keys = if let RequestWrapper::LeaseRevokeRequest(ref req) = wrapper.request {
             self.lease_storage
                 .get_keys(req.id)
-                .into_iter()
-                .map(|k| KeyRange::new(k, ""))
+                .iter()
+                .map(|k| KeyRange::new(k.as_slice(), ""))
                 .collect()
         } else {
             vec![]
@@ -148,30 +173,25 @@ where
             Some(token) => RequestWithToken::new_with_token(request.into_inner().into(), token),
             None => RequestWithToken::new(request.into_inner().into()),
         };
+        self.validator.validate(&wrapper.request)?;
+        self.alarm_store.check_request(&wrapper.request)?;
         let propose_id = self.generate_propose_id();
         let cmd = self.command_from_request_wrapper(propose_id, wrapper);
         if use_fast_path {
-            let cmd_res = self.client.propose(cmd).await.map_err(|err| {
-                if let ProposeError::ExecutionError(e) = err {
-                    tonic::Status::invalid_argument(e)
-                } else {
-                    panic!("propose err {err:?}")
-                }
-            })?;
+            let cmd_res = self.client.propose(cmd).await.map_err(propose_err_status)?;
             Ok((cmd_res, None))
         } else {
-            let (cmd_res, sync_res) = self.client.propose_indexed(cmd).await.map_err(|err| {
-                if let ProposeError::ExecutionError(e) = err {
-                    tonic::Status::invalid_argument(e)
-                } else {
-                    panic!("propose err {err:?}")
-                }
-            })?;
+            let (cmd_res, sync_res) = self
+                .client
+                .propose_indexed(cmd)
+                .await
+                .map_err(propose_err_status)?;
             Ok((cmd_res, Some(sync_res)))
         }
     }
 
     /// Handle keep alive at leader
+    #[allow(clippy::integer_arithmetic)] // Introduced by tokio::select!
     async fn leader_keep_alive(
         &self,
         mut request_stream: tonic::Streaming<LeaseKeepAliveRequest>,
@@ -179,8 +199,21 @@ where
         let (response_tx, response_rx) = mpsc::channel(CHANNEL_SIZE);
         let _hd = tokio::spawn({
             let lease_storage = Arc::clone(&self.lease_storage);
+            let mut shutdown_rx = self.shutdown_rx.clone();
             async move {
-                while let Some(req_result) = request_stream.next().await {
+                loop {
+                    let req_result = tokio::select! {
+                        req_result = request_stream.next() => {
+                            let Some(req_result) = req_result else {
+                                break;
+                            };
+                            req_result
+                        }
+                        _ = shutdown_rx.changed() => {
+                            let _ignore = response_tx.send(Err(shutdown_status())).await;
+                            break;
+                        }
+                    };
                     match req_result {
                         Ok(keep_alive_req) => {
                             debug!("Receive LeaseKeepAliveRequest {:?}", keep_alive_req);
@@ -192,11 +225,7 @@ where
                                     ttl,
                                     ..LeaseKeepAliveResponse::default()
                                 })
-                                .map_err(|e| {
-                                    tonic::Status::invalid_argument(format!(
-                                        "Keep alive error: {e}",
-                                    ))
-                                });
+                                .map_err(tonic::Status::from);
                             assert!(
                                 response_tx.send(res).await.is_ok(),
                                 "Command receiver dropped"
@@ -214,15 +243,19 @@ where
     }
 
     /// Handle keep alive at follower
+    #[allow(clippy::integer_arithmetic)] // Introduced by tokio::select!
     async fn follower_keep_alive(
         &self,
         mut request_stream: tonic::Streaming<LeaseKeepAliveRequest>,
     ) -> Result<ReceiverStream<Result<LeaseKeepAliveResponse, tonic::Status>>, tonic::Status> {
         // TODO: refactor stream forward in a easy way
         let leader_addr = self.state.wait_leader().await?;
-        let mut lease_client = LeaseClient::connect(format!("http://{leader_addr}"))
-            .await
-            .map_err(|_e| tonic::Status::internal("Connect to leader error: {e}"))?;
+        let mut lease_client = LeaseClient::new(
+            self.state
+                .connect(&leader_addr)
+                .await
+                .map_err(|_e| tonic::Status::internal("Connect to leader error: {e}"))?,
+        );
 
         let (request_tx, request_rx) = mpsc::channel(CHANNEL_SIZE);
         let (response_tx, response_rx) = mpsc::channel(CHANNEL_SIZE);
@@ -234,19 +267,113 @@ where
             info!("redirect stream closed");
         });
 
+        let mut shutdown_rx = self.shutdown_rx.clone();
         let _client_handle = tokio::spawn(async move {
             let mut stream = lease_client
                 .lease_keep_alive(ReceiverStream::new(request_rx))
                 .await
                 .unwrap_or_else(|e| panic!("Stream redirect to leader failed: {e:?}"))
                 .into_inner();
-            while let Some(res) = stream.next().await {
-                assert!(response_tx.send(res).await.is_ok(), "receiver dropped");
+            loop {
+                tokio::select! {
+                    res = stream.next() => {
+                        let Some(res) = res else {
+                            break;
+                        };
+                        assert!(response_tx.send(res).await.is_ok(), "receiver dropped");
+                    }
+                    _ = shutdown_rx.changed() => {
+                        let _ignore = response_tx.send(Err(shutdown_status())).await;
+                        break;
+                    }
+                }
             }
         });
 
         Ok(ReceiverStream::new(response_rx))
     }
+
+    /// Stream the events of lease `id`, or of all leases if it's 0, at leader
+    #[allow(clippy::integer_arithmetic)] // Introduced by tokio::select!
+    fn leader_observe(
+        &self,
+        id: i64,
+    ) -> ReceiverStream<Result<LeaseObserveResponse, tonic::Status>> {
+        let (response_tx, response_rx) = mpsc::channel(CHANNEL_SIZE);
+        let mut events = self.lease_storage.observe();
+        let state = Arc::clone(&self.state);
+        let mut shutdown_rx = self.shutdown_rx.clone();
+        let _hd = tokio::spawn(async move {
+            let mut leader_changed = Box::pin(state.leader_listener());
+            loop {
+                let res = tokio::select! {
+                    event = events.recv() => match event {
+                        Ok(event) if id != 0 && event.id != id => continue,
+                        Ok(event) => Ok(event),
+                        Err(RecvError::Lagged(n)) => Err(tonic::Status::aborted(format!(
+                            "observer lags behind, {n} lease events are dropped"
+                        ))),
+                        Err(RecvError::Closed) => break,
+                    },
+                    _ = &mut leader_changed => {
+                        if state.is_leader() {
+                            leader_changed = Box::pin(state.leader_listener());
+                            continue;
+                        }
+                        Err(tonic::Status::unavailable(
+                            "leader changed, please observe the leases again",
+                        ))
+                    }
+                    _ = shutdown_rx.changed() => Err(shutdown_status()),
+                };
+                let is_err = res.is_err();
+                if response_tx.send(res).await.is_err() || is_err {
+                    break;
+                }
+            }
+        });
+        ReceiverStream::new(response_rx)
+    }
+
+    /// Forward the observation to the leader at follower, the renewals are only seen by it
+    #[allow(clippy::integer_arithmetic)] // Introduced by tokio::select!
+    async fn follower_observe(
+        &self,
+        request: tonic::Request<LeaseObserveRequest>,
+    ) -> Result<ReceiverStream<Result<LeaseObserveResponse, tonic::Status>>, tonic::Status> {
+        let leader_addr = self.state.wait_leader().await?;
+        let mut lease_client = LeaseClient::new(
+            self.state
+                .connect(&leader_addr)
+                .await
+                .map_err(|e| tonic::Status::internal(format!("Connect to leader error: {e}")))?,
+        );
+        let mut stream = lease_client
+            .lease_observe(request.into_inner())
+            .await?
+            .into_inner();
+        let (response_tx, response_rx) = mpsc::channel(CHANNEL_SIZE);
+        let mut shutdown_rx = self.shutdown_rx.clone();
+        let _hd = tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    res = stream.next() => {
+                        let Some(res) = res else {
+                            break;
+                        };
+                        if response_tx.send(res).await.is_err() {
+                            break;
+                        }
+                    }
+                    _ = shutdown_rx.changed() => {
+                        let _ignore = response_tx.send(Err(shutdown_status())).await;
+                        break;
+                    }
+                }
+            }
+        });
+        Ok(ReceiverStream::new(response_rx))
+    }
 }
 
 #[tonic::async_trait]
@@ -331,12 +458,12 @@ where
             // TODO wait applied index
             let time_to_live_req = request.into_inner();
             let Some(lease) = self.lease_storage.look_up(time_to_live_req.id) else {
-                return Err(tonic::Status::not_found("Lease not found"));
+                return Err(ExecuteError::lease_not_found().into());
             };
 
             let keys = time_to_live_req
                 .keys
-                .then(|| lease.keys())
+                .then(|| lease.keys().iter().cloned().collect())
                 .unwrap_or_default();
             let res = LeaseTimeToLiveResponse {
                 header: Some(self.lease_storage.gen_header()),
@@ -348,9 +475,10 @@ where
             Ok(tonic::Response::new(res))
         } else {
             let leader_addr = self.state.wait_leader().await?;
-            let mut lease_client = LeaseClient::connect(format!("http://{leader_addr}"))
-                .await
-                .map_err(|e| tonic::Status::internal(format!("Connect to leader error: {e}")))?;
+            let mut lease_client =
+                LeaseClient::new(self.state.connect(&leader_addr).await.map_err(|e| {
+                    tonic::Status::internal(format!("Connect to leader error: {e}"))
+                })?);
             lease_client.lease_time_to_live(request).await
         }
     }
@@ -373,4 +501,42 @@ where
         };
         Ok(tonic::Response::new(res))
     }
+
+    ///Server streaming response type for the LeaseObserve method.
+    type LeaseObserveStream = ReceiverStream<Result<LeaseObserveResponse, tonic::Status>>;
+
+    /// LeaseObserve streams the events of leases: granted, renewed, revoked and expired.
+    async fn lease_observe(
+        &self,
+        request: tonic::Request<LeaseObserveRequest>,
+    ) -> Result<tonic::Response<Self::LeaseObserveStream>, tonic::Status> {
+        debug!("Receive LeaseObserveRequest {:?}", request);
+        let response_stream = if self.is_leader() {
+            self.leader_observe(request.get_ref().id)
+        } else {
+            self.follower_observe(request).await?
+        };
+        Ok(tonic::Response::new(response_stream))
+    }
+
+    /// LeaseModifyTTL changes the TTL of a lease and renews it, the keys stay attached.
+    async fn lease_modify_ttl(
+        &self,
+        request: tonic::Request<LeaseModifyTtlRequest>,
+    ) -> Result<tonic::Response<LeaseModifyTtlResponse>, tonic::Status> {
+        debug!("Receive LeaseModifyTtlRequest {:?}", request);
+
+        let is_fast_path = true;
+        let (res, sync_res) = self.propose(request, is_fast_path).await?;
+
+        let mut res: LeaseModifyTtlResponse = res.decode().into();
+        if let Some(sync_res) = sync_res {
+            let revision = sync_res.revision();
+            debug!("Get revision {:?} for LeaseModifyTtlResponse", revision);
+            if let Some(mut header) = res.header.as_mut() {
+                header.revision = revision;
+            }
+        }
+        Ok(tonic::Response::new(res))
+    }
 }
@@ -0,0 +1,269 @@
+use std::{
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+};
+use tokio_rustls::{server::TlsStream, TlsAcceptor};
+use tokio_stream::{wrappers::ReceiverStream, Stream, StreamExt};
+use tonic::transport::server::Connected;
+use tracing::{debug, warn};
+
+use super::{
+    admission::{ConnectionLimiter, ConnectionPermit},
+    peer_tls::PeerAcceptor,
+    proxy_protocol::ProxyProtocol,
+};
+
+/// How long a client may take to send its PROXY protocol header and finish the TLS
+/// handshake
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long a listener waits after it fails to accept a connection, eg. when the file
+/// descriptors run out
+const ACCEPT_ERROR_BACKOFF: Duration = Duration::from_millis(100);
+/// Size of the channel of the accepted connections
+const ACCEPTED_CHANNEL_SIZE: usize = 128;
+
+/// Information of a connection, it's in the extensions of the requests on the connection
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ConnectInfo {
+    /// Address of the client, which is the one in the PROXY protocol header if there is one
+    remote_addr: SocketAddr,
+}
+
+/// Address of the client who sent the request, which is the real client behind a proxy
+/// if the connection starts with a PROXY protocol header
+pub(crate) fn client_addr<T>(request: &tonic::Request<T>) -> Option<SocketAddr> {
+    request
+        .extensions()
+        .get::<ConnectInfo>()
+        .map(|info| info.remote_addr)
+        .or_else(|| request.remote_addr())
+}
+
+/// Transport of an accepted connection
+#[derive(Debug)]
+enum Transport {
+    /// Plaintext connection of a client or a peer
+    Plain(TcpStream),
+    /// TLS connection of a client, or mutual TLS connection of a peer
+    Tls(Box<TlsStream<TcpStream>>),
+}
+
+/// A connection accepted by the server, either on the member listener or the TLS listener
+#[derive(Debug)]
+pub(super) struct ServerIo {
+    /// Transport of the connection
+    transport: Transport,
+    /// Address of the client
+    remote_addr: SocketAddr,
+    /// Admission of the connection, released when the connection is closed
+    _permit: Option<ConnectionPermit>,
+}
+
+impl Connected for ServerIo {
+    type ConnectInfo = ConnectInfo;
+
+    fn connect_info(&self) -> Self::ConnectInfo {
+        ConnectInfo {
+            remote_addr: self.remote_addr,
+        }
+    }
+}
+
+impl AsyncRead for ServerIo {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut().transport {
+            Transport::Plain(ref mut stream) => Pin::new(stream).poll_read(cx, buf),
+            Transport::Tls(ref mut stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for ServerIo {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut().transport {
+            Transport::Plain(ref mut stream) => Pin::new(stream).poll_write(cx, buf),
+            Transport::Tls(ref mut stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut().transport {
+            Transport::Plain(ref mut stream) => Pin::new(stream).poll_flush(cx),
+            Transport::Tls(ref mut stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut().transport {
+            Transport::Plain(ref mut stream) => Pin::new(stream).poll_shutdown(cx),
+            Transport::Tls(ref mut stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut().transport {
+            Transport::Plain(ref mut stream) => Pin::new(stream).poll_write_vectored(cx, bufs),
+            Transport::Tls(ref mut stream) => Pin::new(stream).poll_write_vectored(cx, bufs),
+        }
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        match self.transport {
+            Transport::Plain(ref stream) => stream.is_write_vectored(),
+            Transport::Tls(ref stream) => stream.is_write_vectored(),
+        }
+    }
+}
+
+/// How the accepted connections are set up before they are served
+#[derive(Debug, Clone)]
+struct Setup {
+    /// Acceptor of the TLS handshakes, `None` for plaintext connections
+    acceptor: Option<TlsAcceptor>,
+    /// Acceptor of the mutual TLS handshakes of the peers, it takes the place of
+    /// `acceptor` on the member listener if peer TLS is enabled
+    peer_acceptor: Option<PeerAcceptor>,
+    /// Reader of the PROXY protocol headers
+    proxy: Option<Arc<ProxyProtocol>>,
+    /// Limiter of the client connections
+    limiter: Option<Arc<ConnectionLimiter>>,
+}
+
+impl Setup {
+    /// Admit a connection from the client, return error if it's beyond the limits
+    fn admit(&self, remote_addr: SocketAddr) -> io::Result<Option<ConnectionPermit>> {
+        let Some(ref limiter) = self.limiter else {
+            return Ok(None);
+        };
+        limiter
+            .admit(remote_addr.ip())
+            .map(Some)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "too many connections"))
+    }
+}
+
+/// Connections accepted on the member listener, merged with the connections accepted on
+/// the TLS listener if there is one. The connections on the member listener are over
+/// mutual TLS if there is a peer acceptor. The headers and handshakes run in their own
+/// tasks, so a slow or broken client only delays and closes its own connection. The
+/// connections beyond the limits are closed once the address of the client is known.
+pub(super) fn incoming(
+    listener: TcpListener,
+    peer_acceptor: Option<PeerAcceptor>,
+    tls: Option<(TcpListener, TlsAcceptor)>,
+    proxy: Option<ProxyProtocol>,
+    limiter: Option<Arc<ConnectionLimiter>>,
+) -> impl Stream<Item = io::Result<ServerIo>> {
+    let (tx, rx) = mpsc::channel(ACCEPTED_CHANNEL_SIZE);
+    let setup = Setup {
+        acceptor: None,
+        peer_acceptor,
+        proxy: proxy.map(Arc::new),
+        limiter,
+    };
+    if let Some((tls_listener, acceptor)) = tls {
+        let tls_setup = Setup {
+            acceptor: Some(acceptor),
+            peer_acceptor: None,
+            ..setup.clone()
+        };
+        let _handle = tokio::spawn(accept(tls_listener, tls_setup, tx.clone()));
+    }
+    let _handle = tokio::spawn(accept(listener, setup, tx));
+    ReceiverStream::new(rx).map(Ok)
+}
+
+/// Accept the connections until the server stops taking them
+async fn accept(listener: TcpListener, setup: Setup, tx: mpsc::Sender<ServerIo>) {
+    loop {
+        #[allow(clippy::integer_arithmetic)] // Introduced by tokio::select!
+        let accepted = tokio::select! {
+            res = listener.accept() => res,
+            () = tx.closed() => return,
+        };
+        let (stream, peer) = match accepted {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                warn!("failed to accept a connection, {e}");
+                tokio::time::sleep(ACCEPT_ERROR_BACKOFF).await;
+                continue;
+            }
+        };
+        if setup.acceptor.is_none() && setup.peer_acceptor.is_none() && setup.proxy.is_none() {
+            let permit = match setup.admit(peer) {
+                Ok(permit) => permit,
+                Err(e) => {
+                    debug!("connection from {peer} is refused, {e}");
+                    continue;
+                }
+            };
+            let io = ServerIo {
+                transport: Transport::Plain(stream),
+                remote_addr: peer,
+                _permit: permit,
+            };
+            if tx.send(io).await.is_err() {
+                return;
+            }
+            continue;
+        }
+        let setup = setup.clone();
+        let tx = tx.clone();
+        let _handle = tokio::spawn(async move {
+            let handshake = handshake(stream, peer, setup);
+            match tokio::time::timeout(HANDSHAKE_TIMEOUT, handshake).await {
+                Ok(Ok(io)) => {
+                    let _ignore = tx.send(io).await;
+                }
+                Ok(Err(e)) => debug!("handshake with {peer} failed, {e}"),
+                Err(_elapsed) => debug!("handshake with {peer} timed out"),
+            }
+        });
+    }
+}
+
+/// Read the PROXY protocol header of a new connection, admit it, then finish its TLS
+/// handshake. A peer must present a certificate valid for the address of the member
+/// advertised at the address it connects from, which is the address of the connection
+/// itself rather than the one in a PROXY protocol header.
+async fn handshake(mut stream: TcpStream, peer: SocketAddr, setup: Setup) -> io::Result<ServerIo> {
+    let remote_addr = match setup.proxy {
+        Some(ref proxy) => proxy.read_header(&mut stream, peer).await?,
+        None => peer,
+    };
+    let permit = setup.admit(remote_addr)?;
+    let transport = match (setup.peer_acceptor, setup.acceptor) {
+        (Some(peer_acceptor), _) => {
+            Transport::Tls(Box::new(peer_acceptor.accept(stream, peer).await?))
+        }
+        (None, Some(acceptor)) => Transport::Tls(Box::new(acceptor.accept(stream).await?)),
+        (None, None) => Transport::Plain(stream),
+    };
+    Ok(ServerIo {
+        transport,
+        remote_addr,
+        _permit: permit,
+    })
+}
@@ -4,8 +4,9 @@ use std::{
     time::{SystemTime, UNIX_EPOCH},
 };
 
+use bytes::Bytes;
 use clippy_utilities::{Cast, OverflowArithmetic};
-use curp::{client::Client, cmd::ProposeId, error::ProposeError};
+use curp::{client::Client, cmd::ProposeId};
 use etcd_client::{EventType, WatchOptions};
 use parking_lot::Mutex;
 use tokio::{sync::mpsc, time::Duration};
@@ -15,8 +16,9 @@ use uuid::Uuid;
 
 use super::{
     auth_server::get_token,
-    command::{Command, CommandResponse, KeyRange, SyncResponse},
+    command::{key_ranges, propose_err_status, Command, CommandResponse, KeyRange, SyncResponse},
     kv_server::KvServer,
+    validation::RequestValidator,
 };
 use crate::{
     client::errors::ClientError,
@@ -28,7 +30,7 @@ use crate::{
         TxnResponse, UnlockRequest, UnlockResponse, WatchClient, WatchCreateRequest, WatchRequest,
     },
     state::State,
-    storage::{storage_api::StorageApi, KvStore},
+    storage::{alarm_store::AlarmStore, storage_api::StorageApi, KvStore},
 };
 
 /// Default session ttl
@@ -48,6 +50,10 @@ where
     state: Arc<State>,
     /// Server name
     name: String,
+    /// Alarms of current node
+    alarm_store: Arc<AlarmStore>,
+    /// Validation of the requests
+    validator: RequestValidator,
 }
 
 impl<S> LockServer<S>
@@ -60,12 +66,16 @@ where
         client: Arc<Client<Command>>,
         state: Arc<State>,
         name: String,
+        alarm_store: Arc<AlarmStore>,
+        validator: RequestValidator,
     ) -> Self {
         Self {
             storage,
             client,
             state,
             name,
+            alarm_store,
+            validator,
         }
     }
 
@@ -76,21 +86,7 @@ where
 
     /// Generate `Command` proposal from `Request`
     fn command_from_request_wrapper(propose_id: ProposeId, wrapper: RequestWithToken) -> Command {
-        #[allow(clippy::wildcard_enum_match_arm)]
-        let keys = match wrapper.request {
-            RequestWrapper::DeleteRangeRequest(ref req) => {
-                vec![KeyRange::new(req.key.as_slice(), "")]
-            }
-            RequestWrapper::RangeRequest(ref req) => {
-                vec![KeyRange::new(req.key.as_slice(), req.range_end.as_slice())]
-            }
-            RequestWrapper::TxnRequest(ref req) => req
-                .compare
-                .iter()
-                .map(|cmp| KeyRange::new(cmp.key.as_slice(), cmp.range_end.as_slice()))
-                .collect(),
-            _ => vec![],
-        };
+        let keys = key_ranges(&wrapper.request);
         Command::new(keys, wrapper, propose_id)
     }
 
@@ -108,25 +104,19 @@ where
             Some(token) => RequestWithToken::new_with_token(request.into(), token),
             None => RequestWithToken::new(request.into()),
         };
+        self.validator.validate(&wrapper.request)?;
+        self.alarm_store.check_request(&wrapper.request)?;
         let propose_id = self.generate_propose_id();
         let cmd = Self::command_from_request_wrapper(propose_id, wrapper);
         if use_fast_path {
-            let cmd_res = self.client.propose(cmd).await.map_err(|err| {
-                if let ProposeError::ExecutionError(e) = err {
-                    tonic::Status::invalid_argument(e)
-                } else {
-                    panic!("propose err {err:?}")
-                }
-            })?;
+            let cmd_res = self.client.propose(cmd).await.map_err(propose_err_status)?;
             Ok((cmd_res, None))
         } else {
-            let (cmd_res, sync_res) = self.client.propose_indexed(cmd).await.map_err(|err| {
-                if let ProposeError::ExecutionError(e) = err {
-                    tonic::Status::invalid_argument(e)
-                } else {
-                    panic!("propose err {err:?}")
-                }
-            })?;
+            let (cmd_res, sync_res) = self
+                .client
+                .propose_indexed(cmd)
+                .await
+                .map_err(propose_err_status)?;
             Ok((cmd_res, Some(sync_res)))
         }
     }
@@ -145,7 +135,7 @@ where
         let put = RequestOp {
             request: Some(Request::RequestPut(PutRequest {
                 key: key.as_bytes().to_vec(),
-                value: vec![],
+                value: Bytes::new(),
                 lease: lease_id,
                 ..Default::default()
             })),
@@ -184,9 +174,12 @@ where
     ) -> Result<(), tonic::Status> {
         let rev = my_rev.overflow_sub(1);
         let self_addr = self.state.self_address();
-        let mut watch_client = WatchClient::connect(format!("http://{self_addr}"))
-            .await
-            .map_err(|e| tonic::Status::internal(format!("Connect error: {e}")))?;
+        let mut watch_client = WatchClient::new(
+            self.state
+                .connect(&self_addr)
+                .await
+                .map_err(|e| tonic::Status::internal(format!("Connect error: {e}")))?,
+        );
         loop {
             let range_end = KeyRange::get_prefix(pfx.as_bytes());
             #[allow(clippy::as_conversions)] // this cast is always safe
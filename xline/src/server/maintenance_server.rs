@@ -0,0 +1,319 @@
+use std::sync::Arc;
+
+use clippy_utilities::NumericCast;
+use curp::server::Rpc;
+use tracing::debug;
+
+use super::{
+    auth_server::get_token,
+    command::{Command, APPLIED_INDEX_KEY, META_TABLE},
+    probe_server::ApplyProgress,
+};
+use crate::{
+    header_gen::HeaderGenerator,
+    rpc::{
+        AlarmAction, AlarmMember, AlarmRequest, AlarmResponse, AlarmType, DefragmentRequest,
+        DefragmentResponse, HashKvRequest, HashKvResponse, HashRequest, HashResponse, Maintenance,
+        MoveLeaderRequest, MoveLeaderResponse, SnapshotRequest, SnapshotResponse, StatusRequest,
+        StatusResponse, TableStatsRequest, TableStatsResponse,
+    },
+    state::State,
+    storage::{
+        alarm_store::AlarmStore, integrity, storage_api::StorageApi, table_stats, AuthStore,
+        ExecuteError,
+    },
+};
+
+/// Size of the chunks a snapshot is sent in
+const SNAPSHOT_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Maintenance Server
+#[derive(Debug)]
+pub(crate) struct MaintenanceServer<S>
+where
+    S: StorageApi,
+{
+    /// Auth storage
+    auth_storage: Arc<AuthStore<S>>,
+    /// Persistent storage
+    persistent: Arc<S>,
+    /// Header generator
+    header_gen: Arc<HeaderGenerator>,
+    /// Alarms of the member
+    alarm_store: Arc<AlarmStore>,
+    /// State of the member
+    state: Arc<State>,
+    /// Progress of the apply loop
+    apply_progress: Arc<ApplyProgress>,
+    /// Curp server, for the commit index of the consensus log
+    curp_server: Rpc<Command>,
+}
+
+impl<S> MaintenanceServer<S>
+where
+    S: StorageApi,
+{
+    /// New `MaintenanceServer`
+    pub(crate) fn new(
+        auth_storage: Arc<AuthStore<S>>,
+        persistent: Arc<S>,
+        header_gen: Arc<HeaderGenerator>,
+        alarm_store: Arc<AlarmStore>,
+        state: Arc<State>,
+        apply_progress: Arc<ApplyProgress>,
+        curp_server: Rpc<Command>,
+    ) -> Self {
+        Self {
+            auth_storage,
+            persistent,
+            header_gen,
+            alarm_store,
+            state,
+            apply_progress,
+            curp_server,
+        }
+    }
+
+    /// Get the index of the last log entry applied to the storage
+    fn applied_index(&self) -> Result<u64, ExecuteError> {
+        let Some(index) = self.persistent.get_value(META_TABLE, APPLIED_INDEX_KEY)? else {
+            return Ok(0);
+        };
+        let index = index
+            .try_into()
+            .map_err(|_ignore| ExecuteError::DbError("Invalid applied index".to_owned()))?;
+        Ok(u64::from_le_bytes(index))
+    }
+
+    /// Health problems of the member: the active alarms, the last failure to flush an
+    /// applied entry, and the committed entries waiting to be applied
+    fn errors(&self, commit_index: u64) -> Vec<String> {
+        let mut errors = self.alarm_store.describe();
+        if let Some(failure) = self.apply_progress.last_flush_failure() {
+            errors.push(failure);
+        }
+        let backlog = commit_index.saturating_sub(self.apply_progress.applied_index());
+        if backlog > 0 {
+            errors.push(format!(
+                "apply backlog: {backlog} committed log entries are not applied yet"
+            ));
+        }
+        errors
+    }
+
+    /// Check if the request is sent by a root user
+    fn check_admin<T>(&self, request: &tonic::Request<T>) -> Result<(), tonic::Status> {
+        self.auth_storage
+            .check_admin_token(get_token(request.metadata()).as_deref())
+            .map_err(|e| tonic::Status::permission_denied(e.to_string()))
+    }
+}
+
+#[tonic::async_trait]
+impl<S> Maintenance for MaintenanceServer<S>
+where
+    S: StorageApi,
+{
+    /// Alarm activates, deactivates, and queries alarms regarding cluster health.
+    /// The alarms are local to the member which receives the request, they are the alarms
+    /// raised by the checks of that member. A member id of 0 means the member itself.
+    async fn alarm(
+        &self,
+        request: tonic::Request<AlarmRequest>,
+    ) -> Result<tonic::Response<AlarmResponse>, tonic::Status> {
+        debug!("Receive AlarmRequest {:?}", request);
+        let req = request.get_ref();
+        let action = AlarmAction::from_i32(req.action)
+            .ok_or_else(|| tonic::Status::invalid_argument("invalid alarm action"))?;
+        let alarm = AlarmType::from_i32(req.alarm)
+            .ok_or_else(|| tonic::Status::invalid_argument("invalid alarm type"))?;
+        if action != AlarmAction::Get {
+            self.check_admin(&request)?;
+        }
+        let member_id = if req.member_id == 0 {
+            self.alarm_store.member_id()
+        } else {
+            req.member_id
+        };
+        // like etcd, only the changed alarm is returned when an alarm is activated or
+        // deactivated
+        let changed = match action {
+            AlarmAction::Get => false,
+            AlarmAction::Activate => self.alarm_store.activate_for(member_id, alarm),
+            AlarmAction::Deactivate => self.alarm_store.deactivate_for(member_id, alarm),
+        };
+        let alarms = match action {
+            AlarmAction::Get => self.alarm_store.get(alarm),
+            AlarmAction::Activate | AlarmAction::Deactivate if changed => vec![AlarmMember {
+                member_id,
+                alarm: alarm.into(),
+            }],
+            AlarmAction::Activate | AlarmAction::Deactivate => vec![],
+        };
+        Ok(tonic::Response::new(AlarmResponse {
+            header: Some(self.header_gen.gen_header()),
+            alarms,
+        }))
+    }
+
+    /// Status gets the status of the member. `dbSize` is the bytes allocated by the
+    /// backend, and `dbSizeInUse` is the bytes of the live key-values in it, the difference
+    /// is what a defragmentation may recover. `raftIndex` is the commit index of the
+    /// consensus log, and `errors` lists the active alarms of the cluster, the last failure
+    /// to flush an applied entry and the depth of the apply backlog.
+    async fn status(
+        &self,
+        request: tonic::Request<StatusRequest>,
+    ) -> Result<tonic::Response<StatusResponse>, tonic::Status> {
+        debug!("Receive StatusRequest {:?}", request);
+        let sizes = self
+            .persistent
+            .table_sizes()
+            .map_err(|e| tonic::Status::internal(e.to_string()))?;
+        let (db_size, db_size_in_use) =
+            sizes
+                .iter()
+                .fold((0_u64, 0_u64), |(physical, logical), &(_, size)| {
+                    (
+                        physical.saturating_add(size.physical()),
+                        logical.saturating_add(size.logical()),
+                    )
+                });
+        let raft_applied_index = self
+            .applied_index()
+            .map_err(|e| tonic::Status::internal(e.to_string()))?;
+        let raft_index = self.curp_server.commit_index();
+        let header = self.header_gen.gen_header();
+        Ok(tonic::Response::new(StatusResponse {
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+            db_size: db_size.numeric_cast(),
+            db_size_in_use: db_size_in_use.numeric_cast(),
+            leader: self.state.leader_member_id().unwrap_or(0),
+            raft_term: header.raft_term,
+            raft_index,
+            raft_applied_index,
+            errors: self.errors(raft_index),
+            header: Some(header),
+            ..StatusResponse::default()
+        }))
+    }
+
+    /// Defragment defragments a member's backend database to recover storage space.
+    async fn defragment(
+        &self,
+        request: tonic::Request<DefragmentRequest>,
+    ) -> Result<tonic::Response<DefragmentResponse>, tonic::Status> {
+        debug!("Receive DefragmentRequest {:?}", request);
+        Err(tonic::Status::new(
+            tonic::Code::Unimplemented,
+            "Not Implemented".to_owned(),
+        ))
+    }
+
+    /// Hash computes the hash of whole backend keyspace.
+    async fn hash(
+        &self,
+        request: tonic::Request<HashRequest>,
+    ) -> Result<tonic::Response<HashResponse>, tonic::Status> {
+        debug!("Receive HashRequest {:?}", request);
+        Err(tonic::Status::new(
+            tonic::Code::Unimplemented,
+            "Not Implemented".to_owned(),
+        ))
+    }
+
+    /// HashKV computes the hash of all MVCC keys up to a given revision, or up to the
+    /// current revision if it's not set. The hash is only comparable between xline members.
+    async fn hash_kv(
+        &self,
+        request: tonic::Request<HashKvRequest>,
+    ) -> Result<tonic::Response<HashKvResponse>, tonic::Status> {
+        debug!("Receive HashKvRequest {:?}", request);
+        let header = self.header_gen.gen_header();
+        let revision = match request.get_ref().revision {
+            rev if rev <= 0 => header.revision,
+            rev if rev > header.revision => return Err(ExecuteError::future_revision().into()),
+            rev => rev,
+        };
+        let persistent = Arc::clone(&self.persistent);
+        let (hash, compact_revision) =
+            tokio::task::spawn_blocking(move || integrity::hash_kv(persistent.as_ref(), revision))
+                .await
+                .map_err(|e| tonic::Status::internal(e.to_string()))??;
+        Ok(tonic::Response::new(HashKvResponse {
+            header: Some(header),
+            hash,
+            compact_revision,
+        }))
+    }
+
+    /// Server streaming response type for the Snapshot method.
+    type SnapshotStream =
+        tokio_stream::Iter<std::vec::IntoIter<Result<SnapshotResponse, tonic::Status>>>;
+
+    /// Snapshot sends a snapshot of the entire backend from a member over a stream to a client.
+    /// The snapshot is taken before the first chunk is sent, it has the same format as the
+    /// snapshot files saved by `xline_snapshot`.
+    async fn snapshot(
+        &self,
+        request: tonic::Request<SnapshotRequest>,
+    ) -> Result<tonic::Response<Self::SnapshotStream>, tonic::Status> {
+        debug!("Receive SnapshotRequest {:?}", request);
+        self.check_admin(&request)?;
+        let header = self.header_gen.gen_header();
+        let persistent = Arc::clone(&self.persistent);
+        let snapshot = tokio::task::spawn_blocking(move || {
+            let mut buf = Vec::new();
+            persistent.snapshot(&mut buf).map(|_count| buf)
+        })
+        .await
+        .map_err(|e| tonic::Status::internal(e.to_string()))?
+        .map_err(|e| tonic::Status::internal(e.to_string()))?;
+        let mut remaining = snapshot.len();
+        let responses: Vec<_> = snapshot
+            .chunks(SNAPSHOT_CHUNK_SIZE)
+            .map(|chunk| {
+                remaining = remaining.saturating_sub(chunk.len());
+                Ok(SnapshotResponse {
+                    header: Some(header.clone()),
+                    remaining_bytes: remaining.numeric_cast(),
+                    blob: chunk.to_vec(),
+                })
+            })
+            .collect();
+        Ok(tonic::Response::new(tokio_stream::iter(responses)))
+    }
+
+    /// MoveLeader requests current leader node to transfer its leadership to transferee.
+    async fn move_leader(
+        &self,
+        request: tonic::Request<MoveLeaderRequest>,
+    ) -> Result<tonic::Response<MoveLeaderResponse>, tonic::Status> {
+        debug!("Receive MoveLeaderRequest {:?}", request);
+        Err(tonic::Status::new(
+            tonic::Code::Unimplemented,
+            "Not Implemented".to_owned(),
+        ))
+    }
+
+    /// TableStats gets the statistics of the kv, lease and auth tables of the member, by
+    /// scanning the tables. Only the root users can get them, like the snapshots.
+    async fn table_stats(
+        &self,
+        request: tonic::Request<TableStatsRequest>,
+    ) -> Result<tonic::Response<TableStatsResponse>, tonic::Status> {
+        debug!("Receive TableStatsRequest {:?}", request);
+        self.check_admin(&request)?;
+        let header = self.header_gen.gen_header();
+        let persistent = Arc::clone(&self.persistent);
+        let tables =
+            tokio::task::spawn_blocking(move || table_stats::table_stats(persistent.as_ref()))
+                .await
+                .map_err(|e| tonic::Status::internal(e.to_string()))?
+                .map_err(|e| tonic::Status::internal(e.to_string()))?;
+        Ok(tonic::Response::new(TableStatsResponse {
+            header: Some(header),
+            tables,
+        }))
+    }
+}
@@ -0,0 +1,292 @@
+use std::pin::Pin;
+
+use hyper::body::{Bytes, HttpBody, SizeHint};
+use tonic::{
+    body::BoxBody,
+    codegen::{http, BoxFuture, Context, Poll, Service, StdError},
+    transport::NamedService,
+};
+use utils::config::GrpcConfig;
+
+/// Length of the header of a grpc message, a compression flag followed by a big endian
+/// `u32` length of the message
+const HEADER_LEN: usize = 5;
+
+/// Parser of the grpc messages in a body, it only reads the headers of the messages, so
+/// the messages are checked as the body streams, before any of them is decoded
+#[derive(Debug)]
+struct MessageParser {
+    /// Max length of a message
+    max: usize,
+    /// Header of the current message read so far
+    header: Vec<u8>,
+    /// Bytes left in the current message
+    remaining: usize,
+}
+
+impl MessageParser {
+    /// New `MessageParser`
+    fn new(max: usize) -> Self {
+        Self {
+            max,
+            header: Vec::with_capacity(HEADER_LEN),
+            remaining: 0,
+        }
+    }
+
+    /// Feed a chunk of the body, returns the offset in the chunk and the length of the
+    /// first message larger than the max
+    fn feed(&mut self, chunk: &[u8]) -> Option<(usize, usize)> {
+        let mut rest = chunk;
+        while !rest.is_empty() {
+            if self.remaining > 0 {
+                let (_, after) = rest.split_at(self.remaining.min(rest.len()));
+                self.remaining = self.remaining.saturating_sub(rest.len());
+                rest = after;
+                continue;
+            }
+            let offset = chunk
+                .len()
+                .saturating_sub(rest.len())
+                .saturating_sub(self.header.len());
+            let (header, after) =
+                rest.split_at(HEADER_LEN.saturating_sub(self.header.len()).min(rest.len()));
+            self.header.extend_from_slice(header);
+            rest = after;
+            if let Some(&[_, a, b, c, d]) = self.header.get(..HEADER_LEN) {
+                self.header.clear();
+                let len = usize::try_from(u32::from_be_bytes([a, b, c, d])).unwrap_or(usize::MAX);
+                if len > self.max {
+                    return Some((offset, len));
+                }
+                self.remaining = len;
+            }
+        }
+        None
+    }
+}
+
+/// Body of a request which fails once it carries a message larger than the max
+#[derive(Debug)]
+pub(crate) struct RecvLimitBody<B> {
+    /// Inner body
+    inner: B,
+    /// Parser of the messages
+    parser: MessageParser,
+}
+
+impl<B> HttpBody for RecvLimitBody<B>
+where
+    B: HttpBody<Data = Bytes> + Unpin,
+    B::Error: Into<StdError>,
+{
+    type Data = Bytes;
+    type Error = tonic::Status;
+
+    #[inline]
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let data = match Pin::new(&mut self.inner).poll_data(cx) {
+            Poll::Ready(Some(Ok(data))) => data,
+            Poll::Ready(Some(Err(e))) => {
+                return Poll::Ready(Some(Err(tonic::Status::from_error(e.into()))))
+            }
+            Poll::Ready(None) => return Poll::Ready(None),
+            Poll::Pending => return Poll::Pending,
+        };
+        if let Some((_, len)) = self.parser.feed(&data) {
+            return Poll::Ready(Some(Err(tonic::Status::resource_exhausted(format!(
+                "received message larger than max ({len} vs. {})",
+                self.parser.max
+            )))));
+        }
+        Poll::Ready(Some(Ok(data)))
+    }
+
+    #[inline]
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<http::HeaderMap>, Self::Error>> {
+        Pin::new(&mut self.inner)
+            .poll_trailers(cx)
+            .map_err(|e| tonic::Status::from_error(e.into()))
+    }
+
+    #[inline]
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    #[inline]
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// Body of a response which ends with `RESOURCE_EXHAUSTED` instead of sending a message
+/// larger than the max, the messages before it are still sent
+#[derive(Debug)]
+struct SendLimitBody {
+    /// Inner body
+    inner: BoxBody,
+    /// Parser of the messages
+    parser: MessageParser,
+    /// Status to end the body with once a message is too large
+    exceeded: Option<tonic::Status>,
+}
+
+impl HttpBody for SendLimitBody {
+    type Data = Bytes;
+    type Error = tonic::Status;
+
+    #[inline]
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        if self.exceeded.is_some() {
+            return Poll::Ready(None);
+        }
+        let data = match Pin::new(&mut self.inner).poll_data(cx) {
+            Poll::Ready(Some(Ok(data))) => data,
+            other => return other,
+        };
+        let Some((offset, len)) = self.parser.feed(&data) else {
+            return Poll::Ready(Some(Ok(data)));
+        };
+        self.exceeded = Some(tonic::Status::resource_exhausted(format!(
+            "trying to send message larger than max ({len} vs. {})",
+            self.parser.max
+        )));
+        if offset == 0 {
+            return Poll::Ready(None);
+        }
+        Poll::Ready(Some(Ok(data.slice(..offset))))
+    }
+
+    #[inline]
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<http::HeaderMap>, Self::Error>> {
+        if let Some(ref status) = self.exceeded {
+            return Poll::Ready(status.to_header_map().map(Some));
+        }
+        Pin::new(&mut self.inner).poll_trailers(cx)
+    }
+
+    #[inline]
+    fn is_end_stream(&self) -> bool {
+        self.exceeded.is_none() && self.inner.is_end_stream()
+    }
+
+    #[inline]
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// Service that limits the sizes of the messages received and sent by a grpc service. The
+/// lengths of the messages are checked as the bodies stream, so that a large request fails
+/// before it's buffered and decoded, and a large response isn't sent.
+#[derive(Debug, Clone)]
+pub(crate) struct MessageSizeService<T> {
+    /// Inner grpc service
+    inner: T,
+    /// Max size of a message the server can receive
+    max_recv_msg_size: usize,
+    /// Max size of a message the server can send
+    max_send_msg_size: usize,
+}
+
+impl<T> MessageSizeService<T> {
+    /// New `MessageSizeService`
+    pub(crate) fn new(inner: T, config: &GrpcConfig) -> Self {
+        Self {
+            inner,
+            max_recv_msg_size: *config.max_recv_msg_size(),
+            max_send_msg_size: *config.max_send_msg_size(),
+        }
+    }
+}
+
+impl<T, B> Service<http::Request<B>> for MessageSizeService<T>
+where
+    T: Service<http::Request<RecvLimitBody<B>>, Response = http::Response<BoxBody>>,
+    T::Future: Send + 'static,
+{
+    type Response = T::Response;
+    type Error = T::Error;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    #[inline]
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        let recv_parser = MessageParser::new(self.max_recv_msg_size);
+        let max_send_msg_size = self.max_send_msg_size;
+        let fut = self.inner.call(req.map(|inner| RecvLimitBody {
+            inner,
+            parser: recv_parser,
+        }));
+        Box::pin(async move {
+            let res = fut.await?;
+            Ok(res.map(|inner| {
+                BoxBody::new(SendLimitBody {
+                    inner,
+                    parser: MessageParser::new(max_send_msg_size),
+                    exceeded: None,
+                })
+            }))
+        })
+    }
+}
+
+impl<T: NamedService> NamedService for MessageSizeService<T> {
+    const NAME: &'static str = T::NAME;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Encode a message of `len` bytes with its header
+    fn message(len: usize) -> Vec<u8> {
+        let mut buf = vec![0];
+        buf.extend_from_slice(&u32::try_from(len).unwrap().to_be_bytes());
+        buf.resize(HEADER_LEN + len, 1);
+        buf
+    }
+
+    #[test]
+    fn test_message_parser_finds_large_messages() {
+        let mut parser = MessageParser::new(10);
+        let chunk: Vec<u8> = [message(10), message(0), message(3)].concat();
+        assert_eq!(parser.feed(&chunk), None);
+
+        let chunk: Vec<u8> = [message(4), message(11), message(2)].concat();
+        assert_eq!(parser.feed(&chunk), Some((9, 11)));
+    }
+
+    #[test]
+    fn test_message_parser_across_chunks() {
+        let mut parser = MessageParser::new(10);
+        let body: Vec<u8> = [message(8), message(20)].concat();
+        // the header of the large message is split between the chunks
+        let (first, second) = body.split_at(15);
+        assert_eq!(parser.feed(first), None);
+        assert_eq!(parser.feed(second), Some((0, 20)));
+
+        let mut parser = MessageParser::new(10);
+        for byte in message(10) {
+            assert_eq!(parser.feed(&[byte]), None);
+        }
+        assert_eq!(parser.feed(&message(100)), Some((0, 100)));
+    }
+}
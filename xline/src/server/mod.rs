@@ -1,16 +1,74 @@
+/// Xline admin server
+mod admin_server;
+/// Admission limits of the client connections and requests
+mod admission;
+/// Audit log
+mod audit;
+/// External authentication providers
+mod auth_provider;
 /// Xline auth server
 mod auth_server;
+/// Periodic backup to object storage
+mod backup;
+/// Periodic sync of the backend
+mod backend_syncer;
+/// Batching of small writes
+mod batch;
+/// Xline cluster server
+mod cluster_server;
 /// Command to be executed
 pub(crate) mod command;
+/// Automatic compaction of the kv history
+mod compactor;
+/// Periodic corruption check across the members
+mod corrupt_check;
+/// Xline health server
+mod health_server;
+/// Periodic snapshot of the index
+mod index_snapshotter;
 /// Xline kv server
 mod kv_server;
+/// Reconciliation of the keys and the leases
+mod lease_reconciler;
 /// Xline lease server
 mod lease_server;
+/// Listeners of the client and peer connections
+mod listener;
 /// Xline lock server
 mod lock_server;
+/// Xline maintenance server
+mod maintenance_server;
+/// Limits of the sizes of the grpc messages
+mod message_size;
+/// Checks of a starting member against its peers
+mod peer_check;
+/// Mutual TLS of the peers with the certificates checked against the member addresses
+mod peer_tls;
+/// Readiness and liveness probes
+mod probe_server;
+/// CPU and heap profiling
+mod profiling;
+/// HAProxy PROXY protocol headers of the client connections
+mod proxy_protocol;
+/// Per-client rate limiter
+mod rate_limiter;
+/// Reload of the config file
+mod reload;
+/// Slow request logging
+mod slow_log;
+/// Server side timeout of rpcs
+mod timeout_service;
+/// TLS listener of the clients with hot rotated certificates
+mod tls;
+/// Periodic purge of the trash
+mod trash_purger;
+/// Validation of the requests before they are proposed
+mod validation;
 /// Xline watch server
 mod watch_server;
 /// Xline server
 mod xline_server;
 
-pub use self::xline_server::XlineServer;
+pub use self::{
+    admin_server::LogFilterHandle, command::set_command_wire_version, xline_server::XlineServer,
+};
@@ -0,0 +1,289 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+use tracing::debug;
+use utils::config::InitialClusterState;
+
+use crate::{
+    rpc::{ClusterClient, MaintenanceClient, MemberListRequest, StatusRequest},
+    state::State,
+};
+
+/// How long a starting member waits for the status of a peer
+const PEER_STATUS_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Status of a peer which responds to a starting member
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct PeerStatus {
+    /// Id of the cluster the peer is in
+    pub(crate) cluster_id: u64,
+    /// Index of the last log entry the peer has committed
+    pub(crate) commit_index: u64,
+    /// Ids of the members the peer has, `None` if the peer doesn't list its members
+    pub(crate) member_ids: Option<Vec<u64>>,
+}
+
+impl PeerStatus {
+    /// Get the status of the peer at `addr`
+    async fn fetch(state: &State, addr: &str) -> Result<Self> {
+        let channel = state.connect(addr).await?;
+        let resp = MaintenanceClient::new(channel.clone())
+            .status(StatusRequest {})
+            .await?
+            .into_inner();
+        let cluster_id = resp
+            .header
+            .ok_or_else(|| anyhow!("no header in status response"))?
+            .cluster_id;
+        // a peer of an older version doesn't serve the member list
+        let member_ids = match ClusterClient::new(channel)
+            .member_list(MemberListRequest {
+                linearizable: false,
+            })
+            .await
+        {
+            Ok(members) => Some(
+                members
+                    .into_inner()
+                    .members
+                    .into_iter()
+                    .map(|member| member.id)
+                    .collect(),
+            ),
+            Err(status) if status.code() == tonic::Code::Unimplemented => None,
+            Err(status) => return Err(status.into()),
+        };
+        Ok(Self {
+            cluster_id,
+            commit_index: resp.raft_index,
+            member_ids,
+        })
+    }
+}
+
+/// A member which is checked against its peers before it recovers its storage
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct StartingMember<'a> {
+    /// Name of the member
+    pub(crate) name: &'a str,
+    /// Id of the member
+    pub(crate) member_id: u64,
+    /// Id of the cluster the member starts in
+    pub(crate) cluster_id: u64,
+    /// Whether the storage of the member is empty
+    pub(crate) empty_storage: bool,
+    /// Whether the member bootstraps a new cluster or joins an existing one
+    pub(crate) initial_cluster_state: InitialClusterState,
+    /// Whether the member is the initial leader given by the config
+    pub(crate) is_leader: bool,
+}
+
+impl StartingMember<'_> {
+    /// Ask the other members in `state` for their status and check the member against
+    /// them. The peers are not waited for, as all members may be starting.
+    pub(crate) async fn check_peers(&self, state: &State) -> Result<()> {
+        let peers = state.others();
+        let statuses = futures::future::join_all(peers.iter().map(|(name, addr)| async move {
+            let fetch = PeerStatus::fetch(state, addr);
+            let status = match tokio::time::timeout(PEER_STATUS_TIMEOUT, fetch).await {
+                Ok(Ok(status)) => Some(status),
+                Ok(Err(e)) => {
+                    debug!("member {name} isn't checked for its cluster id, {e}");
+                    None
+                }
+                Err(_elapsed) => {
+                    debug!("member {name} doesn't respond with its cluster id");
+                    None
+                }
+            };
+            (name.as_str(), addr.as_str(), status)
+        }))
+        .await;
+        self.check(&statuses)
+    }
+
+    /// Check the member against the statuses of its peers, given with their names and
+    /// addresses, `None` for a peer which doesn't respond.
+    ///
+    /// The peers which respond must be in the same cluster, and have the member in their
+    /// member sets, a member removed from the cluster and started again with the old
+    /// members, or started with other members than its peers, is refused before it joins
+    /// them.
+    ///
+    /// A member with an empty storage also follows the initial cluster state: joining an
+    /// existing cluster needs one of the peers to respond, so that a member started with a
+    /// stale config doesn't bootstrap a cluster of its own, and the initial leader of a new
+    /// cluster refuses to bootstrap it again once the peers have committed log entries,
+    /// eg. after its data dir is wiped.
+    fn check(&self, statuses: &[(&str, &str, Option<PeerStatus>)]) -> Result<()> {
+        let mut responded = false;
+        let mut committed = None;
+        for &(name, addr, ref status) in statuses {
+            let Some(ref status) = *status else {
+                continue;
+            };
+            if status.cluster_id != self.cluster_id {
+                bail!(
+                    "{} refuses to start, member {name} at {addr} is in cluster {:x}, but this \
+                     member starts in cluster {:x}, it may have been removed from the cluster, \
+                     or its members differ from the members of its peers",
+                    self.name,
+                    status.cluster_id,
+                    self.cluster_id
+                );
+            }
+            if let Some(ref member_ids) = status.member_ids {
+                if !member_ids.contains(&self.member_id) {
+                    bail!(
+                        "{} refuses to start, member {name} at {addr} doesn't have member {:x} in \
+                         its members, it has been removed from the cluster, start it with an \
+                         empty data dir and the initial cluster state existing once it's added \
+                         again",
+                        self.name,
+                        self.member_id
+                    );
+                }
+            }
+            responded = true;
+            if status.commit_index > 0 {
+                committed = Some((name, status.commit_index));
+            }
+        }
+        if !self.empty_storage {
+            return Ok(());
+        }
+        if self.initial_cluster_state == InitialClusterState::Existing && !responded {
+            bail!(
+                "{} refuses to join the existing cluster with an empty data dir, none of its \
+                 peers responds, start it again once they serve, or with the initial cluster \
+                 state new to bootstrap a new cluster",
+                self.name
+            );
+        }
+        if self.initial_cluster_state == InitialClusterState::New && self.is_leader {
+            if let Some((name, commit_index)) = committed {
+                bail!(
+                    "{} refuses to bootstrap the cluster with an empty data dir, member {name} \
+                     has committed log entries up to {commit_index}, start it without is_leader \
+                     and with the initial cluster state existing to rejoin the cluster",
+                    self.name
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const CLUSTER_ID: u64 = 10;
+    const MEMBER_ID: u64 = 1;
+
+    fn member(
+        empty_storage: bool,
+        state: InitialClusterState,
+        is_leader: bool,
+    ) -> StartingMember<'static> {
+        StartingMember {
+            name: "node1",
+            member_id: MEMBER_ID,
+            cluster_id: CLUSTER_ID,
+            empty_storage,
+            initial_cluster_state: state,
+            is_leader,
+        }
+    }
+
+    fn status(commit_index: u64) -> Option<PeerStatus> {
+        Some(PeerStatus {
+            cluster_id: CLUSTER_ID,
+            commit_index,
+            member_ids: Some(vec![MEMBER_ID, 2, 3]),
+        })
+    }
+
+    #[test]
+    fn test_existing_member_with_empty_storage_needs_a_peer() {
+        let joining = member(true, InitialClusterState::Existing, false);
+        let no_response = [
+            ("node2", "127.0.0.1:2380", None),
+            ("node3", "127.0.0.1:2381", None),
+        ];
+        assert!(joining.check(&no_response).is_err());
+        assert!(joining.check(&[]).is_err());
+
+        let one_response = [
+            ("node2", "127.0.0.1:2380", None),
+            ("node3", "127.0.0.1:2381", status(5)),
+        ];
+        assert!(joining.check(&one_response).is_ok());
+
+        // a member with data has joined its cluster already
+        let restarted = member(false, InitialClusterState::Existing, false);
+        assert!(restarted.check(&no_response).is_ok());
+    }
+
+    #[test]
+    fn test_new_leader_refuses_to_bootstrap_committed_peers() {
+        let leader = member(true, InitialClusterState::New, true);
+        let committed = [
+            ("node2", "127.0.0.1:2380", status(0)),
+            ("node3", "127.0.0.1:2381", status(8)),
+        ];
+        assert!(leader.check(&committed).is_err());
+
+        let fresh = [
+            ("node2", "127.0.0.1:2380", status(0)),
+            ("node3", "127.0.0.1:2381", None),
+        ];
+        assert!(leader.check(&fresh).is_ok());
+
+        // neither a follower nor a leader with data bootstraps the cluster
+        let follower = member(true, InitialClusterState::New, false);
+        assert!(follower.check(&committed).is_ok());
+        let restarted = member(false, InitialClusterState::New, true);
+        assert!(restarted.check(&committed).is_ok());
+    }
+
+    #[test]
+    fn test_peers_in_other_clusters_are_refused() {
+        let restarted = member(false, InitialClusterState::New, false);
+        let other_cluster = [(
+            "node2",
+            "127.0.0.1:2380",
+            Some(PeerStatus {
+                cluster_id: 11,
+                commit_index: 3,
+                member_ids: Some(vec![MEMBER_ID, 2]),
+            }),
+        )];
+        assert!(restarted.check(&other_cluster).is_err());
+    }
+
+    #[test]
+    fn test_removed_member_is_refused() {
+        let removed = Some(PeerStatus {
+            cluster_id: CLUSTER_ID,
+            commit_index: 7,
+            member_ids: Some(vec![2, 3]),
+        });
+        let restarted = member(false, InitialClusterState::New, false);
+        let statuses = [
+            ("node2", "127.0.0.1:2380", None),
+            ("node3", "127.0.0.1:2381", removed),
+        ];
+        assert!(restarted.check(&statuses).is_err());
+
+        // a peer which doesn't list its members doesn't refuse the member
+        let unlisted = Some(PeerStatus {
+            cluster_id: CLUSTER_ID,
+            commit_index: 7,
+            member_ids: None,
+        });
+        assert!(restarted
+            .check(&[("node2", "127.0.0.1:2380", unlisted)])
+            .is_ok());
+    }
+}
@@ -0,0 +1,557 @@
+use std::{
+    collections::HashMap,
+    fmt, io,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::SystemTime,
+};
+
+use anyhow::{anyhow, bail, Result};
+use tokio::net::{self, TcpStream};
+use tokio_rustls::{
+    rustls::{
+        client::{ResolvesClientCert, ServerCertVerified, ServerCertVerifier},
+        server::{AllowAnyAuthenticatedClient, ResolvesServerCert},
+        Certificate, ClientConfig, Error as TlsError, RootCertStore, ServerConfig, ServerName,
+    },
+    server::TlsStream,
+    TlsAcceptor,
+};
+use tonic::transport::ClientTlsConfig;
+use tracing::debug;
+use utils::config::PeerTlsConfig;
+use x509_parser::extensions::GeneralName;
+
+use super::tls::{CertReloader, CertResolver};
+use crate::state::State;
+
+/// Signature algorithms of the certificates of the peers
+static SUPPORTED_SIG_ALGS: &[&webpki::SignatureAlgorithm] = &[
+    &webpki::ECDSA_P256_SHA256,
+    &webpki::ECDSA_P256_SHA384,
+    &webpki::ECDSA_P384_SHA256,
+    &webpki::ECDSA_P384_SHA384,
+    &webpki::ED25519,
+    &webpki::RSA_PSS_2048_8192_SHA256_LEGACY_KEY,
+    &webpki::RSA_PSS_2048_8192_SHA384_LEGACY_KEY,
+    &webpki::RSA_PSS_2048_8192_SHA512_LEGACY_KEY,
+    &webpki::RSA_PKCS1_2048_8192_SHA256,
+    &webpki::RSA_PKCS1_2048_8192_SHA384,
+    &webpki::RSA_PKCS1_2048_8192_SHA512,
+    &webpki::RSA_PKCS1_3072_8192_SHA384,
+];
+
+/// Mutual TLS of the peers. A member presents its certificate both to the peers it connects
+/// to and to the peers which connect to it, and the certificate of a peer is only trusted if
+/// it's issued by the CA and valid for the address of the member advertised where the peer
+/// connects from, so a node holding another certificate of the CA can't pose as a member.
+#[derive(Debug)]
+pub(super) struct PeerTls {
+    /// DER encoded CA certificates
+    ca_certs: Vec<Vec<u8>>,
+    /// Resolver of the member certificate, shared with its reloader
+    resolver: Arc<CertResolver>,
+}
+
+impl PeerTls {
+    /// New `PeerTls` along with the reloader of the member certificate, return `None` if
+    /// peer TLS is disabled
+    ///
+    /// # Errors
+    ///
+    /// Return error if the files can't be read or they don't hold a valid certificate,
+    /// private key and CA certificates
+    pub(super) async fn new(config: &PeerTlsConfig) -> Result<Option<(Self, CertReloader)>> {
+        if !*config.enable() {
+            return Ok(None);
+        }
+        let ca_pem = tokio::fs::read(config.ca_file()).await?;
+        let ca_certs = rustls_pemfile::certs(&mut &*ca_pem)?;
+        if ca_certs.is_empty() {
+            bail!("no certificate is found in the CA file");
+        }
+        let reloader = CertReloader::load(
+            config.cert_file().clone(),
+            config.key_file().clone(),
+            *config.reload_interval(),
+        )
+        .await?;
+        let peer_tls = Self {
+            ca_certs,
+            resolver: reloader.resolver(),
+        };
+        Ok(Some((peer_tls, reloader)))
+    }
+
+    /// Acceptor of the connections on the member listener, which checks the certificates
+    /// of the peers against the addresses of the members in `state`
+    ///
+    /// # Errors
+    ///
+    /// Return error if a CA certificate is invalid
+    pub(super) fn acceptor(&self, state: Arc<State>) -> Result<PeerAcceptor> {
+        let mut roots = RootCertStore::empty();
+        for cert in &self.ca_certs {
+            roots
+                .add(&Certificate(cert.clone()))
+                .map_err(|e| anyhow!("invalid CA certificate, {e:?}"))?;
+        }
+        let resolver: Arc<dyn ResolvesServerCert> = Arc::clone(&self.resolver);
+        let mut config = ServerConfig::builder()
+            .with_safe_defaults()
+            .with_client_cert_verifier(AllowAnyAuthenticatedClient::new(roots))
+            .with_cert_resolver(resolver);
+        config.alpn_protocols = vec![b"h2".to_vec()];
+        Ok(PeerAcceptor {
+            acceptor: TlsAcceptor::from(Arc::new(config)),
+            state,
+        })
+    }
+
+    /// TLS config of the connections to the peers
+    pub(super) fn client_config(&self) -> ClientTlsConfig {
+        ClientTlsConfig::new().rustls_client_config(self.rustls_client_config())
+    }
+
+    /// Rustls config of the connections to the peers, the certificate of a peer must be
+    /// valid for the address it's connected at
+    fn rustls_client_config(&self) -> ClientConfig {
+        let verifier = Arc::new(PeerCertVerifier {
+            ca_certs: self.ca_certs.clone(),
+        });
+        let resolver: Arc<dyn ResolvesClientCert> = Arc::clone(&self.resolver);
+        ClientConfig::builder()
+            .with_safe_defaults()
+            .with_custom_certificate_verifier(verifier)
+            .with_client_cert_resolver(resolver)
+    }
+}
+
+/// Acceptor of the mutual TLS handshakes on the member listener
+#[derive(Clone)]
+pub(super) struct PeerAcceptor {
+    /// Acceptor which requires a certificate issued by the CA
+    acceptor: TlsAcceptor,
+    /// State of current node, which has the addresses of the members
+    state: Arc<State>,
+}
+
+impl fmt::Debug for PeerAcceptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PeerAcceptor").finish_non_exhaustive()
+    }
+}
+
+impl PeerAcceptor {
+    /// Finish the handshake of a peer connecting from `remote_addr`, the connection is
+    /// refused unless a member is advertised at the IP address of the peer, and the
+    /// certificate of the peer is valid for the address of that member, so the certificate
+    /// of a member can't be used from another host. The addresses are the current ones, so
+    /// a member moved by `MemberUpdate` is accepted at its new address.
+    pub(super) async fn accept(
+        &self,
+        stream: TcpStream,
+        remote_addr: SocketAddr,
+    ) -> io::Result<TlsStream<TcpStream>> {
+        let members = members_at(self.state.members(), remote_addr.ip()).await;
+        if members.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("no member is advertised at {}", remote_addr.ip()),
+            ));
+        }
+        let stream = self.acceptor.accept(stream).await?;
+        let member = stream
+            .get_ref()
+            .1
+            .peer_certificates()
+            .and_then(<[Certificate]>::first)
+            .and_then(|cert| member_of(&cert.0, &members));
+        if member.is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!(
+                    "the peer certificate is not valid for the member advertised at {}",
+                    remote_addr.ip()
+                ),
+            ));
+        }
+        Ok(stream)
+    }
+}
+
+/// Verifier of the certificates of the peers a member connects to. A certificate must be
+/// issued by the CA and valid for the address the peer is connected at, which may be an IP
+/// address like the addresses of the members.
+struct PeerCertVerifier {
+    /// DER encoded CA certificates
+    ca_certs: Vec<Vec<u8>>,
+}
+
+impl ServerCertVerifier for PeerCertVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, TlsError> {
+        let anchors = self
+            .ca_certs
+            .iter()
+            .map(|cert| webpki::TrustAnchor::try_from_cert_der(cert))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| TlsError::General(format!("invalid CA certificate, {e:?}")))?;
+        let intermediates: Vec<&[u8]> =
+            intermediates.iter().map(|cert| cert.0.as_slice()).collect();
+        let time =
+            webpki::Time::try_from(now).map_err(|_ignore| TlsError::FailedToGetCurrentTime)?;
+        webpki::EndEntityCert::try_from(end_entity.0.as_slice())
+            .and_then(|cert| {
+                cert.verify_is_valid_tls_server_cert(
+                    SUPPORTED_SIG_ALGS,
+                    &webpki::TlsServerTrustAnchors(&anchors),
+                    &intermediates,
+                    time,
+                )
+            })
+            .map_err(|e| TlsError::InvalidCertificateData(format!("{e:?}")))?;
+        #[allow(clippy::wildcard_enum_match_arm)] // the types of the names are non-exhaustive
+        let host = match *server_name {
+            ServerName::DnsName(ref name) => name.as_ref().to_owned(),
+            ServerName::IpAddress(ip) => ip.to_string(),
+            _ => return Err(TlsError::UnsupportedNameType),
+        };
+        if !SubjectAltNames::parse(&end_entity.0).map_or(false, |names| names.matches(&host)) {
+            return Err(TlsError::InvalidCertificateData(format!(
+                "the peer certificate is not valid for {host}"
+            )));
+        }
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+/// Name of the member whose address the DER encoded certificate is valid for, `members`
+/// is a map from the names of the members to their addresses
+fn member_of(cert: &[u8], members: &HashMap<String, String>) -> Option<String> {
+    let names = SubjectAltNames::parse(cert)?;
+    members
+        .iter()
+        .find(|&(_, addr)| names.matches(host(addr)))
+        .map(|(name, _)| name.clone())
+}
+
+/// Members of `members` advertised at `ip`, the host names of their addresses are
+/// resolved, and a member whose host name can't be resolved isn't at any address
+async fn members_at(members: HashMap<String, String>, ip: IpAddr) -> HashMap<String, String> {
+    let ip = canonical(ip);
+    let mut found = HashMap::new();
+    for (name, addr) in members {
+        let advertised = match host(&addr).parse::<IpAddr>() {
+            Ok(host_ip) => canonical(host_ip) == ip,
+            Err(_) => match net::lookup_host((host(&addr), 0)).await {
+                Ok(mut resolved) => {
+                    resolved.any(|resolved_addr| canonical(resolved_addr.ip()) == ip)
+                }
+                Err(e) => {
+                    debug!("the address {addr} of member {name} isn't resolved, {e}");
+                    false
+                }
+            },
+        };
+        if advertised {
+            let _prev = found.insert(name, addr);
+        }
+    }
+    found
+}
+
+/// IPv4 address of an IPv4-mapped IPv6 address, eg. of a peer accepted on a dual-stack
+/// listener, the other addresses are kept
+fn canonical(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+        IpAddr::V4(_) => ip,
+    }
+}
+
+/// Host of an address, eg. `10.0.0.1` of `10.0.0.1:2380`, and `::1` of `[::1]:2380`
+fn host(addr: &str) -> &str {
+    let host = addr.rsplit_once(':').map_or(addr, |(host, _port)| host);
+    host.strip_prefix('[')
+        .and_then(|host| host.strip_suffix(']'))
+        .unwrap_or(host)
+}
+
+/// DNS names and IP addresses in the subject alternative names of a certificate
+#[derive(Debug, Default, PartialEq, Eq)]
+struct SubjectAltNames {
+    /// DNS names, which may start with a wildcard label
+    dns_names: Vec<String>,
+    /// IP addresses
+    ips: Vec<IpAddr>,
+}
+
+impl SubjectAltNames {
+    /// Parse the names of a DER encoded certificate, return `None` if it's invalid
+    #[allow(clippy::wildcard_enum_match_arm)] // the other names are not addresses
+    fn parse(cert: &[u8]) -> Option<Self> {
+        let (_, cert) = x509_parser::parse_x509_certificate(cert).ok()?;
+        let mut names = Self::default();
+        let Some(san) = cert.subject_alternative_name().ok()? else {
+            return Some(names);
+        };
+        for name in &san.value.general_names {
+            match *name {
+                GeneralName::DNSName(dns_name) => names.dns_names.push(dns_name.to_owned()),
+                GeneralName::IPAddress(octets) => {
+                    if let Ok(v4) = <[u8; 4]>::try_from(octets) {
+                        names.ips.push(IpAddr::from(v4));
+                    } else if let Ok(v6) = <[u8; 16]>::try_from(octets) {
+                        names.ips.push(IpAddr::from(v6));
+                    }
+                }
+                _ => {}
+            }
+        }
+        Some(names)
+    }
+
+    /// Whether one of the names matches `host`, an IP address only matches the IP
+    /// addresses, and a DNS name matches the DNS names case-insensitively, where a wildcard
+    /// only matches the left-most label
+    fn matches(&self, host: &str) -> bool {
+        if let Ok(ip) = host.parse::<IpAddr>() {
+            return self.ips.contains(&ip);
+        }
+        let host = host.strip_suffix('.').unwrap_or(host);
+        self.dns_names
+            .iter()
+            .any(|name| match name.strip_prefix("*.") {
+                Some(domain) => host.split_once('.').map_or(false, |(label, rest)| {
+                    !label.is_empty() && rest.eq_ignore_ascii_case(domain)
+                }),
+                None => name.eq_ignore_ascii_case(host),
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{path::PathBuf, time::Duration};
+
+    use rcgen::{BasicConstraints, CertificateParams, IsCa, SanType};
+    use tokio::net::{TcpListener, TcpSocket};
+    use tokio_rustls::TlsConnector;
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn params(dns_names: &[&str], ips: &[&str]) -> CertificateParams {
+        let mut params =
+            CertificateParams::new(dns_names.iter().map(|&name| name.to_owned()).collect());
+        params
+            .subject_alt_names
+            .extend(ips.iter().map(|ip| SanType::IpAddress(ip.parse().unwrap())));
+        params
+    }
+
+    fn cert_der(dns_names: &[&str], ips: &[&str]) -> Vec<u8> {
+        rcgen::Certificate::from_params(params(dns_names, ips))
+            .unwrap()
+            .serialize_der()
+            .unwrap()
+    }
+
+    fn members(members: &[(&str, &str)]) -> HashMap<String, String> {
+        members
+            .iter()
+            .map(|&(name, addr)| (name.to_owned(), addr.to_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn test_certificate_should_match_the_address_of_a_member() {
+        let members = members(&[
+            ("node1", "node1.xline.svc:2380"),
+            ("node2", "10.0.0.2:2380"),
+            ("node3", "[fd00::3]:2380"),
+        ]);
+        let node1 = cert_der(&["NODE1.xline.svc"], &[]);
+        assert_eq!(member_of(&node1, &members).as_deref(), Some("node1"));
+        let node2 = cert_der(&["node2"], &["10.0.0.2"]);
+        assert_eq!(member_of(&node2, &members).as_deref(), Some("node2"));
+        let node3 = cert_der(&[], &["fd00::3"]);
+        assert_eq!(member_of(&node3, &members).as_deref(), Some("node3"));
+        let wildcard = cert_der(&["*.xline.svc"], &[]);
+        assert_eq!(member_of(&wildcard, &members).as_deref(), Some("node1"));
+
+        // another certificate of the CA isn't valid for any member
+        let impostor = cert_der(&["client.xline.svc", "10.0.0.2.xline.svc"], &["10.0.0.9"]);
+        assert_eq!(member_of(&impostor, &members), None);
+        let deep_wildcard = cert_der(&["*.svc"], &[]);
+        assert_eq!(member_of(&deep_wildcard, &members), None);
+        assert_eq!(member_of(b"not a certificate", &members), None);
+    }
+
+    #[tokio::test]
+    async fn test_peer_should_match_the_member_advertised_at_its_address() {
+        let members = members(&[
+            ("node1", "10.0.0.1:2380"),
+            ("node2", "10.0.0.2:2380"),
+            ("node3", "localhost:2380"),
+            ("node4", "node4.invalid:2380"),
+        ]);
+        let node1 = cert_der(&["node1.xline.svc"], &["10.0.0.1"]);
+        let node2 = cert_der(&["node2.xline.svc"], &["10.0.0.2"]);
+
+        let at_node1 = members_at(members.clone(), "10.0.0.1".parse().unwrap()).await;
+        assert_eq!(at_node1.keys().collect::<Vec<_>>(), ["node1"]);
+        assert_eq!(member_of(&node1, &at_node1).as_deref(), Some("node1"));
+        // the certificate of node2 is refused from the address of node1
+        assert_eq!(member_of(&node2, &at_node1), None);
+
+        // a peer accepted on a dual-stack listener has an IPv4-mapped address
+        let mapped = members_at(members.clone(), "::ffff:10.0.0.2".parse().unwrap()).await;
+        assert_eq!(member_of(&node2, &mapped).as_deref(), Some("node2"));
+
+        // the host names are resolved, and no member is advertised at other addresses
+        let local = members_at(members.clone(), "127.0.0.1".parse().unwrap()).await;
+        assert_eq!(local.keys().collect::<Vec<_>>(), ["node3"]);
+        assert!(members_at(members, "10.0.0.9".parse().unwrap())
+            .await
+            .is_empty());
+    }
+
+    struct Pki {
+        dir: PathBuf,
+        ca: rcgen::Certificate,
+    }
+
+    impl Pki {
+        fn new() -> Self {
+            let dir = std::env::temp_dir().join(format!("xline-peer-tls-{}", Uuid::new_v4()));
+            std::fs::create_dir_all(&dir).unwrap();
+            let mut ca_params = CertificateParams::default();
+            ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+            let ca = rcgen::Certificate::from_params(ca_params).unwrap();
+            std::fs::write(dir.join("ca.crt"), ca.serialize_pem().unwrap()).unwrap();
+            Self { dir, ca }
+        }
+
+        /// Issue a certificate and load it as the member certificate of `name`
+        async fn member(&self, name: &str, dns_names: &[&str], ips: &[&str]) -> PeerTls {
+            let cert = rcgen::Certificate::from_params(params(dns_names, ips)).unwrap();
+            let cert_file = self.dir.join(format!("{name}.crt"));
+            let key_file = self.dir.join(format!("{name}.key"));
+            std::fs::write(
+                &cert_file,
+                cert.serialize_pem_with_signer(&self.ca).unwrap(),
+            )
+            .unwrap();
+            std::fs::write(&key_file, cert.serialize_private_key_pem()).unwrap();
+            let config = PeerTlsConfig::new(
+                true,
+                cert_file,
+                key_file,
+                self.dir.join("ca.crt"),
+                Duration::from_secs(10),
+            );
+            PeerTls::new(&config).await.unwrap().unwrap().0
+        }
+    }
+
+    impl Drop for Pki {
+        fn drop(&mut self) {
+            let _ignore = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    /// Handshake of `client` connecting from `client_ip` with `server` listening at
+    /// `server_host`, return the results of both sides
+    async fn handshake(
+        server: &PeerTls,
+        client: &PeerTls,
+        client_ip: &str,
+        server_host: &str,
+        members: HashMap<String, String>,
+    ) -> (bool, bool) {
+        let state = Arc::new(State::new("node1".to_owned(), None, members));
+        let acceptor = server.acceptor(state).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let accepted = tokio::spawn(async move {
+            let (stream, remote_addr) = listener.accept().await.unwrap();
+            acceptor.accept(stream, remote_addr).await.is_ok()
+        });
+        let connector = TlsConnector::from(Arc::new(client.rustls_client_config()));
+        // every address of 127.0.0.0/8 is a loopback address on Linux
+        let socket = TcpSocket::new_v4().unwrap();
+        socket
+            .bind(SocketAddr::new(client_ip.parse().unwrap(), 0))
+            .unwrap();
+        let stream = socket.connect(addr).await.unwrap();
+        let connected = connector
+            .connect(ServerName::try_from(server_host).unwrap(), stream)
+            .await
+            .is_ok();
+        (connected, accepted.await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_peers_should_be_verified_against_the_member_addresses() {
+        let pki = Pki::new();
+        let node1 = pki.member("node1", &["localhost"], &["127.0.0.1"]).await;
+        let node2 = pki
+            .member("node2", &["node2.xline.svc"], &["127.0.0.2"])
+            .await;
+        let impostor = pki.member("impostor", &["client.xline.svc"], &[]).await;
+        let members = members(&[("node1", "127.0.0.1:2380"), ("node2", "127.0.0.2:2380")]);
+
+        assert_eq!(
+            handshake(&node1, &node2, "127.0.0.2", "127.0.0.1", members.clone()).await,
+            (true, true)
+        );
+        assert_eq!(
+            handshake(&node1, &node2, "127.0.0.2", "localhost", members.clone()).await,
+            (true, true)
+        );
+        // the certificate of node2 is refused from the address of node1
+        assert!(
+            !handshake(&node1, &node2, "127.0.0.1", "127.0.0.1", members.clone())
+                .await
+                .1
+        );
+        // and from an address no member is advertised at
+        assert!(
+            !handshake(&node1, &node2, "127.0.0.3", "127.0.0.1", members.clone())
+                .await
+                .1
+        );
+        // a node holding another certificate of the CA is refused by the member
+        assert!(
+            !handshake(&node1, &impostor, "127.0.0.2", "127.0.0.1", members.clone())
+                .await
+                .1
+        );
+        // and a member refuses to connect to a node which isn't at the address
+        assert!(
+            !handshake(&impostor, &node2, "127.0.0.2", "127.0.0.1", members.clone())
+                .await
+                .0
+        );
+
+        // a certificate issued by another CA is refused
+        let other_pki = Pki::new();
+        let other = other_pki
+            .member("node2", &["node2.xline.svc"], &["127.0.0.2"])
+            .await;
+        assert!(
+            !handshake(&node1, &other, "127.0.0.2", "127.0.0.1", members)
+                .await
+                .1
+        );
+    }
+}
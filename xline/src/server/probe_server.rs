@@ -0,0 +1,348 @@
+use std::{
+    convert::Infallible,
+    fmt::Write,
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use curp::{server::Rpc, LogIndex};
+use engine::TableSize;
+use hyper::{
+    header::{HeaderValue, CONTENT_TYPE},
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, StatusCode,
+};
+use once_cell::sync::OnceCell;
+use parking_lot::Mutex;
+use tokio::sync::watch;
+use tracing::{debug, info, warn};
+
+use super::command::{Command, APPLIED_INDEX_KEY, META_TABLE};
+use crate::{
+    state::State,
+    storage::{storage_api::StorageApi, ExecuteError},
+};
+
+/// Progress of the apply loop, which applies the committed log entries to the storage
+#[derive(Debug)]
+pub(crate) struct ApplyProgress {
+    /// The largest index of the handled log entries, and when the last entry is handled
+    applied: Mutex<(LogIndex, Instant)>,
+    /// The last failure to flush an applied entry to the storage
+    last_flush_failure: Mutex<Option<String>>,
+}
+
+impl ApplyProgress {
+    /// New `ApplyProgress`
+    pub(crate) fn new() -> Self {
+        Self {
+            applied: Mutex::new((0, Instant::now())),
+            last_flush_failure: Mutex::new(None),
+        }
+    }
+
+    /// Record that the log entry at `index` is handled, whether it's applied or failed
+    pub(crate) fn advance(&self, index: LogIndex) {
+        let mut applied = self.applied.lock();
+        *applied = (applied.0.max(index), Instant::now());
+    }
+
+    /// Check if the apply loop is stalled: some committed entries are not handled, and
+    /// no entry has been handled for `timeout`
+    pub(crate) fn is_stalled(&self, commit_index: LogIndex, timeout: Duration) -> bool {
+        let (index, at) = *self.applied.lock();
+        commit_index > index && at.elapsed() > timeout
+    }
+
+    /// The largest index of the handled log entries
+    pub(crate) fn applied_index(&self) -> LogIndex {
+        self.applied.lock().0
+    }
+
+    /// Record that the log entry at `index` failed to be flushed to the storage
+    pub(crate) fn record_flush_failure(&self, index: LogIndex, err: &ExecuteError) {
+        *self.last_flush_failure.lock() = Some(format!(
+            "failed to flush log entry {index} to the storage: {err}"
+        ));
+    }
+
+    /// The last failure to flush a log entry to the storage
+    pub(crate) fn last_flush_failure(&self) -> Option<String> {
+        self.last_flush_failure.lock().clone()
+    }
+}
+
+/// Probe server, serves `/readyz` and `/livez` over plain HTTP for orchestrators like
+/// kubernetes. Unlike the grpc health service, it's started before the storage recovers,
+/// so that a slow recovery is not mistaken for a dead server.
+#[derive(Debug)]
+pub(super) struct ProbeServer<S>
+where
+    S: StorageApi,
+{
+    /// Persistent storage
+    persistent: Arc<S>,
+    /// State of current node
+    state: Arc<State>,
+    /// Progress of the apply loop
+    apply_progress: Arc<ApplyProgress>,
+    /// Curp server, it's set after the storage recovers
+    curp_server: OnceCell<Rpc<Command>>,
+    /// How long the committed entries may stay unapplied before the server is dead
+    apply_stall_timeout: Duration,
+    /// Shutdown signal receiver
+    shutdown_rx: watch::Receiver<bool>,
+}
+
+impl<S> ProbeServer<S>
+where
+    S: StorageApi,
+{
+    /// New `ProbeServer`
+    pub(super) fn new(
+        persistent: Arc<S>,
+        state: Arc<State>,
+        apply_progress: Arc<ApplyProgress>,
+        apply_stall_timeout: Duration,
+        shutdown_rx: watch::Receiver<bool>,
+    ) -> Self {
+        Self {
+            persistent,
+            state,
+            apply_progress,
+            curp_server: OnceCell::new(),
+            apply_stall_timeout,
+            shutdown_rx,
+        }
+    }
+
+    /// Mark the storage as recovered, the curp server is used to check the quorum and the
+    /// commit index from now on
+    pub(super) fn recovered(&self, curp_server: Rpc<Command>) {
+        let _ignore = self.curp_server.set(curp_server);
+    }
+
+    /// Bind `addr` and serve the probes in the background until the server shuts down
+    pub(super) fn serve(self: Arc<Self>, addr: SocketAddr) -> Result<()> {
+        let mut shutdown_rx = self.shutdown_rx.clone();
+        let make_service = make_service_fn(move |_conn| {
+            let probe = Arc::clone(&self);
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req| {
+                    let resp = probe.handle(&req);
+                    async move { Ok::<_, Infallible>(resp) }
+                }))
+            }
+        });
+        let server = hyper::Server::try_bind(&addr)?
+            .serve(make_service)
+            .with_graceful_shutdown(async move {
+                while !*shutdown_rx.borrow() {
+                    if shutdown_rx.changed().await.is_err() {
+                        break;
+                    }
+                }
+            });
+        info!("probes are served at {addr}");
+        let _handle = tokio::spawn(async move {
+            if let Err(e) = server.await {
+                warn!("probe server stopped, {e}");
+            }
+        });
+        Ok(())
+    }
+
+    /// Handle a probe request
+    fn handle(&self, req: &Request<Body>) -> Response<Body> {
+        if req.method() != Method::GET && req.method() != Method::HEAD {
+            return Self::response(StatusCode::METHOD_NOT_ALLOWED, "method not allowed");
+        }
+        let res = match req.uri().path() {
+            "/readyz" => self.readiness(),
+            "/livez" => self.liveness(),
+            "/metrics" => return self.metrics(),
+            _ => return Self::response(StatusCode::NOT_FOUND, "not found"),
+        };
+        match res {
+            Ok(()) => Self::response(StatusCode::OK, "ok"),
+            Err(reason) => {
+                debug!("{} failed: {reason}", req.uri().path());
+                Self::response(StatusCode::SERVICE_UNAVAILABLE, &reason)
+            }
+        }
+    }
+
+    /// Check if the server is ready to serve requests: the storage has recovered and the
+    /// server can reach a quorum of the cluster
+    fn readiness(&self) -> Result<(), String> {
+        if *self.shutdown_rx.borrow() {
+            return Err("server is shutting down".to_owned());
+        }
+        let Some(curp_server) = self.curp_server.get() else {
+            return Err("storage is recovering".to_owned());
+        };
+        if let Err(e) = self.persistent.get_value(META_TABLE, APPLIED_INDEX_KEY) {
+            return Err(format!("storage is not ready, {e}"));
+        }
+        if !curp_server.quorum_connected() {
+            return Err("quorum is not reachable".to_owned());
+        }
+        if self.state.leader_address().is_none() {
+            return Err("no leader in the cluster".to_owned());
+        }
+        Ok(())
+    }
+
+    /// Check if the server is alive: the apply loop is making progress. The server is
+    /// alive while it's recovering or can't reach a quorum, restarting it won't help.
+    fn liveness(&self) -> Result<(), String> {
+        let Some(curp_server) = self.curp_server.get() else {
+            return Ok(());
+        };
+        let commit_index = curp_server.commit_index();
+        if self
+            .apply_progress
+            .is_stalled(commit_index, self.apply_stall_timeout)
+        {
+            return Err(format!(
+                "apply loop is stalled, log entries up to {commit_index} are not applied in {:?}",
+                self.apply_stall_timeout
+            ));
+        }
+        Ok(())
+    }
+
+    /// Serve the metrics in the text format of Prometheus
+    fn metrics(&self) -> Response<Body> {
+        match self.metrics_text() {
+            Ok(text) => {
+                let mut resp = Response::new(Body::from(text));
+                let _prev = resp.headers_mut().insert(
+                    CONTENT_TYPE,
+                    HeaderValue::from_static("text/plain; version=0.0.4"),
+                );
+                resp
+            }
+            Err(e) => Self::response(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()),
+        }
+    }
+
+    /// Render the metrics: the bytes allocated by each table of the backend and the bytes
+    /// of the live key-values in it
+    fn metrics_text(&self) -> Result<String, ExecuteError> {
+        let sizes = self.persistent.table_sizes()?;
+        let mut text = String::new();
+        let gauges: [(&str, &str, fn(&TableSize) -> u64); 2] = [
+            (
+                "xline_db_size_bytes",
+                "Bytes allocated by each table of the backend",
+                TableSize::physical,
+            ),
+            (
+                "xline_db_size_in_use_bytes",
+                "Bytes of the live key-values in each table of the backend",
+                TableSize::logical,
+            ),
+        ];
+        for (name, help, get) in gauges {
+            let _ignore = writeln!(text, "# HELP {name} {help}\n# TYPE {name} gauge");
+            for &(table, ref size) in &sizes {
+                let _ignore = writeln!(text, "{name}{{table=\"{table}\"}} {}", get(size));
+            }
+        }
+        Ok(text)
+    }
+
+    /// Build a plain text response
+    fn response(status: StatusCode, body: &str) -> Response<Body> {
+        let mut resp = Response::new(Body::from(format!("{body}\n")));
+        *resp.status_mut() = status;
+        resp
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use utils::config::StorageConfig;
+
+    use super::*;
+    use crate::storage::db::DBProxy;
+
+    fn init_probe_server() -> (ProbeServer<DBProxy>, watch::Sender<bool>) {
+        let db = DBProxy::open(&StorageConfig::Memory).unwrap();
+        let members = HashMap::from([("node1".to_owned(), "127.0.0.1:2379".to_owned())]);
+        let state = Arc::new(State::new(
+            "node1".to_owned(),
+            Some("node1".to_owned()),
+            members,
+        ));
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let probe = ProbeServer::new(
+            db,
+            state,
+            Arc::new(ApplyProgress::new()),
+            Duration::from_secs(1),
+            shutdown_rx,
+        );
+        (probe, shutdown_tx)
+    }
+
+    fn get(probe: &ProbeServer<DBProxy>, path: &str) -> StatusCode {
+        let req = Request::get(path).body(Body::empty()).unwrap();
+        probe.handle(&req).status()
+    }
+
+    #[test]
+    fn test_probes_before_recovery() {
+        let (probe, shutdown_tx) = init_probe_server();
+        assert_eq!(get(&probe, "/readyz"), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(get(&probe, "/livez"), StatusCode::OK);
+        assert_eq!(get(&probe, "/metrics"), StatusCode::OK);
+        assert_eq!(get(&probe, "/unknown"), StatusCode::NOT_FOUND);
+
+        shutdown_tx.send(true).unwrap();
+        assert_eq!(get(&probe, "/readyz"), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[test]
+    fn test_metrics_report_the_table_sizes() {
+        let (probe, _shutdown_tx) = init_probe_server();
+        let text = probe.metrics_text().unwrap();
+        assert!(text.contains("# TYPE xline_db_size_bytes gauge"));
+        assert!(text.contains("xline_db_size_bytes{table=\"kv\"} 0"));
+        assert!(text.contains("xline_db_size_in_use_bytes{table=\"lease\"} 0"));
+    }
+
+    #[test]
+    fn test_apply_progress_stall() {
+        let progress = ApplyProgress::new();
+        let timeout = Duration::from_millis(100);
+        progress.advance(5);
+        assert!(!progress.is_stalled(5, timeout));
+        assert!(!progress.is_stalled(6, timeout));
+
+        std::thread::sleep(Duration::from_millis(150));
+        assert!(!progress.is_stalled(5, timeout));
+        assert!(progress.is_stalled(6, timeout));
+
+        // handling an old entry again is also progress
+        progress.advance(3);
+        assert!(!progress.is_stalled(6, timeout));
+        assert_eq!(progress.applied_index(), 5);
+    }
+
+    #[test]
+    fn test_apply_progress_records_the_last_flush_failure() {
+        let progress = ApplyProgress::new();
+        assert!(progress.last_flush_failure().is_none());
+        progress.record_flush_failure(3, &ExecuteError::DbError("disk full".to_owned()));
+        progress.record_flush_failure(4, &ExecuteError::DbError("disk full".to_owned()));
+        let failure = progress.last_flush_failure().unwrap();
+        assert!(failure.contains("log entry 4"));
+        assert!(failure.contains("disk full"));
+    }
+}
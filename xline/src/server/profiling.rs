@@ -0,0 +1,80 @@
+use std::time::Duration;
+
+use crate::rpc::HeapStatsResponse;
+
+/// Sample the call stacks of all threads for `duration` with `frequency` samples per second,
+/// return the profile encoded in the pprof protobuf format
+#[cfg(feature = "profiling")]
+pub(super) async fn cpu_profile(
+    duration: Duration,
+    frequency: i32,
+) -> Result<Vec<u8>, tonic::Status> {
+    use pprof::protos::Message;
+
+    // the profiler guard can't be held across await points, so sample in a blocking thread
+    tokio::task::spawn_blocking(move || {
+        let guard = pprof::ProfilerGuardBuilder::default()
+            .frequency(frequency)
+            .blocklist(&["libc", "libgcc", "pthread", "vdso"])
+            .build()
+            .map_err(|e| tonic::Status::unavailable(format!("failed to start profiler: {e}")))?;
+        std::thread::sleep(duration);
+        let profile = guard
+            .report()
+            .build()
+            .and_then(|report| report.pprof())
+            .map_err(|e| tonic::Status::internal(format!("failed to build profile: {e}")))?;
+        let mut body = Vec::new();
+        profile
+            .encode(&mut body)
+            .map_err(|e| tonic::Status::internal(format!("failed to encode profile: {e}")))?;
+        Ok(body)
+    })
+    .await
+    .map_err(|e| tonic::Status::internal(format!("profiler panicked: {e}")))?
+}
+
+/// Sample the call stacks of all threads, it is unavailable without the `profiling` feature
+#[cfg(not(feature = "profiling"))]
+#[allow(clippy::unused_async)] // keep the same signature as the real one
+pub(super) async fn cpu_profile(
+    _duration: Duration,
+    _frequency: i32,
+) -> Result<Vec<u8>, tonic::Status> {
+    Err(profiling_disabled())
+}
+
+/// Get the statistics of the jemalloc heap
+#[cfg(feature = "profiling")]
+pub(super) fn heap_stats() -> Result<HeapStatsResponse, tonic::Status> {
+    use clippy_utilities::NumericCast;
+    use tikv_jemalloc_ctl::{epoch, stats};
+
+    /// Convert the error of jemalloc to status
+    fn to_status(e: tikv_jemalloc_ctl::Error) -> tonic::Status {
+        tonic::Status::internal(format!("failed to read heap statistics: {e}"))
+    }
+
+    // statistics are cached by jemalloc until the epoch is advanced
+    let _epoch = epoch::advance().map_err(to_status)?;
+    Ok(HeapStatsResponse {
+        allocated: stats::allocated::read().map_err(to_status)?.numeric_cast(),
+        active: stats::active::read().map_err(to_status)?.numeric_cast(),
+        metadata: stats::metadata::read().map_err(to_status)?.numeric_cast(),
+        resident: stats::resident::read().map_err(to_status)?.numeric_cast(),
+        mapped: stats::mapped::read().map_err(to_status)?.numeric_cast(),
+        retained: stats::retained::read().map_err(to_status)?.numeric_cast(),
+    })
+}
+
+/// Get the statistics of the heap, it is unavailable without the `profiling` feature
+#[cfg(not(feature = "profiling"))]
+pub(super) fn heap_stats() -> Result<HeapStatsResponse, tonic::Status> {
+    Err(profiling_disabled())
+}
+
+/// The error returned when Xline is built without the `profiling` feature
+#[cfg(not(feature = "profiling"))]
+fn profiling_disabled() -> tonic::Status {
+    tonic::Status::unimplemented("xline is built without the `profiling` feature")
+}
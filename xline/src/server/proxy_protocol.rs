@@ -0,0 +1,356 @@
+use std::{
+    io,
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use tokio::{io::AsyncReadExt, net::TcpStream};
+use utils::config::ProxyProtocolConfig;
+
+/// Signature of a PROXY protocol v1 header
+const V1_SIGNATURE: &[u8] = b"PROXY ";
+/// Signature of a PROXY protocol v2 header
+const V2_SIGNATURE: &[u8] = b"\r\n\r\n\0\r\nQUIT\n";
+/// Max length of a v1 header, including the trailing CRLF
+const V1_MAX_LEN: usize = 107;
+/// Length of the fixed part of a v2 header, which is followed by the addresses
+const V2_FIXED_LEN: usize = 16;
+/// How long to wait for more bytes when the received ones may begin a header
+const PEEK_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Version of a PROXY protocol header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Version {
+    /// Human readable header
+    V1,
+    /// Binary header
+    V2,
+}
+
+/// What the first bytes of a connection tell about its header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Detection {
+    /// The connection starts with a header
+    Header(Version),
+    /// The connection doesn't start with a header
+    NoHeader,
+    /// The bytes received so far begin a header signature, more are needed to tell
+    Undecided,
+}
+
+/// A network whose connections are trusted to carry PROXY protocol headers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct TrustedNet {
+    /// Bits of the network address
+    bits: u128,
+    /// Bit width of the addresses, 32 for IPv4 and 128 for IPv6
+    width: u32,
+    /// Length of the network prefix
+    prefix: u32,
+}
+
+impl TrustedNet {
+    /// Parse a network like `10.0.0.0/8`, or a single address
+    fn parse(net: &str) -> Result<Self> {
+        let (addr, prefix) = net
+            .split_once('/')
+            .map_or((net, None), |(a, p)| (a, Some(p)));
+        let addr: IpAddr = addr
+            .parse()
+            .map_err(|_e| anyhow!("invalid trusted proxy {net}"))?;
+        let (bits, width) = ip_bits(addr);
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .parse()
+                .ok()
+                .filter(|prefix| *prefix <= width)
+                .ok_or_else(|| anyhow!("invalid prefix length of trusted proxy {net}"))?,
+            None => width,
+        };
+        Ok(Self {
+            bits,
+            width,
+            prefix,
+        })
+    }
+
+    /// Check if the network contains the address
+    fn contains(&self, addr: IpAddr) -> bool {
+        let (bits, width) = ip_bits(addr);
+        let host_bits = self.width.saturating_sub(self.prefix);
+        width == self.width
+            && bits.checked_shr(host_bits).unwrap_or(0)
+                == self.bits.checked_shr(host_bits).unwrap_or(0)
+    }
+}
+
+/// Bits and bit width of an address, IPv4-mapped IPv6 addresses are taken as IPv4 ones
+fn ip_bits(addr: IpAddr) -> (u128, u32) {
+    match addr {
+        IpAddr::V4(v4) => (u128::from(u32::from(v4)), 32),
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => (u128::from(u32::from(v4)), 32),
+            None => (u128::from(v6), 128),
+        },
+    }
+}
+
+/// Reader of the HAProxy PROXY protocol headers, so that the address of a client behind a
+/// TCP load balancer is the real one rather than the load balancer's. A header is only
+/// read from the connections of the trusted proxies, and it's optional, as the peers share
+/// the member listener with the clients and never send one.
+#[derive(Debug)]
+pub(super) struct ProxyProtocol {
+    /// Networks of the trusted proxies, every source is trusted if it's empty
+    trusted: Vec<TrustedNet>,
+}
+
+impl ProxyProtocol {
+    /// New `ProxyProtocol`, return `None` if the PROXY protocol is disabled
+    ///
+    /// # Errors
+    ///
+    /// Return error if a trusted proxy is not a valid address or network
+    pub(super) fn new(config: &ProxyProtocolConfig) -> Result<Option<Self>> {
+        if !*config.enable() {
+            return Ok(None);
+        }
+        let trusted = config
+            .trusted_proxies()
+            .iter()
+            .map(|net| TrustedNet::parse(net))
+            .collect::<Result<_>>()?;
+        Ok(Some(Self { trusted }))
+    }
+
+    /// Check if the connections from the address may carry headers
+    fn is_trusted(&self, addr: IpAddr) -> bool {
+        self.trusted.is_empty() || self.trusted.iter().any(|net| net.contains(addr))
+    }
+
+    /// Read the header of a connection from a trusted proxy if it starts with one, return
+    /// the address of the client, which is the peer address if there isn't a header or the
+    /// header doesn't carry one, eg. the health checks of the load balancer
+    ///
+    /// # Errors
+    ///
+    /// Return error if the header is invalid or the connection fails
+    pub(super) async fn read_header(
+        &self,
+        stream: &mut TcpStream,
+        peer: SocketAddr,
+    ) -> io::Result<SocketAddr> {
+        if !self.is_trusted(peer.ip()) {
+            return Ok(peer);
+        }
+        let mut buf = [0; V2_FIXED_LEN];
+        let version = loop {
+            let n = stream.peek(&mut buf).await?;
+            if n == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            match detect(buf.get(..n).unwrap_or_default()) {
+                Detection::Header(version) => break version,
+                Detection::NoHeader => return Ok(peer),
+                Detection::Undecided => tokio::time::sleep(PEEK_INTERVAL).await,
+            }
+        };
+        let addr = match version {
+            Version::V1 => {
+                let mut header = Vec::with_capacity(V1_MAX_LEN);
+                while !header.ends_with(b"\r\n") {
+                    if header.len() >= V1_MAX_LEN {
+                        return Err(invalid("PROXY protocol v1 header is too long"));
+                    }
+                    header.push(stream.read_u8().await?);
+                }
+                parse_v1(&header)?
+            }
+            Version::V2 => {
+                let _n = stream.read_exact(&mut buf).await?;
+                let [.., ver_cmd, family, len_hi, len_lo] = buf;
+                if ver_cmd & 0xF0 != 0x20 {
+                    return Err(invalid("unsupported PROXY protocol version"));
+                }
+                let mut payload = vec![0; usize::from(u16::from_be_bytes([len_hi, len_lo]))];
+                let _n = stream.read_exact(&mut payload).await?;
+                // the LOCAL command is sent by the proxy itself, eg. a health check
+                if ver_cmd & 0x0F == 0 {
+                    None
+                } else {
+                    parse_v2_addr(family, &payload)?
+                }
+            }
+        };
+        Ok(addr.unwrap_or(peer))
+    }
+}
+
+/// Tell if the first bytes of a connection begin a header
+fn detect(prefix: &[u8]) -> Detection {
+    let mut detection = Detection::NoHeader;
+    for (signature, version) in [(V1_SIGNATURE, Version::V1), (V2_SIGNATURE, Version::V2)] {
+        let len = prefix.len().min(signature.len());
+        if prefix.get(..len) != signature.get(..len) {
+            continue;
+        }
+        if len == signature.len() {
+            return Detection::Header(version);
+        }
+        detection = Detection::Undecided;
+    }
+    detection
+}
+
+/// Parse a v1 header like `PROXY TCP4 192.168.0.1 192.168.0.11 56324 2379\r\n`
+fn parse_v1(header: &[u8]) -> io::Result<Option<SocketAddr>> {
+    let header = std::str::from_utf8(header)
+        .map_err(|_e| invalid("PROXY protocol v1 header is not ASCII"))?
+        .trim_end_matches("\r\n");
+    let mut fields = header.split(' ').skip(1);
+    match fields.next() {
+        Some("UNKNOWN") => Ok(None),
+        Some("TCP4" | "TCP6") => {
+            let (Some(src), Some(_dst), Some(src_port), Some(_dst_port), None) =
+                (fields.next(), fields.next(), fields.next(), fields.next(), fields.next())
+            else {
+                return Err(invalid("malformed PROXY protocol v1 header"));
+            };
+            let ip: IpAddr = src
+                .parse()
+                .map_err(|_e| invalid("invalid source address in PROXY protocol header"))?;
+            let port: u16 = src_port
+                .parse()
+                .map_err(|_e| invalid("invalid source port in PROXY protocol header"))?;
+            Ok(Some(SocketAddr::new(ip, port)))
+        }
+        _ => Err(invalid("unsupported protocol in PROXY protocol v1 header")),
+    }
+}
+
+/// Parse the source address of a v2 header, return `None` for the families other than TCP
+/// over IPv4 or IPv6
+fn parse_v2_addr(family: u8, payload: &[u8]) -> io::Result<Option<SocketAddr>> {
+    let (ip, port) = match family {
+        0x11 => (
+            payload
+                .get(..4)
+                .and_then(|b| <[u8; 4]>::try_from(b).ok())
+                .map(IpAddr::from),
+            payload.get(8..10),
+        ),
+        0x21 => (
+            payload
+                .get(..16)
+                .and_then(|b| <[u8; 16]>::try_from(b).ok())
+                .map(IpAddr::from),
+            payload.get(32..34),
+        ),
+        _ => return Ok(None),
+    };
+    match (ip, port.and_then(|b| <[u8; 2]>::try_from(b).ok())) {
+        (Some(ip), Some(port)) => Ok(Some(SocketAddr::new(ip, u16::from_be_bytes(port)))),
+        _ => Err(invalid("truncated addresses in PROXY protocol v2 header")),
+    }
+}
+
+/// An error of an invalid header
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::{io::AsyncWriteExt, net::TcpListener};
+
+    use super::*;
+
+    async fn read_header(proxy: &ProxyProtocol, sent: &[u8]) -> (io::Result<SocketAddr>, Vec<u8>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        client.write_all(sent).await.unwrap();
+        client.shutdown().await.unwrap();
+        let (mut stream, peer) = listener.accept().await.unwrap();
+        let addr = proxy.read_header(&mut stream, peer).await;
+        let mut rest = vec![];
+        let _n = stream.read_to_end(&mut rest).await.unwrap();
+        (addr, rest)
+    }
+
+    fn trust_all() -> ProxyProtocol {
+        ProxyProtocol { trusted: vec![] }
+    }
+
+    #[tokio::test]
+    async fn test_v1_header_should_be_read() {
+        let (addr, rest) = read_header(
+            &trust_all(),
+            b"PROXY TCP4 192.168.0.1 192.168.0.11 56324 2379\r\nPRI * HTTP/2.0",
+        )
+        .await;
+        assert_eq!(addr.unwrap(), "192.168.0.1:56324".parse().unwrap());
+        assert_eq!(rest, b"PRI * HTTP/2.0");
+
+        let (addr, _rest) = read_header(&trust_all(), b"PROXY UNKNOWN\r\n").await;
+        assert!(addr.unwrap().ip().is_loopback());
+
+        let (addr, _rest) = read_header(&trust_all(), b"PROXY TCP4 192.168.0.1\r\n").await;
+        assert!(addr.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_v2_header_should_be_read() {
+        let mut header = V2_SIGNATURE.to_vec();
+        header.extend([
+            0x21, 0x11, 0, 12, 10, 0, 0, 7, 10, 0, 0, 1, 0x1F, 0x90, 0x09, 0x4B,
+        ]);
+        header.extend(b"PRI * HTTP/2.0");
+        let (addr, rest) = read_header(&trust_all(), &header).await;
+        assert_eq!(addr.unwrap(), "10.0.0.7:8080".parse().unwrap());
+        assert_eq!(rest, b"PRI * HTTP/2.0");
+
+        // LOCAL command
+        let mut header = V2_SIGNATURE.to_vec();
+        header.extend([0x20, 0x00, 0, 0]);
+        let (addr, _rest) = read_header(&trust_all(), &header).await;
+        assert!(addr.unwrap().ip().is_loopback());
+    }
+
+    #[tokio::test]
+    async fn test_connections_without_header_should_be_untouched() {
+        let (addr, rest) = read_header(&trust_all(), b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n").await;
+        assert!(addr.unwrap().ip().is_loopback());
+        assert_eq!(rest, b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n");
+    }
+
+    #[tokio::test]
+    async fn test_headers_from_untrusted_sources_should_be_ignored() {
+        let proxy = ProxyProtocol {
+            trusted: vec![TrustedNet::parse("10.0.0.0/8").unwrap()],
+        };
+        let sent = b"PROXY TCP4 192.168.0.1 192.168.0.11 56324 2379\r\n";
+        let (addr, rest) = read_header(&proxy, sent).await;
+        assert!(addr.unwrap().ip().is_loopback());
+        assert_eq!(rest, sent);
+    }
+
+    #[test]
+    fn test_trusted_nets_should_match_addresses() {
+        let net = TrustedNet::parse("10.1.0.0/16").unwrap();
+        assert!(net.contains("10.1.2.3".parse().unwrap()));
+        assert!(net.contains("::ffff:10.1.2.3".parse().unwrap()));
+        assert!(!net.contains("10.2.0.1".parse().unwrap()));
+        assert!(!net.contains("::1".parse().unwrap()));
+        assert!(TrustedNet::parse("0.0.0.0/0")
+            .unwrap()
+            .contains("1.2.3.4".parse().unwrap()));
+        assert!(TrustedNet::parse("fd00::/8")
+            .unwrap()
+            .contains("fd12::1".parse().unwrap()));
+        assert!(TrustedNet::parse("10.0.0.1/33").is_err());
+        assert!(TrustedNet::parse("proxy").is_err());
+    }
+}
@@ -0,0 +1,232 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+use parking_lot::Mutex;
+use tonic::service::Interceptor;
+use utils::config::RateLimitConfig;
+
+use super::{auth_server::get_token, listener::client_addr};
+use crate::storage::{storage_api::StorageApi, AuthStore};
+
+/// Nanoseconds per second, also the number of nano tokens of one token
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+/// Start to evict idle buckets when the number of tracked clients reaches this size
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+/// Token bucket of one client, tokens are counted in nano tokens to avoid float arithmetic
+#[derive(Debug)]
+struct TokenBucket {
+    /// Remaining nano tokens
+    tokens: u128,
+    /// Last refill time
+    last: Instant,
+}
+
+impl TokenBucket {
+    /// New `TokenBucket` with full tokens
+    fn new(capacity: u128, now: Instant) -> Self {
+        Self {
+            tokens: capacity,
+            last: now,
+        }
+    }
+
+    /// Refill tokens according to the elapsed time
+    fn refill(&mut self, qps: u128, capacity: u128, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last).as_nanos();
+        self.tokens = self
+            .tokens
+            .saturating_add(elapsed.saturating_mul(qps))
+            .min(capacity);
+        self.last = now;
+    }
+
+    /// Try to take one token
+    fn try_acquire(&mut self) -> bool {
+        if self.tokens < NANOS_PER_SEC {
+            return false;
+        }
+        self.tokens = self.tokens.wrapping_sub(NANOS_PER_SEC);
+        true
+    }
+}
+
+/// Per-client rate limiter, the limits can be changed at runtime
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    /// Requests allowed per second of each client, 0 means no limit
+    qps: AtomicU64,
+    /// Max requests a client can send in a burst
+    burst: AtomicU64,
+    /// Client identity to its bucket
+    buckets: Mutex<HashMap<String, TokenBucket>>,
+}
+
+impl RateLimiter {
+    /// New `RateLimiter`
+    pub(crate) fn new(config: &RateLimitConfig) -> Self {
+        Self {
+            qps: AtomicU64::new(*config.qps()),
+            burst: AtomicU64::new(*config.burst()),
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Replace the limits, buckets holding more tokens than the new burst are trimmed on
+    /// their next refill
+    pub(crate) fn update(&self, config: &RateLimitConfig) {
+        self.qps.store(*config.qps(), Ordering::Relaxed);
+        self.burst.store(*config.burst(), Ordering::Relaxed);
+        if *config.qps() == 0 {
+            self.buckets.lock().clear();
+        }
+    }
+
+    /// Check if the request of the given client is admitted
+    pub(crate) fn admit(&self, client: &str) -> bool {
+        let qps = u128::from(self.qps.load(Ordering::Relaxed));
+        if qps == 0 {
+            return true;
+        }
+        let capacity =
+            u128::from(self.burst.load(Ordering::Relaxed).max(1)).saturating_mul(NANOS_PER_SEC);
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock();
+        if let Some(bucket) = buckets.get_mut(client) {
+            bucket.refill(qps, capacity, now);
+            return bucket.try_acquire();
+        }
+        if buckets.len() >= MAX_TRACKED_CLIENTS {
+            // a bucket refilled to full is equivalent to a newly created one
+            buckets.retain(|_k, bucket| {
+                bucket.refill(qps, capacity, now);
+                bucket.tokens < capacity
+            });
+        }
+        let mut bucket = TokenBucket::new(capacity, now);
+        let admitted = bucket.try_acquire();
+        let _prev = buckets.insert(client.to_owned(), bucket);
+        admitted
+    }
+}
+
+/// Interceptor that limits the request rate of each client. Clients are identified by
+/// the user of the auth token, or by the peer ip address if the request has no valid token.
+#[derive(Debug)]
+pub(crate) struct RateLimitInterceptor<S>
+where
+    S: StorageApi,
+{
+    /// Rate limiter
+    limiter: Arc<RateLimiter>,
+    /// Auth storage, used to get the user of a token
+    auth_storage: Arc<AuthStore<S>>,
+}
+
+impl<S> Clone for RateLimitInterceptor<S>
+where
+    S: StorageApi,
+{
+    fn clone(&self) -> Self {
+        Self {
+            limiter: Arc::clone(&self.limiter),
+            auth_storage: Arc::clone(&self.auth_storage),
+        }
+    }
+}
+
+impl<S> RateLimitInterceptor<S>
+where
+    S: StorageApi,
+{
+    /// New `RateLimitInterceptor`
+    pub(crate) fn new(limiter: Arc<RateLimiter>, auth_storage: Arc<AuthStore<S>>) -> Self {
+        Self {
+            limiter,
+            auth_storage,
+        }
+    }
+
+    /// Get the identity of the client who sent the request
+    fn client_identity(&self, request: &tonic::Request<()>) -> String {
+        if let Some(claims) = get_token(request.metadata())
+            .and_then(|token| self.auth_storage.verify_token(&token).ok())
+        {
+            return format!("user:{}", claims.username);
+        }
+        client_addr(request).map_or_else(
+            || "unknown".to_owned(),
+            |addr| format!("peer:{}", addr.ip()),
+        )
+    }
+}
+
+impl<S> Interceptor for RateLimitInterceptor<S>
+where
+    S: StorageApi,
+{
+    fn call(&mut self, request: tonic::Request<()>) -> Result<tonic::Request<()>, tonic::Status> {
+        let client = self.client_identity(&request);
+        if self.limiter.admit(&client) {
+            Ok(request)
+        } else {
+            Err(tonic::Status::resource_exhausted(format!(
+                "too many requests from {client}"
+            )))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_token_bucket() {
+        let now = Instant::now();
+        let capacity = 2 * NANOS_PER_SEC;
+        let mut bucket = TokenBucket::new(capacity, now);
+        assert!(bucket.try_acquire());
+        assert!(bucket.try_acquire());
+        assert!(!bucket.try_acquire());
+
+        bucket.refill(10, capacity, now + Duration::from_millis(100));
+        assert!(bucket.try_acquire());
+        assert!(!bucket.try_acquire());
+
+        bucket.refill(10, capacity, now + Duration::from_secs(10));
+        assert_eq!(bucket.tokens, capacity);
+    }
+
+    #[test]
+    fn test_rate_limiter() {
+        let unlimited = RateLimiter::new(&RateLimitConfig::default());
+        assert!((0..1000).all(|_| unlimited.admit("client1")));
+        let limiter = RateLimiter::new(&RateLimitConfig::new(1, 3));
+        for _ in 0..3 {
+            assert!(limiter.admit("client1"));
+        }
+        assert!(!limiter.admit("client1"));
+        assert!(limiter.admit("client2"));
+    }
+
+    #[test]
+    fn test_update_rate_limiter() {
+        let limiter = RateLimiter::new(&RateLimitConfig::default());
+        limiter.update(&RateLimitConfig::new(1, 2));
+        assert!(limiter.admit("client1"));
+        assert!(limiter.admit("client1"));
+        assert!(!limiter.admit("client1"));
+
+        limiter.update(&RateLimitConfig::default());
+        assert!(limiter.admit("client1"));
+    }
+}
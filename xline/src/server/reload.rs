@@ -0,0 +1,123 @@
+use std::path::Path;
+
+use anyhow::Result;
+use utils::config::XlineServerConfig;
+
+/// Read the server config from a toml file, the `XLINE_*` environment variables take
+/// precedence over the file like they do on startup
+pub(super) async fn read_config(path: &Path) -> Result<XlineServerConfig> {
+    let content = tokio::fs::read_to_string(path).await?;
+    Ok(XlineServerConfig::from_toml_with_env(
+        &content,
+        std::env::vars(),
+    )?)
+}
+
+/// Sections of `new` which differ from the running config but can't be applied at runtime
+pub(super) fn restart_required(
+    running: &XlineServerConfig,
+    new: &XlineServerConfig,
+) -> Vec<&'static str> {
+    // only the level of the log config is reloadable
+    let log_changed = running.log().path() != new.log().path()
+        || running.log().rotation() != new.log().rotation();
+    [
+        ("cluster", running.cluster() != new.cluster()),
+        ("storage", running.storage() != new.storage()),
+        ("log", log_changed),
+        ("trace", running.trace() != new.trace()),
+        ("auth", running.auth() != new.auth()),
+        ("grpc", running.grpc() != new.grpc()),
+        (
+            "server_timeout",
+            running.server_timeout() != new.server_timeout(),
+        ),
+        ("audit", running.audit() != new.audit()),
+        ("backup", running.backup() != new.backup()),
+        ("batch", running.batch() != new.batch()),
+        ("probe", running.probe() != new.probe()),
+        (
+            "request_limit",
+            running.request_limit() != new.request_limit(),
+        ),
+        ("compression", running.compression() != new.compression()),
+        (
+            "corrupt_check",
+            running.corrupt_check() != new.corrupt_check(),
+        ),
+        ("tls", running.tls() != new.tls()),
+        ("peer_tls", running.peer_tls() != new.peer_tls()),
+        (
+            "proxy_protocol",
+            running.proxy_protocol() != new.proxy_protocol(),
+        ),
+        ("admission", running.admission() != new.admission()),
+        ("namespace", running.namespaces() != new.namespaces()),
+        ("trash", running.trash() != new.trash()),
+        ("backend", running.backend() != new.backend()),
+        ("lease", running.lease() != new.lease()),
+    ]
+    .into_iter()
+    .filter_map(|(section, changed)| changed.then_some(section))
+    .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn config(
+        level: &str,
+        qps: u64,
+        max_concurrent_streams: u32,
+        revoke_batch_size: usize,
+    ) -> XlineServerConfig {
+        toml::from_str(&format!(
+            r"[cluster]
+            name = 'node1'
+            is_leader = true
+
+            [cluster.members]
+            node1 = '127.0.0.1:2379'
+
+            [storage]
+            engine = 'memory'
+
+            [log]
+            path = '/var/log/xline'
+            level = '{level}'
+
+            [trace]
+            jaeger_online = false
+            jaeger_offline = false
+            jaeger_output_dir = './jaeger_jsons'
+            jaeger_level = 'info'
+
+            [auth]
+
+            [grpc]
+            max_concurrent_streams = {max_concurrent_streams}
+
+            [rate_limit]
+            qps = {qps}
+
+            [lease]
+            revoke_batch_size = {revoke_batch_size}"
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_restart_required() {
+        let running = config("info", 0, 128, 0);
+        assert!(restart_required(&running, &config("debug", 100, 128, 0)).is_empty());
+        assert_eq!(
+            restart_required(&running, &config("debug", 100, 256, 0)),
+            vec!["grpc"]
+        );
+        assert_eq!(
+            restart_required(&running, &config("info", 0, 128, 1000)),
+            vec!["lease"]
+        );
+    }
+}
@@ -0,0 +1,171 @@
+use std::{
+    fmt::Write,
+    time::{Duration, Instant},
+};
+
+use tracing::warn;
+
+/// Max number of bytes of a key shown in the summary of a request
+const MAX_KEY_LEN: usize = 64;
+
+/// Timer of a request, it records the time spent in each phase of the request and
+/// logs the request if its end-to-end latency exceeds the threshold. A request which
+/// fails before it's finished, eg. by a propose timeout, is logged when the timer drops.
+#[derive(Debug)]
+pub(crate) struct SlowRequestTimer {
+    /// Requests slower than it are logged
+    threshold: Duration,
+    /// When the request is received
+    start: Instant,
+    /// When the last phase is finished
+    last: Instant,
+    /// Finished phases and their durations
+    phases: Vec<(&'static str, Duration)>,
+    /// Summary of the request
+    summary: String,
+    /// Whether the request is finished
+    finished: bool,
+}
+
+impl SlowRequestTimer {
+    /// New `SlowRequestTimer`, the request is considered to start at this moment
+    pub(crate) fn new(threshold: Duration) -> Self {
+        let now = Instant::now();
+        Self {
+            threshold,
+            start: now,
+            last: now,
+            phases: Vec::new(),
+            summary: String::new(),
+            finished: false,
+        }
+    }
+
+    /// Set the summary of the request, it's logged if the request is slow
+    pub(crate) fn set_summary(&mut self, summary: String) {
+        self.summary = summary;
+    }
+
+    /// Mark the end of a phase, the phase starts when the previous one finishes
+    pub(crate) fn phase(&mut self, name: &'static str) {
+        let now = Instant::now();
+        self.phases.push((name, now.duration_since(self.last)));
+        self.last = now;
+    }
+
+    /// Finish the request, log it with the summary and the details of the response if it
+    /// is too slow. The details are only built for slow requests. Return whether the
+    /// request is logged.
+    pub(crate) fn finish<F>(mut self, details: F) -> bool
+    where
+        F: FnOnce() -> String,
+    {
+        self.finished = true;
+        let took = self.start.elapsed();
+        if took <= self.threshold {
+            return false;
+        }
+        let details = details();
+        let separator = if details.is_empty() { "" } else { ", " };
+        warn!(
+            "request took too long, took: {took:?}, expected-duration: {:?}, {}{separator}{details}, phases: [{}]",
+            self.threshold,
+            self.summary,
+            self.phases_summary()
+        );
+        true
+    }
+
+    /// Format the durations of all phases
+    fn phases_summary(&self) -> String {
+        let mut res = String::new();
+        for (i, &(name, duration)) in self.phases.iter().enumerate() {
+            if i > 0 {
+                res.push_str(", ");
+            }
+            let _ignore = write!(res, "{name}: {duration:?}");
+        }
+        res
+    }
+}
+
+impl Drop for SlowRequestTimer {
+    #[inline]
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        let took = self.start.elapsed();
+        if took > self.threshold {
+            warn!(
+                "request failed after too long, took: {took:?}, expected-duration: {:?}, {}, phases: [{}]",
+                self.threshold,
+                self.summary,
+                self.phases_summary()
+            );
+        }
+    }
+}
+
+/// Summary of a key range, long keys are truncated
+pub(crate) fn key_range_summary(key: &[u8], range_end: &[u8]) -> String {
+    if range_end.is_empty() {
+        format!("key: {}", truncated_key(key))
+    } else {
+        format!(
+            "key: {}, range_end: {}",
+            truncated_key(key),
+            truncated_key(range_end)
+        )
+    }
+}
+
+/// Show a key as a string, keys longer than `MAX_KEY_LEN` are truncated
+fn truncated_key(key: &[u8]) -> String {
+    match key.get(..MAX_KEY_LEN) {
+        Some(prefix) if key.len() > MAX_KEY_LEN => {
+            format!("{:?}...", String::from_utf8_lossy(prefix))
+        }
+        _ => format!("{:?}", String::from_utf8_lossy(key)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_slow_request_should_be_logged() {
+        let mut timer = SlowRequestTimer::new(Duration::ZERO);
+        std::thread::sleep(Duration::from_millis(1));
+        timer.phase("check");
+        timer.phase("propose");
+        assert_eq!(timer.phases.len(), 2);
+        assert!(timer.phases_summary().starts_with("check: "));
+        timer.set_summary("range request".to_owned());
+        assert!(timer.finish(|| "response_count: 1".to_owned()));
+
+        let timer = SlowRequestTimer::new(Duration::from_secs(60));
+        assert!(!timer.finish(|| unreachable!("details of a fast request should not be built")));
+    }
+
+    #[test]
+    fn test_unfinished_request_is_logged_on_drop() {
+        let mut timer = SlowRequestTimer::new(Duration::ZERO);
+        timer.set_summary("put request".to_owned());
+        timer.phase("check");
+        // the request fails at the propose, it's logged as failed when the timer drops
+        assert!(!timer.finished);
+        drop(timer);
+    }
+
+    #[test]
+    fn test_key_range_summary() {
+        assert_eq!(key_range_summary(b"foo", b""), r#"key: "foo""#);
+        assert_eq!(key_range_summary(b"a", b"b"), r#"key: "a", range_end: "b""#);
+        let long_key = vec![b'a'; MAX_KEY_LEN + 1];
+        let summary = key_range_summary(&long_key, b"");
+        assert!(summary.ends_with("\"..."));
+        assert_eq!(summary.matches('a').count(), MAX_KEY_LEN);
+    }
+}
@@ -0,0 +1,154 @@
+use std::time::Duration;
+
+use tonic::{
+    body::BoxBody,
+    codegen::{http, BoxFuture, Context, Poll, Service},
+    transport::NamedService,
+};
+use utils::config::ServerTimeout;
+
+/// Split the request path of an rpc into its service and method, eg. `/etcdserverpb.KV/Range`
+/// into `etcdserverpb.KV` and `Range`
+pub(super) fn service_and_method(path: &str) -> (&str, &str) {
+    let path = path.trim_start_matches('/');
+    path.split_once('/').unwrap_or((path, ""))
+}
+
+/// Type of an rpc, rpcs of different types have different timeouts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum RpcType {
+    /// Rpc that only reads data
+    Read,
+    /// Rpc that modifies data
+    Write,
+    /// Long-running maintenance rpc
+    Maintenance,
+    /// Rpc that may block or stream for an unbounded time, eg. Watch and Lock
+    Unbounded,
+}
+
+impl RpcType {
+    /// Get the type of an rpc by its request path, eg. `/etcdserverpb.KV/Range`
+    pub(super) fn from_path(path: &str) -> Self {
+        match service_and_method(path) {
+            ("etcdserverpb.Watch", _)
+            | ("etcdserverpb.KV", "WaitRevision")
+            | ("etcdserverpb.Lease", "LeaseKeepAlive" | "LeaseObserve")
+            | ("v3lockpb.Lock", "Lock") => Self::Unbounded,
+            ("etcdserverpb.Maintenance" | "xlineadminpb.Admin", _)
+            | ("etcdserverpb.KV", "Compact") => Self::Maintenance,
+            ("etcdserverpb.KV", "Range" | "KeyHistory")
+            | ("etcdserverpb.Lease", "LeaseTimeToLive" | "LeaseLeases")
+            | (
+                "etcdserverpb.Auth",
+                "AuthStatus" | "UserGet" | "UserList" | "RoleGet" | "RoleList",
+            ) => Self::Read,
+            _ => Self::Write,
+        }
+    }
+
+    /// Get the timeout of this type of rpc
+    fn timeout(self, config: &ServerTimeout) -> Option<Duration> {
+        match self {
+            Self::Read => Some(*config.read_timeout()),
+            Self::Write => Some(*config.write_timeout()),
+            Self::Maintenance => Some(*config.maintenance_timeout()),
+            Self::Unbounded => None,
+        }
+    }
+}
+
+/// Service that enforces server side timeouts on the wrapped grpc service according to
+/// the type of each rpc
+#[derive(Debug, Clone)]
+pub(crate) struct TimeoutService<T> {
+    /// Inner grpc service
+    inner: T,
+    /// Timeouts of each type of rpc
+    config: ServerTimeout,
+}
+
+impl<T> TimeoutService<T> {
+    /// New `TimeoutService`
+    pub(crate) fn new(inner: T, config: ServerTimeout) -> Self {
+        Self { inner, config }
+    }
+}
+
+impl<T, B> Service<http::Request<B>> for TimeoutService<T>
+where
+    T: Service<http::Request<B>, Response = http::Response<BoxBody>>,
+    T::Future: Send + 'static,
+{
+    type Response = T::Response;
+    type Error = T::Error;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    #[inline]
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        let rpc_type = RpcType::from_path(req.uri().path());
+        let fut = self.inner.call(req);
+        let Some(timeout) = rpc_type.timeout(&self.config) else {
+            return Box::pin(fut);
+        };
+        Box::pin(async move {
+            tokio::time::timeout(timeout, fut)
+                .await
+                .unwrap_or_else(|_elapsed| {
+                    Ok(tonic::Status::deadline_exceeded(format!(
+                        "{rpc_type:?} request timed out after {timeout:?}"
+                    ))
+                    .to_http())
+                })
+        })
+    }
+}
+
+impl<T: NamedService> NamedService for TimeoutService<T> {
+    const NAME: &'static str = T::NAME;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rpc_type_from_path() {
+        assert_eq!(RpcType::from_path("/etcdserverpb.KV/Range"), RpcType::Read);
+        assert_eq!(RpcType::from_path("/etcdserverpb.KV/Put"), RpcType::Write);
+        assert_eq!(
+            RpcType::from_path("/etcdserverpb.KV/Compact"),
+            RpcType::Maintenance
+        );
+        assert_eq!(
+            RpcType::from_path("/etcdserverpb.KV/WaitRevision"),
+            RpcType::Unbounded
+        );
+        assert_eq!(
+            RpcType::from_path("/etcdserverpb.Lease/LeaseKeepAlive"),
+            RpcType::Unbounded
+        );
+        assert_eq!(
+            RpcType::from_path("/etcdserverpb.Lease/LeaseObserve"),
+            RpcType::Unbounded
+        );
+        assert_eq!(
+            RpcType::from_path("/v3lockpb.Lock/Lock"),
+            RpcType::Unbounded
+        );
+        assert_eq!(RpcType::from_path("/v3lockpb.Lock/Unlock"), RpcType::Write);
+        assert_eq!(
+            RpcType::from_path("/etcdserverpb.Auth/UserGet"),
+            RpcType::Read
+        );
+        assert_eq!(
+            RpcType::from_path("/xlineadminpb.Admin/SetLogFilter"),
+            RpcType::Maintenance
+        );
+    }
+}
@@ -0,0 +1,311 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Result};
+use parking_lot::RwLock;
+use tokio::sync::{watch, Notify};
+use tokio_rustls::{
+    rustls::{
+        client::ResolvesClientCert,
+        server::{ClientHello, ResolvesServerCert},
+        sign::{self, CertifiedKey},
+        Certificate, PrivateKey, ServerConfig, SignatureScheme,
+    },
+    TlsAcceptor,
+};
+use tracing::{info, warn};
+use utils::config::TlsConfig;
+
+/// The certificate presented in the TLS handshakes, it's swapped when the certificate
+/// files change. The established connections keep their sessions, so the long-lived watch
+/// and lease keep alive streams are not interrupted by a rotation.
+pub(super) struct CertResolver {
+    /// Current certificate and its signing key
+    key: RwLock<Arc<CertifiedKey>>,
+}
+
+impl fmt::Debug for CertResolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CertResolver")
+            .field("certs", &self.key.read().cert.len())
+            .finish()
+    }
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(Arc::clone(&self.key.read()))
+    }
+}
+
+/// A member presents the same certificate when it connects to its peers
+impl ResolvesClientCert for CertResolver {
+    fn resolve(
+        &self,
+        _acceptable_issuers: &[&[u8]],
+        _sigschemes: &[SignatureScheme],
+    ) -> Option<Arc<CertifiedKey>> {
+        Some(Arc::clone(&self.key.read()))
+    }
+
+    fn has_certs(&self) -> bool {
+        true
+    }
+}
+
+/// Reloader of the certificate of the TLS listener. The certificate and key files are
+/// checked every interval, and a new certificate is loaded once their contents change, so
+/// certificates rotated by cert-manager or Vault are picked up without a restart.
+#[derive(Debug)]
+pub(super) struct CertReloader {
+    /// PEM file of the certificate chain
+    cert_file: PathBuf,
+    /// PEM file of the private key
+    key_file: PathBuf,
+    /// Interval between two checks
+    interval: Duration,
+    /// Resolver shared with the acceptor
+    resolver: Arc<CertResolver>,
+    /// Contents of the certificate and key files the current certificate is loaded from
+    loaded: (Vec<u8>, Vec<u8>),
+}
+
+impl CertReloader {
+    /// New `CertReloader`, return `None` if TLS is disabled
+    ///
+    /// # Errors
+    ///
+    /// Return error if the files can't be read or they don't hold a valid certificate and
+    /// private key
+    pub(super) async fn new(config: &TlsConfig) -> Result<Option<Self>> {
+        if !*config.enable() {
+            return Ok(None);
+        }
+        Self::load(
+            config.cert_file().clone(),
+            config.key_file().clone(),
+            *config.reload_interval(),
+        )
+        .await
+        .map(Some)
+    }
+
+    /// New `CertReloader` of the certificate in `cert_file` and `key_file`
+    ///
+    /// # Errors
+    ///
+    /// Return error if the files can't be read or they don't hold a valid certificate and
+    /// private key
+    pub(super) async fn load(
+        cert_file: PathBuf,
+        key_file: PathBuf,
+        interval: Duration,
+    ) -> Result<Self> {
+        let (cert_pem, key_pem) = read_pair(&cert_file, &key_file)
+            .await?
+            .ok_or_else(|| anyhow!("certificate files are changing, try again later"))?;
+        let key = load_certified_key(&cert_pem, &key_pem)?;
+        Ok(Self {
+            cert_file,
+            key_file,
+            interval,
+            resolver: Arc::new(CertResolver {
+                key: RwLock::new(Arc::new(key)),
+            }),
+            loaded: (cert_pem, key_pem),
+        })
+    }
+
+    /// Resolver of the certificate, it always resolves the latest certificate
+    pub(super) fn resolver(&self) -> Arc<CertResolver> {
+        Arc::clone(&self.resolver)
+    }
+
+    /// Acceptor of the TLS connections, the handshakes always present the latest
+    /// certificate
+    pub(super) fn acceptor(&self) -> TlsAcceptor {
+        let resolver: Arc<dyn ResolvesServerCert> = Arc::clone(&self.resolver);
+        let mut config = ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_cert_resolver(resolver);
+        config.alpn_protocols = vec![b"h2".to_vec()];
+        TlsAcceptor::from(Arc::new(config))
+    }
+
+    /// Load a new certificate if the files have changed, return whether it's loaded
+    async fn reload(&mut self) -> Result<bool> {
+        let Some((cert_pem, key_pem)) = read_pair(&self.cert_file, &self.key_file).await? else {
+            return Ok(false);
+        };
+        if cert_pem == self.loaded.0 && key_pem == self.loaded.1 {
+            return Ok(false);
+        }
+        let key = load_certified_key(&cert_pem, &key_pem)?;
+        *self.resolver.key.write() = Arc::new(key);
+        self.loaded = (cert_pem, key_pem);
+        Ok(true)
+    }
+
+    /// Check the files every interval, and at once when `reload` is notified, eg. on
+    /// SIGHUP, until the server shuts down. The current certificate is kept if the new
+    /// files are invalid, eg. only one of them is written yet.
+    #[allow(clippy::integer_arithmetic)] // Introduced by tokio::select!
+    pub(super) async fn run(mut self, reload: Arc<Notify>, mut shutdown_rx: watch::Receiver<bool>) {
+        let mut ticker = tokio::time::interval(self.interval);
+        // the first tick completes immediately, the certificate is just loaded
+        let _ignore = ticker.tick().await;
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {}
+                () = reload.notified() => {}
+                _ = shutdown_rx.changed() => return,
+            }
+            match self.reload().await {
+                Ok(true) => info!("TLS certificate is reloaded from {:?}", self.cert_file),
+                Ok(false) => {}
+                Err(e) => warn!("failed to reload the TLS certificate, keep the current one, {e}"),
+            }
+        }
+    }
+}
+
+/// Read the certificate and key files, return `None` if the certificate file changes while
+/// they are read, so that a certificate is never paired with the key of another one when
+/// the files are swapped atomically, like a mounted Kubernetes secret
+async fn read_pair(cert_file: &Path, key_file: &Path) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+    let cert_pem = tokio::fs::read(cert_file).await?;
+    let key_pem = tokio::fs::read(key_file).await?;
+    let stable = tokio::fs::read(cert_file).await? == cert_pem;
+    Ok(stable.then_some((cert_pem, key_pem)))
+}
+
+/// Parse the PEM encoded certificate chain and private key
+fn load_certified_key(cert_pem: &[u8], key_pem: &[u8]) -> Result<CertifiedKey> {
+    let certs = rustls_pemfile::certs(&mut &*cert_pem)?;
+    if certs.is_empty() {
+        bail!("no certificate is found in the certificate file");
+    }
+    #[allow(clippy::wildcard_enum_match_arm)] // the certificates in the key file are ignored
+    let key = rustls_pemfile::read_all(&mut &*key_pem)?
+        .into_iter()
+        .find_map(|item| match item {
+            rustls_pemfile::Item::RSAKey(key)
+            | rustls_pemfile::Item::PKCS8Key(key)
+            | rustls_pemfile::Item::ECKey(key) => Some(key),
+            _ => None,
+        })
+        .ok_or_else(|| anyhow!("no private key is found in the key file"))?;
+    let key = sign::any_supported_type(&PrivateKey(key))
+        .map_err(|_e| anyhow!("the private key is not supported"))?;
+    Ok(CertifiedKey::new(
+        certs.into_iter().map(Certificate).collect(),
+        key,
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn gen_cert() -> (String, String) {
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_owned()]).unwrap();
+        (
+            cert.serialize_pem().unwrap(),
+            cert.serialize_private_key_pem(),
+        )
+    }
+
+    fn current_cert(reloader: &CertReloader) -> Vec<u8> {
+        reloader.resolver.key.read().cert[0].0.clone()
+    }
+
+    fn der(cert_pem: &str) -> Vec<u8> {
+        rustls_pemfile::certs(&mut cert_pem.as_bytes())
+            .unwrap()
+            .remove(0)
+    }
+
+    #[tokio::test]
+    async fn test_reload_should_swap_the_certificate_when_the_files_change() {
+        let dir = std::env::temp_dir().join(format!("xline-tls-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let cert_file = dir.join("tls.crt");
+        let key_file: PathBuf = dir.join("tls.key");
+        let (cert1, key1) = gen_cert();
+        std::fs::write(&cert_file, &cert1).unwrap();
+        std::fs::write(&key_file, &key1).unwrap();
+        let config = TlsConfig::new(
+            true,
+            "127.0.0.1:0".to_owned(),
+            cert_file.clone(),
+            key_file.clone(),
+            Duration::from_secs(1),
+        );
+
+        let mut reloader = CertReloader::new(&config).await.unwrap().unwrap();
+        assert_eq!(current_cert(&reloader), der(&cert1));
+        assert!(!reloader.reload().await.unwrap());
+
+        // a half written rotation keeps the current certificate
+        let (cert2, key2) = gen_cert();
+        std::fs::write(&key_file, "not a key").unwrap();
+        std::fs::write(&cert_file, &cert2).unwrap();
+        assert!(reloader.reload().await.is_err());
+        assert_eq!(current_cert(&reloader), der(&cert1));
+
+        std::fs::write(&key_file, &key2).unwrap();
+        assert!(reloader.reload().await.unwrap());
+        assert_eq!(current_cert(&reloader), der(&cert2));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_notified_reloader_should_not_wait_for_the_interval() {
+        let dir = std::env::temp_dir().join(format!("xline-tls-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let cert_file = dir.join("tls.crt");
+        let key_file: PathBuf = dir.join("tls.key");
+        let (cert1, key1) = gen_cert();
+        std::fs::write(&cert_file, &cert1).unwrap();
+        std::fs::write(&key_file, &key1).unwrap();
+        let config = TlsConfig::new(
+            true,
+            "127.0.0.1:0".to_owned(),
+            cert_file.clone(),
+            key_file.clone(),
+            Duration::from_secs(3600),
+        );
+        let reloader = CertReloader::new(&config).await.unwrap().unwrap();
+        let resolver = Arc::clone(&reloader.resolver);
+        let reload = Arc::new(Notify::new());
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let handle = tokio::spawn(reloader.run(Arc::clone(&reload), shutdown_rx));
+
+        let (cert2, key2) = gen_cert();
+        std::fs::write(&cert_file, &cert2).unwrap();
+        std::fs::write(&key_file, &key2).unwrap();
+        reload.notify_one();
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert_eq!(resolver.key.read().cert[0].0, der(&cert2));
+
+        shutdown_tx.send(true).unwrap();
+        handle.await.unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_disabled_tls_should_have_no_reloader() {
+        assert!(CertReloader::new(&TlsConfig::default())
+            .await
+            .unwrap()
+            .is_none());
+    }
+}
@@ -0,0 +1,53 @@
+use std::sync::Arc;
+
+use tokio::sync::watch;
+use tracing::{debug, warn};
+
+use crate::storage::{storage_api::StorageApi, trash::TrashStore};
+
+/// Periodic purge of the expired entries in the trash. Each member purges its own trash by
+/// its own clock, the purges are not replicated.
+#[derive(Debug)]
+pub(crate) struct TrashPurger<S>
+where
+    S: StorageApi,
+{
+    /// Trash of the deleted keys
+    trash_store: Arc<TrashStore>,
+    /// Persistent storage
+    persistent: Arc<S>,
+}
+
+impl<S> TrashPurger<S>
+where
+    S: StorageApi,
+{
+    /// New `TrashPurger`
+    pub(crate) fn new(trash_store: Arc<TrashStore>, persistent: Arc<S>) -> Self {
+        Self {
+            trash_store,
+            persistent,
+        }
+    }
+
+    /// Purge the trash every interval until the server shuts down
+    #[allow(clippy::integer_arithmetic)] // Introduced by tokio::select!
+    pub(crate) async fn run(self, mut shutdown_rx: watch::Receiver<bool>) {
+        let mut ticker = tokio::time::interval(self.trash_store.purge_interval());
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {}
+                _ = shutdown_rx.changed() => return,
+            }
+            let trash_store = Arc::clone(&self.trash_store);
+            let persistent = Arc::clone(&self.persistent);
+            match tokio::task::spawn_blocking(move || trash_store.purge(persistent.as_ref())).await
+            {
+                Ok(Ok(0)) => {}
+                Ok(Ok(count)) => debug!("{count} expired entries are purged from the trash"),
+                Ok(Err(e)) => warn!("failed to purge the trash, {e}"),
+                Err(e) => warn!("trash purge task failed, {e}"),
+            }
+        }
+    }
+}
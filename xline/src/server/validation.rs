@@ -0,0 +1,398 @@
+use std::collections::HashSet;
+
+use utils::config::RequestLimitConfig;
+
+use super::command::KeyRange;
+use crate::{
+    rpc::{
+        Compare, DeleteRangeRequest, PutRequest, RangeRequest, Request, RequestOp, RequestWrapper,
+        SortOrder, SortTarget, TxnRequest,
+    },
+    storage::{lease_store::MAX_LEASE_TTL, member_store, ExecuteError},
+};
+
+/// Max depth of the nested txns, a txn not nested in another one is at depth 1
+const MAX_TXN_DEPTH: usize = 32;
+
+/// Validation of the requests, the servers check the requests by it before they are
+/// executed or proposed, so that an invalid request fails with the same error as etcd
+/// without reaching the consensus
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct RequestValidator {
+    /// Max number of operations in a txn
+    max_txn_ops: usize,
+}
+
+impl RequestValidator {
+    /// New `RequestValidator`
+    pub(crate) fn new(request_limit_config: &RequestLimitConfig) -> Self {
+        Self {
+            max_txn_ops: *request_limit_config.max_txn_ops(),
+        }
+    }
+
+    /// Validate a request before it's proposed
+    pub(crate) fn validate(&self, request: &RequestWrapper) -> Result<(), ExecuteError> {
+        match *request {
+            RequestWrapper::RangeRequest(ref req) => Self::check_range(req),
+            RequestWrapper::PutRequest(ref req) => Self::check_put(req),
+            RequestWrapper::DeleteRangeRequest(ref req) => Self::check_delete_range(req),
+            RequestWrapper::TxnRequest(ref req) => self.check_txn(req),
+            RequestWrapper::AuthUserAddRequest(ref req) => {
+                if req.name.is_empty() {
+                    return Err(ExecuteError::user_empty());
+                }
+                Ok(())
+            }
+            RequestWrapper::AuthRoleAddRequest(ref req) => {
+                if req.name.is_empty() {
+                    return Err(ExecuteError::role_empty());
+                }
+                Ok(())
+            }
+            RequestWrapper::AuthRoleGrantPermissionRequest(ref req) => {
+                if req.perm.is_none() {
+                    return Err(ExecuteError::permission_not_given());
+                }
+                Ok(())
+            }
+            RequestWrapper::LeaseGrantRequest(ref req) => Self::check_lease_ttl(req.ttl),
+            RequestWrapper::LeaseModifyTtlRequest(ref req) => Self::check_lease_ttl(req.ttl),
+            RequestWrapper::MemberUpdateRequest(ref req) => Self::check_peer_urls(&req.peer_ur_ls),
+            RequestWrapper::CompactionRequest(_)
+            | RequestWrapper::AuthEnableRequest(_)
+            | RequestWrapper::AuthDisableRequest(_)
+            | RequestWrapper::AuthStatusRequest(_)
+            | RequestWrapper::AuthRoleDeleteRequest(_)
+            | RequestWrapper::AuthRoleGetRequest(_)
+            | RequestWrapper::AuthRoleListRequest(_)
+            | RequestWrapper::AuthRoleRevokePermissionRequest(_)
+            | RequestWrapper::AuthUserChangePasswordRequest(_)
+            | RequestWrapper::AuthUserDeleteRequest(_)
+            | RequestWrapper::AuthUserGetRequest(_)
+            | RequestWrapper::AuthUserGrantRoleRequest(_)
+            | RequestWrapper::AuthUserListRequest(_)
+            | RequestWrapper::AuthUserRevokeRoleRequest(_)
+            | RequestWrapper::AuthenticateRequest(_)
+            | RequestWrapper::AuthApiKeyAddRequest(_)
+            | RequestWrapper::AuthApiKeyRevokeRequest(_)
+            | RequestWrapper::AuthApiKeyListRequest(_)
+            | RequestWrapper::LeaseRevokeRequest(_) => Ok(()),
+        }
+    }
+
+    /// Validate a range request
+    pub(crate) fn check_range(req: &RangeRequest) -> Result<(), ExecuteError> {
+        Self::check_key_range(&req.key, &req.range_end)?;
+        if !SortOrder::is_valid(req.sort_order) || !SortTarget::is_valid(req.sort_target) {
+            return Err(ExecuteError::invalid_sort_option());
+        }
+        Ok(())
+    }
+
+    /// Validate a put request
+    pub(crate) fn check_put(req: &PutRequest) -> Result<(), ExecuteError> {
+        if req.key.is_empty() {
+            return Err(ExecuteError::empty_key());
+        }
+        if req.ignore_value && !req.value.is_empty() {
+            return Err(ExecuteError::value_provided());
+        }
+        if req.ignore_lease && req.lease != 0 {
+            return Err(ExecuteError::lease_provided());
+        }
+        Ok(())
+    }
+
+    /// Validate a delete range request
+    pub(crate) fn check_delete_range(req: &DeleteRangeRequest) -> Result<(), ExecuteError> {
+        Self::check_key_range(&req.key, &req.range_end)
+    }
+
+    /// Validate a txn request
+    pub(crate) fn check_txn(&self, req: &TxnRequest) -> Result<(), ExecuteError> {
+        self.check_txn_at(req, 1)?;
+        let _ignore_success = Self::check_intervals(&req.success)?;
+        let _ignore_failure = Self::check_intervals(&req.failure)?;
+        Ok(())
+    }
+
+    /// Validate a txn request nested at `depth`
+    fn check_txn_at(&self, req: &TxnRequest, depth: usize) -> Result<(), ExecuteError> {
+        if depth > MAX_TXN_DEPTH {
+            return Err(ExecuteError::txn_too_deep());
+        }
+        let opc = req
+            .compare
+            .len()
+            .max(req.success.len())
+            .max(req.failure.len());
+        if opc > self.max_txn_ops {
+            return Err(ExecuteError::too_many_ops());
+        }
+        for c in &req.compare {
+            Self::check_compare(c)?;
+        }
+        for op in req.success.iter().chain(req.failure.iter()) {
+            match op.request {
+                Some(Request::RequestRange(ref r)) => Self::check_range(r),
+                Some(Request::RequestPut(ref r)) => Self::check_put(r),
+                Some(Request::RequestDeleteRange(ref r)) => Self::check_delete_range(r),
+                Some(Request::RequestTxn(ref r)) => self.check_txn_at(r, depth.saturating_add(1)),
+                None => Err(ExecuteError::key_not_found()),
+            }?;
+        }
+        Ok(())
+    }
+
+    /// Validate a compare of a txn
+    fn check_compare(compare: &Compare) -> Result<(), ExecuteError> {
+        Self::check_key_range(&compare.key, &compare.range_end)
+    }
+
+    /// Validate the range `[key, range_end)`, the key must be provided, and the range end
+    /// must not be less than the key unless it's empty or `\0`, which stand for the key only
+    /// and all the keys from the key
+    fn check_key_range(key: &[u8], range_end: &[u8]) -> Result<(), ExecuteError> {
+        if key.is_empty() {
+            return Err(ExecuteError::empty_key());
+        }
+        if !range_end.is_empty() && range_end != [0] && range_end < key {
+            return Err(ExecuteError::invalid_range_end());
+        }
+        Ok(())
+    }
+
+    /// Validate the TTL of a lease
+    fn check_lease_ttl(ttl: i64) -> Result<(), ExecuteError> {
+        if ttl > MAX_LEASE_TTL {
+            return Err(ExecuteError::lease_ttl_too_large());
+        }
+        Ok(())
+    }
+
+    /// Validate the peer URLs of a member, a member serves the peers and the clients on one
+    /// address, so exactly one URL is given
+    fn check_peer_urls(urls: &[String]) -> Result<(), ExecuteError> {
+        match *urls {
+            [ref url] if member_store::peer_addr(url).is_some() => Ok(()),
+            _ => Err(ExecuteError::member_bad_urls()),
+        }
+    }
+
+    /// Check if puts and deletes overlap
+    fn check_intervals(ops: &[RequestOp]) -> Result<(HashSet<&[u8]>, Vec<KeyRange>), ExecuteError> {
+        // TODO: use interval tree is better?
+
+        let mut dels = Vec::new();
+
+        for op in ops {
+            if let Some(Request::RequestDeleteRange(ref req)) = op.request {
+                // collect dels
+                let del = KeyRange {
+                    start: req.key.clone(),
+                    end: req.range_end.clone(),
+                };
+                dels.push(del);
+            }
+        }
+
+        let mut puts: HashSet<&[u8]> = HashSet::new();
+
+        for op in ops {
+            if let Some(Request::RequestTxn(ref req)) = op.request {
+                // handle child txn request
+                let (success_puts, mut success_dels) = Self::check_intervals(&req.success)?;
+                let (failure_puts, mut failure_dels) = Self::check_intervals(&req.failure)?;
+
+                for k in &success_puts {
+                    if !puts.insert(k) {
+                        return Err(ExecuteError::duplicate_key());
+                    }
+                    if dels.iter().any(|del| del.contains_key(k)) {
+                        return Err(ExecuteError::duplicate_key());
+                    }
+                }
+
+                for k in failure_puts {
+                    if !puts.insert(k) && !success_puts.contains(k) {
+                        // only keys in the puts and not in the success_puts is overlap
+                        return Err(ExecuteError::duplicate_key());
+                    }
+                    if dels.iter().any(|del| del.contains_key(k)) {
+                        return Err(ExecuteError::duplicate_key());
+                    }
+                }
+
+                dels.append(&mut success_dels);
+                dels.append(&mut failure_dels);
+            }
+        }
+
+        for op in ops {
+            if let Some(Request::RequestPut(ref req)) = op.request {
+                // check puts in this level
+                if !puts.insert(&req.key) {
+                    return Err(ExecuteError::duplicate_key());
+                }
+                if dels.iter().any(|del| del.contains_key(&req.key)) {
+                    return Err(ExecuteError::duplicate_key());
+                }
+            }
+        }
+        Ok((puts, dels))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+
+    use super::*;
+    use crate::rpc::{LeaseGrantRequest, MemberUpdateRequest};
+
+    fn validator(max_txn_ops: usize) -> RequestValidator {
+        RequestValidator { max_txn_ops }
+    }
+
+    #[test]
+    fn txn_check() {
+        let txn_req = TxnRequest {
+            compare: vec![],
+            success: vec![
+                RequestOp {
+                    request: Some(Request::RequestDeleteRange(DeleteRangeRequest {
+                        key: b"foo1".to_vec(),
+                        range_end: vec![],
+                        prev_kv: false,
+                    })),
+                },
+                RequestOp {
+                    request: Some(Request::RequestTxn(TxnRequest {
+                        compare: vec![],
+                        success: vec![RequestOp {
+                            request: Some(Request::RequestPut(PutRequest {
+                                key: b"foo".to_vec(),
+                                value: Bytes::from_static(b"bar"),
+                                lease: 0,
+                                prev_kv: false,
+                                ignore_value: false,
+                                ignore_lease: false,
+                            })),
+                        }],
+                        failure: vec![RequestOp {
+                            request: Some(Request::RequestPut(PutRequest {
+                                key: b"foo".to_vec(),
+                                value: Bytes::from_static(b"bar"),
+                                lease: 0,
+                                prev_kv: false,
+                                ignore_value: false,
+                                ignore_lease: false,
+                            })),
+                        }],
+                    })),
+                },
+            ],
+            failure: vec![],
+        };
+        let validator = RequestValidator::new(&RequestLimitConfig::default());
+        assert!(validator.check_txn(&txn_req).is_ok());
+    }
+
+    #[test]
+    fn txn_with_too_many_ops_should_fail() {
+        let put = |key: usize| RequestOp {
+            request: Some(Request::RequestPut(PutRequest {
+                key: format!("foo{key}").into_bytes(),
+                ..PutRequest::default()
+            })),
+        };
+        let txn_req = TxnRequest {
+            compare: vec![],
+            success: (0..3).map(put).collect(),
+            failure: vec![],
+        };
+        assert!(validator(3).check_txn(&txn_req).is_ok());
+        let status = tonic::Status::from(validator(2).check_txn(&txn_req).unwrap_err());
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        assert_eq!(
+            status.message(),
+            "etcdserver: too many operations in txn request"
+        );
+    }
+
+    #[test]
+    fn txn_nested_too_deeply_should_fail() {
+        let nest = |txn: TxnRequest| TxnRequest {
+            compare: vec![],
+            success: vec![RequestOp {
+                request: Some(Request::RequestTxn(txn)),
+            }],
+            failure: vec![],
+        };
+        let mut txn_req = TxnRequest::default();
+        for _ in 1..MAX_TXN_DEPTH {
+            txn_req = nest(txn_req);
+        }
+        assert!(validator(1).check_txn(&txn_req).is_ok());
+        let status = tonic::Status::from(validator(1).check_txn(&nest(txn_req)).unwrap_err());
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+    }
+
+    #[test]
+    fn invalid_fields_should_fail() {
+        let validator = validator(128);
+        let range = |key: &[u8], range_end: &[u8]| {
+            RequestWrapper::from(RangeRequest {
+                key: key.to_vec(),
+                range_end: range_end.to_vec(),
+                ..RangeRequest::default()
+            })
+        };
+        assert!(validator.validate(&range(b"b", b"")).is_ok());
+        assert!(validator.validate(&range(b"b", b"\0")).is_ok());
+        assert!(validator.validate(&range(b"b", b"c")).is_ok());
+        assert!(validator.validate(&range(b"", b"c")).is_err());
+        let status = tonic::Status::from(validator.validate(&range(b"b", b"a")).unwrap_err());
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+
+        let put = RequestWrapper::from(PutRequest {
+            key: b"foo".to_vec(),
+            value: Bytes::from_static(b"bar"),
+            ignore_value: true,
+            ..PutRequest::default()
+        });
+        let status = tonic::Status::from(validator.validate(&put).unwrap_err());
+        assert_eq!(status.message(), "etcdserver: value is provided");
+
+        let grant = |ttl| RequestWrapper::from(LeaseGrantRequest { ttl, id: 1 });
+        assert!(validator.validate(&grant(MAX_LEASE_TTL)).is_ok());
+        let status =
+            tonic::Status::from(validator.validate(&grant(MAX_LEASE_TTL + 1)).unwrap_err());
+        assert_eq!(status.code(), tonic::Code::OutOfRange);
+
+        let update = |urls: &[&str]| {
+            RequestWrapper::from(MemberUpdateRequest {
+                id: 1,
+                peer_ur_ls: urls.iter().map(|&url| url.to_owned()).collect(),
+            })
+        };
+        assert!(validator
+            .validate(&update(&["http://10.0.0.1:2379"]))
+            .is_ok());
+        assert!(validator.validate(&update(&["10.0.0.1:2379"])).is_ok());
+        assert!(validator
+            .validate(&update(&["https://10.0.0.1:2379"]))
+            .is_ok());
+        for urls in [
+            &[][..],
+            &["http://10.0.0.1"],
+            &["ftp://10.0.0.1:2379"],
+            &["http://10.0.0.1:2379/path"],
+            &["http://10.0.0.1:2379", "http://10.0.0.2:2379"],
+        ] {
+            let status = tonic::Status::from(validator.validate(&update(urls)).unwrap_err());
+            assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        }
+    }
+}
@@ -1,7 +1,12 @@
-use std::{collections::HashSet, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    iter,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use clippy_utilities::OverflowArithmetic;
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, watch};
 use tokio_stream::{wrappers::ReceiverStream, Stream, StreamExt};
 use tracing::{debug, warn};
 
@@ -12,13 +17,23 @@ use crate::{
         WatchResponse,
     },
     storage::{
-        kvwatcher::{KvWatcher, KvWatcherOps, WatchEvent, WatchId},
+        durable_watch::DurableWatchStore,
+        kvwatcher::{split_events, KvWatcher, KvWatcherOps, WatchEvent, WatchId},
         storage_api::StorageApi,
+        watch_filter::EventFilter,
+        ExecuteError,
     },
 };
 
 /// Default channel size
 const CHANNEL_SIZE: usize = 128;
+/// Cancel reason of watches canceled by server shutdown
+const SHUTDOWN_CANCEL_REASON: &str = "server is shutting down";
+/// Cancel reason of watches starting from a compacted revision
+const COMPACTED_CANCEL_REASON: &str = "etcdserver: mvcc: required revision has been compacted";
+/// Minimum interval between two persistences of the progress of a durable watcher, the
+/// progress is also persisted when the watcher is closed
+const DURABLE_PERSIST_INTERVAL: Duration = Duration::from_secs(1);
 
 /// Watch Server
 #[derive(Debug)]
@@ -28,6 +43,10 @@ where
 {
     /// KV watcher
     watcher: Arc<KvWatcher<S>>,
+    /// Registrations of the durable watchers
+    durable_store: Arc<DurableWatchStore<S>>,
+    /// Shutdown signal receiver
+    shutdown_rx: watch::Receiver<bool>,
 }
 
 impl<S> WatchServer<S>
@@ -35,23 +54,40 @@ where
     S: StorageApi,
 {
     /// New `WatchServer`
-    pub(crate) fn new(watcher: Arc<KvWatcher<S>>) -> Self {
-        Self { watcher }
+    pub(crate) fn new(
+        watcher: Arc<KvWatcher<S>>,
+        durable_store: Arc<DurableWatchStore<S>>,
+        shutdown_rx: watch::Receiver<bool>,
+    ) -> Self {
+        Self {
+            watcher,
+            durable_store,
+            shutdown_rx,
+        }
     }
 
     /// bg task for handle watch connection
     #[allow(clippy::integer_arithmetic)] // Introduced by tokio::select!
     async fn task<ST, W>(
         kv_watcher: Arc<W>,
+        durable_store: Arc<DurableWatchStore<S>>,
         res_tx: mpsc::Sender<Result<WatchResponse, tonic::Status>>,
         mut req_rx: ST,
+        mut shutdown_rx: watch::Receiver<bool>,
     ) where
         ST: Stream<Item = Result<WatchRequest, tonic::Status>> + Unpin,
         W: KvWatcherOps,
     {
         let (event_tx, event_rx) = mpsc::channel(CHANNEL_SIZE);
         let (stop_tx, stop_rx) = flume::bounded(0);
-        let mut watch_handle = WatchHandle::new(kv_watcher, res_tx, event_rx, event_tx, stop_tx);
+        let mut watch_handle = WatchHandle::new(
+            kv_watcher,
+            durable_store,
+            res_tx,
+            event_rx,
+            event_tx,
+            stop_tx,
+        );
         loop {
             tokio::select! {
                 req = req_rx.next() => {
@@ -80,19 +116,47 @@ where
                 _ = stop_rx.recv_async() => {
                     break;
                 }
+                _ = shutdown_rx.changed() => {
+                    watch_handle.handle_shutdown().await;
+                    break;
+                }
             }
         }
     }
 }
 
+/// A durable watcher served by a watch connection
+#[derive(Debug)]
+struct DurableWatcher {
+    /// Resume token of the watcher
+    token: String,
+    /// Create request of the watcher, its `start_revision` is the next revision to deliver
+    request: WatchCreateRequest,
+    /// When the progress is persisted last time
+    persisted_at: Instant,
+}
+
+impl DurableWatcher {
+    /// Persist the progress of the watcher
+    fn persist<S: StorageApi>(&mut self, durable_store: &DurableWatchStore<S>) {
+        if let Err(e) = durable_store.save(&self.token, &self.request) {
+            warn!("failed to persist durable watcher {}, {e}", self.token);
+        }
+        self.persisted_at = Instant::now();
+    }
+}
+
 /// Handler for one watch connection
 #[derive(Debug)]
-struct WatchHandle<W>
+struct WatchHandle<W, S>
 where
     W: KvWatcherOps,
+    S: StorageApi,
 {
     /// KV watcher
     kv_watcher: Arc<W>,
+    /// Registrations of the durable watchers
+    durable_store: Arc<DurableWatchStore<S>>,
     /// `WatchResponse` Sender
     response_tx: mpsc::Sender<Result<WatchResponse, tonic::Status>>,
     /// Event receiver
@@ -101,19 +165,23 @@ where
     event_tx: mpsc::Sender<WatchEvent>,
     /// Watch ID to watcher map
     active_watch_ids: HashSet<WatchId>,
+    /// Durable watchers of the active watches
+    durable_watchers: HashMap<WatchId, DurableWatcher>,
     /// Next available `WatchId`
     next_id: WatchId,
     /// Stop tx
     stop_tx: flume::Sender<()>,
 }
 
-impl<W> WatchHandle<W>
+impl<W, S> WatchHandle<W, S>
 where
     W: KvWatcherOps,
+    S: StorageApi,
 {
     /// New `WatchHandle`
     fn new(
         kv_watcher: Arc<W>,
+        durable_store: Arc<DurableWatchStore<S>>,
         response_tx: mpsc::Sender<Result<WatchResponse, tonic::Status>>,
         event_rx: mpsc::Receiver<WatchEvent>,
         event_tx: mpsc::Sender<WatchEvent>,
@@ -121,10 +189,12 @@ where
     ) -> Self {
         Self {
             kv_watcher,
+            durable_store,
             response_tx,
             event_rx,
             event_tx,
             active_watch_ids: HashSet::new(),
+            durable_watchers: HashMap::new(),
             next_id: 1, // watch_id starts from 1, 0 means auto-generating
             stop_tx,
         }
@@ -162,22 +232,86 @@ where
             }
             return;
         };
-
-        let key_range = KeyRange {
-            start: req.key,
-            end: req.range_end,
+        let created = self.resume_request(req).and_then(|(req, resume_token)| {
+            let filter = EventFilter::new(req.filters.clone(), req.value_predicate.clone())
+                .map_err(tonic::Status::invalid_argument)?;
+            Ok((req, resume_token, filter))
+        });
+        let (req, resume_token, filter) = match created {
+            Ok(created) => created,
+            Err(status) => {
+                if self.response_tx.send(Err(status)).await.is_err() {
+                    self.stop_tx.send(()).unwrap_or_else(|e| {
+                        warn!("failed to send stop signal: {}", e);
+                    });
+                }
+                return;
+            }
         };
-        let (events, revision) = self.kv_watcher.watch(
+        let durable = req.durable.then(|| req.clone());
+
+        let key_ranges = iter::once(KeyRange::new(req.key, req.range_end))
+            .chain(
+                req.extra_ranges
+                    .into_iter()
+                    .map(|range| KeyRange::new(range.key, range.range_end)),
+            )
+            .collect();
+        let watched = self.kv_watcher.watch(
             watch_id,
-            key_range,
+            key_ranges,
             req.start_revision,
-            req.filters,
+            filter,
             self.event_tx.clone(),
         );
+        let (events, revision) = match watched {
+            Ok(watched) => watched,
+            Err(compact_revision) => {
+                // the watch can't start from a compacted revision, it's created and
+                // canceled at once like etcd, the client learns the compacted revision
+                if let Some(ref token) = resume_token {
+                    self.unregister(token);
+                }
+                let response = WatchResponse {
+                    header: Some(ResponseHeader::default()),
+                    watch_id,
+                    created: true,
+                    canceled: true,
+                    compact_revision,
+                    cancel_reason: COMPACTED_CANCEL_REASON.to_owned(),
+                    ..WatchResponse::default()
+                };
+                if self.response_tx.send(Ok(response)).await.is_err() {
+                    self.stop_tx.send(()).unwrap_or_else(|e| {
+                        warn!("failed to send stop signal: {}", e);
+                    });
+                }
+                return;
+            }
+        };
         assert!(
             self.active_watch_ids.insert(watch_id),
             "WatchId {watch_id} already exists in watcher_map",
         );
+        let token = match durable {
+            Some(request) => match self.track_durable(watch_id, request, resume_token, revision) {
+                Ok(token) => token,
+                Err(e) => {
+                    let _revision = self.kv_watcher.cancel(watch_id);
+                    let _prev = self.active_watch_ids.remove(&watch_id);
+                    let result = Err(tonic::Status::internal(format!(
+                        "failed to register durable watcher, {e}"
+                    )));
+                    if self.response_tx.send(result).await.is_err() {
+                        self.stop_tx.send(()).unwrap_or_else(|e| {
+                            warn!("failed to send stop signal: {}", e);
+                        });
+                    }
+                    return;
+                }
+            },
+            None => String::new(),
+        };
 
         let response = WatchResponse {
             header: Some(ResponseHeader {
@@ -186,6 +320,7 @@ where
             }),
             watch_id,
             created: true,
+            resume_token: token,
             ..WatchResponse::default()
         };
         if self.response_tx.send(Ok(response)).await.is_err() {
@@ -193,8 +328,9 @@ where
                 warn!("failed to send stop signal: {}", e);
             });
         }
-        // send initial events
-        if !events.is_empty() {
+        // send initial events, the history may be large, so it's sent in chunks
+        let mut delivered = true;
+        for events in split_events(events) {
             let event_response = WatchResponse {
                 header: Some(ResponseHeader {
                     revision,
@@ -208,8 +344,75 @@ where
                 self.stop_tx.send(()).unwrap_or_else(|e| {
                     warn!("failed to send stop signal: {}", e);
                 });
+                delivered = false;
+                break;
             }
         }
+        if delivered {
+            if let Some(durable) = self.durable_watchers.get_mut(&watch_id) {
+                durable.request.start_revision = revision.overflow_add(1);
+            }
+        }
+    }
+
+    /// Get the request of a watcher to create, it's the registered one if the watcher is
+    /// resumed by a resume token, which is returned along with it
+    fn resume_request(
+        &self,
+        req: WatchCreateRequest,
+    ) -> Result<(WatchCreateRequest, Option<String>), tonic::Status> {
+        if req.resume_token.is_empty() {
+            return Ok((req, None));
+        }
+        match self.durable_store.get(&req.resume_token) {
+            Ok(Some(mut registered)) => {
+                registered.watch_id = req.watch_id;
+                Ok((registered, Some(req.resume_token)))
+            }
+            Ok(None) => Err(tonic::Status::not_found(format!(
+                "Resume token {} doesn't exist",
+                req.resume_token
+            ))),
+            Err(e) => Err(tonic::Status::internal(format!(
+                "failed to get durable watcher {}, {e}",
+                req.resume_token
+            ))),
+        }
+    }
+
+    /// Track a created durable watcher, it's registered unless it's resumed by
+    /// `resume_token`. Return the resume token of the watcher.
+    fn track_durable(
+        &mut self,
+        watch_id: WatchId,
+        mut request: WatchCreateRequest,
+        resume_token: Option<String>,
+        revision: i64,
+    ) -> Result<String, ExecuteError> {
+        // a watcher without a start revision watches the events after the current revision
+        if request.start_revision <= 0 {
+            request.start_revision = revision.overflow_add(1);
+        }
+        let token = match resume_token {
+            Some(token) => token,
+            None => self.durable_store.register(&request)?,
+        };
+        let _prev = self.durable_watchers.insert(
+            watch_id,
+            DurableWatcher {
+                token: token.clone(),
+                request,
+                persisted_at: Instant::now(),
+            },
+        );
+        Ok(token)
+    }
+
+    /// Remove the registration of a durable watcher
+    fn unregister(&self, token: &str) {
+        if let Err(e) = self.durable_store.remove(token) {
+            warn!("failed to remove durable watcher {token}, {e}");
+        }
     }
 
     /// Handle `WatchCancelRequest`
@@ -218,6 +421,9 @@ where
         let result = if self.active_watch_ids.remove(&watch_id) {
             let revision = self.kv_watcher.cancel(watch_id);
             let _prev = self.active_watch_ids.remove(&watch_id);
+            if let Some(durable) = self.durable_watchers.remove(&watch_id) {
+                self.unregister(&durable.token);
+            }
             let response = WatchResponse {
                 header: Some(ResponseHeader {
                     revision,
@@ -261,35 +467,110 @@ where
     /// Handle watch event
     async fn handle_watch_event(&mut self, mut event: WatchEvent) {
         let watch_id = event.watch_id();
+        let compact_revision = event.compact_revision();
         let events = event.take_events();
-        if events.is_empty() {
+        let last_revision = events
+            .iter()
+            .filter_map(|event| event.kv.as_ref())
+            .map(|kv| kv.mod_revision)
+            .max();
+        let response = if compact_revision > 0 {
+            // the watcher lags behind at a compacted revision, it's removed by the kv
+            // watcher and canceled like etcd
+            if !self.active_watch_ids.remove(&watch_id) {
+                return;
+            }
+            if let Some(durable) = self.durable_watchers.remove(&watch_id) {
+                self.unregister(&durable.token);
+            }
+            WatchResponse {
+                header: Some(ResponseHeader {
+                    revision: event.revision(),
+                    ..ResponseHeader::default()
+                }),
+                watch_id,
+                canceled: true,
+                compact_revision,
+                cancel_reason: COMPACTED_CANCEL_REASON.to_owned(),
+                ..WatchResponse::default()
+            }
+        } else if events.is_empty() {
             return;
-        }
-        let response = WatchResponse {
-            header: Some(ResponseHeader {
-                revision: event.revision(),
-                ..ResponseHeader::default()
-            }),
-            watch_id,
-            events,
-            ..WatchResponse::default()
+        } else {
+            WatchResponse {
+                header: Some(ResponseHeader {
+                    revision: event.revision(),
+                    ..ResponseHeader::default()
+                }),
+                watch_id,
+                events,
+                ..WatchResponse::default()
+            }
         };
         if self.response_tx.send(Ok(response)).await.is_err() {
             self.stop_tx.send(()).unwrap_or_else(|e| {
                 warn!("failed to send stop signal: {}", e);
             });
+            return;
+        }
+        if let (Some(durable), Some(revision)) =
+            (self.durable_watchers.get_mut(&watch_id), last_revision)
+        {
+            // the events of the last revision may be split into several responses, so the
+            // watcher is resumed from it, the delivered events may be sent again
+            durable.request.start_revision = revision;
+            if durable.persisted_at.elapsed() >= DURABLE_PERSIST_INTERVAL {
+                durable.persist(&self.durable_store);
+            }
+        }
+    }
+
+    /// Cancel all active watches when the server shuts down, clients can resume the
+    /// watches on another server from the revision in the response header, or resume the
+    /// durable watchers by the resume tokens after the server restarts
+    async fn handle_shutdown(&mut self) {
+        let mut closed = false;
+        let mut durable_watchers = std::mem::take(&mut self.durable_watchers);
+        for watch_id in std::mem::take(&mut self.active_watch_ids) {
+            let revision = self.kv_watcher.cancel(watch_id);
+            let resume_token = match durable_watchers.remove(&watch_id) {
+                Some(mut durable) => {
+                    durable.persist(&self.durable_store);
+                    durable.token
+                }
+                None => String::new(),
+            };
+            if closed {
+                continue;
+            }
+            let response = WatchResponse {
+                header: Some(ResponseHeader {
+                    revision,
+                    ..ResponseHeader::default()
+                }),
+                watch_id,
+                canceled: true,
+                cancel_reason: SHUTDOWN_CANCEL_REASON.to_owned(),
+                resume_token,
+                ..WatchResponse::default()
+            };
+            closed = self.response_tx.send(Ok(response)).await.is_err();
         }
     }
 }
 
-impl<W> Drop for WatchHandle<W>
+impl<W, S> Drop for WatchHandle<W, S>
 where
     W: KvWatcherOps,
+    S: StorageApi,
 {
     fn drop(&mut self) {
         for watch_id in &self.active_watch_ids {
             let _revision = self.kv_watcher.cancel(*watch_id);
         }
+        for durable in self.durable_watchers.values_mut() {
+            durable.persist(&self.durable_store);
+        }
     }
 }
 
@@ -313,7 +594,13 @@ where
         debug!("Receive Watch Connection {:?}", request);
         let req_stream = request.into_inner();
         let (tx, rx) = mpsc::channel(CHANNEL_SIZE);
-        let _hd = tokio::spawn(Self::task(Arc::clone(&self.watcher), tx, req_stream));
+        let _hd = tokio::spawn(Self::task(
+            Arc::clone(&self.watcher),
+            Arc::clone(&self.durable_store),
+            tx,
+            req_stream,
+            self.shutdown_rx.clone(),
+        ));
         Ok(tonic::Response::new(ReceiverStream::new(rx)))
     }
 }
@@ -321,10 +608,16 @@ where
 #[cfg(test)]
 mod test {
 
-    use engine::memory_engine::MemoryEngine;
+    use utils::config::StorageConfig;
 
     use super::*;
-    use crate::storage::{db::DB, kvwatcher::MockKvWatcherOps};
+    use crate::storage::{db::DBProxy, kvwatcher::MockKvWatcherOps};
+
+    fn durable_store() -> Arc<DurableWatchStore<DBProxy>> {
+        Arc::new(DurableWatchStore::new(
+            DBProxy::open(&StorageConfig::Memory).unwrap(),
+        ))
+    }
 
     #[tokio::test]
     async fn test_watch_client_closes_connection() -> Result<(), Box<dyn std::error::Error>> {
@@ -337,13 +630,16 @@ mod test {
         let _ = mock_watcher
             .expect_watch()
             .times(1)
-            .return_const((vec![], 0));
+            .return_const(Ok((vec![], 0)));
         let _ = mock_watcher.expect_cancel().times(1).returning(move |_| 0);
         let watcher = Arc::new(mock_watcher);
-        let handle = tokio::spawn(WatchServer::<DB<MemoryEngine>>::task(
+        let (_shutdown_tx, shutdown_rx) = watch::channel(false);
+        let handle = tokio::spawn(WatchServer::task(
             Arc::clone(&watcher),
+            durable_store(),
             res_tx,
             req_stream,
+            shutdown_rx,
         ));
         req_tx
             .send(Ok(WatchRequest {
@@ -361,4 +657,140 @@ mod test {
         tokio::time::timeout(std::time::Duration::from_secs(3), handle).await??;
         Ok(())
     }
+    #[tokio::test]
+    async fn test_watches_are_canceled_on_shutdown() -> Result<(), Box<dyn std::error::Error>> {
+        let (req_tx, req_rx) = mpsc::channel(CHANNEL_SIZE);
+        let (res_tx, mut res_rx) = mpsc::channel(CHANNEL_SIZE);
+        let req_stream: ReceiverStream<Result<WatchRequest, tonic::Status>> =
+            ReceiverStream::new(req_rx);
+
+        let mut mock_watcher = MockKvWatcherOps::new();
+        let _ = mock_watcher
+            .expect_watch()
+            .times(1)
+            .return_const(Ok((vec![], 3)));
+        let _ = mock_watcher.expect_cancel().times(1).returning(move |_| 5);
+        let watcher = Arc::new(mock_watcher);
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let handle = tokio::spawn(WatchServer::task(
+            Arc::clone(&watcher),
+            durable_store(),
+            res_tx,
+            req_stream,
+            shutdown_rx,
+        ));
+        req_tx
+            .send(Ok(WatchRequest {
+                request_union: Some(RequestUnion::CreateRequest(WatchCreateRequest {
+                    key: vec![0],
+                    range_end: vec![0],
+                    ..Default::default()
+                })),
+            }))
+            .await?;
+        let res = res_rx.recv().await.unwrap()?;
+        assert!(res.created);
+
+        shutdown_tx.send(true)?;
+        let res = res_rx.recv().await.unwrap()?;
+        assert!(res.canceled);
+        assert_eq!(res.watch_id, 1);
+        assert_eq!(res.cancel_reason, SHUTDOWN_CANCEL_REASON);
+        assert_eq!(res.header.unwrap().revision, 5);
+        tokio::time::timeout(std::time::Duration::from_secs(3), handle).await??;
+        assert!(res_rx.recv().await.is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_durable_watchers_are_resumed_after_shutdown(
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let durable_store = durable_store();
+        let create = |request: WatchCreateRequest| WatchRequest {
+            request_union: Some(RequestUnion::CreateRequest(request)),
+        };
+
+        let (req_tx, req_rx) = mpsc::channel(CHANNEL_SIZE);
+        let (res_tx, mut res_rx) = mpsc::channel(CHANNEL_SIZE);
+        let mut mock_watcher = MockKvWatcherOps::new();
+        let _ = mock_watcher
+            .expect_watch()
+            .times(1)
+            .return_const(Ok((vec![], 3)));
+        let _ = mock_watcher.expect_cancel().times(1).returning(move |_| 3);
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let handle = tokio::spawn(WatchServer::task(
+            Arc::new(mock_watcher),
+            Arc::clone(&durable_store),
+            res_tx,
+            ReceiverStream::new(req_rx),
+            shutdown_rx,
+        ));
+        req_tx
+            .send(Ok(create(WatchCreateRequest {
+                key: b"foo".to_vec(),
+                durable: true,
+                ..Default::default()
+            })))
+            .await?;
+        let res = res_rx.recv().await.unwrap()?;
+        assert!(res.created);
+        let token = res.resume_token;
+        assert!(!token.is_empty());
+        shutdown_tx.send(true)?;
+        let res = res_rx.recv().await.unwrap()?;
+        assert!(res.canceled);
+        assert_eq!(res.resume_token, token);
+        tokio::time::timeout(std::time::Duration::from_secs(3), handle).await??;
+
+        // the watcher is resumed from the revision after the one it's created at
+        let (req_tx, req_rx) = mpsc::channel(CHANNEL_SIZE);
+        let (res_tx, mut res_rx) = mpsc::channel(CHANNEL_SIZE);
+        let mut mock_watcher = MockKvWatcherOps::new();
+        let _ = mock_watcher
+            .expect_watch()
+            .withf(|_, key_ranges, start_rev, _, _| key_ranges.len() == 1 && *start_rev == 4)
+            .times(1)
+            .return_const(Ok((vec![], 6)));
+        let _ = mock_watcher.expect_cancel().times(1).returning(move |_| 6);
+        let (_shutdown_tx, shutdown_rx) = watch::channel(false);
+        let handle = tokio::spawn(WatchServer::task(
+            Arc::new(mock_watcher),
+            Arc::clone(&durable_store),
+            res_tx,
+            ReceiverStream::new(req_rx),
+            shutdown_rx,
+        ));
+        req_tx
+            .send(Ok(create(WatchCreateRequest {
+                resume_token: "unknown".to_owned(),
+                ..Default::default()
+            })))
+            .await?;
+        let status = res_rx.recv().await.unwrap().unwrap_err();
+        assert_eq!(status.code(), tonic::Code::NotFound);
+        req_tx
+            .send(Ok(create(WatchCreateRequest {
+                resume_token: token.clone(),
+                ..Default::default()
+            })))
+            .await?;
+        let res = res_rx.recv().await.unwrap()?;
+        assert!(res.created);
+        assert_eq!(res.resume_token, token);
+
+        // the registration is removed once the watcher is canceled
+        req_tx
+            .send(Ok(WatchRequest {
+                request_union: Some(RequestUnion::CancelRequest(WatchCancelRequest {
+                    watch_id: res.watch_id,
+                })),
+            }))
+            .await?;
+        assert!(res_rx.recv().await.unwrap()?.canceled);
+        assert_eq!(durable_store.get(&token)?, None);
+        drop(req_tx);
+        tokio::time::timeout(std::time::Duration::from_secs(3), handle).await??;
+        Ok(())
+    }
 }
@@ -1,34 +1,86 @@
-use std::{collections::HashMap, future::Future, net::SocketAddr, sync::Arc, time::Duration};
+use std::{
+    collections::HashMap, future::Future, io, net::SocketAddr, path::PathBuf, sync::Arc,
+    time::Duration,
+};
 
-use anyhow::Result;
+use anyhow::{anyhow, bail, Result};
 use curp::{client::Client, server::Rpc, ProtocolServer};
 use jsonwebtoken::{DecodingKey, EncodingKey};
 use tokio::{
     net::TcpListener,
-    sync::{broadcast, mpsc},
+    sync::{broadcast, mpsc, watch, Notify},
+};
+use tokio_stream::Stream;
+use tonic::{service::interceptor::InterceptedService, transport::Server};
+use tracing::{error, info, info_span, warn};
+use utils::{
+    config::{
+        AdmissionConfig, BackendConfig, BackupConfig, BatchConfig, CompactConfig,
+        CompressionConfig, CorruptCheckConfig, CurpConfig, ExternalAuthConfig, GrpcConfig,
+        InitialClusterState, LeaseConfig, ProbeConfig, ProxyProtocolConfig, RequestLimitConfig,
+        ServerTimeout, SyncPolicy, TlsConfig, XlineServerConfig,
+    },
+    tracing::Extract,
 };
-use tokio_stream::wrappers::TcpListenerStream;
-use tonic::transport::Server;
-use tracing::info;
-use utils::config::{ClientTimeout, CurpConfig};
 
 use super::{
+    admin_server::{AdminServer, LogFilterHandle},
+    admission::{ConnectionLimiter, InFlightLimitService, RequestLanes},
+    audit::AuditLog,
+    auth_provider::ExternalAuth,
     auth_server::AuthServer,
+    backend_syncer::BackendSyncer,
+    backup::Backup,
+    cluster_server::ClusterServer,
     command::{Command, CommandExecutor},
+    compactor::Compactor,
+    corrupt_check::CorruptChecker,
+    health_server::HealthServer,
+    index_snapshotter::IndexSnapshotter,
     kv_server::KvServer,
+    lease_reconciler::LeaseReconciler,
     lease_server::LeaseServer,
+    listener::{self, ServerIo},
     lock_server::LockServer,
+    maintenance_server::MaintenanceServer,
+    message_size::MessageSizeService,
+    peer_check::StartingMember,
+    peer_tls::{PeerAcceptor, PeerTls},
+    probe_server::{ApplyProgress, ProbeServer},
+    proxy_protocol::ProxyProtocol,
+    rate_limiter::{RateLimitInterceptor, RateLimiter},
+    reload,
+    timeout_service::TimeoutService,
+    tls::CertReloader,
+    trash_purger::TrashPurger,
+    validation::RequestValidator,
     watch_server::WatchServer,
 };
 use crate::{
-    header_gen::HeaderGenerator,
+    header_gen::{gen_cluster_id, HeaderGenerator},
     id_gen::IdGenerator,
     rpc::{
-        AuthServer as RpcAuthServer, KvServer as RpcKvServer, LeaseServer as RpcLeaseServer,
-        LockServer as RpcLockServer, WatchServer as RpcWatchServer,
+        AdminServer as RpcAdminServer, AlarmType, AuthServer as RpcAuthServer,
+        ClusterServer as RpcClusterServer, HealthServer as RpcHealthServer,
+        KvServer as RpcKvServer, LeaseServer as RpcLeaseServer, LockServer as RpcLockServer,
+        MaintenanceServer as RpcMaintenanceServer, WatchServer as RpcWatchServer,
     },
     state::State,
-    storage::{index::Index, storage_api::StorageApi, AuthStore, KvStore, LeaseStore},
+    storage::{
+        alarm_store::AlarmStore,
+        durable_watch::DurableWatchStore,
+        index::Index,
+        integrity::{self, TableChecksums},
+        member_identity::{self, MemberIdentity},
+        member_store::{self, MemberStore},
+        migration,
+        namespace::NamespaceStore,
+        quota::QuotaStore,
+        revision_time::RevisionTimeIndex,
+        storage_api::StorageApi,
+        trash::TrashStore,
+        AuthStore, KvStore, LeaseStore,
+    },
 };
 
 /// Default channel size
@@ -37,6 +89,18 @@ const CHANNEL_SIZE: usize = 128;
 /// Rpc Server of curp protocol
 type CurpServer = Rpc<Command>;
 
+/// Enable the gzip compression of a generated grpc server if `$enable` is set
+macro_rules! with_compression {
+    ($server:expr, $enable:expr) => {{
+        let server = $server;
+        if $enable {
+            server.accept_gzip().send_gzip()
+        } else {
+            server
+        }
+    }};
+}
+
 /// Xline server
 #[derive(Debug)]
 pub struct XlineServer<S>
@@ -51,47 +115,142 @@ where
     auth_storage: Arc<AuthStore<S>>,
     /// Lease storage
     lease_storage: Arc<LeaseStore<S>>,
+    /// Members of the cluster
+    member_store: Arc<MemberStore>,
     /// persistent storage
     persistent: Arc<S>,
+    /// Index of the kv storage
+    index: Arc<Index>,
+    /// Header generator
+    header_gen: Arc<HeaderGenerator>,
+    /// Whether the member bootstraps a new cluster or joins an existing one
+    initial_cluster_state: InitialClusterState,
     /// Consensus client
     client: Arc<Client<Command>>,
     /// Curp server timeout
     curp_cfg: Arc<CurpConfig>,
     /// Id generator
     id_gen: Arc<IdGenerator>,
+    /// Grpc server config
+    grpc_cfg: GrpcConfig,
+    /// Per-client rate limiter
+    rate_limiter: Arc<RateLimiter>,
+    /// Server side timeouts of rpcs
+    server_timeout: ServerTimeout,
+    /// Audit log
+    audit_log: Arc<AuditLog>,
+    /// External authentication config
+    external_auth_cfg: Option<ExternalAuthConfig>,
+    /// Handle to change the log filter at runtime
+    log_filter: Option<LogFilterHandle>,
+    /// Backup config
+    backup_cfg: BackupConfig,
+    /// Proposal batching config
+    batch_cfg: BatchConfig,
+    /// Readiness and liveness probe config
+    probe_cfg: ProbeConfig,
+    /// Request limit config
+    request_limit_cfg: RequestLimitConfig,
+    /// Progress of the apply loop
+    apply_progress: Arc<ApplyProgress>,
+    /// Write quotas of the users
+    quota_store: Arc<QuotaStore>,
+    /// Namespaces of the keys
+    namespace_store: Arc<NamespaceStore>,
+    /// Trash of the deleted keys
+    trash_store: Arc<TrashStore>,
+    /// Index from the wall-clock time to the revisions
+    revision_times: Arc<RevisionTimeIndex>,
+    /// Backend config
+    backend_cfg: BackendConfig,
+    /// Grpc response compression config
+    compression_cfg: CompressionConfig,
+    /// Corruption check config
+    corrupt_check_cfg: CorruptCheckConfig,
+    /// Compaction config, the compactor follows it when the config is reloaded
+    compact_cfg_tx: watch::Sender<CompactConfig>,
+    /// Lease config
+    lease_cfg: LeaseConfig,
+    /// Client TLS config
+    tls_cfg: TlsConfig,
+    /// Acceptor of the peer connections on the member listener, `None` if the peers
+    /// connect in plaintext
+    peer_acceptor: Option<PeerAcceptor>,
+    /// Notified to reload the TLS certificates at once, eg. on SIGHUP
+    cert_reload: Arc<Notify>,
+    /// PROXY protocol config
+    proxy_protocol_cfg: ProxyProtocolConfig,
+    /// Admission limits config
+    admission_cfg: AdmissionConfig,
+    /// Lanes of the rpcs in flight
+    request_lanes: RequestLanes,
+    /// Alarms of current node
+    alarm_store: Arc<AlarmStore>,
+    /// Shutdown signal sender, watch and lease keep alive streams are canceled when it is triggered
+    shutdown_tx: watch::Sender<bool>,
 }
 
 impl<S> XlineServer<S>
 where
     S: StorageApi,
 {
-    /// New `XlineServer`
+    /// New `XlineServer` of `config`, the members and the curp config are given apart from
+    /// it since they're resolved at the start, eg. the members are discovered by the SRV
+    /// records of `discovery_srv`, and the standbys are set in the curp config
+    ///
+    /// The addresses of the members updated by `MemberUpdate` are recorded in the storage,
+    /// they win over the addresses of the other members in `all_members`, and the members
+    /// keep their ids after the updates.
     ///
     /// # Errors
     ///
-    /// Return `ExecuteError::DbError` if the server cannot initialize the database
+    /// Return `ExecuteError::DbError` if the server cannot read the recorded members, or
+    /// error if the peer TLS certificates can't be loaded
     ///
     /// # Panics
     ///
     /// panic when peers do not contain leader address
     #[inline]
     pub async fn new(
-        name: String,
+        config: &XlineServerConfig,
         all_members: HashMap<String, String>,
-        is_leader: bool,
-        key_pair: Option<(EncodingKey, DecodingKey)>,
         curp_config: CurpConfig,
-        client_timeout: ClientTimeout,
+        key_pair: Option<(EncodingKey, DecodingKey)>,
+        log_filter: Option<LogFilterHandle>,
         persistent: Arc<S>,
-    ) -> Self {
-        // TODO: temporary solution, need real cluster id and member id
-        let header_gen = Arc::new(HeaderGenerator::new(0, 0));
-        let id_gen = Arc::new(IdGenerator::new(0));
-        let leader_id = is_leader.then(|| name.clone());
-        let state = Arc::new(State::new(name, leader_id, all_members.clone()));
+    ) -> Result<Self> {
+        let cluster_config = config.cluster();
+        let name = cluster_config.name().clone();
+        let recorded = member_store::load(persistent.as_ref())?;
+        let (all_members, member_ids) = member_store::resolve(&name, all_members, recorded);
+        let member_id = member_ids.get(&name).copied().unwrap_or(0);
+        let header_gen = Arc::new(HeaderGenerator::new(
+            gen_cluster_id(member_ids.values().copied()),
+            member_id,
+        ));
+        let id_gen = Arc::new(IdGenerator::new(member_id));
+        let audit_log = Arc::new(AuditLog::new(config.audit(), &name));
+        let leader_id = cluster_config.is_leader().then(|| name.clone());
+        let peer_tls = PeerTls::new(config.peer_tls()).await?;
+        let peer_tls_config = peer_tls
+            .as_ref()
+            .map(|&(ref peer_tls, _)| peer_tls.client_config());
+        let state = Arc::new(State::with_member_ids(
+            name,
+            leader_id,
+            all_members.clone(),
+            member_ids,
+            peer_tls_config.clone(),
+        ));
+        let member_store = Arc::new(MemberStore::new(
+            Arc::clone(&state),
+            Arc::clone(&header_gen),
+            curp_config.standbys.clone(),
+        ));
         let curp_config = Arc::new(curp_config);
         let (lease_cmd_tx, lease_cmd_rx) = mpsc::channel(CHANNEL_SIZE);
         let index = Arc::new(Index::new());
+        let namespace_store = Arc::new(NamespaceStore::new(config.namespaces().clone()));
 
         let kv_storage = Arc::new(KvStore::new(
             lease_cmd_tx.clone(),
@@ -101,29 +260,86 @@ where
         ));
         let lease_storage = Arc::new(LeaseStore::new(
             lease_cmd_rx,
+            *config.lease(),
             Arc::clone(&state),
             Arc::clone(&header_gen),
             Arc::clone(&persistent),
-            index,
-            kv_storage.kv_update_tx(),
+            Arc::clone(&index),
         ));
         let auth_storage = Arc::new(AuthStore::new(
             lease_cmd_tx,
             key_pair,
-            header_gen,
+            Arc::clone(&header_gen),
             Arc::clone(&persistent),
+            Arc::clone(&namespace_store),
         ));
-        let client = Arc::new(Client::<Command>::new(all_members.clone(), client_timeout).await);
-        Self {
+        // the standbys don't count toward the quorum, so the proposals are only sent to the
+        // members which do
+        let voters = all_members
+            .iter()
+            .filter(|&(name, _)| !curp_config.standbys.contains(name))
+            .map(|(name, addr)| (name.clone(), addr.clone()))
+            .collect();
+        let client = Arc::new(
+            Client::<Command>::new_with_tls(
+                voters,
+                *cluster_config.client_timeout(),
+                peer_tls_config,
+            )
+            .await,
+        );
+        let (shutdown_tx, _shutdown_rx) = watch::channel(false);
+        let cert_reload = Arc::new(Notify::new());
+        let peer_acceptor = match peer_tls {
+            Some((peer_tls, reloader)) => {
+                let _handle =
+                    tokio::spawn(reloader.run(Arc::clone(&cert_reload), shutdown_tx.subscribe()));
+                Some(peer_tls.acceptor(Arc::clone(&state))?)
+            }
+            None => None,
+        };
+        Ok(Self {
             state,
             kv_storage,
             auth_storage,
             lease_storage,
+            member_store,
             persistent,
+            index,
+            header_gen,
+            initial_cluster_state: *cluster_config.initial_cluster_state(),
             client,
             curp_cfg: curp_config,
             id_gen,
-        }
+            grpc_cfg: *config.grpc(),
+            rate_limiter: Arc::new(RateLimiter::new(config.rate_limit())),
+            server_timeout: *config.server_timeout(),
+            audit_log,
+            external_auth_cfg: config.auth().external().clone(),
+            log_filter,
+            backup_cfg: config.backup().clone(),
+            batch_cfg: *config.batch(),
+            probe_cfg: config.probe().clone(),
+            request_limit_cfg: *config.request_limit(),
+            apply_progress: Arc::new(ApplyProgress::new()),
+            quota_store: Arc::new(QuotaStore::new(*config.quota())),
+            namespace_store,
+            trash_store: Arc::new(TrashStore::new(*config.trash())),
+            revision_times: Arc::new(RevisionTimeIndex::new()),
+            backend_cfg: *config.backend(),
+            compression_cfg: *config.compression(),
+            corrupt_check_cfg: *config.corrupt_check(),
+            compact_cfg_tx: watch::channel(config.compact().clone()).0,
+            lease_cfg: *config.lease(),
+            tls_cfg: config.tls().clone(),
+            peer_acceptor,
+            cert_reload,
+            proxy_protocol_cfg: config.proxy_protocol().clone(),
+            admission_cfg: *config.admission(),
+            request_lanes: RequestLanes::new(config.admission()),
+            alarm_store: Arc::new(AlarmStore::new(member_id)),
+            shutdown_tx,
+        })
     }
 
     /// Server id
@@ -136,28 +352,313 @@ where
         self.state.is_leader()
     }
 
-    /// Start `XlineServer`
+    /// Create a server builder with the grpc config applied
+    fn server_builder(&self) -> Server {
+        Server::builder()
+            .http2_keepalive_interval(Some(*self.grpc_cfg.keepalive_interval()))
+            .http2_keepalive_timeout(Some(*self.grpc_cfg.keepalive_timeout()))
+            .max_concurrent_streams(*self.grpc_cfg.max_concurrent_streams())
+            .trace_fn(|req| {
+                let span = info_span!("grpc_request", path = %req.uri().path());
+                req.headers().extract_span_into(&span);
+                span
+            })
+    }
+
+    /// Wrap a client service with the limits of the message sizes, the lanes of the rpcs in
+    /// flight and the server side timeouts, the time a maintenance rpc waits in its lane
+    /// counts toward its timeout
+    fn client_service<T>(
+        &self,
+        inner: T,
+    ) -> TimeoutService<InFlightLimitService<MessageSizeService<T>>> {
+        TimeoutService::new(
+            InFlightLimitService::new(
+                MessageSizeService::new(inner, &self.grpc_cfg),
+                self.request_lanes.clone(),
+            ),
+            self.server_timeout,
+        )
+    }
+
+    /// Create an interceptor that limits the request rate of clients
+    fn rate_limit_interceptor(&self) -> RateLimitInterceptor<S> {
+        RateLimitInterceptor::new(
+            Arc::clone(&self.rate_limiter),
+            Arc::clone(&self.auth_storage),
+        )
+    }
+
+    /// Wait for the shutdown signal, then cancel all watch and lease keep alive streams and
+    /// hand off the leadership, so that the grpc server can stop without waiting for them
+    async fn shutdown_signal<F>(&self, signal: F, curp_server: CurpServer)
+    where
+        F: Future<Output = ()>,
+    {
+        signal.await;
+        info!("{} starts to shut down", self.id());
+        let _ignore = self.shutdown_tx.send(true);
+        if self.is_leader() {
+            if let Some(leader_id) = curp_server.transfer_leadership().await {
+                info!("leadership is handed off to {leader_id}");
+            } else {
+                warn!("failed to hand off leadership before shutdown");
+            }
+        }
+    }
+
+    /// Start the periodic backup if it is enabled, it stops when the server shuts down
+    fn start_backup(&self) -> Result<()> {
+        if let Some(backup) = Backup::new(
+            &self.backup_cfg,
+            Arc::clone(&self.persistent),
+            Arc::clone(&self.state),
+        )? {
+            let _handle = tokio::spawn(backup.run(self.shutdown_tx.subscribe()));
+        }
+        Ok(())
+    }
+
+    /// Start the periodic corruption check if it is enabled, it stops when the server shuts
+    /// down
+    fn start_corrupt_check(&self) {
+        if let Some(checker) = CorruptChecker::new(
+            &self.corrupt_check_cfg,
+            Arc::clone(&self.persistent),
+            Arc::clone(&self.state),
+            Arc::clone(&self.header_gen),
+            Arc::clone(&self.alarm_store),
+        ) {
+            let _handle = tokio::spawn(checker.run(self.shutdown_tx.subscribe()));
+        }
+    }
+
+    /// Start the automatic compaction, it's paused while it's disabled, and stops when the
+    /// server shuts down
+    fn start_compactor(&self) {
+        let compactor = Compactor::new(
+            self.compact_cfg_tx.subscribe(),
+            Arc::clone(&self.kv_storage),
+            Arc::clone(&self.auth_storage),
+            Arc::clone(&self.client),
+            Arc::clone(&self.state),
+        );
+        let _handle = tokio::spawn(compactor.run(self.shutdown_tx.subscribe()));
+    }
+
+    /// Start the reconciliation of the keys and the leases if it is enabled, it stops when
+    /// the server shuts down
+    fn start_lease_reconciler(&self) {
+        if let Some(reconciler) = LeaseReconciler::new(
+            &self.lease_cfg,
+            Arc::clone(&self.kv_storage),
+            Arc::clone(&self.lease_storage),
+            Arc::clone(&self.auth_storage),
+            Arc::clone(&self.client),
+            Arc::clone(&self.state),
+        ) {
+            let _handle = tokio::spawn(reconciler.run(self.shutdown_tx.subscribe()));
+        }
+    }
+
+    /// Start the periodic index snapshot, it stops when the server shuts down
+    fn start_index_snapshotter(&self) {
+        let snapshotter = IndexSnapshotter::new(Arc::clone(&self.kv_storage));
+        let _handle = tokio::spawn(snapshotter.run(self.shutdown_tx.subscribe()));
+    }
+
+    /// Start the periodic purge of the trash if it's enabled, it stops when the server shuts
+    /// down
+    fn start_trash_purger(&self) {
+        if !self.trash_store.is_enabled() {
+            return;
+        }
+        let purger = TrashPurger::new(Arc::clone(&self.trash_store), Arc::clone(&self.persistent));
+        let _handle = tokio::spawn(purger.run(self.shutdown_tx.subscribe()));
+    }
+
+    /// Start the periodic sync of the backend if its sync policy is `interval`, it stops
+    /// when the server shuts down
+    fn start_backend_syncer(&self) {
+        if *self.backend_cfg.sync() != SyncPolicy::Interval {
+            return;
+        }
+        let syncer = BackendSyncer::new(
+            Arc::clone(&self.persistent),
+            *self.backend_cfg.sync_interval(),
+        );
+        let _handle = tokio::spawn(syncer.run(self.shutdown_tx.subscribe()));
+    }
+
+    /// Serve the readiness and liveness probes if they are enabled, they stop when the
+    /// server shuts down
+    fn start_probe(&self) -> Result<Option<Arc<ProbeServer<S>>>> {
+        if !*self.probe_cfg.enable() {
+            return Ok(None);
+        }
+        let addr: SocketAddr = self.probe_cfg.addr().parse()?;
+        let probe = Arc::new(ProbeServer::new(
+            Arc::clone(&self.persistent),
+            Arc::clone(&self.state),
+            Arc::clone(&self.apply_progress),
+            *self.probe_cfg.apply_stall_timeout(),
+            self.shutdown_tx.subscribe(),
+        ));
+        Arc::clone(&probe).serve(addr)?;
+        Ok(Some(probe))
+    }
+
+    /// Connections accepted on the member listener, along with the client connections on
+    /// the TLS listener if TLS is enabled. The certificate is reloaded when its files
+    /// change until the server shuts down. The member listener only accepts the peers
+    /// over mutual TLS if peer TLS is enabled.
+    async fn incoming(
+        &self,
+        listener: TcpListener,
+    ) -> Result<impl Stream<Item = io::Result<ServerIo>>> {
+        let proxy = ProxyProtocol::new(&self.proxy_protocol_cfg)?;
+        let limiter = ConnectionLimiter::new(
+            &self.admission_cfg,
+            self.state.members().values().map(String::as_str),
+        );
+        let peer_acceptor = self.peer_acceptor.clone();
+        let Some(reloader) = CertReloader::new(&self.tls_cfg).await? else {
+            return Ok(listener::incoming(
+                listener,
+                peer_acceptor,
+                None,
+                proxy,
+                limiter,
+            ));
+        };
+        let acceptor = reloader.acceptor();
+        let tls_listener = TcpListener::bind(self.tls_cfg.addr()).await?;
+        info!("serving clients over TLS on {}", self.tls_cfg.addr());
+        let _handle =
+            tokio::spawn(reloader.run(Arc::clone(&self.cert_reload), self.shutdown_tx.subscribe()));
+        Ok(listener::incoming(
+            listener,
+            peer_acceptor,
+            Some((tls_listener, acceptor)),
+            proxy,
+            limiter,
+        ))
+    }
+
+    /// Check that the storage belongs to this member before it's recovered, the identity of
+    /// the member is recorded in a storage without one
+    fn check_member_identity(&self) -> Result<()> {
+        let identity = MemberIdentity {
+            member_id: self.header_gen.member_id(),
+            cluster_id: self.header_gen.cluster_id(),
+        };
+        member_identity::check(self.persistent.as_ref(), identity)
+            .map_err(|e| anyhow!("{} refuses to start, {e}", self.id()))
+    }
+
+    /// Check the member against its peers before it recovers, see `StartingMember`
+    async fn check_peers(&self, empty_storage: bool) -> Result<()> {
+        let member = StartingMember {
+            name: self.state.id(),
+            member_id: self.header_gen.member_id(),
+            cluster_id: self.header_gen.cluster_id(),
+            empty_storage,
+            initial_cluster_state: self.initial_cluster_state,
+            // the curp server isn't started yet, so it's the initial leader given by the config
+            is_leader: self.is_leader(),
+        };
+        member.check_peers(&self.state).await
+    }
+
+    /// Check the storage for corruption before it recovers if the initial check is
+    /// enabled: verify the table checksums recorded at the last shutdown, and check the
+    /// kv table which the index is recovered from
+    fn check_storage(&self) -> Result<()> {
+        if !*self.corrupt_check_cfg.initial_check() {
+            return Ok(());
+        }
+        let mut problems = match TableChecksums::take(self.persistent.as_ref())? {
+            Some(checksums) => checksums.verify(self.persistent.as_ref())?,
+            None => {
+                info!("no table checksums are recorded, the last shutdown was not graceful");
+                vec![]
+            }
+        };
+        problems.extend(integrity::check_kv_table(self.persistent.as_ref())?);
+        self.handle_corruption(problems)
+    }
+
+    /// Cross check the recovered index against the kv table if the initial check is enabled
+    fn check_recovered_index(&self) -> Result<()> {
+        if !*self.corrupt_check_cfg.initial_check() {
+            return Ok(());
+        }
+        let problems = integrity::check_index(self.persistent.as_ref(), &self.index)?;
+        self.handle_corruption(problems)
+    }
+
+    /// Refuse to start if corruption is found, or raise the CORRUPT alarm and serve the
+    /// reads only if `serve_on_corrupt` is set
+    fn handle_corruption(&self, problems: Vec<String>) -> Result<()> {
+        if problems.is_empty() {
+            return Ok(());
+        }
+        for problem in &problems {
+            error!("storage corruption detected: {problem}");
+        }
+        if !*self.corrupt_check_cfg.serve_on_corrupt() {
+            bail!(
+                "storage of {} is corrupted, {} problems found",
+                self.id(),
+                problems.len()
+            );
+        }
+        let _activated = self.alarm_store.activate(AlarmType::Corrupt);
+        Ok(())
+    }
+
+    /// Persist the storage after the grpc server stops along with a snapshot of the index,
+    /// and record the table checksums to be verified at the next start. They are not
+    /// recorded if the storage is corrupted, so that the corruption is detected again.
+    fn finish_shutdown(&self) -> Result<()> {
+        if let Err(e) = self.kv_storage.save_index_snapshot() {
+            warn!("failed to save the index snapshot, {e}");
+        }
+        self.persistent.sync()?;
+        if *self.corrupt_check_cfg.initial_check()
+            && !self.alarm_store.is_self_active(AlarmType::Corrupt)
+        {
+            match TableChecksums::compute(self.persistent.as_ref())? {
+                Some(checksums) => checksums.record(self.persistent.as_ref())?,
+                None => warn!("log entries are applied while the table checksums are computed"),
+            }
+        }
+        info!("{} shuts down", self.id());
+        Ok(())
+    }
+
+    /// Start `XlineServer`, the server shuts down gracefully on SIGTERM or SIGINT
     ///
     /// # Errors
     ///
-    /// Will return `Err` when `tonic::Server` serve return an error
+    /// Will return `Err` when the listeners can't be bound, the TLS certificate can't be
+    /// loaded, or `tonic::Server` serve return an error
     #[inline]
     pub async fn start(&self, addr: SocketAddr) -> Result<()> {
+        let probe = self.start_probe()?;
+        let empty_storage = migration::is_empty(self.persistent.as_ref())?;
+        migration::migrate(self.persistent.as_ref())?;
+        self.check_member_identity()?;
+        self.check_peers(empty_storage).await?;
+        self.check_storage()?;
         // lease storage must recover before kv storage
         self.lease_storage.recover()?;
         self.kv_storage.recover().await?;
+        self.check_recovered_index()?;
+        self.namespace_store.recover(&self.kv_storage)?;
         self.auth_storage.recover()?;
-        let (kv_server, lock_server, lease_server, auth_server, watch_server, curp_server) =
-            self.init_servers().await;
-        Ok(Server::builder()
-            .add_service(RpcLockServer::new(lock_server))
-            .add_service(RpcKvServer::new(kv_server))
-            .add_service(RpcLeaseServer::from_arc(lease_server))
-            .add_service(RpcAuthServer::new(auth_server))
-            .add_service(RpcWatchServer::new(watch_server))
-            .add_service(ProtocolServer::new(curp_server))
-            .serve(addr)
-            .await?)
+        self.serve(TcpListener::bind(addr).await?, terminate_signal(), probe)
+            .await
     }
 
     /// Start `XlineServer` from listeners
@@ -174,17 +675,95 @@ where
     where
         F: Future<Output = ()>,
     {
-        let (kv_server, lock_server, lease_server, auth_server, watch_server, curp_server) =
-            self.init_servers().await;
-        Ok(Server::builder()
-            .add_service(RpcLockServer::new(lock_server))
-            .add_service(RpcKvServer::new(kv_server))
-            .add_service(RpcLeaseServer::from_arc(lease_server))
-            .add_service(RpcAuthServer::new(auth_server))
-            .add_service(RpcWatchServer::new(watch_server))
+        let probe = self.start_probe()?;
+        migration::migrate(self.persistent.as_ref())?;
+        // the listeners of the peers may be bound but not served yet, so they are not asked
+        self.check_member_identity()?;
+        self.serve(xline_listener, signal, probe).await
+    }
+
+    /// Start the background tasks and the services of a recovered server, then serve the
+    /// connections accepted on `listener` until `signal` resolves. Both start paths serve
+    /// through it, so they have the same services, limits and connection handling.
+    async fn serve<F>(
+        &self,
+        listener: TcpListener,
+        signal: F,
+        probe: Option<Arc<ProbeServer<S>>>,
+    ) -> Result<()>
+    where
+        F: Future<Output = ()>,
+    {
+        self.start_backup()?;
+        self.start_corrupt_check();
+        self.start_compactor();
+        self.start_index_snapshotter();
+        self.start_trash_purger();
+        self.start_backend_syncer();
+        self.start_lease_reconciler();
+        let (
+            kv_server,
+            lock_server,
+            lease_server,
+            auth_server,
+            watch_server,
+            health_server,
+            admin_server,
+            maintenance_server,
+            cluster_server,
+            curp_server,
+        ) = self.init_servers().await?;
+        if let Some(probe) = probe {
+            probe.recovered(curp_server.clone());
+        }
+        let signal = self.shutdown_signal(signal, curp_server.clone());
+        self.server_builder()
+            .add_service(self.client_service(InterceptedService::new(
+                RpcLockServer::new(lock_server),
+                self.rate_limit_interceptor(),
+            )))
+            .add_service(self.client_service(InterceptedService::new(
+                with_compression!(RpcKvServer::new(kv_server), *self.compression_cfg.kv()),
+                self.rate_limit_interceptor(),
+            )))
+            .add_service(self.client_service(InterceptedService::new(
+                with_compression!(
+                    RpcLeaseServer::from_arc(lease_server),
+                    *self.compression_cfg.lease()
+                ),
+                self.rate_limit_interceptor(),
+            )))
+            .add_service(self.client_service(InterceptedService::new(
+                RpcAuthServer::new(auth_server),
+                self.rate_limit_interceptor(),
+            )))
+            .add_service(self.client_service(InterceptedService::new(
+                with_compression!(
+                    RpcWatchServer::new(watch_server),
+                    *self.compression_cfg.watch()
+                ),
+                self.rate_limit_interceptor(),
+            )))
+            .add_service(RpcHealthServer::new(health_server))
+            .add_service(self.client_service(InterceptedService::new(
+                RpcAdminServer::new(admin_server),
+                self.rate_limit_interceptor(),
+            )))
+            .add_service(self.client_service(InterceptedService::new(
+                with_compression!(
+                    RpcMaintenanceServer::new(maintenance_server),
+                    *self.compression_cfg.maintenance()
+                ),
+                self.rate_limit_interceptor(),
+            )))
+            .add_service(self.client_service(InterceptedService::new(
+                RpcClusterServer::new(cluster_server),
+                self.rate_limit_interceptor(),
+            )))
             .add_service(ProtocolServer::new(curp_server))
-            .serve_with_incoming_shutdown(TcpListenerStream::new(xline_listener), signal)
-            .await?)
+            .serve_with_incoming_shutdown(self.incoming(listener).await?, signal)
+            .await?;
+        self.finish_shutdown()
     }
 
     /// Leader change task
@@ -192,9 +771,13 @@ where
         mut rx: broadcast::Receiver<Option<String>>,
         state: Arc<State>,
         lease_storage: Arc<LeaseStore<S>>,
+        header_gen: Arc<HeaderGenerator>,
+        curp_server: CurpServer,
     ) {
         while let Ok(leader_id) = rx.recv().await {
             info!("receive new leader_id: {leader_id:?}");
+            // the term changes only along with the leader
+            header_gen.set_term(curp_server.term());
             let leader_state_changed = state.set_leader_id(leader_id);
             let is_leader = state.is_leader();
             if leader_state_changed {
@@ -207,52 +790,119 @@ where
         }
     }
 
-    /// Init `KvServer`, `LockServer`, `LeaseServer`, `WatchServer` and `CurpServer`
-    /// for the Xline Server.
+    /// Member update task, the addresses of the other members updated by `MemberUpdate` are
+    /// applied to the connections of the curp server and the consensus client until the
+    /// server shuts down
+    #[allow(clippy::integer_arithmetic)] // Introduced by tokio::select!
+    async fn member_update_task(
+        state: Arc<State>,
+        curp_server: CurpServer,
+        client: Arc<Client<Command>>,
+        mut shutdown_rx: watch::Receiver<bool>,
+    ) {
+        let mut applied = state.others();
+        loop {
+            // listen before the members are read, so that no update is missed
+            let listener = state.member_listener();
+            for (name, addr) in state.others() {
+                if applied.get(&name) == Some(&addr) {
+                    continue;
+                }
+                curp_server.update_addr(name.clone(), addr.clone());
+                client.update_addr(&name, addr.clone()).await;
+                let _prev = applied.insert(name, addr);
+            }
+            tokio::select! {
+                () = listener => {}
+                _ = shutdown_rx.changed() => return,
+            }
+        }
+    }
+
+    /// Init `KvServer`, `LockServer`, `LeaseServer`, `WatchServer`, `HealthServer`,
+    /// `AdminServer`, `MaintenanceServer`, `ClusterServer` and `CurpServer` for the Xline
+    /// Server.
     #[allow(clippy::type_complexity)] // it is easy to read
     async fn init_servers(
         &self,
-    ) -> (
+    ) -> Result<(
         KvServer<S>,
         LockServer<S>,
         Arc<LeaseServer<S>>,
         AuthServer<S>,
         WatchServer<S>,
+        HealthServer<S>,
+        AdminServer<S>,
+        MaintenanceServer<S>,
+        ClusterServer,
         CurpServer,
-    ) {
+    )> {
+        let cmd_executor = CommandExecutor::new(
+            Arc::clone(&self.kv_storage),
+            Arc::clone(&self.auth_storage),
+            Arc::clone(&self.lease_storage),
+            Arc::clone(&self.member_store),
+            Arc::clone(&self.persistent),
+            Arc::clone(&self.apply_progress),
+            Arc::clone(&self.quota_store),
+            Arc::clone(&self.namespace_store),
+            Arc::clone(&self.trash_store),
+            Arc::clone(&self.revision_times),
+            Arc::clone(&self.alarm_store),
+        );
+        cmd_executor.recover()?;
+        let validator = RequestValidator::new(&self.request_limit_cfg);
+        let external_auth = self
+            .external_auth_cfg
+            .as_ref()
+            .map(ExternalAuth::new)
+            .transpose()?;
         let curp_server = CurpServer::new(
             self.id(),
             self.is_leader(),
             self.state.others(),
-            CommandExecutor::new(
-                Arc::clone(&self.kv_storage),
-                Arc::clone(&self.auth_storage),
-                Arc::clone(&self.lease_storage),
-                Arc::clone(&self.persistent),
-            ),
+            cmd_executor,
             Arc::clone(&self.curp_cfg),
             None,
+            self.state.peer_tls(),
         )
         .await;
         let _handle = tokio::spawn({
             let state = Arc::clone(&self.state);
             let lease_storage = Arc::clone(&self.lease_storage);
+            let header_gen = Arc::clone(&self.header_gen);
             let rx = curp_server.leader_rx();
-            Self::leader_change_task(rx, state, lease_storage)
+            Self::leader_change_task(rx, state, lease_storage, header_gen, curp_server.clone())
         });
-        (
+        let _handle = tokio::spawn(Self::member_update_task(
+            Arc::clone(&self.state),
+            curp_server.clone(),
+            Arc::clone(&self.client),
+            self.shutdown_tx.subscribe(),
+        ));
+        Ok((
             KvServer::new(
                 Arc::clone(&self.kv_storage),
                 Arc::clone(&self.auth_storage),
                 Arc::clone(&self.state),
                 Arc::clone(&self.client),
                 self.id(),
+                *self.server_timeout.slow_request_threshold(),
+                Arc::clone(&self.audit_log),
+                self.batch_cfg,
+                self.request_limit_cfg,
+                validator,
+                Arc::clone(&self.alarm_store),
+                Arc::clone(&self.trash_store),
+                Arc::clone(&self.persistent),
             ),
             LockServer::new(
                 Arc::clone(&self.kv_storage),
                 Arc::clone(&self.client),
                 Arc::clone(&self.state),
                 self.id(),
+                Arc::clone(&self.alarm_store),
+                validator,
             ),
             LeaseServer::new(
                 Arc::clone(&self.lease_storage),
@@ -261,14 +911,149 @@ where
                 self.id(),
                 Arc::clone(&self.state),
                 Arc::clone(&self.id_gen),
+                self.shutdown_tx.subscribe(),
+                Arc::clone(&self.alarm_store),
+                validator,
             ),
             AuthServer::new(
                 Arc::clone(&self.auth_storage),
                 Arc::clone(&self.client),
                 self.id(),
+                Arc::clone(&self.audit_log),
+                Arc::clone(&self.alarm_store),
+                validator,
+                external_auth,
+            ),
+            WatchServer::new(
+                self.kv_storage.kv_watcher(),
+                Arc::new(DurableWatchStore::new(Arc::clone(&self.persistent))),
+                self.shutdown_tx.subscribe(),
+            ),
+            HealthServer::new(
+                Arc::clone(&self.persistent),
+                Arc::clone(&self.state),
+                Arc::clone(&self.alarm_store),
+                self.shutdown_tx.subscribe(),
+            ),
+            AdminServer::new(
+                Arc::clone(&self.auth_storage),
+                Arc::clone(&self.persistent),
+                self.log_filter.clone(),
+                self.shutdown_tx.subscribe(),
+            ),
+            MaintenanceServer::new(
+                Arc::clone(&self.auth_storage),
+                Arc::clone(&self.persistent),
+                Arc::clone(&self.header_gen),
+                Arc::clone(&self.alarm_store),
+                Arc::clone(&self.state),
+                Arc::clone(&self.apply_progress),
+                curp_server.clone(),
+            ),
+            ClusterServer::new(
+                Arc::clone(&self.member_store),
+                Arc::clone(&self.client),
+                self.id(),
+                Arc::clone(&self.alarm_store),
+                validator,
             ),
-            WatchServer::new(self.kv_storage.kv_watcher()),
             curp_server,
-        )
+        ))
+    }
+
+    /// Reload the config file at `path` whenever the process receives SIGHUP. The log level
+    /// and the rate limits are applied at runtime, changes of the other settings are ignored
+    /// with a warning because they take effect only after a restart. `config` is the config
+    /// the server is started with. Return only if SIGHUP can't be listened for.
+    #[inline]
+    pub async fn reload_config_on_hangup(&self, path: PathBuf, config: XlineServerConfig) {
+        #[cfg(unix)]
+        {
+            let mut sighup =
+                match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()) {
+                    Ok(sighup) => sighup,
+                    Err(e) => {
+                        warn!("failed to listen for SIGHUP, config reload is disabled, {e}");
+                        return;
+                    }
+                };
+            let mut applied = config.clone();
+            while sighup.recv().await.is_some() {
+                match reload::read_config(&path).await {
+                    Ok(new_config) => {
+                        self.apply_config(&config, &applied, &new_config);
+                        applied = new_config;
+                    }
+                    Err(e) => warn!("failed to reload config from {path:?}, {e}"),
+                }
+            }
+        }
+        #[cfg(not(unix))]
+        {
+            let _ignore = (path, config);
+            warn!("config reload on SIGHUP is not supported on this platform");
+        }
+    }
+
+    /// Apply the reloadable settings of `new` which differ from `applied`, `running` is the
+    /// config the server is started with
+    fn apply_config(
+        &self,
+        running: &XlineServerConfig,
+        applied: &XlineServerConfig,
+        new: &XlineServerConfig,
+    ) {
+        let level = new.log().level();
+        if level != applied.log().level() {
+            if let Some(ref log_filter) = self.log_filter {
+                match log_filter.set(&level.to_string()) {
+                    Ok(prev) => info!("log filter is reloaded from {prev:?} to {level}"),
+                    Err(e) => warn!("failed to reload log level, {e}"),
+                }
+            }
+        }
+        if new.rate_limit() != applied.rate_limit() {
+            self.rate_limiter.update(new.rate_limit());
+            info!("rate limit is reloaded to {:?}", new.rate_limit());
+        }
+        if new.quota() != applied.quota() {
+            self.quota_store.update(*new.quota());
+            info!("quota is reloaded to {:?}", new.quota());
+        }
+        if new.compact() != applied.compact() {
+            let _ignore = self.compact_cfg_tx.send(new.compact().clone());
+            info!("compaction is reloaded to {:?}", new.compact());
+        }
+        // the certificate files may be rotated without a change of the config
+        self.cert_reload.notify_waiters();
+        let sections = reload::restart_required(running, new);
+        if !sections.is_empty() {
+            warn!("changes of {sections:?} in the config take effect only after a restart");
+        }
+    }
+}
+
+/// Resolve when the process receives SIGTERM or SIGINT
+async fn terminate_signal() {
+    #[allow(clippy::integer_arithmetic)] // Introduced by tokio::select!
+    #[cfg(unix)]
+    {
+        let mut sigterm =
+            match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+                Ok(sigterm) => sigterm,
+                Err(e) => {
+                    warn!("failed to listen for SIGTERM, {e}");
+                    let _ignore = tokio::signal::ctrl_c().await;
+                    return;
+                }
+            };
+        tokio::select! {
+            _ = sigterm.recv() => {}
+            _ = tokio::signal::ctrl_c() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ignore = tokio::signal::ctrl_c().await;
     }
 }
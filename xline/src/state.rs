@@ -2,6 +2,10 @@ use std::collections::HashMap;
 
 use event_listener::{Event, EventListener};
 use parking_lot::RwLock;
+use tonic::transport::{Channel, ClientTlsConfig, Endpoint};
+
+#[cfg(test)]
+use crate::header_gen::gen_member_id;
 
 /// State of current node
 #[derive(Debug, Default)]
@@ -10,24 +14,50 @@ pub(crate) struct State {
     id: String,
     /// Leader id
     leader_id: RwLock<Option<String>>,
-    /// Address of all members
-    members: HashMap<String, String>,
+    /// Address of all members, the address of a member changes by `MemberUpdate`
+    members: RwLock<HashMap<String, String>>,
+    /// Id of all members, it's kept when the address of a member changes
+    member_ids: HashMap<String, u64>,
     /// leader change event, notify when get new leader_id
     event: Event,
+    /// member change event, notify when the address of a member changes
+    member_event: Event,
+    /// TLS config of the connections to the members, `None` if they are in plaintext
+    peer_tls: Option<ClientTlsConfig>,
 }
 
 impl State {
-    /// New `State`
+    /// New `State`, the ids of the members are derived from their names and addresses
+    #[cfg(test)]
     pub(crate) fn new(
         id: String,
         leader_id: Option<String>,
         members: HashMap<String, String>,
+    ) -> Self {
+        let member_ids = members
+            .iter()
+            .map(|(name, addr)| (name.clone(), gen_member_id(name, addr)))
+            .collect();
+        Self::with_member_ids(id, leader_id, members, member_ids, None)
+    }
+
+    /// New `State` with the ids of the members, eg. the ids recorded before their addresses
+    /// changed, the members are connected over TLS if `peer_tls` is given
+    pub(crate) fn with_member_ids(
+        id: String,
+        leader_id: Option<String>,
+        members: HashMap<String, String>,
+        member_ids: HashMap<String, u64>,
+        peer_tls: Option<ClientTlsConfig>,
     ) -> Self {
         Self {
             id,
             leader_id: RwLock::new(leader_id),
-            members,
+            members: RwLock::new(members),
+            member_ids,
             event: Event::new(),
+            member_event: Event::new(),
+            peer_tls,
         }
     }
 
@@ -37,21 +67,59 @@ impl State {
     }
 
     /// Get self address
-    pub(crate) fn self_address(&self) -> &str {
-        self.members.get(&self.id).unwrap_or_else(|| {
+    pub(crate) fn self_address(&self) -> String {
+        let members = self.members.read();
+        members.get(&self.id).cloned().unwrap_or_else(|| {
             panic!(
                 "Self address not found, id: {}, members: {:?}",
-                self.id, self.members
+                self.id, *members
             )
         })
     }
 
     /// Get leader address
-    pub(crate) fn leader_address(&self) -> Option<&str> {
+    pub(crate) fn leader_address(&self) -> Option<String> {
+        self.leader_id
+            .read()
+            .as_ref()
+            .and_then(|id| self.members.read().get(id).cloned())
+    }
+
+    /// Get the member id of the leader
+    pub(crate) fn leader_member_id(&self) -> Option<u64> {
         self.leader_id
             .read()
             .as_ref()
-            .and_then(|id| self.members.get(id).map(String::as_str))
+            .and_then(|id| self.member_ids.get(id).copied())
+    }
+
+    /// Get the id of a member
+    pub(crate) fn member_id(&self, name: &str) -> Option<u64> {
+        self.member_ids.get(name).copied()
+    }
+
+    /// Get the name of the member with `member_id`
+    pub(crate) fn member_name(&self, member_id: u64) -> Option<&str> {
+        self.member_ids
+            .iter()
+            .find_map(|(name, &id)| (id == member_id).then_some(name.as_str()))
+    }
+
+    /// Set the address of a member, return false if it's unchanged or the member is unknown
+    pub(crate) fn update_member(&self, name: &str, addr: String) -> bool {
+        let mut members = self.members.write();
+        match members.get_mut(name) {
+            Some(old) if *old != addr => *old = addr,
+            Some(_) | None => return false,
+        }
+        drop(members);
+        self.member_event.notify(usize::MAX);
+        true
+    }
+
+    /// listener of the address changes of the members
+    pub(crate) fn member_listener(&self) -> EventListener {
+        self.member_event.listen()
     }
 
     /// listener of leader change
@@ -80,25 +148,54 @@ impl State {
             .map_or(false, |id| self.id == *id)
     }
 
+    /// Get address of all members
+    pub(crate) fn members(&self) -> HashMap<String, String> {
+        self.members.read().clone()
+    }
+
     /// Get address of other members
     pub(crate) fn others(&self) -> HashMap<String, String> {
-        let mut members = self.members.clone();
+        let mut members = self.members();
         let _ignore = members.remove(&self.id);
         members
     }
 
+    /// TLS config of the connections to the members, `None` if they are in plaintext
+    pub(crate) fn peer_tls(&self) -> Option<ClientTlsConfig> {
+        self.peer_tls.clone()
+    }
+
+    /// Scheme of the URLs of the members, `https` if they are connected over TLS
+    pub(crate) fn peer_scheme(&self) -> &'static str {
+        if self.peer_tls.is_some() {
+            "https"
+        } else {
+            "http"
+        }
+    }
+
+    /// Connect to the member at `addr`, over mutual TLS if it's enabled for the peers
+    pub(crate) async fn connect(&self, addr: &str) -> Result<Channel, tonic::transport::Error> {
+        let endpoint = match self.peer_tls {
+            Some(ref tls) => {
+                Endpoint::from_shared(format!("https://{addr}"))?.tls_config(tls.clone())?
+            }
+            None => Endpoint::from_shared(format!("http://{addr}"))?,
+        };
+        endpoint.connect().await
+    }
+
     /// Wait leader until current node has a leader
     pub(crate) async fn wait_leader(&self) -> Result<String, tonic::Status> {
         let listener = {
             if let Some(leader_addr) = self.leader_address() {
-                return Ok(leader_addr.to_owned());
+                return Ok(leader_addr);
             }
             self.leader_listener()
         };
 
         listener.await;
         self.leader_address()
-            .map(str::to_owned)
             .ok_or_else(|| tonic::Status::internal("Get leader address error"))
     }
 }
@@ -133,7 +230,7 @@ mod test {
         assert!(!state.set_leader_id(Some("2".to_owned())));
         assert_eq!(state.id(), "1");
         assert_eq!(state.self_address(), "1");
-        assert_eq!(state.leader_address(), Some("2"));
+        assert_eq!(state.leader_address().as_deref(), Some("2"));
         assert!(!state.is_leader());
         assert_eq!(
             state.others(),
@@ -142,4 +239,28 @@ mod test {
         timeout(Duration::from_secs(1), handle).await??;
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_update_member() {
+        let state = State::new(
+            "1".to_owned(),
+            Some("2".to_owned()),
+            HashMap::from([
+                ("1".to_owned(), "127.0.0.1:2379".to_owned()),
+                ("2".to_owned(), "127.0.0.1:2380".to_owned()),
+            ]),
+        );
+        let id = state.member_id("2").unwrap();
+        let listener = state.member_listener();
+        assert!(state.update_member("2", "10.0.0.2:2380".to_owned()));
+        timeout(Duration::from_secs(1), listener).await.unwrap();
+        assert_eq!(state.leader_address().as_deref(), Some("10.0.0.2:2380"));
+        // the id is kept along with the name
+        assert_eq!(state.member_id("2"), Some(id));
+        assert_eq!(state.member_name(id), Some("2"));
+        assert_eq!(state.leader_member_id(), Some(id));
+
+        assert!(!state.update_member("2", "10.0.0.2:2380".to_owned()));
+        assert!(!state.update_member("3", "10.0.0.3:2380".to_owned()));
+    }
 }
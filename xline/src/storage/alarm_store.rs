@@ -0,0 +1,147 @@
+use std::collections::BTreeSet;
+
+use parking_lot::RwLock;
+use tracing::{info, warn};
+
+use super::ExecuteError;
+use crate::rpc::{AlarmMember, AlarmType, RequestWrapper};
+
+/// Alarms known by the member. They are raised by the checks of the member itself, either
+/// on its own storage or, on the leader, on the storages of the other members, so they are
+/// neither replicated to nor visible on the other members, and they are not persisted.
+#[derive(Debug)]
+pub(crate) struct AlarmStore {
+    /// Id of the member
+    member_id: u64,
+    /// Active alarms and the members they are raised for
+    alarms: RwLock<BTreeSet<(u64, AlarmType)>>,
+}
+
+impl AlarmStore {
+    /// New `AlarmStore`
+    pub(crate) fn new(member_id: u64) -> Self {
+        Self {
+            member_id,
+            alarms: RwLock::new(BTreeSet::new()),
+        }
+    }
+
+    /// Get the id of the member
+    pub(crate) fn member_id(&self) -> u64 {
+        self.member_id
+    }
+
+    /// Raise an alarm for the member itself, return false if it's already active.
+    /// `AlarmType::None` is ignored.
+    pub(crate) fn activate(&self, alarm: AlarmType) -> bool {
+        self.activate_for(self.member_id, alarm)
+    }
+
+    /// Raise an alarm for a member, return false if it's already active.
+    /// `AlarmType::None` is ignored.
+    pub(crate) fn activate_for(&self, member_id: u64, alarm: AlarmType) -> bool {
+        if alarm == AlarmType::None {
+            return false;
+        }
+        let activated = self.alarms.write().insert((member_id, alarm));
+        if activated {
+            warn!("alarm {alarm:?} is raised on member {member_id:x}");
+        }
+        activated
+    }
+
+    /// Clear an alarm of a member, return false if it's not active
+    pub(crate) fn deactivate_for(&self, member_id: u64, alarm: AlarmType) -> bool {
+        let deactivated = self.alarms.write().remove(&(member_id, alarm));
+        if deactivated {
+            info!("alarm {alarm:?} is cleared on member {member_id:x}");
+        }
+        deactivated
+    }
+
+    /// Check if an alarm is active for any member
+    pub(crate) fn is_active(&self, alarm: AlarmType) -> bool {
+        self.alarms.read().iter().any(|&(_, a)| a == alarm)
+    }
+
+    /// Check if an alarm is active for the member itself
+    pub(crate) fn is_self_active(&self, alarm: AlarmType) -> bool {
+        self.alarms.read().contains(&(self.member_id, alarm))
+    }
+
+    /// Check if a request can be proposed or executed. The cluster is read only while the
+    /// CORRUPT alarm is active, the reads are still served but the writes are rejected, so
+    /// that a corrupted member doesn't spread its corruption.
+    pub(crate) fn check_request(&self, request: &RequestWrapper) -> Result<(), ExecuteError> {
+        if request.is_write_request() && self.is_active(AlarmType::Corrupt) {
+            return Err(ExecuteError::corrupt());
+        }
+        Ok(())
+    }
+
+    /// Get the active alarms of a type, or all active alarms if it's `AlarmType::None`
+    pub(crate) fn get(&self, alarm: AlarmType) -> Vec<AlarmMember> {
+        self.alarms
+            .read()
+            .iter()
+            .filter(|&&(_, a)| alarm == AlarmType::None || a == alarm)
+            .map(|&(member_id, a)| AlarmMember {
+                member_id,
+                alarm: a.into(),
+            })
+            .collect()
+    }
+
+    /// Describe the active alarms like etcd does in the errors of `StatusResponse`,
+    /// eg. `memberID:1 alarm:CORRUPT`
+    pub(crate) fn describe(&self) -> Vec<String> {
+        self.alarms
+            .read()
+            .iter()
+            .map(|&(member_id, alarm)| {
+                let name = match alarm {
+                    AlarmType::None => "NONE",
+                    AlarmType::Nospace => "NOSPACE",
+                    AlarmType::Corrupt => "CORRUPT",
+                };
+                format!("memberID:{member_id} alarm:{name}")
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rpc::{PutRequest, RangeRequest};
+
+    #[test]
+    fn test_activate_and_deactivate_alarms() {
+        let store = AlarmStore::new(1);
+        assert!(!store.activate(AlarmType::None));
+        assert!(store.activate(AlarmType::Corrupt));
+        assert!(!store.activate(AlarmType::Corrupt));
+        assert!(store.is_active(AlarmType::Corrupt));
+        assert_eq!(store.get(AlarmType::None).len(), 1);
+        assert!(store.get(AlarmType::Nospace).is_empty());
+
+        let put = RequestWrapper::PutRequest(PutRequest::default());
+        let range = RequestWrapper::RangeRequest(RangeRequest::default());
+        assert!(store.check_request(&put).is_err());
+        assert!(store.check_request(&range).is_ok());
+
+        assert!(store.deactivate_for(1, AlarmType::Corrupt));
+        assert!(store.check_request(&put).is_ok());
+        assert!(!store.deactivate_for(1, AlarmType::Corrupt));
+        assert!(store.get(AlarmType::None).is_empty());
+
+        assert!(store.activate_for(2, AlarmType::Corrupt));
+        assert!(!store.is_self_active(AlarmType::Corrupt));
+        assert!(store.check_request(&put).is_err());
+        assert_eq!(store.get(AlarmType::Corrupt)[0].member_id, 2);
+        assert_eq!(
+            store.describe(),
+            vec!["memberID:2 alarm:CORRUPT".to_owned()]
+        );
+    }
+}
@@ -0,0 +1,102 @@
+use std::collections::{BTreeMap, HashMap};
+
+use curp::{cmd::ProposeId, LogIndex};
+
+use super::{storage_api::StorageApi, ExecuteError};
+
+/// Table of the applied propose ids, the keys are the log indexes in big endian
+pub(crate) const APPLIED_ID_TABLE: &str = "applied_id";
+
+/// Number of the latest log entries whose propose ids are kept
+pub(crate) const APPLIED_ID_WINDOW: LogIndex = 100_000;
+
+/// Propose ids of the latest log entries. A proposal may be appended to the log more than
+/// once, eg. when the client retries it after the leader changes, and the log entries after
+/// the flushed applied index are applied again after a crash. The ids are used to detect and
+/// skip the proposals which have been applied, so that they don't bump the revision or grant
+/// a lease twice.
+#[derive(Debug, Default)]
+pub(crate) struct AppliedIds {
+    /// Log index of the first entry of each propose id
+    indexes: HashMap<ProposeId, LogIndex>,
+    /// Propose ids in the order of the log
+    ids: BTreeMap<LogIndex, ProposeId>,
+}
+
+impl AppliedIds {
+    /// Recover the applied ids flushed to the storage
+    pub(crate) fn recover<S: StorageApi>(storage: &S) -> Result<Self, ExecuteError> {
+        let mut applied_ids = Self::default();
+        for (key, value) in storage.get_all(APPLIED_ID_TABLE)? {
+            let index = key
+                .try_into()
+                .map(LogIndex::from_be_bytes)
+                .map_err(|e| ExecuteError::DbError(format!("Invalid applied id index {e:?}")))?;
+            let id = String::from_utf8(value)
+                .map_err(|e| ExecuteError::DbError(format!("Invalid applied id, error: {e}")))?;
+            let _ignore = applied_ids.insert(&ProposeId::new(id), index);
+        }
+        Ok(applied_ids)
+    }
+
+    /// Record that the proposal `id` is at `index` of the log, return `false` if it has
+    /// been recorded at a smaller index, which means the entry at `index` is a replay.
+    /// The ids which fall out of the window are forgotten.
+    pub(crate) fn insert(&mut self, id: &ProposeId, index: LogIndex) -> bool {
+        if self.is_replayed(id, index) {
+            return false;
+        }
+        if self.indexes.insert(id.clone(), index).is_none() {
+            let _prev = self.ids.insert(index, id.clone());
+        }
+        let end = index.saturating_sub(APPLIED_ID_WINDOW);
+        while let Some((&first, _)) = self.ids.first_key_value() {
+            if first >= end {
+                break;
+            }
+            if let Some(old) = self.ids.remove(&first) {
+                let _ignore = self.indexes.remove(&old);
+            }
+        }
+        true
+    }
+
+    /// Check if the proposal `id` at `index` has been recorded at a smaller index
+    pub(crate) fn is_replayed(&self, id: &ProposeId, index: LogIndex) -> bool {
+        self.indexes.get(id).map_or(false, |&first| first < index)
+    }
+
+    /// Forget all ids
+    pub(crate) fn clear(&mut self) {
+        self.indexes.clear();
+        self.ids.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_replay_is_detected() {
+        let mut applied_ids = AppliedIds::default();
+        let id = ProposeId::new("id".to_owned());
+        assert!(applied_ids.insert(&id, 1));
+        // the same entry may be prepared again after a reset
+        assert!(applied_ids.insert(&id, 1));
+        assert!(!applied_ids.is_replayed(&id, 1));
+        assert!(!applied_ids.insert(&id, 3));
+        assert!(applied_ids.is_replayed(&id, 3));
+    }
+
+    #[test]
+    fn test_old_ids_are_forgotten() {
+        let mut applied_ids = AppliedIds::default();
+        let old = ProposeId::new("old".to_owned());
+        assert!(applied_ids.insert(&old, 1));
+        let new = ProposeId::new("new".to_owned());
+        assert!(applied_ids.insert(&new, APPLIED_ID_WINDOW + 2));
+        assert!(!applied_ids.is_replayed(&old, APPLIED_ID_WINDOW + 3));
+        assert!(applied_ids.is_replayed(&new, APPLIED_ID_WINDOW + 3));
+    }
+}
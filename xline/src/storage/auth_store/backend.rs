@@ -4,7 +4,7 @@ use curp::cmd::ProposeId;
 use prost::Message;
 
 use crate::{
-    rpc::{Role, User},
+    rpc::{ApiKey, Role, User},
     storage::{db::WriteOp, storage_api::StorageApi, ExecuteError},
 };
 
@@ -18,6 +18,8 @@ pub(crate) const AUTH_TABLE: &str = "auth";
 pub(crate) const AUTH_ENABLE_KEY: &[u8] = b"enable";
 /// Key of `AuthRevision`
 pub(crate) const AUTH_REVISION_KEY: &[u8] = b"revision";
+/// Prefix of the keys of the API keys in auth table
+pub(crate) const API_KEY_PREFIX: &str = "api_key/";
 /// Root user
 pub(crate) const ROOT_USER: &str = "root";
 /// Root role
@@ -58,7 +60,7 @@ where
             Some(value) => Ok(User::decode(value.as_slice()).unwrap_or_else(|e| {
                 panic!("Failed to decode user from value, error: {e:?}, value: {value:?}");
             })),
-            None => Err(ExecuteError::user_not_found(username)),
+            None => Err(ExecuteError::user_not_found()),
         }
     }
 
@@ -68,7 +70,7 @@ where
             Some(value) => Ok(Role::decode(value.as_slice()).unwrap_or_else(|e| {
                 panic!("Failed to decode role from value, error: {e:?}, value: {value:?}");
             })),
-            None => Err(ExecuteError::role_not_found(rolename)),
+            None => Err(ExecuteError::role_not_found()),
         }
     }
 
@@ -102,6 +104,35 @@ where
         Ok(roles)
     }
 
+    /// Get an API key by its id
+    pub(crate) fn get_api_key(&self, id: &str) -> Result<ApiKey, ExecuteError> {
+        match self
+            .db
+            .get_value(AUTH_TABLE, format!("{API_KEY_PREFIX}{id}"))?
+        {
+            Some(value) => Ok(ApiKey::decode(value.as_slice()).unwrap_or_else(|e| {
+                panic!("Failed to decode api key from value, error: {e:?}, value: {value:?}");
+            })),
+            None => Err(ExecuteError::api_key_not_found()),
+        }
+    }
+
+    /// Get all API keys in the `AuthStore`
+    pub(crate) fn get_all_api_keys(&self) -> Result<Vec<ApiKey>, ExecuteError> {
+        let api_keys = self
+            .db
+            .get_all(AUTH_TABLE)?
+            .into_iter()
+            .filter(|&(ref key, _)| key.starts_with(API_KEY_PREFIX.as_bytes()))
+            .map(|(_, value)| {
+                ApiKey::decode(value.as_slice()).unwrap_or_else(|e| {
+                    panic!("Failed to decode api key from value, error: {e:?}, value: {value:?}");
+                })
+            })
+            .collect();
+        Ok(api_keys)
+    }
+
     /// get auth enable
     pub(crate) fn get_enable(&self) -> Result<bool, ExecuteError> {
         if let Some(enabled) = self.db.get_value(AUTH_TABLE, AUTH_ENABLE_KEY)? {
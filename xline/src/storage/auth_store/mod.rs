@@ -5,5 +5,7 @@ mod perms;
 /// Storage for auth
 mod store;
 
-pub(crate) use backend::{AUTH_ENABLE_KEY, AUTH_REVISION_KEY, AUTH_TABLE, ROLE_TABLE, USER_TABLE};
-pub(crate) use store::AuthStore;
+pub(crate) use backend::{
+    API_KEY_PREFIX, AUTH_ENABLE_KEY, AUTH_REVISION_KEY, AUTH_TABLE, ROLE_TABLE, USER_TABLE,
+};
+pub(crate) use store::{hash_api_key, AuthStore};
@@ -9,10 +9,13 @@ use jsonwebtoken::{
 };
 use serde::{Deserialize, Serialize};
 
-use crate::server::command::KeyRange;
+use crate::{
+    rpc::{Permission, Type},
+    server::command::KeyRange,
+};
 
-/// default token ttl
-const DEFAULT_TOKEN_TTL: u64 = 300;
+/// default token ttl, it's also the max ttl of a token
+pub(super) const DEFAULT_TOKEN_TTL: u64 = 300;
 
 /// Claims of Token
 #[derive(Debug, Serialize, Deserialize)]
@@ -21,6 +24,12 @@ pub(crate) struct TokenClaims {
     pub(crate) username: String,
     /// Revision
     pub(crate) revision: i64,
+    /// Permissions the token is restricted to, `None` if it has all permissions of the user
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) scope: Option<UserPermissions>,
+    /// Roles of an external identity, `None` if the token is assigned to a user of Xline
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) roles: Option<Vec<String>>,
     /// Expiration
     exp: u64,
 }
@@ -33,8 +42,16 @@ pub(crate) trait TokenOperate {
     /// Error type
     type Error;
 
-    /// Assign a token with claims.
-    fn assign(&self, username: &str, revision: i64) -> Result<String, Self::Error>;
+    /// Assign a token with claims, it's restricted to `scope` if it's given, and expires
+    /// after `ttl` seconds. The token of an external identity carries its `roles`.
+    fn assign(
+        &self,
+        username: &str,
+        revision: i64,
+        scope: Option<UserPermissions>,
+        roles: Option<Vec<String>>,
+        ttl: u64,
+    ) -> Result<String, Self::Error>;
 
     /// Verify token and return claims.
     fn verify(&self, token: &str) -> Result<Self::Claims, Self::Error>;
@@ -72,7 +89,14 @@ impl TokenOperate for JwtTokenManager {
 
     type Claims = TokenClaims;
 
-    fn assign(&self, username: &str, revision: i64) -> Result<String, Self::Error> {
+    fn assign(
+        &self,
+        username: &str,
+        revision: i64,
+        scope: Option<UserPermissions>,
+        roles: Option<Vec<String>>,
+        ttl: u64,
+    ) -> Result<String, Self::Error> {
         let now = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .unwrap_or_else(|e| panic!("SystemTime before UNIX EPOCH! {e}"))
@@ -80,7 +104,9 @@ impl TokenOperate for JwtTokenManager {
         let claims = TokenClaims {
             username: username.to_owned(),
             revision,
-            exp: now.wrapping_add(DEFAULT_TOKEN_TTL),
+            scope,
+            roles,
+            exp: now.wrapping_add(ttl),
         };
         let token =
             jsonwebtoken::encode(&Header::new(Algorithm::RS256), &claims, &self.encoding_key)?;
@@ -98,7 +124,7 @@ impl TokenOperate for JwtTokenManager {
 }
 
 /// Permissions if a user
-#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
 pub(crate) struct UserPermissions {
     /// `KeyRange` has read permission
     pub(crate) read: Vec<KeyRange>,
@@ -114,6 +140,49 @@ impl UserPermissions {
             write: Vec::new(),
         }
     }
+
+    /// Grant a permission
+    pub(crate) fn grant(&mut self, permission: Permission) {
+        let key_range = KeyRange {
+            start: permission.key,
+            end: permission.range_end,
+        };
+        #[allow(clippy::unwrap_used)] // safe unwrap
+        match Type::from_i32(permission.perm_type).unwrap() {
+            Type::Readwrite => {
+                self.read.push(key_range.clone());
+                self.write.push(key_range);
+            }
+            Type::Write => {
+                self.write.push(key_range);
+            }
+            Type::Read => {
+                self.read.push(key_range);
+            }
+        }
+    }
+
+    /// Check if an operation of `perm_type` on `key_range` is permitted
+    pub(crate) fn permits(&self, key_range: &KeyRange, perm_type: Type) -> bool {
+        let ranges = match perm_type {
+            Type::Read => &self.read,
+            Type::Write => &self.write,
+            Type::Readwrite => {
+                unreachable!("Readwrite is unreachable");
+            }
+        };
+        ranges.iter().any(|kr| kr.contains_range(key_range))
+    }
+}
+
+impl FromIterator<Permission> for UserPermissions {
+    fn from_iter<T: IntoIterator<Item = Permission>>(iter: T) -> Self {
+        let mut permissions = Self::new();
+        for permission in iter {
+            permissions.grant(permission);
+        }
+        permissions
+    }
 }
 
 /// Permissions cache
@@ -17,17 +17,23 @@ use pbkdf2::{
     password_hash::{PasswordHash, PasswordVerifier},
     Pbkdf2,
 };
+use sha2::{Digest, Sha256};
 use tokio::sync::mpsc;
 use utils::parking_lot_lock::RwLockMap;
 
 use super::{
     backend::{ROOT_ROLE, ROOT_USER},
-    perms::{JwtTokenManager, PermissionCache, TokenClaims, TokenOperate, UserPermissions},
+    perms::{
+        JwtTokenManager, PermissionCache, TokenClaims, TokenOperate, UserPermissions,
+        DEFAULT_TOKEN_TTL,
+    },
 };
 use crate::{
     header_gen::HeaderGenerator,
     revision_number::RevisionNumber,
     rpc::{
+        ApiKey, AuthApiKeyAddRequest, AuthApiKeyAddResponse, AuthApiKeyListRequest,
+        AuthApiKeyListResponse, AuthApiKeyRevokeRequest, AuthApiKeyRevokeResponse,
         AuthDisableRequest, AuthDisableResponse, AuthEnableRequest, AuthEnableResponse,
         AuthRoleAddRequest, AuthRoleAddResponse, AuthRoleDeleteRequest, AuthRoleDeleteResponse,
         AuthRoleGetRequest, AuthRoleGetResponse, AuthRoleGrantPermissionRequest,
@@ -47,6 +53,7 @@ use crate::{
         auth_store::backend::AuthStoreBackend,
         db::WriteOp,
         lease_store::{Lease, LeaseMessage},
+        namespace::NamespaceStore,
         storage_api::StorageApi,
         ExecuteError,
     },
@@ -72,6 +79,8 @@ where
     permission_cache: RwLock<PermissionCache>,
     /// The manager of token
     token_manager: Option<JwtTokenManager>,
+    /// Namespaces which the users are bound to by their roles
+    namespaces: Arc<NamespaceStore>,
 }
 
 impl<S> AuthStore<S>
@@ -85,6 +94,7 @@ where
         key_pair: Option<(EncodingKey, DecodingKey)>,
         header_gen: Arc<HeaderGenerator>,
         storage: Arc<S>,
+        namespaces: Arc<NamespaceStore>,
     ) -> Self {
         let backend = Arc::new(AuthStoreBackend::new(storage));
         Self {
@@ -97,6 +107,7 @@ where
             token_manager: key_pair.map(|(encoding_key, decoding_key)| {
                 JwtTokenManager::new(encoding_key, decoding_key)
             }),
+            namespaces,
         }
     }
 
@@ -117,9 +128,21 @@ where
 
     /// Assign token
     pub(crate) fn assign(&self, username: &str) -> Result<String, ExecuteError> {
+        self.assign_scoped(username, None, None, DEFAULT_TOKEN_TTL)
+    }
+
+    /// Assign a token restricted to `scope`, which expires after `ttl` seconds, the token
+    /// of an external identity carries its `roles`
+    fn assign_scoped(
+        &self,
+        username: &str,
+        scope: Option<UserPermissions>,
+        roles: Option<Vec<String>>,
+        ttl: u64,
+    ) -> Result<String, ExecuteError> {
         match self.token_manager {
             Some(ref token_manager) => token_manager
-                .assign(username, self.revision())
+                .assign(username, self.revision(), scope, roles, ttl)
                 .map_err(|_ignore| ExecuteError::invalid_auth_token()),
             None => Err(ExecuteError::token_manager_not_init()),
         }
@@ -172,23 +195,7 @@ where
                 continue;
             };
             for permission in role.key_permission {
-                let key_range = KeyRange {
-                    start: permission.key,
-                    end: permission.range_end,
-                };
-                #[allow(clippy::unwrap_used)] // safe unwrap
-                match Type::from_i32(permission.perm_type).unwrap() {
-                    Type::Readwrite => {
-                        user_permission.read.push(key_range.clone());
-                        user_permission.write.push(key_range.clone());
-                    }
-                    Type::Write => {
-                        user_permission.write.push(key_range.clone());
-                    }
-                    Type::Read => {
-                        user_permission.read.push(key_range.clone());
-                    }
-                }
+                user_permission.grant(permission);
             }
         }
         user_permission
@@ -252,6 +259,15 @@ where
             RequestWrapper::AuthenticateRequest(ref req) => {
                 self.handle_authenticate_request(req).map(Into::into)
             }
+            RequestWrapper::AuthApiKeyAddRequest(ref req) => {
+                self.handle_api_key_add_request(req).map(Into::into)
+            }
+            RequestWrapper::AuthApiKeyRevokeRequest(ref req) => {
+                self.handle_api_key_revoke_request(req).map(Into::into)
+            }
+            RequestWrapper::AuthApiKeyListRequest(ref req) => {
+                self.handle_api_key_list_request(req).map(Into::into)
+            }
             _ => {
                 unreachable!("Other request should not be sent to this store");
             }
@@ -309,7 +325,49 @@ where
         if !self.is_enabled() {
             return Err(ExecuteError::auth_not_enabled());
         }
-        let token = self.assign(&req.name)?;
+        let (scope, ttl) = Self::token_options(req)?;
+        let token = self.assign_scoped(&req.name, scope, None, ttl)?;
+        Ok(AuthenticateResponse {
+            header: Some(self.header_gen.gen_header_without_revision()),
+            token,
+        })
+    }
+
+    /// Get the scope and the ttl of the token requested by `req`
+    fn token_options(
+        req: &AuthenticateRequest,
+    ) -> Result<(Option<UserPermissions>, u64), ExecuteError> {
+        let ttl = match u64::try_from(req.ttl) {
+            Ok(0) => DEFAULT_TOKEN_TTL,
+            Ok(ttl) if ttl <= DEFAULT_TOKEN_TTL => ttl,
+            _ => return Err(ExecuteError::invalid_token_ttl()),
+        };
+        if req
+            .scopes
+            .iter()
+            .any(|perm| Type::from_i32(perm.perm_type).is_none())
+        {
+            return Err(ExecuteError::invalid_token_scope());
+        }
+        let scope = (!req.scopes.is_empty())
+            .then(|| req.scopes.iter().cloned().collect::<UserPermissions>());
+        Ok((scope, ttl))
+    }
+
+    /// Assign a token to an external identity with its `roles`, the identity is
+    /// authenticated by the external provider of the member, so the token is assigned
+    /// locally without consensus
+    pub(crate) fn assign_external(
+        &self,
+        req: &AuthenticateRequest,
+        username: &str,
+        roles: Vec<String>,
+    ) -> Result<AuthenticateResponse, ExecuteError> {
+        if !self.is_enabled() {
+            return Err(ExecuteError::auth_not_enabled());
+        }
+        let (scope, ttl) = Self::token_options(req)?;
+        let token = self.assign_scoped(username, scope, Some(roles), ttl)?;
         Ok(AuthenticateResponse {
             header: Some(self.header_gen.gen_header_without_revision()),
             token,
@@ -323,7 +381,7 @@ where
     ) -> Result<AuthUserAddResponse, ExecuteError> {
         debug!("handle_user_add_request");
         if self.backend.get_user(&req.name).is_ok() {
-            return Err(ExecuteError::user_already_exists(&req.name));
+            return Err(ExecuteError::user_already_exists());
         }
         Ok(AuthUserAddResponse {
             header: Some(self.header_gen.gen_header_without_revision()),
@@ -418,7 +476,7 @@ where
         }
         let user = self.backend.get_user(&req.name)?;
         if user.roles.binary_search(&req.role).is_err() {
-            return Err(ExecuteError::role_not_granted(&req.role));
+            return Err(ExecuteError::role_not_granted());
         }
         Ok(AuthUserRevokeRoleResponse {
             header: Some(self.header_gen.gen_header_without_revision()),
@@ -432,7 +490,7 @@ where
     ) -> Result<AuthRoleAddResponse, ExecuteError> {
         debug!("handle_role_add_request");
         if self.backend.get_role(&req.name).is_ok() {
-            return Err(ExecuteError::role_already_exists(&req.name));
+            return Err(ExecuteError::role_already_exists());
         }
         Ok(AuthRoleAddResponse {
             header: Some(self.header_gen.gen_header_without_revision()),
@@ -530,6 +588,54 @@ where
         })
     }
 
+    /// Handle `AuthApiKeyAddRequest`
+    fn handle_api_key_add_request(
+        &self,
+        req: &AuthApiKeyAddRequest,
+    ) -> Result<AuthApiKeyAddResponse, ExecuteError> {
+        debug!("handle_api_key_add_request");
+        let _user = self.backend.get_user(&req.user)?;
+        Ok(AuthApiKeyAddResponse {
+            header: Some(self.header_gen.gen_header_without_revision()),
+            id: req.id.clone(),
+            key: String::new(),
+        })
+    }
+
+    /// Handle `AuthApiKeyRevokeRequest`
+    fn handle_api_key_revoke_request(
+        &self,
+        req: &AuthApiKeyRevokeRequest,
+    ) -> Result<AuthApiKeyRevokeResponse, ExecuteError> {
+        debug!("handle_api_key_revoke_request");
+        let _api_key = self.backend.get_api_key(&req.id)?;
+        Ok(AuthApiKeyRevokeResponse {
+            header: Some(self.header_gen.gen_header_without_revision()),
+        })
+    }
+
+    /// Handle `AuthApiKeyListRequest`, the hashes of the keys are not returned
+    fn handle_api_key_list_request(
+        &self,
+        req: &AuthApiKeyListRequest,
+    ) -> Result<AuthApiKeyListResponse, ExecuteError> {
+        debug!("handle_api_key_list_request");
+        let keys = self
+            .backend
+            .get_all_api_keys()?
+            .into_iter()
+            .filter(|api_key| req.user.is_empty() || api_key.user == req.user)
+            .map(|api_key| ApiKey {
+                hashed_key: vec![],
+                ..api_key
+            })
+            .collect();
+        Ok(AuthApiKeyListResponse {
+            header: Some(self.header_gen.gen_header_without_revision()),
+            keys,
+        })
+    }
+
     /// sync a auth request
     pub(crate) fn after_sync(
         &self,
@@ -573,7 +679,7 @@ where
             }
             RequestWrapper::AuthUserDeleteRequest(ref req) => {
                 debug!("Sync AuthUserDeleteRequest {:?}", req);
-                self.sync_user_delete_request(id, req);
+                self.sync_user_delete_request(id, req)?;
             }
             RequestWrapper::AuthRoleAddRequest(ref req) => {
                 debug!("Sync AuthRoleAddRequest {:?}", req);
@@ -600,6 +706,17 @@ where
             RequestWrapper::AuthenticateRequest(ref req) => {
                 debug!("Sync AuthenticateRequest {:?}", req);
             }
+            RequestWrapper::AuthApiKeyAddRequest(ref req) => {
+                debug!("Sync AuthApiKeyAddRequest {:?}", req.id);
+                self.sync_api_key_add_request(id, req);
+            }
+            RequestWrapper::AuthApiKeyRevokeRequest(ref req) => {
+                debug!("Sync AuthApiKeyRevokeRequest {:?}", req);
+                self.sync_api_key_revoke_request(id, req);
+            }
+            RequestWrapper::AuthApiKeyListRequest(ref req) => {
+                debug!("Sync AuthApiKeyListRequest {:?}", req);
+            }
             _ => {
                 unreachable!("Other request should not be sent to this store");
             }
@@ -650,8 +767,13 @@ where
         self.backend.buffer_op(id, WriteOp::PutUser(user));
     }
 
-    /// Sync `AuthUserDeleteRequest` and return whether authstore is changed.
-    fn sync_user_delete_request(&self, id: &ProposeId, req: &AuthUserDeleteRequest) {
+    /// Sync `AuthUserDeleteRequest` and return whether authstore is changed. The API keys
+    /// of the user are revoked with it.
+    fn sync_user_delete_request(
+        &self,
+        id: &ProposeId,
+        req: &AuthUserDeleteRequest,
+    ) -> Result<(), ExecuteError> {
         self.permission_cache.map_write(|mut cache| {
             let _ignore = cache.user_permissions.remove(&req.name);
             cache.role_to_users_map.iter_mut().for_each(|(_, users)| {
@@ -665,6 +787,13 @@ where
             .buffer_op(id, WriteOp::PutAuthRevision(revision));
         self.backend
             .buffer_op(id, WriteOp::DeleteUser(req.name.clone()));
+        for api_key in self.backend.get_all_api_keys()? {
+            if api_key.user == req.name {
+                self.backend
+                    .buffer_op(id, WriteOp::DeleteApiKey(api_key.id));
+            }
+        }
+        Ok(())
     }
 
     /// Sync `AuthUserChangePasswordRequest` and return whether authstore is changed.
@@ -691,10 +820,10 @@ where
         let mut user = self.backend.get_user(&req.user)?;
         let role = self.backend.get_role(&req.role);
         if (req.role != ROOT_ROLE) && role.is_err() {
-            return Err(ExecuteError::role_not_found(&req.role));
+            return Err(ExecuteError::role_not_found());
         }
-        let Err(idx) =  user.roles.binary_search(&req.role) else {
-            return Err(ExecuteError::user_already_has_role(&req.user, &req.role));
+        let Err(idx) = user.roles.binary_search(&req.role) else {
+            return Err(ExecuteError::user_already_has_role());
         };
         user.roles.insert(idx, req.role.clone());
         if let Ok(role) = role {
@@ -744,7 +873,7 @@ where
         let idx = user
             .roles
             .binary_search(&req.role)
-            .map_err(|_ignore| ExecuteError::role_not_granted(&req.role))?;
+            .map_err(|_ignore| ExecuteError::role_not_granted())?;
         let _ignore = user.roles.remove(idx);
         self.permission_cache.map_write(|mut cache| {
             let user_permissions = self.get_user_permissions(&user, None);
@@ -908,6 +1037,30 @@ where
         Ok(())
     }
 
+    /// Sync `AuthApiKeyAddRequest`
+    fn sync_api_key_add_request(&self, id: &ProposeId, req: &AuthApiKeyAddRequest) {
+        let api_key = ApiKey {
+            id: req.id.clone(),
+            user: req.user.clone(),
+            hashed_key: req.hashed_key.clone(),
+            description: req.description.clone(),
+        };
+        let revision = self.revision.next();
+        self.backend
+            .buffer_op(id, WriteOp::PutAuthRevision(revision));
+        self.backend.buffer_op(id, WriteOp::PutApiKey(api_key));
+    }
+
+    /// Sync `AuthApiKeyRevokeRequest`, the tokens assigned before are invalidated with the
+    /// auth revision
+    fn sync_api_key_revoke_request(&self, id: &ProposeId, req: &AuthApiKeyRevokeRequest) {
+        let revision = self.revision.next();
+        self.backend
+            .buffer_op(id, WriteOp::PutAuthRevision(revision));
+        self.backend
+            .buffer_op(id, WriteOp::DeleteApiKey(req.id.clone()));
+    }
+
     /// Auth revision
     pub(crate) fn revision(&self) -> i64 {
         self.revision.get()
@@ -938,6 +1091,23 @@ where
         Ok(self.revision())
     }
 
+    /// Check an API key, return the user of the key and the auth revision checked at
+    pub(crate) fn check_api_key(&self, key: &str) -> Result<(String, i64), ExecuteError> {
+        if !self.is_enabled() {
+            return Err(ExecuteError::auth_not_enabled());
+        }
+        let (key_id, _secret) = key.split_once('.').ok_or_else(ExecuteError::auth_failed)?;
+        let api_key = self
+            .backend
+            .get_api_key(key_id)
+            .map_err(|_ignore| ExecuteError::auth_failed())?;
+        if api_key.hashed_key != hash_api_key(key) {
+            return Err(ExecuteError::auth_failed());
+        }
+        let _user = self.backend.get_user(&api_key.user)?;
+        Ok((api_key.user, self.revision()))
+    }
+
     /// Check if the request need admin permission
     fn need_admin_permission(wrapper: &RequestWithToken) -> bool {
         matches!(
@@ -956,6 +1126,10 @@ where
                 | RequestWrapper::AuthRoleDeleteRequest(_)
                 | RequestWrapper::AuthUserListRequest(_)
                 | RequestWrapper::AuthRoleListRequest(_)
+                | RequestWrapper::AuthApiKeyAddRequest(_)
+                | RequestWrapper::AuthApiKeyRevokeRequest(_)
+                | RequestWrapper::AuthApiKeyListRequest(_)
+                | RequestWrapper::MemberUpdateRequest(_)
         )
     }
 
@@ -964,6 +1138,16 @@ where
         self.permission_cache.map_read(|cache| cache.clone())
     }
 
+    /// Get the user whose writes of `wrapper` are limited by the write quotas. The root
+    /// user is not limited, neither are the requests when auth is disabled.
+    pub(crate) fn quota_user(&self, wrapper: &RequestWithToken) -> Option<String> {
+        if !self.is_enabled() {
+            return None;
+        }
+        let claims = self.verify_token(wrapper.token.as_ref()?).ok()?;
+        (claims.username != ROOT_USER).then_some(claims.username)
+    }
+
     /// check if the request is permitted
     pub(crate) async fn check_permission(
         &self,
@@ -985,32 +1169,40 @@ where
         if claims.revision < self.revision() {
             return Err(ExecuteError::token_old_revision());
         }
-        let username = claims.username;
+        let username = claims.username.as_str();
         if Self::need_admin_permission(wrapper) {
-            self.check_admin_permission(&username)?;
+            // a scoped token never manages the auth
+            if claims.scope.is_some() {
+                return Err(ExecuteError::PermissionDenied);
+            }
+            self.check_admin_permission(&claims)?;
         } else {
             #[allow(clippy::wildcard_enum_match_arm)]
             match wrapper.request {
                 RequestWrapper::RangeRequest(ref range_req) => {
-                    self.check_range_permission(&username, range_req)?;
+                    self.check_range_permission(&claims, range_req)?;
                 }
                 RequestWrapper::PutRequest(ref put_req) => {
-                    self.check_put_permission(&username, put_req).await?;
+                    self.check_put_permission(&claims, put_req).await?;
                 }
                 RequestWrapper::DeleteRangeRequest(ref del_range_req) => {
-                    self.check_delete_permission(&username, del_range_req)?;
+                    self.check_delete_permission(&claims, del_range_req)?;
                 }
                 RequestWrapper::TxnRequest(ref txn_req) => {
-                    self.check_txn_permission(&username, txn_req).await?;
+                    self.check_txn_permission(&claims, txn_req).await?;
                 }
                 RequestWrapper::LeaseRevokeRequest(ref lease_revoke_req) => {
-                    self.check_lease_revoke_permission(&username, lease_revoke_req)
+                    self.check_lease_revoke_permission(&claims, lease_revoke_req)
                         .await?;
                 }
+                RequestWrapper::LeaseModifyTtlRequest(ref lease_modify_ttl_req) => {
+                    // changing the ttl changes the lifetime of the keys like revoking the lease
+                    self.check_lease(&claims, lease_modify_ttl_req.id).await?;
+                }
                 RequestWrapper::AuthUserGetRequest(ref user_get_req) => {
-                    self.check_admin_permission(&username).map_or_else(
+                    self.check_admin_permission(&claims).map_or_else(
                         |e| {
-                            if user_get_req.name == username {
+                            if claims.roles.is_none() && user_get_req.name == username {
                                 Ok(())
                             } else {
                                 Err(e)
@@ -1020,10 +1212,9 @@ where
                     )?;
                 }
                 RequestWrapper::AuthRoleGetRequest(ref role_get_req) => {
-                    self.check_admin_permission(&username).map_or_else(
+                    self.check_admin_permission(&claims).map_or_else(
                         |e| {
-                            let user = self.backend.get_user(&username)?;
-                            if user.has_role(&role_get_req.role) {
+                            if self.user_of(&claims)?.has_role(&role_get_req.role) {
                                 Ok(())
                             } else {
                                 Err(e)
@@ -1041,41 +1232,41 @@ where
     /// check if range request is permitted
     fn check_range_permission(
         &self,
-        username: &str,
+        claims: &TokenClaims,
         req: &RangeRequest,
     ) -> Result<(), ExecuteError> {
-        self.check_op_permission(username, &req.key, &req.range_end, Type::Read)
+        self.check_op_permission(claims, &req.key, &req.range_end, Type::Read)
     }
 
     /// check if put request is permitted
     async fn check_put_permission(
         &self,
-        username: &str,
+        claims: &TokenClaims,
         req: &PutRequest,
     ) -> Result<(), ExecuteError> {
         if req.prev_kv {
-            self.check_op_permission(username, &req.key, &[], Type::Read)?;
+            self.check_op_permission(claims, &req.key, &[], Type::Read)?;
         }
-        self.check_lease(username, req.lease).await?;
-        self.check_op_permission(username, &req.key, &[], Type::Write)
+        self.check_lease(claims, req.lease).await?;
+        self.check_op_permission(claims, &req.key, &[], Type::Write)
     }
 
     /// check if delete request is permitted
     fn check_delete_permission(
         &self,
-        username: &str,
+        claims: &TokenClaims,
         req: &DeleteRangeRequest,
     ) -> Result<(), ExecuteError> {
         if req.prev_kv {
-            self.check_op_permission(username, &req.key, &req.range_end, Type::Read)?;
+            self.check_op_permission(claims, &req.key, &req.range_end, Type::Read)?;
         }
-        self.check_op_permission(username, &req.key, &req.range_end, Type::Write)
+        self.check_op_permission(claims, &req.key, &req.range_end, Type::Write)
     }
 
     /// check if txn request is permitted
     async fn check_txn_permission(
         &self,
-        username: &str,
+        claims: &TokenClaims,
         req: &TxnRequest,
     ) -> Result<(), ExecuteError> {
         let mut check_queue = VecDeque::new();
@@ -1086,18 +1277,18 @@ where
         while let Some(req_op) = check_queue.pop_front() {
             match req_op.request {
                 Some(Request::RequestRange(ref range_req)) => {
-                    self.check_range_permission(username, range_req)?;
+                    self.check_range_permission(claims, range_req)?;
                 }
                 Some(Request::RequestPut(ref put_req)) => {
-                    self.check_put_permission(username, put_req).await?;
+                    self.check_put_permission(claims, put_req).await?;
                 }
                 Some(Request::RequestDeleteRange(ref del_range_req)) => {
-                    self.check_delete_permission(username, del_range_req)?;
+                    self.check_delete_permission(claims, del_range_req)?;
                 }
                 Some(Request::RequestTxn(ref txn_req)) => {
                     for compare in &txn_req.compare {
                         self.check_op_permission(
-                            username,
+                            claims,
                             &compare.key,
                             &compare.range_end,
                             Type::Read,
@@ -1116,72 +1307,97 @@ where
     /// check if lease revoke request is permitted
     async fn check_lease_revoke_permission(
         &self,
-        username: &str,
+        claims: &TokenClaims,
         req: &LeaseRevokeRequest,
     ) -> Result<(), ExecuteError> {
-        self.check_lease(username, req.id).await
+        self.check_lease(claims, req.id).await
     }
 
     /// check if user can revoke lease
-    async fn check_lease(&self, username: &str, lease_id: i64) -> Result<(), ExecuteError> {
+    async fn check_lease(&self, claims: &TokenClaims, lease_id: i64) -> Result<(), ExecuteError> {
         let lease = self.get_lease(lease_id).await;
         if let Some(lease) = lease {
-            let keys = lease.keys();
-            for key in keys {
-                self.check_op_permission(username, &key, &[], Type::Write)?;
+            for key in lease.keys().iter() {
+                self.check_op_permission(claims, key, &[], Type::Write)?;
             }
         }
         Ok(())
     }
 
-    /// Check if the user has admin permission
-    fn check_admin_permission(&self, username: &str) -> Result<(), ExecuteError> {
+    /// Check if the token belongs to a user with the root role, always pass when auth is disabled
+    pub(crate) fn check_admin_token(&self, token: Option<&str>) -> Result<(), ExecuteError> {
         if !self.is_enabled() {
             return Ok(());
         }
-        let user = self.backend.get_user(username)?;
-        if user.has_role(ROOT_ROLE) {
+        let claims = self.verify_token(token.ok_or_else(ExecuteError::token_not_provided)?)?;
+        if claims.scope.is_some() {
+            return Err(ExecuteError::PermissionDenied);
+        }
+        self.check_admin_permission(&claims)
+    }
+
+    /// Check if the token bearer has admin permission
+    fn check_admin_permission(&self, claims: &TokenClaims) -> Result<(), ExecuteError> {
+        if !self.is_enabled() {
+            return Ok(());
+        }
+        if self.user_of(claims)?.has_role(ROOT_ROLE) {
             return Ok(());
         }
         Err(ExecuteError::PermissionDenied)
     }
 
-    /// check permission for a kv operation
+    /// Get the user bearing the token, an external identity is a user with the sorted roles
+    /// carried by its token, it never takes the roles of the user with the same name
+    fn user_of(&self, claims: &TokenClaims) -> Result<User, ExecuteError> {
+        match claims.roles {
+            Some(ref roles) => Ok(User {
+                name: claims.username.as_bytes().to_vec(),
+                roles: roles.iter().sorted().cloned().collect(),
+                ..User::default()
+            }),
+            None => self.backend.get_user(&claims.username),
+        }
+    }
+
+    /// check permission for a kv operation, a scoped token must also have the permission
+    /// in its scope
     fn check_op_permission(
         &self,
-        username: &str,
+        claims: &TokenClaims,
         key: &[u8],
         range_end: &[u8],
         perm_type: Type,
     ) -> Result<(), ExecuteError> {
-        let user = self.backend.get_user(username)?;
+        let key_range = KeyRange::new(key, range_end);
+        if let Some(ref scope) = claims.scope {
+            if !scope.permits(&key_range, perm_type) {
+                return Err(ExecuteError::PermissionDenied);
+            }
+        }
+        let user = self.user_of(claims)?;
         if user.has_role(ROOT_ROLE) {
             return Ok(());
         }
-        let key_range = KeyRange::new(key, range_end);
-        if let Some(permissions) = self.permission_cache.read().user_permissions.get(username) {
-            match perm_type {
-                Type::Read => {
-                    if permissions
-                        .read
-                        .iter()
-                        .any(|kr| kr.contains_range(&key_range))
-                    {
-                        return Ok(());
-                    }
-                }
-                Type::Write => {
-                    if permissions
-                        .write
-                        .iter()
-                        .any(|kr| kr.contains_range(&key_range))
-                    {
-                        return Ok(());
-                    }
-                }
-                Type::Readwrite => {
-                    unreachable!("Readwrite is unreachable");
-                }
+        if !self.namespaces.permits(&user.roles, &key_range) {
+            return Err(ExecuteError::PermissionDenied);
+        }
+        // the permissions of an external identity are not cached, it's not a user of Xline
+        if claims.roles.is_some() {
+            if self
+                .get_user_permissions(&user, None)
+                .permits(&key_range, perm_type)
+            {
+                return Ok(());
+            }
+        } else if let Some(permissions) = self
+            .permission_cache
+            .read()
+            .user_permissions
+            .get(&claims.username)
+        {
+            if permissions.permits(&key_range, perm_type) {
+                return Ok(());
             }
         }
         Err(ExecuteError::PermissionDenied)
@@ -1195,9 +1411,7 @@ where
     /// Recover data from persistent storage
     pub(crate) fn recover(&self) -> Result<(), ExecuteError> {
         let enabled = self.backend.get_enable()?;
-        if enabled {
-            self.enabled.store(true, AtomicOrdering::Relaxed);
-        }
+        self.enabled.store(enabled, AtomicOrdering::Relaxed);
         let revision = self.backend.get_revision()?;
         self.revision.set(revision);
         self.create_permission_cache()?;
@@ -1205,6 +1419,11 @@ where
     }
 }
 
+/// Hash an API key, only the hashes of the keys are stored in the auth table
+pub(crate) fn hash_api_key(key: &str) -> Vec<u8> {
+    Sha256::digest(key.as_bytes()).to_vec()
+}
+
 #[cfg(test)]
 mod test {
     use std::collections::HashMap;
@@ -1386,6 +1605,139 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_scoped_tokens_are_restricted_to_their_scopes() -> Result<(), ExecuteError> {
+        let db = DBProxy::open(&StorageConfig::Memory)?;
+        let store = init_auth_store(db);
+        store.enabled.store(true, AtomicOrdering::Relaxed);
+        #[allow(clippy::as_conversions)] // This cast is always valid
+        let read_foo = Permission {
+            perm_type: Type::Read as i32,
+            key: b"foo".to_vec(),
+            range_end: vec![],
+        };
+        let authenticate = |scopes: Vec<Permission>, ttl| {
+            store.handle_authenticate_request(&AuthenticateRequest {
+                name: "u".to_owned(),
+                password: String::new(),
+                scopes,
+                ttl,
+                external_token: String::new(),
+                api_key: String::new(),
+            })
+        };
+
+        let claims = store.verify_token(&authenticate(vec![read_foo.clone()], 10)?.token)?;
+        assert!(store
+            .check_op_permission(&claims, b"foo", &[], Type::Read)
+            .is_ok());
+        assert!(store
+            .check_op_permission(&claims, b"foo", &[], Type::Write)
+            .is_err());
+        let claims = store.verify_token(&authenticate(vec![], 0)?.token)?;
+        assert!(claims.scope.is_none());
+        assert!(store
+            .check_op_permission(&claims, b"foo", &[], Type::Write)
+            .is_ok());
+
+        assert!(authenticate(vec![read_foo.clone()], -1).is_err());
+        assert!(authenticate(vec![read_foo.clone()], 301).is_err());
+        let unknown_type = Permission {
+            perm_type: 3,
+            ..read_foo
+        };
+        assert!(authenticate(vec![unknown_type], 10).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_external_identities_only_have_the_roles_of_their_tokens() -> Result<(), ExecuteError> {
+        let db = DBProxy::open(&StorageConfig::Memory)?;
+        let store = init_auth_store(db);
+        store.enabled.store(true, AtomicOrdering::Relaxed);
+        let authenticate = |username: &str, roles: Vec<String>| {
+            let res = store.assign_external(&AuthenticateRequest::default(), username, roles)?;
+            store.verify_token(&res.token)
+        };
+
+        let claims = authenticate("external:u", vec!["r".to_owned()])?;
+        assert!(store
+            .check_op_permission(&claims, b"foo", &[], Type::Write)
+            .is_ok());
+        assert!(store
+            .check_op_permission(&claims, b"bar", &[], Type::Read)
+            .is_err());
+        assert!(store.check_admin_permission(&claims).is_err());
+
+        // an external identity never takes the roles of the user with the same name
+        let claims = authenticate("u", vec![])?;
+        assert!(store
+            .check_op_permission(&claims, b"foo", &[], Type::Read)
+            .is_err());
+
+        let claims = authenticate("external:admin", vec![ROOT_ROLE.to_owned()])?;
+        assert!(store.check_admin_permission(&claims).is_ok());
+        assert!(store
+            .check_op_permission(&claims, b"bar", &[], Type::Write)
+            .is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_api_keys_are_checked_and_revoked() -> Result<(), ExecuteError> {
+        let db = DBProxy::open(&StorageConfig::Memory)?;
+        let store = init_auth_store(db);
+        let add_req = |id: &str, user: &str| {
+            RequestWithToken::new(
+                AuthApiKeyAddRequest {
+                    user: user.to_owned(),
+                    description: "bot".to_owned(),
+                    id: id.to_owned(),
+                    hashed_key: hash_api_key(&format!("{id}.secret")),
+                }
+                .into(),
+            )
+        };
+        assert!(exe_and_sync(&store, &add_req("k1", "u")).is_ok());
+        assert!(exe_and_sync(&store, &add_req("k2", "unknown")).is_err());
+        assert!(store.check_api_key("k1.secret").is_err());
+
+        store.enabled.store(true, AtomicOrdering::Relaxed);
+        let (user, revision) = store.check_api_key("k1.secret")?;
+        assert_eq!(user, "u");
+        assert_eq!(revision, store.revision());
+        assert!(store.check_api_key("k1.wrong").is_err());
+        assert!(store.check_api_key("k1").is_err());
+
+        let list = store.handle_api_key_list_request(&AuthApiKeyListRequest {
+            user: "u".to_owned(),
+        })?;
+        assert_eq!(list.keys.len(), 1);
+        assert!(list.keys[0].hashed_key.is_empty());
+
+        let revoke_req = RequestWithToken::new(
+            AuthApiKeyRevokeRequest {
+                id: "k1".to_owned(),
+            }
+            .into(),
+        );
+        assert!(exe_and_sync(&store, &revoke_req).is_ok());
+        assert!(store.check_api_key("k1.secret").is_err());
+        assert!(exe_and_sync(&store, &revoke_req).is_err());
+
+        // the keys of a user are revoked with the user
+        assert!(exe_and_sync(&store, &add_req("k3", "u")).is_ok());
+        let delete_req = RequestWithToken::new(
+            AuthUserDeleteRequest {
+                name: "u".to_owned(),
+            }
+            .into(),
+        );
+        assert!(exe_and_sync(&store, &delete_req).is_ok());
+        assert!(store.backend.get_all_api_keys()?.is_empty());
+        Ok(())
+    }
+
     fn init_auth_store(db: Arc<DBProxy>) -> AuthStore<DBProxy> {
         let store = init_empty_store(db);
         let req1 = RequestWithToken::new(
@@ -1446,7 +1798,13 @@ mod test {
         let key_pair = test_key_pair();
         let header_gen = Arc::new(HeaderGenerator::new(0, 0));
         let (lease_cmd_tx, _) = mpsc::channel(1);
-        AuthStore::new(lease_cmd_tx, key_pair, header_gen, db)
+        AuthStore::new(
+            lease_cmd_tx,
+            key_pair,
+            header_gen,
+            db,
+            Arc::new(NamespaceStore::new(vec![])),
+        )
     }
 
     fn exe_and_sync(
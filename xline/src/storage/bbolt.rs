@@ -0,0 +1,257 @@
+use super::snapshot::SnapshotError;
+
+/// Magic number of a meta page
+const MAGIC: u32 = 0xED0C_DAED;
+/// Version of the file format
+const VERSION: u32 = 2;
+/// Page size used if the first meta page is invalid
+const DEFAULT_PAGE_SIZE: usize = 4096;
+/// Size of a page header: id, flags, count and overflow
+const PAGE_HEADER_SIZE: usize = 16;
+/// Size of a branch or leaf page element
+const ELEMENT_SIZE: usize = 16;
+/// Size of a bucket header: root page id and sequence
+const BUCKET_HEADER_SIZE: usize = 16;
+/// Size of the meta fields covered by the checksum
+const META_SIZE: usize = 56;
+/// Flag of branch pages
+const BRANCH_PAGE_FLAG: u16 = 0x01;
+/// Flag of leaf pages
+const LEAF_PAGE_FLAG: u16 = 0x02;
+/// Flag of meta pages
+const META_PAGE_FLAG: u16 = 0x04;
+/// Flag of the leaf elements which are nested buckets
+const BUCKET_LEAF_FLAG: u32 = 0x01;
+/// Max depth of a B+ tree, deeper trees are considered corrupted
+const MAX_DEPTH: usize = 64;
+
+/// Read-only view of a bbolt database, which is the backend of etcd. The pages are
+/// read from the memory, the database must not be modified while it's read.
+#[derive(Debug)]
+pub(crate) struct Bolt<'a> {
+    /// Content of the database file
+    data: &'a [u8],
+    /// Size of a page
+    page_size: usize,
+    /// Root page of the root bucket
+    root: u64,
+}
+
+/// Root of a bucket
+#[derive(Debug, Clone, Copy)]
+enum BucketRoot<'a> {
+    /// The bucket is stored in its own pages
+    Page(u64),
+    /// The bucket is small enough to be stored in the value of its parent, the slice
+    /// is the inline page
+    Inline(&'a [u8]),
+}
+
+impl<'a> Bolt<'a> {
+    /// Open a database, the meta page with the largest transaction id is used
+    pub(crate) fn open(data: &'a [u8]) -> Result<Self, SnapshotError> {
+        let meta0 = Meta::read(data);
+        let page_size = meta0
+            .as_ref()
+            .map_or(DEFAULT_PAGE_SIZE, |meta| meta.page_size);
+        let meta1 = data.get(page_size..).and_then(Meta::read);
+        let meta = match (meta0, meta1) {
+            (Some(meta0), Some(meta1)) => {
+                if meta1.txid > meta0.txid {
+                    meta1
+                } else {
+                    meta0
+                }
+            }
+            (Some(meta), None) | (None, Some(meta)) => meta,
+            (None, None) => return Err(invalid("no valid meta page")),
+        };
+        Ok(Self {
+            data,
+            page_size: meta.page_size,
+            root: meta.root,
+        })
+    }
+
+    /// Visit the key-values of the bucket `name` in the root bucket in the order of the keys,
+    /// the nested buckets in it are skipped. Return `false` if the bucket doesn't exist.
+    pub(crate) fn visit_bucket<F>(&self, name: &[u8], mut on_kv: F) -> Result<bool, SnapshotError>
+    where
+        F: FnMut(&[u8], &[u8]) -> Result<(), SnapshotError>,
+    {
+        let mut bucket = None;
+        self.visit(
+            BucketRoot::Page(self.root),
+            0,
+            &mut |key, value, is_bucket| {
+                if is_bucket && key == name {
+                    bucket = Some(Self::bucket_root(value)?);
+                }
+                Ok(())
+            },
+        )?;
+        let root = match bucket {
+            Some(root) => root,
+            None => return Ok(false),
+        };
+        self.visit(root, 0, &mut |key, value, is_bucket| {
+            if is_bucket {
+                return Ok(());
+            }
+            on_kv(key, value)
+        })?;
+        Ok(true)
+    }
+
+    /// Get the root of a bucket from its value in the parent bucket
+    fn bucket_root(value: &'a [u8]) -> Result<BucketRoot<'a>, SnapshotError> {
+        let root = read_u64(value, 0)?;
+        if root == 0 {
+            let page = value
+                .get(BUCKET_HEADER_SIZE..)
+                .ok_or_else(|| invalid("truncated inline bucket"))?;
+            Ok(BucketRoot::Inline(page))
+        } else {
+            Ok(BucketRoot::Page(root))
+        }
+    }
+
+    /// Get the page `id` and its overflow pages
+    fn page(&self, id: u64) -> Result<&'a [u8], SnapshotError> {
+        let start = usize::try_from(id)
+            .ok()
+            .and_then(|id| id.checked_mul(self.page_size))
+            .ok_or_else(|| invalid(format!("page {id} is out of range")))?;
+        let header = slice(self.data, start, PAGE_HEADER_SIZE)?;
+        let overflow = usize::try_from(read_u32(header, 12)?)
+            .map_err(|_e| invalid(format!("page {id} is too large")))?;
+        let len = overflow
+            .checked_add(1)
+            .and_then(|pages| pages.checked_mul(self.page_size))
+            .ok_or_else(|| invalid(format!("page {id} is too large")))?;
+        slice(self.data, start, len)
+    }
+
+    /// Visit the elements of the tree rooted at `root` in the order of the keys
+    fn visit<F>(
+        &self,
+        root: BucketRoot<'a>,
+        depth: usize,
+        on_element: &mut F,
+    ) -> Result<(), SnapshotError>
+    where
+        F: FnMut(&'a [u8], &'a [u8], bool) -> Result<(), SnapshotError>,
+    {
+        if depth > MAX_DEPTH {
+            return Err(invalid("B+ tree is too deep"));
+        }
+        let page = match root {
+            BucketRoot::Page(id) => self.page(id)?,
+            BucketRoot::Inline(page) => page,
+        };
+        let flags = read_u16(page, 8)?;
+        let count = usize::from(read_u16(page, 10)?);
+        for i in 0..count {
+            let offset = i
+                .checked_mul(ELEMENT_SIZE)
+                .and_then(|offset| offset.checked_add(PAGE_HEADER_SIZE))
+                .ok_or_else(|| invalid("page element is out of range"))?;
+            let element = slice(page, offset, ELEMENT_SIZE)?;
+            if flags & LEAF_PAGE_FLAG != 0 {
+                let element_flags = read_u32(element, 0)?;
+                let key_offset = offset.checked_add(to_usize(read_u32(element, 4)?)?);
+                let key_size = to_usize(read_u32(element, 8)?)?;
+                let value_size = to_usize(read_u32(element, 12)?)?;
+                let key_offset = key_offset.ok_or_else(|| invalid("key is out of range"))?;
+                let key = slice(page, key_offset, key_size)?;
+                let value_offset = key_offset
+                    .checked_add(key_size)
+                    .ok_or_else(|| invalid("value is out of range"))?;
+                let value = slice(page, value_offset, value_size)?;
+                on_element(key, value, element_flags & BUCKET_LEAF_FLAG != 0)?;
+            } else if flags & BRANCH_PAGE_FLAG != 0 {
+                let child = read_u64(element, 8)?;
+                self.visit(BucketRoot::Page(child), depth.wrapping_add(1), on_element)?;
+            } else {
+                return Err(invalid(format!("unexpected page flags {flags:#x}")));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Meta page of the database
+#[derive(Debug)]
+struct Meta {
+    /// Size of a page
+    page_size: usize,
+    /// Root page of the root bucket
+    root: u64,
+    /// Id of the transaction which wrote the meta
+    txid: u64,
+}
+
+impl Meta {
+    /// Read a meta page, return `None` if it's invalid
+    fn read(page: &[u8]) -> Option<Self> {
+        let flags = read_u16(page, 8).ok()?;
+        let meta = page.get(PAGE_HEADER_SIZE..)?;
+        let fields = meta.get(..META_SIZE)?;
+        let checksum = read_u64(meta, META_SIZE).ok()?;
+        if flags & META_PAGE_FLAG == 0
+            || read_u32(meta, 0).ok()? != MAGIC
+            || read_u32(meta, 4).ok()? != VERSION
+            || fnv64a(fields) != checksum
+        {
+            return None;
+        }
+        Some(Self {
+            page_size: to_usize(read_u32(meta, 8).ok()?).ok()?,
+            root: read_u64(meta, 16).ok()?,
+            txid: read_u64(meta, 48).ok()?,
+        })
+    }
+}
+
+/// FNV-1a 64 bit hash, the checksum of the meta pages
+pub(crate) fn fnv64a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Get `len` bytes from `start`
+fn slice(data: &[u8], start: usize, len: usize) -> Result<&[u8], SnapshotError> {
+    start
+        .checked_add(len)
+        .and_then(|end| data.get(start..end))
+        .ok_or_else(|| invalid("page is out of range"))
+}
+
+/// Read a little endian `u16` at `offset`
+fn read_u16(data: &[u8], offset: usize) -> Result<u16, SnapshotError> {
+    let bytes = slice(data, offset, 2)?;
+    Ok(u16::from_le_bytes(bytes.try_into().unwrap_or_default()))
+}
+
+/// Read a little endian `u32` at `offset`
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, SnapshotError> {
+    let bytes = slice(data, offset, 4)?;
+    Ok(u32::from_le_bytes(bytes.try_into().unwrap_or_default()))
+}
+
+/// Read a little endian `u64` at `offset`
+fn read_u64(data: &[u8], offset: usize) -> Result<u64, SnapshotError> {
+    let bytes = slice(data, offset, 8)?;
+    Ok(u64::from_le_bytes(bytes.try_into().unwrap_or_default()))
+}
+
+/// Convert a size read from the database to `usize`
+fn to_usize(size: u32) -> Result<usize, SnapshotError> {
+    usize::try_from(size).map_err(|_e| invalid(format!("size {size} is too large")))
+}
+
+/// Error of a corrupted database
+fn invalid(msg: impl Into<String>) -> SnapshotError {
+    SnapshotError::InvalidSnapshot(format!("invalid bbolt database, {}", msg.into()))
+}
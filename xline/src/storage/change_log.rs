@@ -0,0 +1,334 @@
+use std::{
+    fs::File,
+    io::{BufReader, Read},
+    path::Path,
+};
+
+use engine::{
+    engine_api::StorageEngine, error::EngineError, rocksdb_engine::RocksEngine, WriteOperation,
+};
+use parking_lot::Mutex;
+use prost::Message;
+use tokio::sync::watch;
+
+use super::{
+    db::{ENGINE_TABLES, XLINE_TABLES},
+    snapshot::{self, SnapshotError},
+};
+use crate::{
+    rpc::{ChangeLogEntry, ChangeLogOp, OpType},
+    server::command::META_TABLE,
+};
+
+/// Table of the change log, the key of an entry is its index in big endian
+pub(crate) const CHANGE_LOG_TABLE: &str = "change_log";
+/// Key of the index of the last change log entry in the meta table
+pub(crate) const CHANGE_LOG_INDEX_KEY: &str = "change_log_index";
+
+/// Durable log of the operations flushed to the storage. Each flush is appended as an entry
+/// in the same batch as the operations, so the log never misses or duplicates a write.
+#[derive(Debug)]
+pub(crate) struct ChangeLog {
+    /// Index of the last entry, it's locked while appending to keep the indexes contiguous
+    last_index: Mutex<u64>,
+    /// Notifies the index of the last entry
+    last_index_tx: watch::Sender<u64>,
+}
+
+impl ChangeLog {
+    /// Recover the change log from the engine
+    pub(crate) fn recover<E: StorageEngine>(engine: &E) -> Result<Self, EngineError> {
+        let last_index = engine
+            .get(META_TABLE, CHANGE_LOG_INDEX_KEY)?
+            .map(|bytes| decode_index(&bytes))
+            .transpose()?
+            .unwrap_or(0);
+        let (last_index_tx, _rx) = watch::channel(last_index);
+        Ok(Self {
+            last_index: Mutex::new(last_index),
+            last_index_tx,
+        })
+    }
+
+    /// Write the operations and append them as a new entry
+    pub(crate) fn append<E: StorageEngine>(
+        &self,
+        engine: &E,
+        mut ops: Vec<WriteOperation>,
+        sync: bool,
+    ) -> Result<(), EngineError> {
+        let mut last_index = self.last_index.lock();
+        let index = last_index.wrapping_add(1);
+        let entry = ChangeLogEntry {
+            index,
+            ops: ops.iter().map(ChangeLogOp::from).collect(),
+        };
+        ops.push(WriteOperation::new_put(
+            CHANGE_LOG_TABLE,
+            index.to_be_bytes(),
+            entry.encode_to_vec(),
+        ));
+        ops.push(WriteOperation::new_put(
+            META_TABLE,
+            CHANGE_LOG_INDEX_KEY,
+            index.to_le_bytes(),
+        ));
+        engine.write_batch(ops, sync)?;
+        *last_index = index;
+        let _ignore = self.last_index_tx.send(index);
+        Ok(())
+    }
+
+    /// Read at most `limit` contiguous entries from `start`, the result is empty if the entry
+    /// `start` does not exist, either it's trimmed or not appended yet
+    pub(crate) fn read<E: StorageEngine>(
+        engine: &E,
+        start: u64,
+        limit: usize,
+    ) -> Result<Vec<ChangeLogEntry>, EngineError> {
+        let keys: Vec<_> = (start..).take(limit).map(u64::to_be_bytes).collect();
+        engine
+            .get_multi(CHANGE_LOG_TABLE, &keys)?
+            .into_iter()
+            .map_while(|value| value)
+            .map(|value| {
+                ChangeLogEntry::decode(value.as_slice())
+                    .map_err(|e| EngineError::UnderlyingError(format!("invalid change log: {e}")))
+            })
+            .collect()
+    }
+
+    /// Delete the entries before `end`
+    pub(crate) fn trim<E: StorageEngine>(engine: &E, end: u64) -> Result<(), EngineError> {
+        engine.write_batch(
+            vec![WriteOperation::new_delete_range(
+                CHANGE_LOG_TABLE,
+                0_u64.to_be_bytes(),
+                end.to_be_bytes(),
+            )],
+            true,
+        )
+    }
+
+    /// Subscribe to the index of the last entry
+    pub(crate) fn subscribe(&self) -> watch::Receiver<u64> {
+        self.last_index_tx.subscribe()
+    }
+}
+
+impl From<&WriteOperation> for ChangeLogOp {
+    #[inline]
+    fn from(op: &WriteOperation) -> Self {
+        match *op {
+            WriteOperation::Put {
+                table,
+                ref key,
+                ref value,
+            } => ChangeLogOp {
+                r#type: OpType::Put.into(),
+                table: table.to_owned(),
+                key: key.clone(),
+                value: value.clone(),
+                range_end: vec![],
+            },
+            WriteOperation::Delete { table, ref key } => ChangeLogOp {
+                r#type: OpType::Delete.into(),
+                table: table.to_owned(),
+                key: key.clone(),
+                value: vec![],
+                range_end: vec![],
+            },
+            WriteOperation::DeleteRange {
+                table,
+                ref from,
+                ref to,
+            } => ChangeLogOp {
+                r#type: OpType::DeleteRange.into(),
+                table: table.to_owned(),
+                key: from.clone(),
+                value: vec![],
+                range_end: to.clone(),
+            },
+            _ => unreachable!("unknown write operation {op:?}"),
+        }
+    }
+}
+
+/// Apply the entries of a shipped change log file to a restored snapshot, return the number
+/// of applied entries. The file is a sequence of encoded `ChangeLogEntry`s, each of them is
+/// prefixed by its length in 4 bytes big endian. Entries which are already in the snapshot
+/// are skipped, the applied entries must follow the snapshot without a gap.
+///
+/// # Errors
+///
+/// Return `SnapshotError` if the file is invalid, the entries don't follow the snapshot or
+/// failed to write the engine
+#[inline]
+pub fn apply<E, R>(engine: &E, mut reader: R) -> Result<u64, SnapshotError>
+where
+    E: StorageEngine,
+    R: Read,
+{
+    let mut last_index = engine
+        .get(META_TABLE, CHANGE_LOG_INDEX_KEY)?
+        .map(|bytes| decode_index(&bytes))
+        .transpose()?
+        .unwrap_or(0);
+    let mut count: u64 = 0;
+    while let Some(field) = snapshot::read_optional_field(&mut reader)? {
+        let entry = ChangeLogEntry::decode(field.as_slice()).map_err(|e| {
+            SnapshotError::InvalidSnapshot(format!("invalid change log entry: {e}"))
+        })?;
+        if entry.index <= last_index {
+            continue;
+        }
+        if entry.index != last_index.wrapping_add(1) {
+            return Err(SnapshotError::InvalidSnapshot(format!(
+                "change log entry {} does not follow entry {last_index}",
+                entry.index
+            )));
+        }
+        let mut ops = entry
+            .ops
+            .into_iter()
+            .map(to_write_operation)
+            .collect::<Result<Vec<_>, _>>()?;
+        ops.push(WriteOperation::new_put(
+            META_TABLE,
+            CHANGE_LOG_INDEX_KEY,
+            entry.index.to_le_bytes(),
+        ));
+        engine.write_batch(ops, false)?;
+        last_index = entry.index;
+        count = count.saturating_add(1);
+    }
+    engine.write_batch(vec![], true)?;
+    Ok(count)
+}
+
+/// Apply the shipped change log file at `path` to the data dir restored from a snapshot,
+/// return the number of applied entries. The server using `data_dir` must be stopped.
+///
+/// # Errors
+///
+/// Return `SnapshotError` if failed to open the database, the file is invalid or the entries
+/// don't follow the snapshot
+#[inline]
+pub fn apply_file(path: &Path, data_dir: &Path) -> Result<u64, SnapshotError> {
+    let reader = BufReader::new(File::open(path)?);
+    let engine = RocksEngine::new(data_dir, &ENGINE_TABLES)?;
+    apply(&engine, reader)
+}
+
+/// Convert a change log operation back to a write operation
+fn to_write_operation(op: ChangeLogOp) -> Result<WriteOperation, SnapshotError> {
+    let table = XLINE_TABLES
+        .into_iter()
+        .find(|t| *t == op.table)
+        .ok_or_else(|| {
+            SnapshotError::InvalidSnapshot(format!("unknown table {:?} in change log", op.table))
+        })?;
+    match OpType::from_i32(op.r#type) {
+        Some(OpType::Put) => Ok(WriteOperation::new_put(table, op.key, op.value)),
+        Some(OpType::Delete) => Ok(WriteOperation::new_delete(table, op.key)),
+        Some(OpType::DeleteRange) => Ok(WriteOperation::new_delete_range(
+            table,
+            op.key,
+            op.range_end,
+        )),
+        None => Err(SnapshotError::InvalidSnapshot(format!(
+            "unknown operation type {} in change log",
+            op.r#type
+        ))),
+    }
+}
+
+/// Decode the index of the last entry stored in the meta table
+fn decode_index(bytes: &[u8]) -> Result<u64, EngineError> {
+    bytes
+        .try_into()
+        .map(u64::from_le_bytes)
+        .map_err(|_e| EngineError::UnderlyingError("invalid change log index".to_owned()))
+}
+
+#[cfg(test)]
+mod test {
+    use engine::memory_engine::MemoryEngine;
+
+    use super::*;
+    use crate::storage::{db::ENGINE_TABLES, kv_store::KV_TABLE};
+
+    #[test]
+    fn test_append_read_and_trim() -> Result<(), EngineError> {
+        let engine = MemoryEngine::new(&ENGINE_TABLES)?;
+        let change_log = ChangeLog::recover(&engine)?;
+        let mut index_rx = change_log.subscribe();
+        for i in 0..3_u8 {
+            change_log.append(
+                &engine,
+                vec![WriteOperation::new_put(KV_TABLE, [i], [i])],
+                false,
+            )?;
+        }
+        assert_eq!(*index_rx.borrow_and_update(), 3);
+        assert_eq!(ChangeLog::recover(&engine)?.subscribe().borrow().clone(), 3);
+
+        let entries = ChangeLog::read(&engine, 2, 10)?;
+        assert_eq!(
+            entries.iter().map(|e| e.index).collect::<Vec<_>>(),
+            vec![2, 3]
+        );
+        assert_eq!(entries[0].ops[0].key, vec![1]);
+
+        ChangeLog::trim(&engine, 3)?;
+        assert!(ChangeLog::read(&engine, 1, 10)?.is_empty());
+        assert_eq!(ChangeLog::read(&engine, 3, 10)?.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_shipped_entries() -> Result<(), SnapshotError> {
+        let engine = MemoryEngine::new(&ENGINE_TABLES)?;
+        let change_log = ChangeLog::recover(&engine)?;
+        change_log.append(
+            &engine,
+            vec![WriteOperation::new_put(KV_TABLE, "key1", "value1")],
+            false,
+        )?;
+        let mut snapshot = Vec::new();
+        let _count = snapshot::save(&engine, &mut snapshot)?;
+        change_log.append(
+            &engine,
+            vec![
+                WriteOperation::new_put(KV_TABLE, "key2", "value2"),
+                WriteOperation::new_delete(KV_TABLE, "key1"),
+            ],
+            false,
+        )?;
+        let mut shipped = Vec::new();
+        for entry in ChangeLog::read(&engine, 1, 10)? {
+            snapshot::write_field(&mut shipped, &entry.encode_to_vec())?;
+        }
+
+        let restored = MemoryEngine::new(&ENGINE_TABLES)?;
+        let _count = snapshot::restore(&restored, snapshot.as_slice())?;
+        // the first entry is in the snapshot already
+        assert_eq!(apply(&restored, shipped.as_slice())?, 1);
+        assert_eq!(
+            restored.get_all(KV_TABLE)?,
+            vec![(b"key2".to_vec(), b"value2".to_vec())]
+        );
+
+        let mut gap = Vec::new();
+        let entry = ChangeLogEntry {
+            index: 5,
+            ops: vec![],
+        };
+        snapshot::write_field(&mut gap, &entry.encode_to_vec())?;
+        assert!(matches!(
+            apply(&restored, gap.as_slice()),
+            Err(SnapshotError::InvalidSnapshot(_))
+        ));
+        Ok(())
+    }
+}
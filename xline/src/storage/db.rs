@@ -1,35 +1,75 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::HashMap, fs::File, io::BufReader, path::Path, sync::Arc};
 
 use curp::cmd::ProposeId;
 use engine::{
-    engine_api::StorageEngine, memory_engine::MemoryEngine, rocksdb_engine::RocksEngine,
-    WriteOperation,
+    engine_api::StorageEngine,
+    error::EngineError,
+    memory_engine::MemoryEngine,
+    rocksdb_engine::{Compression, EngineOptions, RocksEngine, TableOptions},
+    TableSize, WriteOperation,
 };
 use parking_lot::Mutex;
 use prost::Message;
-use utils::config::StorageConfig;
+use tokio::sync::watch;
+use tracing::instrument;
+use utils::config::{BackendConfig, StorageConfig, SyncPolicy, TableCompression, TableConfig};
 
 use crate::{
-    rpc::{PbLease, Role, User},
+    rpc::{ApiKey, ChangeLogEntry, PbLease, Role, User},
     server::command::{APPLIED_INDEX_KEY, META_TABLE},
 };
 
 use super::{
-    auth_store::{AUTH_ENABLE_KEY, AUTH_REVISION_KEY, AUTH_TABLE, ROLE_TABLE, USER_TABLE},
-    kv_store::KV_TABLE,
+    applied_ids::APPLIED_ID_TABLE,
+    auth_store::{
+        API_KEY_PREFIX, AUTH_ENABLE_KEY, AUTH_REVISION_KEY, AUTH_TABLE, ROLE_TABLE, USER_TABLE,
+    },
+    change_log::{ChangeLog, CHANGE_LOG_TABLE},
+    durable_watch::DURABLE_WATCH_TABLE,
+    index_snapshot::INDEX_SNAPSHOT_KEY,
+    integrity::CHECKSUMS_KEY,
+    kv_store::{COMPACT_REVISION_KEY, KV_TABLE},
     lease_store::LEASE_TABLE,
+    member_identity::MEMBER_IDENTITY_KEY,
+    member_store::MEMBERS_KEY,
+    migration::STORAGE_VERSION_KEY,
+    quota::{QuotaUsage, QUOTA_TABLE},
+    revision_time::REVISION_TIME_TABLE,
+    snapshot,
     storage_api::StorageApi,
+    trash::TRASH_TABLE,
     ExecuteError, Revision,
 };
 
 /// Xline Server Storage Table
-const XLINE_TABLES: [&str; 6] = [
+pub(crate) const XLINE_TABLES: [&str; 11] = [
     META_TABLE,
+    APPLIED_ID_TABLE,
     KV_TABLE,
     LEASE_TABLE,
     AUTH_TABLE,
     USER_TABLE,
     ROLE_TABLE,
+    QUOTA_TABLE,
+    TRASH_TABLE,
+    REVISION_TIME_TABLE,
+    DURABLE_WATCH_TABLE,
+];
+
+/// Tables opened by the engine, the change log is not a part of the snapshot
+pub(crate) const ENGINE_TABLES: [&str; 12] = [
+    META_TABLE,
+    APPLIED_ID_TABLE,
+    KV_TABLE,
+    LEASE_TABLE,
+    AUTH_TABLE,
+    USER_TABLE,
+    ROLE_TABLE,
+    QUOTA_TABLE,
+    TRASH_TABLE,
+    REVISION_TIME_TABLE,
+    DURABLE_WATCH_TABLE,
+    CHANGE_LOG_TABLE,
 ];
 
 /// Database to store revision to kv mapping
@@ -39,6 +79,11 @@ pub struct DB<S: StorageEngine> {
     engine: Arc<S>,
     /// Buffer
     buffer: Mutex<HashMap<ProposeId, Vec<WriteOp>>>,
+    /// Log of the flushed operations, `None` if the change log is disabled
+    change_log: Option<ChangeLog>,
+    /// Sync every flush to the disk, otherwise the flushed operations are buffered by the OS
+    /// until the next sync
+    sync_on_flush: bool,
 }
 
 impl<S> DB<S>
@@ -52,8 +97,51 @@ where
         Self {
             engine: Arc::new(engine),
             buffer: Mutex::new(HashMap::new()),
+            change_log: None,
+            sync_on_flush: false,
         }
     }
+
+    /// New `DB` which logs the flushed operations to the change log
+    ///
+    /// # Errors
+    ///
+    /// Return `ExecuteError::DbError` if failed to recover the change log
+    #[inline]
+    pub fn with_change_log(engine: S) -> Result<Self, ExecuteError> {
+        let change_log = ChangeLog::recover(&engine).map_err(|e| {
+            ExecuteError::DbError(format!("Failed to recover change log, error: {e}"))
+        })?;
+        Ok(Self {
+            engine: Arc::new(engine),
+            buffer: Mutex::new(HashMap::new()),
+            change_log: Some(change_log),
+            sync_on_flush: false,
+        })
+    }
+
+    /// Sync every flush to the disk if `sync_on_flush` is true
+    #[inline]
+    #[must_use]
+    pub fn with_sync_on_flush(mut self, sync_on_flush: bool) -> Self {
+        self.sync_on_flush = sync_on_flush;
+        self
+    }
+
+    /// Write a batch of operations, they are logged if the change log is enabled
+    fn write_batch(&self, ops: Vec<WriteOperation>, sync: bool) -> Result<(), EngineError> {
+        match self.change_log {
+            Some(ref change_log) => change_log.append(self.engine.as_ref(), ops, sync),
+            None => self.engine.write_batch(ops, sync),
+        }
+    }
+
+    /// Get the change log, return an error if it's disabled
+    fn enabled_change_log(&self) -> Result<&ChangeLog, ExecuteError> {
+        self.change_log
+            .as_ref()
+            .ok_or_else(|| ExecuteError::DbError("Change log is disabled".to_owned()))
+    }
 }
 
 impl<S> StorageApi for DB<S>
@@ -95,6 +183,37 @@ where
         })
     }
 
+    fn scan<F>(&self, table: &'static str, visitor: F) -> Result<(), ExecuteError>
+    where
+        F: FnMut(&[u8], &[u8]) -> Result<(), ExecuteError>,
+    {
+        self.scan_from(table, &[], visitor)
+    }
+
+    fn scan_from<F>(
+        &self,
+        table: &'static str,
+        from: &[u8],
+        mut visitor: F,
+    ) -> Result<(), ExecuteError>
+    where
+        F: FnMut(&[u8], &[u8]) -> Result<(), ExecuteError>,
+    {
+        // the error of the visitor is kept here, the engine only sees it as an abort
+        let mut visitor_err = None;
+        let res = self.engine.visit_from(table, from, |key, value| {
+            visitor(key, value).map_err(|e| {
+                let msg = e.to_string();
+                visitor_err = Some(e);
+                EngineError::UnderlyingError(msg)
+            })
+        });
+        if let Some(e) = visitor_err {
+            return Err(e);
+        }
+        res.map_err(|e| ExecuteError::DbError(format!("Failed to scan {table:?}: {e}")))
+    }
+
     fn reset(&self) -> Result<(), ExecuteError> {
         let start = vec![];
         let end = vec![0xff];
@@ -102,8 +221,7 @@ where
             .iter()
             .map(|table| WriteOperation::new_delete_range(table, start.as_slice(), end.as_slice()))
             .collect();
-        self.engine
-            .write_batch(ops, true)
+        self.write_batch(ops, true)
             .map_err(|e| ExecuteError::DbError(format!("Failed to reset database, error: {e}")))
     }
 
@@ -116,15 +234,90 @@ where
         }
     }
 
+    #[instrument(skip(self))]
     fn flush(&self, id: &ProposeId) -> Result<(), ExecuteError> {
+        fail::fail_point!("db_before_flush", |_| Err(ExecuteError::DbError(
+            "Failed to flush ops, error: failpoint db_before_flush".to_owned()
+        )));
         if let Some(ops) = self.buffer.lock().remove(id) {
             let wr_ops = ops.into_iter().map(WriteOperation::from).collect();
-            self.engine
-                .write_batch(wr_ops, false)
+            self.write_batch(wr_ops, self.sync_on_flush)
                 .map_err(|e| ExecuteError::DbError(format!("Failed to flush ops, error: {e}")))?;
         }
+        fail::fail_point!("db_after_flush");
         Ok(())
     }
+
+    fn sync(&self) -> Result<(), ExecuteError> {
+        // ops of unfinished commands are left in the buffer, they will be re-executed
+        // after recovery because their applied index has not been flushed either
+        self.engine
+            .write_batch(vec![], true)
+            .map_err(|e| ExecuteError::DbError(format!("Failed to sync database, error: {e}")))
+    }
+
+    fn snapshot<W>(&self, writer: W) -> Result<u64, ExecuteError>
+    where
+        W: std::io::Write,
+    {
+        snapshot::save(self.engine.as_ref(), writer)
+            .map_err(|e| ExecuteError::DbError(format!("Failed to save snapshot, error: {e}")))
+    }
+
+    fn install_snapshot(&self, path: &Path, applied_index: u64) -> Result<(), ExecuteError> {
+        // verify first so that a corrupted snapshot will not wipe the storage
+        let _entries = snapshot::verify_file(path)
+            .map_err(|e| ExecuteError::DbError(format!("Invalid snapshot, error: {e}")))?;
+        let reader = File::open(path)
+            .map(BufReader::new)
+            .map_err(|e| ExecuteError::DbError(format!("Failed to open snapshot, error: {e}")))?;
+        // the snapshot is taken by another member, this member keeps its own identity
+        let identity = self.get_value(META_TABLE, MEMBER_IDENTITY_KEY)?;
+        self.reset()?;
+        fail::fail_point!("db_install_snapshot_after_reset");
+        let _restored = snapshot::restore(self.engine.as_ref(), reader).map_err(|e| {
+            ExecuteError::DbError(format!("Failed to install snapshot, error: {e}"))
+        })?;
+        let mut ops = vec![WriteOp::PutAppliedIndex(applied_index).into()];
+        if let Some(identity) = identity {
+            ops.push(WriteOp::PutMemberIdentity(identity).into());
+        }
+        self.engine
+            .write_batch(ops, true)
+            .map_err(|e| ExecuteError::DbError(format!("Failed to install snapshot, error: {e}")))
+    }
+
+    fn read_change_log(
+        &self,
+        start: u64,
+        limit: usize,
+    ) -> Result<Vec<ChangeLogEntry>, ExecuteError> {
+        let _change_log = self.enabled_change_log()?;
+        ChangeLog::read(self.engine.as_ref(), start, limit)
+            .map_err(|e| ExecuteError::DbError(format!("Failed to read change log, error: {e}")))
+    }
+
+    fn trim_change_log(&self, end: u64) -> Result<(), ExecuteError> {
+        let _change_log = self.enabled_change_log()?;
+        ChangeLog::trim(self.engine.as_ref(), end)
+            .map_err(|e| ExecuteError::DbError(format!("Failed to trim change log, error: {e}")))
+    }
+
+    fn subscribe_change_log(&self) -> Option<watch::Receiver<u64>> {
+        self.change_log.as_ref().map(ChangeLog::subscribe)
+    }
+
+    fn table_sizes(&self) -> Result<Vec<(&'static str, TableSize)>, ExecuteError> {
+        ENGINE_TABLES
+            .iter()
+            .map(|&table| {
+                let size = self.engine.table_size(table).map_err(|e| {
+                    ExecuteError::DbError(format!("Failed to get the size of {table}: {e}"))
+                })?;
+                Ok((table, size))
+            })
+            .collect()
+    }
 }
 
 /// `DBProxy` is designed to mask the different type of `DB<MemoryEngine>` and `DB<RocksEngine>`
@@ -179,6 +372,26 @@ impl StorageApi for DBProxy {
         }
     }
 
+    fn scan<F>(&self, table: &'static str, visitor: F) -> Result<(), ExecuteError>
+    where
+        F: FnMut(&[u8], &[u8]) -> Result<(), ExecuteError>,
+    {
+        match *self {
+            DBProxy::MemDB(ref inner_db) => inner_db.scan(table, visitor),
+            DBProxy::RocksDB(ref inner_db) => inner_db.scan(table, visitor),
+        }
+    }
+
+    fn scan_from<F>(&self, table: &'static str, from: &[u8], visitor: F) -> Result<(), ExecuteError>
+    where
+        F: FnMut(&[u8], &[u8]) -> Result<(), ExecuteError>,
+    {
+        match *self {
+            DBProxy::MemDB(ref inner_db) => inner_db.scan_from(table, from, visitor),
+            DBProxy::RocksDB(ref inner_db) => inner_db.scan_from(table, from, visitor),
+        }
+    }
+
     fn reset(&self) -> Result<(), ExecuteError> {
         match *self {
             DBProxy::MemDB(ref inner_db) => inner_db.reset(),
@@ -199,6 +412,62 @@ impl StorageApi for DBProxy {
             DBProxy::RocksDB(ref inner_db) => inner_db.flush(id),
         }
     }
+
+    fn sync(&self) -> Result<(), ExecuteError> {
+        match *self {
+            DBProxy::MemDB(ref inner_db) => inner_db.sync(),
+            DBProxy::RocksDB(ref inner_db) => inner_db.sync(),
+        }
+    }
+
+    fn snapshot<W>(&self, writer: W) -> Result<u64, ExecuteError>
+    where
+        W: std::io::Write,
+    {
+        match *self {
+            DBProxy::MemDB(ref inner_db) => inner_db.snapshot(writer),
+            DBProxy::RocksDB(ref inner_db) => inner_db.snapshot(writer),
+        }
+    }
+
+    fn install_snapshot(&self, path: &Path, applied_index: u64) -> Result<(), ExecuteError> {
+        match *self {
+            DBProxy::MemDB(ref inner_db) => inner_db.install_snapshot(path, applied_index),
+            DBProxy::RocksDB(ref inner_db) => inner_db.install_snapshot(path, applied_index),
+        }
+    }
+
+    fn read_change_log(
+        &self,
+        start: u64,
+        limit: usize,
+    ) -> Result<Vec<ChangeLogEntry>, ExecuteError> {
+        match *self {
+            DBProxy::MemDB(ref inner_db) => inner_db.read_change_log(start, limit),
+            DBProxy::RocksDB(ref inner_db) => inner_db.read_change_log(start, limit),
+        }
+    }
+
+    fn trim_change_log(&self, end: u64) -> Result<(), ExecuteError> {
+        match *self {
+            DBProxy::MemDB(ref inner_db) => inner_db.trim_change_log(end),
+            DBProxy::RocksDB(ref inner_db) => inner_db.trim_change_log(end),
+        }
+    }
+
+    fn subscribe_change_log(&self) -> Option<watch::Receiver<u64>> {
+        match *self {
+            DBProxy::MemDB(ref inner_db) => inner_db.subscribe_change_log(),
+            DBProxy::RocksDB(ref inner_db) => inner_db.subscribe_change_log(),
+        }
+    }
+
+    fn table_sizes(&self) -> Result<Vec<(&'static str, TableSize)>, ExecuteError> {
+        match *self {
+            DBProxy::MemDB(ref inner_db) => inner_db.table_sizes(),
+            DBProxy::RocksDB(ref inner_db) => inner_db.table_sizes(),
+        }
+    }
 }
 
 impl DBProxy {
@@ -209,30 +478,133 @@ impl DBProxy {
     /// Return `ExecuteError::DbError` when open db failed
     #[inline]
     pub fn open(config: &StorageConfig) -> Result<Arc<DBProxy>, ExecuteError> {
+        Self::open_with_change_log(config, false)
+    }
+
+    /// Create a new `DBProxy`, the flushed operations are logged to the change log if
+    /// `change_log` is true
+    ///
+    /// # Errors
+    ///
+    /// Return `ExecuteError::DbError` when open db failed
+    #[inline]
+    pub fn open_with_change_log(
+        config: &StorageConfig,
+        change_log: bool,
+    ) -> Result<Arc<DBProxy>, ExecuteError> {
+        Self::open_with_backend(config, &BackendConfig::default(), change_log)
+    }
+
+    /// Create a new `DBProxy` whose engine is tuned by `backend` and whose flushes are synced
+    /// by its sync policy, the flushed operations are logged to the change log if
+    /// `change_log` is true
+    ///
+    /// # Errors
+    ///
+    /// Return `ExecuteError::DbError` when open db failed
+    #[inline]
+    pub fn open_with_backend(
+        config: &StorageConfig,
+        backend: &BackendConfig,
+        change_log: bool,
+    ) -> Result<Arc<DBProxy>, ExecuteError> {
         match *config {
             StorageConfig::Memory => {
-                let engine = MemoryEngine::new(&XLINE_TABLES)
+                let engine = MemoryEngine::new(&ENGINE_TABLES)
                     .map_err(|e| ExecuteError::DbError(format!("Cannot open database: {e}")))?;
-                Ok(Arc::new(DBProxy::MemDB(DB::new(engine))))
+                let db = open_db(engine, backend, change_log)?;
+                Ok(Arc::new(DBProxy::MemDB(db)))
             }
-            StorageConfig::RocksDB(ref path) => {
-                let engine = RocksEngine::new(path, &XLINE_TABLES)
+            StorageConfig::MemoryFile(ref path) => {
+                let engine = MemoryEngine::open(path, &ENGINE_TABLES)
                     .map_err(|e| ExecuteError::DbError(format!("Cannot open database: {e}")))?;
-                Ok(Arc::new(DBProxy::RocksDB(DB::new(engine))))
+                let db = open_db(engine, backend, change_log)?;
+                Ok(Arc::new(DBProxy::MemDB(db)))
+            }
+            StorageConfig::RocksDB(ref path) => {
+                let engine = RocksEngine::with_options(
+                    path,
+                    &ENGINE_TABLES,
+                    EngineOptions::new(
+                        *backend.block_cache_size(),
+                        *backend.direct_io(),
+                        *backend.compaction_rate_limit(),
+                    ),
+                    &table_options(backend),
+                )
+                .map_err(|e| ExecuteError::DbError(format!("Cannot open database: {e}")))?;
+                let db = open_db(engine, backend, change_log)?;
+                Ok(Arc::new(DBProxy::RocksDB(db)))
             }
             _ => unreachable!(),
         }
     }
 }
 
+/// Open a `DB` on `engine` whose flushes are synced by the sync policy of `backend`
+fn open_db<E: StorageEngine>(
+    engine: E,
+    backend: &BackendConfig,
+    change_log: bool,
+) -> Result<DB<E>, ExecuteError> {
+    let db = if change_log {
+        DB::with_change_log(engine)?
+    } else {
+        DB::new(engine)
+    };
+    Ok(db.with_sync_on_flush(*backend.sync() == SyncPolicy::Always))
+}
+
+/// Get the options of the tables tuned by the backend config
+fn table_options(backend: &BackendConfig) -> Vec<(&'static str, TableOptions)> {
+    let options = |config: &TableConfig| {
+        let compression = match *config.compression() {
+            TableCompression::None => Compression::None,
+            TableCompression::Snappy => Compression::Snappy,
+            TableCompression::Lz4 => Compression::Lz4,
+            TableCompression::Zstd => Compression::Zstd,
+            _ => unreachable!("unknown table compression"),
+        };
+        TableOptions::new(
+            *config.block_cache_size(),
+            compression,
+            *config.write_buffer_size(),
+            *config.bloom_filter_bits(),
+        )
+    };
+    vec![
+        (KV_TABLE, options(backend.kv())),
+        (LEASE_TABLE, options(backend.lease())),
+        (AUTH_TABLE, options(backend.auth())),
+        (USER_TABLE, options(backend.auth())),
+        (ROLE_TABLE, options(backend.auth())),
+    ]
+}
+
 /// Buffered Write Operation
 #[derive(Debug, Clone)]
 #[non_exhaustive]
 pub enum WriteOp {
     /// Put a key-value pair to kv table
     PutKeyValue(Revision, Vec<u8>),
+    /// Delete a compacted key-value pair from kv table
+    DeleteKeyValue(Revision),
+    /// Put the compacted revision of kv table to meta table
+    PutCompactRevision(i64),
     /// Put the applied index to meta table
     PutAppliedIndex(u64),
+    /// Put the version of the on-disk format to meta table
+    PutStorageVersion(u64),
+    /// Put the encoded identity of the member to meta table
+    PutMemberIdentity(Vec<u8>),
+    /// Put the encoded members updated by `MemberUpdate` to meta table
+    PutMembers(Vec<u8>),
+    /// Put the encoded index snapshot to meta table
+    PutIndexSnapshot(Vec<u8>),
+    /// Put the propose id of a log entry to applied id table
+    PutAppliedId(u64, ProposeId),
+    /// Delete the propose id of a log entry from applied id table
+    DeleteAppliedId(u64),
     /// Put a lease to lease table
     PutLease(PbLease),
     /// Delete a lease from lease table
@@ -249,6 +621,30 @@ pub enum WriteOp {
     PutRole(Role),
     /// Delete a role from role table
     DeleteRole(String),
+    /// Put an API key to auth table
+    PutApiKey(ApiKey),
+    /// Delete an API key from auth table
+    DeleteApiKey(String),
+    /// Put the write quota usage of a user to quota table
+    PutQuotaUsage(String, QuotaUsage),
+    /// Delete the write quota usage of a user from quota table
+    DeleteQuotaUsage(String),
+    /// Put the encoded table checksums to meta table
+    PutChecksums(Vec<u8>),
+    /// Delete the table checksums from meta table
+    DeleteChecksums,
+    /// Put an encoded entry to trash table
+    PutTrash(Vec<u8>, Vec<u8>),
+    /// Delete an entry from trash table
+    DeleteTrash(Vec<u8>),
+    /// Put the first revision applied in a second to revision time table
+    PutRevisionTime(u64, i64),
+    /// Delete the entry of a second from revision time table
+    DeleteRevisionTime(u64),
+    /// Put the registration of a durable watcher to durable watch table
+    PutDurableWatch(String, Vec<u8>),
+    /// Delete the registration of a durable watcher from durable watch table
+    DeleteDurableWatch(String),
 }
 
 impl From<WriteOp> for WriteOperation {
@@ -259,9 +655,33 @@ impl From<WriteOp> for WriteOperation {
                 let key = rev.encode_to_vec();
                 WriteOperation::new_put(KV_TABLE, key, value)
             }
+            WriteOp::DeleteKeyValue(rev) => {
+                WriteOperation::new_delete(KV_TABLE, rev.encode_to_vec())
+            }
+            WriteOp::PutCompactRevision(rev) => {
+                WriteOperation::new_put(META_TABLE, COMPACT_REVISION_KEY, rev.to_le_bytes())
+            }
             WriteOp::PutAppliedIndex(index) => {
                 WriteOperation::new_put(META_TABLE, APPLIED_INDEX_KEY, index.to_le_bytes())
             }
+            WriteOp::PutStorageVersion(version) => {
+                WriteOperation::new_put(META_TABLE, STORAGE_VERSION_KEY, version.to_le_bytes())
+            }
+            WriteOp::PutMemberIdentity(identity) => {
+                WriteOperation::new_put(META_TABLE, MEMBER_IDENTITY_KEY, identity)
+            }
+            WriteOp::PutMembers(members) => {
+                WriteOperation::new_put(META_TABLE, MEMBERS_KEY, members)
+            }
+            WriteOp::PutIndexSnapshot(snapshot) => {
+                WriteOperation::new_put(META_TABLE, INDEX_SNAPSHOT_KEY, snapshot)
+            }
+            WriteOp::PutAppliedId(index, id) => {
+                WriteOperation::new_put(APPLIED_ID_TABLE, index.to_be_bytes(), id.as_str())
+            }
+            WriteOp::DeleteAppliedId(index) => {
+                WriteOperation::new_delete(APPLIED_ID_TABLE, index.to_be_bytes())
+            }
             WriteOp::PutLease(lease) => WriteOperation::new_put(
                 LEASE_TABLE,
                 lease.id.encode_to_vec(),
@@ -284,6 +704,41 @@ impl From<WriteOp> for WriteOperation {
                 WriteOperation::new_put(ROLE_TABLE, role.name, value)
             }
             WriteOp::DeleteRole(name) => WriteOperation::new_delete(ROLE_TABLE, name),
+            WriteOp::PutApiKey(api_key) => {
+                let value = api_key.encode_to_vec();
+                WriteOperation::new_put(
+                    AUTH_TABLE,
+                    format!("{API_KEY_PREFIX}{}", api_key.id),
+                    value,
+                )
+            }
+            WriteOp::DeleteApiKey(id) => {
+                WriteOperation::new_delete(AUTH_TABLE, format!("{API_KEY_PREFIX}{id}"))
+            }
+            WriteOp::PutQuotaUsage(user, usage) => {
+                WriteOperation::new_put(QUOTA_TABLE, user, usage.encode())
+            }
+            WriteOp::DeleteQuotaUsage(user) => WriteOperation::new_delete(QUOTA_TABLE, user),
+            WriteOp::PutChecksums(checksums) => {
+                WriteOperation::new_put(META_TABLE, CHECKSUMS_KEY, checksums)
+            }
+            WriteOp::DeleteChecksums => WriteOperation::new_delete(META_TABLE, CHECKSUMS_KEY),
+            WriteOp::PutTrash(key, value) => WriteOperation::new_put(TRASH_TABLE, key, value),
+            WriteOp::DeleteTrash(key) => WriteOperation::new_delete(TRASH_TABLE, key),
+            WriteOp::PutRevisionTime(secs, revision) => WriteOperation::new_put(
+                REVISION_TIME_TABLE,
+                secs.to_be_bytes().to_vec(),
+                revision.to_be_bytes().to_vec(),
+            ),
+            WriteOp::DeleteRevisionTime(secs) => {
+                WriteOperation::new_delete(REVISION_TIME_TABLE, secs.to_be_bytes().to_vec())
+            }
+            WriteOp::PutDurableWatch(token, value) => {
+                WriteOperation::new_put(DURABLE_WATCH_TABLE, token, value)
+            }
+            WriteOp::DeleteDurableWatch(token) => {
+                WriteOperation::new_delete(DURABLE_WATCH_TABLE, token)
+            }
         }
     }
 }
@@ -315,4 +770,63 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn test_scan() -> Result<(), ExecuteError> {
+        let db = DBProxy::open(&StorageConfig::Memory)?;
+        let id = ProposeId::new("test-id".to_owned());
+        for rev in 1..=3 {
+            db.buffer_op(&id, WriteOp::PutKeyValue(Revision::new(rev, 0), vec![1]));
+        }
+        db.flush(&id)?;
+
+        let mut revisions = vec![];
+        db.scan(KV_TABLE, |key, _value| {
+            revisions.push(Revision::decode(key).revision());
+            Ok(())
+        })?;
+        assert_eq!(revisions, vec![1, 2, 3]);
+
+        let res = db.scan(KV_TABLE, |_key, _value| Err(ExecuteError::corrupt()));
+        assert!(matches!(res, Err(ExecuteError::KvError(_))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_flushes_are_synced_by_the_sync_policy() -> Result<(), ExecuteError> {
+        let open = |data_dir: &str, sync: SyncPolicy| {
+            let backend = BackendConfig::new(
+                TableConfig::default(),
+                TableConfig::default(),
+                TableConfig::default(),
+                sync,
+                utils::config::default_sync_interval(),
+                0,
+                false,
+                0,
+            );
+            DBProxy::open_with_backend(
+                &StorageConfig::MemoryFile(PathBuf::from(data_dir)),
+                &backend,
+                false,
+            )
+        };
+        for (data_dir, sync, kept) in [
+            ("/tmp/test_sync_always", SyncPolicy::Always, true),
+            ("/tmp/test_sync_never", SyncPolicy::Never, false),
+        ] {
+            let _ignore = std::fs::remove_dir_all(data_dir);
+            let db = open(data_dir, sync)?;
+            let id = ProposeId::new("test-id".to_owned());
+            db.buffer_op(&id, WriteOp::PutKeyValue(Revision::new(1, 0), vec![1]));
+            db.flush(&id)?;
+            // the memory file engine only dumps the synced writes, like a crash of the
+            // machine losing the writes buffered by the OS
+            let db = open(data_dir, sync)?;
+            assert_eq!(db.get_all(KV_TABLE)?.is_empty(), !kept);
+            std::fs::remove_dir_all(data_dir).map_err(|e| ExecuteError::DbError(e.to_string()))?;
+        }
+        Ok(())
+    }
 }
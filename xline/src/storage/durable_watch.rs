@@ -0,0 +1,110 @@
+use std::sync::Arc;
+
+use curp::cmd::ProposeId;
+use prost::Message;
+use uuid::Uuid;
+
+use super::{db::WriteOp, storage_api::StorageApi, ExecuteError};
+use crate::rpc::WatchCreateRequest;
+
+/// Table of the durable watchers, the keys are the resume tokens, and the values are the
+/// encoded create requests of the watchers
+pub(crate) const DURABLE_WATCH_TABLE: &str = "durable_watch";
+
+/// Registrations of the durable watchers. A durable watcher is kept as its create request,
+/// whose start revision is the next revision to deliver, so that it's resumed by its resume
+/// token after the stream is closed or the server restarts.
+///
+/// The registrations are written by the member serving the watchers, they are never
+/// replicated, so a watcher is only resumed on the member it's created on. A registration
+/// is removed when its watcher is canceled by the client or its next revision is compacted.
+#[derive(Debug)]
+pub(crate) struct DurableWatchStore<S>
+where
+    S: StorageApi,
+{
+    /// Persistent storage
+    storage: Arc<S>,
+}
+
+impl<S> DurableWatchStore<S>
+where
+    S: StorageApi,
+{
+    /// New `DurableWatchStore`
+    pub(crate) fn new(storage: Arc<S>) -> Self {
+        Self { storage }
+    }
+
+    /// Register a durable watcher, return its resume token
+    pub(crate) fn register(&self, request: &WatchCreateRequest) -> Result<String, ExecuteError> {
+        let token = Uuid::new_v4().to_string();
+        self.save(&token, request)?;
+        Ok(token)
+    }
+
+    /// Save the registration of a durable watcher
+    pub(crate) fn save(
+        &self,
+        token: &str,
+        request: &WatchCreateRequest,
+    ) -> Result<(), ExecuteError> {
+        let id = ProposeId::new(format!("{DURABLE_WATCH_TABLE}-{token}"));
+        self.storage.buffer_op(
+            &id,
+            WriteOp::PutDurableWatch(token.to_owned(), request.encode_to_vec()),
+        );
+        self.storage.flush(&id)
+    }
+
+    /// Get the registration of a durable watcher, `None` if the token is unknown
+    pub(crate) fn get(&self, token: &str) -> Result<Option<WatchCreateRequest>, ExecuteError> {
+        self.storage
+            .get_value(DURABLE_WATCH_TABLE, token)?
+            .map(|value| {
+                WatchCreateRequest::decode(value.as_slice()).map_err(|e| {
+                    ExecuteError::DbError(format!("Invalid durable watcher {token}, {e}"))
+                })
+            })
+            .transpose()
+    }
+
+    /// Remove the registration of a durable watcher
+    pub(crate) fn remove(&self, token: &str) -> Result<(), ExecuteError> {
+        let id = ProposeId::new(format!("{DURABLE_WATCH_TABLE}-{token}"));
+        self.storage
+            .buffer_op(&id, WriteOp::DeleteDurableWatch(token.to_owned()));
+        self.storage.flush(&id)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use utils::config::StorageConfig;
+
+    use super::*;
+    use crate::storage::db::DBProxy;
+
+    #[test]
+    fn test_durable_watchers_are_resumed_by_the_tokens() {
+        let db = DBProxy::open(&StorageConfig::Memory).unwrap();
+        let store = DurableWatchStore::new(Arc::clone(&db));
+        let mut request = WatchCreateRequest {
+            key: b"foo".to_vec(),
+            start_revision: 3,
+            durable: true,
+            ..WatchCreateRequest::default()
+        };
+        let token = store.register(&request).unwrap();
+        assert_eq!(store.get(&token).unwrap(), Some(request.clone()));
+
+        request.start_revision = 8;
+        store.save(&token, &request).unwrap();
+        let reopened = DurableWatchStore::new(db);
+        assert_eq!(reopened.get(&token).unwrap().unwrap().start_revision, 8);
+
+        reopened.remove(&token).unwrap();
+        assert_eq!(reopened.get(&token).unwrap(), None);
+        assert_eq!(reopened.get("unknown").unwrap(), None);
+    }
+}
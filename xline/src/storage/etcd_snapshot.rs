@@ -0,0 +1,464 @@
+use std::{fs, path::Path};
+
+use engine::{engine_api::StorageEngine, rocksdb_engine::RocksEngine, WriteOperation};
+use pbkdf2::{
+    password_hash::{rand_core::OsRng, PasswordHasher, SaltString},
+    Pbkdf2,
+};
+use prost::Message;
+use sha2::{Digest, Sha256};
+
+use super::{
+    auth_store::{AUTH_ENABLE_KEY, AUTH_REVISION_KEY, AUTH_TABLE, ROLE_TABLE, USER_TABLE},
+    bbolt::Bolt,
+    db::ENGINE_TABLES,
+    kv_store::KV_TABLE,
+    lease_store::LEASE_TABLE,
+    snapshot::{check_empty_dir, SnapshotError},
+    Revision,
+};
+use crate::rpc::{KeyValue, PbLease, Role, User};
+
+/// Bucket of the key-values, the keys are the revisions
+const KEY_BUCKET: &[u8] = b"key";
+/// Bucket of the leases
+const LEASE_BUCKET: &[u8] = b"lease";
+/// Bucket of the auth status
+const AUTH_BUCKET: &[u8] = b"auth";
+/// Bucket of the users
+const AUTH_USERS_BUCKET: &[u8] = b"authUsers";
+/// Bucket of the roles
+const AUTH_ROLES_BUCKET: &[u8] = b"authRoles";
+/// Key of the auth status in the auth bucket
+const ETCD_AUTH_ENABLED_KEY: &[u8] = b"authEnabled";
+/// Key of the auth revision in the auth bucket
+const ETCD_AUTH_REVISION_KEY: &[u8] = b"authRevision";
+/// Size of a revision key: main revision, '_' and sub revision
+const REVISION_KEY_SIZE: usize = 17;
+/// Marker appended to the revision keys of tombstones
+const TOMBSTONE_MARKER: u8 = b't';
+/// `etcdctl snapshot save` appends the SHA-256 of the database if the size of the
+/// database file modulo this is the size of the hash
+const HASHED_FILE_ALIGNMENT: usize = 512;
+/// Max number of entries written to the engine in one batch
+const IMPORT_BATCH_SIZE: usize = 1024;
+
+/// Summary of an imported etcd snapshot
+#[allow(clippy::module_name_repetitions)]
+#[non_exhaustive]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportSummary {
+    /// Number of the imported revisions of the keys, including the deletions
+    pub kvs: u64,
+    /// The latest revision
+    pub revision: i64,
+    /// Number of the imported leases
+    pub leases: u64,
+    /// Number of the imported roles
+    pub roles: u64,
+    /// Number of the imported users
+    pub users: u64,
+    /// Names of the users whose passwords must be changed before they authenticate. The
+    /// bcrypt password hashes of etcd can't be verified by Xline, they are replaced by
+    /// hashes of random passwords.
+    pub users_to_reset: Vec<String>,
+    /// Whether the auth was enabled in etcd. The auth is always disabled after the import,
+    /// it should be enabled after the passwords are changed.
+    pub auth_enabled: bool,
+}
+
+/// Import an etcd snapshot, which is the bbolt database of etcd, into the engine. The
+/// history of the keys, the leases, the users and the roles are imported with their
+/// revisions, so that the clients see the same revisions as in etcd.
+///
+/// # Errors
+///
+/// Return `SnapshotError` if the snapshot is invalid or failed to write the engine
+#[inline]
+pub fn import<E: StorageEngine>(engine: &E, db: &[u8]) -> Result<ImportSummary, SnapshotError> {
+    let bolt = Bolt::open(db)?;
+    let mut summary = ImportSummary::default();
+    let mut ops = Vec::with_capacity(IMPORT_BATCH_SIZE);
+    let mut write = |op: WriteOperation| -> Result<(), SnapshotError> {
+        ops.push(op);
+        if ops.len() >= IMPORT_BATCH_SIZE {
+            engine.write_batch(std::mem::take(&mut ops), false)?;
+        }
+        Ok(())
+    };
+
+    if !bolt.visit_bucket(KEY_BUCKET, |key, value| {
+        let (revision, tombstone) = decode_revision_key(key)?;
+        let mut kv = decode::<KeyValue>(value, "key-value")?;
+        if tombstone {
+            kv = KeyValue {
+                key: kv.key,
+                mod_revision: revision.revision(),
+                ..KeyValue::default()
+            };
+        }
+        summary.kvs = summary.kvs.wrapping_add(1);
+        summary.revision = revision.revision();
+        write(WriteOperation::new_put(
+            KV_TABLE,
+            revision.encode_to_vec(),
+            kv.encode_to_vec(),
+        ))
+    })? {
+        return Err(SnapshotError::InvalidSnapshot(
+            "not an etcd snapshot, key bucket not found".to_owned(),
+        ));
+    }
+
+    let _found = bolt.visit_bucket(LEASE_BUCKET, |_key, value| {
+        let lease = decode::<PbLease>(value, "lease")?;
+        summary.leases = summary.leases.wrapping_add(1);
+        write(WriteOperation::new_put(
+            LEASE_TABLE,
+            lease.id.encode_to_vec(),
+            lease.encode_to_vec(),
+        ))
+    })?;
+
+    let _found = bolt.visit_bucket(AUTH_BUCKET, |key, value| {
+        if key == ETCD_AUTH_ENABLED_KEY {
+            summary.auth_enabled = value.first().map_or(false, |&enabled| enabled != 0);
+        } else if key == ETCD_AUTH_REVISION_KEY {
+            let revision = value
+                .try_into()
+                .map(u64::from_be_bytes)
+                .map_err(|_e| invalid_value("auth revision"))?;
+            let revision = i64::try_from(revision).map_err(|_e| invalid_value("auth revision"))?;
+            write(WriteOperation::new_put(
+                AUTH_TABLE,
+                AUTH_REVISION_KEY,
+                revision.encode_to_vec(),
+            ))?;
+        } else {
+            // other keys of the auth bucket are not used by Xline
+        }
+        Ok(())
+    })?;
+    write(WriteOperation::new_put(
+        AUTH_TABLE,
+        AUTH_ENABLE_KEY,
+        vec![u8::from(false)],
+    ))?;
+
+    let _found = bolt.visit_bucket(AUTH_USERS_BUCKET, |_key, value| {
+        let mut user = decode::<User>(value, "user")?;
+        let need_password = user.options.as_ref().map_or(true, |o| !o.no_password);
+        if need_password {
+            user.password = random_password_hash().into_bytes();
+            summary
+                .users_to_reset
+                .push(String::from_utf8_lossy(&user.name).into_owned());
+        }
+        summary.users = summary.users.wrapping_add(1);
+        write(WriteOperation::new_put(
+            USER_TABLE,
+            user.name.clone(),
+            user.encode_to_vec(),
+        ))
+    })?;
+
+    let _found = bolt.visit_bucket(AUTH_ROLES_BUCKET, |_key, value| {
+        let role = decode::<Role>(value, "role")?;
+        summary.roles = summary.roles.wrapping_add(1);
+        write(WriteOperation::new_put(
+            ROLE_TABLE,
+            role.name.clone(),
+            role.encode_to_vec(),
+        ))
+    })?;
+
+    engine.write_batch(ops, true)?;
+    Ok(summary)
+}
+
+/// Import the etcd snapshot file at `path` into a new data dir, which is saved by
+/// `etcdctl snapshot save` or copied from the `member/snap/db` of a stopped etcd member.
+/// Both `data_dir` and `curp_data_dir` must be empty, the imported member starts with an
+/// empty consensus log. The whole file is read into the memory.
+///
+/// # Errors
+///
+/// Return `SnapshotError` if the directories are not empty, the snapshot is invalid or
+/// failed to write the database
+#[inline]
+pub fn import_from_file(
+    path: &Path,
+    data_dir: &Path,
+    curp_data_dir: &Path,
+) -> Result<ImportSummary, SnapshotError> {
+    check_empty_dir(data_dir)?;
+    check_empty_dir(curp_data_dir)?;
+    let data = fs::read(path)?;
+    let db = strip_hash(&data)?;
+    let engine = RocksEngine::new(data_dir, &ENGINE_TABLES)?;
+    import(&engine, db)
+}
+
+/// Verify and remove the SHA-256 appended by `etcdctl snapshot save`, the database is
+/// returned as is if it has no hash
+fn strip_hash(data: &[u8]) -> Result<&[u8], SnapshotError> {
+    let hash_size = Sha256::output_size();
+    if data.len() % HASHED_FILE_ALIGNMENT != hash_size {
+        return Ok(data);
+    }
+    let (db, hash) = data.split_at(data.len().wrapping_sub(hash_size));
+    if Sha256::digest(db).as_slice() != hash {
+        return Err(SnapshotError::InvalidSnapshot(
+            "SHA-256 of the etcd snapshot mismatches".to_owned(),
+        ));
+    }
+    Ok(db)
+}
+
+/// Decode a revision key of etcd, return the revision and whether it's a tombstone
+fn decode_revision_key(key: &[u8]) -> Result<(Revision, bool), SnapshotError> {
+    let (revision, marker) = key.split_at(REVISION_KEY_SIZE.min(key.len()));
+    let tombstone = match *marker {
+        [] => false,
+        [TOMBSTONE_MARKER] => true,
+        _ => return Err(invalid_value("revision key")),
+    };
+    match *revision {
+        [m0, m1, m2, m3, m4, m5, m6, m7, b'_', s0, s1, s2, s3, s4, s5, s6, s7] => {
+            let main = i64::from_be_bytes([m0, m1, m2, m3, m4, m5, m6, m7]);
+            let sub = i64::from_be_bytes([s0, s1, s2, s3, s4, s5, s6, s7]);
+            Ok((Revision::new(main, sub), tombstone))
+        }
+        _ => Err(invalid_value("revision key")),
+    }
+}
+
+/// Decode a protobuf message of etcd
+fn decode<M: Message + Default>(value: &[u8], name: &str) -> Result<M, SnapshotError> {
+    M::decode(value).map_err(|e| SnapshotError::InvalidSnapshot(format!("invalid {name}, {e}")))
+}
+
+/// Error of an invalid value
+fn invalid_value(name: &str) -> SnapshotError {
+    SnapshotError::InvalidSnapshot(format!("invalid {name}"))
+}
+
+/// Hash a random password, nobody can authenticate with it
+fn random_password_hash() -> String {
+    let password = SaltString::generate(&mut OsRng);
+    let salt = SaltString::generate(&mut OsRng);
+    Pbkdf2
+        .hash_password(password.as_str().as_bytes(), salt.as_ref())
+        .unwrap_or_else(|e| panic!("Failed to hash password: {e}"))
+        .to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use engine::memory_engine::MemoryEngine;
+
+    use super::*;
+    use crate::storage::bbolt::fnv64a;
+
+    const PAGE_SIZE: usize = 4096;
+
+    /// Build a leaf page with the elements `(key, value, flags)`
+    fn leaf_page(id: u64, elements: &[(&[u8], Vec<u8>, u32)]) -> Vec<u8> {
+        let mut header = Vec::new();
+        header.extend_from_slice(&id.to_le_bytes());
+        header.extend_from_slice(&0x02_u16.to_le_bytes());
+        header.extend_from_slice(&u16::try_from(elements.len()).unwrap().to_le_bytes());
+        header.extend_from_slice(&0_u32.to_le_bytes());
+        let mut data = Vec::new();
+        for (i, &(key, ref value, flags)) in elements.iter().enumerate() {
+            let pos = (elements.len() - i) * 16 + data.len();
+            header.extend_from_slice(&flags.to_le_bytes());
+            header.extend_from_slice(&u32::try_from(pos).unwrap().to_le_bytes());
+            header.extend_from_slice(&u32::try_from(key.len()).unwrap().to_le_bytes());
+            header.extend_from_slice(&u32::try_from(value.len()).unwrap().to_le_bytes());
+            data.extend_from_slice(key);
+            data.extend_from_slice(value);
+        }
+        header.extend_from_slice(&data);
+        header
+    }
+
+    /// Build an inline bucket with the key-values
+    fn inline_bucket(kvs: &[(&[u8], Vec<u8>)]) -> Vec<u8> {
+        let elements: Vec<_> = kvs
+            .iter()
+            .map(|&(key, ref value)| (key, value.clone(), 0))
+            .collect();
+        let mut bucket = vec![0; 16];
+        bucket.extend(leaf_page(0, &elements));
+        bucket
+    }
+
+    /// Build a meta page
+    fn meta_page(id: u64, root: u64, txid: u64) -> Vec<u8> {
+        let mut meta = Vec::new();
+        meta.extend_from_slice(&0xED0C_DAED_u32.to_le_bytes());
+        meta.extend_from_slice(&2_u32.to_le_bytes());
+        meta.extend_from_slice(&u32::try_from(PAGE_SIZE).unwrap().to_le_bytes());
+        meta.extend_from_slice(&0_u32.to_le_bytes());
+        meta.extend_from_slice(&root.to_le_bytes());
+        meta.extend_from_slice(&0_u64.to_le_bytes());
+        meta.extend_from_slice(&0_u64.to_le_bytes());
+        meta.extend_from_slice(&4_u64.to_le_bytes());
+        meta.extend_from_slice(&txid.to_le_bytes());
+        meta.extend_from_slice(&fnv64a(&meta).to_le_bytes());
+        let mut page = Vec::new();
+        page.extend_from_slice(&id.to_le_bytes());
+        page.extend_from_slice(&0x04_u16.to_le_bytes());
+        page.extend_from_slice(&0_u16.to_le_bytes());
+        page.extend_from_slice(&0_u32.to_le_bytes());
+        page.extend(meta);
+        page
+    }
+
+    fn revision_key(main: i64, sub: i64, tombstone: bool) -> Vec<u8> {
+        let mut key = main.to_be_bytes().to_vec();
+        key.push(b'_');
+        key.extend_from_slice(&sub.to_be_bytes());
+        if tombstone {
+            key.push(b't');
+        }
+        key
+    }
+
+    /// Build a bbolt database like the one of etcd
+    fn etcd_db() -> Vec<u8> {
+        let put = KeyValue {
+            key: b"foo".to_vec(),
+            value: b"bar".to_vec(),
+            create_revision: 2,
+            mod_revision: 2,
+            version: 1,
+            lease: 0,
+        };
+        let delete = KeyValue {
+            key: b"foo".to_vec(),
+            ..KeyValue::default()
+        };
+        let keys = inline_bucket(&[
+            (revision_key(2, 0, false).as_slice(), put.encode_to_vec()),
+            (revision_key(3, 0, true).as_slice(), delete.encode_to_vec()),
+        ]);
+        let lease = PbLease {
+            id: 1,
+            ttl: 10,
+            remaining_ttl: 0,
+        };
+        let leases = inline_bucket(&[(1_i64.to_be_bytes().as_slice(), lease.encode_to_vec())]);
+        let auth = inline_bucket(&[
+            (ETCD_AUTH_ENABLED_KEY, vec![1]),
+            (ETCD_AUTH_REVISION_KEY, 5_u64.to_be_bytes().to_vec()),
+        ]);
+        let root_user = User {
+            name: b"root".to_vec(),
+            password: b"$2a$10$bcrypt".to_vec(),
+            roles: vec!["root".to_owned()],
+            options: None,
+        };
+        let mut cn_user = User {
+            name: b"cn".to_vec(),
+            password: vec![],
+            roles: vec![],
+            options: Some(Default::default()),
+        };
+        cn_user.options.as_mut().unwrap().no_password = true;
+        let users = inline_bucket(&[
+            (b"cn".as_slice(), cn_user.encode_to_vec()),
+            (b"root".as_slice(), root_user.encode_to_vec()),
+        ]);
+        let role = Role {
+            name: b"root".to_vec(),
+            key_permission: vec![],
+        };
+        let roles = inline_bucket(&[(b"root".as_slice(), role.encode_to_vec())]);
+        let root = leaf_page(
+            2,
+            &[
+                (AUTH_BUCKET, auth, 1),
+                (AUTH_ROLES_BUCKET, roles, 1),
+                (AUTH_USERS_BUCKET, users, 1),
+                (KEY_BUCKET, keys, 1),
+                (LEASE_BUCKET, leases, 1),
+            ],
+        );
+
+        let mut db = Vec::new();
+        for (i, page) in [meta_page(0, 2, 4), meta_page(1, 2, 3), root]
+            .into_iter()
+            .enumerate()
+        {
+            assert!(page.len() <= PAGE_SIZE);
+            db.extend(page);
+            db.resize((i + 1) * PAGE_SIZE, 0);
+        }
+        db
+    }
+
+    #[test]
+    fn test_import() -> Result<(), SnapshotError> {
+        let engine = MemoryEngine::new(&ENGINE_TABLES)?;
+        let summary = import(&engine, &etcd_db())?;
+        assert_eq!(summary.kvs, 2);
+        assert_eq!(summary.revision, 3);
+        assert_eq!(summary.leases, 1);
+        assert_eq!(summary.users, 2);
+        assert_eq!(summary.roles, 1);
+        assert_eq!(summary.users_to_reset, vec!["root".to_owned()]);
+        assert!(summary.auth_enabled);
+
+        let kvs = engine.get_all(KV_TABLE)?;
+        assert_eq!(kvs.len(), 2);
+        let tombstone = engine
+            .get(KV_TABLE, Revision::new(3, 0).encode_to_vec())?
+            .unwrap();
+        assert_eq!(
+            KeyValue::decode(tombstone.as_slice()).unwrap(),
+            KeyValue {
+                key: b"foo".to_vec(),
+                mod_revision: 3,
+                ..KeyValue::default()
+            }
+        );
+        assert!(engine.get(LEASE_TABLE, 1_i64.encode_to_vec())?.is_some());
+        assert_eq!(
+            engine.get(AUTH_TABLE, AUTH_ENABLE_KEY)?,
+            Some(vec![u8::from(false)])
+        );
+        assert_eq!(
+            engine.get(AUTH_TABLE, AUTH_REVISION_KEY)?,
+            Some(5_i64.encode_to_vec())
+        );
+        let root = User::decode(engine.get(USER_TABLE, "root")?.unwrap().as_slice()).unwrap();
+        assert!(root.password.starts_with(b"$pbkdf2"));
+        assert!(engine.get(ROLE_TABLE, "root")?.is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_corrupted_db_should_fail() {
+        let engine = MemoryEngine::new(&ENGINE_TABLES).unwrap();
+        let mut db = etcd_db();
+        for byte in db.iter_mut().take(2 * PAGE_SIZE) {
+            *byte = 0;
+        }
+        assert!(import(&engine, &db).is_err());
+        assert!(import(&engine, &etcd_db()[..2 * PAGE_SIZE]).is_err());
+    }
+
+    #[test]
+    fn test_strip_hash() -> Result<(), SnapshotError> {
+        let db = etcd_db();
+        assert_eq!(strip_hash(&db)?, db.as_slice());
+        let mut hashed = db.clone();
+        hashed.extend_from_slice(&Sha256::digest(&db));
+        assert_eq!(strip_hash(&hashed)?, db.as_slice());
+        *hashed.last_mut().unwrap() ^= 1;
+        assert!(strip_hash(&hashed).is_err());
+        Ok(())
+    }
+}
@@ -1,123 +1,399 @@
 use thiserror::Error;
 
+/// Error messages of etcd and their gRPC codes. The etcd clients pattern-match on the
+/// messages, and the execution errors reach the servers as strings through curp, so the
+/// messages must be the same as etcd's. The last ones are xline's own errors.
+const ETCD_ERRORS: [(&str, tonic::Code); 49] = [
+    (
+        "etcdserver: key is not provided",
+        tonic::Code::InvalidArgument,
+    ),
+    ("etcdserver: key not found", tonic::Code::InvalidArgument),
+    (
+        "etcdserver: value is provided",
+        tonic::Code::InvalidArgument,
+    ),
+    (
+        "etcdserver: lease is provided",
+        tonic::Code::InvalidArgument,
+    ),
+    (
+        "etcdserver: too many operations in txn request",
+        tonic::Code::InvalidArgument,
+    ),
+    (
+        "etcdserver: duplicate key given in txn request",
+        tonic::Code::InvalidArgument,
+    ),
+    (
+        "etcdserver: invalid sort option",
+        tonic::Code::InvalidArgument,
+    ),
+    (
+        "etcdserver: mvcc: required revision has been compacted",
+        tonic::Code::OutOfRange,
+    ),
+    (
+        "etcdserver: mvcc: required revision is a future revision",
+        tonic::Code::OutOfRange,
+    ),
+    (
+        "etcdserver: mvcc: database space exceeded",
+        tonic::Code::ResourceExhausted,
+    ),
+    (
+        "etcdserver: requested lease not found",
+        tonic::Code::NotFound,
+    ),
+    (
+        "etcdserver: lease already exists",
+        tonic::Code::FailedPrecondition,
+    ),
+    ("etcdserver: too large lease TTL", tonic::Code::OutOfRange),
+    (
+        "etcdserver: root user does not exist",
+        tonic::Code::FailedPrecondition,
+    ),
+    (
+        "etcdserver: root user does not have root role",
+        tonic::Code::FailedPrecondition,
+    ),
+    (
+        "etcdserver: user name already exists",
+        tonic::Code::FailedPrecondition,
+    ),
+    (
+        "etcdserver: user name is empty",
+        tonic::Code::InvalidArgument,
+    ),
+    (
+        "etcdserver: user name not found",
+        tonic::Code::FailedPrecondition,
+    ),
+    (
+        "etcdserver: role name already exists",
+        tonic::Code::FailedPrecondition,
+    ),
+    (
+        "etcdserver: role name not found",
+        tonic::Code::FailedPrecondition,
+    ),
+    (
+        "etcdserver: role name is empty",
+        tonic::Code::InvalidArgument,
+    ),
+    (
+        "etcdserver: authentication failed, invalid user ID or password",
+        tonic::Code::InvalidArgument,
+    ),
+    (
+        "etcdserver: authentication failed, password was given for no password user",
+        tonic::Code::InvalidArgument,
+    ),
+    (
+        "etcdserver: permission denied",
+        tonic::Code::PermissionDenied,
+    ),
+    (
+        "etcdserver: role is not granted to the user",
+        tonic::Code::FailedPrecondition,
+    ),
+    (
+        "etcdserver: permission is not granted to the role",
+        tonic::Code::FailedPrecondition,
+    ),
+    (
+        "etcdserver: permission not given",
+        tonic::Code::InvalidArgument,
+    ),
+    (
+        "etcdserver: authentication is not enabled",
+        tonic::Code::FailedPrecondition,
+    ),
+    (
+        "etcdserver: invalid auth token",
+        tonic::Code::Unauthenticated,
+    ),
+    (
+        "etcdserver: invalid auth management",
+        tonic::Code::InvalidArgument,
+    ),
+    (
+        "etcdserver: revision of auth store is old",
+        tonic::Code::InvalidArgument,
+    ),
+    (
+        "etcdserver: user is already granted the role",
+        tonic::Code::FailedPrecondition,
+    ),
+    ("etcdserver: member not found", tonic::Code::NotFound),
+    (
+        "etcdserver: given member URLs are invalid",
+        tonic::Code::InvalidArgument,
+    ),
+    ("etcdserver: no leader", tonic::Code::Unavailable),
+    ("etcdserver: not leader", tonic::Code::FailedPrecondition),
+    (
+        "etcdserver: request is too large",
+        tonic::Code::InvalidArgument,
+    ),
+    ("etcdserver: request timed out", tonic::Code::Unavailable),
+    ("etcdserver: corrupt cluster", tonic::Code::DataLoss),
+    (
+        "etcdserver: user quota exceeded",
+        tonic::Code::ResourceExhausted,
+    ),
+    ("etcdserver: key is too large", tonic::Code::InvalidArgument),
+    (
+        "etcdserver: value is too large",
+        tonic::Code::InvalidArgument,
+    ),
+    (
+        "etcdserver: invalid page token",
+        tonic::Code::InvalidArgument,
+    ),
+    (
+        "etcdserver: namespace quota exceeded",
+        tonic::Code::ResourceExhausted,
+    ),
+    (
+        "etcdserver: invalid token ttl",
+        tonic::Code::InvalidArgument,
+    ),
+    (
+        "etcdserver: invalid token scope",
+        tonic::Code::InvalidArgument,
+    ),
+    ("etcdserver: api key not found", tonic::Code::NotFound),
+    (
+        "etcdserver: range end is less than the key",
+        tonic::Code::InvalidArgument,
+    ),
+    (
+        "etcdserver: txn request is nested too deeply",
+        tonic::Code::InvalidArgument,
+    ),
+];
+
 /// Error met when executing commands
 #[derive(Error, Debug, Clone)]
 pub enum ExecuteError {
     /// Kv error
-    #[error("kv error: {0}")]
+    #[error("{0}")]
     KvError(String),
     /// Lease error
-    #[error("lease error: {0}")]
+    #[error("{0}")]
     LeaseError(String),
     /// Auth error
-    #[error("auth error: {0}")]
+    #[error("{0}")]
     AuthError(String),
+    /// Cluster error
+    #[error("{0}")]
+    ClusterError(String),
     /// Db error
     #[error("db error: {0}")]
     DbError(String),
     /// Permission denied
-    #[error("permission denied")]
+    #[error("etcdserver: permission denied")]
     PermissionDenied,
 }
 
 impl ExecuteError {
+    /// Key is not provided
+    pub(crate) fn empty_key() -> Self {
+        Self::KvError("etcdserver: key is not provided".to_owned())
+    }
+
     /// Key not found
     pub(crate) fn key_not_found() -> Self {
-        Self::KvError("key not found".to_owned())
+        Self::KvError("etcdserver: key not found".to_owned())
+    }
+
+    /// Value is provided while `ignore_value` is set
+    pub(crate) fn value_provided() -> Self {
+        Self::KvError("etcdserver: value is provided".to_owned())
+    }
+
+    /// Lease is provided while `ignore_lease` is set
+    pub(crate) fn lease_provided() -> Self {
+        Self::KvError("etcdserver: lease is provided".to_owned())
+    }
+
+    /// Too many operations in a txn
+    pub(crate) fn too_many_ops() -> Self {
+        Self::KvError("etcdserver: too many operations in txn request".to_owned())
+    }
+
+    /// A key is written more than once in a txn
+    pub(crate) fn duplicate_key() -> Self {
+        Self::KvError("etcdserver: duplicate key given in txn request".to_owned())
+    }
+
+    /// The range end is less than the key
+    pub(crate) fn invalid_range_end() -> Self {
+        Self::KvError("etcdserver: range end is less than the key".to_owned())
+    }
+
+    /// The txns are nested too deeply
+    pub(crate) fn txn_too_deep() -> Self {
+        Self::KvError("etcdserver: txn request is nested too deeply".to_owned())
+    }
+
+    /// Request is larger than the max request size
+    pub(crate) fn request_too_large() -> Self {
+        Self::KvError("etcdserver: request is too large".to_owned())
+    }
+
+    /// Key is larger than the max key size
+    pub(crate) fn key_too_large() -> Self {
+        Self::KvError("etcdserver: key is too large".to_owned())
+    }
+
+    /// Value is larger than the max value size
+    pub(crate) fn value_too_large() -> Self {
+        Self::KvError("etcdserver: value is too large".to_owned())
+    }
+
+    /// The page token of a range is malformed, or it doesn't belong to the range
+    pub(crate) fn invalid_page_token() -> Self {
+        Self::KvError("etcdserver: invalid page token".to_owned())
+    }
+
+    /// The write quota of the user is exceeded
+    pub(crate) fn quota_exceeded() -> Self {
+        Self::KvError("etcdserver: user quota exceeded".to_owned())
+    }
+
+    /// The hard quota of a namespace is exceeded
+    pub(crate) fn namespace_quota_exceeded() -> Self {
+        Self::KvError("etcdserver: namespace quota exceeded".to_owned())
+    }
+
+    /// Writes are rejected while the CORRUPT alarm is active
+    pub(crate) fn corrupt() -> Self {
+        Self::KvError("etcdserver: corrupt cluster".to_owned())
+    }
+
+    /// Required revision is older than the compacted revision
+    pub(crate) fn compacted() -> Self {
+        Self::KvError("etcdserver: mvcc: required revision has been compacted".to_owned())
+    }
+
+    /// Required revision is larger than the current revision
+    pub(crate) fn future_revision() -> Self {
+        Self::KvError("etcdserver: mvcc: required revision is a future revision".to_owned())
+    }
+
+    /// Invalid sort option
+    pub(crate) fn invalid_sort_option() -> Self {
+        Self::KvError("etcdserver: invalid sort option".to_owned())
     }
 
     /// Lease not found
-    pub(crate) fn lease_not_found(lease_id: i64) -> Self {
-        Self::LeaseError(format!("lease {lease_id} not found"))
+    pub(crate) fn lease_not_found() -> Self {
+        Self::LeaseError("etcdserver: requested lease not found".to_owned())
     }
 
-    /// Lease is expired
-    pub(crate) fn lease_expired(lease_id: i64) -> Self {
-        Self::LeaseError(format!("lease {lease_id} is expired"))
+    /// Lease is expired, it's not found for the clients
+    pub(crate) fn lease_expired() -> Self {
+        Self::lease_not_found()
     }
 
     /// Lease ttl is too large
-    pub(crate) fn lease_ttl_too_large(ttl: i64) -> Self {
-        Self::LeaseError(format!("lease ttl is too large: {ttl}"))
+    pub(crate) fn lease_ttl_too_large() -> Self {
+        Self::LeaseError("etcdserver: too large lease TTL".to_owned())
     }
 
     /// Lease already exists
-    pub(crate) fn lease_already_exists(lease_id: i64) -> Self {
-        Self::LeaseError(format!("lease {lease_id} already exists"))
+    pub(crate) fn lease_already_exists() -> Self {
+        Self::LeaseError("etcdserver: lease already exists".to_owned())
     }
 
     /// Lease current node is not leader
     pub(crate) fn lease_not_leader() -> Self {
-        Self::LeaseError("current node is not leader".to_owned())
+        Self::LeaseError("etcdserver: not leader".to_owned())
     }
 
     /// Auth is not enabled
     pub(crate) fn auth_not_enabled() -> Self {
-        Self::AuthError("auth is not enabled".to_owned())
+        Self::AuthError("etcdserver: authentication is not enabled".to_owned())
     }
 
     /// Auth failed
     pub(crate) fn auth_failed() -> Self {
-        Self::AuthError("invalid username or password".to_owned())
+        Self::AuthError("etcdserver: authentication failed, invalid user ID or password".to_owned())
+    }
+
+    /// User name is empty
+    pub(crate) fn user_empty() -> Self {
+        Self::AuthError("etcdserver: user name is empty".to_owned())
     }
 
     /// User not found
-    pub(crate) fn user_not_found(username: &str) -> Self {
-        Self::AuthError(format!("user {username} not found"))
+    pub(crate) fn user_not_found() -> Self {
+        Self::AuthError("etcdserver: user name not found".to_owned())
     }
 
     /// User already exists
-    pub(crate) fn user_already_exists(username: &str) -> Self {
-        Self::AuthError(format!("user {username} already exists"))
+    pub(crate) fn user_already_exists() -> Self {
+        Self::AuthError("etcdserver: user name already exists".to_owned())
     }
 
     /// User already has role
-    pub(crate) fn user_already_has_role(username: &str, rolename: &str) -> Self {
-        Self::AuthError(format!("user {username} already has role {rolename}"))
+    pub(crate) fn user_already_has_role() -> Self {
+        Self::AuthError("etcdserver: user is already granted the role".to_owned())
     }
 
-    /// Permission already exists
+    /// Password was given for a no password user
     pub(crate) fn no_password_user() -> Self {
-        Self::AuthError("password was given for no password user".to_owned())
+        Self::AuthError(
+            "etcdserver: authentication failed, password was given for no password user".to_owned(),
+        )
+    }
+
+    /// Role name is empty
+    pub(crate) fn role_empty() -> Self {
+        Self::AuthError("etcdserver: role name is empty".to_owned())
     }
 
     /// Role not found
-    pub(crate) fn role_not_found(rolename: &str) -> Self {
-        Self::AuthError(format!("role {rolename} not found"))
+    pub(crate) fn role_not_found() -> Self {
+        Self::AuthError("etcdserver: role name not found".to_owned())
     }
 
     /// Role already exists
-    pub(crate) fn role_already_exists(rolename: &str) -> Self {
-        Self::AuthError(format!("role {rolename} already exists"))
+    pub(crate) fn role_already_exists() -> Self {
+        Self::AuthError("etcdserver: role name already exists".to_owned())
     }
 
     /// Role not granted
-    pub(crate) fn role_not_granted(rolename: &str) -> Self {
-        Self::AuthError(format!("role {rolename} is not granted to the user"))
+    pub(crate) fn role_not_granted() -> Self {
+        Self::AuthError("etcdserver: role is not granted to the user".to_owned())
     }
+
     /// Root role not exist
     pub(crate) fn root_role_not_exist() -> Self {
-        Self::AuthError("root user does not have root role".to_owned())
+        Self::AuthError("etcdserver: root user does not have root role".to_owned())
     }
 
     /// Permission not granted
     pub(crate) fn permission_not_granted() -> Self {
-        Self::AuthError("permission not granted to the role".to_owned())
+        Self::AuthError("etcdserver: permission is not granted to the role".to_owned())
     }
 
     /// Permission not given
     pub(crate) fn permission_not_given() -> Self {
-        Self::AuthError("permission not given".to_owned())
+        Self::AuthError("etcdserver: permission not given".to_owned())
     }
 
     /// Invalid auth management
     pub(crate) fn invalid_auth_management() -> Self {
-        Self::AuthError("invalid auth management".to_owned())
+        Self::AuthError("etcdserver: invalid auth management".to_owned())
     }
 
     /// Invalid auth token
     pub(crate) fn invalid_auth_token() -> Self {
-        Self::AuthError("invalid auth token".to_owned())
+        Self::AuthError("etcdserver: invalid auth token".to_owned())
     }
 
     /// Token manager is not initialized
@@ -125,13 +401,131 @@ impl ExecuteError {
         Self::AuthError("token manager is not initialized".to_owned())
     }
 
-    /// Token is not provided
+    /// Token is not provided, etcd treats it as an empty user name
     pub(crate) fn token_not_provided() -> Self {
-        Self::AuthError("token is not provided".to_owned())
+        Self::user_empty()
     }
 
     /// Token is expired
     pub(crate) fn token_old_revision() -> Self {
-        Self::AuthError("token's revision is older than current revision".to_owned())
+        Self::AuthError("etcdserver: revision of auth store is old".to_owned())
+    }
+
+    /// The TTL of a token is negative or larger than the default TTL
+    pub(crate) fn invalid_token_ttl() -> Self {
+        Self::AuthError("etcdserver: invalid token ttl".to_owned())
+    }
+
+    /// The scope of a token has a permission of an unknown type
+    pub(crate) fn invalid_token_scope() -> Self {
+        Self::AuthError("etcdserver: invalid token scope".to_owned())
+    }
+
+    /// API key not found
+    pub(crate) fn api_key_not_found() -> Self {
+        Self::AuthError("etcdserver: api key not found".to_owned())
+    }
+
+    /// Member not found
+    pub(crate) fn member_not_found() -> Self {
+        Self::ClusterError("etcdserver: member not found".to_owned())
+    }
+
+    /// The peer URLs of a member are not one http URL of an address with a port
+    pub(crate) fn member_bad_urls() -> Self {
+        Self::ClusterError("etcdserver: given member URLs are invalid".to_owned())
+    }
+}
+
+impl From<ExecuteError> for tonic::Status {
+    #[inline]
+    fn from(err: ExecuteError) -> Self {
+        match err {
+            ExecuteError::DbError(_) => tonic::Status::internal(err.to_string()),
+            ExecuteError::KvError(msg)
+            | ExecuteError::LeaseError(msg)
+            | ExecuteError::AuthError(msg)
+            | ExecuteError::ClusterError(msg) => status_of_message(msg),
+            ExecuteError::PermissionDenied => status_of_message(err.to_string()),
+        }
+    }
+}
+
+/// Get the status of an execution error which is received as a string, the code is the
+/// one etcd returns with the message
+pub(crate) fn status_of_message(msg: String) -> tonic::Status {
+    let code = ETCD_ERRORS
+        .iter()
+        .find_map(|&(etcd_msg, code)| (etcd_msg == msg).then_some(code))
+        .unwrap_or_else(|| {
+            if msg.starts_with("db error") {
+                tonic::Code::Internal
+            } else {
+                tonic::Code::Unknown
+            }
+        });
+    tonic::Status::new(code, msg)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_status_of_execute_error() {
+        let cases = [
+            (ExecuteError::lease_not_found(), tonic::Code::NotFound),
+            (ExecuteError::lease_ttl_too_large(), tonic::Code::OutOfRange),
+            (
+                ExecuteError::PermissionDenied,
+                tonic::Code::PermissionDenied,
+            ),
+            (ExecuteError::too_many_ops(), tonic::Code::InvalidArgument),
+            (
+                ExecuteError::value_too_large(),
+                tonic::Code::InvalidArgument,
+            ),
+            (ExecuteError::corrupt(), tonic::Code::DataLoss),
+            (
+                ExecuteError::quota_exceeded(),
+                tonic::Code::ResourceExhausted,
+            ),
+            (
+                ExecuteError::namespace_quota_exceeded(),
+                tonic::Code::ResourceExhausted,
+            ),
+            (
+                ExecuteError::invalid_auth_token(),
+                tonic::Code::Unauthenticated,
+            ),
+            (
+                ExecuteError::auth_not_enabled(),
+                tonic::Code::FailedPrecondition,
+            ),
+            (
+                ExecuteError::DbError("io".to_owned()),
+                tonic::Code::Internal,
+            ),
+            (ExecuteError::token_manager_not_init(), tonic::Code::Unknown),
+            (ExecuteError::member_not_found(), tonic::Code::NotFound),
+            (
+                ExecuteError::member_bad_urls(),
+                tonic::Code::InvalidArgument,
+            ),
+        ];
+        for (err, code) in cases {
+            let msg = err.to_string();
+            let status = tonic::Status::from(err);
+            assert_eq!(status.code(), code, "{msg}");
+            assert_eq!(status.message(), msg);
+        }
+    }
+
+    #[test]
+    fn test_status_of_message_from_curp() {
+        let msg = ExecuteError::lease_not_found().to_string();
+        assert_eq!(status_of_message(msg).code(), tonic::Code::NotFound);
+        let msg = ExecuteError::DbError("io".to_owned()).to_string();
+        assert_eq!(status_of_message(msg).code(), tonic::Code::Internal);
     }
 }
@@ -1,26 +1,63 @@
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, ops::Bound};
 
 use clippy_utilities::OverflowArithmetic;
-use parking_lot::Mutex;
+use parking_lot::RwLock;
 
 use super::revision::{KeyRevision, Revision};
 use crate::server::command::{KeyRange, RangeType};
 
+/// Revisions of a key
+type Revisions = RwLock<Vec<KeyRevision>>;
+
+/// Keys deleted by `delete_keys` under one lock of the index, the lock is released between
+/// the batches so that new keys can be inserted meanwhile
+const DELETE_BATCH_SIZE: usize = 1000;
+
 /// KV store inner
+///
+/// The revisions of each key have their own lock, so the map only needs to be locked
+/// exclusively when a new key is inserted. Ranges and updates of existing keys share the
+/// map, and they only block each other on the same key.
 #[derive(Debug)]
 pub(crate) struct Index {
     /// index
-    index: Mutex<BTreeMap<Vec<u8>, Vec<KeyRevision>>>,
+    index: RwLock<BTreeMap<Vec<u8>, Revisions>>,
 }
 
 impl Index {
     /// New `Index`
     pub(crate) fn new() -> Self {
         Self {
-            index: Mutex::new(BTreeMap::new()),
+            index: RwLock::new(BTreeMap::new()),
         }
     }
 
+    /// Remove all keys from the index
+    pub(crate) fn clear(&self) {
+        self.index.write().clear();
+    }
+
+    /// Copy the keys and their revisions up to a revision out of the index, the keys
+    /// without such revisions are skipped. Keys inserted during the copy are blocked.
+    pub(crate) fn snapshot(&self, revision: i64) -> Vec<(Vec<u8>, Vec<KeyRevision>)> {
+        self.index
+            .read()
+            .iter()
+            .filter_map(|(key, revs)| {
+                let revs = revs.read();
+                let end = revs.partition_point(|rev| rev.mod_revision <= revision);
+                let revs = revs.get(..end).filter(|revs| !revs.is_empty())?.to_vec();
+                Some((key.clone(), revs))
+            })
+            .collect()
+    }
+
+    /// Load the revisions of a key copied by `snapshot`, the later revisions of the key are
+    /// restored after it
+    pub(crate) fn load(&self, key: Vec<u8>, revisions: Vec<KeyRevision>) {
+        let _prev = self.index.write().insert(key, RwLock::new(revisions));
+    }
+
     /// Filter out `KeyRevision` that is less than one revision and convert to `Revision`
     fn filter_revision(revs: &[KeyRevision], revision: i64) -> Vec<Revision> {
         revs.iter()
@@ -49,6 +86,51 @@ impl Index {
         rev.filter(|kr| !kr.is_deleted())
             .map(KeyRevision::as_revision)
     }
+
+    /// Check if a key exists at a revision, or at the latest revision if revision <= 0
+    fn is_alive(revs: &Revisions, revision: i64) -> bool {
+        Self::get_revision(&revs.read(), revision).is_some()
+    }
+
+    /// Mark a key as deleted if it is not, return its latest revision before the deletion
+    /// and the deletion revision
+    fn mark_deletion(
+        revs: &Revisions,
+        revision: i64,
+        sub_revision: i64,
+    ) -> Option<(Revision, Revision)> {
+        let mut revs = revs.write();
+        Self::get_revision(&revs, 0).map(|rev| {
+            let del_rev = KeyRevision::new_deletion(revision, sub_revision);
+            revs.push(del_rev);
+            (rev, del_rev.as_revision())
+        })
+    }
+
+    /// Append a new `KeyRevision` to the revisions of an existing key
+    fn update_revision(
+        revs: &Revisions,
+        key: &[u8],
+        revision: i64,
+        sub_revision: i64,
+    ) -> KeyRevision {
+        let mut revs = revs.write();
+        let Some(rev) = revs.last() else {
+            panic!("Get empty revision list for key {key:?}");
+        };
+        let new_rev = if rev.is_deleted() {
+            KeyRevision::new(revision, 1, revision, sub_revision)
+        } else {
+            KeyRevision::new(
+                rev.create_revision,
+                rev.version.overflow_add(1),
+                revision,
+                sub_revision,
+            )
+        };
+        revs.push(new_rev);
+        new_rev
+    }
 }
 
 /// Operations of Index
@@ -56,6 +138,9 @@ pub(crate) trait IndexOperate {
     /// Get `Revision` of keys, get the latest `Revision` when revision <= 0
     fn get(&self, key: &[u8], range_end: &[u8], revision: i64) -> Vec<Revision>;
 
+    /// Count the keys that exist at a revision, or at the latest revision if revision <= 0
+    fn count(&self, key: &[u8], range_end: &[u8], revision: i64) -> usize;
+
     /// Get `Revision` of keys from one revision
     fn get_from_rev(&self, key: &[u8], range_end: &[u8], revision: i64) -> Vec<Revision>;
 
@@ -68,6 +153,16 @@ pub(crate) trait IndexOperate {
         sub_revision: i64,
     ) -> Vec<(Revision, Revision)>;
 
+    /// Mark a list of keys as deleted in batches, the sub revision of each key is
+    /// `sub_revision` plus its position in `keys`. Return latest revision before deletion
+    /// and deletion revision of the keys which are not deleted already.
+    fn delete_keys(
+        &self,
+        keys: &[Vec<u8>],
+        revision: i64,
+        sub_revision: i64,
+    ) -> Vec<(Revision, Revision)>;
+
     /// Insert or update `KeyRevision` of a key
     fn insert_or_update_revision(
         &self,
@@ -86,49 +181,79 @@ pub(crate) trait IndexOperate {
         version: i64,
     );
 
-    // TODO: fn compact(rev:i64)
+    /// Compact the revisions of the keys at a revision. The latest revision of a key at
+    /// the compaction revision is kept unless it's a deletion, the older ones are removed,
+    /// and so are the keys left without revisions. Return the removed revisions.
+    fn compact(&self, revision: i64) -> Vec<Revision>;
 }
 
 impl IndexOperate for Index {
     fn get(&self, key: &[u8], range_end: &[u8], revision: i64) -> Vec<Revision> {
-        let index = self.index.lock();
+        let index = self.index.read();
         match RangeType::get_range_type(key, range_end) {
             RangeType::OneKey => index
                 .get(key)
-                .and_then(|revs| Self::get_revision(revs, revision))
+                .and_then(|revs| Self::get_revision(&revs.read(), revision))
                 .map(|rev| vec![rev])
                 .unwrap_or_default(),
             RangeType::AllKeys => index
                 .values()
-                .filter_map(|revs| Self::get_revision(revs, revision))
+                .filter_map(|revs| Self::get_revision(&revs.read(), revision))
                 .collect(),
             RangeType::Range => index
                 .range(KeyRange {
                     start: key.to_vec(),
                     end: range_end.to_vec(),
                 })
-                .filter_map(|(_k, revs)| Self::get_revision(revs, revision))
+                .filter_map(|(_k, revs)| Self::get_revision(&revs.read(), revision))
                 .collect(),
         }
     }
 
+    fn count(&self, key: &[u8], range_end: &[u8], revision: i64) -> usize {
+        let index = self.index.read();
+        match RangeType::get_range_type(key, range_end) {
+            RangeType::OneKey => index
+                .get(key)
+                .map_or(0, |revs| usize::from(Self::is_alive(revs, revision))),
+            RangeType::AllKeys => index
+                .values()
+                .filter(|revs| Self::is_alive(revs, revision))
+                .count(),
+            // a prefix is counted by walking the keys from the prefix while they share it,
+            // without building the range or comparing against its end
+            RangeType::Range if range_end == KeyRange::get_prefix(key) => index
+                .range::<[u8], _>((Bound::Included(key), Bound::Unbounded))
+                .take_while(|&(k, _)| k.starts_with(key))
+                .filter(|&(_, revs)| Self::is_alive(revs, revision))
+                .count(),
+            RangeType::Range => index
+                .range(KeyRange {
+                    start: key.to_vec(),
+                    end: range_end.to_vec(),
+                })
+                .filter(|&(_, revs)| Self::is_alive(revs, revision))
+                .count(),
+        }
+    }
+
     fn get_from_rev(&self, key: &[u8], range_end: &[u8], revision: i64) -> Vec<Revision> {
-        let index = self.index.lock();
+        let index = self.index.read();
         match RangeType::get_range_type(key, range_end) {
             RangeType::OneKey => index
                 .get(key)
-                .map(|revs| Self::filter_revision(revs, revision))
+                .map(|revs| Self::filter_revision(&revs.read(), revision))
                 .unwrap_or_default(),
             RangeType::AllKeys => index
                 .values()
-                .flat_map(|revs| Self::filter_revision(revs, revision))
+                .flat_map(|revs| Self::filter_revision(&revs.read(), revision))
                 .collect(),
             RangeType::Range => index
                 .range(KeyRange {
                     start: key.to_vec(),
                     end: range_end.to_vec(),
                 })
-                .flat_map(|(_k, revs)| Self::filter_revision(revs, revision))
+                .flat_map(|(_k, revs)| Self::filter_revision(&revs.read(), revision))
                 .collect(),
         }
     }
@@ -140,79 +265,72 @@ impl IndexOperate for Index {
         revision: i64,
         sub_revision: i64,
     ) -> Vec<(Revision, Revision)> {
-        let mut index = self.index.lock();
+        let index = self.index.read();
         match RangeType::get_range_type(key, range_end) {
             RangeType::OneKey => index
-                .get_mut(key)
-                .and_then(|revs| {
-                    Self::get_revision(revs, 0)
-                        .map(|rev| {
-                            let del_rev = KeyRevision::new_deletion(revision, sub_revision);
-                            revs.push(del_rev);
-                            (rev, del_rev.as_revision())
-                        })
-                        .map(|rev_pair| vec![rev_pair])
-                })
+                .get(key)
+                .and_then(|revs| Self::mark_deletion(revs, revision, sub_revision))
+                .map(|rev_pair| vec![rev_pair])
                 .unwrap_or_default(),
             RangeType::AllKeys => index
-                .values_mut()
+                .values()
                 .zip(0..)
                 .filter_map(|(revs, i)| {
-                    Self::get_revision(revs, 0).map(|rev| {
-                        let del_rev =
-                            KeyRevision::new_deletion(revision, sub_revision.overflow_add(i));
-                        revs.push(del_rev);
-                        (rev, del_rev.as_revision())
-                    })
+                    Self::mark_deletion(revs, revision, sub_revision.overflow_add(i))
                 })
                 .collect(),
             RangeType::Range => index
-                .range_mut(KeyRange {
+                .range(KeyRange {
                     start: key.to_vec(),
                     end: range_end.to_vec(),
                 })
                 .zip(0..)
                 .filter_map(|((_k, revs), i)| {
-                    Self::get_revision(revs, 0).map(|rev| {
-                        let del_rev =
-                            KeyRevision::new_deletion(revision, sub_revision.overflow_add(i));
-                        revs.push(del_rev);
-                        (rev, del_rev.as_revision())
-                    })
+                    Self::mark_deletion(revs, revision, sub_revision.overflow_add(i))
                 })
                 .collect(),
         }
     }
 
+    fn delete_keys(
+        &self,
+        keys: &[Vec<u8>],
+        revision: i64,
+        sub_revision: i64,
+    ) -> Vec<(Revision, Revision)> {
+        let mut deleted = Vec::with_capacity(keys.len());
+        let mut sub_revisions = sub_revision..;
+        for batch in keys.chunks(DELETE_BATCH_SIZE) {
+            let index = self.index.read();
+            for (key, sub_rev) in batch.iter().zip(sub_revisions.by_ref()) {
+                if let Some(rev_pair) = index
+                    .get(key)
+                    .and_then(|revs| Self::mark_deletion(revs, revision, sub_rev))
+                {
+                    deleted.push(rev_pair);
+                }
+            }
+        }
+        deleted
+    }
+
     fn insert_or_update_revision(
         &self,
         key: &[u8],
         revision: i64,
         sub_revision: i64,
     ) -> KeyRevision {
-        let mut index = self.index.lock();
-        if let Some(revisions) = index.get_mut(key) {
-            if let Some(rev) = revisions.last() {
-                let new_rev = if rev.is_deleted() {
-                    KeyRevision::new(revision, 1, revision, sub_revision)
-                } else {
-                    KeyRevision::new(
-                        rev.create_revision,
-                        rev.version.overflow_add(1),
-                        revision,
-                        sub_revision,
-                    )
-                };
-                revisions.push(new_rev);
-                new_rev
-            } else {
-                panic!("Get empty revision list for key {key:?}");
-            }
-        } else {
-            let new_rev = KeyRevision::new(revision, 1, revision, sub_revision);
-            let _prev_val = index.insert(key.to_vec(), vec![new_rev]);
-            new_rev
+        if let Some(revs) = self.index.read().get(key) {
+            return Self::update_revision(revs, key, revision, sub_revision);
         }
+        // the key may be inserted after the read lock is released, check it again
+        let mut index = self.index.write();
+        if let Some(revs) = index.get(key) {
+            return Self::update_revision(revs, key, revision, sub_revision);
+        }
+        let new_rev = KeyRevision::new(revision, 1, revision, sub_revision);
+        let _prev_val = index.insert(key.to_vec(), RwLock::new(vec![new_rev]));
+        new_rev
     }
 
     fn restore(
@@ -223,16 +341,51 @@ impl IndexOperate for Index {
         create_revision: i64,
         version: i64,
     ) {
-        let mut index = self.index.lock();
+        let mut index = self.index.write();
         let new_rev = KeyRevision::new(create_revision, version, revision, sub_revision);
-        index.entry(key).or_insert_with(Vec::new).push(new_rev);
+        index
+            .entry(key)
+            .or_insert_with(|| RwLock::new(Vec::new()))
+            .get_mut()
+            .push(new_rev);
+    }
+
+    fn compact(&self, revision: i64) -> Vec<Revision> {
+        let mut compacted = vec![];
+        self.index.write().retain(|_key, revs| {
+            let revs = revs.get_mut();
+            let at_revision = revs.partition_point(|rev| rev.mod_revision <= revision);
+            let Some(latest) = at_revision.checked_sub(1).and_then(|idx| revs.get(idx)) else {
+                return true;
+            };
+            let end = if latest.is_deleted() {
+                at_revision
+            } else {
+                at_revision.overflow_sub(1)
+            };
+            compacted.extend(revs.drain(..end).map(|rev| rev.as_revision()));
+            !revs.is_empty()
+        });
+        compacted
     }
 }
 
 #[cfg(test)]
 mod test {
+    use std::{sync::Arc, thread};
+
     use super::*;
 
+    /// Copy the keys and their revisions out of the index
+    fn dump(index: &Index) -> BTreeMap<Vec<u8>, Vec<KeyRevision>> {
+        index
+            .index
+            .read()
+            .iter()
+            .map(|(key, revs)| (key.clone(), revs.read().clone()))
+            .collect()
+    }
+
     fn init_and_test_insert() -> Index {
         let index = Index::new();
 
@@ -241,7 +394,7 @@ mod test {
         index.insert_or_update_revision(b"key", 3, 1);
 
         assert_eq!(
-            *index.index.lock(),
+            dump(&index),
             BTreeMap::from_iter(vec![(
                 b"key".to_vec(),
                 vec![
@@ -266,6 +419,25 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_count() {
+        let index = init_and_test_insert();
+        index.insert_or_update_revision(b"key1", 4, 0);
+        index.insert_or_update_revision(b"kez", 5, 0);
+        index.delete(b"key1", b"", 6, 0);
+        index.insert_or_update_revision(b"\xff\xff", 7, 0);
+
+        assert_eq!(index.count(b"key", b"", 0), 1);
+        assert_eq!(index.count(b"key1", b"", 0), 0);
+        assert_eq!(index.count(b"key1", b"", 5), 1);
+        assert_eq!(index.count(b"\0", b"\0", 0), 3);
+        assert_eq!(index.count(b"key", b"kez", 0), 1);
+        assert_eq!(index.count(b"key", &KeyRange::get_prefix(b"key"), 0), 1);
+        assert_eq!(index.count(b"key", &KeyRange::get_prefix(b"key"), 4), 2);
+        assert_eq!(index.count(b"k", &KeyRange::get_prefix(b"k"), 0), 2);
+        assert_eq!(index.count(b"\xff", &KeyRange::get_prefix(b"\xff"), 0), 1);
+    }
+
     #[test]
     fn test_delete() {
         let index = init_and_test_insert();
@@ -274,7 +446,7 @@ mod test {
             vec![(Revision::new(3, 1), Revision::new(4, 0))]
         );
         assert_eq!(
-            *index.index.lock(),
+            dump(&index),
             BTreeMap::from_iter(vec![(
                 b"key".to_vec(),
                 vec![
@@ -287,6 +459,86 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_delete_keys() {
+        let index = init_and_test_insert();
+        let keys = (0..DELETE_BATCH_SIZE.overflow_add(2))
+            .map(|i| format!("key{i}").into_bytes())
+            .collect::<Vec<_>>();
+        for key in &keys {
+            index.insert_or_update_revision(key, 4, 0);
+        }
+        index.delete(&keys[1], b"", 5, 0);
+
+        let deleted = index.delete_keys(&keys, 6, 0);
+        assert_eq!(deleted.len(), keys.len().overflow_sub(1));
+        assert_eq!(deleted[0], (Revision::new(4, 0), Revision::new(6, 0)));
+        assert_eq!(deleted[1], (Revision::new(4, 0), Revision::new(6, 2)));
+        assert_eq!(
+            deleted.last(),
+            Some(&(Revision::new(4, 0), Revision::new(6, 1001)))
+        );
+        assert!(index.get(&keys[0], b"", 0).is_empty());
+        assert_eq!(index.get(b"key", b"", 0), vec![Revision::new(3, 1)]);
+    }
+
+    #[test]
+    fn test_compact() {
+        let index = init_and_test_insert();
+        index.insert_or_update_revision(b"foo", 4, 0);
+        index.delete(b"foo", b"", 5, 0);
+        index.insert_or_update_revision(b"bar", 6, 0);
+
+        assert_eq!(index.compact(1), vec![]);
+        assert_eq!(index.compact(2), vec![Revision::new(1, 3)]);
+        assert_eq!(
+            index.compact(5),
+            vec![
+                Revision::new(4, 0),
+                Revision::new(5, 0),
+                Revision::new(2, 2)
+            ]
+        );
+        assert_eq!(
+            dump(&index),
+            BTreeMap::from_iter(vec![
+                (b"bar".to_vec(), vec![KeyRevision::new(6, 1, 6, 0)]),
+                (b"key".to_vec(), vec![KeyRevision::new(1, 3, 3, 1)]),
+            ])
+        );
+        assert_eq!(index.get(b"key", b"", 3), vec![Revision::new(3, 1)]);
+    }
+
+    #[test]
+    fn test_snapshot_and_load() {
+        let index = init_and_test_insert();
+        index.insert_or_update_revision(b"foo", 4, 0);
+        index.delete(b"foo", b"", 5, 0);
+        let snapshot = index.snapshot(4);
+        assert_eq!(
+            snapshot,
+            vec![
+                (b"foo".to_vec(), vec![KeyRevision::new(4, 1, 4, 0)]),
+                (
+                    b"key".to_vec(),
+                    vec![
+                        KeyRevision::new(1, 1, 1, 3),
+                        KeyRevision::new(1, 2, 2, 2),
+                        KeyRevision::new(1, 3, 3, 1),
+                    ]
+                ),
+            ]
+        );
+        assert!(index.snapshot(0).is_empty());
+
+        let loaded = Index::new();
+        for (key, revisions) in snapshot {
+            loaded.load(key, revisions);
+        }
+        loaded.restore(b"foo".to_vec(), 5, 0, 0, 0);
+        assert_eq!(dump(&loaded), dump(&index));
+    }
+
     #[test]
     fn test_restore() {
         let index = Index::new();
@@ -294,7 +546,7 @@ mod test {
         index.restore(b"key".to_vec(), 3, 0, 2, 2);
         index.restore(b"foo".to_vec(), 4, 0, 4, 1);
         assert_eq!(
-            *index.index.lock(),
+            dump(&index),
             BTreeMap::from_iter(vec![
                 (b"foo".to_vec(), vec![KeyRevision::new(4, 1, 4, 0)]),
                 (
@@ -304,4 +556,27 @@ mod test {
             ])
         );
     }
+
+    #[test]
+    fn test_get_while_inserting() {
+        let index = Arc::new(Index::new());
+        index.insert_or_update_revision(b"key", 1, 0);
+        let writer = thread::spawn({
+            let index = Arc::clone(&index);
+            move || {
+                for i in 2_i64..1000 {
+                    let key = format!("key{}", i.rem_euclid(10));
+                    index.insert_or_update_revision(key.as_bytes(), i, 0);
+                    index.insert_or_update_revision(b"key", i, 1);
+                }
+            }
+        });
+        for _ in 0..1000 {
+            let revs = index.get(b"key", b"kez", 0);
+            assert!(!revs.is_empty() && revs.len() <= 11);
+        }
+        writer.join().unwrap();
+        assert_eq!(index.get(b"key", b"kez", 0).len(), 11);
+        assert_eq!(index.get(b"key", b"", 0), vec![Revision::new(999, 1)]);
+    }
 }
@@ -0,0 +1,122 @@
+use clippy_utilities::NumericCast;
+use prost::bytes::{Buf, BufMut};
+
+use super::{revision::KeyRevision, ExecuteError};
+
+/// Key of the index snapshot in the meta table
+pub(crate) const INDEX_SNAPSHOT_KEY: &str = "index_snapshot";
+
+/// Snapshot of the index at a revision. The index is recovered from it and the revisions
+/// after it in the kv table, instead of the whole kv table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct IndexSnapshot {
+    /// The revision of the snapshot, the revisions after it are not in the snapshot
+    pub(crate) revision: i64,
+    /// The compacted revision of the index when the snapshot is taken
+    pub(crate) compact_revision: i64,
+    /// Keys in the snapshot
+    pub(crate) keys: Vec<KeySnapshot>,
+}
+
+/// Revisions of a key in the index snapshot
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct KeySnapshot {
+    /// The key
+    pub(crate) key: Vec<u8>,
+    /// The lease attached to the latest value of the key, 0 if there isn't one
+    pub(crate) lease: i64,
+    /// Revisions of the key
+    pub(crate) revisions: Vec<KeyRevision>,
+}
+
+impl IndexSnapshot {
+    /// Encode the snapshot to be stored in the meta table
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.put_i64(self.revision);
+        buf.put_i64(self.compact_revision);
+        for key in &self.keys {
+            buf.put_u32(key.key.len().numeric_cast());
+            buf.put_slice(&key.key);
+            buf.put_i64(key.lease);
+            buf.put_u32(key.revisions.len().numeric_cast());
+            for rev in &key.revisions {
+                buf.put_i64(rev.create_revision);
+                buf.put_i64(rev.version);
+                buf.put_i64(rev.mod_revision);
+                buf.put_i64(rev.sub_revision);
+            }
+        }
+        buf
+    }
+
+    /// Decode the snapshot read from the meta table
+    pub(crate) fn decode(mut buf: &[u8]) -> Result<Self, ExecuteError> {
+        let invalid = || ExecuteError::DbError("Invalid index snapshot".to_owned());
+        let check =
+            |buf: &[u8], len: usize| (buf.remaining() >= len).then_some(()).ok_or_else(invalid);
+        let len = |n: u32| usize::try_from(n).map_err(|_ignore| invalid());
+        check(buf, 16)?;
+        let revision = buf.get_i64();
+        let compact_revision = buf.get_i64();
+        let mut keys = vec![];
+        while buf.has_remaining() {
+            check(buf, 4)?;
+            let key_len = len(buf.get_u32())?;
+            check(buf, key_len.saturating_add(12))?;
+            let key = buf.copy_to_bytes(key_len).to_vec();
+            let lease = buf.get_i64();
+            let count = len(buf.get_u32())?;
+            check(buf, count.saturating_mul(32))?;
+            let revisions = (0..count)
+                .map(|_| {
+                    KeyRevision::new(buf.get_i64(), buf.get_i64(), buf.get_i64(), buf.get_i64())
+                })
+                .collect();
+            keys.push(KeySnapshot {
+                key,
+                lease,
+                revisions,
+            });
+        }
+        Ok(Self {
+            revision,
+            compact_revision,
+            keys,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use clippy_utilities::OverflowArithmetic;
+
+    use super::*;
+
+    #[test]
+    fn test_encode_and_decode() {
+        let snapshot = IndexSnapshot {
+            revision: 10,
+            compact_revision: 3,
+            keys: vec![
+                KeySnapshot {
+                    key: b"bar".to_vec(),
+                    lease: 0,
+                    revisions: vec![
+                        KeyRevision::new(4, 1, 4, 0),
+                        KeyRevision::new_deletion(6, 0),
+                    ],
+                },
+                KeySnapshot {
+                    key: b"foo".to_vec(),
+                    lease: 7,
+                    revisions: vec![KeyRevision::new(2, 3, 9, 1)],
+                },
+            ],
+        };
+        let buf = snapshot.encode();
+        assert_eq!(IndexSnapshot::decode(&buf).unwrap(), snapshot);
+        assert!(IndexSnapshot::decode(&buf[..buf.len().overflow_sub(1)]).is_err());
+        assert!(IndexSnapshot::decode(&buf[..8]).is_err());
+    }
+}
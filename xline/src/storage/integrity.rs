@@ -0,0 +1,395 @@
+use std::collections::HashMap;
+
+use clippy_utilities::NumericCast;
+use curp::cmd::ProposeId;
+use prost::Message;
+
+use super::{
+    db::{WriteOp, XLINE_TABLES},
+    durable_watch::DURABLE_WATCH_TABLE,
+    index::{Index, IndexOperate},
+    kv_store::{read_compact_revision, KV_TABLE},
+    storage_api::StorageApi,
+    ExecuteError, Revision,
+};
+use crate::{
+    rpc::KeyValue,
+    server::command::{APPLIED_INDEX_KEY, META_TABLE},
+};
+
+/// Key of the table checksums in the meta table
+pub(crate) const CHECKSUMS_KEY: &str = "checksums";
+
+/// Range start and end to get all keys from the index
+const ALL_KEYS: &[u8] = &[0];
+
+/// Checksums of the tables, recorded when the server shuts down and verified when it starts
+/// again. The meta table is not covered, the checksums are stored in it, nor is the durable
+/// watch table, which the watch streams may write while the server shuts down.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct TableChecksums {
+    /// Applied index when the checksums are computed, the checksums are only valid if no
+    /// more log entries are applied since then
+    applied_index: u64,
+    /// Crc32 of each table
+    checksums: Vec<(String, u32)>,
+}
+
+impl TableChecksums {
+    /// Compute the checksums of the tables, `None` if some log entries are applied while
+    /// they are computed
+    pub(crate) fn compute<S: StorageApi>(storage: &S) -> Result<Option<Self>, ExecuteError> {
+        let applied_index = read_applied_index(storage)?;
+        let checksums = XLINE_TABLES
+            .into_iter()
+            .filter(|&table| table != META_TABLE && table != DURABLE_WATCH_TABLE)
+            .map(|table| Ok((table.to_owned(), table_checksum(storage, table)?)))
+            .collect::<Result<_, ExecuteError>>()?;
+        if applied_index != read_applied_index(storage)? {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            applied_index,
+            checksums,
+        }))
+    }
+
+    /// Record the checksums in the meta table
+    pub(crate) fn record<S: StorageApi>(&self, storage: &S) -> Result<(), ExecuteError> {
+        let id = ProposeId::new(CHECKSUMS_KEY.to_owned());
+        storage.buffer_op(&id, WriteOp::PutChecksums(self.encode()));
+        storage.flush(&id)?;
+        storage.sync()
+    }
+
+    /// Take the recorded checksums out of the meta table, they are outdated as soon as the
+    /// server writes again
+    pub(crate) fn take<S: StorageApi>(storage: &S) -> Result<Option<Self>, ExecuteError> {
+        let Some(buf) = storage.get_value(META_TABLE, CHECKSUMS_KEY)? else {
+            return Ok(None);
+        };
+        let id = ProposeId::new(CHECKSUMS_KEY.to_owned());
+        storage.buffer_op(&id, WriteOp::DeleteChecksums);
+        storage.flush(&id)?;
+        storage.sync()?;
+        Self::decode(&buf).map(Some)
+    }
+
+    /// Verify the checksums against the current tables, return the problems found. They
+    /// are not verified if log entries are applied after they are recorded, eg. the server
+    /// is killed after it shuts down the grpc server.
+    pub(crate) fn verify<S: StorageApi>(&self, storage: &S) -> Result<Vec<String>, ExecuteError> {
+        if read_applied_index(storage)? != self.applied_index {
+            return Ok(vec![]);
+        }
+        let mut problems = vec![];
+        for &(ref name, recorded) in &self.checksums {
+            let Some(&table) = XLINE_TABLES.iter().find(|&&t| t == name.as_str()) else {
+                continue;
+            };
+            let checksum = table_checksum(storage, table)?;
+            if checksum != recorded {
+                problems.push(format!(
+                    "checksum of table {table} is {checksum:#010x}, {recorded:#010x} is recorded at shutdown"
+                ));
+            }
+        }
+        Ok(problems)
+    }
+
+    /// Encode the checksums to be stored in the meta table
+    fn encode(&self) -> Vec<u8> {
+        let mut buf = self.applied_index.to_be_bytes().to_vec();
+        for &(ref table, checksum) in &self.checksums {
+            buf.push(table.len().numeric_cast());
+            buf.extend_from_slice(table.as_bytes());
+            buf.extend_from_slice(&checksum.to_be_bytes());
+        }
+        buf
+    }
+
+    /// Decode the checksums read from the meta table
+    fn decode(mut buf: &[u8]) -> Result<Self, ExecuteError> {
+        let invalid = || ExecuteError::DbError("Invalid table checksums".to_owned());
+        let (index, rest) = buf.split_at(buf.len().min(8));
+        let applied_index = u64::from_be_bytes(index.try_into().map_err(|_ignore| invalid())?);
+        buf = rest;
+        let mut checksums = vec![];
+        while let Some((&len, rest)) = buf.split_first() {
+            let len = usize::from(len);
+            let table = rest.get(..len).ok_or_else(invalid)?;
+            let checksum = rest
+                .get(len..len.saturating_add(4))
+                .ok_or_else(invalid)?
+                .try_into()
+                .map_err(|_ignore| invalid())?;
+            let table = String::from_utf8(table.to_vec()).map_err(|_ignore| invalid())?;
+            checksums.push((table, u32::from_be_bytes(checksum)));
+            buf = rest.get(len.saturating_add(4)..).unwrap_or_default();
+        }
+        Ok(Self {
+            applied_index,
+            checksums,
+        })
+    }
+}
+
+/// Get the applied index stored in the meta table
+fn read_applied_index<S: StorageApi>(storage: &S) -> Result<u64, ExecuteError> {
+    let Some(buf) = storage.get_value(META_TABLE, APPLIED_INDEX_KEY)? else {
+        return Ok(0);
+    };
+    let buf = buf
+        .try_into()
+        .map_err(|_ignore| ExecuteError::DbError("Invalid applied index".to_owned()))?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+/// Crc32 of all keys and values of a table
+fn table_checksum<S: StorageApi>(storage: &S, table: &'static str) -> Result<u32, ExecuteError> {
+    let mut hasher = crc32fast::Hasher::new();
+    storage.scan(table, |key, value| {
+        hash_entry(&mut hasher, key, value);
+        Ok(())
+    })?;
+    Ok(hasher.finalize())
+}
+
+/// Crc32 of the key values in the kv table up to `revision` and the compacted revision it's
+/// computed at. It's the same on every member which has applied the revision and compacted
+/// at the same revision unless the storage of the member is corrupted.
+pub(crate) fn hash_kv<S: StorageApi>(
+    storage: &S,
+    revision: i64,
+) -> Result<(u32, i64), ExecuteError> {
+    let compact_revision = read_compact_revision(storage)?;
+    if revision < compact_revision {
+        return Err(ExecuteError::compacted());
+    }
+    let mut hasher = crc32fast::Hasher::new();
+    storage.scan(KV_TABLE, |key, value| {
+        // an invalid revision is hashed too, it's a corruption to be found
+        if key.len() != 16 || Revision::decode(key).revision() <= revision {
+            hash_entry(&mut hasher, key, value);
+        }
+        Ok(())
+    })?;
+    // the hash is incomparable if some revisions are removed while they are hashed
+    if read_compact_revision(storage)? != compact_revision {
+        return Err(ExecuteError::DbError(
+            "The kv table is compacted while it's hashed".to_owned(),
+        ));
+    }
+    Ok((hasher.finalize(), compact_revision))
+}
+
+/// Feed a key and its value to `hasher`, their lengths are fed too so that the boundary
+/// between them can't be moved without changing the hash
+fn hash_entry(hasher: &mut crc32fast::Hasher, key: &[u8], value: &[u8]) {
+    hasher.update(&key.len().numeric_cast::<u64>().to_be_bytes());
+    hasher.update(key);
+    hasher.update(&value.len().numeric_cast::<u64>().to_be_bytes());
+    hasher.update(value);
+}
+
+/// Check the kv table before the index is recovered from it, return the problems found.
+/// Every key must be a revision, every value must be a key value of that revision, and the
+/// versions of a key must be continuous after the compacted revision.
+pub(crate) fn check_kv_table<S: StorageApi>(storage: &S) -> Result<Vec<String>, ExecuteError> {
+    let compact_revision = read_compact_revision(storage)?;
+    let mut problems = vec![];
+    // last create revision and version of the keys
+    let mut latest: HashMap<Vec<u8>, (i64, i64)> = HashMap::new();
+    storage.scan(KV_TABLE, |key, value| {
+        if key.len() != 16 {
+            problems.push(format!("invalid revision {key:?} in kv table"));
+            return Ok(());
+        }
+        let rev = Revision::decode(key);
+        let kv = match KeyValue::decode(value) {
+            Ok(kv) => kv,
+            Err(e) => {
+                problems.push(format!("invalid key value at revision {rev:?}, {e}"));
+                return Ok(());
+            }
+        };
+        if kv.mod_revision != rev.revision() {
+            problems.push(format!(
+                "key value at revision {rev:?} has mod revision {}",
+                kv.mod_revision
+            ));
+        }
+        // a deletion has neither create revision nor version
+        let is_deletion = kv.create_revision == 0 && kv.version == 0;
+        let expected = match latest.get(&kv.key) {
+            Some(&(create_revision, version)) if version != 0 => {
+                (create_revision, version.saturating_add(1))
+            }
+            // the earlier versions of a key may be compacted
+            None if rev.revision() <= compact_revision => (kv.create_revision, kv.version),
+            _ => (rev.revision(), 1),
+        };
+        if !is_deletion && (kv.create_revision, kv.version) != expected {
+            problems.push(format!(
+                "key {:?} at revision {rev:?} has create revision {} and version {}, expect {} and {}",
+                kv.key, kv.create_revision, kv.version, expected.0, expected.1
+            ));
+        }
+        let _prev = latest.insert(kv.key, (kv.create_revision, kv.version));
+        Ok(())
+    })?;
+    Ok(problems)
+}
+
+/// Cross check the recovered index against the kv table, return the problems found. The
+/// latest revision of every key in the index must be the latest key value of that key in
+/// the kv table.
+pub(crate) fn check_index<S: StorageApi>(
+    storage: &S,
+    index: &Index,
+) -> Result<Vec<String>, ExecuteError> {
+    let mut problems = vec![];
+    let mut live_keys: HashMap<Vec<u8>, Revision> = HashMap::new();
+    storage.scan(KV_TABLE, |key, value| {
+        let rev = Revision::decode(key);
+        let kv = KeyValue::decode(value).map_err(|e| {
+            ExecuteError::DbError(format!("Failed to decode key-value from DB, error: {e}"))
+        })?;
+        if kv.create_revision == 0 && kv.version == 0 {
+            let _prev = live_keys.remove(&kv.key);
+        } else {
+            let _prev = live_keys.insert(kv.key, rev);
+        }
+        Ok(())
+    })?;
+    let revisions = index.get(ALL_KEYS, ALL_KEYS, 0);
+    if revisions.len() != live_keys.len() {
+        problems.push(format!(
+            "index has {} keys, kv table has {} keys",
+            revisions.len(),
+            live_keys.len()
+        ));
+    }
+    let values = storage.get_values(
+        KV_TABLE,
+        &revisions
+            .iter()
+            .map(Revision::encode_to_vec)
+            .collect::<Vec<_>>(),
+    )?;
+    for (rev, value) in revisions.iter().zip(values) {
+        let Some(value) = value else {
+            problems.push(format!("revision {rev:?} in index is not in kv table"));
+            continue;
+        };
+        let kv = KeyValue::decode(value.as_slice()).map_err(|e| {
+            ExecuteError::DbError(format!("Failed to decode key-value from DB, error: {e}"))
+        })?;
+        if live_keys.get(&kv.key) != Some(rev) {
+            problems.push(format!(
+                "latest revision of key {:?} is {rev:?} in index, {:?} in kv table",
+                kv.key,
+                live_keys.get(&kv.key)
+            ));
+        }
+    }
+    Ok(problems)
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use utils::config::StorageConfig;
+
+    use super::*;
+    use crate::storage::db::DBProxy;
+
+    fn put(db: &DBProxy, rev: i64, kv: KeyValue) {
+        let id = ProposeId::new(format!("put-{rev}"));
+        db.buffer_op(
+            &id,
+            WriteOp::PutKeyValue(Revision::new(rev, 0), kv.encode_to_vec()),
+        );
+        db.flush(&id).unwrap();
+    }
+
+    fn kv(key: &str, create_revision: i64, mod_revision: i64, version: i64) -> KeyValue {
+        KeyValue {
+            key: key.as_bytes().to_vec(),
+            create_revision,
+            mod_revision,
+            version,
+            ..KeyValue::default()
+        }
+    }
+
+    fn init_db() -> Arc<DBProxy> {
+        let db = DBProxy::open(&StorageConfig::Memory).unwrap();
+        put(&db, 2, kv("foo", 2, 2, 1));
+        put(&db, 3, kv("foo", 2, 3, 2));
+        put(&db, 4, kv("bar", 4, 4, 1));
+        put(&db, 5, kv("bar", 0, 5, 0));
+        db
+    }
+
+    #[test]
+    fn test_checksums_should_detect_changes() {
+        let db = init_db();
+        let checksums = TableChecksums::compute(db.as_ref()).unwrap().unwrap();
+        assert_eq!(
+            TableChecksums::decode(&checksums.encode()).unwrap(),
+            checksums
+        );
+        checksums.record(db.as_ref()).unwrap();
+
+        let recorded = TableChecksums::take(db.as_ref()).unwrap().unwrap();
+        assert!(recorded.verify(db.as_ref()).unwrap().is_empty());
+        assert!(TableChecksums::take(db.as_ref()).unwrap().is_none());
+
+        put(&db, 6, kv("baz", 6, 6, 1));
+        assert_eq!(recorded.verify(db.as_ref()).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_hash_kv_up_to_revision() {
+        let db = init_db();
+        let hash = hash_kv(db.as_ref(), 5).unwrap();
+        assert_ne!(hash_kv(db.as_ref(), 3).unwrap(), hash);
+
+        put(&db, 6, kv("baz", 6, 6, 1));
+        assert_eq!(hash_kv(db.as_ref(), 5).unwrap(), hash);
+        assert_ne!(hash_kv(db.as_ref(), 6).unwrap(), hash);
+
+        let id = ProposeId::new("compact".to_owned());
+        db.buffer_op(&id, WriteOp::DeleteKeyValue(Revision::new(2, 0)));
+        db.buffer_op(&id, WriteOp::PutCompactRevision(3));
+        db.flush(&id).unwrap();
+        assert_eq!(hash_kv(db.as_ref(), 5).unwrap().1, 3);
+        assert!(hash_kv(db.as_ref(), 2).is_err());
+        assert!(check_kv_table(db.as_ref()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_check_kv_table() {
+        let db = init_db();
+        assert!(check_kv_table(db.as_ref()).unwrap().is_empty());
+
+        // version 2 of foo is lost
+        put(&db, 6, kv("foo", 2, 6, 4));
+        put(&db, 7, kv("baz", 7, 8, 1));
+        assert_eq!(check_kv_table(db.as_ref()).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_check_index() {
+        let db = init_db();
+        let index = Index::new();
+        index.restore(b"foo".to_vec(), 2, 0, 2, 1);
+        index.restore(b"foo".to_vec(), 3, 0, 2, 2);
+        assert!(check_index(db.as_ref(), &index).unwrap().is_empty());
+
+        index.restore(b"bar".to_vec(), 4, 0, 4, 1);
+        assert!(!check_index(db.as_ref(), &index).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,558 @@
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+
+use clippy_utilities::OverflowArithmetic;
+use curp::cmd::ProposeId;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use tokio::sync::mpsc;
+use utils::config::StorageConfig;
+
+use super::{
+    db::DBProxy, index::Index, kvwatcher::KvWatcherOps, lease_store::LeaseMessage,
+    storage_api::StorageApi, watch_filter::EventFilter, ExecuteError, KvStore,
+};
+use crate::{
+    header_gen::HeaderGenerator,
+    rpc::{
+        CompactionRequest, CompactionResponse, Compare, CompareResult, CompareTarget,
+        DeleteRangeRequest, DeleteRangeResponse, Event, EventType, KeyValue, PutRequest,
+        PutResponse, RangeRequest, RangeResponse, Request, RequestOp, RequestWithToken,
+        RequestWrapper, Response, ResponseOp, ResponseWrapper, TargetUnion, TxnRequest,
+        TxnResponse,
+    },
+    server::command::KeyRange,
+};
+
+/// Number of the random sequences checked
+const SEQUENCES: u64 = 50;
+/// Number of the requests in a sequence
+const REQUESTS_PER_SEQUENCE: usize = 100;
+/// Keys of the requests, a small key space makes the requests touch the same keys often
+const KEYS: [&str; 5] = ["a", "b", "c", "d", "e"];
+/// Values of the puts, a few values make the value compares succeed sometimes
+const VALUES: [&str; 3] = ["0", "1", "2"];
+
+/// Reference model of the kv store. It keeps the whole key space of every revision not
+/// compacted, which is simple enough to be obviously right.
+#[derive(Debug)]
+struct Model {
+    /// The latest revision, every synced request takes a revision
+    revision: i64,
+    /// The compacted revision
+    compact_revision: i64,
+    /// The key space at each revision from the compacted one
+    history: BTreeMap<i64, BTreeMap<Vec<u8>, KeyValue>>,
+    /// The events expected by a watcher of the whole key space
+    events: Vec<(i64, Event)>,
+}
+
+impl Model {
+    /// New empty model at the initial revision of the kv store
+    fn new() -> Self {
+        Self {
+            revision: 1,
+            compact_revision: 0,
+            history: BTreeMap::from([(1, BTreeMap::new())]),
+            events: vec![],
+        }
+    }
+
+    /// The key space at the latest revision
+    fn current(&self) -> &BTreeMap<Vec<u8>, KeyValue> {
+        &self.history[&self.revision]
+    }
+
+    /// Get the key values in a range at a revision, 0 means the latest one
+    fn range(
+        &self,
+        key: &[u8],
+        range_end: &[u8],
+        revision: i64,
+    ) -> Result<Vec<KeyValue>, ExecuteError> {
+        if revision > 0 && revision < self.compact_revision {
+            return Err(ExecuteError::compacted());
+        }
+        let revision = if revision == 0 {
+            self.revision
+        } else {
+            revision
+        };
+        Ok(self.history[&revision]
+            .iter()
+            .filter(|&(k, _)| in_range(key, range_end, k))
+            .map(|(_, kv)| kv.clone())
+            .collect())
+    }
+
+    /// Check a compare of a txn at the latest revision
+    fn check_compare(&self, cmp: &Compare) -> bool {
+        let kvs = self.range(&cmp.key, &cmp.range_end, 0).unwrap();
+        if kvs.is_empty() {
+            return !matches!(cmp.target_union, Some(TargetUnion::Value(_)))
+                && compare_kv(cmp, &KeyValue::default());
+        }
+        kvs.iter().all(|kv| compare_kv(cmp, kv))
+    }
+
+    /// Execute a request against the latest revision
+    fn execute(&self, request: &RequestWrapper) -> Result<ResponseWrapper, ExecuteError> {
+        #[allow(clippy::wildcard_enum_match_arm)]
+        match *request {
+            RequestWrapper::RangeRequest(ref req) => {
+                let mut kvs = self.range(&req.key, &req.range_end, req.revision)?;
+                let count = kvs.len() as i64;
+                let more = req.limit > 0 && count > req.limit;
+                if more {
+                    kvs.truncate(req.limit as usize);
+                }
+                // the next page starts after the last key, at the revision of this page
+                let mut next_page_token = vec![];
+                if let (true, false, Some(last)) = (more, req.range_end.is_empty(), kvs.last()) {
+                    let revision = if req.revision == 0 {
+                        self.revision
+                    } else {
+                        req.revision
+                    };
+                    next_page_token.extend_from_slice(&revision.to_be_bytes());
+                    next_page_token.extend_from_slice(&last.key);
+                    next_page_token.push(0);
+                }
+                Ok(RangeResponse {
+                    header: None,
+                    kvs,
+                    more,
+                    count,
+                    next_page_token,
+                }
+                .into())
+            }
+            RequestWrapper::PutRequest(ref req) => Ok(PutResponse {
+                header: None,
+                prev_kv: req
+                    .prev_kv
+                    .then(|| self.current().get(&req.key).cloned())
+                    .flatten(),
+            }
+            .into()),
+            RequestWrapper::DeleteRangeRequest(ref req) => {
+                let prev_kvs = self.range(&req.key, &req.range_end, 0)?;
+                Ok(DeleteRangeResponse {
+                    header: None,
+                    deleted: prev_kvs.len() as i64,
+                    prev_kvs: if req.prev_kv { prev_kvs } else { vec![] },
+                }
+                .into())
+            }
+            RequestWrapper::TxnRequest(ref req) => {
+                let succeeded = req.compare.iter().all(|cmp| self.check_compare(cmp));
+                let ops = if succeeded {
+                    &req.success
+                } else {
+                    &req.failure
+                };
+                let responses = ops
+                    .iter()
+                    .map(|op| self.execute(&op.clone().into()).map(Into::into))
+                    .collect::<Result<_, _>>()?;
+                Ok(TxnResponse {
+                    header: None,
+                    succeeded,
+                    responses,
+                }
+                .into())
+            }
+            RequestWrapper::CompactionRequest(ref req) => {
+                if req.revision <= self.compact_revision {
+                    return Err(ExecuteError::compacted());
+                }
+                if req.revision > self.revision {
+                    return Err(ExecuteError::future_revision());
+                }
+                Ok(CompactionResponse { header: None }.into())
+            }
+            _ => unreachable!("only kv requests are generated"),
+        }
+    }
+
+    /// Sync a request at the next revision, return the revision
+    fn sync(&mut self, request: &RequestWrapper) -> i64 {
+        let revision = self.revision.overflow_add(1);
+        let mut kvs = self.current().clone();
+        let mut events = vec![];
+        #[allow(clippy::wildcard_enum_match_arm)]
+        match *request {
+            RequestWrapper::RangeRequest(_) => {}
+            RequestWrapper::PutRequest(ref req) => {
+                events.push(Self::put(&mut kvs, req, revision));
+            }
+            RequestWrapper::DeleteRangeRequest(ref req) => {
+                events.append(&mut Self::delete_range(&mut kvs, req, revision));
+            }
+            RequestWrapper::TxnRequest(ref req) => {
+                let succeeded = req.compare.iter().all(|cmp| self.check_compare(cmp));
+                let ops = if succeeded {
+                    &req.success
+                } else {
+                    &req.failure
+                };
+                for op in ops {
+                    match op.request {
+                        Some(Request::RequestPut(ref put)) => {
+                            events.push(Self::put(&mut kvs, put, revision));
+                        }
+                        Some(Request::RequestDeleteRange(ref del)) => {
+                            events.append(&mut Self::delete_range(&mut kvs, del, revision));
+                        }
+                        Some(Request::RequestRange(_) | Request::RequestTxn(_)) | None => {}
+                    }
+                }
+            }
+            RequestWrapper::CompactionRequest(ref req) => {
+                if req.revision > self.compact_revision {
+                    self.compact_revision = req.revision;
+                    self.history = self.history.split_off(&req.revision);
+                }
+            }
+            _ => unreachable!("only kv requests are generated"),
+        }
+        self.revision = revision;
+        let _prev = self.history.insert(revision, kvs);
+        self.events
+            .extend(events.into_iter().map(|event| (revision, event)));
+        revision
+    }
+
+    /// Put a key at a revision, return the event
+    fn put(kvs: &mut BTreeMap<Vec<u8>, KeyValue>, req: &PutRequest, revision: i64) -> Event {
+        let prev_kv = kvs.get(&req.key).cloned();
+        let kv = KeyValue {
+            key: req.key.clone(),
+            value: req.value.clone(),
+            create_revision: prev_kv
+                .as_ref()
+                .map_or(revision, |prev| prev.create_revision),
+            mod_revision: revision,
+            version: prev_kv
+                .as_ref()
+                .map_or(1, |prev| prev.version.overflow_add(1)),
+            lease: 0,
+        };
+        let _prev = kvs.insert(req.key.clone(), kv.clone());
+        Event {
+            r#type: EventType::Put as i32,
+            kv: Some(kv),
+            prev_kv,
+        }
+    }
+
+    /// Delete a range at a revision, return the events
+    fn delete_range(
+        kvs: &mut BTreeMap<Vec<u8>, KeyValue>,
+        req: &DeleteRangeRequest,
+        revision: i64,
+    ) -> Vec<Event> {
+        let keys: Vec<_> = kvs
+            .keys()
+            .filter(|k| in_range(&req.key, &req.range_end, k))
+            .cloned()
+            .collect();
+        keys.into_iter()
+            .map(|key| Event {
+                r#type: EventType::Delete as i32,
+                prev_kv: kvs.remove(&key),
+                kv: Some(KeyValue {
+                    key,
+                    mod_revision: revision,
+                    ..KeyValue::default()
+                }),
+            })
+            .collect()
+    }
+}
+
+/// Check if a key is in the range of a request
+fn in_range(key: &[u8], range_end: &[u8], k: &[u8]) -> bool {
+    match *range_end {
+        [] => k == key,
+        [0] => k >= key,
+        _ => k >= key && k < range_end,
+    }
+}
+
+/// Check a key value against a compare
+fn compare_kv(cmp: &Compare, kv: &KeyValue) -> bool {
+    let ordering = match cmp.target_union {
+        Some(TargetUnion::Version(v)) => kv.version.cmp(&v),
+        Some(TargetUnion::CreateRevision(v)) => kv.create_revision.cmp(&v),
+        Some(TargetUnion::ModRevision(v)) => kv.mod_revision.cmp(&v),
+        Some(TargetUnion::Value(ref v)) => kv.value.cmp(v),
+        Some(TargetUnion::Lease(v)) => kv.lease.cmp(&v),
+        None => unreachable!("every compare has a target"),
+    };
+    match cmp.result() {
+        CompareResult::Equal => ordering.is_eq(),
+        CompareResult::Greater => ordering.is_gt(),
+        CompareResult::Less => ordering.is_lt(),
+        CompareResult::NotEqual => ordering.is_ne(),
+    }
+}
+
+/// Generate a random key
+fn gen_key(rng: &mut StdRng) -> Vec<u8> {
+    KEYS.choose(rng).unwrap().as_bytes().to_vec()
+}
+
+/// Generate a random range end of a key, which is a single key, all keys from it or a range
+fn gen_range_end(rng: &mut StdRng, key: &[u8]) -> Vec<u8> {
+    match rng.gen_range(0..3) {
+        0 => vec![],
+        1 => vec![0],
+        _ => {
+            let end = gen_key(rng);
+            if end.as_slice() > key {
+                end
+            } else {
+                vec![]
+            }
+        }
+    }
+}
+
+/// Generate a random put
+fn gen_put(rng: &mut StdRng, key: Vec<u8>) -> PutRequest {
+    PutRequest {
+        key,
+        value: VALUES.choose(rng).unwrap().as_bytes().to_vec(),
+        prev_kv: rng.gen_bool(0.5),
+        ..PutRequest::default()
+    }
+}
+
+/// Generate a random compare of a key
+fn gen_compare(rng: &mut StdRng, revision: i64) -> Compare {
+    let (target, target_union) = match rng.gen_range(0..5) {
+        0 => (
+            CompareTarget::Value,
+            TargetUnion::Value(VALUES.choose(rng).unwrap().as_bytes().to_vec()),
+        ),
+        1 => (
+            CompareTarget::Version,
+            TargetUnion::Version(rng.gen_range(0..3)),
+        ),
+        2 => (
+            CompareTarget::Create,
+            TargetUnion::CreateRevision(rng.gen_range(0..=revision)),
+        ),
+        3 => (
+            CompareTarget::Mod,
+            TargetUnion::ModRevision(rng.gen_range(0..=revision)),
+        ),
+        _ => (
+            CompareTarget::Lease,
+            TargetUnion::Lease(rng.gen_range(0..2)),
+        ),
+    };
+    let result = [
+        CompareResult::Equal,
+        CompareResult::Greater,
+        CompareResult::Less,
+        CompareResult::NotEqual,
+    ]
+    .choose(rng)
+    .copied()
+    .unwrap();
+    Compare {
+        result: result as i32,
+        target: target as i32,
+        key: gen_key(rng),
+        range_end: vec![],
+        target_union: Some(target_union),
+    }
+}
+
+/// Generate the operations of a txn branch. Each of them touches a different key, like etcd
+/// requires, so they don't depend on each other.
+fn gen_txn_ops(rng: &mut StdRng) -> Vec<RequestOp> {
+    let mut keys = KEYS.to_vec();
+    keys.shuffle(rng);
+    let count = rng.gen_range(0..=3);
+    keys.into_iter()
+        .take(count)
+        .map(|key| {
+            let key = key.as_bytes().to_vec();
+            let request = match rng.gen_range(0..3) {
+                0 => Request::RequestRange(RangeRequest {
+                    key,
+                    ..RangeRequest::default()
+                }),
+                1 => Request::RequestPut(gen_put(rng, key)),
+                _ => Request::RequestDeleteRange(DeleteRangeRequest {
+                    key,
+                    prev_kv: rng.gen_bool(0.5),
+                    ..DeleteRangeRequest::default()
+                }),
+            };
+            RequestOp {
+                request: Some(request),
+            }
+        })
+        .collect()
+}
+
+/// Generate a random request, the revisions in it are around the ones of `model`
+fn gen_request(rng: &mut StdRng, model: &Model) -> RequestWrapper {
+    match rng.gen_range(0..10) {
+        0..=2 => {
+            let key = gen_key(rng);
+            gen_put(rng, key).into()
+        }
+        3 | 4 => {
+            let key = gen_key(rng);
+            DeleteRangeRequest {
+                range_end: gen_range_end(rng, &key),
+                key,
+                prev_kv: rng.gen_bool(0.5),
+            }
+            .into()
+        }
+        5 | 6 => TxnRequest {
+            compare: (0..rng.gen_range(0..3))
+                .map(|_| gen_compare(rng, model.revision))
+                .collect(),
+            success: gen_txn_ops(rng),
+            failure: gen_txn_ops(rng),
+        }
+        .into(),
+        7 => CompactionRequest {
+            revision: rng.gen_range(0..=model.revision.overflow_add(1)),
+            physical: false,
+        }
+        .into(),
+        _ => {
+            let key = gen_key(rng);
+            RangeRequest {
+                range_end: gen_range_end(rng, &key),
+                key,
+                revision: if rng.gen_bool(0.3) {
+                    rng.gen_range(0..=model.revision)
+                } else {
+                    0
+                },
+                limit: rng.gen_range(0..3),
+                ..RangeRequest::default()
+            }
+            .into()
+        }
+    }
+}
+
+/// Normalize a response for comparison, the headers are removed as the model has none
+fn normalize(result: Result<ResponseWrapper, ExecuteError>) -> Result<Option<ResponseOp>, String> {
+    /// Remove the headers of a response and its sub responses
+    fn strip_headers(op: &mut ResponseOp) {
+        match op.response {
+            Some(Response::ResponseRange(ref mut resp)) => resp.header = None,
+            Some(Response::ResponsePut(ref mut resp)) => resp.header = None,
+            Some(Response::ResponseDeleteRange(ref mut resp)) => resp.header = None,
+            Some(Response::ResponseTxn(ref mut resp)) => {
+                resp.header = None;
+                resp.responses.iter_mut().for_each(strip_headers);
+            }
+            None => {}
+        }
+    }
+
+    match result {
+        Ok(ResponseWrapper::CompactionResponse(_)) => Ok(None),
+        Ok(resp) => {
+            let mut op: ResponseOp = resp.into();
+            strip_headers(&mut op);
+            Ok(Some(op))
+        }
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// New empty kv store whose keys are never attached to a lease
+fn init_empty_store(db: Arc<DBProxy>) -> KvStore<DBProxy> {
+    let header_gen = Arc::new(HeaderGenerator::new(0, 0));
+    let (lease_cmd_tx, mut lease_cmd_rx) = mpsc::channel(128);
+    let _handle = tokio::spawn(async move {
+        while let Some(msg) = lease_cmd_rx.recv().await {
+            match msg {
+                LeaseMessage::GetLease(tx, _) => {
+                    let _ignore = tx.send(0);
+                }
+                LeaseMessage::Attach(tx, _, _) | LeaseMessage::Detach(tx, _, _) => {
+                    let _ignore = tx.send(Ok(()));
+                }
+                LeaseMessage::LookUp(tx, _) => {
+                    let _ignore = tx.send(None);
+                }
+            }
+        }
+    });
+    KvStore::new(lease_cmd_tx, header_gen, db, Arc::new(Index::new()))
+}
+
+/// Run a random sequence of requests against the kv store and the model, and check that
+/// they return the same responses and revisions, and that a watcher of the whole key space
+/// receives the events of the model
+async fn check_sequence(seed: u64) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let db = DBProxy::open(&StorageConfig::Memory).unwrap();
+    let store = init_empty_store(Arc::clone(&db));
+    let mut model = Model::new();
+    let (event_tx, mut event_rx) = mpsc::channel(REQUESTS_PER_SEQUENCE);
+    let _initial = store
+        .kv_watcher()
+        .watch(
+            1,
+            vec![KeyRange {
+                start: vec![0],
+                end: vec![0],
+            }],
+            0,
+            EventFilter::default(),
+            event_tx,
+        )
+        .unwrap();
+
+    for step in 0..REQUESTS_PER_SEQUENCE {
+        let request = gen_request(&mut rng, &model);
+        let ctx = format!("seed {seed}, step {step}, request {request:?}");
+        let req = RequestWithToken::new(request.clone());
+        let result = store.execute(&req).map(|resp| resp.decode());
+        let expected = model.execute(&request);
+        assert_eq!(normalize(result), normalize(expected.clone()), "{ctx}");
+        if expected.is_err() {
+            continue;
+        }
+
+        let id = ProposeId::new(format!("{seed}-{step}"));
+        let mut revision = store.prepare();
+        let sync_res = store.after_sync(&id, &req, &mut revision).await.unwrap();
+        db.flush(&id).unwrap();
+        drop(revision);
+        assert_eq!(sync_res.revision(), model.sync(&request), "{ctx}");
+        assert_eq!(store.revision(), model.revision, "{ctx}");
+        assert_eq!(store.compact_revision(), model.compact_revision, "{ctx}");
+    }
+
+    let mut received = vec![];
+    while received.len() < model.events.len() {
+        let mut event = tokio::time::timeout(Duration::from_secs(1), event_rx.recv())
+            .await
+            .unwrap_or_else(|_| panic!("seed {seed}, watch events are missing"))
+            .unwrap();
+        let revision = event.revision();
+        received.extend(event.take_events().into_iter().map(|e| (revision, e)));
+    }
+    assert_eq!(received, model.events, "seed {seed}");
+}
+
+#[tokio::test]
+async fn test_kv_store_matches_model() {
+    for seed in 0..SEQUENCES {
+        check_sequence(seed).await;
+    }
+}
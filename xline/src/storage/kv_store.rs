@@ -4,6 +4,7 @@ use std::{
     sync::Arc,
 };
 
+use bytes::Bytes;
 use clippy_utilities::{Cast, OverflowArithmetic};
 use curp::cmd::ProposeId;
 use prost::Message;
@@ -12,28 +13,42 @@ use tracing::{debug, warn};
 
 use super::{
     index::{Index, IndexOperate},
+    index_snapshot::{IndexSnapshot, KeySnapshot, INDEX_SNAPSHOT_KEY},
     kvwatcher::KvWatcher,
     lease_store::LeaseMessage,
     storage_api::StorageApi,
+    update_sequencer::{PreparedRevision, UpdateSequencer},
     Revision,
 };
 use crate::{
     header_gen::HeaderGenerator,
     revision_number::RevisionNumber,
     rpc::{
-        Compare, CompareResult, CompareTarget, DeleteRangeRequest, DeleteRangeResponse, Event,
-        EventType, KeyValue, PutRequest, PutResponse, RangeRequest, RangeResponse, Request,
-        RequestWithToken, RequestWrapper, ResponseWrapper, SortOrder, SortTarget, TargetUnion,
-        TxnRequest, TxnResponse,
+        CompactionRequest, CompactionResponse, Compare, CompareResult, CompareTarget,
+        DeleteRangeRequest, DeleteRangeResponse, Event, EventType, KeyHistoryRequest,
+        KeyHistoryResponse, KeyValue, PutRequest, PutResponse, RangeRequest, RangeResponse,
+        Request, RequestWithToken, RequestWrapper, ResponseHeader, ResponseWrapper, SortOrder,
+        SortTarget, TargetUnion, TxnRequest, TxnResponse, WaitRevisionResponse,
     },
-    server::command::{CommandResponse, KeyRange, SyncResponse},
+    server::command::{CommandResponse, KeyRange, SyncResponse, META_TABLE},
     storage::{db::WriteOp, ExecuteError},
 };
 
 /// KV table name
 pub(crate) const KV_TABLE: &str = "kv";
-/// Default channel size
-const CHANNEL_SIZE: usize = 128;
+/// Key of the compacted revision of the kv table in the meta table
+pub(crate) const COMPACT_REVISION_KEY: &str = "compact_revision";
+
+/// Get the compacted revision stored in the meta table, 0 if the kv table is never compacted
+pub(crate) fn read_compact_revision<S: StorageApi>(storage: &S) -> Result<i64, ExecuteError> {
+    let Some(buf) = storage.get_value(META_TABLE, COMPACT_REVISION_KEY)? else {
+        return Ok(0);
+    };
+    let buf = buf
+        .try_into()
+        .map_err(|_ignore| ExecuteError::DbError("Invalid compact revision".to_owned()))?;
+    Ok(i64::from_le_bytes(buf))
+}
 
 /// KV store
 #[derive(Debug)]
@@ -59,10 +74,12 @@ where
     db: Arc<DB>,
     /// Revision
     revision: Arc<RevisionNumber>,
+    /// Compacted revision, the history before it is removed
+    compact_revision: RevisionNumber,
     /// Header generator
     header_gen: Arc<HeaderGenerator>,
-    /// KV update sender
-    kv_update_tx: mpsc::Sender<(i64, Vec<Event>)>,
+    /// Sequencer of the updates sent to the KV watcher
+    sequencer: Arc<UpdateSequencer>,
     /// Lease command sender
     lease_cmd_tx: mpsc::Sender<LeaseMessage>,
 }
@@ -78,7 +95,7 @@ where
         storage: Arc<DB>,
         index: Arc<Index>,
     ) -> Self {
-        let (kv_update_tx, kv_update_rx) = mpsc::channel(CHANNEL_SIZE);
+        let (kv_update_tx, kv_update_rx) = mpsc::unbounded_channel();
         let inner = Arc::new(KvStoreBackend::new(
             kv_update_tx,
             lease_cmd_tx,
@@ -100,32 +117,103 @@ where
             .map(CommandResponse::new)
     }
 
+    /// Allocate the revision of a request, it must be called in the order of the log
+    pub(crate) fn prepare(&self) -> PreparedRevision {
+        self.inner.sequencer.prepare()
+    }
+
     /// sync a kv request
     pub(crate) async fn after_sync(
         &self,
         id: &ProposeId,
         request: &RequestWithToken,
+        revision: &mut PreparedRevision,
     ) -> Result<SyncResponse, ExecuteError> {
         self.inner
-            .sync_request(id, &request.request)
+            .sync_request(id, &request.request, revision)
             .await
             .map(SyncResponse::new)
     }
 
+    /// Get current revision of KV store
+    pub(crate) fn revision(&self) -> i64 {
+        self.inner.revision()
+    }
+
+    /// Generate a response header at the current revision
+    pub(crate) fn gen_header(&self) -> ResponseHeader {
+        self.inner.header_gen.gen_header()
+    }
+
+    /// Get compacted revision of KV store
+    pub(crate) fn compact_revision(&self) -> i64 {
+        self.inner.compact_revision()
+    }
+
+    /// Wait until the applied revision of KV store reaches `revision`, the revision of the
+    /// response header is the applied revision
+    pub(crate) async fn wait_revision(&self, revision: i64) -> WaitRevisionResponse {
+        let applied = self.inner.sequencer.wait_applied(revision).await;
+        let mut header = self.inner.header_gen.gen_header();
+        header.revision = applied;
+        WaitRevisionResponse {
+            header: Some(header),
+        }
+    }
+
+    /// List the retained revisions of a key, the revisions written after the revision of
+    /// the response header are not listed
+    pub(crate) fn key_history(
+        &self,
+        req: &KeyHistoryRequest,
+    ) -> Result<KeyHistoryResponse, ExecuteError> {
+        let compact_revision = self.compact_revision();
+        if req.start_revision > 0 && req.start_revision < compact_revision {
+            return Err(ExecuteError::compacted());
+        }
+        let header = self.inner.header_gen.gen_header();
+        let key_range = KeyRange::new(req.key.clone(), vec![]);
+        let mut events =
+            self.inner
+                .get_events(&[key_range], req.start_revision, header.revision)?;
+        let more = match usize::try_from(req.limit) {
+            Ok(limit) if limit > 0 && events.len() > limit => {
+                events.truncate(limit);
+                true
+            }
+            _ => false,
+        };
+        Ok(KeyHistoryResponse {
+            header: Some(header),
+            events,
+            more,
+        })
+    }
+
     /// Get KV watcher
     pub(crate) fn kv_watcher(&self) -> Arc<KvWatcher<DB>> {
         Arc::clone(&self.kv_watcher)
     }
 
-    /// Get KV update tx
-    pub(crate) fn kv_update_tx(&self) -> mpsc::Sender<(i64, Vec<Event>)> {
-        self.inner.kv_update_tx.clone()
-    }
-
     /// Recover data from persistent storage
     pub(crate) async fn recover(&self) -> Result<(), ExecuteError> {
         self.inner.recover_from_current_db().await
     }
+
+    /// Get the latest key-values in a range
+    pub(crate) fn latest_kvs(
+        &self,
+        key: &[u8],
+        range_end: &[u8],
+    ) -> Result<Vec<KeyValue>, ExecuteError> {
+        self.inner.get_range(key, range_end, 0)
+    }
+
+    /// Save a snapshot of the index, so that the next recovery only reads the kv table
+    /// after it. Return the revision of the snapshot, or `None` if it's skipped.
+    pub(crate) fn save_index_snapshot(&self) -> Result<Option<i64>, ExecuteError> {
+        self.inner.save_index_snapshot()
+    }
 }
 
 impl<DB> KvStoreBackend<DB>
@@ -134,18 +222,20 @@ where
 {
     /// New `KvStoreBackend`
     pub(crate) fn new(
-        kv_update_tx: mpsc::Sender<(i64, Vec<Event>)>,
+        kv_update_tx: mpsc::UnboundedSender<(i64, Vec<Event>)>,
         lease_cmd_tx: mpsc::Sender<LeaseMessage>,
         header_gen: Arc<HeaderGenerator>,
         db: Arc<DB>,
         index: Arc<Index>,
     ) -> Self {
+        let revision = header_gen.revision_arc();
         Self {
             index,
             db,
-            revision: header_gen.revision_arc(),
+            sequencer: Arc::new(UpdateSequencer::new(Arc::clone(&revision), kv_update_tx)),
+            revision,
+            compact_revision: RevisionNumber::new(0),
             header_gen,
-            kv_update_tx,
             lease_cmd_tx,
         }
     }
@@ -155,12 +245,9 @@ where
         self.revision.get()
     }
 
-    /// Notify KV changes to KV watcher
-    async fn notify_updates(&self, revision: i64, updates: Vec<Event>) {
-        assert!(
-            self.kv_update_tx.send((revision, updates)).await.is_ok(),
-            "Failed to send updates to KV watcher"
-        );
+    /// Get compacted revision of KV store
+    pub(crate) fn compact_revision(&self) -> i64 {
+        self.compact_revision.get()
     }
 
     /// Sort kvs by sort target and order
@@ -282,7 +369,7 @@ where
                 } else {
                     0
                 };
-                Self::compare_i64(kv.mod_revision, les)
+                Self::compare_i64(kv.lease, les)
             }
         };
 
@@ -340,20 +427,36 @@ where
         rx.await.unwrap_or_else(|_e| panic!("res sender is closed"))
     }
 
-    /// Recover data from current db
+    /// Recover data from current db. The kv table is scanned instead of loaded, so that
+    /// the memory used doesn't grow with the size of the table.
     async fn recover_from_current_db(&self) -> Result<(), ExecuteError> {
+        self.index.clear();
+        let compact_revision = read_compact_revision(self.db.as_ref())?;
+        self.compact_revision.set(compact_revision);
         let mut key_to_lease: HashMap<Vec<u8>, i64> = HashMap::new();
-        let kvs = self.db.get_all(KV_TABLE)?;
-
-        let current_rev = kvs
-            .last()
-            .map_or(1, |pair| Revision::decode(&pair.0).revision());
-        self.revision.set(current_rev);
-
-        for (key, value) in kvs {
-            let rev = Revision::decode(key.as_slice());
-            let kv = KeyValue::decode(value.as_slice())
-                .unwrap_or_else(|e| panic!("decode kv error: {e:?}"));
+        let mut current_rev = 1;
+        let mut from = vec![];
+
+        let mut snapshot_compact_revision = None;
+        if let Some(snapshot) = self.load_index_snapshot(compact_revision)? {
+            // only the revisions after the snapshot are recovered from the kv table
+            from = Revision::new(snapshot.revision.overflow_add(1), 0).encode_to_vec();
+            snapshot_compact_revision = Some(snapshot.compact_revision);
+            for key in snapshot.keys {
+                if let Some(rev) = key.revisions.last() {
+                    current_rev = current_rev.max(rev.mod_revision);
+                }
+                if key.lease != 0 {
+                    let _ignore = key_to_lease.insert(key.key.clone(), key.lease);
+                }
+                self.index.load(key.key, key.revisions);
+            }
+        }
+        self.db.scan_from(KV_TABLE, &from, |key, value| {
+            let rev = Revision::decode(key);
+            let kv = KeyValue::decode(value).map_err(|e| {
+                ExecuteError::DbError(format!("Failed to decode key-value from DB, error: {e}"))
+            })?;
 
             if kv.lease == 0 {
                 let _ignore = key_to_lease.remove(&kv.key);
@@ -368,18 +471,118 @@ where
                 kv.create_revision,
                 kv.version,
             );
+            current_rev = rev.revision();
+            Ok(())
+        })?;
+        if snapshot_compact_revision.map_or(false, |rev| rev < compact_revision) {
+            // the kv table is compacted again after the snapshot
+            let _compacted = self.index.compact(compact_revision);
         }
+        self.revision.set(current_rev);
+        self.sequencer.reset();
 
         for (key, lease_id) in key_to_lease {
-            self.attach(lease_id, key).await?;
+            // a key whose lease is missing is left to the lease reconciler
+            if let Err(e) = self.attach(lease_id, key.clone()).await {
+                warn!(
+                    "failed to attach key {:?} to lease {lease_id}, {e}",
+                    String::from_utf8_lossy(&key)
+                );
+            }
         }
 
-        // compact Lock free
-
         Ok(())
     }
 }
 
+/// index snapshot operations
+impl<DB> KvStoreBackend<DB>
+where
+    DB: StorageApi,
+{
+    /// Save a snapshot of the index at the applied revision to the meta table, return the
+    /// revision of the snapshot, or `None` if a compaction is synced while the index is
+    /// copied
+    fn save_index_snapshot(&self) -> Result<Option<i64>, ExecuteError> {
+        let compact_revision = self.compact_revision();
+        // the revisions not larger than the applied revision are flushed
+        let revision = self.sequencer.applied_revision();
+        let entries = self.index.snapshot(revision);
+        if self.compact_revision() != compact_revision {
+            return Ok(None);
+        }
+        // the leases of the keys are read from their latest values
+        let latest = entries
+            .iter()
+            .filter_map(|&(_, ref revs)| revs.last().filter(|rev| !rev.is_deleted()))
+            .map(|rev| rev.as_revision().encode_to_vec())
+            .collect::<Vec<_>>();
+        let mut leases = HashMap::new();
+        for value in self.db.get_values(KV_TABLE, &latest)? {
+            let Some(value) = value else {
+                return Err(ExecuteError::DbError(
+                    "the latest value of a key in the index is missing".to_owned(),
+                ));
+            };
+            let kv = KeyValue::decode(value.as_slice()).map_err(|e| {
+                ExecuteError::DbError(format!("Failed to decode key-value from DB, error: {e}"))
+            })?;
+            if kv.lease != 0 {
+                let _ignore = leases.insert(kv.key, kv.lease);
+            }
+        }
+        let keys = entries
+            .into_iter()
+            .map(|(key, revisions)| KeySnapshot {
+                lease: leases.get(&key).copied().unwrap_or_default(),
+                key,
+                revisions,
+            })
+            .collect();
+        let snapshot = IndexSnapshot {
+            revision,
+            compact_revision,
+            keys,
+        };
+        let id = ProposeId::new(INDEX_SNAPSHOT_KEY.to_owned());
+        self.db
+            .buffer_op(&id, WriteOp::PutIndexSnapshot(snapshot.encode()));
+        self.db.flush(&id)?;
+        Ok(Some(revision))
+    }
+
+    /// Load the index snapshot from the meta table. It's ignored if it can't be decoded, or
+    /// the compaction when it's taken is not flushed to the kv table.
+    fn load_index_snapshot(
+        &self,
+        compact_revision: i64,
+    ) -> Result<Option<IndexSnapshot>, ExecuteError> {
+        let Some(buf) = self.db.get_value(META_TABLE, INDEX_SNAPSHOT_KEY)? else {
+            return Ok(None);
+        };
+        let snapshot = match IndexSnapshot::decode(&buf) {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                warn!("ignore the index snapshot, {e}");
+                return Ok(None);
+            }
+        };
+        if snapshot.compact_revision > compact_revision {
+            warn!(
+                "ignore the index snapshot compacted at revision {}, the kv table is compacted \
+                 at revision {compact_revision}",
+                snapshot.compact_revision
+            );
+            return Ok(None);
+        }
+        debug!(
+            "recover the index from the snapshot at revision {}",
+            snapshot.revision
+        );
+        Ok(Some(snapshot))
+    }
+}
+
 /// db operations
 impl<DB> KvStoreBackend<DB>
 where
@@ -395,7 +598,8 @@ where
         let kvs = values
             .into_iter()
             .flatten()
-            .map(|v| KeyValue::decode(v.as_slice()))
+            // decoding from `Bytes` makes the value a slice of the buffer instead of a copy
+            .map(|v| KeyValue::decode(Bytes::from(v)))
             .collect::<Result<_, _>>()
             .map_err(|e| {
                 ExecuteError::DbError(format!("Failed to decode key-value from DB, error: {e}"))
@@ -425,11 +629,11 @@ where
         limit: usize,
         count_only: bool,
     ) -> Result<(Vec<KeyValue>, usize), ExecuteError> {
-        let mut revisions = self.index.get(key, range_end, revision);
-        let total = revisions.len();
         if count_only {
-            return Ok((vec![], total));
+            return Ok((vec![], self.index.count(key, range_end, revision)));
         }
+        let mut revisions = self.index.get(key, range_end, revision);
+        let total = revisions.len();
         if limit != 0 {
             revisions.truncate(limit);
         }
@@ -440,12 +644,30 @@ where
     /// Get `KeyValue` start from a revision and convert to `Event`
     pub(crate) fn get_event_from_revision(
         &self,
-        key_range: KeyRange,
+        key_ranges: &[KeyRange],
         revision: i64,
     ) -> Result<Vec<Event>, ExecuteError> {
-        let key = key_range.start.as_slice();
-        let range_end = key_range.end.as_slice();
-        let revisions = self.index.get_from_rev(key, range_end, revision);
+        self.get_events(key_ranges, revision, i64::MAX)
+    }
+
+    /// Get the events of some key ranges between two revisions, both inclusive, in the
+    /// order of their revisions. An event whose key is in several ranges is got once.
+    pub(crate) fn get_events(
+        &self,
+        key_ranges: &[KeyRange],
+        from: i64,
+        to: i64,
+    ) -> Result<Vec<Event>, ExecuteError> {
+        let mut revisions: Vec<_> = key_ranges
+            .iter()
+            .flat_map(|key_range| {
+                self.index
+                    .get_from_rev(&key_range.start, &key_range.end, from)
+            })
+            .filter(|rev| rev.revision() <= to)
+            .collect();
+        revisions.sort_by_key(|rev| (rev.revision(), rev.sub_revision()));
+        revisions.dedup();
         let events = self
             .get_values(&revisions)?
             .into_iter()
@@ -499,6 +721,10 @@ where
                 debug!("Receive TxnRequest {:?}", req);
                 self.handle_txn_request(req).map(Into::into)
             }
+            RequestWrapper::CompactionRequest(ref req) => {
+                debug!("Receive CompactionRequest {:?}", req);
+                self.handle_compaction_request(req).map(Into::into)
+            }
             _ => unreachable!("Other request should not be sent to this store"),
         };
         res
@@ -507,6 +733,16 @@ where
     /// Handle `RangeRequest`
     fn handle_range_request(&self, req: &RangeRequest) -> Result<RangeResponse, ExecuteError> {
         debug!("handle_range_request kvs");
+        let paginated = is_paginated(req);
+        let (key, revision) = match decode_page_token(req)? {
+            Some((revision, key)) => (key, revision),
+            // the following pages are read at the revision of the first page
+            None if paginated && req.revision == 0 => (req.key.clone(), self.revision()),
+            None => (req.key.clone(), req.revision),
+        };
+        if revision > 0 && revision < self.compact_revision() {
+            return Err(ExecuteError::compacted());
+        }
         let storage_fetch_limit = if (req.sort_order() != SortOrder::None)
             || (req.max_mod_revision != 0)
             || (req.min_mod_revision != 0)
@@ -519,9 +755,9 @@ where
             req.limit.overflow_add(1) // get one extra for "more" flag
         };
         let (mut kvs, total) = self.get_range_with_opts(
-            &req.key,
+            &key,
             &req.range_end,
-            req.revision,
+            revision,
             storage_fetch_limit.cast(),
             req.count_only,
         )?;
@@ -546,6 +782,9 @@ where
         if (req.limit > 0) && (kvs.len() > req.limit.cast()) {
             response.more = true;
             kvs.truncate(req.limit.cast());
+            if let (true, Some(last)) = (paginated, kvs.last()) {
+                response.next_page_token = encode_page_token(revision, &last.key);
+            }
         }
         if req.keys_only {
             kvs.iter_mut().for_each(|kv| kv.value.clear());
@@ -614,13 +853,31 @@ where
         })
     }
 
+    /// Handle `CompactionRequest`, the history can only be compacted forward and up to the
+    /// current revision
+    fn handle_compaction_request(
+        &self,
+        req: &CompactionRequest,
+    ) -> Result<CompactionResponse, ExecuteError> {
+        if req.revision <= self.compact_revision() {
+            return Err(ExecuteError::compacted());
+        }
+        if req.revision > self.revision() {
+            return Err(ExecuteError::future_revision());
+        }
+        Ok(CompactionResponse {
+            header: Some(self.header_gen.gen_header()),
+        })
+    }
+
     /// Sync requests in kv store
     async fn sync_request(
         &self,
         id: &ProposeId,
         wrapper: &RequestWrapper,
+        revision: &mut PreparedRevision,
     ) -> Result<i64, ExecuteError> {
-        let next_revision = self.revision.next();
+        let next_revision = revision.revision();
         #[allow(clippy::wildcard_enum_match_arm)] // only kv requests can be sent to kv store
         let events = match *wrapper {
             RequestWrapper::RangeRequest(ref req) => {
@@ -637,14 +894,41 @@ where
             RequestWrapper::TxnRequest(ref req) => {
                 self.sync_txn_request(id, req, next_revision).await?
             }
+            RequestWrapper::CompactionRequest(ref req) => {
+                self.sync_compaction_request(id, req);
+                Vec::new()
+            }
             _ => {
                 unreachable!("only kv requests can be sent to kv store");
             }
         };
-        self.notify_updates(next_revision, events).await;
+        revision.notify(events);
         Ok(next_revision)
     }
 
+    /// Sync `CompactionRequest`, remove the compacted revisions from the index and the kv
+    /// table. It's skipped if a later compaction is synced before it.
+    fn sync_compaction_request(&self, id: &ProposeId, req: &CompactionRequest) {
+        debug!("Sync CompactionRequest {:?}", req);
+        if req.revision <= self.compact_revision() {
+            return;
+        }
+        // the compacted revision is set before the index is compacted, so that an index
+        // snapshot taken meanwhile finds it changed
+        self.compact_revision.set(req.revision);
+        let revisions = self.index.compact(req.revision);
+        debug!(
+            "compact {} revisions at revision {}",
+            revisions.len(),
+            req.revision
+        );
+        for rev in revisions {
+            self.db.buffer_op(id, WriteOp::DeleteKeyValue(rev));
+        }
+        self.db
+            .buffer_op(id, WriteOp::PutCompactRevision(req.revision));
+    }
+
     /// Sync `TxnRequest` and return if kvstore is changed
     async fn sync_txn_request(
         &self,
@@ -816,6 +1100,47 @@ where
     }
 }
 
+/// Check if the pages of a range can be read by page tokens, the keys of a page must be
+/// after the keys of the previous pages
+fn is_paginated(req: &RangeRequest) -> bool {
+    req.limit > 0
+        && !req.count_only
+        && !req.range_end.is_empty()
+        && (req.sort_order() == SortOrder::None
+            || (req.sort_order() == SortOrder::Ascend && req.sort_target() == SortTarget::Key))
+}
+
+/// Encode the token of the page after the one whose last key is `last_key`, it's the
+/// revision of the pages followed by the first key of the next page
+fn encode_page_token(revision: i64, last_key: &[u8]) -> Vec<u8> {
+    let mut token = revision.to_be_bytes().to_vec();
+    token.extend_from_slice(last_key);
+    token.push(0);
+    token
+}
+
+/// Decode the page token of a range, return the revision of the pages and the first key of
+/// the page, or `None` if it's the first page
+fn decode_page_token(req: &RangeRequest) -> Result<Option<(i64, Vec<u8>)>, ExecuteError> {
+    if req.page_token.is_empty() {
+        return Ok(None);
+    }
+    if !is_paginated(req) {
+        return Err(ExecuteError::invalid_page_token());
+    }
+    let (revision, key) = req.page_token.split_at(req.page_token.len().min(8));
+    let revision = revision
+        .try_into()
+        .map(i64::from_be_bytes)
+        .map_err(|_e| ExecuteError::invalid_page_token())?;
+    // the page must be in the range, whose permission is checked
+    if revision <= 0 || (req.revision != 0 && req.revision != revision) || key < req.key.as_slice()
+    {
+        return Err(ExecuteError::invalid_page_token());
+    }
+    Ok(Some((revision, key.to_vec())))
+}
+
 #[cfg(test)]
 mod test {
 
@@ -997,7 +1322,9 @@ mod test {
         let db = DBProxy::open(&StorageConfig::Memory)?;
         let store = init_store(db).await?;
         let id = ProposeId::new("test-id".to_owned());
-        let _ignore = store.after_sync(&id, &txn_req).await?;
+        let _ignore = store
+            .after_sync(&id, &txn_req, &mut store.prepare())
+            .await?;
         store.inner.db.flush(&id)?;
         let request = RangeRequest {
             key: "success".into(),
@@ -1012,6 +1339,225 @@ mod test {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_compaction() -> Result<(), ExecuteError> {
+        let db = DBProxy::open(&StorageConfig::Memory)?;
+        let store = init_store(Arc::clone(&db)).await?;
+        let put_req = RequestWithToken::new(
+            PutRequest {
+                key: "a".into(),
+                value: "a1".into(),
+                ..Default::default()
+            }
+            .into(),
+        );
+        let id = ProposeId::new("test-id".to_owned());
+        let _sync_res = store
+            .after_sync(&id, &put_req, &mut store.prepare())
+            .await?;
+        store.inner.db.flush(&id)?;
+
+        let compact_req = |revision| {
+            RequestWithToken::new(
+                CompactionRequest {
+                    revision,
+                    physical: false,
+                }
+                .into(),
+            )
+        };
+        assert!(store.execute(&compact_req(100)).is_err());
+        let _cmd_res = store.execute(&compact_req(7))?;
+        let _sync_res = store
+            .after_sync(&id, &compact_req(7), &mut store.prepare())
+            .await?;
+        store.inner.db.flush(&id)?;
+        assert_eq!(store.compact_revision(), 7);
+        assert!(store.execute(&compact_req(7)).is_err());
+
+        let range_req = |revision| RangeRequest {
+            key: "a".into(),
+            range_end: vec![],
+            revision,
+            ..Default::default()
+        };
+        assert!(store.inner.handle_range_request(&range_req(6)).is_err());
+        let res = store.inner.handle_range_request(&range_req(7))?;
+        assert_eq!(res.kvs[0].value, b"a1");
+        assert_eq!(
+            db.get_value(KV_TABLE, Revision::new(2, 0).encode_to_vec())?,
+            None
+        );
+
+        let new_store = init_empty_store(db);
+        new_store.recover().await?;
+        assert_eq!(new_store.compact_revision(), 7);
+        let res = new_store.inner.handle_range_request(&range_req(0))?;
+        assert_eq!(res.kvs[0].value, b"a1");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_range_pages_are_read_at_the_same_revision() -> Result<(), ExecuteError> {
+        let db = DBProxy::open(&StorageConfig::Memory)?;
+        let store = init_store(db).await?;
+        let page = |page_token: Vec<u8>| {
+            store.inner.handle_range_request(&RangeRequest {
+                key: vec![0],
+                range_end: vec![0],
+                limit: 2,
+                page_token,
+                ..Default::default()
+            })
+        };
+        let first = page(vec![])?;
+        assert!(first.more);
+        assert!(!first.next_page_token.is_empty());
+
+        let req = RequestWithToken::new(
+            PutRequest {
+                key: "c".into(),
+                value: "c2".into(),
+                ..Default::default()
+            }
+            .into(),
+        );
+        let _cmd_res = store.execute(&req)?;
+        let id = ProposeId::new("test-id".to_owned());
+        let _sync_res = store.after_sync(&id, &req, &mut store.prepare()).await?;
+        store.inner.db.flush(&id)?;
+
+        let second = page(first.next_page_token)?;
+        let values: Vec<_> = second.kvs.iter().map(|kv| kv.value.to_vec()).collect();
+        assert_eq!(values, vec![b"c".to_vec(), b"d".to_vec()]);
+        let last = page(second.next_page_token)?;
+        assert_eq!(last.kvs.len(), 1);
+        assert!(!last.more);
+        assert!(last.next_page_token.is_empty());
+
+        // a token can't leave the range whose permission is checked
+        let mut token = 6_i64.to_be_bytes().to_vec();
+        token.push(b'a');
+        let single_key = RangeRequest {
+            key: "b".into(),
+            limit: 1,
+            page_token: token,
+            ..Default::default()
+        };
+        assert!(store.inner.handle_range_request(&single_key).is_err());
+        let before_start = RangeRequest {
+            range_end: "z".into(),
+            ..single_key
+        };
+        assert!(store.inner.handle_range_request(&before_start).is_err());
+        assert!(page(vec![1, 2, 3]).is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_key_history() -> Result<(), ExecuteError> {
+        let db = DBProxy::open(&StorageConfig::Memory)?;
+        let store = init_store(db).await?;
+        let requests: [RequestWrapper; 2] = [
+            PutRequest {
+                key: "a".into(),
+                value: "a2".into(),
+                ..Default::default()
+            }
+            .into(),
+            DeleteRangeRequest {
+                key: "a".into(),
+                ..Default::default()
+            }
+            .into(),
+        ];
+        for req in requests {
+            let req = RequestWithToken::new(req);
+            let _cmd_res = store.execute(&req)?;
+            let id = ProposeId::new("test-id".to_owned());
+            let _sync_res = store.after_sync(&id, &req, &mut store.prepare()).await?;
+            store.inner.db.flush(&id)?;
+        }
+
+        let history = |start_revision, limit| {
+            store.key_history(&KeyHistoryRequest {
+                key: "a".into(),
+                start_revision,
+                limit,
+            })
+        };
+        let res = history(0, 0)?;
+        let revisions: Vec<_> = res
+            .events
+            .iter()
+            .map(|event| (event.r#type(), event.kv.as_ref().unwrap().mod_revision))
+            .collect();
+        assert_eq!(
+            revisions,
+            vec![
+                (EventType::Put, 2),
+                (EventType::Put, 7),
+                (EventType::Delete, 8)
+            ]
+        );
+        assert_eq!(res.events[1].kv.as_ref().unwrap().value, b"a2");
+        assert!(!res.more);
+
+        let res = history(3, 1)?;
+        assert_eq!(res.events.len(), 1);
+        assert_eq!(res.events[0].kv.as_ref().unwrap().mod_revision, 7);
+        assert!(res.more);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_recover_from_index_snapshot() -> Result<(), ExecuteError> {
+        let db = DBProxy::open(&StorageConfig::Memory)?;
+        let store = init_store(Arc::clone(&db)).await?;
+        assert_eq!(store.save_index_snapshot()?, Some(6));
+
+        let put_req = RequestWithToken::new(
+            PutRequest {
+                key: "a".into(),
+                value: "a2".into(),
+                ..Default::default()
+            }
+            .into(),
+        );
+        let del_req = RequestWithToken::new(
+            DeleteRangeRequest {
+                key: "b".into(),
+                ..Default::default()
+            }
+            .into(),
+        );
+        let id = ProposeId::new("test-id".to_owned());
+        for req in [put_req, del_req] {
+            let _sync_res = store.after_sync(&id, &req, &mut store.prepare()).await?;
+            store.inner.db.flush(&id)?;
+        }
+
+        let new_store = init_empty_store(db);
+        new_store.recover().await?;
+        assert_eq!(new_store.revision(), 8);
+        let range_req = |revision| RangeRequest {
+            key: vec![0],
+            range_end: vec![0],
+            revision,
+            ..Default::default()
+        };
+        for revision in [0, 6, 7] {
+            assert_eq!(
+                new_store
+                    .inner
+                    .handle_range_request(&range_req(revision))?
+                    .kvs,
+                store.inner.handle_range_request(&range_req(revision))?.kvs
+            );
+        }
+        Ok(())
+    }
+
     fn sort_req(sort_order: SortOrder, sort_target: SortTarget) -> RangeRequest {
         RangeRequest {
             key: vec![0],
@@ -1037,7 +1583,7 @@ mod test {
             );
             let _cmd_res = store.execute(&req)?;
             let id = ProposeId::new("test-id".to_owned());
-            let _sync_res = store.after_sync(&id, &req).await?;
+            let _sync_res = store.after_sync(&id, &req, &mut store.prepare()).await?;
             store.inner.db.flush(&id)?;
         }
         Ok(store)
@@ -1048,8 +1594,15 @@ mod test {
         let (lease_cmd_tx, mut lease_cmd_rx) = mpsc::channel(128);
         let index = Arc::new(Index::new());
         let _handle = tokio::spawn(async move {
-            while let Some(LeaseMessage::GetLease(tx, _)) = lease_cmd_rx.recv().await {
-                assert!(tx.send(0).is_ok());
+            while let Some(msg) = lease_cmd_rx.recv().await {
+                match msg {
+                    LeaseMessage::GetLease(tx, _) => assert!(tx.send(0).is_ok()),
+                    // the keys are never attached, a deletion detaches them from lease 0
+                    LeaseMessage::Attach(tx, _, _) | LeaseMessage::Detach(tx, _, _) => {
+                        assert!(tx.send(Ok(())).is_ok());
+                    }
+                    LeaseMessage::LookUp(tx, _) => assert!(tx.send(None).is_ok()),
+                }
             }
         });
         KvStore::new(lease_cmd_tx, header_gen, db, index)
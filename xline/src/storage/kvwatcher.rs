@@ -1,34 +1,75 @@
 use std::{
     collections::{HashMap, HashSet},
     hash::Hash,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        Arc, Weak,
+    },
+    time::Duration,
 };
 
-use futures::{stream::FuturesUnordered, StreamExt};
+use clippy_utilities::OverflowArithmetic;
 use log::warn;
-use parking_lot::RwLock;
-use tokio::sync::mpsc;
+use parking_lot::{Mutex, RwLock};
+use prost::Message;
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tracing::{debug, instrument};
 use utils::parking_lot_lock::RwLockMap;
 
-use super::storage_api::StorageApi;
+use super::{storage_api::StorageApi, watch_filter::EventFilter};
 use crate::{rpc::Event, server::command::KeyRange, storage::kv_store::KvStoreBackend};
 
+/// Interval between two resyncs of the unsynced watchers
+const RESYNC_INTERVAL: Duration = Duration::from_millis(100);
+/// An unsynced watcher is caught up by at most this many revisions in one resync
+const RESYNC_MAX_REVISIONS: i64 = 1000;
+/// A watch event has at most this many events
+const CHUNK_MAX_EVENTS: usize = 1000;
+/// A watch event has at most this many bytes of encoded events, unless it has a single
+/// event larger than it
+const CHUNK_MAX_BYTES: usize = 1_048_576;
+
 /// Watch ID
 pub(crate) type WatchId = i64;
 
 /// Watcher
 #[derive(Debug)]
 struct Watcher {
-    /// Key Range
-    key_range: KeyRange,
+    /// Key ranges, an event is sent once even if its key is in several ranges
+    key_ranges: Vec<KeyRange>,
     /// Watch ID
     watch_id: WatchId,
     /// Start revision of this watcher
     start_rev: i64,
-    /// Event filters
-    filters: Vec<i32>,
+    /// Event filter
+    filter: EventFilter,
     /// Sender of watch event
     event_tx: mpsc::Sender<WatchEvent>,
+    /// Progress of the watcher
+    progress: Mutex<Progress>,
+}
+
+/// Progress of a watcher. The updates of the kv store are sent to a watcher without
+/// waiting, a watcher whose channel is full becomes unsynced, it skips the updates and is
+/// caught up from the storage by the resync, so a slow watch never blocks the others.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Progress {
+    /// The watcher receives the updates of the kv store
+    Synced {
+        /// The updates up to this revision are sent by the resync, they are skipped
+        resynced: i64,
+        /// The revision of the last sent updates
+        last_revision: i64,
+        /// Number of the events of the last revision sent
+        sent: usize,
+    },
+    /// The watcher lags behind and skips the updates of the kv store
+    Unsynced {
+        /// The first revision whose events are not all sent
+        revision: i64,
+        /// Number of the events of the revision already sent
+        sent: usize,
+    },
 }
 
 impl PartialEq for Watcher {
@@ -48,18 +89,23 @@ impl Hash for Watcher {
 impl Watcher {
     /// New `WatcherInner`
     fn new(
-        key_range: KeyRange,
+        key_ranges: Vec<KeyRange>,
         watch_id: WatchId,
         start_rev: i64,
-        filters: Vec<i32>,
+        filter: EventFilter,
         event_tx: mpsc::Sender<WatchEvent>,
     ) -> Self {
         Self {
-            key_range,
+            key_ranges,
             watch_id,
             start_rev,
-            filters,
+            filter,
             event_tx,
+            progress: Mutex::new(Progress::Synced {
+                resynced: 0,
+                last_revision: 0,
+                sent: 0,
+            }),
         }
     }
 
@@ -68,9 +114,9 @@ impl Watcher {
         self.watch_id
     }
 
-    /// Get key range
-    fn key_range(&self) -> &KeyRange {
-        &self.key_range
+    /// Get key ranges
+    fn key_ranges(&self) -> &[KeyRange] {
+        &self.key_ranges
     }
 
     /// Get start revision
@@ -78,21 +124,76 @@ impl Watcher {
         self.start_rev
     }
 
-    /// Notify events
-    async fn notify(&self, (revision, mut events): (i64, Vec<Event>)) {
+    /// Remove the events filtered out by the watcher
+    fn filter(&self, events: &mut Vec<Event>) {
+        self.filter.filter(events);
+    }
+
+    /// Check if the watcher lags behind
+    fn is_unsynced(&self) -> bool {
+        matches!(*self.progress.lock(), Progress::Unsynced { .. })
+    }
+
+    /// Notify events without waiting, the watcher becomes unsynced if its channel is full
+    fn notify(&self, revision: i64, mut events: Vec<Event>) {
         if revision < self.start_rev() {
             return;
         }
-        events.retain(|event| self.filters.iter().all(|filter| filter != &event.r#type));
-        let watch_event = WatchEvent {
-            id: self.watch_id(),
-            events,
-            revision,
+        self.filter(&mut events);
+        if events.is_empty() {
+            return;
+        }
+        let mut progress = self.progress.lock();
+        let Progress::Synced {
+            resynced,
+            last_revision,
+            sent,
+        } = *progress else {
+            return;
         };
-        assert!(
-            self.event_tx.send(watch_event).await.is_ok(),
-            "WatchEvent receiver is closed"
-        );
+        if revision <= resynced {
+            return;
+        }
+        let mut sent = if revision == last_revision { sent } else { 0 };
+        match self.try_send_chunks(revision, events, &mut sent) {
+            Ok(()) => {
+                *progress = Progress::Synced {
+                    resynced,
+                    last_revision: revision,
+                    sent,
+                };
+            }
+            Err(TrySendError::Full(_)) => {
+                debug!(
+                    "watcher {} is unsynced at revision {revision}",
+                    self.watch_id
+                );
+                *progress = Progress::Unsynced { revision, sent };
+            }
+            // the watch stream is closed, the watcher will be canceled
+            Err(TrySendError::Closed(_)) => {}
+        }
+    }
+
+    /// Send the events of a revision in chunks without waiting, `sent` is increased by the
+    /// number of the events sent, so a revision partially sent can be resumed
+    fn try_send_chunks(
+        &self,
+        revision: i64,
+        events: Vec<Event>,
+        sent: &mut usize,
+    ) -> Result<(), TrySendError<WatchEvent>> {
+        for chunk in split_events(events) {
+            let count = chunk.len();
+            self.event_tx.try_send(WatchEvent {
+                id: self.watch_id(),
+                events: chunk,
+                revision,
+                compact_revision: 0,
+            })?;
+            *sent = sent.overflow_add(count);
+        }
+        Ok(())
     }
 }
 
@@ -116,6 +217,8 @@ where
     storage: Arc<KvStoreBackend<S>>,
     /// Watch indexes
     watcher_map: RwLock<WatcherMap>,
+    /// The latest revision whose updates are being or have been dispatched to the watchers
+    dispatched: AtomicI64,
 }
 
 /// Store all watchers
@@ -138,7 +241,6 @@ impl WatcherMap {
 
     /// Insert a new watcher to the map and create. Internally, it will create a index for this watcher.
     fn insert(&mut self, watcher: Arc<Watcher>) {
-        let key_range = watcher.key_range().clone();
         let watch_id = watcher.watch_id();
         assert!(
             self.watchers
@@ -146,30 +248,30 @@ impl WatcherMap {
                 .is_none(),
             "can't insert a watcher twice"
         );
-        assert!(
-            self.index
-                .entry(key_range)
+        // a range given twice by the watcher is indexed once
+        for key_range in watcher.key_ranges() {
+            let _new = self
+                .index
+                .entry(key_range.clone())
                 .or_insert_with(HashSet::new)
-                .insert(watcher),
-            "can't insert a watcher twice"
-        );
+                .insert(Arc::clone(&watcher));
+        }
     }
 
-    /// Remove a watcher
-    #[allow(clippy::expect_used)] // the logic is managed internally
+    /// Remove a watcher, a watcher canceled by the resync may be removed again
     fn remove(&mut self, watch_id: WatchId) {
-        let watcher = self.watchers.remove(&watch_id).expect("no such watcher");
-        let key_range = watcher.key_range();
-        let is_empty = {
-            let watchers = self
-                .index
-                .get_mut(key_range)
-                .expect("no such watcher in index");
-            assert!(watchers.remove(&watcher), "no such watcher in index");
-            watchers.is_empty()
+        let Some(watcher) = self.watchers.remove(&watch_id) else {
+            return;
         };
-        if is_empty {
-            assert!(self.index.remove(key_range).is_some());
+        for key_range in watcher.key_ranges() {
+            // a range given twice by the watcher is removed at its first occurrence
+            let Some(watchers) = self.index.get_mut(key_range) else {
+                continue;
+            };
+            let _removed = watchers.remove(&watcher);
+            if watchers.is_empty() {
+                assert!(self.index.remove(key_range).is_some());
+            }
         }
     }
 }
@@ -181,32 +283,47 @@ where
     /// New `KvWatcher`
     pub(super) fn new(
         storage: Arc<KvStoreBackend<S>>,
-        mut kv_update_rx: mpsc::Receiver<(i64, Vec<Event>)>,
+        mut kv_update_rx: mpsc::UnboundedReceiver<(i64, Vec<Event>)>,
     ) -> Self {
         let inner = Arc::new(KvWatcherInner::new(storage));
         let inner_clone = Arc::clone(&inner);
         let _handle = tokio::spawn(async move {
             while let Some(updates) = kv_update_rx.recv().await {
-                inner_clone.handle_kv_updates(updates).await;
+                inner_clone.handle_kv_updates(updates);
             }
         });
+        let weak = Arc::downgrade(&inner);
+        let _resync_handle = tokio::spawn(Self::run_resync(weak));
         Self { inner }
     }
+
+    /// Resync the unsynced watchers every interval until the watcher is dropped
+    async fn run_resync(inner: Weak<KvWatcherInner<S>>) {
+        let mut ticker = tokio::time::interval(RESYNC_INTERVAL);
+        loop {
+            let _instant = ticker.tick().await;
+            let Some(inner) = inner.upgrade() else {
+                return;
+            };
+            inner.resync();
+        }
+    }
 }
 
 /// Operations of KV watcher
 #[allow(clippy::integer_arithmetic, clippy::indexing_slicing)] // Introduced by mockall::automock
 #[cfg_attr(test, mockall::automock)]
 pub(crate) trait KvWatcherOps {
-    /// Create a watch to KV store
+    /// Create a watch to KV store, return the initial events and the current revision, or
+    /// the compacted revision if the start revision has been compacted
     fn watch(
         &self,
         id: WatchId,
-        key_range: KeyRange,
+        key_ranges: Vec<KeyRange>,
         start_rev: i64,
-        filters: Vec<i32>,
+        filter: EventFilter,
         event_tx: mpsc::Sender<WatchEvent>,
-    ) -> (Vec<Event>, i64);
+    ) -> Result<(Vec<Event>, i64), i64>;
 
     /// Cancel a watch from KV store
     fn cancel(&self, id: WatchId) -> i64;
@@ -220,13 +337,13 @@ where
     fn watch(
         &self,
         id: WatchId,
-        key_range: KeyRange,
+        key_ranges: Vec<KeyRange>,
         start_rev: i64,
-        filters: Vec<i32>,
+        filter: EventFilter,
         event_tx: mpsc::Sender<WatchEvent>,
-    ) -> (Vec<Event>, i64) {
+    ) -> Result<(Vec<Event>, i64), i64> {
         self.inner
-            .watch(id, key_range, start_rev, filters, event_tx)
+            .watch(id, key_ranges, start_rev, filter, event_tx)
     }
 
     /// Cancel a watch from KV store
@@ -244,6 +361,7 @@ where
         Self {
             storage,
             watcher_map: RwLock::new(WatcherMap::new()),
+            dispatched: AtomicI64::new(0),
         }
     }
 
@@ -251,29 +369,34 @@ where
     fn watch(
         &self,
         id: WatchId,
-        key_range: KeyRange,
+        key_ranges: Vec<KeyRange>,
         start_rev: i64,
-        filters: Vec<i32>,
+        filter: EventFilter,
         event_tx: mpsc::Sender<WatchEvent>,
-    ) -> (Vec<Event>, i64) {
-        let watcher = Watcher::new(key_range.clone(), id, start_rev, filters, event_tx);
+    ) -> Result<(Vec<Event>, i64), i64> {
+        let compact_revision = self.storage.compact_revision();
+        if start_rev > 0 && start_rev < compact_revision {
+            return Err(compact_revision);
+        }
+        let watcher = Watcher::new(key_ranges, id, start_rev, filter, event_tx);
 
         let revision = self.storage.revision();
         // TODO: handle racing that new event is generated before watcher is registered
-        let initial_events = if start_rev == 0 {
+        let mut initial_events = if start_rev == 0 {
             vec![]
         } else {
             self.storage
-                .get_event_from_revision(key_range, start_rev)
+                .get_event_from_revision(watcher.key_ranges(), start_rev)
                 .unwrap_or_else(|e| {
                     warn!("failed to get initial events for watcher: {:?}", e);
                     vec![]
                 })
         };
+        watcher.filter(&mut initial_events);
 
         self.watcher_map.write().insert(Arc::new(watcher));
 
-        (initial_events, revision)
+        Ok((initial_events, revision))
     }
 
     /// Cancel a watch from KV store
@@ -283,8 +406,11 @@ where
         revision
     }
 
-    /// Handle KV store updates
-    async fn handle_kv_updates(&self, (revision, all_events): (i64, Vec<Event>)) {
+    /// Handle KV store updates, they are sent to the watchers without waiting
+    #[instrument(skip(self, all_events))]
+    fn handle_kv_updates(&self, (revision, all_events): (i64, Vec<Event>)) {
+        // set before the updates are sent, see `resync_watcher`
+        let _prev = self.dispatched.fetch_max(revision, Ordering::SeqCst);
         let watcher_events = self.watcher_map.map_read(|watcher_map_r| {
             let mut watcher_events: HashMap<Arc<Watcher>, Vec<Event>> = HashMap::new();
             for event in all_events {
@@ -315,13 +441,139 @@ where
             watcher_events
         });
 
-        let _ig = watcher_events
-            .into_iter()
-            .map(|(watcher, events)| async move { watcher.notify((revision, events)).await })
-            .collect::<FuturesUnordered<_>>()
-            .collect::<Vec<_>>()
-            .await;
+        for (watcher, events) in watcher_events {
+            watcher.notify(revision, events);
+        }
+    }
+
+    /// Catch up the unsynced watchers from the storage
+    fn resync(&self) {
+        let unsynced: Vec<_> = self
+            .watcher_map
+            .read()
+            .watchers
+            .values()
+            .filter(|watcher| watcher.is_unsynced())
+            .cloned()
+            .collect();
+        for watcher in unsynced {
+            self.resync_watcher(&watcher);
+        }
     }
+
+    /// Send the events of an unsynced watcher read from the storage, until its channel is
+    /// full again or it catches up with the dispatched updates. A watcher unsynced at a
+    /// compacted revision is canceled.
+    fn resync_watcher(&self, watcher: &Watcher) {
+        let Progress::Unsynced { revision, sent } = *watcher.progress.lock() else {
+            return;
+        };
+        let compact_revision = self.storage.compact_revision();
+        if revision < compact_revision {
+            let watch_event = WatchEvent {
+                id: watcher.watch_id(),
+                events: vec![],
+                revision: self.storage.revision(),
+                compact_revision,
+            };
+            if watcher.event_tx.try_send(watch_event).is_ok() {
+                debug!(
+                    "watcher {} is canceled, revision {revision} is compacted",
+                    watcher.watch_id()
+                );
+                self.watcher_map.write().remove(watcher.watch_id());
+            }
+            return;
+        }
+        // the updates up to the dispatched revision are flushed to the storage
+        let dispatched = self.dispatched.load(Ordering::SeqCst);
+        let through = dispatched.min(revision.overflow_add(RESYNC_MAX_REVISIONS.overflow_sub(1)));
+        let events = match self
+            .storage
+            .get_events(watcher.key_ranges(), revision, through)
+        {
+            Ok(events) => events,
+            Err(e) => {
+                warn!("failed to resync watcher {}: {e:?}", watcher.watch_id());
+                return;
+            }
+        };
+
+        // the dispatcher skips the updates of an unsynced watcher, only the resync changes
+        // its progress
+        let mut progress = watcher.progress.lock();
+        for (rev, mut events) in group_by_revision(events) {
+            watcher.filter(&mut events);
+            let skipped = if rev == revision { sent } else { 0 };
+            let events = events.split_off(skipped.min(events.len()));
+            if events.is_empty() {
+                continue;
+            }
+            let mut delivered = skipped;
+            let result = watcher.try_send_chunks(rev, events, &mut delivered);
+            // a revision partially sent is resumed from its events not sent yet
+            *progress = Progress::Unsynced {
+                revision: rev,
+                sent: delivered,
+            };
+            if result.is_err() {
+                return;
+            }
+        }
+        // the watcher is synced if no update after `through` is dispatched meanwhile,
+        // otherwise it may have been skipped
+        *progress = if through >= self.dispatched.load(Ordering::SeqCst) {
+            Progress::Synced {
+                resynced: through,
+                last_revision: through,
+                sent: 0,
+            }
+        } else {
+            Progress::Unsynced {
+                revision: through.overflow_add(1),
+                sent: 0,
+            }
+        };
+    }
+}
+
+/// Split the events of a revision into chunks bounded by both the number and the encoded
+/// size of the events, so that a revision with a huge number of events, eg. a large
+/// `DeleteRange`, neither fills up the channel of a watcher at once nor builds a watch
+/// response too large for the clients. Each chunk has at least one event.
+pub(crate) fn split_events(events: Vec<Event>) -> Vec<Vec<Event>> {
+    let mut chunks: Vec<Vec<Event>> = vec![];
+    let mut chunk_bytes = 0;
+    for event in events {
+        let bytes = event.encoded_len();
+        match chunks.last_mut() {
+            Some(chunk)
+                if chunk.len() < CHUNK_MAX_EVENTS
+                    && chunk_bytes.overflow_add(bytes) <= CHUNK_MAX_BYTES =>
+            {
+                chunk_bytes = chunk_bytes.overflow_add(bytes);
+                chunk.push(event);
+            }
+            _ => {
+                chunk_bytes = bytes;
+                chunks.push(vec![event]);
+            }
+        }
+    }
+    chunks
+}
+
+/// Group the events in the order of revisions by their revisions
+fn group_by_revision(events: Vec<Event>) -> Vec<(i64, Vec<Event>)> {
+    let mut groups: Vec<(i64, Vec<Event>)> = vec![];
+    for event in events {
+        let revision = event.kv.as_ref().map_or(0, |kv| kv.mod_revision);
+        match groups.last_mut() {
+            Some(&mut (rev, ref mut group)) if rev == revision => group.push(event),
+            _ => groups.push((revision, vec![event])),
+        }
+    }
+    groups
 }
 
 /// Watch Event
@@ -333,6 +585,9 @@ pub(crate) struct WatchEvent {
     events: Vec<Event>,
     /// Revision when this event is generated
     revision: i64,
+    /// The compacted revision if the watcher is canceled because the revision it lags
+    /// behind at is compacted, 0 otherwise
+    compact_revision: i64,
 }
 
 impl WatchEvent {
@@ -350,4 +605,162 @@ impl WatchEvent {
     pub(crate) fn take_events(&mut self) -> Vec<Event> {
         std::mem::take(&mut self.events)
     }
+
+    /// Get the compacted revision which the watcher is canceled for, 0 if it isn't
+    pub(crate) fn compact_revision(&self) -> i64 {
+        self.compact_revision
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use curp::cmd::ProposeId;
+    use utils::config::StorageConfig;
+
+    use super::*;
+    use crate::{
+        header_gen::HeaderGenerator,
+        rpc::{KeyValue, PutRequest, RequestWithToken},
+        storage::{db::DBProxy, index::Index, lease_store::LeaseMessage, KvStore},
+    };
+
+    fn init_empty_store(db: Arc<DBProxy>) -> KvStore<DBProxy> {
+        let header_gen = Arc::new(HeaderGenerator::new(0, 0));
+        let (lease_cmd_tx, mut lease_cmd_rx) = mpsc::channel(128);
+        let index = Arc::new(Index::new());
+        let _handle = tokio::spawn(async move {
+            while let Some(LeaseMessage::GetLease(tx, _)) = lease_cmd_rx.recv().await {
+                assert!(tx.send(0).is_ok());
+            }
+        });
+        KvStore::new(lease_cmd_tx, header_gen, db, index)
+    }
+
+    async fn put(store: &KvStore<DBProxy>, db: &DBProxy, key: &str, value: &str) {
+        let req = RequestWithToken::new(
+            PutRequest {
+                key: key.into(),
+                value: value.into(),
+                ..Default::default()
+            }
+            .into(),
+        );
+        let _cmd_res = store.execute(&req).unwrap();
+        let id = ProposeId::new("test-id".to_owned());
+        let mut revision = store.prepare();
+        let _sync_res = store.after_sync(&id, &req, &mut revision).await.unwrap();
+        db.flush(&id).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_slow_watcher_is_resynced() {
+        let db = DBProxy::open(&StorageConfig::Memory).unwrap();
+        let store = init_empty_store(Arc::clone(&db));
+        let (event_tx, mut event_rx) = mpsc::channel(1);
+        let _initial = store
+            .kv_watcher()
+            .watch(
+                1,
+                vec![KeyRange {
+                    start: "foo".into(),
+                    end: vec![],
+                }],
+                0,
+                EventFilter::default(),
+                event_tx,
+            )
+            .unwrap();
+        for value in ["1", "2", "3"] {
+            put(&store, &db, "foo", value).await;
+        }
+        for (expected_rev, expected_value) in [(2, "1"), (3, "2"), (4, "3")] {
+            let mut event = tokio::time::timeout(Duration::from_secs(1), event_rx.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(event.revision(), expected_rev);
+            let events = event.take_events();
+            assert_eq!(events.len(), 1);
+            assert_eq!(
+                events[0].kv.as_ref().unwrap().value,
+                expected_value.as_bytes()
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_watcher_with_several_ranges_should_get_each_event_once() {
+        let db = DBProxy::open(&StorageConfig::Memory).unwrap();
+        let store = init_empty_store(Arc::clone(&db));
+        put(&store, &db, "a1", "old").await;
+        put(&store, &db, "c1", "old").await;
+        let (event_tx, mut event_rx) = mpsc::channel(128);
+        let ranges = vec![
+            KeyRange::new("a", "b"),
+            KeyRange::new("c", "d"),
+            // overlaps with the first range
+            KeyRange::new("a1", ""),
+        ];
+        let (initial, _revision) = store
+            .kv_watcher()
+            .watch(1, ranges, 1, EventFilter::default(), event_tx)
+            .unwrap();
+        let keys: Vec<_> = initial
+            .iter()
+            .map(|event| event.kv.as_ref().unwrap().key.clone())
+            .collect();
+        assert_eq!(keys, vec![b"a1".to_vec(), b"c1".to_vec()]);
+
+        for key in ["a1", "b1", "c2"] {
+            put(&store, &db, key, "new").await;
+        }
+        let mut keys = vec![];
+        while keys.len() < 2 {
+            let mut event = tokio::time::timeout(Duration::from_secs(1), event_rx.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            keys.extend(
+                event
+                    .take_events()
+                    .into_iter()
+                    .map(|event| event.kv.unwrap().key),
+            );
+        }
+        assert_eq!(keys, vec![b"a1".to_vec(), b"c2".to_vec()]);
+        assert!(event_rx.try_recv().is_err());
+
+        let _revision = store.kv_watcher().cancel(1);
+        assert!(store.kv_watcher().inner.watcher_map.read().index.is_empty());
+    }
+
+    #[test]
+    fn test_split_events() {
+        let event = |value_len: usize| Event {
+            kv: Some(KeyValue {
+                key: b"foo".to_vec(),
+                value: vec![0; value_len],
+                ..KeyValue::default()
+            }),
+            ..Event::default()
+        };
+        let count = CHUNK_MAX_EVENTS.overflow_mul(2).overflow_add(1);
+        let sizes: Vec<_> = split_events((0..count).map(|_| event(1)).collect())
+            .iter()
+            .map(Vec::len)
+            .collect();
+        assert_eq!(sizes, vec![CHUNK_MAX_EVENTS, CHUNK_MAX_EVENTS, 1]);
+
+        let large = CHUNK_MAX_BYTES.overflow_div(3);
+        let sizes: Vec<_> = split_events((0..5).map(|_| event(large)).collect())
+            .iter()
+            .map(Vec::len)
+            .collect();
+        assert_eq!(sizes, vec![2, 2, 1]);
+        let sizes: Vec<_> = split_events(vec![event(CHUNK_MAX_BYTES), event(1)])
+            .iter()
+            .map(Vec::len)
+            .collect();
+        assert_eq!(sizes, vec![1, 1]);
+    }
 }
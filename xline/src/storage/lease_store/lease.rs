@@ -1,7 +1,7 @@
-use std::{
-    collections::HashSet,
-    time::{Duration, Instant},
-};
+use std::{collections::BTreeSet, sync::Arc, time::Duration};
+
+// the runtime's clock, so that the expiry follows the paused time of the tests
+use tokio::time::Instant;
 
 /// Lease
 #[derive(Debug, Clone)]
@@ -12,10 +12,13 @@ pub(crate) struct Lease {
     ttl: Duration,
     /// Remaining time of lease
     remaining_ttl: Duration,
-    /// Keys attached to this lease
-    keys_set: HashSet<Vec<u8>>,
+    /// Keys attached to this lease, they are shared with the readers of the lease and
+    /// copied on write when a reader still holds them
+    keys_set: Arc<BTreeSet<Vec<u8>>>,
     /// Expiration time
     expiry: Option<Instant>,
+    /// When the expiry is refreshed last time
+    refreshed_at: Option<Instant>,
 }
 
 impl Lease {
@@ -25,14 +28,15 @@ impl Lease {
             id,
             ttl: Duration::from_secs(ttl),
             remaining_ttl: Duration::from_secs(0),
-            keys_set: HashSet::new(),
+            keys_set: Arc::new(BTreeSet::new()),
             expiry: None,
+            refreshed_at: None,
         }
     }
 
-    /// Return keys of lease
-    pub(crate) fn keys(&self) -> Vec<Vec<u8>> {
-        self.keys_set.iter().cloned().collect()
+    /// Return keys of lease, it's a handle to the keys instead of a copy
+    pub(crate) fn keys(&self) -> Arc<BTreeSet<Vec<u8>>> {
+        Arc::clone(&self.keys_set)
     }
 
     /// Lease id
@@ -45,6 +49,11 @@ impl Lease {
         self.ttl
     }
 
+    /// Set the lease ttl, it takes effect on the next refresh
+    pub(crate) fn set_ttl(&mut self, ttl: Duration) {
+        self.ttl = ttl;
+    }
+
     /// Lease remaining
     pub(crate) fn remaining(&self) -> Duration {
         if let Some(exp) = self.expiry {
@@ -70,11 +79,19 @@ impl Lease {
 
     /// Refresh expiry and return new expiry
     pub(crate) fn refresh(&mut self, extend: Duration) -> Instant {
-        let new_expiry = Instant::now() + extend + self.remaining_ttl();
+        let now = Instant::now();
+        let new_expiry = now + extend + self.remaining_ttl();
         self.expiry = Some(new_expiry);
+        self.refreshed_at = Some(now);
         new_expiry
     }
 
+    /// Check if the expiry is refreshed within `interval`
+    pub(crate) fn refreshed_within(&self, interval: Duration) -> bool {
+        self.refreshed_at
+            .map_or(false, |refreshed_at| refreshed_at.elapsed() < interval)
+    }
+
     /// Set expiry to `None`
     pub(crate) fn forever(&mut self) {
         self.expiry = None;
@@ -82,11 +99,13 @@ impl Lease {
 
     /// Insert a key to lease
     pub(crate) fn insert_key(&mut self, key: Vec<u8>) {
-        let _ignore = self.keys_set.insert(key);
+        let _ignore = Arc::make_mut(&mut self.keys_set).insert(key);
     }
 
     /// Remove a key from lease
     pub(crate) fn remove_key(&mut self, key: &[u8]) {
-        let _ignore = self.keys_set.remove(key);
+        if self.keys_set.contains(key) {
+            let _ignore = Arc::make_mut(&mut self.keys_set).remove(key);
+        }
     }
 }
@@ -1,6 +1,7 @@
-use std::{cmp::Reverse, time::Instant};
+use std::cmp::Reverse;
 
 use priority_queue::PriorityQueue;
+use tokio::time::Instant;
 
 /// Priority queue of lease
 #[derive(Debug)]
@@ -6,9 +6,9 @@ mod lease_queue;
 mod message;
 
 use std::{
-    collections::HashMap,
+    collections::{BTreeSet, HashMap},
     sync::Arc,
-    time::{Duration, Instant},
+    time::Duration,
 };
 
 use clippy_utilities::Cast;
@@ -16,7 +16,11 @@ use curp::cmd::ProposeId;
 use log::debug;
 use parking_lot::RwLock;
 use prost::Message;
-use tokio::sync::mpsc;
+use tokio::{
+    sync::{broadcast, mpsc},
+    time::Instant,
+};
+use utils::config::LeaseConfig;
 
 use self::lease_queue::LeaseQueue;
 pub(crate) use self::{lease::Lease, message::LeaseMessage};
@@ -25,13 +29,14 @@ use super::{
     index::{Index, IndexOperate},
     kv_store::KV_TABLE,
     storage_api::StorageApi,
+    update_sequencer::PreparedRevision,
     ExecuteError,
 };
 use crate::{
     header_gen::HeaderGenerator,
-    revision_number::RevisionNumber,
     rpc::{
-        Event, EventType, KeyValue, LeaseGrantRequest, LeaseGrantResponse, LeaseRevokeRequest,
+        Event, EventType, KeyValue, LeaseEventType, LeaseGrantRequest, LeaseGrantResponse,
+        LeaseModifyTtlRequest, LeaseModifyTtlResponse, LeaseObserveResponse, LeaseRevokeRequest,
         LeaseRevokeResponse, PbLease, RequestWithToken, RequestWrapper, ResponseHeader,
         ResponseWrapper,
     },
@@ -43,9 +48,11 @@ use crate::{
 /// Lease table name
 pub(crate) const LEASE_TABLE: &str = "lease";
 /// Max lease ttl
-const MAX_LEASE_TTL: i64 = 9_000_000_000;
+pub(crate) const MAX_LEASE_TTL: i64 = 9_000_000_000;
 /// Min lease ttl
 const MIN_LEASE_TTL: i64 = 1; // TODO: this num should calculated by election ticks and heartbeat
+/// Number of the lease events buffered for each observer
+const OBSERVER_CHANNEL_SIZE: usize = 1024;
 
 /// Lease store
 #[derive(Debug)]
@@ -66,22 +73,47 @@ struct LeaseCollection {
     item_map: HashMap<Vec<u8>, i64>,
     /// lease queue
     expired_queue: LeaseQueue,
+    /// Lease config
+    config: LeaseConfig,
 }
 
 impl LeaseCollection {
     /// New `LeaseCollection`
-    fn new() -> Self {
+    fn new(config: LeaseConfig) -> Self {
         Self {
             lease_map: HashMap::new(),
             item_map: HashMap::new(),
             expired_queue: LeaseQueue::new(),
+            config,
         }
     }
 
-    /// Find expired leases
+    /// Get the jitter added to the expiry of a lease. It's a part of the max jitter
+    /// determined by the lease id, so that the leases with the same ttl refreshed at the
+    /// same time are spread over the max jitter, and a lease keeps its part when it's
+    /// refreshed again.
+    fn jitter(&self, lease_id: i64) -> Duration {
+        let max_jitter = u64::try_from(self.config.expiry_jitter().as_millis()).unwrap_or(u64::MAX);
+        if max_jitter == 0 {
+            return Duration::ZERO;
+        }
+        // the ids are mixed by the multiplicative hashing, the adjacent ids are far apart
+        let hash = u64::from_be_bytes(lease_id.to_be_bytes()).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+        Duration::from_millis(hash.wrapping_rem(max_jitter))
+    }
+
+    /// Find expired leases, at most `revoke_batch_size` of them, the others are found by
+    /// the next calls
     fn find_expired_leases(&mut self) -> Vec<i64> {
+        let limit = match *self.config.revoke_batch_size() {
+            0 => usize::MAX,
+            limit => limit,
+        };
         let mut expired_leases = vec![];
         while let Some(expiry) = self.expired_queue.peek() {
+            if expired_leases.len() >= limit {
+                break;
+            }
             if *expiry <= Instant::now() {
                 #[allow(clippy::unwrap_used)] // queue.peek() returns Some
                 let id = self.expired_queue.pop().unwrap();
@@ -95,15 +127,29 @@ impl LeaseCollection {
         expired_leases
     }
 
+    /// Get the remaining ttl of a lease if it's renewed within the min keep alive interval,
+    /// which is at most a third of its ttl, the renewal is coalesced with the last one then
+    fn coalesced_renewal(&self, lease_id: i64) -> Option<i64> {
+        let min_interval = *self.config.min_keep_alive_interval();
+        if min_interval.is_zero() {
+            return None;
+        }
+        let lease = self.lease_map.get(&lease_id)?;
+        let interval = min_interval.min(lease.ttl().checked_div(3).unwrap_or_default());
+        (lease.refreshed_within(interval) && !lease.expired())
+            .then(|| lease.remaining().as_secs().cast())
+    }
+
     /// Renew lease
     fn renew(&mut self, lease_id: i64) -> Result<i64, ExecuteError> {
+        let jitter = self.jitter(lease_id);
         self.lease_map.get_mut(&lease_id).map_or_else(
-            || Err(ExecuteError::lease_not_found(lease_id)),
+            || Err(ExecuteError::lease_not_found()),
             |lease| {
                 if lease.expired() {
-                    return Err(ExecuteError::lease_expired(lease_id));
+                    return Err(ExecuteError::lease_expired());
                 }
-                let expiry = lease.refresh(Duration::default());
+                let expiry = lease.refresh(jitter);
                 let _ignore = self.expired_queue.update(lease_id, expiry);
                 Ok(lease.ttl().as_secs().cast())
             },
@@ -113,7 +159,7 @@ impl LeaseCollection {
     /// Attach key to lease
     fn attach(&mut self, lease_id: i64, key: Vec<u8>) -> Result<(), ExecuteError> {
         self.lease_map.get_mut(&lease_id).map_or_else(
-            || Err(ExecuteError::lease_not_found(lease_id)),
+            || Err(ExecuteError::lease_not_found()),
             |lease| {
                 lease.insert_key(key.clone());
                 let _ignore = self.item_map.insert(key, lease_id);
@@ -125,7 +171,7 @@ impl LeaseCollection {
     /// Detach key from lease
     fn detach(&mut self, lease_id: i64, key: &[u8]) -> Result<(), ExecuteError> {
         self.lease_map.get_mut(&lease_id).map_or_else(
-            || Err(ExecuteError::lease_not_found(lease_id)),
+            || Err(ExecuteError::lease_not_found()),
             |lease| {
                 lease.remove_key(key);
                 let _ignore = self.item_map.remove(key);
@@ -143,7 +189,7 @@ impl LeaseCollection {
     fn grant(&mut self, lease_id: i64, ttl: i64, is_leader: bool) -> PbLease {
         let mut lease = Lease::new(lease_id, ttl.max(MIN_LEASE_TTL).cast());
         if is_leader {
-            let expiry = lease.refresh(Duration::ZERO);
+            let expiry = lease.refresh(self.jitter(lease_id));
             let _ignore = self.expired_queue.insert(lease_id, expiry);
         } else {
             lease.forever();
@@ -156,6 +202,23 @@ impl LeaseCollection {
         }
     }
 
+    /// Change the ttl of a lease, keeping its keys. The lease is renewed with the new ttl
+    /// on the leader, the followers never expire it.
+    fn modify_ttl(&mut self, lease_id: i64, ttl: i64, is_leader: bool) -> Option<PbLease> {
+        let jitter = self.jitter(lease_id);
+        let lease = self.lease_map.get_mut(&lease_id)?;
+        lease.set_ttl(Duration::from_secs(ttl.max(MIN_LEASE_TTL).cast()));
+        if is_leader {
+            let expiry = lease.refresh(jitter);
+            let _ignore = self.expired_queue.update(lease_id, expiry);
+        }
+        Some(PbLease {
+            id: lease.id(),
+            ttl: lease.ttl().as_secs().cast(),
+            remaining_ttl: lease.remaining_ttl().as_secs().cast(),
+        })
+    }
+
     /// Revokes a lease
     fn revoke(&mut self, lease_id: i64) -> Option<Lease> {
         self.lease_map.remove(&lease_id)
@@ -169,9 +232,16 @@ impl LeaseCollection {
 
     /// Promote current node
     fn promote(&mut self, extend: Duration) {
-        for lease in self.lease_map.values_mut() {
-            let expiry = lease.refresh(extend);
-            let _ignore = self.expired_queue.insert(lease.id(), expiry);
+        let jitters: Vec<_> = self
+            .lease_map
+            .keys()
+            .map(|&id| (id, self.jitter(id)))
+            .collect();
+        for (id, jitter) in jitters {
+            if let Some(lease) = self.lease_map.get_mut(&id) {
+                let expiry = lease.refresh(extend.saturating_add(jitter));
+                let _ignore = self.expired_queue.insert(id, expiry);
+            }
         }
     }
 }
@@ -190,12 +260,10 @@ where
     index: Arc<Index>,
     /// Current node is leader or not
     state: Arc<State>,
-    /// Revision
-    revision: Arc<RevisionNumber>,
     /// Header generator
     header_gen: Arc<HeaderGenerator>,
-    /// KV update sender
-    kv_update_tx: mpsc::Sender<(i64, Vec<Event>)>,
+    /// Sender of the lease events to the observers
+    observers: broadcast::Sender<LeaseObserveResponse>,
 }
 
 impl<DB> LeaseStore<DB>
@@ -206,19 +274,13 @@ where
     #[allow(clippy::integer_arithmetic)] // Introduced by tokio::select!
     pub(crate) fn new(
         mut lease_cmd_rx: mpsc::Receiver<LeaseMessage>,
+        config: LeaseConfig,
         state: Arc<State>,
         header_gen: Arc<HeaderGenerator>,
         db: Arc<DB>,
         index: Arc<Index>,
-        kv_update_tx: mpsc::Sender<(i64, Vec<Event>)>,
     ) -> Self {
-        let inner = Arc::new(LeaseStoreBackend::new(
-            state,
-            header_gen,
-            db,
-            index,
-            kv_update_tx,
-        ));
+        let inner = Arc::new(LeaseStoreBackend::new(config, state, header_gen, db, index));
         let _handle = tokio::spawn({
             let inner = Arc::clone(&inner);
             async move {
@@ -262,15 +324,15 @@ where
             .map(CommandResponse::new)
     }
 
-    /// sync a lease request
-    pub(crate) async fn after_sync(
+    /// sync a lease request, a `LeaseRevokeRequest` deletes its keys at the prepared revision
+    pub(crate) fn after_sync(
         &self,
         id: &ProposeId,
         request: &RequestWithToken,
+        revision: Option<&mut PreparedRevision>,
     ) -> Result<SyncResponse, ExecuteError> {
         self.inner
-            .sync_request(id, &request.request)
-            .await
+            .sync_request(id, &request.request, revision)
             .map(SyncResponse::new)
     }
 
@@ -298,13 +360,18 @@ where
         leases
     }
 
+    /// Detach a key from a lease
+    pub(crate) fn detach(&self, lease_id: i64, key: &[u8]) -> Result<(), ExecuteError> {
+        self.inner.detach(lease_id, key)
+    }
+
     /// Find expired leases
     pub(crate) fn find_expired_leases(&self) -> Vec<i64> {
         self.inner.lease_collection.write().find_expired_leases()
     }
 
     /// Get keys attached to a lease
-    pub(crate) fn get_keys(&self, lease_id: i64) -> Vec<Vec<u8>> {
+    pub(crate) fn get_keys(&self, lease_id: i64) -> Arc<BTreeSet<Vec<u8>>> {
         self.inner
             .lease_collection
             .read()
@@ -319,7 +386,19 @@ where
         if !self.is_leader() {
             return Err(ExecuteError::lease_not_leader());
         }
-        self.inner.lease_collection.write().renew(lease_id)
+        let mut lease_collection = self.inner.lease_collection.write();
+        if let Some(ttl) = lease_collection.coalesced_renewal(lease_id) {
+            return Ok(ttl);
+        }
+        let ttl = lease_collection.renew(lease_id)?;
+        drop(lease_collection);
+        self.inner.notify(LeaseEventType::Renew, lease_id, ttl);
+        Ok(ttl)
+    }
+
+    /// Subscribe the lease events
+    pub(crate) fn observe(&self) -> broadcast::Receiver<LeaseObserveResponse> {
+        self.inner.observers.subscribe()
     }
 
     /// Generate `ResponseHeader`
@@ -349,23 +428,34 @@ where
 {
     /// New `LeaseStoreBackend`
     pub(crate) fn new(
+        config: LeaseConfig,
         state: Arc<State>,
         header_gen: Arc<HeaderGenerator>,
         db: Arc<DB>,
         index: Arc<Index>,
-        kv_update_tx: mpsc::Sender<(i64, Vec<Event>)>,
     ) -> Self {
+        let (observers, _) = broadcast::channel(OBSERVER_CHANNEL_SIZE);
         Self {
-            lease_collection: RwLock::new(LeaseCollection::new()),
+            lease_collection: RwLock::new(LeaseCollection::new(config)),
             db,
             state,
-            revision: header_gen.revision_arc(),
             header_gen,
+            observers,
             index,
-            kv_update_tx,
         }
     }
 
+    /// Notify the observers of a lease event, it's dropped if there is no observer
+    fn notify(&self, event_type: LeaseEventType, id: i64, ttl: i64) {
+        let _ignore = self.observers.send(LeaseObserveResponse {
+            header: Some(self.header_gen.gen_header()),
+            #[allow(clippy::as_conversions)] // This cast is always valid
+            r#type: event_type as i32,
+            id,
+            ttl,
+        });
+    }
+
     /// Check if the node is leader
     fn is_leader(&self) -> bool {
         self.state.is_leader()
@@ -403,11 +493,10 @@ where
     /// Recover data form persistent storage
     fn recover_from_current_db(&self) -> Result<(), ExecuteError> {
         let leases = self.get_all()?;
+        let mut lease_collection = self.lease_collection.write();
+        *lease_collection = LeaseCollection::new(lease_collection.config);
         for lease in leases {
-            let _ignore = self
-                .lease_collection
-                .write()
-                .grant(lease.id, lease.ttl, false);
+            let _ignore = lease_collection.grant(lease.id, lease.ttl, false);
         }
         Ok(())
     }
@@ -428,6 +517,10 @@ where
                 debug!("Receive LeaseRevokeRequest {:?}", req);
                 self.handle_lease_revoke_request(req).map(Into::into)
             }
+            RequestWrapper::LeaseModifyTtlRequest(ref req) => {
+                debug!("Receive LeaseModifyTtlRequest {:?}", req);
+                self.handle_lease_modify_ttl_request(req).map(Into::into)
+            }
             _ => unreachable!("Other request should not be sent to this store"),
         };
         res
@@ -439,13 +532,13 @@ where
         req: &LeaseGrantRequest,
     ) -> Result<LeaseGrantResponse, ExecuteError> {
         if req.id == 0 {
-            return Err(ExecuteError::lease_not_found(0));
+            return Err(ExecuteError::lease_not_found());
         }
         if req.ttl > MAX_LEASE_TTL {
-            return Err(ExecuteError::lease_ttl_too_large(req.ttl));
+            return Err(ExecuteError::lease_ttl_too_large());
         }
         if self.lease_collection.read().contains_lease(req.id) {
-            return Err(ExecuteError::lease_already_exists(req.id));
+            return Err(ExecuteError::lease_already_exists());
         }
 
         Ok(LeaseGrantResponse {
@@ -466,38 +559,89 @@ where
                 header: Some(self.header_gen.gen_header_without_revision()),
             })
         } else {
-            Err(ExecuteError::lease_not_found(req.id))
+            Err(ExecuteError::lease_not_found())
         }
     }
 
+    /// Handle `LeaseModifyTtlRequest`
+    fn handle_lease_modify_ttl_request(
+        &self,
+        req: &LeaseModifyTtlRequest,
+    ) -> Result<LeaseModifyTtlResponse, ExecuteError> {
+        if req.ttl > MAX_LEASE_TTL {
+            return Err(ExecuteError::lease_ttl_too_large());
+        }
+        if !self.lease_collection.read().contains_lease(req.id) {
+            return Err(ExecuteError::lease_not_found());
+        }
+        Ok(LeaseModifyTtlResponse {
+            header: Some(self.header_gen.gen_header_without_revision()),
+            id: req.id,
+            ttl: req.ttl.max(MIN_LEASE_TTL),
+        })
+    }
+
     /// Sync `RequestWithToken`
-    async fn sync_request(
+    fn sync_request(
         &self,
         id: &ProposeId,
         wrapper: &RequestWrapper,
+        revision: Option<&mut PreparedRevision>,
     ) -> Result<i64, ExecuteError> {
         #[allow(clippy::wildcard_enum_match_arm)]
         match *wrapper {
             RequestWrapper::LeaseGrantRequest(ref req) => {
                 debug!("Sync LeaseGrantRequest {:?}", req);
-                self.sync_lease_grant_request(id, req);
+                let ttl = self.sync_lease_grant_request(id, req);
+                self.notify(LeaseEventType::Grant, req.id, ttl);
             }
             RequestWrapper::LeaseRevokeRequest(ref req) => {
                 debug!("Sync LeaseRevokeRequest {:?}", req);
-                self.sync_lease_revoke_request(id, req).await?;
+                let revision = revision.unwrap_or_else(|| {
+                    unreachable!("the revision of LeaseRevokeRequest should be prepared")
+                });
+                // only the leader tracks the expiry, the expired leases are revoked by it
+                let expired = self
+                    .lease_collection
+                    .read()
+                    .lease_map
+                    .get(&req.id)
+                    .map_or(false, Lease::expired);
+                self.sync_lease_revoke_request(id, req, revision)?;
+                let event_type = if expired {
+                    LeaseEventType::Expire
+                } else {
+                    LeaseEventType::Revoke
+                };
+                self.notify(event_type, req.id, 0);
+                // the keys are deleted at the prepared revision, which isn't applied yet
+                return Ok(revision.revision());
+            }
+            RequestWrapper::LeaseModifyTtlRequest(ref req) => {
+                debug!("Sync LeaseModifyTtlRequest {:?}", req);
+                let lease = self
+                    .lease_collection
+                    .write()
+                    .modify_ttl(req.id, req.ttl, self.is_leader())
+                    .ok_or_else(ExecuteError::lease_not_found)?;
+                let ttl = lease.ttl;
+                self.db.buffer_op(id, WriteOp::PutLease(lease));
+                self.notify(LeaseEventType::Renew, req.id, ttl);
             }
             _ => unreachable!("Other request should not be sent to this store"),
         };
         Ok(self.header_gen.revision())
     }
 
-    /// Sync `LeaseGrantRequest`
-    fn sync_lease_grant_request(&self, id: &ProposeId, req: &LeaseGrantRequest) {
+    /// Sync `LeaseGrantRequest` and return the ttl of the granted lease
+    fn sync_lease_grant_request(&self, id: &ProposeId, req: &LeaseGrantRequest) -> i64 {
         let lease = self
             .lease_collection
             .write()
             .grant(req.id, req.ttl, self.is_leader());
+        let ttl = lease.ttl;
         self.db.buffer_op(id, WriteOp::PutLease(lease));
+        ttl
     }
 
     /// Get all `PbLease`
@@ -515,15 +659,16 @@ where
     }
 
     /// Sync `LeaseRevokeRequest`
-    async fn sync_lease_revoke_request(
+    fn sync_lease_revoke_request(
         &self,
         id: &ProposeId,
         req: &LeaseRevokeRequest,
+        prepared: &mut PreparedRevision,
     ) -> Result<(), ExecuteError> {
         self.db.buffer_op(id, WriteOp::DeleteLease(req.id));
         let keys = match self.lease_collection.read().lease_map.get(&req.id) {
-            Some(l) => l.keys(),
-            None => return Err(ExecuteError::lease_not_found(req.id)),
+            Some(l) => l.keys().iter().cloned().collect::<Vec<_>>(),
+            None => return Err(ExecuteError::lease_not_found()),
         };
 
         if keys.is_empty() {
@@ -531,19 +676,18 @@ where
             return Ok(());
         }
 
-        let revision = self.revision.next();
-        let (prev_keys, del_revs): (Vec<Vec<u8>>, Vec<Revision>) = keys
+        let revision = prepared.revision();
+        let (prev_keys, del_revs): (Vec<Vec<u8>>, Vec<Revision>) = self
+            .index
+            .delete_keys(&keys, revision, 0)
             .into_iter()
-            .zip(0..)
-            .map(|(key, sub_revision)| {
-                let (prev_rev, del_rev) = self
-                    .index
-                    .delete(&key, &[], revision, sub_revision)
-                    .pop()
-                    .unwrap_or_else(|| panic!("delete one key should return 1 result"));
-                (prev_rev.encode_to_vec(), del_rev)
-            })
+            .map(|(prev_rev, del_rev)| (prev_rev.encode_to_vec(), del_rev))
             .unzip();
+        assert_eq!(
+            del_revs.len(),
+            keys.len(),
+            "the keys attached to a lease should not be deleted"
+        );
         let prev_kvs: Vec<KeyValue> = self
             .db
             .get_values(KV_TABLE, &prev_keys)?
@@ -555,10 +699,6 @@ where
                 ExecuteError::DbError(format!("Failed to decode key-value from DB, error: {e}"))
             })?;
         assert_eq!(prev_kvs.len(), del_revs.len());
-        for kv in &prev_kvs {
-            let lease_id = self.get_lease(&kv.key);
-            self.detach(lease_id, kv.key.as_slice())?;
-        }
         prev_kvs
             .iter()
             .zip(del_revs.into_iter())
@@ -589,11 +729,14 @@ where
             })
             .collect();
 
-        let _ignore = self.lease_collection.write().revoke(req.id);
-        assert!(
-            self.kv_update_tx.send((revision, updates)).await.is_ok(),
-            "Failed to send updates to KV watcher"
-        );
+        // the keys are detached along with the lease under one lock
+        let mut lease_collection = self.lease_collection.write();
+        for key in &keys {
+            let _ignore = lease_collection.item_map.remove(key);
+        }
+        let _ignore = lease_collection.revoke(req.id);
+        drop(lease_collection);
+        prepared.notify(updates);
         Ok(())
     }
 }
@@ -605,7 +748,10 @@ mod test {
     use utils::config::StorageConfig;
 
     use super::*;
-    use crate::storage::db::DBProxy;
+    use crate::{
+        revision_number::RevisionNumber,
+        storage::{db::DBProxy, update_sequencer::UpdateSequencer},
+    };
 
     #[tokio::test(flavor = "multi_thread", worker_threads = 10)]
     async fn test_lease_storage() -> Result<(), Box<dyn Error>> {
@@ -634,6 +780,50 @@ mod test {
         Ok(())
     }
 
+    #[tokio::test(start_paused = true)]
+    async fn test_expiries_are_spread_by_the_jitter() {
+        let mut leases = LeaseCollection::new(LeaseConfig::new(
+            Duration::from_secs(10),
+            2,
+            Duration::ZERO,
+            Duration::ZERO,
+        ));
+        for id in 1..=4 {
+            let _lease = leases.grant(id, 10, true);
+        }
+        tokio::time::advance(Duration::from_secs(9)).await;
+        assert!(leases.find_expired_leases().is_empty());
+        tokio::time::advance(Duration::from_secs(2)).await;
+        assert_eq!(leases.find_expired_leases(), vec![4]);
+
+        // the expired leases are found in batches, in the order of their expiries
+        tokio::time::advance(Duration::from_secs(9)).await;
+        assert_eq!(leases.find_expired_leases(), vec![3, 2]);
+        assert_eq!(leases.find_expired_leases(), vec![1]);
+        assert!(leases.find_expired_leases().is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_frequent_renewals_are_coalesced() -> Result<(), ExecuteError> {
+        let mut leases = LeaseCollection::new(LeaseConfig::new(
+            Duration::ZERO,
+            0,
+            Duration::from_secs(5),
+            Duration::ZERO,
+        ));
+        let _lease1 = leases.grant(1, 30, true);
+        let _lease2 = leases.grant(2, 6, true);
+        tokio::time::advance(Duration::from_secs(3)).await;
+        assert_eq!(leases.coalesced_renewal(1), Some(27));
+        // the min interval is at most a third of the ttl
+        assert_eq!(leases.coalesced_renewal(2), None);
+        tokio::time::advance(Duration::from_secs(2)).await;
+        assert_eq!(leases.coalesced_renewal(1), None);
+        assert_eq!(leases.renew(1)?, 30);
+        assert_eq!(leases.coalesced_renewal(1), Some(30));
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_recover() -> Result<(), ExecuteError> {
         let db = DBProxy::open(&StorageConfig::Memory)?;
@@ -658,13 +848,66 @@ mod test {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_modify_ttl_keeps_the_keys() -> Result<(), ExecuteError> {
+        let db = DBProxy::open(&StorageConfig::Memory)?;
+        let store = init_store(Arc::clone(&db));
+
+        let req1 = RequestWithToken::new(LeaseGrantRequest { ttl: 10, id: 1 }.into());
+        let _ignore1 = exe_and_sync_req(&store, &req1).await?;
+        store.inner.attach(1, "key".into())?;
+        let req2 = RequestWithToken::new(LeaseModifyTtlRequest { id: 1, ttl: 60 }.into());
+        let res: LeaseModifyTtlResponse = exe_and_sync_req(&store, &req2).await?.into();
+        assert_eq!((res.id, res.ttl), (1, 60));
+
+        let lease = store.look_up(1).unwrap();
+        assert_eq!(lease.ttl(), Duration::from_secs(60));
+        assert_eq!(store.get_keys(1).len(), 1);
+        // the new ttl is persisted
+        let new_store = init_store(db);
+        new_store.recover()?;
+        assert_eq!(new_store.look_up(1).unwrap().ttl(), Duration::from_secs(60));
+
+        let req3 = RequestWithToken::new(LeaseModifyTtlRequest { id: 2, ttl: 60 }.into());
+        assert!(exe_and_sync_req(&store, &req3).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_observe_lease_events() -> Result<(), ExecuteError> {
+        let db = DBProxy::open(&StorageConfig::Memory)?;
+        let store = init_store(db);
+        let mut events = store.observe();
+
+        let req1 = RequestWithToken::new(LeaseGrantRequest { ttl: 10, id: 1 }.into());
+        let _ignore1 = exe_and_sync_req(&store, &req1).await?;
+        let req2 = RequestWithToken::new(LeaseRevokeRequest { id: 1 }.into());
+        let _ignore2 = exe_and_sync_req(&store, &req2).await?;
+
+        let grant = events.try_recv().unwrap();
+        assert_eq!(grant.r#type(), LeaseEventType::Grant);
+        assert_eq!((grant.id, grant.ttl), (1, 10));
+        let revoke = events.try_recv().unwrap();
+        assert_eq!(revoke.r#type(), LeaseEventType::Revoke);
+        assert_eq!((revoke.id, revoke.ttl), (1, 0));
+        assert!(events.try_recv().is_err());
+
+        Ok(())
+    }
+
     fn init_store(db: Arc<DBProxy>) -> LeaseStore<DBProxy> {
         let (_, lease_cmd_rx) = mpsc::channel(1);
-        let (kv_update_tx, _) = mpsc::channel(1);
         let state = Arc::new(State::default());
         let header_gen = Arc::new(HeaderGenerator::new(0, 0));
         let index = Arc::new(Index::new());
-        LeaseStore::new(lease_cmd_rx, state, header_gen, db, index, kv_update_tx)
+        LeaseStore::new(
+            lease_cmd_rx,
+            LeaseConfig::default(),
+            state,
+            header_gen,
+            db,
+            index,
+        )
     }
 
     async fn exe_and_sync_req(
@@ -673,7 +916,12 @@ mod test {
     ) -> Result<ResponseWrapper, ExecuteError> {
         let cmd_res = ls.execute(req)?;
         let id = ProposeId::new("test-id".to_owned());
-        let _ignore = ls.after_sync(&id, req).await?;
+        let (kv_update_tx, _kv_update_rx) = mpsc::unbounded_channel();
+        let sequencer = Arc::new(UpdateSequencer::new(
+            Arc::new(RevisionNumber::default()),
+            kv_update_tx,
+        ));
+        let _ignore = ls.after_sync(&id, req, Some(&mut sequencer.prepare()))?;
         ls.inner.db.flush(&id)?;
         Ok(cmd_res.decode())
     }
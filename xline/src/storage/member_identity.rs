@@ -0,0 +1,122 @@
+use curp::cmd::ProposeId;
+use tracing::info;
+
+use super::{db::WriteOp, storage_api::StorageApi, ExecuteError};
+use crate::server::command::META_TABLE;
+
+/// Key of the member identity in the meta table
+pub(crate) const MEMBER_IDENTITY_KEY: &str = "member_identity";
+
+/// Identity of the member which owns a storage, it's recorded at the first start of the
+/// member, so that a data dir isn't reused by another member or in another cluster.
+/// Both ids are derived from the members given at the start, see `header_gen`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct MemberIdentity {
+    /// Id of the member
+    pub(crate) member_id: u64,
+    /// Id of the cluster
+    pub(crate) cluster_id: u64,
+}
+
+impl MemberIdentity {
+    /// Encode the identity as the member id followed by the cluster id
+    pub(crate) fn encode(self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(16);
+        buf.extend_from_slice(&self.member_id.to_le_bytes());
+        buf.extend_from_slice(&self.cluster_id.to_le_bytes());
+        buf
+    }
+
+    /// Decode an encoded identity
+    fn decode(buf: &[u8]) -> Result<Self, ExecuteError> {
+        let invalid = || ExecuteError::DbError("Invalid member identity".to_owned());
+        let (member_id, cluster_id) = buf.split_at(buf.len().min(8));
+        Ok(Self {
+            member_id: u64::from_le_bytes(member_id.try_into().map_err(|_ignore| invalid())?),
+            cluster_id: u64::from_le_bytes(cluster_id.try_into().map_err(|_ignore| invalid())?),
+        })
+    }
+
+    /// Get the identity recorded in the meta table
+    pub(crate) fn read<S: StorageApi>(storage: &S) -> Result<Option<Self>, ExecuteError> {
+        storage
+            .get_value(META_TABLE, MEMBER_IDENTITY_KEY)?
+            .map(|buf| Self::decode(&buf))
+            .transpose()
+    }
+}
+
+/// Check that the storage belongs to the member of `identity` before it's recovered, the
+/// identity is recorded if the storage has none, ie. it's new, restored from a snapshot,
+/// or written before the identity is recorded.
+///
+/// A storage of another cluster is refused, eg. a data dir left by an old cluster, and so
+/// is a storage of another member in the same cluster, eg. a data dir copied from a peer,
+/// or one of a member started again with another name or address after it's replaced.
+/// Both would give the cluster two members with the same history under different ids.
+pub(crate) fn check<S: StorageApi>(
+    storage: &S,
+    identity: MemberIdentity,
+) -> Result<(), ExecuteError> {
+    match MemberIdentity::read(storage)? {
+        Some(recorded) if recorded.cluster_id != identity.cluster_id => {
+            Err(ExecuteError::DbError(format!(
+                "the data dir belongs to member {:x} of cluster {:x}, but this member starts in \
+                 cluster {:x}, start it with the members of its cluster, or with an empty data \
+                 dir to join the new cluster",
+                recorded.member_id, recorded.cluster_id, identity.cluster_id
+            )))
+        }
+        Some(recorded) if recorded.member_id != identity.member_id => {
+            Err(ExecuteError::DbError(format!(
+                "the data dir belongs to member {:x}, but this member starts as member {:x} of \
+                 the same cluster, the name or the address of a member can't change, start it \
+                 with an empty data dir to join as a new member",
+                recorded.member_id, identity.member_id
+            )))
+        }
+        Some(_) => Ok(()),
+        None => {
+            info!(
+                "record the storage as member {:x} of cluster {:x}",
+                identity.member_id, identity.cluster_id
+            );
+            let id = ProposeId::new(MEMBER_IDENTITY_KEY.to_owned());
+            storage.buffer_op(&id, WriteOp::PutMemberIdentity(identity.encode()));
+            storage.flush(&id)?;
+            storage.sync()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use utils::config::StorageConfig;
+
+    use super::*;
+    use crate::storage::db::DBProxy;
+
+    #[test]
+    fn test_storage_is_refused_by_other_members() {
+        let db = DBProxy::open(&StorageConfig::Memory).unwrap();
+        let identity = MemberIdentity {
+            member_id: 1,
+            cluster_id: 10,
+        };
+        check(db.as_ref(), identity).unwrap();
+        assert_eq!(MemberIdentity::read(db.as_ref()).unwrap(), Some(identity));
+        check(db.as_ref(), identity).unwrap();
+
+        let other_member = MemberIdentity {
+            member_id: 2,
+            cluster_id: 10,
+        };
+        assert!(check(db.as_ref(), other_member).is_err());
+        let other_cluster = MemberIdentity {
+            member_id: 1,
+            cluster_id: 11,
+        };
+        assert!(check(db.as_ref(), other_cluster).is_err());
+        assert_eq!(MemberIdentity::read(db.as_ref()).unwrap(), Some(identity));
+    }
+}
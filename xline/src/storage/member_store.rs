@@ -0,0 +1,430 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use curp::cmd::ProposeId;
+use parking_lot::RwLock;
+use prost::Message;
+use tracing::info;
+
+use super::{db::WriteOp, storage_api::StorageApi, ExecuteError};
+use crate::{
+    header_gen::{gen_member_id, HeaderGenerator},
+    rpc::{
+        Member, MemberListResponse, MemberUpdateRequest, MemberUpdateResponse, RequestWithToken,
+        RequestWrapper, ResponseHeader, ResponseWrapper,
+    },
+    server::command::{CommandResponse, SyncResponse, META_TABLE},
+    state::State,
+};
+
+/// Key of the members updated by `MemberUpdate` in the meta table
+pub(crate) const MEMBERS_KEY: &str = "members";
+
+/// Members recorded in the storage, a map from their names to their ids and addresses
+type RecordedMembers = HashMap<String, (u64, String)>;
+
+/// Get the address of a peer URL, it's an http or https URL or an address, with a port and
+/// without a path, eg. `http://10.0.0.1:2379`
+pub(crate) fn peer_addr(url: &str) -> Option<&str> {
+    let addr = url
+        .strip_prefix("http://")
+        .or_else(|| url.strip_prefix("https://"))
+        .unwrap_or(url);
+    let (host, port) = addr.rsplit_once(':')?;
+    (!host.is_empty() && !host.contains('/') && port.parse::<u16>().is_ok()).then_some(addr)
+}
+
+/// Whether a peer URL has the scheme `scheme`, an address without a scheme has any scheme
+fn has_scheme(url: &str, scheme: &str) -> bool {
+    url.split_once("://")
+        .map_or(true, |(url_scheme, _)| url_scheme == scheme)
+}
+
+/// Get the members recorded by the last `MemberUpdate`, `None` if no member is updated
+pub(crate) fn load<S: StorageApi>(storage: &S) -> Result<Option<RecordedMembers>, ExecuteError> {
+    let Some(buf) = storage.get_value(META_TABLE, MEMBERS_KEY)? else {
+        return Ok(None);
+    };
+    let list = MemberListResponse::decode(buf.as_slice())
+        .map_err(|e| ExecuteError::DbError(format!("Invalid members, error: {e}")))?;
+    list.members
+        .into_iter()
+        .map(|member| {
+            let addr = member
+                .peer_ur_ls
+                .first()
+                .and_then(|url| peer_addr(url))
+                .ok_or_else(|| {
+                    ExecuteError::DbError(format!("Invalid address of member {}", member.name))
+                })?
+                .to_owned();
+            Ok((member.name, (member.id, addr)))
+        })
+        .collect::<Result<_, _>>()
+        .map(Some)
+}
+
+/// Resolve the members given by the config against the recorded ones, return the
+/// addresses and the ids of the members. A member keeps the id it's recorded with after its
+/// address is updated, and the recorded addresses win over the config, which may be stale on
+/// the members started before the update, except the address of the member `name` itself,
+/// which it's started on.
+pub(crate) fn resolve(
+    name: &str,
+    members: HashMap<String, String>,
+    recorded: Option<RecordedMembers>,
+) -> (HashMap<String, String>, HashMap<String, u64>) {
+    let mut recorded = recorded.unwrap_or_default();
+    let mut ids = HashMap::new();
+    let addrs = members
+        .into_iter()
+        .map(|(member, addr)| {
+            let (id, addr) = match recorded.remove(&member) {
+                Some((id, recorded_addr)) if member != name && recorded_addr != addr => {
+                    info!("member {member} is at {recorded_addr} since it's updated, not {addr}");
+                    (id, recorded_addr)
+                }
+                Some((id, _)) => (id, addr),
+                None => (gen_member_id(&member, &addr), addr),
+            };
+            let _prev = ids.insert(member.clone(), id);
+            (member, addr)
+        })
+        .collect();
+    (addrs, ids)
+}
+
+/// Members of the cluster, their names and ids are given by the config, and their
+/// addresses are updated by `MemberUpdate`. The updates are applied to the connections of
+/// the other members through the `State`, while a member keeps serving on the address it's
+/// started on until it's restarted on the updated one.
+#[derive(Debug)]
+pub(crate) struct MemberStore {
+    /// State of current node
+    state: Arc<State>,
+    /// Header generator
+    header_gen: Arc<HeaderGenerator>,
+    /// Names of the standby members, they are listed as learners
+    standbys: HashSet<String>,
+    /// Addresses of the members applied from the log, including the address of this member
+    addrs: RwLock<HashMap<String, String>>,
+}
+
+impl MemberStore {
+    /// New `MemberStore`
+    pub(crate) fn new(
+        state: Arc<State>,
+        header_gen: Arc<HeaderGenerator>,
+        standbys: HashSet<String>,
+    ) -> Self {
+        let addrs = RwLock::new(state.members());
+        Self {
+            state,
+            header_gen,
+            standbys,
+            addrs,
+        }
+    }
+
+    /// Header of the responses which list the members
+    pub(crate) fn header(&self) -> ResponseHeader {
+        self.header_gen.gen_header_without_revision()
+    }
+
+    /// All members of the cluster, sorted by their ids
+    pub(crate) fn members(&self) -> Vec<Member> {
+        self.members_of(&self.addrs.read())
+    }
+
+    /// Members at `addrs`, sorted by their ids, the URLs have the scheme the members are
+    /// connected with, `https` if peer TLS is enabled
+    fn members_of(&self, addrs: &HashMap<String, String>) -> Vec<Member> {
+        let scheme = self.state.peer_scheme();
+        let mut members: Vec<_> = addrs
+            .iter()
+            .map(|(name, addr)| Member {
+                id: self
+                    .state
+                    .member_id(name)
+                    .unwrap_or_else(|| gen_member_id(name, addr)),
+                name: name.clone(),
+                peer_ur_ls: vec![format!("{scheme}://{addr}")],
+                client_ur_ls: vec![format!("{scheme}://{addr}")],
+                is_learner: self.standbys.contains(name),
+            })
+            .collect();
+        members.sort_unstable_by_key(|member| member.id);
+        members
+    }
+
+    /// Get the name and the new address of the member updated by `req`, the scheme of the
+    /// URL must be the one the members are connected with
+    fn update_of<'a>(
+        &'a self,
+        req: &'a MemberUpdateRequest,
+    ) -> Result<(&'a str, &'a str), ExecuteError> {
+        let name = self
+            .state
+            .member_name(req.id)
+            .ok_or_else(ExecuteError::member_not_found)?;
+        let addr = match *req.peer_ur_ls {
+            [ref url] if has_scheme(url, self.state.peer_scheme()) => peer_addr(url),
+            _ => None,
+        }
+        .ok_or_else(ExecuteError::member_bad_urls)?;
+        Ok((name, addr))
+    }
+
+    /// Get the update of a member from a request
+    fn member_update(wrapper: &RequestWithToken) -> &MemberUpdateRequest {
+        let RequestWrapper::MemberUpdateRequest(ref req) = wrapper.request else {
+            unreachable!("only the cluster requests are handled by the member store");
+        };
+        req
+    }
+
+    /// Execute a cluster request, the response lists the members with the update
+    pub(crate) fn execute(
+        &self,
+        wrapper: &RequestWithToken,
+    ) -> Result<CommandResponse, ExecuteError> {
+        let (name, addr) = self.update_of(Self::member_update(wrapper))?;
+        let mut addrs = self.addrs.read().clone();
+        let _prev = addrs.insert(name.to_owned(), addr.to_owned());
+        Ok(CommandResponse::new(ResponseWrapper::MemberUpdateResponse(
+            MemberUpdateResponse {
+                header: Some(self.header()),
+                members: self.members_of(&addrs),
+            },
+        )))
+    }
+
+    /// Sync a cluster request, the updated members are recorded in the meta table, and the
+    /// address of another member is applied to the `State`
+    pub(crate) fn after_sync<S: StorageApi>(
+        &self,
+        storage: &S,
+        id: &ProposeId,
+        wrapper: &RequestWithToken,
+    ) -> Result<SyncResponse, ExecuteError> {
+        let (name, addr) = self.update_of(Self::member_update(wrapper))?;
+        let members = {
+            let mut addrs = self.addrs.write();
+            let _prev = addrs.insert(name.to_owned(), addr.to_owned());
+            self.members_of(&addrs)
+        };
+        self.apply(name, addr);
+        storage.buffer_op(id, WriteOp::PutMembers(Self::encode(members)));
+        Ok(SyncResponse::new(self.header_gen.revision()))
+    }
+
+    /// Recover the members from the storage, eg. after a snapshot is installed
+    pub(crate) fn recover<S: StorageApi>(&self, storage: &S) -> Result<(), ExecuteError> {
+        let Some(recorded) = load(storage)? else {
+            return Ok(());
+        };
+        let mut addrs = self.addrs.write();
+        for (name, (_, addr)) in recorded {
+            if let Some(old) = addrs.get_mut(&name) {
+                self.apply(&name, &addr);
+                *old = addr;
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply the address of a member to the `State`, this member keeps its own address
+    fn apply(&self, name: &str, addr: &str) {
+        if name != self.state.id() && self.state.update_member(name, addr.to_owned()) {
+            info!("the address of member {name} is updated to {addr}");
+        }
+    }
+
+    /// Encode the members to be recorded in the meta table
+    fn encode(members: Vec<Member>) -> Vec<u8> {
+        MemberListResponse {
+            header: None,
+            members,
+        }
+        .encode_to_vec()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tonic::transport::ClientTlsConfig;
+    use utils::config::StorageConfig;
+
+    use super::*;
+    use crate::storage::db::DBProxy;
+
+    fn store() -> MemberStore {
+        let members = HashMap::from([
+            ("node1".to_owned(), "127.0.0.1:2379".to_owned()),
+            ("node2".to_owned(), "127.0.0.1:2380".to_owned()),
+        ]);
+        let state = Arc::new(State::new("node1".to_owned(), None, members));
+        MemberStore::new(
+            state,
+            Arc::new(HeaderGenerator::new(1, 2)),
+            HashSet::from(["node2".to_owned()]),
+        )
+    }
+
+    fn update(store: &MemberStore, name: &str, url: &str) -> RequestWithToken {
+        RequestWithToken::new(RequestWrapper::MemberUpdateRequest(MemberUpdateRequest {
+            id: store.state.member_id(name).unwrap(),
+            peer_ur_ls: vec![url.to_owned()],
+        }))
+    }
+
+    #[test]
+    fn test_peer_addr() {
+        assert_eq!(peer_addr("http://10.0.0.1:2379"), Some("10.0.0.1:2379"));
+        assert_eq!(peer_addr("node1:2379"), Some("node1:2379"));
+        assert_eq!(peer_addr("http://[::1]:2379"), Some("[::1]:2379"));
+        assert_eq!(peer_addr("http://10.0.0.1"), None);
+        assert_eq!(peer_addr("https://10.0.0.1:2379"), Some("10.0.0.1:2379"));
+        assert_eq!(peer_addr("ftp://10.0.0.1:2379"), None);
+        assert_eq!(peer_addr("http://10.0.0.1:2379/"), None);
+        assert_eq!(peer_addr(":2379"), None);
+    }
+
+    #[test]
+    fn test_member_list() {
+        let store = store();
+        let members = store.members();
+        assert_eq!(members.len(), 2);
+        let node2 = members
+            .iter()
+            .find(|member| member.name == "node2")
+            .unwrap();
+        assert_eq!(node2.id, gen_member_id("node2", "127.0.0.1:2380"));
+        assert_eq!(node2.peer_ur_ls, vec!["http://127.0.0.1:2380".to_owned()]);
+        assert!(node2.is_learner);
+    }
+
+    #[test]
+    fn test_member_update_is_recorded() {
+        let db = DBProxy::open(&StorageConfig::Memory).unwrap();
+        let store = store();
+        let node2_id = store.state.member_id("node2").unwrap();
+
+        let req = update(&store, "node2", "http://10.0.0.2:2380");
+        let res = store.execute(&req).unwrap().decode();
+        let ResponseWrapper::MemberUpdateResponse(res) = res else {
+            panic!("unexpected response {res:?}");
+        };
+        let node2 = res.members.iter().find(|m| m.id == node2_id).unwrap();
+        assert_eq!(node2.peer_ur_ls, vec!["http://10.0.0.2:2380".to_owned()]);
+        // the update isn't applied until it's synced
+        assert_eq!(store.state.others()["node2"], "127.0.0.1:2380");
+
+        let id = ProposeId::new("update-node2".to_owned());
+        let _sync_res = store.after_sync(db.as_ref(), &id, &req).unwrap();
+        db.flush(&id).unwrap();
+        assert_eq!(store.state.others()["node2"], "10.0.0.2:2380");
+        assert_eq!(store.members(), res.members);
+
+        // this member keeps serving on its address until it's restarted
+        let req = update(&store, "node1", "http://10.0.0.1:2379");
+        let id = ProposeId::new("update-node1".to_owned());
+        let _sync_res = store.after_sync(db.as_ref(), &id, &req).unwrap();
+        db.flush(&id).unwrap();
+        assert_eq!(store.state.self_address(), "127.0.0.1:2379");
+
+        let recorded = load(db.as_ref()).unwrap().unwrap();
+        assert_eq!(recorded["node2"], (node2_id, "10.0.0.2:2380".to_owned()));
+        assert_eq!(recorded["node1"].1, "10.0.0.1:2379");
+
+        let unknown =
+            RequestWithToken::new(RequestWrapper::MemberUpdateRequest(MemberUpdateRequest {
+                id: 3,
+                peer_ur_ls: vec!["http://10.0.0.3:2380".to_owned()],
+            }));
+        assert!(store.execute(&unknown).is_err());
+    }
+
+    #[test]
+    fn test_member_update_to_an_https_url() {
+        let db = DBProxy::open(&StorageConfig::Memory).unwrap();
+        let members = HashMap::from([
+            ("node1".to_owned(), "127.0.0.1:2379".to_owned()),
+            ("node2".to_owned(), "127.0.0.1:2380".to_owned()),
+        ]);
+        let member_ids = members
+            .iter()
+            .map(|(name, addr)| (name.clone(), gen_member_id(name, addr)))
+            .collect();
+        let state = Arc::new(State::with_member_ids(
+            "node1".to_owned(),
+            None,
+            members,
+            member_ids,
+            Some(ClientTlsConfig::new()),
+        ));
+        let store = MemberStore::new(state, Arc::new(HeaderGenerator::new(1, 2)), HashSet::new());
+        assert!(store
+            .members()
+            .iter()
+            .flat_map(|member| &member.peer_ur_ls)
+            .all(|url| url.starts_with("https://")));
+
+        let req = update(&store, "node2", "https://10.0.0.2:2380");
+        let _res = store.execute(&req).unwrap();
+        let id = ProposeId::new("update-node2".to_owned());
+        let _sync_res = store.after_sync(db.as_ref(), &id, &req).unwrap();
+        db.flush(&id).unwrap();
+        assert_eq!(store.state.others()["node2"], "10.0.0.2:2380");
+        let node2 = store
+            .members()
+            .into_iter()
+            .find(|member| member.name == "node2")
+            .unwrap();
+        assert_eq!(node2.peer_ur_ls, vec!["https://10.0.0.2:2380".to_owned()]);
+        let recorded = load(db.as_ref()).unwrap().unwrap();
+        assert_eq!(recorded["node2"].1, "10.0.0.2:2380");
+
+        // the members are connected over TLS, so an http URL is refused
+        let req = update(&store, "node2", "http://10.0.0.3:2380");
+        assert!(store.execute(&req).is_err());
+        let req = update(&store, "node2", "10.0.0.3:2380");
+        assert!(store.execute(&req).is_ok());
+
+        // and an https URL is refused without peer TLS
+        let plain = store();
+        let req = update(&plain, "node2", "https://10.0.0.2:2380");
+        assert!(plain.execute(&req).is_err());
+    }
+
+    #[test]
+    fn test_resolve_keeps_the_recorded_members() {
+        let node2_id = gen_member_id("node2", "127.0.0.1:2380");
+        let recorded = HashMap::from([
+            ("node1".to_owned(), (1, "10.0.0.1:2379".to_owned())),
+            ("node2".to_owned(), (node2_id, "10.0.0.2:2380".to_owned())),
+        ]);
+        let config = HashMap::from([
+            ("node1".to_owned(), "10.0.0.1:2379".to_owned()),
+            ("node2".to_owned(), "127.0.0.1:2380".to_owned()),
+            ("node3".to_owned(), "127.0.0.1:2381".to_owned()),
+        ]);
+        let (addrs, ids) = resolve("node1", config.clone(), Some(recorded));
+        assert_eq!(addrs["node2"], "10.0.0.2:2380");
+        assert_eq!(ids["node2"], node2_id);
+        assert_eq!(ids["node1"], 1);
+        assert_eq!(ids["node3"], gen_member_id("node3", "127.0.0.1:2381"));
+
+        // a member is started on the address in its config
+        let recorded =
+            HashMap::from([("node2".to_owned(), (node2_id, "10.0.0.2:2380".to_owned()))]);
+        let (addrs, ids) = resolve("node2", config.clone(), Some(recorded));
+        assert_eq!(addrs["node2"], "127.0.0.1:2380");
+        assert_eq!(ids["node2"], node2_id);
+
+        let (addrs, _) = resolve("node1", config.clone(), None);
+        assert_eq!(addrs, config);
+    }
+}
@@ -0,0 +1,176 @@
+use curp::cmd::ProposeId;
+use tracing::info;
+
+use super::{
+    db::{WriteOp, XLINE_TABLES},
+    storage_api::StorageApi,
+    ExecuteError,
+};
+use crate::server::command::{APPLIED_INDEX_KEY, META_TABLE};
+
+/// Key of the storage version in the meta table
+pub(crate) const STORAGE_VERSION_KEY: &str = "storage_version";
+
+/// Version of the on-disk format written by this build. It must be bumped along with a new
+/// migration whenever the encoding of a table changes, eg. the layout of the revisions in
+/// the kv table, the lease proto or the auth tables.
+pub(crate) const STORAGE_VERSION: u64 = 1;
+
+/// Upgrade the tables from one version to the next one, it returns the writes to be
+/// flushed together with the new version
+type MigrateFn<S> = fn(&S) -> Result<Vec<WriteOp>, ExecuteError>;
+
+/// Migration of the storage from a version to the next one
+struct Migration<S> {
+    /// The version it upgrades from
+    from: u64,
+    /// What it changes, it's logged when it runs
+    description: &'static str,
+    /// The upgrade
+    migrate: MigrateFn<S>,
+}
+
+/// All migrations, in the order of the versions they upgrade from
+fn migrations<S: StorageApi>() -> Vec<Migration<S>> {
+    vec![]
+}
+
+/// Upgrade the storage to the version written by this build before it's recovered. Each
+/// migration is flushed atomically along with the version it upgrades to, so an interrupted
+/// upgrade resumes from the last finished migration at the next start.
+///
+/// The storage without a version is either new, or written before the versioning, whose
+/// format is version 1. The table checksums recorded at the last shutdown are dropped once
+/// a migration rewrites the tables.
+pub(crate) fn migrate<S: StorageApi>(storage: &S) -> Result<(), ExecuteError> {
+    migrate_to(storage, &migrations(), STORAGE_VERSION)
+}
+
+/// Upgrade the storage to `target` by `migrations`
+fn migrate_to<S: StorageApi>(
+    storage: &S,
+    migrations: &[Migration<S>],
+    target: u64,
+) -> Result<(), ExecuteError> {
+    let mut version = match read_storage_version(storage)? {
+        Some(version) => version,
+        None if is_empty(storage)? => target,
+        None => 1,
+    };
+    if version > target {
+        return Err(ExecuteError::DbError(format!(
+            "storage version {version} is newer than {target}, it's written by a newer xline"
+        )));
+    }
+    let id = ProposeId::new(STORAGE_VERSION_KEY.to_owned());
+    while version < target {
+        let migration = migrations
+            .iter()
+            .find(|m| m.from == version)
+            .ok_or_else(|| {
+                ExecuteError::DbError(format!("no migration from storage version {version}"))
+            })?;
+        info!(
+            "migrate storage from version {version}: {}",
+            migration.description
+        );
+        for op in (migration.migrate)(storage)? {
+            storage.buffer_op(&id, op);
+        }
+        version = version.saturating_add(1);
+        storage.buffer_op(&id, WriteOp::DeleteChecksums);
+        storage.buffer_op(&id, WriteOp::PutStorageVersion(version));
+        storage.flush(&id)?;
+    }
+    if read_storage_version(storage)? != Some(version) {
+        storage.buffer_op(&id, WriteOp::PutStorageVersion(version));
+        storage.flush(&id)?;
+    }
+    storage.sync()
+}
+
+/// Check if nothing is written to the storage. The applied index isn't written by
+/// `xline_snapshot restore`, so the tables are checked too.
+pub(crate) fn is_empty<S: StorageApi>(storage: &S) -> Result<bool, ExecuteError> {
+    if storage.get_value(META_TABLE, APPLIED_INDEX_KEY)?.is_some() {
+        return Ok(false);
+    }
+    for table in XLINE_TABLES {
+        let mut empty = true;
+        // stop the scan at the first key
+        let scanned = storage.scan(table, |_key, _value| {
+            empty = false;
+            Err(ExecuteError::DbError(format!("{table} is not empty")))
+        });
+        if !empty {
+            return Ok(false);
+        }
+        scanned?;
+    }
+    Ok(true)
+}
+
+/// Get the storage version stored in the meta table
+fn read_storage_version<S: StorageApi>(storage: &S) -> Result<Option<u64>, ExecuteError> {
+    let Some(buf) = storage.get_value(META_TABLE, STORAGE_VERSION_KEY)? else {
+        return Ok(None);
+    };
+    let buf = buf
+        .try_into()
+        .map_err(|_ignore| ExecuteError::DbError("Invalid storage version".to_owned()))?;
+    Ok(Some(u64::from_le_bytes(buf)))
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use utils::config::StorageConfig;
+
+    use super::*;
+    use crate::storage::db::DBProxy;
+
+    fn init_db(applied_index: Option<u64>) -> Arc<DBProxy> {
+        let db = DBProxy::open(&StorageConfig::Memory).unwrap();
+        if let Some(index) = applied_index {
+            let id = ProposeId::new("init".to_owned());
+            db.buffer_op(&id, WriteOp::PutAppliedIndex(index));
+            db.flush(&id).unwrap();
+        }
+        db
+    }
+
+    fn rewrite_applied_index(storage: &DBProxy) -> Result<Vec<WriteOp>, ExecuteError> {
+        let _index = storage.get_value(META_TABLE, APPLIED_INDEX_KEY)?;
+        Ok(vec![WriteOp::PutAppliedIndex(100)])
+    }
+
+    #[test]
+    fn test_new_storage_is_marked_with_current_version() {
+        let db = init_db(None);
+        migrate(db.as_ref()).unwrap();
+        assert_eq!(
+            read_storage_version(db.as_ref()).unwrap(),
+            Some(STORAGE_VERSION)
+        );
+    }
+
+    #[test]
+    fn test_migrate_from_unversioned_storage() {
+        let db = init_db(Some(5));
+        let migrations = [Migration {
+            from: 1,
+            description: "rewrite applied index",
+            migrate: rewrite_applied_index,
+        }];
+        migrate_to(db.as_ref(), &migrations, 2).unwrap();
+        assert_eq!(read_storage_version(db.as_ref()).unwrap(), Some(2));
+        assert_eq!(
+            db.get_value(META_TABLE, APPLIED_INDEX_KEY).unwrap(),
+            Some(100_u64.to_le_bytes().to_vec())
+        );
+
+        assert!(migrate_to(db.as_ref(), &migrations, 1).is_err());
+        assert!(migrate_to(db.as_ref(), &[], 3).is_err());
+    }
+}
@@ -1,21 +1,62 @@
+/// Alarms of the member
+pub(crate) mod alarm_store;
+/// Propose ids of the applied log entries
+pub(crate) mod applied_ids;
 /// Storage for Auth
 pub(crate) mod auth_store;
+/// Reader of bbolt databases
+pub(crate) mod bbolt;
+/// Durable log of the flushed operations
+pub mod change_log;
 /// Database module
 pub mod db;
+/// Registrations of the durable watchers
+pub(crate) mod durable_watch;
+/// Import of etcd snapshots
+pub mod etcd_snapshot;
 /// Execute error
 pub(crate) mod execute_error;
 /// Index module
 pub(crate) mod index;
+/// Persisted snapshot of the index
+pub(crate) mod index_snapshot;
+/// Corruption checks of the storage
+pub(crate) mod integrity;
+/// Model based tests of the kv store
+#[cfg(test)]
+mod kv_model_test;
 /// Storage for KV
 pub(crate) mod kv_store;
 /// KV watcher module
 pub(crate) mod kvwatcher;
 /// Storage for lease
 pub(crate) mod lease_store;
+/// Identity of the member which owns the storage
+pub(crate) mod member_identity;
+/// Members of the cluster
+pub(crate) mod member_store;
+/// Versioning and migrations of the on-disk format
+pub(crate) mod migration;
+/// Namespaces of the keys
+pub(crate) mod namespace;
+/// Per user write quotas
+pub mod quota;
 /// Revision module
 pub(crate) mod revision;
+/// Index from the wall-clock time to the revisions
+pub(crate) mod revision_time;
+/// Offline snapshot of the storage
+pub mod snapshot;
 /// Persistent storage abstraction
 pub(crate) mod storage_api;
+/// Statistics of the tables
+pub(crate) mod table_stats;
+/// Trash of the deleted keys
+pub(crate) mod trash;
+/// Sequencer of the kv updates
+pub(crate) mod update_sequencer;
+/// Filters of the watch events
+pub(crate) mod watch_filter;
 
 pub(crate) use self::{
     auth_store::AuthStore, execute_error::ExecuteError, kv_store::KvStore, lease_store::LeaseStore,
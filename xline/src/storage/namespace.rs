@@ -0,0 +1,314 @@
+use std::collections::HashMap;
+
+use clippy_utilities::NumericCast;
+use parking_lot::RwLock;
+use utils::config::NamespaceConfig;
+
+use super::{storage_api::StorageApi, ExecuteError, KvStore};
+use crate::{
+    rpc::{Event, EventType, KeyValue, PutRequest, Request, RequestOp, RequestWrapper},
+    server::command::KeyRange,
+};
+
+/// Bytes and keys stored in a namespace
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct NamespaceUsage {
+    /// Bytes of the keys and values
+    bytes: u64,
+    /// Number of the keys
+    keys: u64,
+}
+
+impl NamespaceUsage {
+    /// New `NamespaceUsage`
+    #[cfg(test)]
+    fn new(bytes: u64, keys: u64) -> Self {
+        Self { bytes, keys }
+    }
+
+    /// Add a key-value to the usage
+    fn add(&mut self, kv: &KeyValue) {
+        self.bytes = self.bytes.saturating_add(size_of(kv));
+        self.keys = self.keys.saturating_add(1);
+    }
+
+    /// Remove a key-value from the usage
+    fn remove(&mut self, kv: &KeyValue) {
+        self.bytes = self.bytes.saturating_sub(size_of(kv));
+        self.keys = self.keys.saturating_sub(1);
+    }
+}
+
+/// Bytes of the key and value of a key-value
+fn size_of(kv: &KeyValue) -> u64 {
+    kv.key.len().saturating_add(kv.value.len()).numeric_cast()
+}
+
+/// A namespace in the store
+#[derive(Debug)]
+struct Namespace {
+    /// Config of the namespace
+    config: NamespaceConfig,
+    /// Range of the keys under the prefix
+    range: KeyRange,
+}
+
+impl Namespace {
+    /// Check if a user with `roles` is bound to the namespace
+    fn is_bound_to(&self, roles: &[String]) -> bool {
+        self.config.roles().iter().any(|role| roles.contains(role))
+    }
+}
+
+/// Namespaces of the keys. A user bound to some namespaces by its roles can only access
+/// the keys under their prefixes, and the keys under a prefix are limited by the quotas of
+/// the namespace, whoever writes them.
+///
+/// The usages are the keys stored in the namespaces at the applied revision, they are
+/// updated by the events of the synced commands, and rebuilt from the kv store when it's
+/// recovered. The hard quotas are checked when the writes are executed, so the concurrent
+/// writes to a namespace may exceed them a little.
+#[derive(Debug)]
+pub(crate) struct NamespaceStore {
+    /// Namespaces, a key belongs to the first namespace whose prefix contains it
+    namespaces: Vec<Namespace>,
+    /// Usages of the namespaces by name
+    usages: RwLock<HashMap<String, NamespaceUsage>>,
+}
+
+impl NamespaceStore {
+    /// New `NamespaceStore`
+    pub(crate) fn new(configs: Vec<NamespaceConfig>) -> Self {
+        let namespaces = configs
+            .into_iter()
+            .map(|config| {
+                let prefix = config.prefix().as_bytes();
+                let range = KeyRange::new(prefix, KeyRange::get_prefix(prefix));
+                Namespace { config, range }
+            })
+            .collect();
+        Self {
+            namespaces,
+            usages: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Get the namespace of a key
+    fn namespace_of(&self, key: &[u8]) -> Option<&Namespace> {
+        self.namespaces
+            .iter()
+            .find(|namespace| namespace.range.contains_key(key))
+    }
+
+    /// Check if a user with `roles` may access `range`. The users not bound to any
+    /// namespace are only limited by their permissions.
+    pub(crate) fn permits(&self, roles: &[String], range: &KeyRange) -> bool {
+        let mut bound = self
+            .namespaces
+            .iter()
+            .filter(|namespace| namespace.is_bound_to(roles))
+            .peekable();
+        bound.peek().is_none() || bound.any(|namespace| namespace.range.contains_range(range))
+    }
+
+    /// Rebuild the usages from the latest key-values in the kv store
+    pub(crate) fn recover<S: StorageApi>(&self, kv_store: &KvStore<S>) -> Result<(), ExecuteError> {
+        let mut usages = HashMap::new();
+        for namespace in &self.namespaces {
+            let mut usage = NamespaceUsage::default();
+            for kv in kv_store.latest_kvs(&namespace.range.start, &namespace.range.end)? {
+                // a key under nested prefixes belongs to the first namespace
+                if self.namespace_of(&kv.key).map_or(false, |owner| {
+                    owner.config.name() == namespace.config.name()
+                }) {
+                    usage.add(&kv);
+                }
+            }
+            let _prev = usages.insert(namespace.config.name().clone(), usage);
+        }
+        *self.usages.write() = usages;
+        Ok(())
+    }
+
+    /// Check if `request` can be written without exceeding the hard quotas of the
+    /// namespaces. Which branch of a txn succeeds is unknown before it's executed, and an
+    /// existing key may be overwritten, so every put is counted as a new key.
+    pub(crate) fn check(&self, request: &RequestWrapper) -> Result<(), ExecuteError> {
+        if self.namespaces.is_empty() {
+            return Ok(());
+        }
+        let mut costs: HashMap<&str, NamespaceUsage> = HashMap::new();
+        #[allow(clippy::wildcard_enum_match_arm)]
+        match *request {
+            RequestWrapper::PutRequest(ref req) => self.cost_of_put(req, &mut costs),
+            RequestWrapper::TxnRequest(ref req) => {
+                self.cost_of_ops(req.success.iter().chain(req.failure.iter()), &mut costs);
+            }
+            _ => return Ok(()),
+        }
+        let usages = self.usages.read();
+        for namespace in &self.namespaces {
+            let Some(cost) = costs.get(namespace.config.name().as_str()) else {
+                continue;
+            };
+            let usage = usages
+                .get(namespace.config.name())
+                .copied()
+                .unwrap_or_default();
+            if exceeds(
+                usage.bytes.saturating_add(cost.bytes),
+                *namespace.config.hard_bytes(),
+            ) || exceeds(
+                usage.keys.saturating_add(cost.keys),
+                *namespace.config.hard_keys(),
+            ) {
+                return Err(ExecuteError::namespace_quota_exceeded());
+            }
+        }
+        Ok(())
+    }
+
+    /// Add the cost of a put to the namespace of its key
+    fn cost_of_put<'a>(&'a self, req: &PutRequest, costs: &mut HashMap<&'a str, NamespaceUsage>) {
+        if let Some(namespace) = self.namespace_of(&req.key) {
+            let cost = costs.entry(namespace.config.name().as_str()).or_default();
+            cost.bytes = cost
+                .bytes
+                .saturating_add(req.key.len().saturating_add(req.value.len()).numeric_cast());
+            cost.keys = cost.keys.saturating_add(1);
+        }
+    }
+
+    /// Add the costs of the puts in the operations of a txn
+    fn cost_of_ops<'a, 'b>(
+        &'a self,
+        ops: impl Iterator<Item = &'b RequestOp>,
+        costs: &mut HashMap<&'a str, NamespaceUsage>,
+    ) {
+        for op in ops {
+            match op.request {
+                Some(Request::RequestPut(ref req)) => self.cost_of_put(req, costs),
+                Some(Request::RequestTxn(ref req)) => {
+                    self.cost_of_ops(req.success.iter().chain(req.failure.iter()), costs);
+                }
+                Some(Request::RequestRange(_) | Request::RequestDeleteRange(_)) | None => {}
+            }
+        }
+    }
+
+    /// Update the usages by the events of a synced command
+    pub(crate) fn apply(&self, events: &[Event]) {
+        if self.namespaces.is_empty() {
+            return;
+        }
+        let mut usages = self.usages.write();
+        for event in events {
+            let Some(ref kv) = event.kv else {
+                continue;
+            };
+            let Some(namespace) = self.namespace_of(&kv.key) else {
+                continue;
+            };
+            let usage = usages.entry(namespace.config.name().clone()).or_default();
+            if let Some(ref prev) = event.prev_kv {
+                usage.remove(prev);
+            }
+            #[allow(clippy::as_conversions)] // This cast is always valid
+            let is_put = event.r#type == EventType::Put as i32;
+            if is_put {
+                usage.add(kv);
+            }
+        }
+    }
+
+    /// Get the usage of a namespace
+    #[cfg(test)]
+    fn usage(&self, name: &str) -> NamespaceUsage {
+        self.usages.read().get(name).copied().unwrap_or_default()
+    }
+
+    /// Forget all usages
+    pub(crate) fn clear(&self) {
+        self.usages.write().clear();
+    }
+}
+
+/// Check if `value` exceeds `quota`, 0 means no limit
+fn exceeds(value: u64, quota: u64) -> bool {
+    quota != 0 && value > quota
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+
+    use super::*;
+
+    fn store() -> NamespaceStore {
+        NamespaceStore::new(vec![
+            NamespaceConfig::new(
+                "a".to_owned(),
+                "/a/".to_owned(),
+                vec!["role-a".to_owned()],
+                0,
+                2,
+            ),
+            NamespaceConfig::new("b".to_owned(), "/b/".to_owned(), vec![], 10, 0),
+        ])
+    }
+
+    fn put(key: &str, value: &'static str) -> RequestWrapper {
+        RequestWrapper::PutRequest(PutRequest {
+            key: key.as_bytes().to_vec(),
+            value: Bytes::from_static(value.as_bytes()),
+            ..PutRequest::default()
+        })
+    }
+
+    fn event(r#type: EventType, key: &str, value: &str, prev: Option<&str>) -> Event {
+        let kv = |value: &str| KeyValue {
+            key: key.as_bytes().to_vec(),
+            value: value.as_bytes().to_vec().into(),
+            ..KeyValue::default()
+        };
+        Event {
+            r#type: r#type as i32,
+            kv: Some(kv(value)),
+            prev_kv: prev.map(kv),
+        }
+    }
+
+    #[test]
+    fn test_bound_users_can_only_access_their_namespaces() {
+        let store = store();
+        let roles = vec!["role-a".to_owned()];
+        assert!(store.permits(&roles, &KeyRange::new("/a/foo", "")));
+        assert!(store.permits(&roles, &KeyRange::new("/a/", "/a0")));
+        assert!(!store.permits(&roles, &KeyRange::new("/b/foo", "")));
+        assert!(!store.permits(&roles, &KeyRange::new("/a/", "/b/")));
+        // the users not bound to any namespace are not limited
+        assert!(store.permits(&["other".to_owned()], &KeyRange::new("/b/foo", "")));
+        assert!(store.permits(&[], &KeyRange::new("/a/foo", "")));
+    }
+
+    #[test]
+    fn test_hard_quotas_of_namespaces_reject_writes() {
+        let store = store();
+        assert!(store.check(&put("/a/1", "v")).is_ok());
+        store.apply(&[
+            event(EventType::Put, "/a/1", "v", None),
+            event(EventType::Put, "/a/2", "v", None),
+        ]);
+        assert_eq!(store.usage("a"), NamespaceUsage::new(8, 2));
+        assert!(store.check(&put("/a/3", "v")).is_err());
+        assert!(store.check(&put("/c/3", "v")).is_ok());
+        assert!(store.check(&put("/b/3", "too long")).is_err());
+
+        // an overwrite only changes the bytes, a deletion frees the key
+        store.apply(&[event(EventType::Put, "/a/1", "vv", Some("v"))]);
+        assert_eq!(store.usage("a"), NamespaceUsage::new(9, 2));
+        store.apply(&[event(EventType::Delete, "/a/1", "", Some("vv"))]);
+        assert_eq!(store.usage("a"), NamespaceUsage::new(4, 1));
+        assert!(store.check(&put("/a/3", "v")).is_ok());
+    }
+}
@@ -0,0 +1,255 @@
+use std::collections::HashMap;
+
+use clippy_utilities::NumericCast;
+use parking_lot::RwLock;
+use tracing::warn;
+use utils::config::QuotaConfig;
+
+use super::{storage_api::StorageApi, ExecuteError};
+use crate::rpc::{PutRequest, Request, RequestOp, RequestWrapper, TxnRequest};
+
+/// Table of the write quota usages, the keys are the user names
+pub(crate) const QUOTA_TABLE: &str = "quota";
+
+/// Bytes and keys written by a user
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QuotaUsage {
+    /// Bytes of the written keys and values
+    bytes: u64,
+    /// Number of the written keys
+    keys: u64,
+}
+
+impl QuotaUsage {
+    /// New `QuotaUsage`
+    pub(crate) fn new(bytes: u64, keys: u64) -> Self {
+        Self { bytes, keys }
+    }
+
+    /// Usage of a write request. Which branch of a txn succeeds is unknown before it's
+    /// executed, so the larger branch is counted.
+    pub(crate) fn of_request(request: &RequestWrapper) -> Self {
+        #[allow(clippy::wildcard_enum_match_arm)]
+        match *request {
+            RequestWrapper::PutRequest(ref req) => Self::of_put(req),
+            RequestWrapper::TxnRequest(ref req) => Self::of_txn(req),
+            _ => Self::default(),
+        }
+    }
+
+    /// Usage of a txn request
+    fn of_txn(req: &TxnRequest) -> Self {
+        let success = Self::of_ops(&req.success);
+        let failure = Self::of_ops(&req.failure);
+        Self::new(
+            success.bytes.max(failure.bytes),
+            success.keys.max(failure.keys),
+        )
+    }
+
+    /// Usage of a put request
+    fn of_put(req: &PutRequest) -> Self {
+        let bytes: u64 = req.key.len().saturating_add(req.value.len()).numeric_cast();
+        Self::new(bytes, 1)
+    }
+
+    /// Usage of the operations of a txn branch
+    fn of_ops(ops: &[RequestOp]) -> Self {
+        ops.iter()
+            .filter_map(|op| match op.request {
+                Some(Request::RequestPut(ref req)) => Some(Self::of_put(req)),
+                Some(Request::RequestTxn(ref req)) => Some(Self::of_txn(req)),
+                Some(Request::RequestRange(_) | Request::RequestDeleteRange(_)) | None => None,
+            })
+            .fold(Self::default(), Self::add)
+    }
+
+    /// Add two usages
+    fn add(self, other: Self) -> Self {
+        Self::new(
+            self.bytes.saturating_add(other.bytes),
+            self.keys.saturating_add(other.keys),
+        )
+    }
+
+    /// Encode the usage to be stored in the quota table
+    pub(crate) fn encode(self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(16);
+        buf.extend_from_slice(&self.bytes.to_be_bytes());
+        buf.extend_from_slice(&self.keys.to_be_bytes());
+        buf
+    }
+
+    /// Decode a usage read from the quota table
+    fn decode(buf: &[u8]) -> Result<Self, ExecuteError> {
+        let invalid = || ExecuteError::DbError(format!("Invalid quota usage {buf:?}"));
+        let (bytes, keys) = buf.split_at(buf.len().min(8));
+        let bytes = bytes.try_into().map_err(|_ignore| invalid())?;
+        let keys = keys.try_into().map_err(|_ignore| invalid())?;
+        Ok(Self::new(
+            u64::from_be_bytes(bytes),
+            u64::from_be_bytes(keys),
+        ))
+    }
+}
+
+/// Write quotas of the users. The usages are charged when the writes are synced, in the
+/// order of the log, so they are the same on every node. The hard quotas are checked when
+/// the writes are executed, so the concurrent writes of a user may exceed them a little.
+#[derive(Debug)]
+pub(crate) struct QuotaStore {
+    /// Quota config, it's reloadable
+    config: RwLock<QuotaConfig>,
+    /// Usages of the users
+    usages: RwLock<HashMap<String, QuotaUsage>>,
+}
+
+impl QuotaStore {
+    /// New `QuotaStore`
+    pub(crate) fn new(config: QuotaConfig) -> Self {
+        Self {
+            config: RwLock::new(config),
+            usages: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Update the quotas, the usages are kept, so a user over a lowered hard quota can't
+    /// write until its usage is under it again
+    pub(crate) fn update(&self, config: QuotaConfig) {
+        *self.config.write() = config;
+    }
+
+    /// Recover the usages flushed to the storage
+    pub(crate) fn recover<S: StorageApi>(&self, storage: &S) -> Result<(), ExecuteError> {
+        let mut usages = HashMap::new();
+        for (key, value) in storage.get_all(QUOTA_TABLE)? {
+            let user = String::from_utf8(key)
+                .map_err(|e| ExecuteError::DbError(format!("Invalid quota user, error: {e}")))?;
+            let _prev = usages.insert(user, QuotaUsage::decode(&value)?);
+        }
+        *self.usages.write() = usages;
+        Ok(())
+    }
+
+    /// Check if `user` can write `request` without exceeding the hard quotas
+    pub(crate) fn check(&self, user: &str, request: &RequestWrapper) -> Result<(), ExecuteError> {
+        let cost = QuotaUsage::of_request(request);
+        if cost == QuotaUsage::default() {
+            return Ok(());
+        }
+        let usage = self.usage(user).add(cost);
+        let config = *self.config.read();
+        if exceeds(usage.bytes, *config.hard_bytes()) || exceeds(usage.keys, *config.hard_keys()) {
+            return Err(ExecuteError::quota_exceeded());
+        }
+        Ok(())
+    }
+
+    /// Charge `user` for a synced `request`, return the new usage to be flushed, or
+    /// `None` if the request writes nothing
+    pub(crate) fn charge(&self, user: &str, request: &RequestWrapper) -> Option<QuotaUsage> {
+        let cost = QuotaUsage::of_request(request);
+        if cost == QuotaUsage::default() {
+            return None;
+        }
+        let mut usages = self.usages.write();
+        let usage = usages.entry(user.to_owned()).or_default();
+        let prev = *usage;
+        *usage = prev.add(cost);
+        let config = *self.config.read();
+        let (soft_bytes, soft_keys) = (*config.soft_bytes(), *config.soft_keys());
+        if !exceeds(prev.bytes, soft_bytes) && exceeds(usage.bytes, soft_bytes) {
+            warn!(
+                "user {user} exceeds the soft quota of {soft_bytes} bytes, {} bytes written",
+                usage.bytes
+            );
+        }
+        if !exceeds(prev.keys, soft_keys) && exceeds(usage.keys, soft_keys) {
+            warn!(
+                "user {user} exceeds the soft quota of {soft_keys} keys, {} keys written",
+                usage.keys
+            );
+        }
+        Some(*usage)
+    }
+
+    /// Get the usage of `user`
+    pub(crate) fn usage(&self, user: &str) -> QuotaUsage {
+        self.usages.read().get(user).copied().unwrap_or_default()
+    }
+
+    /// Forget the usage of a deleted user
+    pub(crate) fn remove(&self, user: &str) {
+        let _ignore = self.usages.write().remove(user);
+    }
+
+    /// Forget all usages
+    pub(crate) fn clear(&self) {
+        self.usages.write().clear();
+    }
+}
+
+/// Check if `value` exceeds `quota`, 0 means no limit
+fn exceeds(value: u64, quota: u64) -> bool {
+    quota != 0 && value > quota
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+
+    use super::*;
+
+    fn put(key: &str, value: &'static str) -> PutRequest {
+        PutRequest {
+            key: key.as_bytes().to_vec(),
+            value: Bytes::from_static(value.as_bytes()),
+            ..PutRequest::default()
+        }
+    }
+
+    #[test]
+    fn test_usage_of_request() {
+        let req = RequestWrapper::PutRequest(put("foo", "bar"));
+        assert_eq!(QuotaUsage::of_request(&req), QuotaUsage::new(6, 1));
+
+        let op = |req| RequestOp {
+            request: Some(Request::RequestPut(req)),
+        };
+        let req = RequestWrapper::TxnRequest(TxnRequest {
+            compare: vec![],
+            success: vec![op(put("a", "1")), op(put("b", "2"))],
+            failure: vec![op(put("foo", "long value"))],
+        });
+        assert_eq!(QuotaUsage::of_request(&req), QuotaUsage::new(13, 2));
+
+        let usage = QuotaUsage::new(13, 2);
+        assert_eq!(QuotaUsage::decode(&usage.encode()).unwrap(), usage);
+    }
+
+    #[test]
+    fn test_hard_quota_rejects_writes() {
+        let store = QuotaStore::new(QuotaConfig::new(0, 10, 0, 0));
+        let req = RequestWrapper::PutRequest(put("foo", "bar"));
+        assert!(store.check("alice", &req).is_ok());
+        assert_eq!(store.charge("alice", &req), Some(QuotaUsage::new(6, 1)));
+        assert!(store.check("alice", &req).is_err());
+        assert!(store.check("bob", &req).is_ok());
+
+        store.remove("alice");
+        assert!(store.check("alice", &req).is_ok());
+    }
+
+    #[test]
+    fn test_reloaded_hard_quota_applies_to_the_usages() {
+        let store = QuotaStore::new(QuotaConfig::default());
+        let req = RequestWrapper::PutRequest(put("foo", "bar"));
+        assert_eq!(store.charge("alice", &req), Some(QuotaUsage::new(6, 1)));
+        assert!(store.check("alice", &req).is_ok());
+
+        store.update(QuotaConfig::new(0, 10, 0, 0));
+        assert!(store.check("alice", &req).is_err());
+        store.update(QuotaConfig::default());
+        assert!(store.check("alice", &req).is_ok());
+    }
+}
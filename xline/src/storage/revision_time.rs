@@ -0,0 +1,209 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use curp::cmd::ProposeId;
+use parking_lot::Mutex;
+
+use super::{db::WriteOp, storage_api::StorageApi, ExecuteError};
+use crate::rpc::{Event, RequestWrapper};
+
+/// Table of the revision times, the keys are the seconds since the unix epoch, and the
+/// values are the first revisions applied in the seconds
+pub(crate) const REVISION_TIME_TABLE: &str = "revision_time";
+
+/// Seconds since the unix epoch
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Decode an entry of the revision time table
+fn decode(key: &[u8], value: &[u8]) -> Result<(u64, i64), ExecuteError> {
+    let invalid = || ExecuteError::DbError(format!("Invalid revision time entry {key:?}"));
+    let secs = key.try_into().map_err(|_ignore| invalid())?;
+    let revision = value.try_into().map_err(|_ignore| invalid())?;
+    Ok((u64::from_be_bytes(secs), i64::from_be_bytes(revision)))
+}
+
+/// Index from the wall-clock time to the revisions. The first revision applied in each
+/// second is recorded along with the writes of the revision, so there is at most an entry
+/// per second, and a revision is resolved from a time to the second.
+///
+/// The time is the clock of each member when it applies the revision, the entries are
+/// never replicated by themselves. The entries before the compacted revision are removed
+/// by the compactions, except the last one, which the compacted revision is resolved by.
+#[derive(Debug)]
+pub(crate) struct RevisionTimeIndex {
+    /// Second of the latest entry, `None` before it's read from the table
+    last: Mutex<Option<u64>>,
+}
+
+impl RevisionTimeIndex {
+    /// New `RevisionTimeIndex`
+    pub(crate) fn new() -> Self {
+        Self {
+            last: Mutex::new(None),
+        }
+    }
+
+    /// Get the second of the latest entry in the table, 0 if it's empty
+    fn read_last<S: StorageApi>(storage: &S) -> Result<u64, ExecuteError> {
+        let mut last = 0;
+        storage.scan(REVISION_TIME_TABLE, |key, value| {
+            last = last.max(decode(key, value)?.0);
+            Ok(())
+        })?;
+        Ok(last)
+    }
+
+    /// Record the time of a synced revision if it's the first one applied in this second,
+    /// and remove the entries compacted by a compaction
+    pub(crate) fn sync<S: StorageApi>(
+        &self,
+        storage: &S,
+        id: &ProposeId,
+        request: &RequestWrapper,
+        revision: i64,
+        events: &[Event],
+    ) -> Result<(), ExecuteError> {
+        if let RequestWrapper::CompactionRequest(ref req) = *request {
+            return Self::compact(storage, id, req.revision);
+        }
+        if events.is_empty() {
+            return Ok(());
+        }
+        let now = now_secs();
+        let mut last = self.last.lock();
+        let last_secs = match *last {
+            Some(secs) => secs,
+            None => Self::read_last(storage)?,
+        };
+        // the clock may go backwards, the entries are kept in the order of the revisions
+        if now <= last_secs {
+            *last = Some(last_secs);
+            return Ok(());
+        }
+        storage.buffer_op(id, WriteOp::PutRevisionTime(now, revision));
+        *last = Some(now);
+        Ok(())
+    }
+
+    /// Remove the entries before the last one whose revision is not greater than
+    /// `compact_revision`
+    fn compact<S: StorageApi>(
+        storage: &S,
+        id: &ProposeId,
+        compact_revision: i64,
+    ) -> Result<(), ExecuteError> {
+        let mut compacted = Vec::new();
+        storage.scan(REVISION_TIME_TABLE, |key, value| {
+            let (secs, revision) = decode(key, value)?;
+            if revision <= compact_revision {
+                compacted.push(secs);
+            }
+            Ok(())
+        })?;
+        let _last = compacted.pop();
+        for secs in compacted {
+            storage.buffer_op(id, WriteOp::DeleteRevisionTime(secs));
+        }
+        Ok(())
+    }
+
+    /// Get the latest revision applied at or before `timestamp`, in seconds since the unix
+    /// epoch, `current` is the revision applied now. Return `None` if the time is before
+    /// the first entry, the revision is unknown then.
+    pub(crate) fn revision_at<S: StorageApi>(
+        storage: &S,
+        timestamp: u64,
+        current: i64,
+    ) -> Result<Option<i64>, ExecuteError> {
+        let mut recorded = false;
+        let mut next = None;
+        storage.scan(REVISION_TIME_TABLE, |key, value| {
+            let (secs, revision) = decode(key, value)?;
+            if secs <= timestamp {
+                recorded = true;
+            } else if next.is_none() {
+                next = Some(revision);
+            }
+            Ok(())
+        })?;
+        if !recorded {
+            return Ok(None);
+        }
+        // the revisions before the first one applied after the second are applied in it
+        Ok(Some(
+            next.map_or(current, |revision| revision.saturating_sub(1)),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use utils::config::StorageConfig;
+
+    use super::*;
+    use crate::{
+        rpc::{CompactionRequest, PutRequest},
+        storage::db::DBProxy,
+    };
+
+    fn put(storage: &DBProxy, secs: u64, revision: i64) {
+        let id = ProposeId::new(format!("{secs}"));
+        storage.buffer_op(&id, WriteOp::PutRevisionTime(secs, revision));
+        storage.flush(&id).unwrap();
+    }
+
+    #[test]
+    fn test_revisions_are_resolved_by_the_time() {
+        let db = DBProxy::open(&StorageConfig::Memory).unwrap();
+        assert_eq!(
+            RevisionTimeIndex::revision_at(db.as_ref(), 100, 1).unwrap(),
+            None
+        );
+        put(&db, 100, 2);
+        put(&db, 102, 5);
+        put(&db, 105, 9);
+        let revision_at = |timestamp| RevisionTimeIndex::revision_at(db.as_ref(), timestamp, 12);
+        assert_eq!(revision_at(99).unwrap(), None);
+        assert_eq!(revision_at(100).unwrap(), Some(4));
+        assert_eq!(revision_at(101).unwrap(), Some(4));
+        assert_eq!(revision_at(104).unwrap(), Some(8));
+        assert_eq!(revision_at(105).unwrap(), Some(12));
+        assert_eq!(revision_at(200).unwrap(), Some(12));
+
+        // the last entry before the compacted revision is kept
+        let id = ProposeId::new("compact".to_owned());
+        let compact = RequestWrapper::CompactionRequest(CompactionRequest {
+            revision: 6,
+            physical: false,
+        });
+        let index = RevisionTimeIndex::new();
+        index.sync(db.as_ref(), &id, &compact, 13, &[]).unwrap();
+        db.flush(&id).unwrap();
+        assert_eq!(revision_at(101).unwrap(), None);
+        assert_eq!(revision_at(102).unwrap(), Some(8));
+
+        // a revision is recorded if it's the first one in the second
+        let id = ProposeId::new("put".to_owned());
+        let put = RequestWrapper::PutRequest(PutRequest::default());
+        let before = now_secs();
+        index
+            .sync(db.as_ref(), &id, &put, 13, &[Event::default()])
+            .unwrap();
+        index
+            .sync(db.as_ref(), &id, &put, 14, &[Event::default()])
+            .unwrap();
+        db.flush(&id).unwrap();
+        assert_eq!(
+            RevisionTimeIndex::revision_at(db.as_ref(), now_secs(), 14).unwrap(),
+            Some(14)
+        );
+        assert_eq!(
+            RevisionTimeIndex::revision_at(db.as_ref(), before - 1, 14).unwrap(),
+            Some(12)
+        );
+    }
+}
@@ -0,0 +1,510 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use clippy_utilities::NumericCast;
+use engine::{
+    engine_api::StorageEngine, error::EngineError, rocksdb_engine::RocksEngine, WriteOperation,
+};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use super::{
+    db::{ENGINE_TABLES, XLINE_TABLES},
+    member_identity::MEMBER_IDENTITY_KEY,
+};
+use crate::server::command::{APPLIED_INDEX_KEY, META_TABLE};
+
+/// Magic number at the beginning of a snapshot file
+const MAGIC: &[u8; 8] = b"XLINESNP";
+/// Version of the snapshot file format
+const VERSION: u32 = 2;
+/// Records are grouped into chunks of about this size, each chunk has its own checksum
+const CHUNK_SIZE: usize = 64 * 1024;
+/// Max number of entries written to the engine in one batch when restoring
+const RESTORE_BATCH_SIZE: usize = 1024;
+
+/// Error met when saving or restoring a snapshot
+#[allow(clippy::module_name_repetitions)]
+#[non_exhaustive]
+#[derive(Error, Debug)]
+pub enum SnapshotError {
+    /// I/O error when reading or writing the snapshot file
+    #[error("snapshot I/O error: {0}")]
+    IoError(#[from] io::Error),
+    /// Error of the storage engine
+    #[error("storage engine error: {0}")]
+    EngineError(#[from] EngineError),
+    /// The snapshot file is not a valid snapshot
+    #[error("invalid snapshot: {0}")]
+    InvalidSnapshot(String),
+    /// The directory to restore into is not empty
+    #[error("directory {0:?} is not empty")]
+    DirNotEmpty(PathBuf),
+}
+
+/// Save all tables of the engine to a snapshot, return the number of saved entries.
+/// The snapshot is consistent even if the engine is being written.
+///
+/// The snapshot consists of a header (magic number and format version), then the records
+/// grouped into chunks and a trailer. A record is the table name, the key and the value,
+/// each of them is prefixed by its length. A chunk is prefixed by its length and followed
+/// by its CRC32, an empty chunk ends the chunks. The trailer is the number of records and
+/// the SHA-256 of everything before it.
+///
+/// # Errors
+///
+/// Return `SnapshotError` if failed to read the engine or write the snapshot
+#[inline]
+pub fn save<E, W>(engine: &E, writer: W) -> Result<u64, SnapshotError>
+where
+    E: StorageEngine,
+    W: Write,
+{
+    let mut writer = DigestWriter::new(writer);
+    writer.write_all(MAGIC)?;
+    writer.write_all(&VERSION.to_be_bytes())?;
+    let mut count: u64 = 0;
+    let mut chunk = Vec::with_capacity(CHUNK_SIZE);
+    engine.visit_snapshot(&XLINE_TABLES, |table, key, value| {
+        write_field(&mut chunk, table.as_bytes())?;
+        write_field(&mut chunk, key)?;
+        write_field(&mut chunk, value)?;
+        count = count.saturating_add(1);
+        if chunk.len() >= CHUNK_SIZE {
+            write_chunk(&mut writer, &chunk)?;
+            chunk.clear();
+        }
+        Ok(())
+    })?;
+    if !chunk.is_empty() {
+        write_chunk(&mut writer, &chunk)?;
+    }
+    write_chunk(&mut writer, &[])?;
+    writer.write_all(&count.to_be_bytes())?;
+    let (mut writer, digest) = writer.finish();
+    writer.write_all(&digest)?;
+    writer.flush()?;
+    Ok(count)
+}
+
+/// Restore a snapshot into the engine, return the number of restored entries.
+///
+/// The applied index is not restored, because the restored data will be replicated by
+/// a new consensus log which starts from the beginning. Neither is the identity of the
+/// member which took the snapshot, the restoring member records its own at its start.
+/// The change log index is restored, so that the shipped change log can be applied after
+/// the snapshot.
+///
+/// A chunk is written to the engine only after its checksum is verified, but the snapshot
+/// is read only once, so the chunks before a corrupted one may have been written. Use
+/// `verify` first if the engine must not be touched by a corrupted snapshot.
+///
+/// # Errors
+///
+/// Return `SnapshotError` if the snapshot is invalid or failed to write the engine
+#[inline]
+pub fn restore<E, R>(engine: &E, reader: R) -> Result<u64, SnapshotError>
+where
+    E: StorageEngine,
+    R: Read,
+{
+    let mut ops = Vec::with_capacity(RESTORE_BATCH_SIZE);
+    let count = read_snapshot(reader, |table, key, value| {
+        if table == META_TABLE
+            && (key == APPLIED_INDEX_KEY.as_bytes() || key == MEMBER_IDENTITY_KEY.as_bytes())
+        {
+            return Ok(());
+        }
+        ops.push(WriteOperation::new_put(table, key, value));
+        if ops.len() >= RESTORE_BATCH_SIZE {
+            engine.write_batch(std::mem::take(&mut ops), false)?;
+        }
+        Ok(())
+    })?;
+    engine.write_batch(ops, true)?;
+    Ok(count)
+}
+
+/// Verify the checksums of a snapshot without restoring it, return the number of entries
+///
+/// # Errors
+///
+/// Return `SnapshotError` if the snapshot is truncated, corrupted or failed to read it
+#[inline]
+pub fn verify<R: Read>(reader: R) -> Result<u64, SnapshotError> {
+    read_snapshot(reader, |_table, _key, _value| Ok(()))
+}
+
+/// Read a snapshot and pass each record of the verified chunks to `on_record`, return the
+/// number of records after the trailer is verified
+fn read_snapshot<R, F>(reader: R, mut on_record: F) -> Result<u64, SnapshotError>
+where
+    R: Read,
+    F: FnMut(&'static str, Vec<u8>, Vec<u8>) -> Result<(), SnapshotError>,
+{
+    let mut reader = DigestReader::new(reader);
+    let mut magic = [0; MAGIC.len()];
+    read_exact(&mut reader, &mut magic)?;
+    if &magic != MAGIC {
+        return Err(SnapshotError::InvalidSnapshot(
+            "not an xline snapshot".to_owned(),
+        ));
+    }
+    let version = u32::from_be_bytes(read_array(&mut reader)?);
+    if version != VERSION {
+        return Err(SnapshotError::InvalidSnapshot(format!(
+            "unsupported version {version}"
+        )));
+    }
+
+    let mut count: u64 = 0;
+    let mut chunk_index: u64 = 0;
+    loop {
+        let chunk = read_field(&mut reader)?;
+        let checksum = u32::from_be_bytes(read_array(&mut reader)?);
+        if crc32fast::hash(&chunk) != checksum {
+            return Err(SnapshotError::InvalidSnapshot(format!(
+                "checksum mismatch in chunk {chunk_index}"
+            )));
+        }
+        if chunk.is_empty() {
+            break;
+        }
+        let mut records = chunk.as_slice();
+        while !records.is_empty() {
+            let table_name = read_field(&mut records)?;
+            let table = XLINE_TABLES
+                .into_iter()
+                .find(|t| t.as_bytes() == table_name)
+                .ok_or_else(|| {
+                    SnapshotError::InvalidSnapshot(format!(
+                        "unknown table {:?}",
+                        String::from_utf8_lossy(&table_name)
+                    ))
+                })?;
+            let key = read_field(&mut records)?;
+            let value = read_field(&mut records)?;
+            on_record(table, key, value)?;
+            count = count.saturating_add(1);
+        }
+        chunk_index = chunk_index.saturating_add(1);
+    }
+    let expected = u64::from_be_bytes(read_array(&mut reader)?);
+    if expected != count {
+        return Err(SnapshotError::InvalidSnapshot(format!(
+            "expect {expected} entries, found {count}"
+        )));
+    }
+    let (mut reader, digest) = reader.finish();
+    let expected_digest: [u8; 32] = read_array(&mut reader)?;
+    if digest != expected_digest {
+        return Err(SnapshotError::InvalidSnapshot(
+            "checksum mismatch in the snapshot".to_owned(),
+        ));
+    }
+    Ok(count)
+}
+
+/// Save the data in `data_dir` to a snapshot file at `path`, return the number of saved
+/// entries. The server using `data_dir` must be stopped, the database can't be opened
+/// by two processes at the same time.
+///
+/// # Errors
+///
+/// Return `SnapshotError` if failed to open the database or write the snapshot file
+#[inline]
+pub fn save_to_file(data_dir: &Path, path: &Path) -> Result<u64, SnapshotError> {
+    if !data_dir.exists() {
+        return Err(SnapshotError::IoError(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("data dir {} not found", data_dir.display()),
+        )));
+    }
+    let engine = RocksEngine::new(data_dir, &ENGINE_TABLES)?;
+    // write to a temporary file first, so that a partial snapshot never looks complete
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".part");
+    let tmp_path = PathBuf::from(tmp_path);
+    let file = File::create(&tmp_path)?;
+    let count = save(&engine, BufWriter::new(&file))?;
+    file.sync_all()?;
+    fs::rename(&tmp_path, path)?;
+    Ok(count)
+}
+
+/// Restore the snapshot file at `path` into a new data dir, return the number of restored
+/// entries. Both `data_dir` and `curp_data_dir` must be empty, the restored member starts
+/// with an empty consensus log.
+///
+/// # Errors
+///
+/// Return `SnapshotError` if the directories are not empty, the snapshot is invalid or failed
+/// to write the database
+#[inline]
+pub fn restore_from_file(
+    path: &Path,
+    data_dir: &Path,
+    curp_data_dir: &Path,
+) -> Result<u64, SnapshotError> {
+    check_empty_dir(data_dir)?;
+    check_empty_dir(curp_data_dir)?;
+    // verify the whole file first, so that a corrupted snapshot leaves no data behind
+    let _count = verify_file(path)?;
+    let reader = BufReader::new(File::open(path)?);
+    let engine = RocksEngine::new(data_dir, &ENGINE_TABLES)?;
+    restore(&engine, reader)
+}
+
+/// Verify the checksums of the snapshot file at `path`, return the number of entries
+///
+/// # Errors
+///
+/// Return `SnapshotError` if the snapshot is truncated, corrupted or failed to read it
+#[inline]
+pub fn verify_file(path: &Path) -> Result<u64, SnapshotError> {
+    verify(BufReader::new(File::open(path)?))
+}
+
+/// Check if the directory does not exist or is empty
+pub(super) fn check_empty_dir(dir: &Path) -> Result<(), SnapshotError> {
+    match fs::read_dir(dir) {
+        Ok(mut entries) => {
+            if entries.next().is_some() {
+                return Err(SnapshotError::DirNotEmpty(dir.to_path_buf()));
+            }
+            Ok(())
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Write a chunk prefixed by its length and followed by its checksum
+fn write_chunk<W: Write>(writer: &mut W, chunk: &[u8]) -> io::Result<()> {
+    write_field(writer, chunk)?;
+    writer.write_all(&crc32fast::hash(chunk).to_be_bytes())
+}
+
+/// Write a field prefixed by its length
+pub(super) fn write_field<W: Write>(writer: &mut W, field: &[u8]) -> io::Result<()> {
+    let len = u32::try_from(field.len()).map_err(|_e| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("field of {} bytes is too large", field.len()),
+        )
+    })?;
+    writer.write_all(&len.to_be_bytes())?;
+    writer.write_all(field)
+}
+
+/// Read a field prefixed by its length
+fn read_field<R: Read>(reader: &mut R) -> Result<Vec<u8>, SnapshotError> {
+    let len = u32::from_be_bytes(read_array(reader)?);
+    let mut field = Vec::new();
+    let read = reader.take(u64::from(len)).read_to_end(&mut field)?;
+    if read.numeric_cast::<u64>() != u64::from(len) {
+        return Err(truncated());
+    }
+    Ok(field)
+}
+
+/// Read a field prefixed by its length, return `None` at the end of file
+pub(super) fn read_optional_field<R: Read>(
+    reader: &mut R,
+) -> Result<Option<Vec<u8>>, SnapshotError> {
+    let mut first = [0; 1];
+    if reader.read(&mut first)? == 0 {
+        return Ok(None);
+    }
+    read_field(&mut first.as_slice().chain(reader)).map(Some)
+}
+
+/// Read a fixed size array
+fn read_array<R: Read, const N: usize>(reader: &mut R) -> Result<[u8; N], SnapshotError> {
+    let mut buf = [0; N];
+    read_exact(reader, &mut buf)?;
+    Ok(buf)
+}
+
+/// Fill the buffer, an unexpected EOF means the snapshot is truncated
+fn read_exact<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<(), SnapshotError> {
+    reader.read_exact(buf).map_err(|e| {
+        if e.kind() == io::ErrorKind::UnexpectedEof {
+            truncated()
+        } else {
+            e.into()
+        }
+    })
+}
+
+/// Writer which computes the SHA-256 of the written bytes
+struct DigestWriter<W> {
+    /// Inner writer
+    inner: W,
+    /// Digest of the written bytes
+    hasher: Sha256,
+}
+
+impl<W: Write> DigestWriter<W> {
+    /// New `DigestWriter`
+    fn new(inner: W) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+        }
+    }
+
+    /// Return the inner writer and the digest
+    fn finish(self) -> (W, [u8; 32]) {
+        (self.inner, self.hasher.finalize().into())
+    }
+}
+
+impl<W: Write> Write for DigestWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(buf.get(..written).unwrap_or_default());
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Reader which computes the SHA-256 of the read bytes
+struct DigestReader<R> {
+    /// Inner reader
+    inner: R,
+    /// Digest of the read bytes
+    hasher: Sha256,
+}
+
+impl<R: Read> DigestReader<R> {
+    /// New `DigestReader`
+    fn new(inner: R) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+        }
+    }
+
+    /// Return the inner reader and the digest
+    fn finish(self) -> (R, [u8; 32]) {
+        (self.inner, self.hasher.finalize().into())
+    }
+}
+
+impl<R: Read> Read for DigestReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.update(buf.get(..read).unwrap_or_default());
+        Ok(read)
+    }
+}
+
+/// The error of a truncated snapshot
+fn truncated() -> SnapshotError {
+    SnapshotError::InvalidSnapshot("unexpected end of file".to_owned())
+}
+
+#[cfg(test)]
+mod test {
+    use engine::memory_engine::MemoryEngine;
+
+    use super::*;
+    use crate::storage::kv_store::KV_TABLE;
+
+    fn init_engine() -> MemoryEngine {
+        let engine = MemoryEngine::new(&XLINE_TABLES).unwrap();
+        engine
+            .write_batch(
+                vec![
+                    WriteOperation::new_put(KV_TABLE, "key1", "value1"),
+                    WriteOperation::new_put(KV_TABLE, "key2", "value2"),
+                    WriteOperation::new_put(META_TABLE, APPLIED_INDEX_KEY, 5_u64.to_le_bytes()),
+                ],
+                true,
+            )
+            .unwrap();
+        engine
+    }
+
+    #[test]
+    fn test_save_and_restore() -> Result<(), SnapshotError> {
+        let mut snapshot = Vec::new();
+        assert_eq!(save(&init_engine(), &mut snapshot)?, 3);
+
+        let engine = MemoryEngine::new(&XLINE_TABLES)?;
+        assert_eq!(restore(&engine, snapshot.as_slice())?, 3);
+        let mut kvs = engine.get_all(KV_TABLE)?;
+        kvs.sort();
+        assert_eq!(
+            kvs,
+            vec![
+                (b"key1".to_vec(), b"value1".to_vec()),
+                (b"key2".to_vec(), b"value2".to_vec())
+            ]
+        );
+        assert_eq!(engine.get(META_TABLE, APPLIED_INDEX_KEY)?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_restore_invalid_snapshot_should_fail() -> Result<(), SnapshotError> {
+        let mut snapshot = Vec::new();
+        let _count = save(&init_engine(), &mut snapshot)?;
+
+        let engine = MemoryEngine::new(&XLINE_TABLES)?;
+        let truncated = &snapshot[..snapshot.len() - 10];
+        assert!(matches!(
+            restore(&engine, truncated),
+            Err(SnapshotError::InvalidSnapshot(_))
+        ));
+        assert!(matches!(
+            restore(&engine, &b"not a snapshot"[..]),
+            Err(SnapshotError::InvalidSnapshot(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_corrupted_snapshot_should_fail() -> Result<(), SnapshotError> {
+        let engine = init_engine();
+        let ops = (0..2000_u32)
+            .map(|i| WriteOperation::new_put(KV_TABLE, i.to_be_bytes(), vec![0; 64]))
+            .collect();
+        engine.write_batch(ops, false)?;
+        let mut snapshot = Vec::new();
+        assert_eq!(save(&engine, &mut snapshot)?, 2003);
+        assert_eq!(verify(snapshot.as_slice())?, 2003);
+
+        // corrupt a value in the second chunk
+        let mut corrupted = snapshot.clone();
+        corrupted[CHUNK_SIZE + 100] ^= 0xff;
+        let err = verify(corrupted.as_slice()).unwrap_err();
+        assert!(err.to_string().contains("chunk 1"), "{err}");
+
+        // corrupt the count and the file checksum in the trailer
+        let mut corrupted = snapshot.clone();
+        let count_pos = corrupted.len() - 33;
+        corrupted[count_pos] ^= 0x01;
+        assert!(matches!(
+            verify(corrupted.as_slice()),
+            Err(SnapshotError::InvalidSnapshot(_))
+        ));
+        let mut corrupted = snapshot;
+        let last = corrupted.len() - 1;
+        corrupted[last] ^= 0x01;
+        let err = verify(corrupted.as_slice()).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("checksum mismatch in the snapshot"),
+            "{err}"
+        );
+        Ok(())
+    }
+}
@@ -1,6 +1,11 @@
+use std::path::Path;
+
 use curp::cmd::ProposeId;
+use engine::TableSize;
+use tokio::sync::watch;
 
 use super::{db::WriteOp, ExecuteError};
+use crate::rpc::ChangeLogEntry;
 
 /// The Stable Storage Api
 pub trait StorageApi: Send + Sync + 'static + std::fmt::Debug {
@@ -34,6 +39,31 @@ pub trait StorageApi: Send + Sync + 'static + std::fmt::Debug {
     #[allow(clippy::type_complexity)] // it's clear that (Vec<u8>, Vec<u8>) is a key-value pair
     fn get_all(&self, table: &'static str) -> Result<Vec<(Vec<u8>, Vec<u8>)>, ExecuteError>;
 
+    /// Scan all keys and values of the given table in order without loading the whole
+    /// table into memory, the scan stops at the first error returned by `visitor`
+    ///
+    /// # Errors
+    ///
+    /// if error occurs in storage or in `visitor`, return `Err(error)`
+    fn scan<F>(&self, table: &'static str, visitor: F) -> Result<(), ExecuteError>
+    where
+        F: FnMut(&[u8], &[u8]) -> Result<(), ExecuteError>;
+
+    /// Scan the keys not less than `from` and their values of the given table in order,
+    /// like `scan`
+    ///
+    /// # Errors
+    ///
+    /// if error occurs in storage or in `visitor`, return `Err(error)`
+    fn scan_from<F>(
+        &self,
+        table: &'static str,
+        from: &[u8],
+        visitor: F,
+    ) -> Result<(), ExecuteError>
+    where
+        F: FnMut(&[u8], &[u8]) -> Result<(), ExecuteError>;
+
     /// Reset the storage
     ///
     /// # Errors
@@ -46,4 +76,59 @@ pub trait StorageApi: Send + Sync + 'static + std::fmt::Debug {
 
     /// Flush the buffer to storage
     fn flush(&self, id: &ProposeId) -> Result<(), ExecuteError>;
+
+    /// Make sure all flushed operations are persisted to disk
+    ///
+    /// # Errors
+    ///
+    /// if error occurs in storage, return `Err(error)`
+    fn sync(&self) -> Result<(), ExecuteError>;
+
+    /// Save a consistent snapshot of the storage to the writer, return the number of
+    /// saved entries
+    ///
+    /// # Errors
+    ///
+    /// if error occurs in storage or the writer, return `Err(error)`
+    fn snapshot<W>(&self, writer: W) -> Result<u64, ExecuteError>
+    where
+        W: std::io::Write;
+
+    /// Replace all data of the storage with the snapshot file at `path` saved by `snapshot`,
+    /// `applied_index` is the index of the last log entry included in the snapshot
+    ///
+    /// # Errors
+    ///
+    /// if the snapshot is invalid or error occurs in storage, return `Err(error)`
+    fn install_snapshot(&self, path: &Path, applied_index: u64) -> Result<(), ExecuteError>;
+
+    /// Read at most `limit` contiguous entries of the change log from `start`, the result
+    /// is empty if the entry `start` is trimmed or not appended yet
+    ///
+    /// # Errors
+    ///
+    /// if the change log is disabled or error occurs in storage, return `Err(error)`
+    fn read_change_log(
+        &self,
+        start: u64,
+        limit: usize,
+    ) -> Result<Vec<ChangeLogEntry>, ExecuteError>;
+
+    /// Delete the entries of the change log before `end`
+    ///
+    /// # Errors
+    ///
+    /// if the change log is disabled or error occurs in storage, return `Err(error)`
+    fn trim_change_log(&self, end: u64) -> Result<(), ExecuteError>;
+
+    /// Subscribe to the index of the last change log entry, return `None` if the change log
+    /// is disabled
+    fn subscribe_change_log(&self) -> Option<watch::Receiver<u64>>;
+
+    /// Get the sizes of the tables in the storage, including the change log
+    ///
+    /// # Errors
+    ///
+    /// if error occurs in storage, return `Err(error)`
+    fn table_sizes(&self) -> Result<Vec<(&'static str, TableSize)>, ExecuteError>;
 }
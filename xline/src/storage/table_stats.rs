@@ -0,0 +1,111 @@
+use clippy_utilities::NumericCast;
+use prost::Message;
+
+use super::{
+    auth_store::{AUTH_TABLE, ROLE_TABLE, USER_TABLE},
+    kv_store::KV_TABLE,
+    lease_store::LEASE_TABLE,
+    storage_api::StorageApi,
+    ExecuteError, Revision,
+};
+use crate::rpc::{KeyValue, TableStats};
+
+/// Tables reported by the statistics, the auth table also covers the users and the roles
+const STATS_TABLES: [(&str, &[&str]); 3] = [
+    ("kv", &[KV_TABLE]),
+    ("lease", &[LEASE_TABLE]),
+    ("auth", &[AUTH_TABLE, USER_TABLE, ROLE_TABLE]),
+];
+
+/// Count the entries of the tables by scanning them. The tombstones are the deletions
+/// retained in the kv table, and the oldest revision is the first revision retained in it,
+/// they are 0 for the other tables, which keep no history.
+pub(crate) fn table_stats<S: StorageApi>(storage: &S) -> Result<Vec<TableStats>, ExecuteError> {
+    let mut stats = Vec::with_capacity(STATS_TABLES.len());
+    for &(name, tables) in &STATS_TABLES {
+        let mut table_stats = TableStats {
+            name: name.to_owned(),
+            ..TableStats::default()
+        };
+        for table in tables {
+            storage.scan(table, |key, value| {
+                table_stats.keys = table_stats.keys.saturating_add(1);
+                table_stats.bytes = table_stats
+                    .bytes
+                    .saturating_add(key.len().saturating_add(value.len()).numeric_cast());
+                if *table == KV_TABLE {
+                    count_revision(&mut table_stats, key, value)?;
+                }
+                Ok(())
+            })?;
+        }
+        stats.push(table_stats);
+    }
+    Ok(stats)
+}
+
+/// Count a revision of the kv table in the statistics
+fn count_revision(stats: &mut TableStats, key: &[u8], value: &[u8]) -> Result<(), ExecuteError> {
+    if key.len() != 16 {
+        return Err(ExecuteError::DbError(format!(
+            "Invalid revision {key:?} in kv table"
+        )));
+    }
+    let revision = Revision::decode(key).revision();
+    if stats.oldest_revision == 0 || revision < stats.oldest_revision {
+        stats.oldest_revision = revision;
+    }
+    let kv = KeyValue::decode(value)
+        .map_err(|e| ExecuteError::DbError(format!("Invalid key value of {revision}, {e}")))?;
+    // a deletion is kept as a key value without a create revision
+    if kv.create_revision == 0 {
+        stats.tombstones = stats.tombstones.saturating_add(1);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use curp::cmd::ProposeId;
+    use utils::config::StorageConfig;
+
+    use super::*;
+    use crate::storage::db::{DBProxy, WriteOp};
+
+    fn put_revision(db: &DBProxy, revision: i64, create_revision: i64) {
+        let id = ProposeId::new(format!("{revision}"));
+        let kv = KeyValue {
+            key: b"foo".to_vec(),
+            create_revision,
+            mod_revision: revision,
+            ..KeyValue::default()
+        };
+        db.buffer_op(
+            &id,
+            WriteOp::PutKeyValue(Revision::new(revision, 0), kv.encode_to_vec()),
+        );
+        db.flush(&id).unwrap();
+    }
+
+    #[test]
+    fn test_table_stats_count_the_revisions_and_tombstones() {
+        let db = DBProxy::open(&StorageConfig::Memory).unwrap();
+        put_revision(&db, 3, 3);
+        put_revision(&db, 4, 3);
+        put_revision(&db, 5, 0);
+        let stats = table_stats(db.as_ref()).unwrap();
+        let names: Vec<_> = stats.iter().map(|stats| stats.name.as_str()).collect();
+        assert_eq!(names, ["kv", "lease", "auth"]);
+        assert_eq!(stats[0].keys, 3);
+        assert_eq!(stats[0].tombstones, 1);
+        assert_eq!(stats[0].oldest_revision, 3);
+        assert!(stats[0].bytes > 3 * 16);
+        assert_eq!(
+            stats[1],
+            TableStats {
+                name: "lease".to_owned(),
+                ..TableStats::default()
+            }
+        );
+    }
+}
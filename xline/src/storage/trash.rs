@@ -0,0 +1,279 @@
+use std::{
+    collections::BTreeMap,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use clippy_utilities::NumericCast;
+use curp::cmd::ProposeId;
+use prost::Message;
+use utils::config::TrashConfig;
+
+use super::{db::WriteOp, storage_api::StorageApi, ExecuteError};
+use crate::{
+    rpc::{Event, EventType, KeyValue, RequestWrapper},
+    server::command::KeyRange,
+};
+
+/// Table of the deleted key-values, the keys are the deleted keys followed by the
+/// revisions of the deletions
+pub(crate) const TRASH_TABLE: &str = "trash";
+
+/// Propose id of the purges of the trash
+const PURGE_ID: &str = "trash_purge";
+
+/// A deleted key-value kept in the trash
+#[derive(Debug, Clone)]
+pub(crate) struct TrashEntry {
+    /// Revision of the deletion
+    revision: i64,
+    /// When the key-value is deleted, in milliseconds since the unix epoch, by the clock of
+    /// the member which synced the deletion
+    deleted_at: u64,
+    /// The deleted key-value
+    kv: KeyValue,
+}
+
+impl TrashEntry {
+    /// Get the revision of the deletion
+    #[cfg(test)]
+    fn revision(&self) -> i64 {
+        self.revision
+    }
+
+    /// Get the deleted key-value
+    pub(crate) fn kv(&self) -> &KeyValue {
+        &self.kv
+    }
+
+    /// Encode the key of the entry in the trash table
+    fn encode_key(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(self.kv.key.len().saturating_add(8));
+        buf.extend_from_slice(&self.kv.key);
+        buf.extend_from_slice(&self.revision.to_be_bytes());
+        buf
+    }
+
+    /// Encode the value of the entry in the trash table
+    fn encode_value(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(self.kv.encoded_len().saturating_add(8));
+        buf.extend_from_slice(&self.deleted_at.to_be_bytes());
+        self.kv.encode(&mut buf).unwrap_or_else(|e| {
+            unreachable!("encoding to a vec with enough capacity never fails, {e}")
+        });
+        buf
+    }
+
+    /// Decode an entry of the trash table
+    fn decode(key: &[u8], value: &[u8]) -> Result<Self, ExecuteError> {
+        let invalid = || ExecuteError::DbError(format!("Invalid trash entry {key:?}"));
+        let (_, revision) = key.split_at(key.len().saturating_sub(8));
+        let (deleted_at, kv) = value.split_at(value.len().min(8));
+        let revision = revision.try_into().map_err(|_ignore| invalid())?;
+        let deleted_at = deleted_at.try_into().map_err(|_ignore| invalid())?;
+        Ok(Self {
+            revision: i64::from_be_bytes(revision),
+            deleted_at: u64::from_be_bytes(deleted_at),
+            kv: KeyValue::decode(kv).map_err(|_ignore| invalid())?,
+        })
+    }
+}
+
+/// Milliseconds since the unix epoch
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis()
+        .numeric_cast()
+}
+
+/// Trash of the deleted keys. With the trash enabled, the key-values deleted by a
+/// `DeleteRange` or a txn are put into the trash table along with the deletion, and kept
+/// there for the retention period. The keys deleted by the expired leases are not kept.
+///
+/// The entries are written in the order of the log like the deletions, but they are
+/// expired by the clock of each member, and they are never replicated by themselves: an
+/// `Undelete` restores the keys by proposing a txn putting the values read from the trash.
+#[derive(Debug)]
+pub(crate) struct TrashStore {
+    /// Trash config
+    config: TrashConfig,
+}
+
+impl TrashStore {
+    /// New `TrashStore`
+    pub(crate) fn new(config: TrashConfig) -> Self {
+        Self { config }
+    }
+
+    /// Check if the deleted keys are kept in the trash
+    pub(crate) fn is_enabled(&self) -> bool {
+        *self.config.enable()
+    }
+
+    /// Check if an entry deleted at `deleted_at` is expired at `now`
+    fn is_expired(&self, deleted_at: u64, now: u64) -> bool {
+        let retention: u64 = self.config.retention().as_millis().numeric_cast();
+        deleted_at.saturating_add(retention) <= now
+    }
+
+    /// Put the key-values deleted by a synced request into the trash
+    pub(crate) fn sync<S: StorageApi>(
+        &self,
+        storage: &S,
+        id: &ProposeId,
+        request: &RequestWrapper,
+        events: &[Event],
+    ) {
+        if !self.is_enabled()
+            || !matches!(
+                *request,
+                RequestWrapper::DeleteRangeRequest(_) | RequestWrapper::TxnRequest(_)
+            )
+        {
+            return;
+        }
+        let deleted_at = now_millis();
+        #[allow(clippy::as_conversions)] // This cast is always valid
+        let deletions = events
+            .iter()
+            .filter(|event| event.r#type == EventType::Delete as i32);
+        for event in deletions {
+            let (Some(kv), Some(prev)) = (event.kv.as_ref(), event.prev_kv.as_ref()) else {
+                continue;
+            };
+            let entry = TrashEntry {
+                revision: kv.mod_revision,
+                deleted_at,
+                kv: prev.clone(),
+            };
+            storage.buffer_op(
+                id,
+                WriteOp::PutTrash(entry.encode_key(), entry.encode_value()),
+            );
+        }
+    }
+
+    /// Get the latest deletion of each key in `range` which is still in the trash
+    pub(crate) fn list<S: StorageApi>(
+        &self,
+        storage: &S,
+        range: &KeyRange,
+    ) -> Result<Vec<TrashEntry>, ExecuteError> {
+        let now = now_millis();
+        let mut latest: BTreeMap<Vec<u8>, TrashEntry> = BTreeMap::new();
+        storage.scan_from(TRASH_TABLE, &range.start, |key, value| {
+            let entry = TrashEntry::decode(key, value)?;
+            if !range.contains_key(&entry.kv.key) || self.is_expired(entry.deleted_at, now) {
+                return Ok(());
+            }
+            match latest.get(&entry.kv.key) {
+                Some(prev) if prev.revision >= entry.revision => {}
+                Some(_) | None => {
+                    let _prev = latest.insert(entry.kv.key.clone(), entry);
+                }
+            }
+            Ok(())
+        })?;
+        Ok(latest.into_values().collect())
+    }
+
+    /// Remove the expired entries from the trash, return the number of removed entries
+    pub(crate) fn purge<S: StorageApi>(&self, storage: &S) -> Result<usize, ExecuteError> {
+        let now = now_millis();
+        let mut expired = Vec::new();
+        storage.scan(TRASH_TABLE, |key, value| {
+            let (_, deleted_at) = value.split_at(value.len().min(8));
+            let deleted_at = deleted_at
+                .try_into()
+                .map_err(|_ignore| ExecuteError::DbError(format!("Invalid trash entry {key:?}")))?;
+            if self.is_expired(u64::from_be_bytes(deleted_at), now) {
+                expired.push(key.to_vec());
+            }
+            Ok(())
+        })?;
+        let count = expired.len();
+        if count == 0 {
+            return Ok(0);
+        }
+        let id = ProposeId::new(PURGE_ID.to_owned());
+        for key in expired {
+            storage.buffer_op(&id, WriteOp::DeleteTrash(key));
+        }
+        storage.flush(&id)?;
+        Ok(count)
+    }
+
+    /// Interval between two purges of the trash
+    pub(crate) fn purge_interval(&self) -> Duration {
+        (*self.config.retention() / 10).max(Duration::from_secs(1))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use utils::config::StorageConfig;
+
+    use super::*;
+    use crate::{
+        rpc::{DeleteRangeRequest, PutRequest},
+        storage::db::DBProxy,
+    };
+
+    fn deletion(key: &str, value: &str, revision: i64) -> Event {
+        Event {
+            r#type: EventType::Delete as i32,
+            kv: Some(KeyValue {
+                key: key.as_bytes().to_vec(),
+                mod_revision: revision,
+                ..KeyValue::default()
+            }),
+            prev_kv: Some(KeyValue {
+                key: key.as_bytes().to_vec(),
+                value: value.as_bytes().to_vec().into(),
+                mod_revision: revision - 1,
+                ..KeyValue::default()
+            }),
+        }
+    }
+
+    #[test]
+    fn test_deleted_keys_are_kept_until_they_expire() {
+        let db = DBProxy::open(&StorageConfig::Memory).unwrap();
+        let trash = TrashStore::new(TrashConfig::new(true, Duration::from_secs(3600)));
+        let delete = RequestWrapper::DeleteRangeRequest(DeleteRangeRequest::default());
+        let id = ProposeId::new("1".to_owned());
+        trash.sync(
+            db.as_ref(),
+            &id,
+            &delete,
+            &[deletion("a", "1", 2), deletion("b", "2", 2)],
+        );
+        db.flush(&id).unwrap();
+        let id = ProposeId::new("2".to_owned());
+        trash.sync(db.as_ref(), &id, &delete, &[deletion("a", "3", 5)]);
+        // the deletions of the expired leases are not kept
+        let put = RequestWrapper::PutRequest(PutRequest::default());
+        trash.sync(db.as_ref(), &id, &put, &[deletion("c", "4", 5)]);
+        db.flush(&id).unwrap();
+
+        let entries = trash.list(db.as_ref(), &KeyRange::new("a", "c")).unwrap();
+        let entries: Vec<_> = entries
+            .iter()
+            .map(|entry| (entry.kv().key.clone(), entry.revision()))
+            .collect();
+        assert_eq!(entries, vec![(b"a".to_vec(), 5), (b"b".to_vec(), 2)]);
+        let entries = trash.list(db.as_ref(), &KeyRange::new("a", "")).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].kv().value, b"3".as_slice());
+        assert_eq!(trash.purge(db.as_ref()).unwrap(), 0);
+
+        let expired = TrashStore::new(TrashConfig::new(true, Duration::ZERO));
+        assert!(expired
+            .list(db.as_ref(), &KeyRange::new("a", "c"))
+            .unwrap()
+            .is_empty());
+        assert_eq!(expired.purge(db.as_ref()).unwrap(), 3);
+        assert!(db.get_all(TRASH_TABLE).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,254 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use parking_lot::Mutex;
+use tokio::sync::mpsc;
+
+use crate::{
+    revision_number::{RevisionNumber, RevisionNumberWatcher},
+    rpc::Event,
+};
+
+/// The updates of a revision are sent in chunks of at most this many events, so that a
+/// revision with many updates, eg. a revoked lease with many keys, doesn't hold the kv
+/// watcher for long or build a huge watch response
+const UPDATE_CHUNK_SIZE: usize = 1000;
+
+/// Sequencer of the kv updates. Revisions are allocated in the order of the log, but the
+/// after syncs of non-conflicting commands run concurrently and may finish in any order, so
+/// the updates are held here until all updates of the smaller revisions are sent to the kv
+/// watcher.
+///
+/// The revision of the kv store, which is read by every response header, is only
+/// published when a batch of consecutive revisions is finished, instead of being bumped for
+/// every allocated revision.
+#[derive(Debug)]
+pub(crate) struct UpdateSequencer {
+    /// Revision of the kv store, it's the applied revision
+    revision: Arc<RevisionNumber>,
+    /// The last allocated revision, it's only changed by `prepare` and `reset`
+    allocated: RevisionNumber,
+    /// The largest revision that it and all revisions before it are finished
+    applied: RevisionNumberWatcher,
+    /// Updates waiting for the smaller revisions
+    pending: Mutex<PendingUpdates>,
+    /// KV update sender
+    kv_update_tx: mpsc::UnboundedSender<(i64, Vec<Event>)>,
+}
+
+/// Updates waiting to be sent
+#[derive(Debug)]
+struct PendingUpdates {
+    /// The revision whose updates will be sent next
+    next: i64,
+    /// Finished revisions after `next`
+    updates: BTreeMap<i64, Vec<Event>>,
+}
+
+impl UpdateSequencer {
+    /// New `UpdateSequencer`, the updates start from the revision after the current one
+    pub(crate) fn new(
+        revision: Arc<RevisionNumber>,
+        kv_update_tx: mpsc::UnboundedSender<(i64, Vec<Event>)>,
+    ) -> Self {
+        let next = revision.get().wrapping_add(1);
+        Self {
+            applied: RevisionNumberWatcher::new(revision.get()),
+            allocated: RevisionNumber::new(revision.get()),
+            revision,
+            pending: Mutex::new(PendingUpdates {
+                next,
+                updates: BTreeMap::new(),
+            }),
+            kv_update_tx,
+        }
+    }
+
+    /// Allocate the next revision, it must be called in the order of the log
+    pub(crate) fn prepare(self: &Arc<Self>) -> PreparedRevision {
+        PreparedRevision {
+            revision: self.allocated.next(),
+            events: Vec::new(),
+            sequencer: Arc::clone(self),
+        }
+    }
+
+    /// Get the applied revision, all revisions not larger than it are finished
+    pub(crate) fn applied_revision(&self) -> i64 {
+        self.applied.get()
+    }
+
+    /// Wait until the applied revision reaches `revision`, return the applied revision
+    pub(crate) async fn wait_applied(&self, revision: i64) -> i64 {
+        self.applied.wait(revision).await
+    }
+
+    /// Forget the pending updates and start from the revision after the current one, it's
+    /// called after the kv store is recovered
+    pub(crate) fn reset(&self) {
+        let mut pending = self.pending.lock();
+        let current = self.revision.get();
+        pending.next = current.wrapping_add(1);
+        pending.updates.clear();
+        self.allocated.set(current);
+        self.applied.set(current);
+    }
+
+    /// Mark a revision finished and send all updates that are no longer waiting
+    fn finish(&self, revision: i64, events: Vec<Event>) {
+        let mut pending = self.pending.lock();
+        if revision < pending.next {
+            // allocated before the last reset
+            return;
+        }
+        let _prev = pending.updates.insert(revision, events);
+        let mut next = pending.next;
+        while let Some(ready) = pending.updates.remove(&next) {
+            // sent while holding the lock so that the updates are received in order
+            self.send(next, ready);
+            next = next.wrapping_add(1);
+        }
+        if next != pending.next {
+            pending.next = next;
+            let applied = next.wrapping_sub(1);
+            self.revision.set(applied);
+            self.applied.set(applied);
+        }
+    }
+
+    /// Send the updates of a revision in chunks, a revision without updates is sent too
+    fn send(&self, revision: i64, events: Vec<Event>) {
+        let mut events = events.into_iter().peekable();
+        loop {
+            let chunk = events.by_ref().take(UPDATE_CHUNK_SIZE).collect();
+            assert!(
+                self.kv_update_tx.send((revision, chunk)).is_ok(),
+                "Failed to send updates to KV watcher"
+            );
+            if events.peek().is_none() {
+                break;
+            }
+        }
+    }
+}
+
+/// A revision allocated to a command. The updates are sent to the kv watcher when it's
+/// dropped, so the revision is finished even if the after sync fails or isn't called. It
+/// should be dropped after the writes of the command are flushed, so that the data of an
+/// applied revision can be read.
+#[derive(Debug)]
+pub(crate) struct PreparedRevision {
+    /// The allocated revision
+    revision: i64,
+    /// Updates of the revision
+    events: Vec<Event>,
+    /// Sequencer of the revision
+    sequencer: Arc<UpdateSequencer>,
+}
+
+impl PreparedRevision {
+    /// Get the revision
+    pub(crate) fn revision(&self) -> i64 {
+        self.revision
+    }
+
+    /// Get the updates of the revision
+    pub(crate) fn events(&self) -> &[Event] {
+        &self.events
+    }
+
+    /// Set the updates of the revision, they are sent when the revision is dropped
+    pub(crate) fn notify(&mut self, events: Vec<Event>) {
+        self.events = events;
+    }
+}
+
+impl Drop for PreparedRevision {
+    fn drop(&mut self) {
+        self.sequencer
+            .finish(self.revision, std::mem::take(&mut self.events));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use clippy_utilities::OverflowArithmetic;
+
+    use super::*;
+    use crate::rpc::KeyValue;
+
+    fn event(revision: i64) -> Event {
+        Event {
+            kv: Some(KeyValue {
+                key: b"foo".to_vec(),
+                mod_revision: revision,
+                ..KeyValue::default()
+            }),
+            ..Event::default()
+        }
+    }
+
+    #[test]
+    fn test_updates_are_sent_in_order() {
+        let (kv_update_tx, mut kv_update_rx) = mpsc::unbounded_channel();
+        let sequencer = Arc::new(UpdateSequencer::new(
+            Arc::new(RevisionNumber::default()),
+            kv_update_tx,
+        ));
+        let rev2 = sequencer.prepare();
+        let mut rev3 = sequencer.prepare();
+        let mut rev4 = sequencer.prepare();
+        assert_eq!(rev2.revision(), 2);
+
+        rev4.notify(vec![event(4)]);
+        drop(rev4);
+        rev3.notify(vec![event(3)]);
+        drop(rev3);
+        assert!(kv_update_rx.try_recv().is_err());
+        assert_eq!(sequencer.applied_revision(), 1);
+        assert_eq!(sequencer.revision.get(), 1);
+        // a revision dropped without updates doesn't block the following ones
+        drop(rev2);
+        assert_eq!(sequencer.applied_revision(), 4);
+        assert_eq!(sequencer.revision.get(), 4);
+        let revisions: Vec<_> = std::iter::from_fn(|| kv_update_rx.try_recv().ok())
+            .map(|(revision, events)| (revision, events.len()))
+            .collect();
+        assert_eq!(revisions, vec![(2, 0), (3, 1), (4, 1)]);
+    }
+
+    #[test]
+    fn test_large_updates_are_sent_in_chunks() {
+        let (kv_update_tx, mut kv_update_rx) = mpsc::unbounded_channel();
+        let sequencer = Arc::new(UpdateSequencer::new(
+            Arc::new(RevisionNumber::default()),
+            kv_update_tx,
+        ));
+        let count = UPDATE_CHUNK_SIZE.overflow_mul(2).overflow_add(1);
+        sequencer
+            .prepare()
+            .notify((0..count).map(|_| event(2)).collect());
+        let chunks: Vec<_> = std::iter::from_fn(|| kv_update_rx.try_recv().ok())
+            .map(|(revision, events)| (revision, events.len()))
+            .collect();
+        assert_eq!(
+            chunks,
+            vec![(2, UPDATE_CHUNK_SIZE), (2, UPDATE_CHUNK_SIZE), (2, 1)]
+        );
+    }
+
+    #[test]
+    fn test_reset_drops_stale_revisions() {
+        let (kv_update_tx, mut kv_update_rx) = mpsc::unbounded_channel();
+        let revision = Arc::new(RevisionNumber::default());
+        let sequencer = Arc::new(UpdateSequencer::new(Arc::clone(&revision), kv_update_tx));
+        let stale = sequencer.prepare();
+        revision.set(10);
+        sequencer.reset();
+        assert_eq!(sequencer.applied_revision(), 10);
+        drop(stale);
+        assert!(kv_update_rx.try_recv().is_err());
+        assert_eq!(sequencer.applied_revision(), 10);
+        sequencer.prepare().notify(vec![event(11)]);
+        assert_eq!(kv_update_rx.try_recv().unwrap().0, 11);
+    }
+}
@@ -0,0 +1,172 @@
+use clippy_utilities::NumericCast;
+use regex::bytes::{Regex, RegexBuilder};
+
+use crate::rpc::{Event, EventType, WatchValuePredicate};
+
+/// Max size of a compiled value regex, so that a watcher can't take much memory of the
+/// dispatcher by a huge pattern
+const REGEX_SIZE_LIMIT: usize = 1_048_576;
+
+/// Filter of the events sent to a watcher, evaluated by the dispatcher before the events
+/// are sent to the watch stream
+#[derive(Debug, Default)]
+pub(crate) struct EventFilter {
+    /// Types of the events filtered out, the values of the filter types are the same as
+    /// the event types they filter out
+    types: Vec<i32>,
+    /// Predicate of the values of the put events, `None` if all of them are sent
+    predicate: Option<ValuePredicate>,
+}
+
+/// A compiled `WatchValuePredicate`, a value matches it if it matches all of its conditions
+#[derive(Debug)]
+struct ValuePredicate {
+    /// Prefix of the value
+    prefix: Vec<u8>,
+    /// Regex searched in the value
+    regex: Option<Regex>,
+    /// Min size of the value
+    min_size: u64,
+    /// Max size of the value, `None` if it's unbounded
+    max_size: Option<u64>,
+}
+
+impl ValuePredicate {
+    /// Compile a `WatchValuePredicate`, return an error if it's invalid
+    fn new(predicate: WatchValuePredicate) -> Result<Self, String> {
+        let regex = if predicate.value_regex.is_empty() {
+            None
+        } else {
+            let regex = RegexBuilder::new(&predicate.value_regex)
+                .size_limit(REGEX_SIZE_LIMIT)
+                .build()
+                .map_err(|e| format!("invalid value regex, {e}"))?;
+            Some(regex)
+        };
+        let max_size = (predicate.max_value_size > 0).then_some(predicate.max_value_size);
+        if max_size.map_or(false, |max_size| max_size < predicate.min_value_size) {
+            return Err(format!(
+                "max value size {} is less than min value size {}",
+                predicate.max_value_size, predicate.min_value_size
+            ));
+        }
+        Ok(Self {
+            prefix: predicate.value_prefix,
+            regex,
+            min_size: predicate.min_value_size,
+            max_size,
+        })
+    }
+
+    /// Check if a value matches the predicate
+    fn matches(&self, value: &[u8]) -> bool {
+        let size: u64 = value.len().numeric_cast();
+        value.starts_with(&self.prefix)
+            && size >= self.min_size
+            && self.max_size.map_or(true, |max_size| size <= max_size)
+            && self
+                .regex
+                .as_ref()
+                .map_or(true, |regex| regex.is_match(value))
+    }
+}
+
+impl EventFilter {
+    /// New `EventFilter`, return an error if the predicate is invalid
+    pub(crate) fn new(
+        types: Vec<i32>,
+        predicate: Option<WatchValuePredicate>,
+    ) -> Result<Self, String> {
+        Ok(Self {
+            types,
+            predicate: predicate.map(ValuePredicate::new).transpose()?,
+        })
+    }
+
+    /// Check if an event is sent to the watcher. The predicate only applies to the put
+    /// events, the value of a deleted key is unknown, so a delete event is always sent
+    /// unless its type is filtered out.
+    fn matches(&self, event: &Event) -> bool {
+        if self.types.contains(&event.r#type) {
+            return false;
+        }
+        #[allow(clippy::as_conversions)] // This cast is always valid
+        let is_put = event.r#type == EventType::Put as i32;
+        match (self.predicate.as_ref(), event.kv.as_ref()) {
+            (Some(predicate), Some(kv)) if is_put => predicate.matches(&kv.value),
+            _ => true,
+        }
+    }
+
+    /// Remove the events filtered out
+    pub(crate) fn filter(&self, events: &mut Vec<Event>) {
+        events.retain(|event| self.matches(event));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rpc::KeyValue;
+
+    #[allow(clippy::as_conversions)] // This cast is always valid
+    fn event(event_type: EventType, value: &str) -> Event {
+        Event {
+            r#type: event_type as i32,
+            kv: Some(KeyValue {
+                value: value.as_bytes().to_vec(),
+                ..KeyValue::default()
+            }),
+            prev_kv: None,
+        }
+    }
+
+    #[test]
+    fn test_events_are_filtered_by_the_value_predicate() {
+        let filter = EventFilter::new(
+            vec![],
+            Some(WatchValuePredicate {
+                value_prefix: b"job-".to_vec(),
+                value_regex: "(failed|done)$".to_owned(),
+                min_value_size: 0,
+                max_value_size: 12,
+            }),
+        )
+        .unwrap();
+        let mut events = vec![
+            event(EventType::Put, "job-1-done"),
+            event(EventType::Put, "job-1-running"),
+            event(EventType::Put, "task-1-done"),
+            event(EventType::Put, "job-100-failed"),
+            event(EventType::Delete, ""),
+        ];
+        filter.filter(&mut events);
+        let values: Vec<_> = events
+            .iter()
+            .map(|event| event.kv.as_ref().unwrap().value.as_slice())
+            .collect();
+        assert_eq!(values, [b"job-1-done".as_slice(), b""]);
+
+        // `NODELETE` has the same value as the delete event type
+        #[allow(clippy::as_conversions)] // This cast is always valid
+        let filter = EventFilter::new(vec![EventType::Delete as i32], None).unwrap();
+        let mut events = vec![event(EventType::Put, "a"), event(EventType::Delete, "")];
+        filter.filter(&mut events);
+        assert_eq!(events.len(), 1);
+    }
+
+    #[test]
+    fn test_invalid_predicates_are_rejected() {
+        let predicate = |value_regex: &str, min_value_size, max_value_size| {
+            Some(WatchValuePredicate {
+                value_regex: value_regex.to_owned(),
+                min_value_size,
+                max_value_size,
+                ..WatchValuePredicate::default()
+            })
+        };
+        assert!(EventFilter::new(vec![], predicate("(", 0, 0)).is_err());
+        assert!(EventFilter::new(vec![], predicate("", 5, 4)).is_err());
+        assert!(EventFilter::new(vec![], predicate("", 5, 0)).is_ok());
+    }
+}
@@ -0,0 +1,76 @@
+mod common;
+
+use std::{error::Error, time::Duration};
+
+use etcd_client::Client;
+use tokio::{net::TcpListener, time};
+
+use crate::common::Cluster;
+
+/// Wait until the member at `addr` lists member `name` at `url`
+async fn wait_listed(addr: &str, name: &str, url: &str) -> Result<(), Box<dyn Error>> {
+    let mut client = Client::connect(vec![addr.to_owned()], None).await?;
+    for _ in 0..50 {
+        let resp = client.member_list().await?;
+        if resp
+            .members()
+            .iter()
+            .any(|member| member.name() == name && member.peer_urls() == [url.to_owned()])
+        {
+            return Ok(());
+        }
+        time::sleep(Duration::from_millis(100)).await;
+    }
+    Err(format!("{addr} doesn't list {name} at {url}").into())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 10)]
+async fn test_member_update() -> Result<(), Box<dyn Error>> {
+    let mut cluster = Cluster::new(3).await;
+    cluster.start().await;
+    let addrs = cluster.addrs().clone();
+    let mut client = Client::connect(vec![addrs["server0"].clone()], None).await?;
+    let resp = client.member_list().await?;
+    assert_eq!(resp.members().len(), 3);
+    let server2 = resp
+        .members()
+        .iter()
+        .find(|member| member.name() == "server2")
+        .ok_or("server2 isn't listed")?
+        .id();
+
+    // move server2 to an address no member serves on
+    let unused = TcpListener::bind("127.0.0.1:0").await?.local_addr()?;
+    let moved = format!("http://{unused}");
+    let resp = client.member_update(server2, vec![moved.clone()]).await?;
+    assert!(resp
+        .members()
+        .iter()
+        .any(|member| member.id() == server2 && member.peer_urls() == [moved.clone()]));
+    for name in ["server0", "server1", "server2"] {
+        wait_listed(&addrs[name], "server2", &moved).await?;
+    }
+    // the quorum is still reachable
+    let _put = client.put("foo", "bar", None).await?;
+
+    // and back to the address it serves on, the id is kept
+    let back = format!("http://{}", addrs["server2"]);
+    let _resp = client.member_update(server2, vec![back.clone()]).await?;
+    for name in ["server0", "server1", "server2"] {
+        wait_listed(&addrs[name], "server2", &back).await?;
+    }
+    let _put = client.put("foo", "baz", None).await?;
+
+    let err = client
+        .member_update(server2.wrapping_add(1), vec![back.clone()])
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("member not found"), "{err}");
+    // an https URL needs peer TLS
+    let err = client
+        .member_update(server2, vec!["https://127.0.0.1:2379".to_owned()])
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("member URLs are invalid"), "{err}");
+    Ok(())
+}
@@ -7,7 +7,14 @@ use tokio::{
     sync::broadcast::{self, Sender},
     time::{self, Duration},
 };
-use utils::config::{ClientTimeout, CurpConfig, StorageConfig};
+use utils::config::{
+    default_log_level, default_rotation, AdmissionConfig, AuditConfig, AuthConfig, BackendConfig,
+    BackupConfig, BatchConfig, ClientTimeout, ClusterConfig, CompactConfig, CompressionConfig,
+    CorruptCheckConfig, CurpConfig, GrpcConfig, InitialClusterState, LeaseConfig, LogConfig,
+    PeerTlsConfig, ProbeConfig, ProxyProtocolConfig, QuotaConfig, RateLimitConfig,
+    RequestLimitConfig, ServerTimeout, StorageConfig, TlsConfig, TraceConfig, TrashConfig,
+    XlineServerConfig,
+};
 use xline::{client::Client, server::XlineServer, storage::db::DBProxy};
 
 /// Cluster
@@ -58,19 +65,21 @@ impl Cluster {
             #[allow(clippy::unwrap_used)]
             let db = DBProxy::open(&StorageConfig::Memory).unwrap();
             tokio::spawn(async move {
+                let curp_config = CurpConfig {
+                    data_dir: format!("/tmp/curp-{}", random_id()).into(),
+                    ..Default::default()
+                };
+                let config = Self::test_config(name, all_members.clone(), is_leader, &curp_config);
                 let server = XlineServer::new(
-                    name,
+                    &config,
                     all_members,
-                    is_leader,
+                    curp_config,
                     Self::test_key_pair(),
-                    CurpConfig {
-                        data_dir: format!("/tmp/curp-{}", random_id()).into(),
-                        ..Default::default()
-                    },
-                    ClientTimeout::default(),
+                    None,
                     db,
                 )
-                .await;
+                .await
+                .unwrap();
                 let signal = async {
                     let _ = rx.recv().await;
                 };
@@ -103,6 +112,52 @@ impl Cluster {
         &self.all_members
     }
 
+    fn test_config(
+        name: String,
+        all_members: HashMap<String, String>,
+        is_leader: bool,
+        curp_config: &CurpConfig,
+    ) -> XlineServerConfig {
+        let cluster = ClusterConfig::new(
+            name,
+            all_members,
+            None,
+            is_leader,
+            InitialClusterState::New,
+            curp_config.clone(),
+            ClientTimeout::default(),
+            None,
+            vec![],
+        );
+        XlineServerConfig::new(
+            cluster,
+            StorageConfig::Memory,
+            LogConfig::new("/tmp".into(), default_rotation(), default_log_level()),
+            TraceConfig::new(false, false, "/tmp".into(), default_log_level(), None),
+            AuthConfig::new(None, None, None),
+            GrpcConfig::default(),
+            RateLimitConfig::default(),
+            ServerTimeout::default(),
+            AuditConfig::default(),
+            BackupConfig::default(),
+            BatchConfig::default(),
+            ProbeConfig::default(),
+            RequestLimitConfig::default(),
+            QuotaConfig::default(),
+            CompressionConfig::default(),
+            CorruptCheckConfig::default(),
+            CompactConfig::default(),
+            TlsConfig::default(),
+            PeerTlsConfig::default(),
+            ProxyProtocolConfig::default(),
+            AdmissionConfig::default(),
+            vec![],
+            TrashConfig::default(),
+            BackendConfig::default(),
+            LeaseConfig::default(),
+        )
+    }
+
     fn test_key_pair() -> Option<(EncodingKey, DecodingKey)> {
         let private_key = include_bytes!("../private.pem");
         let public_key = include_bytes!("../public.pem");
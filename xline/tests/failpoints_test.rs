@@ -0,0 +1,46 @@
+#![cfg(feature = "failpoints")]
+
+mod common;
+
+use std::{error::Error, time::Duration};
+
+use etcd_client::{AlarmAction, AlarmType, Client};
+
+use crate::common::Cluster;
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 10)]
+async fn test_flush_failure_raises_corrupt_alarm() -> Result<(), Box<dyn Error>> {
+    let _scenario = fail::FailScenario::setup();
+    let mut cluster = Cluster::new(3).await;
+    cluster.start().await;
+    let addrs: Vec<_> = cluster.addrs().values().cloned().collect();
+    let mut client = Client::connect(addrs.clone(), None).await?;
+    client.put("foo", "bar", None).await?;
+
+    // the next flush of a member fails after its memory states are updated
+    fail::cfg("db_before_flush", "1*return")?;
+    let _res = client.put("foo", "baz", None).await;
+
+    let mut corrupted = false;
+    for _ in 0..50 {
+        for addr in &addrs {
+            let mut member_client = Client::connect([addr], None).await?;
+            let resp = member_client
+                .alarm(AlarmAction::Get, AlarmType::None, None)
+                .await?;
+            corrupted |= resp
+                .alarms()
+                .iter()
+                .any(|alarm| alarm.alarm() == AlarmType::Corrupt);
+        }
+        if corrupted {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert!(
+        corrupted,
+        "the member whose flush failed should raise CORRUPT"
+    );
+    Ok(())
+}
@@ -2,7 +2,7 @@ mod common;
 
 use std::error::Error;
 
-use etcd_client::Client;
+use etcd_client::{Client, CompactionOptions, GetOptions};
 use xline::client::kv_types::{
     DeleteRangeRequest, PutRequest, RangeRequest, SortOrder, SortTarget,
 };
@@ -174,6 +174,30 @@ async fn test_range_redirect() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+#[tokio::test(flavor = "multi_thread", worker_threads = 10)]
+async fn test_kv_compact() -> Result<(), Box<dyn Error>> {
+    let mut cluster = Cluster::new(3).await;
+    cluster.start().await;
+
+    let addr = cluster.addrs()["server0"].clone();
+    let mut kv_client = Client::connect([addr], None).await?.kv_client();
+    let first = kv_client.put("foo", "bar0", None).await?;
+    let first_rev = first.header().map_or(0, |h| h.revision());
+    let second = kv_client.put("foo", "bar1", None).await?;
+    let second_rev = second.header().map_or(0, |h| h.revision());
+
+    let _res = kv_client
+        .compact(second_rev, Some(CompactionOptions::new().with_physical()))
+        .await?;
+    let options = GetOptions::new().with_revision(first_rev);
+    assert!(kv_client.get("foo", Some(options)).await.is_err());
+    let res = kv_client.get("foo", None).await?;
+    assert_eq!(res.kvs()[0].value(), b"bar1");
+    assert!(kv_client.compact(first_rev, None).await.is_err());
+
+    Ok(())
+}
+
 #[tokio::test(flavor = "multi_thread", worker_threads = 10)]
 async fn test_kv_delete() -> Result<(), Box<dyn Error>> {
     struct TestCase<'a> {
@@ -22,7 +22,7 @@ async fn test_lease_expired() -> Result<(), Box<dyn Error>> {
         .await?;
     let res = client.range(RangeRequest::new("foo")).await?;
     assert_eq!(res.kvs.len(), 1);
-    assert_eq!(res.kvs[0].value, b"bar");
+    assert_eq!(res.kvs[0].value, b"bar".as_slice());
 
     tokio::time::sleep(Duration::from_secs(3)).await;
 
@@ -48,7 +48,7 @@ async fn test_lease_keep_alive() -> Result<(), Box<dyn Error>> {
         .await?;
     let res = client.range(RangeRequest::new("foo")).await?;
     assert_eq!(res.kvs.len(), 1);
-    assert_eq!(res.kvs[0].value, b"bar");
+    assert_eq!(res.kvs[0].value, b"bar".as_slice());
 
     let mut c = etcd_client::Client::connect(vec![non_leader_ep], None).await?;
     let (mut keeper, mut stream) = c.lease_keep_alive(lease_id).await?;
@@ -65,7 +65,7 @@ async fn test_lease_keep_alive() -> Result<(), Box<dyn Error>> {
     tokio::time::sleep(Duration::from_secs(3)).await;
     let res = client.range(RangeRequest::new("foo")).await?;
     assert_eq!(res.kvs.len(), 1);
-    assert_eq!(res.kvs[0].value, b"bar");
+    assert_eq!(res.kvs[0].value, b"bar".as_slice());
 
     handle.abort();
     tokio::time::sleep(Duration::from_secs(2)).await;
@@ -0,0 +1,43 @@
+use anyhow::Result;
+use clap::{Args, Subcommand};
+use xline_client::clients::auth::AuthClient;
+
+use crate::printer;
+
+/// Arguments of `auth`
+#[derive(Args, Debug)]
+pub(crate) struct AuthArgs {
+    /// Subcommand
+    #[clap(subcommand)]
+    command: AuthCommand,
+}
+
+/// Subcommands of `auth`
+#[derive(Subcommand, Debug, Clone, Copy)]
+enum AuthCommand {
+    /// Enable authentication
+    Enable,
+    /// Disable authentication
+    Disable,
+    /// Get the status of authentication
+    Status,
+}
+
+/// Execute `auth`
+pub(crate) async fn execute(client: &mut AuthClient, args: AuthArgs) -> Result<()> {
+    match args.command {
+        AuthCommand::Enable => {
+            let _resp = client.auth_enable().await?;
+            println!("Authentication Enabled");
+        }
+        AuthCommand::Disable => {
+            let _resp = client.auth_disable().await?;
+            println!("Authentication Disabled");
+        }
+        AuthCommand::Status => {
+            let resp = client.auth_status().await?;
+            printer::print_auth_status(&resp);
+        }
+    }
+    Ok(())
+}
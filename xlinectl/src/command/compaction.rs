@@ -0,0 +1,23 @@
+use anyhow::Result;
+use clap::Args;
+use xline_client::{clients::kv::KvClient, types::kv::CompactionRequest};
+
+use crate::printer;
+
+/// Arguments of `compaction`
+#[derive(Args, Debug)]
+pub(crate) struct CompactionArgs {
+    /// The history before this revision is compacted
+    revision: i64,
+    /// Wait until the compacted history is removed from the backend
+    #[clap(long)]
+    physical: bool,
+}
+
+/// Execute `compaction`
+pub(crate) async fn execute(client: &mut KvClient, args: CompactionArgs) -> Result<()> {
+    let request = CompactionRequest::new(args.revision).with_physical(args.physical);
+    let _resp = client.compact(request).await?;
+    printer::print_compaction(args.revision);
+    Ok(())
+}
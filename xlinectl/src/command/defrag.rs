@@ -0,0 +1,30 @@
+use anyhow::{bail, Result};
+
+use crate::{printer, ConnectionArgs};
+
+/// Execute `defrag`, each endpoint is defragmented separately, so that the failure of
+/// one member doesn't stop the others
+pub(crate) async fn execute(connection: &ConnectionArgs) -> Result<()> {
+    let mut failed = Vec::new();
+    for (endpoint, client) in connection.connect_each().await? {
+        let result = match client {
+            Ok(client) => client
+                .maintenance_client()
+                .defragment()
+                .await
+                .map_err(Into::into),
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(_resp) => printer::print_defrag(endpoint, None),
+            Err(e) => {
+                printer::print_defrag(endpoint, Some(&e.to_string()));
+                failed.push(endpoint);
+            }
+        }
+    }
+    if !failed.is_empty() {
+        bail!("failed to defragment {}", failed.join(","));
+    }
+    Ok(())
+}
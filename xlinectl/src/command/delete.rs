@@ -0,0 +1,47 @@
+use anyhow::Result;
+use clap::Args;
+use xline_client::{clients::kv::KvClient, types::kv::DeleteRangeRequest};
+
+use crate::printer;
+
+/// Arguments of `del`
+#[derive(Args, Debug)]
+pub(crate) struct DeleteArgs {
+    /// The key, or the start of the range
+    key: String,
+    /// The end of the range, exclusive
+    range_end: Option<String>,
+    /// Delete the keys with the prefix `key`
+    #[clap(long, conflicts_with_all = &["range-end", "from-key"])]
+    prefix: bool,
+    /// Delete the keys not less than `key`
+    #[clap(long, conflicts_with = "range-end")]
+    from_key: bool,
+    /// Return the deleted key-values
+    #[clap(long)]
+    prev_kv: bool,
+}
+
+impl DeleteArgs {
+    /// Build the request
+    pub(crate) fn request(&self) -> DeleteRangeRequest {
+        let mut request = DeleteRangeRequest::new(self.key.as_bytes());
+        if let Some(ref range_end) = self.range_end {
+            request = request.with_range_end(range_end.as_bytes());
+        }
+        if self.prefix {
+            request = request.with_prefix();
+        }
+        if self.from_key {
+            request = request.with_from_key();
+        }
+        request.with_prev_kv(self.prev_kv)
+    }
+}
+
+/// Execute `del`
+pub(crate) async fn execute(client: &mut KvClient, args: DeleteArgs) -> Result<()> {
+    let resp = client.delete(args.request()).await?;
+    printer::print_delete(&resp);
+    Ok(())
+}
@@ -0,0 +1,90 @@
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Result};
+use clap::{Args, Subcommand};
+use xline_client::{errors::ClientError, types::kv::RangeRequest, Client};
+
+use crate::{printer, ConnectionArgs};
+
+/// Arguments of `endpoint`
+#[derive(Args, Debug)]
+pub(crate) struct EndpointArgs {
+    /// Subcommand
+    #[clap(subcommand)]
+    command: EndpointCommand,
+}
+
+/// Subcommands of `endpoint`
+#[derive(Subcommand, Debug)]
+enum EndpointCommand {
+    /// Get the status of each endpoint
+    Status,
+    /// Check the health of each endpoint by reading a key through the consensus
+    Health,
+    /// Get the hash of the keys of each endpoint
+    Hashkv {
+        /// Hash the keys up to this revision, 0 for the latest revision
+        #[clap(long, default_value_t = 0)]
+        rev: i64,
+    },
+    /// Get the statistics of the kv, lease and auth tables of each endpoint
+    TableStats,
+}
+
+/// Execute `endpoint`, the command is sent to each endpoint separately, it fails if any
+/// endpoint fails
+pub(crate) async fn execute(connection: &ConnectionArgs, args: EndpointArgs) -> Result<()> {
+    let mut failed = Vec::new();
+    for (endpoint, client) in connection.connect_each().await? {
+        let result = match client {
+            Ok(client) => execute_on(&client, endpoint, &args.command).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            printer::print_endpoint_error(endpoint, &e.to_string());
+            failed.push(endpoint);
+        }
+    }
+    if !failed.is_empty() {
+        bail!("failed to check {}", failed.join(","));
+    }
+    Ok(())
+}
+
+/// Execute the subcommand on the client connected to `endpoint`
+async fn execute_on(client: &Client, endpoint: &str, command: &EndpointCommand) -> Result<()> {
+    match *command {
+        EndpointCommand::Status => {
+            let resp = client.maintenance_client().status().await?;
+            printer::print_endpoint_status(endpoint, &resp);
+        }
+        EndpointCommand::Health => {
+            let took = check_health(client).await?;
+            printer::print_endpoint_health(endpoint, took);
+        }
+        EndpointCommand::Hashkv { rev } => {
+            let resp = client.maintenance_client().hash_kv(rev).await?;
+            printer::print_endpoint_hash_kv(endpoint, &resp);
+        }
+        EndpointCommand::TableStats => {
+            let resp = client.maintenance_client().table_stats().await?;
+            printer::print_endpoint_table_stats(endpoint, &resp);
+        }
+    }
+    Ok(())
+}
+
+/// Read the key `health` by a linearizable read, return the time it takes. Being denied
+/// by the auth store still means the read went through the consensus.
+async fn check_health(client: &Client) -> Result<Duration> {
+    let start = Instant::now();
+    match client.kv_client().range(RangeRequest::new("health")).await {
+        Ok(_resp) => Ok(start.elapsed()),
+        Err(ClientError::RpcError(ref status))
+            if status.code() == tonic::Code::PermissionDenied =>
+        {
+            Ok(start.elapsed())
+        }
+        Err(e) => Err(e.into()),
+    }
+}
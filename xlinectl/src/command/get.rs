@@ -0,0 +1,123 @@
+use anyhow::{bail, Result};
+use clap::{Args, ValueEnum};
+use xline_client::{
+    clients::kv::KvClient,
+    types::kv::{RangeRequest, SortOrder, SortTarget},
+};
+
+use crate::printer;
+
+/// Order of the keys
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub(crate) enum Order {
+    /// Ascending order
+    Ascend,
+    /// Descending order
+    Descend,
+}
+
+/// Target to sort the keys by
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub(crate) enum SortBy {
+    /// Sort by key
+    Key,
+    /// Sort by version
+    Version,
+    /// Sort by create revision
+    Create,
+    /// Sort by mod revision
+    Modify,
+    /// Sort by value
+    Value,
+}
+
+/// Arguments of `get`
+#[derive(Args, Debug)]
+pub(crate) struct GetArgs {
+    /// The key, or the start of the range
+    key: String,
+    /// The end of the range, exclusive
+    range_end: Option<String>,
+    /// Get the keys with the prefix `key`
+    #[clap(long, conflicts_with_all = &["range-end", "from-key"])]
+    prefix: bool,
+    /// Get the keys not less than `key`
+    #[clap(long, conflicts_with = "range-end")]
+    from_key: bool,
+    /// Max number of the keys, 0 means no limit
+    #[clap(long, default_value_t = 0)]
+    limit: i64,
+    /// Get the keys at the revision, 0 means the latest revision
+    #[clap(long, default_value_t = 0)]
+    rev: i64,
+    /// Get the keys at the latest revision applied at or before the time, in seconds since
+    /// the unix epoch, by the connected member
+    #[clap(long, conflicts_with = "rev")]
+    at: Option<i64>,
+    /// Order of the keys
+    #[clap(long, value_enum)]
+    order: Option<Order>,
+    /// Target to sort the keys by, the keys are in ascending order if `order` is not given
+    #[clap(long, value_enum)]
+    sort_by: Option<SortBy>,
+    /// Get the keys only
+    #[clap(long)]
+    keys_only: bool,
+    /// Get the number of the keys only
+    #[clap(long)]
+    count_only: bool,
+    /// Serve the request by the connected member locally
+    #[clap(long)]
+    serializable: bool,
+}
+
+impl GetArgs {
+    /// Build the request
+    pub(crate) fn request(&self) -> RangeRequest {
+        let mut request = RangeRequest::new(self.key.as_bytes());
+        if let Some(ref range_end) = self.range_end {
+            request = request.with_range_end(range_end.as_bytes());
+        }
+        if self.prefix {
+            request = request.with_prefix();
+        }
+        if self.from_key {
+            request = request.with_from_key();
+        }
+        let sort_order = match (self.order, self.sort_by) {
+            (Some(Order::Ascend), _) | (None, Some(_)) => SortOrder::Ascend,
+            (Some(Order::Descend), _) => SortOrder::Descend,
+            (None, None) => SortOrder::None,
+        };
+        let sort_target = match self.sort_by {
+            Some(SortBy::Key) | None => SortTarget::Key,
+            Some(SortBy::Version) => SortTarget::Version,
+            Some(SortBy::Create) => SortTarget::Create,
+            Some(SortBy::Modify) => SortTarget::Mod,
+            Some(SortBy::Value) => SortTarget::Value,
+        };
+        request
+            .with_limit(self.limit)
+            .with_revision(self.rev)
+            .with_sort_order(sort_order)
+            .with_sort_target(sort_target)
+            .with_keys_only(self.keys_only)
+            .with_count_only(self.count_only)
+            .with_serializable(self.serializable)
+    }
+}
+
+/// Execute `get`
+pub(crate) async fn execute(client: &mut KvClient, args: GetArgs) -> Result<()> {
+    let mut request = args.request();
+    if let Some(timestamp) = args.at {
+        let resp = client.revision_at(timestamp).await?;
+        if resp.revision == 0 {
+            bail!("no revision is recorded at or before {timestamp}");
+        }
+        request = request.with_revision(resp.revision);
+    }
+    let resp = client.range(request).await?;
+    printer::print_range(&resp, args.count_only);
+    Ok(())
+}
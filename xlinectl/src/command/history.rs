@@ -0,0 +1,28 @@
+use anyhow::Result;
+use clap::Args;
+use xline_client::{clients::kv::KvClient, types::kv::KeyHistoryRequest};
+
+use crate::printer;
+
+/// Arguments of `history`
+#[derive(Args, Debug)]
+pub(crate) struct HistoryArgs {
+    /// The key
+    key: String,
+    /// List the revisions from this revision, 0 means from the compacted revision
+    #[clap(long, default_value_t = 0)]
+    rev: i64,
+    /// Max number of the revisions, 0 means no limit
+    #[clap(long, default_value_t = 0)]
+    limit: i64,
+}
+
+/// Execute `history`
+pub(crate) async fn execute(client: &mut KvClient, args: HistoryArgs) -> Result<()> {
+    let request = KeyHistoryRequest::new(args.key.as_bytes())
+        .with_start_revision(args.rev)
+        .with_limit(args.limit);
+    let resp = client.key_history(request).await?;
+    printer::print_key_history(&resp);
+    Ok(())
+}
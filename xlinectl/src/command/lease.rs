@@ -0,0 +1,101 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use clap::{Args, Subcommand};
+use xline_client::{
+    clients::lease::LeaseClient,
+    types::lease::{LeaseGrantRequest, LeaseTimeToLiveRequest},
+};
+
+use crate::printer;
+
+/// Arguments of `lease`
+#[derive(Args, Debug)]
+pub(crate) struct LeaseArgs {
+    /// Subcommand
+    #[clap(subcommand)]
+    command: LeaseCommand,
+}
+
+/// Subcommands of `lease`
+#[derive(Subcommand, Debug)]
+enum LeaseCommand {
+    /// Grant a lease
+    Grant {
+        /// Time-to-live of the lease in seconds
+        ttl: i64,
+    },
+    /// Revoke a lease and delete the keys attached to it
+    Revoke {
+        /// Id of the lease
+        id: i64,
+    },
+    /// Change the time-to-live of a lease, keeping the keys attached to it
+    ModifyTtl {
+        /// Id of the lease
+        id: i64,
+        /// New time-to-live of the lease in seconds
+        ttl: i64,
+    },
+    /// Get the time-to-live of a lease
+    Timetolive {
+        /// Id of the lease
+        id: i64,
+        /// Get the keys attached to the lease
+        #[clap(long)]
+        keys: bool,
+    },
+    /// Keep a lease alive until it expires or the command is interrupted
+    KeepAlive {
+        /// Id of the lease
+        id: i64,
+        /// Refresh the lease once
+        #[clap(long)]
+        once: bool,
+    },
+    /// List all leases
+    List,
+}
+
+/// Execute `lease`
+pub(crate) async fn execute(client: &mut LeaseClient, args: LeaseArgs) -> Result<()> {
+    match args.command {
+        LeaseCommand::Grant { ttl } => {
+            let resp = client.grant(LeaseGrantRequest::new(ttl)).await?;
+            printer::print_lease_grant(&resp);
+        }
+        LeaseCommand::Revoke { id } => {
+            let _resp = client.revoke(id).await?;
+            printer::print_lease_revoke(id);
+        }
+        LeaseCommand::ModifyTtl { id, ttl } => {
+            let resp = client.modify_ttl(id, ttl).await?;
+            printer::print_lease_modify_ttl(&resp);
+        }
+        LeaseCommand::Timetolive { id, keys } => {
+            let request = LeaseTimeToLiveRequest::new(id).with_keys(keys);
+            let resp = client.time_to_live(request).await?;
+            printer::print_lease_time_to_live(&resp);
+        }
+        LeaseCommand::KeepAlive { id, once } => keep_alive(client, id, once).await?,
+        LeaseCommand::List => {
+            let resp = client.leases().await?;
+            printer::print_lease_list(&resp);
+        }
+    }
+    Ok(())
+}
+
+/// Refresh a lease at a third of its time-to-live, and print the new time-to-live
+async fn keep_alive(client: &mut LeaseClient, id: i64, once: bool) -> Result<()> {
+    let (sender, mut stream) = client.keep_alive(id).await?;
+    while let Some(resp) = stream.message().await? {
+        printer::print_lease_keep_alive(&resp);
+        if once || resp.ttl <= 0 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_secs(resp.ttl.unsigned_abs()) / 3).await;
+        sender.keep_alive().await?;
+    }
+    Ok(())
+}
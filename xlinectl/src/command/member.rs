@@ -0,0 +1,100 @@
+use anyhow::Result;
+use clap::{Args, Subcommand};
+use xline_client::clients::cluster::ClusterClient;
+
+use crate::printer;
+
+/// Arguments of `member`
+#[derive(Args, Debug)]
+pub(crate) struct MemberArgs {
+    /// Subcommand
+    #[clap(subcommand)]
+    command: MemberCommand,
+}
+
+/// Subcommands of `member`
+#[derive(Subcommand, Debug)]
+enum MemberCommand {
+    /// Add a member into the cluster
+    Add {
+        /// Peer urls of the new member, eg. http://10.0.0.1:2380,http://10.0.0.1:2381
+        #[clap(long, value_delimiter = ',', required = true)]
+        peer_urls: Vec<String>,
+        /// Add the member as a learner
+        #[clap(long)]
+        learner: bool,
+    },
+    /// Remove a member from the cluster
+    Remove {
+        /// Id of the member in hex
+        #[clap(value_parser = parse_member_id)]
+        id: u64,
+    },
+    /// Update the peer urls of a member
+    Update {
+        /// Id of the member in hex
+        #[clap(value_parser = parse_member_id)]
+        id: u64,
+        /// New peer urls of the member
+        #[clap(long, value_delimiter = ',', required = true)]
+        peer_urls: Vec<String>,
+    },
+    /// List all members of the cluster
+    List {
+        /// Read the members from the leader
+        #[clap(long)]
+        linearizable: bool,
+    },
+    /// Promote a learner to a voting member
+    Promote {
+        /// Id of the member in hex
+        #[clap(value_parser = parse_member_id)]
+        id: u64,
+    },
+}
+
+/// Parse a member id in hex, the ids are printed in hex
+fn parse_member_id(id: &str) -> Result<u64, String> {
+    u64::from_str_radix(id, 16).map_err(|e| format!("invalid member id {id}, {e}"))
+}
+
+/// Execute `member`
+pub(crate) async fn execute(client: &mut ClusterClient, args: MemberArgs) -> Result<()> {
+    match args.command {
+        MemberCommand::Add { peer_urls, learner } => {
+            let resp = client.member_add(peer_urls, learner).await?;
+            printer::print_member_add(&resp);
+        }
+        MemberCommand::Remove { id } => {
+            let _resp = client.member_remove(id).await?;
+            printer::print_member_change(id, "removed from");
+        }
+        MemberCommand::Update { id, peer_urls } => {
+            let _resp = client.member_update(id, peer_urls).await?;
+            printer::print_member_change(id, "updated in");
+        }
+        MemberCommand::List { linearizable } => {
+            let resp = client.member_list(linearizable).await?;
+            resp.members.iter().for_each(printer::print_member);
+        }
+        MemberCommand::Promote { id } => {
+            let _resp = client.member_promote(id).await?;
+            printer::print_member_change(id, "promoted in");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_member_id() {
+        assert_eq!(
+            parse_member_id("8e9e05c52164694d").unwrap(),
+            0x8e9e_05c5_2164_694d
+        );
+        assert!(parse_member_id("xyz").is_err());
+    }
+}
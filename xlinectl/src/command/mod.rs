@@ -0,0 +1,32 @@
+/// `auth` command
+pub(crate) mod auth;
+/// `compaction` command
+pub(crate) mod compaction;
+/// `defrag` command
+pub(crate) mod defrag;
+/// `del` command
+pub(crate) mod delete;
+/// `endpoint` command
+pub(crate) mod endpoint;
+/// `get` command
+pub(crate) mod get;
+/// `history` command
+pub(crate) mod history;
+/// `lease` command
+pub(crate) mod lease;
+/// `member` command
+pub(crate) mod member;
+/// `put` command
+pub(crate) mod put;
+/// `role` command
+pub(crate) mod role;
+/// `snapshot` command
+pub(crate) mod snapshot;
+/// `txn` command
+pub(crate) mod txn;
+/// `undelete` command
+pub(crate) mod undelete;
+/// `user` command
+pub(crate) mod user;
+/// `watch` command
+pub(crate) mod watch;
@@ -0,0 +1,44 @@
+use anyhow::Result;
+use clap::Args;
+use xline_client::{clients::kv::KvClient, types::kv::PutRequest};
+
+use crate::printer;
+
+/// Arguments of `put`
+#[derive(Args, Debug)]
+pub(crate) struct PutArgs {
+    /// The key
+    key: String,
+    /// The value
+    value: String,
+    /// Id of the lease to attach the key to
+    #[clap(long, default_value_t = 0)]
+    lease: i64,
+    /// Return the previous key-value
+    #[clap(long)]
+    prev_kv: bool,
+    /// Keep the current value of the key, `value` is ignored
+    #[clap(long)]
+    ignore_value: bool,
+    /// Keep the current lease of the key, `lease` is ignored
+    #[clap(long)]
+    ignore_lease: bool,
+}
+
+impl PutArgs {
+    /// Build the request
+    pub(crate) fn request(&self) -> PutRequest {
+        PutRequest::new(self.key.as_bytes(), self.value.as_bytes())
+            .with_lease(self.lease)
+            .with_prev_kv(self.prev_kv)
+            .with_ignore_value(self.ignore_value)
+            .with_ignore_lease(self.ignore_lease)
+    }
+}
+
+/// Execute `put`
+pub(crate) async fn execute(client: &mut KvClient, args: PutArgs) -> Result<()> {
+    let resp = client.put(args.request()).await?;
+    printer::print_put(&resp);
+    Ok(())
+}
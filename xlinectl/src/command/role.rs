@@ -0,0 +1,140 @@
+use anyhow::Result;
+use clap::{Args, Subcommand, ValueEnum};
+use xline_client::{
+    clients::auth::AuthClient,
+    types::auth::{Permission, PermissionType},
+};
+
+use crate::printer;
+
+/// Type of a permission
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub(crate) enum PermType {
+    /// Permission to read
+    Read,
+    /// Permission to write
+    Write,
+    /// Permission to read and write
+    Readwrite,
+}
+
+impl From<PermType> for PermissionType {
+    fn from(perm_type: PermType) -> Self {
+        match perm_type {
+            PermType::Read => PermissionType::Read,
+            PermType::Write => PermissionType::Write,
+            PermType::Readwrite => PermissionType::Readwrite,
+        }
+    }
+}
+
+/// Arguments of `role`
+#[derive(Args, Debug)]
+pub(crate) struct RoleArgs {
+    /// Subcommand
+    #[clap(subcommand)]
+    command: RoleCommand,
+}
+
+/// Subcommands of `role`
+#[derive(Subcommand, Debug)]
+enum RoleCommand {
+    /// Add a role
+    Add {
+        /// Name of the role
+        name: String,
+    },
+    /// Delete a role
+    Delete {
+        /// Name of the role
+        name: String,
+    },
+    /// Grant a permission on a key or a range of keys to a role
+    GrantPermission {
+        /// Name of the role
+        name: String,
+        /// Type of the permission
+        #[clap(value_enum)]
+        perm_type: PermType,
+        /// The key, or the start of the range
+        key: String,
+        /// The end of the range, exclusive
+        range_end: Option<String>,
+        /// Grant the permission on the keys with the prefix `key`
+        #[clap(long, conflicts_with_all = &["range-end", "from-key"])]
+        prefix: bool,
+        /// Grant the permission on the keys not less than `key`
+        #[clap(long, conflicts_with = "range-end")]
+        from_key: bool,
+    },
+    /// Revoke a permission on a key or a range of keys from a role
+    RevokePermission {
+        /// Name of the role
+        name: String,
+        /// The key, or the start of the range
+        key: String,
+        /// The end of the range, exclusive
+        range_end: Option<String>,
+    },
+    /// List all roles
+    List,
+    /// Get the permissions of a role
+    Get {
+        /// Name of the role
+        name: String,
+    },
+}
+
+/// Execute `role`
+pub(crate) async fn execute(client: &mut AuthClient, args: RoleArgs) -> Result<()> {
+    match args.command {
+        RoleCommand::Add { name } => {
+            let _resp = client.role_add(name.as_str()).await?;
+            println!("Role {name} created");
+        }
+        RoleCommand::Delete { name } => {
+            let _resp = client.role_delete(name.as_str()).await?;
+            println!("Role {name} deleted");
+        }
+        RoleCommand::GrantPermission {
+            name,
+            perm_type,
+            key,
+            range_end,
+            prefix,
+            from_key,
+        } => {
+            let mut perm = Permission::new(perm_type.into(), key);
+            if let Some(range_end) = range_end {
+                perm = perm.with_range_end(range_end);
+            }
+            if prefix {
+                perm = perm.with_prefix();
+            }
+            if from_key {
+                perm = perm.with_from_key();
+            }
+            let _resp = client.role_grant_permission(name.as_str(), perm).await?;
+            println!("Role {name} updated");
+        }
+        RoleCommand::RevokePermission {
+            name,
+            key,
+            range_end,
+        } => {
+            let _resp = client
+                .role_revoke_permission(name.as_str(), key, range_end.unwrap_or_default())
+                .await?;
+            println!("Permission of role {name} is revoked");
+        }
+        RoleCommand::List => {
+            let resp = client.role_list().await?;
+            resp.roles.iter().for_each(|role| println!("{role}"));
+        }
+        RoleCommand::Get { name } => {
+            let resp = client.role_get(name.as_str()).await?;
+            printer::print_role(&name, &resp);
+        }
+    }
+    Ok(())
+}
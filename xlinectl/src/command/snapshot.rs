@@ -0,0 +1,95 @@
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use clap::{Args, Subcommand};
+use utils::config::default_curp_data_dir;
+use xline::storage::snapshot;
+use xline_client::clients::maintenance::MaintenanceClient;
+
+use crate::{printer, ConnectionArgs};
+
+/// Arguments of `snapshot`
+#[derive(Args, Debug)]
+pub(crate) struct SnapshotArgs {
+    /// Subcommand
+    #[clap(subcommand)]
+    command: SnapshotCommand,
+}
+
+/// Subcommands of `snapshot`
+#[derive(Subcommand, Debug)]
+enum SnapshotCommand {
+    /// Save a snapshot of the backend of a member to a file
+    Save {
+        /// Path of the snapshot file
+        #[clap(value_parser)]
+        path: PathBuf,
+    },
+    /// Restore a snapshot file into the data dir of a new member, it works offline
+    Restore {
+        /// Path of the snapshot file
+        #[clap(value_parser)]
+        path: PathBuf,
+        /// Data dir of the storage, it must be empty
+        #[clap(long, value_parser)]
+        data_dir: PathBuf,
+        /// Data dir of the consensus log of the restored member, it must be empty
+        #[clap(long, value_parser, default_value_os_t = default_curp_data_dir())]
+        curp_data_dir: PathBuf,
+    },
+    /// Verify a snapshot file and print its status, it works offline
+    Status {
+        /// Path of the snapshot file
+        #[clap(value_parser)]
+        path: PathBuf,
+    },
+}
+
+/// Execute `snapshot`, only `save` connects to the servers
+pub(crate) async fn execute(connection: &ConnectionArgs, args: SnapshotArgs) -> Result<()> {
+    match args.command {
+        SnapshotCommand::Save { path } => {
+            let client = connection.connect().await?;
+            save(&mut client.maintenance_client(), &path).await?;
+            let count = snapshot::verify_file(&path)?;
+            printer::print_snapshot_save(&path, count);
+        }
+        SnapshotCommand::Restore {
+            path,
+            data_dir,
+            curp_data_dir,
+        } => {
+            let count = snapshot::restore_from_file(&path, &data_dir, &curp_data_dir)?;
+            printer::print_snapshot_restore(&data_dir, count);
+        }
+        SnapshotCommand::Status { path } => {
+            let count = snapshot::verify_file(&path)?;
+            let size = fs::metadata(&path)?.len();
+            printer::print_snapshot_status(&path, count, size);
+        }
+    }
+    Ok(())
+}
+
+/// Receive a snapshot and save it to `path`, it's written to a temporary file first, so
+/// that a partial snapshot never looks complete
+async fn save(client: &mut MaintenanceClient, path: &Path) -> Result<()> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".part");
+    let tmp_path = PathBuf::from(tmp_path);
+    let file = File::create(&tmp_path)?;
+    let mut writer = BufWriter::new(&file);
+    let mut stream = client.snapshot().await?;
+    while let Some(resp) = stream.message().await? {
+        writer.write_all(&resp.blob)?;
+    }
+    writer.flush()?;
+    drop(writer);
+    file.sync_all()?;
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
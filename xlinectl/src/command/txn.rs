@@ -0,0 +1,197 @@
+use std::io::{self, BufRead, Write};
+
+use anyhow::{anyhow, bail, Result};
+use clap::{Args, Parser};
+use xline_client::{
+    clients::kv::KvClient,
+    types::kv::{Compare, CompareResult, TxnOp, TxnRequest},
+};
+
+use crate::{
+    command::{delete::DeleteArgs, get::GetArgs, put::PutArgs},
+    printer,
+};
+
+/// Arguments of `txn`
+#[derive(Args, Debug)]
+pub(crate) struct TxnArgs {
+    /// Prompt for the compares and the operations
+    #[clap(long, short)]
+    interactive: bool,
+}
+
+/// Operation in a txn, its arguments are the same as the command of it
+#[derive(Parser, Debug)]
+#[clap(no_binary_name = true)]
+enum TxnOpArgs {
+    /// `Range` operation
+    Get(GetArgs),
+    /// `Put` operation
+    Put(PutArgs),
+    /// `DeleteRange` operation
+    Del(DeleteArgs),
+}
+
+/// Split a line into arguments by whitespaces, the arguments can be quoted by `"`. The
+/// brackets are separators too if `brackets` is set.
+fn split_args(line: &str, brackets: bool) -> Result<Vec<String>> {
+    let mut args = vec![];
+    let mut arg: Option<String> = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                let quoted = arg.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => {
+                            let escaped = chars
+                                .next()
+                                .ok_or_else(|| anyhow!("unterminated quote in {line}"))?;
+                            quoted.push(escaped);
+                        }
+                        Some(other) => quoted.push(other),
+                        None => bail!("unterminated quote in {line}"),
+                    }
+                }
+            }
+            '(' | ')' if brackets => args.extend(arg.take()),
+            _ if c.is_whitespace() => args.extend(arg.take()),
+            _ => arg.get_or_insert_with(String::new).push(c),
+        }
+    }
+    args.extend(arg);
+    Ok(args)
+}
+
+/// Parse a compare, eg. `value("foo") = "bar"`, `mod("foo") > "3"`. The targets are
+/// `value`, `mod`, `create`, `version` and `lease`.
+fn parse_compare(line: &str) -> Result<Compare> {
+    let args = split_args(line, true)?;
+    let [target, key, op, value] = <[String; 4]>::try_from(args)
+        .map_err(|_e| anyhow!("invalid compare {line}, eg. value(\"foo\") = \"bar\""))?;
+    let result = match op.as_str() {
+        "=" | "==" => CompareResult::Equal,
+        "!=" => CompareResult::NotEqual,
+        "<" => CompareResult::Less,
+        ">" => CompareResult::Greater,
+        _ => bail!("invalid operator {op} in compare {line}"),
+    };
+    let compare = match target.to_lowercase().as_str() {
+        "value" | "val" => Compare::value(key, result, value),
+        "mod" | "m" => Compare::mod_revision(key, result, value.parse()?),
+        "create" | "c" => Compare::create_revision(key, result, value.parse()?),
+        "version" | "ver" => Compare::version(key, result, value.parse()?),
+        "lease" => Compare::lease(key, result, value.parse()?),
+        _ => bail!("invalid target {target} in compare {line}"),
+    };
+    Ok(compare)
+}
+
+/// Parse an operation, eg. `put foo bar`, `get foo --prefix`, `del foo`
+fn parse_op(line: &str) -> Result<TxnOp> {
+    let op = match TxnOpArgs::try_parse_from(split_args(line, false)?)? {
+        TxnOpArgs::Get(args) => TxnOp::range(args.request()),
+        TxnOpArgs::Put(args) => TxnOp::put(args.request()),
+        TxnOpArgs::Del(args) => TxnOp::delete(args.request()),
+    };
+    Ok(op)
+}
+
+/// Read the lines of a section until an empty line, and parse each of them
+fn read_section<T>(
+    lines: &mut impl Iterator<Item = io::Result<String>>,
+    prompt: Option<&str>,
+    parse: fn(&str) -> Result<T>,
+) -> Result<Vec<T>> {
+    if let Some(prompt) = prompt {
+        println!("{prompt}");
+        io::stdout().flush()?;
+    }
+    let mut items = vec![];
+    for line in lines {
+        let line = line?;
+        if line.trim().is_empty() {
+            break;
+        }
+        items.push(parse(line.trim())?);
+    }
+    Ok(items)
+}
+
+/// Execute `txn`. The compares, the operations executed if the compares succeed, and the
+/// operations executed if any compare fails are read from stdin in order, and each
+/// section ends with an empty line.
+pub(crate) async fn execute(client: &mut KvClient, args: TxnArgs) -> Result<()> {
+    let request = {
+        let stdin = io::stdin();
+        let mut lines = stdin.lock().lines();
+        let prompt = |p| args.interactive.then_some(p);
+        let compares = read_section(&mut lines, prompt("compares:"), parse_compare)?;
+        let success = read_section(
+            &mut lines,
+            prompt("success requests (get, put, del):"),
+            parse_op,
+        )?;
+        let failure = read_section(
+            &mut lines,
+            prompt("failure requests (get, put, del):"),
+            parse_op,
+        )?;
+        TxnRequest::new()
+            .when(compares)
+            .and_then(success)
+            .or_else(failure)
+    };
+    let resp = client.txn(request).await?;
+    printer::print_txn(&resp);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use xline_client::types::kv::{DeleteRangeRequest, PutRequest};
+
+    use super::*;
+
+    #[test]
+    fn test_split_args() {
+        assert_eq!(
+            split_args(r#"put "foo bar" "b\"az""#, false).unwrap(),
+            ["put", "foo bar", "b\"az"]
+        );
+        assert_eq!(
+            split_args(r#"value("foo(1)") = "bar""#, true).unwrap(),
+            ["value", "foo(1)", "=", "bar"]
+        );
+        assert!(split_args(r#"put "foo"#, false).is_err());
+    }
+
+    #[test]
+    fn test_parse_compare() {
+        assert_eq!(
+            parse_compare(r#"value("foo") = "bar""#).unwrap(),
+            Compare::value("foo", CompareResult::Equal, "bar")
+        );
+        assert_eq!(
+            parse_compare(r#"mod("foo") > "3""#).unwrap(),
+            Compare::mod_revision("foo", CompareResult::Greater, 3)
+        );
+        assert!(parse_compare(r#"value("foo") ~ "bar""#).is_err());
+        assert!(parse_compare(r#"mod("foo") > "bar""#).is_err());
+    }
+
+    #[test]
+    fn test_parse_op() {
+        assert_eq!(
+            parse_op("put foo bar").unwrap(),
+            TxnOp::put(PutRequest::new("foo", "bar"))
+        );
+        assert_eq!(
+            parse_op("del foo --prefix").unwrap(),
+            TxnOp::delete(DeleteRangeRequest::new("foo").with_prefix())
+        );
+        assert!(parse_op("watch foo").is_err());
+    }
+}
@@ -0,0 +1,52 @@
+use anyhow::Result;
+use clap::Args;
+use xline_client::{clients::kv::KvClient, types::kv::UndeleteRequest};
+
+use crate::printer;
+
+/// Arguments of `undelete`
+#[derive(Args, Debug)]
+pub(crate) struct UndeleteArgs {
+    /// The key, or the start of the range
+    key: String,
+    /// The end of the range, exclusive
+    range_end: Option<String>,
+    /// Restore the keys with the prefix `key`
+    #[clap(long, conflicts_with_all = &["range-end", "from-key"])]
+    prefix: bool,
+    /// Restore the keys not less than `key`
+    #[clap(long, conflicts_with = "range-end")]
+    from_key: bool,
+}
+
+impl UndeleteArgs {
+    /// Build the request
+    fn request(&self) -> UndeleteRequest {
+        let mut request = UndeleteRequest::new(self.key.as_bytes());
+        if let Some(ref range_end) = self.range_end {
+            request = request.with_range_end(range_end.as_bytes());
+        }
+        if self.prefix {
+            request = request.with_prefix();
+        }
+        if self.from_key {
+            request = request.with_from_key();
+        }
+        request
+    }
+}
+
+/// Execute `undelete`, the requests are sent until all keys in the range are restored
+pub(crate) async fn execute(client: &mut KvClient, args: UndeleteArgs) -> Result<()> {
+    let mut restored = 0;
+    loop {
+        let resp = client.undelete(args.request()).await?;
+        restored = resp.restored.saturating_add(restored);
+        // no key is restored if all of them are recreated meanwhile
+        if !resp.more || resp.restored == 0 {
+            break;
+        }
+    }
+    printer::print_undelete(restored);
+    Ok(())
+}
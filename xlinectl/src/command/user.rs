@@ -0,0 +1,116 @@
+use anyhow::Result;
+use clap::{Args, Subcommand};
+use xline_client::clients::auth::AuthClient;
+
+use crate::{printer, prompt};
+
+/// Arguments of `user`
+#[derive(Args, Debug)]
+pub(crate) struct UserArgs {
+    /// Subcommand
+    #[clap(subcommand)]
+    command: UserCommand,
+}
+
+/// Subcommands of `user`
+#[derive(Subcommand, Debug)]
+enum UserCommand {
+    /// Add a user, the password is prompted if it's not given
+    Add {
+        /// Name of the user
+        name: String,
+        /// Password of the user
+        #[clap(long)]
+        password: Option<String>,
+        /// Add the user without password, it can't authenticate by password
+        #[clap(long, conflicts_with = "password")]
+        no_password: bool,
+    },
+    /// Delete a user
+    Delete {
+        /// Name of the user
+        name: String,
+    },
+    /// Change the password of a user, the password is prompted if it's not given
+    Passwd {
+        /// Name of the user
+        name: String,
+        /// New password of the user
+        #[clap(long)]
+        password: Option<String>,
+    },
+    /// Grant a role to a user
+    GrantRole {
+        /// Name of the user
+        name: String,
+        /// Name of the role
+        role: String,
+    },
+    /// Revoke a role from a user
+    RevokeRole {
+        /// Name of the user
+        name: String,
+        /// Name of the role
+        role: String,
+    },
+    /// List all users
+    List,
+    /// Get the roles of a user
+    Get {
+        /// Name of the user
+        name: String,
+    },
+}
+
+/// Execute `user`
+pub(crate) async fn execute(client: &mut AuthClient, args: UserArgs) -> Result<()> {
+    match args.command {
+        UserCommand::Add {
+            name,
+            password,
+            no_password,
+        } => {
+            if no_password {
+                let _resp = client.user_add_without_password(name.as_str()).await?;
+            } else {
+                let password = match password {
+                    Some(password) => password,
+                    None => prompt::read_new_password(&name)?,
+                };
+                let _resp = client.user_add(name.as_str(), password).await?;
+            }
+            println!("User {name} created");
+        }
+        UserCommand::Delete { name } => {
+            let _resp = client.user_delete(name.as_str()).await?;
+            println!("User {name} deleted");
+        }
+        UserCommand::Passwd { name, password } => {
+            let password = match password {
+                Some(password) => password,
+                None => prompt::read_new_password(&name)?,
+            };
+            let _resp = client.user_change_password(name, password).await?;
+            println!("Password updated");
+        }
+        UserCommand::GrantRole { name, role } => {
+            let _resp = client.user_grant_role(name.as_str(), role.as_str()).await?;
+            println!("Role {role} is granted to user {name}");
+        }
+        UserCommand::RevokeRole { name, role } => {
+            let _resp = client
+                .user_revoke_role(name.as_str(), role.as_str())
+                .await?;
+            println!("Role {role} is revoked from user {name}");
+        }
+        UserCommand::List => {
+            let resp = client.user_list().await?;
+            resp.users.iter().for_each(|user| println!("{user}"));
+        }
+        UserCommand::Get { name } => {
+            let resp = client.user_get(name.as_str()).await?;
+            printer::print_user(&name, &resp);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,64 @@
+use anyhow::Result;
+use clap::Args;
+use xline_client::{clients::watch::WatchClient, types::watch::WatchRequest};
+
+use crate::printer;
+
+/// Arguments of `watch`
+#[derive(Args, Debug)]
+pub(crate) struct WatchArgs {
+    /// The key, or the start of the range
+    key: String,
+    /// The end of the range, exclusive
+    range_end: Option<String>,
+    /// Watch the keys with the prefix `key`
+    #[clap(long, conflicts_with = "range-end")]
+    prefix: bool,
+    /// Watch the events from the revision, 0 means the events after the current revision
+    #[clap(long, default_value_t = 0)]
+    rev: i64,
+    /// Get the previous key-values of the events
+    #[clap(long)]
+    prev_kv: bool,
+    /// Print only the put events whose values start with the prefix
+    #[clap(long)]
+    value_prefix: Option<String>,
+    /// Print only the put events whose values match the regular expression
+    #[clap(long)]
+    value_regex: Option<String>,
+}
+
+impl WatchArgs {
+    /// Build the request
+    fn request(&self) -> WatchRequest {
+        let mut request = WatchRequest::new(self.key.as_bytes());
+        if let Some(ref range_end) = self.range_end {
+            request = request.with_range_end(range_end.as_bytes());
+        }
+        if self.prefix {
+            request = request.with_prefix();
+        }
+        if let Some(ref value_prefix) = self.value_prefix {
+            request = request.with_value_prefix(value_prefix.as_bytes());
+        }
+        if let Some(ref value_regex) = self.value_regex {
+            request = request.with_value_regex(value_regex.as_str());
+        }
+        request
+            .with_start_revision(self.rev)
+            .with_prev_kv(self.prev_kv)
+    }
+}
+
+/// Execute `watch`, the events are printed until the command is interrupted
+pub(crate) async fn execute(client: &mut WatchClient, args: WatchArgs) -> Result<()> {
+    let (_watcher, mut stream) = client.watch(args.request()).await?;
+    while let Some(resp) = stream.message().await? {
+        if resp.canceled {
+            println!("watch is canceled, {}", resp.cancel_reason);
+            break;
+        }
+        resp.events.iter().for_each(printer::print_event);
+    }
+    Ok(())
+}
@@ -0,0 +1,315 @@
+//! xlinectl, the command line client of Xline
+#![deny(
+    // The following are allowed by default lints according to
+    // https://doc.rust-lang.org/rustc/lints/listing/allowed-by-default.html
+
+    absolute_paths_not_starting_with_crate,
+    // box_pointers, async trait must use it
+    // elided_lifetimes_in_paths,  // allow anonymous lifetime
+    explicit_outlives_requirements,
+    keyword_idents,
+    macro_use_extern_crate,
+    meta_variable_misuse,
+    missing_abi,
+    missing_copy_implementations,
+    missing_debug_implementations,
+    missing_docs,
+    // must_not_suspend, unstable
+    non_ascii_idents,
+    // non_exhaustive_omitted_patterns, unstable
+    noop_method_call,
+    pointer_structural_match,
+    rust_2021_incompatible_closure_captures,
+    rust_2021_incompatible_or_patterns,
+    rust_2021_prefixes_incompatible_syntax,
+    rust_2021_prelude_collisions,
+    single_use_lifetimes,
+    trivial_casts,
+    trivial_numeric_casts,
+    unreachable_pub,
+    unsafe_code,
+    unsafe_op_in_unsafe_fn,
+    unstable_features,
+    // unused_crate_dependencies, the false positive case blocks us
+    unused_extern_crates,
+    unused_import_braces,
+    unused_lifetimes,
+    unused_qualifications,
+    unused_results,
+    variant_size_differences,
+
+    warnings, // treat all warnings as errors
+
+    clippy::all,
+    clippy::pedantic,
+    clippy::cargo,
+
+    // The followings are selected restriction lints for rust 1.57
+    clippy::as_conversions,
+    clippy::clone_on_ref_ptr,
+    clippy::create_dir,
+    clippy::dbg_macro,
+    clippy::decimal_literal_representation,
+    // clippy::default_numeric_fallback, too verbose when dealing with numbers
+    clippy::disallowed_script_idents,
+    clippy::else_if_without_else,
+    clippy::exhaustive_enums,
+    clippy::exhaustive_structs,
+    clippy::exit,
+    clippy::expect_used,
+    clippy::filetype_is_file,
+    clippy::float_arithmetic,
+    clippy::float_cmp_const,
+    clippy::get_unwrap,
+    clippy::if_then_some_else_none,
+    // clippy::implicit_return, it's idiomatic Rust code.
+    clippy::indexing_slicing,
+    // clippy::inline_asm_x86_att_syntax, stick to intel syntax
+    clippy::inline_asm_x86_intel_syntax,
+    clippy::integer_arithmetic,
+    // clippy::integer_division, required in the project
+    clippy::let_underscore_must_use,
+    clippy::lossy_float_literal,
+    clippy::map_err_ignore,
+    clippy::mem_forget,
+    clippy::missing_docs_in_private_items,
+    clippy::missing_enforced_import_renames,
+    clippy::missing_inline_in_public_items,
+    // clippy::mod_module_files, mod.rs file is used
+    clippy::modulo_arithmetic,
+    clippy::multiple_inherent_impl,
+    // clippy::panic, allow in application code
+    // clippy::panic_in_result_fn, not necessary as panic is banned
+    clippy::pattern_type_mismatch,
+    clippy::print_stderr,
+    // clippy::print_stdout, the results are printed to stdout
+    clippy::rc_buffer,
+    clippy::rc_mutex,
+    clippy::rest_pat_in_fully_bound_structs,
+    clippy::same_name_method,
+    clippy::self_named_module_files,
+    // clippy::shadow_reuse, it’s a common pattern in Rust code
+    // clippy::shadow_same, it’s a common pattern in Rust code
+    clippy::shadow_unrelated,
+    clippy::str_to_string,
+    clippy::string_add,
+    clippy::string_to_string,
+    clippy::todo,
+    clippy::unimplemented,
+    clippy::unnecessary_self_imports,
+    clippy::unneeded_field_pattern,
+    // clippy::unreachable, allow unreachable panic, which is out of expectation
+    clippy::unwrap_in_result,
+    clippy::unwrap_used,
+    // clippy::use_debug, debug is allow for debug log
+    clippy::verbose_file_reads,
+    clippy::wildcard_enum_match_arm,
+
+    // The followings are selected lints from 1.61.0 to 1.67.1
+    clippy::as_ptr_cast_mut,
+    clippy::derive_partial_eq_without_eq,
+    clippy::empty_drop,
+    clippy::empty_structs_with_brackets,
+    clippy::format_push_string,
+    clippy::iter_on_empty_collections,
+    clippy::iter_on_single_items,
+    clippy::large_include_file,
+    clippy::manual_clamp,
+    clippy::suspicious_xor_used_as_pow,
+    clippy::unnecessary_safety_comment,
+    clippy::unnecessary_safety_doc,
+    clippy::unused_peekable,
+    clippy::unused_rounding
+)]
+#![allow(
+    clippy::panic, // allow debug_assert, panic in production code
+    clippy::multiple_crate_versions, // caused by the dependency, can't be fixed
+    clippy::module_name_repetitions, // TODO: refactor module to remove this
+)]
+#![cfg_attr(
+    test,
+    allow(
+        clippy::indexing_slicing,
+        unused_results,
+        clippy::unwrap_used,
+        clippy::as_conversions,
+        clippy::shadow_unrelated,
+    )
+)]
+
+/// Subcommands of xlinectl
+mod command;
+/// Printers of the responses
+mod printer;
+/// Prompts of the passwords
+mod prompt;
+
+use std::time::Duration;
+
+use anyhow::Result;
+use clap::{Args, Parser, Subcommand};
+use utils::parse_duration;
+use xline_client::{Client, ClientOptions};
+
+use crate::command::{
+    auth, compaction, defrag, delete, endpoint, get, history, lease, member, put, role, snapshot,
+    txn, undelete, user, watch,
+};
+
+/// Command line arguments
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct ClientArgs {
+    /// Arguments of the connection
+    #[clap(flatten)]
+    connection: ConnectionArgs,
+    /// Subcommand
+    #[clap(subcommand)]
+    command: Command,
+}
+
+/// Arguments of the connection to the Xline servers
+#[derive(Args, Debug)]
+pub(crate) struct ConnectionArgs {
+    /// Endpoints of the Xline servers, eg. 127.0.0.1:2379,127.0.0.1:2380
+    #[clap(
+        long,
+        env = "XLINECTL_ENDPOINTS",
+        value_delimiter = ',',
+        default_value = "127.0.0.1:2379"
+    )]
+    endpoints: Vec<String>,
+    /// User to authenticate with, eg. name:password, the password is prompted if it's not
+    /// given
+    #[clap(long, env = "XLINECTL_USER")]
+    user: Option<String>,
+    /// Timeout of connecting to an endpoint, eg. 2s
+    #[clap(long, value_parser = parse_duration)]
+    dial_timeout: Option<Duration>,
+    /// Timeout of each command, eg. 5s
+    #[clap(long, value_parser = parse_duration)]
+    command_timeout: Option<Duration>,
+}
+
+/// Subcommands
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Get the keys in a range
+    Get(get::GetArgs),
+    /// Put a key-value
+    Put(put::PutArgs),
+    /// Delete the keys in a range
+    Del(delete::DeleteArgs),
+    /// Restore the deleted keys in a range from the trash
+    Undelete(undelete::UndeleteArgs),
+    /// Process a txn read from stdin
+    Txn(txn::TxnArgs),
+    /// Compact the history before a revision
+    Compaction(compaction::CompactionArgs),
+    /// List the retained revisions of a key
+    History(history::HistoryArgs),
+    /// Lease commands
+    Lease(lease::LeaseArgs),
+    /// Watch the events of the keys in a range
+    Watch(watch::WatchArgs),
+    /// Authentication commands
+    Auth(auth::AuthArgs),
+    /// User commands
+    User(user::UserArgs),
+    /// Role commands
+    Role(role::RoleArgs),
+    /// Snapshot commands
+    Snapshot(snapshot::SnapshotArgs),
+    /// Defragment the backend of each endpoint
+    Defrag,
+    /// Endpoint commands
+    Endpoint(endpoint::EndpointArgs),
+    /// Member commands
+    Member(member::MemberArgs),
+}
+
+impl ConnectionArgs {
+    /// Get the options of the client
+    pub(crate) fn client_options(&self) -> Result<ClientOptions> {
+        let mut options = ClientOptions::new();
+        if let Some(ref user) = self.user {
+            options = match user.split_once(':') {
+                Some((name, password)) => options.with_user(name, password),
+                None => options.with_user(user, prompt::read_password("Password: ")?),
+            };
+        }
+        if let Some(timeout) = self.command_timeout {
+            options = options.with_timeout(timeout);
+        }
+        if let Some(connect_timeout) = self.dial_timeout {
+            options = options.with_connect_timeout(connect_timeout);
+        }
+        Ok(options)
+    }
+
+    /// Connect to all endpoints
+    pub(crate) async fn connect(&self) -> Result<Client> {
+        Ok(Client::connect(&self.endpoints, self.client_options()?).await?)
+    }
+
+    /// Connect to each endpoint separately, so that the commands can be sent to every
+    /// member, failing to connect to an endpoint doesn't affect the others
+    pub(crate) async fn connect_each(&self) -> Result<Vec<(&str, Result<Client>)>> {
+        let options = self.client_options()?;
+        let mut clients = Vec::with_capacity(self.endpoints.len());
+        for endpoint in &self.endpoints {
+            let client = Client::connect([endpoint], options.clone()).await;
+            clients.push((endpoint.as_str(), client.map_err(Into::into)));
+        }
+        Ok(clients)
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let ClientArgs {
+        connection,
+        command,
+    } = ClientArgs::parse();
+    let connect = || connection.connect();
+    // the snapshot, `defrag` and endpoint commands connect by themselves, some of them
+    // work offline, and some are sent to each endpoint
+    match command {
+        Command::Snapshot(snapshot_args) => snapshot::execute(&connection, snapshot_args).await,
+        Command::Defrag => defrag::execute(&connection).await,
+        Command::Endpoint(endpoint_args) => endpoint::execute(&connection, endpoint_args).await,
+        Command::Member(member_args) => {
+            member::execute(&mut connect().await?.cluster_client(), member_args).await
+        }
+        Command::Get(get_args) => get::execute(&mut connect().await?.kv_client(), get_args).await,
+        Command::Put(put_args) => put::execute(&mut connect().await?.kv_client(), put_args).await,
+        Command::Del(delete_args) => {
+            delete::execute(&mut connect().await?.kv_client(), delete_args).await
+        }
+        Command::Undelete(undelete_args) => {
+            undelete::execute(&mut connect().await?.kv_client(), undelete_args).await
+        }
+        Command::Txn(txn_args) => txn::execute(&mut connect().await?.kv_client(), txn_args).await,
+        Command::Compaction(compaction_args) => {
+            compaction::execute(&mut connect().await?.kv_client(), compaction_args).await
+        }
+        Command::History(history_args) => {
+            history::execute(&mut connect().await?.kv_client(), history_args).await
+        }
+        Command::Lease(lease_args) => {
+            lease::execute(&mut connect().await?.lease_client(), lease_args).await
+        }
+        Command::Watch(watch_args) => {
+            watch::execute(&mut connect().await?.watch_client(), watch_args).await
+        }
+        Command::Auth(auth_args) => {
+            auth::execute(&mut connect().await?.auth_client(), auth_args).await
+        }
+        Command::User(user_args) => {
+            user::execute(&mut connect().await?.auth_client(), user_args).await
+        }
+        Command::Role(role_args) => {
+            role::execute(&mut connect().await?.auth_client(), role_args).await
+        }
+    }
+}
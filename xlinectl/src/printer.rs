@@ -0,0 +1,300 @@
+use std::{path::Path, time::Duration};
+
+use xline_client::types::{
+    auth::{AuthRoleGetResponse, AuthStatusResponse, AuthUserGetResponse, PermissionType},
+    cluster::{Member, MemberAddResponse},
+    kv::{
+        DeleteRangeResponse, KeyHistoryResponse, KeyValue, PutResponse, RangeResponse,
+        TxnOpResponse, TxnResponse,
+    },
+    lease::{
+        LeaseGrantResponse, LeaseKeepAliveResponse, LeaseLeasesResponse, LeaseModifyTtlResponse,
+        LeaseTimeToLiveResponse,
+    },
+    maintenance::{HashKvResponse, StatusResponse, TableStatsResponse},
+    watch::{Event, EventType},
+};
+
+/// Print a key-value, the key and the value are printed in two lines
+pub(crate) fn print_kv(kv: &KeyValue) {
+    println!("{}", String::from_utf8_lossy(&kv.key));
+    if !kv.value.is_empty() {
+        println!("{}", String::from_utf8_lossy(&kv.value));
+    }
+}
+
+/// Print the response of `get`
+pub(crate) fn print_range(resp: &RangeResponse, count_only: bool) {
+    if count_only {
+        println!("{}", resp.count);
+        return;
+    }
+    resp.kvs.iter().for_each(print_kv);
+}
+
+/// Print the response of `put`
+pub(crate) fn print_put(resp: &PutResponse) {
+    println!("OK");
+    if let Some(ref prev_kv) = resp.prev_kv {
+        print_kv(prev_kv);
+    }
+}
+
+/// Print the response of `del`
+pub(crate) fn print_delete(resp: &DeleteRangeResponse) {
+    println!("{}", resp.deleted);
+    resp.prev_kvs.iter().for_each(print_kv);
+}
+
+/// Print the response of `txn`
+pub(crate) fn print_txn(resp: &TxnResponse) {
+    println!("{}", if resp.succeeded { "SUCCESS" } else { "FAILURE" });
+    for op in &resp.responses {
+        println!();
+        match op.response {
+            Some(TxnOpResponse::ResponseRange(ref range)) => print_range(range, false),
+            Some(TxnOpResponse::ResponsePut(ref put)) => print_put(put),
+            Some(TxnOpResponse::ResponseDeleteRange(ref delete)) => print_delete(delete),
+            Some(TxnOpResponse::ResponseTxn(ref txn)) => print_txn(txn),
+            None => {}
+        }
+    }
+}
+
+/// Print an event, its type, key-value and previous key-value
+pub(crate) fn print_event(event: &Event) {
+    match event.r#type() {
+        EventType::Put => println!("PUT"),
+        EventType::Delete => println!("DELETE"),
+    }
+    if let Some(ref kv) = event.kv {
+        print_kv(kv);
+    }
+    if let Some(ref prev_kv) = event.prev_kv {
+        print_kv(prev_kv);
+    }
+}
+
+/// Print the response of `history`, each revision with the type and the revision of its
+/// event
+pub(crate) fn print_key_history(resp: &KeyHistoryResponse) {
+    for event in &resp.events {
+        let Some(ref kv) = event.kv else {
+            continue;
+        };
+        match event.r#type() {
+            EventType::Put => println!("PUT, revision {}, lease {}", kv.mod_revision, kv.lease),
+            EventType::Delete => println!("DELETE, revision {}", kv.mod_revision),
+        }
+        if !kv.value.is_empty() {
+            println!("{}", String::from_utf8_lossy(&kv.value));
+        }
+    }
+    if resp.more {
+        println!("more revisions are not listed");
+    }
+}
+
+/// Print the number of the keys restored by `undelete`
+pub(crate) fn print_undelete(restored: i64) {
+    println!("{restored}");
+}
+
+/// Print the response of `lease grant`
+pub(crate) fn print_lease_grant(resp: &LeaseGrantResponse) {
+    println!("lease {} granted with TTL({}s)", resp.id, resp.ttl);
+}
+
+/// Print the result of `lease revoke`
+pub(crate) fn print_lease_revoke(id: i64) {
+    println!("lease {id} revoked");
+}
+
+/// Print the response of `lease modify-ttl`
+pub(crate) fn print_lease_modify_ttl(resp: &LeaseModifyTtlResponse) {
+    println!("lease {} modified with TTL({}s)", resp.id, resp.ttl);
+}
+
+/// Print the response of `lease timetolive`
+pub(crate) fn print_lease_time_to_live(resp: &LeaseTimeToLiveResponse) {
+    if resp.ttl <= 0 {
+        println!("lease {} already expired", resp.id);
+        return;
+    }
+    println!(
+        "lease {} granted with TTL({}s), remaining({}s)",
+        resp.id, resp.granted_ttl, resp.ttl
+    );
+    if !resp.keys.is_empty() {
+        let keys: Vec<_> = resp
+            .keys
+            .iter()
+            .map(|key| String::from_utf8_lossy(key))
+            .collect();
+        println!("attached keys({})", keys.join(", "));
+    }
+}
+
+/// Print a response of `lease keep-alive`
+pub(crate) fn print_lease_keep_alive(resp: &LeaseKeepAliveResponse) {
+    if resp.ttl <= 0 {
+        println!("lease {} expired or revoked", resp.id);
+    } else {
+        println!("lease {} keepalived with TTL({}s)", resp.id, resp.ttl);
+    }
+}
+
+/// Print the response of `lease list`
+pub(crate) fn print_lease_list(resp: &LeaseLeasesResponse) {
+    println!("found {} leases", resp.leases.len());
+    for lease in &resp.leases {
+        println!("{}", lease.id);
+    }
+}
+
+/// Print the response of `auth status`
+pub(crate) fn print_auth_status(resp: &AuthStatusResponse) {
+    println!("Authentication Status: {}", resp.enabled);
+    println!("AuthRevision: {}", resp.auth_revision);
+}
+
+/// Print the response of `user get`
+pub(crate) fn print_user(name: &str, resp: &AuthUserGetResponse) {
+    println!("User: {name}");
+    println!("Roles: {}", resp.roles.join(" "));
+}
+
+/// Print the response of `role get`
+pub(crate) fn print_role(name: &str, resp: &AuthRoleGetResponse) {
+    println!("Role {name}");
+    println!("KV Read:");
+    for perm in &resp.perm {
+        if matches!(
+            perm.perm_type(),
+            PermissionType::Read | PermissionType::Readwrite
+        ) {
+            print_perm_range(&perm.key, &perm.range_end);
+        }
+    }
+    println!("KV Write:");
+    for perm in &resp.perm {
+        if matches!(
+            perm.perm_type(),
+            PermissionType::Write | PermissionType::Readwrite
+        ) {
+            print_perm_range(&perm.key, &perm.range_end);
+        }
+    }
+}
+
+/// Print the range of a permission
+fn print_perm_range(key: &[u8], range_end: &[u8]) {
+    let key = String::from_utf8_lossy(key);
+    match range_end {
+        [] => println!("\t{key}"),
+        [0] => println!("\t[{key}, <open ended>"),
+        _ => println!("\t[{key}, {})", String::from_utf8_lossy(range_end)),
+    }
+}
+
+/// Print the result of `compaction`
+pub(crate) fn print_compaction(revision: i64) {
+    println!("compacted revision {revision}");
+}
+
+/// Print the result of `defrag` on an endpoint, `error` is `None` if it succeeds
+pub(crate) fn print_defrag(endpoint: &str, error: Option<&str>) {
+    match error {
+        None => println!("Finished defragmenting member[{endpoint}]"),
+        Some(e) => println!("Failed to defragment member[{endpoint}] ({e})"),
+    }
+}
+
+/// Print the result of `snapshot save`
+pub(crate) fn print_snapshot_save(path: &Path, count: u64) {
+    println!("Snapshot saved at {}, {count} entries", path.display());
+}
+
+/// Print the result of `snapshot restore`
+pub(crate) fn print_snapshot_restore(data_dir: &Path, count: u64) {
+    println!("Restored {count} entries to {}", data_dir.display());
+}
+
+/// Print the result of `snapshot status`
+pub(crate) fn print_snapshot_status(path: &Path, count: u64, size: u64) {
+    println!("{}", path.display());
+    println!("Total entries: {count}");
+    println!("Total size: {size} bytes");
+}
+
+/// Print a member, the id is printed in hex
+pub(crate) fn print_member(member: &Member) {
+    let status = if member.name.is_empty() {
+        "unstarted"
+    } else {
+        "started"
+    };
+    println!(
+        "{:x}, {status}, {}, {}, {}, {}",
+        member.id,
+        member.name,
+        member.peer_ur_ls.join(","),
+        member.client_ur_ls.join(","),
+        member.is_learner
+    );
+}
+
+/// Print the response of `member add`
+pub(crate) fn print_member_add(resp: &MemberAddResponse) {
+    if let Some(ref member) = resp.member {
+        println!("Member {:x} added to cluster", member.id);
+    }
+    resp.members.iter().for_each(print_member);
+}
+
+/// Print the result of `member remove`, `member update` and `member promote`
+pub(crate) fn print_member_change(id: u64, change: &str) {
+    println!("Member {id:x} {change} cluster");
+}
+
+/// Print the response of `endpoint status`
+pub(crate) fn print_endpoint_status(endpoint: &str, resp: &StatusResponse) {
+    let member_id = resp.header.as_ref().map_or(0, |header| header.member_id);
+    println!(
+        "{endpoint}, {member_id:x}, {}, {}, {}, {}, {}, {}, {}, {}, {}",
+        resp.version,
+        resp.db_size,
+        resp.db_size_in_use,
+        resp.leader == member_id,
+        resp.is_learner,
+        resp.raft_term,
+        resp.raft_index,
+        resp.raft_applied_index,
+        resp.errors.join(",")
+    );
+}
+
+/// Print the result of `endpoint health`
+pub(crate) fn print_endpoint_health(endpoint: &str, took: Duration) {
+    println!("{endpoint} is healthy: successfully committed proposal: took = {took:?}");
+}
+
+/// Print the response of `endpoint hashkv`
+pub(crate) fn print_endpoint_hash_kv(endpoint: &str, resp: &HashKvResponse) {
+    println!("{endpoint}, {}, {}", resp.hash, resp.compact_revision);
+}
+
+/// Print the response of `endpoint table-stats`, a line for each table
+pub(crate) fn print_endpoint_table_stats(endpoint: &str, resp: &TableStatsResponse) {
+    for table in &resp.tables {
+        println!(
+            "{endpoint}, {}, {}, {}, {}, {}",
+            table.name, table.keys, table.bytes, table.tombstones, table.oldest_revision
+        );
+    }
+}
+
+/// Print the error of an endpoint command
+pub(crate) fn print_endpoint_error(endpoint: &str, error: &str) {
+    println!("{endpoint} failed: {error}");
+}
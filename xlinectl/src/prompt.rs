@@ -0,0 +1,16 @@
+use anyhow::{bail, Result};
+
+/// Read a password from the terminal without echoing it
+pub(crate) fn read_password(prompt: &str) -> Result<String> {
+    Ok(rpassword::prompt_password(prompt)?)
+}
+
+/// Read a new password from the terminal, it's typed twice to be confirmed
+pub(crate) fn read_new_password(name: &str) -> Result<String> {
+    let password = read_password(&format!("Password of {name}: "))?;
+    let confirmed = read_password("Type password again for confirmation: ")?;
+    if password != confirmed {
+        bail!("the passwords don't match");
+    }
+    Ok(password)
+}